@@ -0,0 +1,103 @@
+//! The embedded source: a world simulated inside the plugin, on a thread of
+//! its own so the audio thread only ever touches atomics.
+//!
+//! The audio thread reports the time it has played; the world thread ticks
+//! by however much has passed since it last looked, so the world still moves
+//! with the audio and stands still while the host is stopped.
+
+use ambient_core::engine::WorldEngine;
+use ambient_core::events::Event;
+use audio::mapping::{MappingProfile, WorldInputs};
+use audio::params::{AudioParams, SharedAudioParams};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// How often the world thread catches up with the audio.
+const POLL: Duration = Duration::from_millis(5);
+
+struct Shared {
+    params: SharedAudioParams,
+    /// Nanoseconds of audio played, written only by the audio thread.
+    played_ns: AtomicU64,
+    stop: AtomicBool,
+}
+
+/// A world and the thread that runs it. Dropping it stops the thread.
+pub struct Embedded {
+    shared: Arc<Shared>,
+    world: Arc<Mutex<WorldEngine>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Embedded {
+    /// Starts a world at rest, mapped through the default profile.
+    pub fn start() -> std::io::Result<Self> {
+        let world = WorldEngine::new();
+        let profile = MappingProfile::default();
+        let initial = profile.map(&WorldInputs::from_snapshot(&world.get_snapshot()));
+        let shared = Arc::new(Shared {
+            params: SharedAudioParams::new(initial),
+            played_ns: AtomicU64::new(0),
+            stop: AtomicBool::new(false),
+        });
+        let world = Arc::new(Mutex::new(world));
+        let thread = {
+            let (shared, world) = (Arc::clone(&shared), Arc::clone(&world));
+            std::thread::Builder::new()
+                .name("ambient-world".to_string())
+                .spawn(move || {
+                    let mut seen = 0;
+                    while !shared.stop.load(Ordering::Relaxed) {
+                        let played = shared.played_ns.load(Ordering::Relaxed);
+                        if played > seen {
+                            let dt = (played - seen) as f64 * 1e-9;
+                            seen = played;
+                            let snapshot = world
+                                .lock()
+                                .unwrap()
+                                .apply(Event::Tick { dt })
+                                .resulting_snapshot;
+                            shared
+                                .params
+                                .set(profile.map(&WorldInputs::from_snapshot(&snapshot)));
+                        }
+                        std::thread::sleep(POLL);
+                    }
+                })?
+        };
+        Ok(Self {
+            shared,
+            world,
+            thread: Some(thread),
+        })
+    }
+
+    /// Notes `secs` more of audio played. Safe on the audio thread.
+    pub fn advance(&self, secs: f64) {
+        self.shared
+            .played_ns
+            .fetch_add((secs * 1e9) as u64, Ordering::Relaxed);
+    }
+
+    /// The parameters the world last mapped to. Safe on the audio thread.
+    pub fn params(&self) -> AudioParams {
+        self.shared.params.get()
+    }
+
+    /// Simulated seconds the world has run. Takes the world's lock, so not
+    /// for the audio thread.
+    pub fn sim_time(&self) -> f64 {
+        self.world.lock().unwrap().sim_time()
+    }
+}
+
+impl Drop for Embedded {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
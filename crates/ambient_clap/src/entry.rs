@@ -0,0 +1,718 @@
+//! The C ABI: the `clap_entry` symbol, its factory of one plugin and the
+//! params, audio-ports and state extensions.
+//!
+//! The host calls in from its main thread and its audio thread. [`Plugin`]
+//! sits behind a mutex the audio thread only ever tries, playing silence for
+//! a block on the rare occasion the main thread holds it; parameter values are
+//! read from [`SharedValues`] without locking at all.
+
+use crate::params::{PARAMS, SOURCE_ID, SavedState, SharedValues, Source, find};
+use crate::plugin::Plugin;
+use clap_sys::entry::clap_plugin_entry;
+use clap_sys::events::{
+    CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_PARAM_VALUE, clap_event_param_value, clap_input_events,
+    clap_output_events,
+};
+use clap_sys::ext::audio_ports::{
+    CLAP_AUDIO_PORT_IS_MAIN, CLAP_EXT_AUDIO_PORTS, CLAP_PORT_STEREO, clap_audio_port_info,
+    clap_plugin_audio_ports,
+};
+use clap_sys::ext::params::{
+    CLAP_EXT_PARAMS, CLAP_PARAM_IS_AUTOMATABLE, CLAP_PARAM_IS_ENUM, CLAP_PARAM_IS_STEPPED,
+    clap_param_info, clap_plugin_params,
+};
+use clap_sys::ext::state::{CLAP_EXT_STATE, clap_plugin_state};
+use clap_sys::factory::plugin_factory::{CLAP_PLUGIN_FACTORY_ID, clap_plugin_factory};
+use clap_sys::host::clap_host;
+use clap_sys::id::{CLAP_INVALID_ID, clap_id};
+use clap_sys::plugin::{clap_plugin, clap_plugin_descriptor};
+use clap_sys::plugin_features::{
+    CLAP_PLUGIN_FEATURE_INSTRUMENT, CLAP_PLUGIN_FEATURE_STEREO, CLAP_PLUGIN_FEATURE_SYNTHESIZER,
+};
+use clap_sys::process::{
+    CLAP_PROCESS_CONTINUE, CLAP_PROCESS_ERROR, clap_process, clap_process_status,
+};
+use clap_sys::stream::{clap_istream, clap_ostream};
+use clap_sys::version::CLAP_VERSION;
+use std::ffi::{CStr, c_char, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{error, warn};
+
+/// The plugin's id, as hosts store it in sessions.
+pub const PLUGIN_ID: &CStr = c"world.ambient.layers";
+
+const VERSION: &CStr =
+    match CStr::from_bytes_with_nul(concat!(env!("CARGO_PKG_VERSION"), "\0").as_bytes()) {
+        Ok(version) => version,
+        Err(_) => panic!("the package version has no nul"),
+    };
+
+/// Pointers to the static feature strings, null-terminated.
+struct Features([*const c_char; 4]);
+
+// The pointers are to 'static strings that are never written.
+unsafe impl Sync for Features {}
+
+static FEATURES: Features = Features([
+    CLAP_PLUGIN_FEATURE_INSTRUMENT.as_ptr(),
+    CLAP_PLUGIN_FEATURE_SYNTHESIZER.as_ptr(),
+    CLAP_PLUGIN_FEATURE_STEREO.as_ptr(),
+    std::ptr::null(),
+]);
+
+static DESCRIPTOR: clap_plugin_descriptor = clap_plugin_descriptor {
+    clap_version: CLAP_VERSION,
+    id: PLUGIN_ID.as_ptr(),
+    name: c"Ambient World".as_ptr(),
+    vendor: c"ambient_world".as_ptr(),
+    url: c"".as_ptr(),
+    manual_url: c"".as_ptr(),
+    support_url: c"".as_ptr(),
+    version: VERSION.as_ptr(),
+    description: c"The ambient world's layer stack, played from host automation, an embedded world or a running server".as_ptr(),
+    features: FEATURES.0.as_ptr(),
+};
+
+/// The entry point hosts look up by name.
+#[unsafe(export_name = "clap_entry")]
+pub static CLAP_ENTRY: clap_plugin_entry = clap_plugin_entry {
+    clap_version: CLAP_VERSION,
+    init: Some(entry_init),
+    deinit: Some(entry_deinit),
+    get_factory: Some(entry_get_factory),
+};
+
+static FACTORY: clap_plugin_factory = clap_plugin_factory {
+    get_plugin_count: Some(factory_plugin_count),
+    get_plugin_descriptor: Some(factory_plugin_descriptor),
+    create_plugin: Some(factory_create_plugin),
+};
+
+static PARAMS_EXT: clap_plugin_params = clap_plugin_params {
+    count: Some(params_count),
+    get_info: Some(params_get_info),
+    get_value: Some(params_get_value),
+    value_to_text: Some(params_value_to_text),
+    text_to_value: Some(params_text_to_value),
+    flush: Some(params_flush),
+};
+
+static AUDIO_PORTS_EXT: clap_plugin_audio_ports = clap_plugin_audio_ports {
+    count: Some(audio_ports_count),
+    get: Some(audio_ports_get),
+};
+
+static STATE_EXT: clap_plugin_state = clap_plugin_state {
+    save: Some(state_save),
+    load: Some(state_load),
+};
+
+/// One plugin instance; `clap.plugin_data` points back to it.
+struct Instance {
+    clap: clap_plugin,
+    host: *const clap_host,
+    values: Arc<SharedValues>,
+    plugin: Mutex<Plugin>,
+    /// A main-thread callback is pending to open or close the remote session.
+    callback_requested: AtomicBool,
+}
+
+/// The instance behind `plugin`.
+///
+/// # Safety
+/// `plugin` must be one [`factory_create_plugin`] returned and not yet
+/// destroyed.
+unsafe fn instance<'a>(plugin: *const clap_plugin) -> &'a Instance {
+    unsafe { &*((*plugin).plugin_data as *const Instance) }
+}
+
+/// Copies `text` into a C string buffer of `capacity` bytes, truncating.
+fn write_text(text: &str, buffer: *mut c_char, capacity: usize) -> bool {
+    if buffer.is_null() || capacity == 0 {
+        return false;
+    }
+    let length = text.len().min(capacity - 1);
+    // SAFETY: the host gave `capacity` writable bytes at `buffer`.
+    unsafe {
+        std::ptr::copy_nonoverlapping(text.as_ptr() as *const c_char, buffer, length);
+        *buffer.add(length) = 0;
+    }
+    true
+}
+
+/// Applies the parameter changes among `events`.
+///
+/// # Safety
+/// `events` must be null or a valid host event list.
+unsafe fn apply_events(plugin: &mut Plugin, events: *const clap_input_events) {
+    let Some(list) = (unsafe { events.as_ref() }) else {
+        return;
+    };
+    let (Some(size), Some(get)) = (list.size, list.get) else {
+        return;
+    };
+    for index in 0..unsafe { size(list) } {
+        let Some(header) = (unsafe { get(list, index).as_ref() }) else {
+            continue;
+        };
+        if header.space_id == CLAP_CORE_EVENT_SPACE_ID && header.type_ == CLAP_EVENT_PARAM_VALUE {
+            let event = unsafe { &*(header as *const _ as *const clap_event_param_value) };
+            plugin.set_param(event.param_id, event.value);
+        }
+    }
+}
+
+/// Asks the host for an `on_main_thread` call if the remote session needs
+/// opening or closing and none is pending.
+fn request_remote_sync(instance: &Instance, plugin: &Plugin) {
+    if !plugin.remote_out_of_sync() || instance.callback_requested.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    // SAFETY: the host outlives its plugins.
+    let host = unsafe { &*instance.host };
+    if let Some(request_callback) = host.request_callback {
+        unsafe { request_callback(instance.host) };
+    }
+}
+
+unsafe extern "C" fn entry_init(_plugin_path: *const c_char) -> bool {
+    true
+}
+
+unsafe extern "C" fn entry_deinit() {}
+
+unsafe extern "C" fn entry_get_factory(factory_id: *const c_char) -> *const c_void {
+    if !factory_id.is_null() && unsafe { CStr::from_ptr(factory_id) } == CLAP_PLUGIN_FACTORY_ID {
+        &FACTORY as *const clap_plugin_factory as *const c_void
+    } else {
+        std::ptr::null()
+    }
+}
+
+unsafe extern "C" fn factory_plugin_count(_factory: *const clap_plugin_factory) -> u32 {
+    1
+}
+
+unsafe extern "C" fn factory_plugin_descriptor(
+    _factory: *const clap_plugin_factory,
+    index: u32,
+) -> *const clap_plugin_descriptor {
+    if index == 0 {
+        &DESCRIPTOR
+    } else {
+        std::ptr::null()
+    }
+}
+
+unsafe extern "C" fn factory_create_plugin(
+    _factory: *const clap_plugin_factory,
+    host: *const clap_host,
+    plugin_id: *const c_char,
+) -> *const clap_plugin {
+    if host.is_null() || plugin_id.is_null() || unsafe { CStr::from_ptr(plugin_id) } != PLUGIN_ID {
+        return std::ptr::null();
+    }
+    let plugin = Plugin::new();
+    let instance = Box::into_raw(Box::new(Instance {
+        clap: clap_plugin {
+            desc: &DESCRIPTOR,
+            plugin_data: std::ptr::null_mut(),
+            init: Some(plugin_init),
+            destroy: Some(plugin_destroy),
+            activate: Some(plugin_activate),
+            deactivate: Some(plugin_deactivate),
+            start_processing: Some(plugin_start_processing),
+            stop_processing: Some(plugin_stop_processing),
+            reset: Some(plugin_reset),
+            process: Some(plugin_process),
+            get_extension: Some(plugin_get_extension),
+            on_main_thread: Some(plugin_on_main_thread),
+        },
+        host,
+        values: plugin.values(),
+        plugin: Mutex::new(plugin),
+        callback_requested: AtomicBool::new(false),
+    }));
+    // SAFETY: just allocated; freed again in `plugin_destroy`.
+    unsafe {
+        (*instance).clap.plugin_data = instance as *mut c_void;
+        &(*instance).clap
+    }
+}
+
+unsafe extern "C" fn plugin_init(_plugin: *const clap_plugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_destroy(plugin: *const clap_plugin) {
+    let instance = unsafe { (*plugin).plugin_data as *mut Instance };
+    drop(unsafe { Box::from_raw(instance) });
+}
+
+unsafe extern "C" fn plugin_activate(
+    plugin: *const clap_plugin,
+    sample_rate: f64,
+    _min_frames_count: u32,
+    max_frames_count: u32,
+) -> bool {
+    let instance = unsafe { instance(plugin) };
+    let mut plugin = instance.plugin.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = plugin.activate(sample_rate, max_frames_count) {
+        error!("Could not start the embedded world: {}", e);
+        return false;
+    }
+    true
+}
+
+unsafe extern "C" fn plugin_deactivate(plugin: *const clap_plugin) {
+    let instance = unsafe { instance(plugin) };
+    let mut plugin = instance.plugin.lock().unwrap_or_else(|e| e.into_inner());
+    plugin.deactivate();
+}
+
+unsafe extern "C" fn plugin_start_processing(_plugin: *const clap_plugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_stop_processing(_plugin: *const clap_plugin) {}
+
+unsafe extern "C" fn plugin_reset(_plugin: *const clap_plugin) {}
+
+unsafe extern "C" fn plugin_process(
+    plugin: *const clap_plugin,
+    process: *const clap_process,
+) -> clap_process_status {
+    let instance = unsafe { instance(plugin) };
+    let process = unsafe { &*process };
+    let Some(output) = (unsafe { process.audio_outputs.as_ref() }) else {
+        return CLAP_PROCESS_ERROR;
+    };
+    if process.audio_outputs_count == 0 || output.data32.is_null() || output.channel_count < 2 {
+        return CLAP_PROCESS_ERROR;
+    }
+    let frames = process.frames_count as usize;
+    // SAFETY: the host gave two or more channels of `frames` samples each.
+    let (left, right) = unsafe {
+        (
+            std::slice::from_raw_parts_mut(*output.data32, frames),
+            std::slice::from_raw_parts_mut(*output.data32.add(1), frames),
+        )
+    };
+    let Ok(mut plugin) = instance.plugin.try_lock() else {
+        left.fill(0.0);
+        right.fill(0.0);
+        return CLAP_PROCESS_CONTINUE;
+    };
+    unsafe { apply_events(&mut plugin, process.in_events) };
+    plugin.process(left, right);
+    request_remote_sync(instance, &plugin);
+    CLAP_PROCESS_CONTINUE
+}
+
+unsafe extern "C" fn plugin_get_extension(
+    _plugin: *const clap_plugin,
+    id: *const c_char,
+) -> *const c_void {
+    if id.is_null() {
+        return std::ptr::null();
+    }
+    let id = unsafe { CStr::from_ptr(id) };
+    if id == CLAP_EXT_PARAMS {
+        &PARAMS_EXT as *const clap_plugin_params as *const c_void
+    } else if id == CLAP_EXT_AUDIO_PORTS {
+        &AUDIO_PORTS_EXT as *const clap_plugin_audio_ports as *const c_void
+    } else if id == CLAP_EXT_STATE {
+        &STATE_EXT as *const clap_plugin_state as *const c_void
+    } else {
+        std::ptr::null()
+    }
+}
+
+unsafe extern "C" fn plugin_on_main_thread(plugin: *const clap_plugin) {
+    let instance = unsafe { instance(plugin) };
+    let mut plugin = instance.plugin.lock().unwrap_or_else(|e| e.into_inner());
+    instance.callback_requested.store(false, Ordering::Release);
+    if let Err(e) = plugin.sync_remote() {
+        warn!("Could not follow the remote world: {}", e);
+    }
+}
+
+unsafe extern "C" fn params_count(_plugin: *const clap_plugin) -> u32 {
+    PARAMS.len() as u32
+}
+
+unsafe extern "C" fn params_get_info(
+    _plugin: *const clap_plugin,
+    param_index: u32,
+    param_info: *mut clap_param_info,
+) -> bool {
+    let (Some(spec), Some(info)) = (PARAMS.get(param_index as usize), unsafe {
+        param_info.as_mut()
+    }) else {
+        return false;
+    };
+    info.id = spec.id;
+    info.flags = CLAP_PARAM_IS_AUTOMATABLE;
+    if spec.stepped {
+        info.flags |= CLAP_PARAM_IS_STEPPED | CLAP_PARAM_IS_ENUM;
+    }
+    info.cookie = std::ptr::null_mut();
+    write_text(spec.name, info.name.as_mut_ptr(), info.name.len());
+    write_text("", info.module.as_mut_ptr(), info.module.len());
+    info.min_value = spec.min;
+    info.max_value = spec.max;
+    info.default_value = spec.default;
+    true
+}
+
+unsafe extern "C" fn params_get_value(
+    plugin: *const clap_plugin,
+    param_id: clap_id,
+    out_value: *mut f64,
+) -> bool {
+    let instance = unsafe { instance(plugin) };
+    match (instance.values.get(param_id), unsafe { out_value.as_mut() }) {
+        (Some(value), Some(out)) => {
+            *out = value;
+            true
+        }
+        _ => false,
+    }
+}
+
+unsafe extern "C" fn params_value_to_text(
+    _plugin: *const clap_plugin,
+    param_id: clap_id,
+    value: f64,
+    out_buffer: *mut c_char,
+    out_buffer_capacity: u32,
+) -> bool {
+    let Some(spec) = find(param_id) else {
+        return false;
+    };
+    let text = match spec.key {
+        "source" => Source::from_value(value).name().to_string(),
+        "base_freq_hz" => format!("{:.1} Hz", value),
+        "pulse_bpm" => format!("{:.1} BPM", value),
+        _ => format!("{:.3}", value),
+    };
+    write_text(&text, out_buffer, out_buffer_capacity as usize)
+}
+
+unsafe extern "C" fn params_text_to_value(
+    _plugin: *const clap_plugin,
+    param_id: clap_id,
+    param_value_text: *const c_char,
+    out_value: *mut f64,
+) -> bool {
+    if param_value_text.is_null() || find(param_id).is_none() {
+        return false;
+    }
+    let Ok(text) = unsafe { CStr::from_ptr(param_value_text) }.to_str() else {
+        return false;
+    };
+    let text = text.trim();
+    let value = if param_id == SOURCE_ID {
+        Source::ALL
+            .into_iter()
+            .find(|source| source.name().eq_ignore_ascii_case(text))
+            .map(Source::value)
+    } else {
+        // Units as `value_to_text` writes them
+        text.split_whitespace()
+            .next()
+            .and_then(|number| number.parse().ok())
+    };
+    match (value, unsafe { out_value.as_mut() }) {
+        (Some(value), Some(out)) => {
+            *out = value;
+            true
+        }
+        _ => false,
+    }
+}
+
+unsafe extern "C" fn params_flush(
+    plugin: *const clap_plugin,
+    in_: *const clap_input_events,
+    _out: *const clap_output_events,
+) {
+    let instance = unsafe { instance(plugin) };
+    let mut plugin = instance.plugin.lock().unwrap_or_else(|e| e.into_inner());
+    unsafe { apply_events(&mut plugin, in_) };
+    request_remote_sync(instance, &plugin);
+}
+
+unsafe extern "C" fn audio_ports_count(_plugin: *const clap_plugin, is_input: bool) -> u32 {
+    if is_input { 0 } else { 1 }
+}
+
+unsafe extern "C" fn audio_ports_get(
+    _plugin: *const clap_plugin,
+    index: u32,
+    is_input: bool,
+    info: *mut clap_audio_port_info,
+) -> bool {
+    let Some(info) = (unsafe { info.as_mut() }) else {
+        return false;
+    };
+    if is_input || index != 0 {
+        return false;
+    }
+    info.id = 0;
+    write_text("Main", info.name.as_mut_ptr(), info.name.len());
+    info.flags = CLAP_AUDIO_PORT_IS_MAIN;
+    info.channel_count = 2;
+    info.port_type = CLAP_PORT_STEREO.as_ptr();
+    info.in_place_pair = CLAP_INVALID_ID;
+    true
+}
+
+unsafe extern "C" fn state_save(plugin: *const clap_plugin, stream: *const clap_ostream) -> bool {
+    let instance = unsafe { instance(plugin) };
+    let saved = instance
+        .plugin
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .save_state();
+    let Ok(bytes) = serde_json::to_vec(&saved) else {
+        return false;
+    };
+    let Some(stream) = (unsafe { stream.as_ref() }) else {
+        return false;
+    };
+    let Some(write) = stream.write else {
+        return false;
+    };
+    let mut written = 0;
+    while written < bytes.len() {
+        let rest = &bytes[written..];
+        let count = unsafe { write(stream, rest.as_ptr() as *const c_void, rest.len() as u64) };
+        if count <= 0 {
+            return false;
+        }
+        written += count as usize;
+    }
+    true
+}
+
+unsafe extern "C" fn state_load(plugin: *const clap_plugin, stream: *const clap_istream) -> bool {
+    let instance = unsafe { instance(plugin) };
+    let Some(stream) = (unsafe { stream.as_ref() }) else {
+        return false;
+    };
+    let Some(read) = stream.read else {
+        return false;
+    };
+    let mut bytes = Vec::new();
+    let mut chunk = [0u8; 1024];
+    loop {
+        let count = unsafe {
+            read(
+                stream,
+                chunk.as_mut_ptr() as *mut c_void,
+                chunk.len() as u64,
+            )
+        };
+        match count {
+            0 => break,
+            count if count < 0 => return false,
+            count => bytes.extend_from_slice(&chunk[..count as usize]),
+        }
+    }
+    let Ok(saved) = serde_json::from_slice::<SavedState>(&bytes) else {
+        return false;
+    };
+    let mut plugin = instance.plugin.lock().unwrap_or_else(|e| e.into_inner());
+    plugin.load_state(&saved);
+    // Loading happens on the main thread, where the session may open
+    if let Err(e) = plugin.sync_remote() {
+        warn!("Could not follow the remote world: {}", e);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap_sys::audio_buffer::clap_audio_buffer;
+    use clap_sys::events::clap_event_header;
+
+    /// A host that supports nothing.
+    fn host() -> clap_host {
+        clap_host {
+            clap_version: CLAP_VERSION,
+            host_data: std::ptr::null_mut(),
+            name: c"test".as_ptr(),
+            vendor: c"test".as_ptr(),
+            url: c"".as_ptr(),
+            version: c"1".as_ptr(),
+            get_extension: None,
+            request_restart: None,
+            request_process: None,
+            request_callback: None,
+        }
+    }
+
+    unsafe extern "C" fn events_size(list: *const clap_input_events) -> u32 {
+        unsafe { (*((*list).ctx as *const Vec<clap_event_param_value>)).len() as u32 }
+    }
+
+    unsafe extern "C" fn events_get(
+        list: *const clap_input_events,
+        index: u32,
+    ) -> *const clap_event_header {
+        let events = unsafe { &*((*list).ctx as *const Vec<clap_event_param_value>) };
+        &events[index as usize].header
+    }
+
+    fn param_value(param_id: clap_id, value: f64) -> clap_event_param_value {
+        clap_event_param_value {
+            header: clap_event_header {
+                size: std::mem::size_of::<clap_event_param_value>() as u32,
+                time: 0,
+                space_id: CLAP_CORE_EVENT_SPACE_ID,
+                type_: CLAP_EVENT_PARAM_VALUE,
+                flags: 0,
+            },
+            param_id,
+            cookie: std::ptr::null_mut(),
+            note_id: -1,
+            port_index: -1,
+            channel: -1,
+            key: -1,
+            value,
+        }
+    }
+
+    unsafe extern "C" fn ostream_write(
+        stream: *const clap_ostream,
+        buffer: *const c_void,
+        size: u64,
+    ) -> i64 {
+        let out = unsafe { &mut *((*stream).ctx as *mut Vec<u8>) };
+        // Short writes, as hosts may make
+        let size = size.min(7) as usize;
+        out.extend_from_slice(unsafe { std::slice::from_raw_parts(buffer as *const u8, size) });
+        size as i64
+    }
+
+    unsafe extern "C" fn istream_read(
+        stream: *const clap_istream,
+        buffer: *mut c_void,
+        size: u64,
+    ) -> i64 {
+        let input = unsafe { &mut *((*stream).ctx as *mut &[u8]) };
+        let size = (size as usize).min(input.len());
+        unsafe { std::ptr::copy_nonoverlapping(input.as_ptr(), buffer as *mut u8, size) };
+        *input = &input[size..];
+        size as i64
+    }
+
+    #[test]
+    fn test_a_host_can_drive_the_plugin() {
+        let host = host();
+        unsafe {
+            let factory = (CLAP_ENTRY.get_factory.unwrap())(CLAP_PLUGIN_FACTORY_ID.as_ptr())
+                as *const clap_plugin_factory;
+            assert_eq!((*factory).get_plugin_count.unwrap()(factory), 1);
+            let descriptor = (*factory).get_plugin_descriptor.unwrap()(factory, 0);
+            assert_eq!(CStr::from_ptr((*descriptor).id), PLUGIN_ID);
+            let create = (*factory).create_plugin.unwrap();
+            assert!(create(factory, &host, c"other".as_ptr()).is_null());
+            let plugin = create(factory, &host, PLUGIN_ID.as_ptr());
+            let plugin = &*plugin;
+            assert!(plugin.init.unwrap()(plugin));
+
+            let params = &*(plugin.get_extension.unwrap()(plugin, CLAP_EXT_PARAMS.as_ptr())
+                as *const clap_plugin_params);
+            assert_eq!(params.count.unwrap()(plugin), PARAMS.len() as u32);
+            let mut info = std::mem::zeroed::<clap_param_info>();
+            assert!(params.get_info.unwrap()(plugin, 2, &mut info));
+            assert_eq!(CStr::from_ptr(info.name.as_ptr()), c"Base frequency");
+
+            // Switch to the host's values and set the frequency
+            let events = vec![param_value(SOURCE_ID, 0.0), param_value(2, 220.0)];
+            let list = clap_input_events {
+                ctx: &events as *const _ as *mut c_void,
+                size: Some(events_size),
+                get: Some(events_get),
+            };
+            params.flush.unwrap()(plugin, &list, std::ptr::null());
+            let mut value = 0.0;
+            assert!(params.get_value.unwrap()(plugin, 2, &mut value));
+            assert!((value - 220.0).abs() < 1e-6);
+            let mut text = [0 as c_char; 32];
+            assert!(params.value_to_text.unwrap()(
+                plugin,
+                2,
+                value,
+                text.as_mut_ptr(),
+                32
+            ));
+            assert_eq!(CStr::from_ptr(text.as_ptr()), c"220.0 Hz");
+            assert!(params.text_to_value.unwrap()(
+                plugin,
+                2,
+                text.as_ptr(),
+                &mut value
+            ));
+            assert!((value - 220.0).abs() < 1e-6);
+
+            assert!(plugin.activate.unwrap()(plugin, 48_000.0, 1, 512));
+            let (mut left, mut right) = (vec![0.0f32; 512], vec![0.0f32; 512]);
+            let mut channels = [left.as_mut_ptr(), right.as_mut_ptr()];
+            let mut output = clap_audio_buffer {
+                data32: channels.as_mut_ptr(),
+                data64: std::ptr::null_mut(),
+                channel_count: 2,
+                latency: 0,
+                constant_mask: 0,
+            };
+            let process = clap_process {
+                steady_time: -1,
+                frames_count: 512,
+                transport: std::ptr::null(),
+                audio_inputs: std::ptr::null(),
+                audio_outputs: &mut output,
+                audio_inputs_count: 0,
+                audio_outputs_count: 1,
+                in_events: std::ptr::null(),
+                out_events: std::ptr::null(),
+            };
+            let mut heard = false;
+            for _ in 0..50 {
+                let status = plugin.process.unwrap()(plugin, &process);
+                assert_eq!(status, CLAP_PROCESS_CONTINUE);
+                heard |= left.iter().any(|s| s.abs() > 1e-4);
+            }
+            assert!(heard);
+            plugin.deactivate.unwrap()(plugin);
+
+            // Saved and loaded into a fresh instance
+            let state = &*(plugin.get_extension.unwrap()(plugin, CLAP_EXT_STATE.as_ptr())
+                as *const clap_plugin_state);
+            let mut saved = Vec::new();
+            let ostream = clap_ostream {
+                ctx: &mut saved as *mut Vec<u8> as *mut c_void,
+                write: Some(ostream_write),
+            };
+            assert!(state.save.unwrap()(plugin, &ostream));
+            let restored = &*create(factory, &host, PLUGIN_ID.as_ptr());
+            let mut input: &[u8] = &saved;
+            let istream = clap_istream {
+                ctx: &mut input as *mut &[u8] as *mut c_void,
+                read: Some(istream_read),
+            };
+            assert!(state.load.unwrap()(restored, &istream));
+            assert!(params.get_value.unwrap()(restored, SOURCE_ID, &mut value));
+            assert_eq!(value, Source::Host.value());
+            assert!(params.get_value.unwrap()(restored, 2, &mut value));
+            assert!((value - 220.0).abs() < 1e-6);
+
+            plugin.destroy.unwrap()(plugin);
+            restored.destroy.unwrap()(restored);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! The audio crate's layer stack as a CLAP plugin, so the engine can live
+//! inside a DAW session.
+//!
+//! The plugin is a stereo instrument whose parameters are the
+//! [`AudioParams`](audio::params::AudioParams) the layers play, after a
+//! `Source` parameter choosing where those come from:
+//!
+//! - `host`: the host's own values, automated like any synth's
+//! - `embedded` (the default): a world simulated inside the plugin on a
+//!   thread of its own, advanced with the audio and mapped through the
+//!   default profile
+//! - `remote`: a running server's world, followed over the WebSocket
+//!   protocol at `AMBIENT_URL` (default `http://localhost:3000`), with
+//!   `AMBIENT_TOKEN` as its API token when set; needs the `remote` feature,
+//!   without which it plays the embedded world
+//!
+//! With a world as the source, the host reads the parameters back as the
+//! world moves them, and the values it sets are kept for when it takes over
+//! again. Sessions save the source and the host's values.
+//!
+//! Build with `cargo build --release -p ambient_clap` and copy the library
+//! into the host's CLAP folder with a `.clap` extension, e.g.
+//! `cp target/release/libambient_clap.so ~/.clap/ambient.clap` on Linux.
+
+pub mod embedded;
+mod entry;
+pub mod params;
+pub mod plugin;
+#[cfg(feature = "remote")]
+pub mod remote;
+
+pub use entry::{CLAP_ENTRY, PLUGIN_ID};
@@ -0,0 +1,233 @@
+//! The plugin's parameters: where the sound's parameters come from, then the
+//! [`AudioParams`] the layer stack plays.
+
+use audio::params::{AudioParams, SharedAudioParams};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Id of the source parameter; the audio parameters follow from 1.
+pub const SOURCE_ID: u32 = 0;
+
+/// Where the audio parameters come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// The host's values, automated like any synth's.
+    Host,
+    /// A world simulated inside the plugin, advanced with the audio.
+    Embedded,
+    /// A running server's world, followed over the WebSocket protocol.
+    Remote,
+}
+
+impl Source {
+    pub const ALL: [Source; 3] = [Source::Host, Source::Embedded, Source::Remote];
+
+    /// The source a parameter value selects, rounding to the nearest.
+    pub fn from_value(value: f64) -> Self {
+        let index = value.round().clamp(0.0, 2.0) as usize;
+        Self::ALL[index]
+    }
+
+    pub fn value(self) -> f64 {
+        self as u32 as f64
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Host => "host",
+            Source::Embedded => "embedded",
+            Source::Remote => "remote",
+        }
+    }
+}
+
+/// One parameter as the host sees it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamSpec {
+    pub id: u32,
+    /// Name shown by the host.
+    pub name: &'static str,
+    /// Key in saved state.
+    pub key: &'static str,
+    pub min: f64,
+    pub max: f64,
+    pub default: f64,
+    /// Whole values only.
+    pub stepped: bool,
+}
+
+const fn spec(id: u32, name: &'static str, key: &'static str, range: [f64; 3]) -> ParamSpec {
+    ParamSpec {
+        id,
+        name,
+        key,
+        min: range[0],
+        max: range[1],
+        default: range[2],
+        stepped: false,
+    }
+}
+
+/// Every parameter, in id order. The audio parameters default to what a
+/// world at rest, every parameter at 0.5, plays.
+pub const PARAMS: [ParamSpec; 12] = [
+    ParamSpec {
+        stepped: true,
+        ..spec(SOURCE_ID, "Source", "source", [0.0, 2.0, 1.0])
+    },
+    spec(1, "Master gain", "master_gain", [0.0, 1.0, 0.1]),
+    spec(2, "Base frequency", "base_freq_hz", [40.0, 1000.0, 160.0]),
+    spec(3, "Detune ratio", "detune_ratio", [1.0, 1.05, 1.005]),
+    spec(4, "Brightness", "brightness", [0.0, 1.0, 0.75]),
+    spec(5, "Motion", "motion", [0.0, 1.0, 0.25]),
+    spec(6, "Texture", "texture", [0.0, 1.0, 0.15]),
+    spec(7, "Sparkle impulse", "sparkle_impulse", [0.0, 1.0, 0.0]),
+    spec(8, "Pulse BPM", "pulse_bpm", [0.0, 240.0, 0.0]),
+    spec(9, "Reverb size", "reverb_size", [0.0, 1.0, 0.5]),
+    spec(10, "Pre-delay", "pre_delay_ms", [0.0, 200.0, 40.0]),
+    spec(11, "Grit", "grit", [0.0, 1.0, 0.0]),
+];
+
+pub fn find(id: u32) -> Option<&'static ParamSpec> {
+    PARAMS.get(id as usize)
+}
+
+/// The audio parameters at their defaults.
+pub fn defaults() -> AudioParams {
+    let mut params = AudioParams::default();
+    for spec in &PARAMS[1..] {
+        set(&mut params, spec.id, spec.default);
+    }
+    params
+}
+
+fn field(params: &mut AudioParams, id: u32) -> Option<&mut f32> {
+    match id {
+        1 => Some(&mut params.master_gain),
+        2 => Some(&mut params.base_freq_hz),
+        3 => Some(&mut params.detune_ratio),
+        4 => Some(&mut params.brightness),
+        5 => Some(&mut params.motion),
+        6 => Some(&mut params.texture),
+        7 => Some(&mut params.sparkle_impulse),
+        8 => Some(&mut params.pulse_bpm),
+        9 => Some(&mut params.reverb_size),
+        10 => Some(&mut params.pre_delay_ms),
+        11 => Some(&mut params.grit),
+        _ => None,
+    }
+}
+
+/// The audio parameter `id` of `params`.
+pub fn get(params: &AudioParams, id: u32) -> Option<f64> {
+    let mut params = *params;
+    field(&mut params, id).map(|value| *value as f64)
+}
+
+/// Sets audio parameter `id`, clamped to its range. Returns whether `id`
+/// names an audio parameter.
+pub fn set(params: &mut AudioParams, id: u32, value: f64) -> bool {
+    let (Some(spec), Some(field)) = (find(id), field(params, id)) else {
+        return false;
+    };
+    if value.is_finite() {
+        *field = value.clamp(spec.min, spec.max) as f32;
+    }
+    true
+}
+
+/// What is playing, readable from any thread.
+#[derive(Debug)]
+pub struct SharedValues {
+    source: AtomicU32,
+    params: SharedAudioParams,
+}
+
+impl SharedValues {
+    pub fn new(source: Source, params: AudioParams) -> Self {
+        Self {
+            source: AtomicU32::new(source as u32),
+            params: SharedAudioParams::new(params),
+        }
+    }
+
+    pub fn store(&self, source: Source, params: AudioParams) {
+        self.source.store(source as u32, Ordering::Relaxed);
+        self.params.set(params);
+    }
+
+    pub fn get(&self, id: u32) -> Option<f64> {
+        if id == SOURCE_ID {
+            return Some(self.source.load(Ordering::Relaxed) as f64);
+        }
+        get(&self.params.get(), id)
+    }
+}
+
+/// What a host session saves: the source and the host's own values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedState {
+    pub source: Source,
+    /// Audio parameters by key; missing ones take their defaults.
+    #[serde(default)]
+    pub params: BTreeMap<String, f64>,
+}
+
+impl SavedState {
+    pub fn new(source: Source, params: &AudioParams) -> Self {
+        let params = PARAMS[1..]
+            .iter()
+            .filter_map(|spec| Some((spec.key.to_string(), get(params, spec.id)?)))
+            .collect();
+        Self { source, params }
+    }
+
+    /// The saved audio parameters over the defaults.
+    pub fn audio_params(&self) -> AudioParams {
+        let mut params = defaults();
+        for spec in &PARAMS[1..] {
+            if let Some(value) = self.params.get(spec.key) {
+                set(&mut params, spec.id, *value);
+            }
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audio::mapping::WorldInputs;
+
+    #[test]
+    fn test_params_round_trip_and_clamp() {
+        for (index, spec) in PARAMS.iter().enumerate() {
+            assert_eq!(spec.id as usize, index);
+            assert!((spec.min..=spec.max).contains(&spec.default));
+        }
+        let mut params = defaults();
+        let rest = AudioParams::from_world_state(&WorldInputs::default());
+        for id in (1..7).chain(9..12) {
+            assert!((get(&params, id).unwrap() - get(&rest, id).unwrap()).abs() < 1e-6);
+        }
+
+        assert!(set(&mut params, 2, 2000.0));
+        assert_eq!(params.base_freq_hz, 1000.0);
+        assert!(set(&mut params, 4, f64::NAN));
+        assert_eq!(params.brightness, 0.75);
+        assert!(!set(&mut params, SOURCE_ID, 2.0));
+        assert!(!set(&mut params, 12, 0.5));
+
+        assert_eq!(Source::from_value(1.6), Source::Remote);
+        assert_eq!(Source::from_value(-3.0), Source::Host);
+
+        let saved = SavedState::new(Source::Host, &params);
+        let json = serde_json::to_string(&saved).unwrap();
+        let loaded: SavedState = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.audio_params(), params);
+        let partial: SavedState = serde_json::from_str(r#"{"source": "remote"}"#).unwrap();
+        assert_eq!(partial.audio_params(), defaults());
+    }
+}
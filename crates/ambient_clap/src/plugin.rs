@@ -0,0 +1,238 @@
+//! The plugin without its C ABI: parameters in, stereo blocks out.
+
+use crate::embedded::Embedded;
+use crate::params::{self, SavedState, SharedValues, Source};
+#[cfg(feature = "remote")]
+use crate::remote::Remote;
+use audio::mixer::Mixer;
+use audio::params::AudioParams;
+use std::sync::Arc;
+
+/// The layer stack and whichever source feeds it.
+pub struct Plugin {
+    source: Source,
+    /// The host's values, played from [`Source::Host`].
+    host_params: AudioParams,
+    values: Arc<SharedValues>,
+    /// Started with the first activation and kept until the plugin goes.
+    embedded: Option<Embedded>,
+    #[cfg(feature = "remote")]
+    remote: Option<Remote>,
+    sample_rate: f64,
+    mixer: Option<Mixer>,
+    /// Interleaved stereo for the mixer to render into.
+    scratch: Vec<f32>,
+}
+
+impl Default for Plugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin {
+    pub fn new() -> Self {
+        let source = Source::Embedded;
+        let host_params = params::defaults();
+        Self {
+            source,
+            host_params,
+            values: Arc::new(SharedValues::new(source, host_params)),
+            embedded: None,
+            #[cfg(feature = "remote")]
+            remote: None,
+            sample_rate: 48_000.0,
+            mixer: None,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// What is playing, for reading off the audio thread.
+    pub fn values(&self) -> Arc<SharedValues> {
+        Arc::clone(&self.values)
+    }
+
+    pub fn source(&self) -> Source {
+        self.source
+    }
+
+    /// Builds the layer stack for `sample_rate` and blocks of up to
+    /// `max_frames`, starting the embedded world if it has not yet been.
+    /// Returns an error if its thread could not be started.
+    pub fn activate(&mut self, sample_rate: f64, max_frames: u32) -> std::io::Result<()> {
+        if self.embedded.is_none() {
+            self.embedded = Some(Embedded::start()?);
+        }
+        self.sample_rate = sample_rate;
+        self.mixer = Some(Mixer::new(sample_rate as f32));
+        self.scratch = vec![0.0; max_frames as usize * 2];
+        Ok(())
+    }
+
+    pub fn deactivate(&mut self) {
+        self.mixer = None;
+    }
+
+    /// Sets parameter `id`. Audio parameters set from a world source are
+    /// kept for when the source returns to the host.
+    pub fn set_param(&mut self, id: u32, value: f64) {
+        if id == params::SOURCE_ID {
+            self.source = Source::from_value(value);
+        } else {
+            params::set(&mut self.host_params, id, value);
+        }
+        if self.source == Source::Host {
+            self.values.store(self.source, self.host_params);
+        }
+    }
+
+    /// Whether the remote session needs opening or closing, which
+    /// [`Plugin::sync_remote`] does off the audio thread.
+    #[cfg(feature = "remote")]
+    pub fn remote_out_of_sync(&self) -> bool {
+        (self.source == Source::Remote) != self.remote.is_some()
+    }
+
+    #[cfg(not(feature = "remote"))]
+    pub fn remote_out_of_sync(&self) -> bool {
+        false
+    }
+
+    /// Opens the remote session while the source is remote and closes it
+    /// otherwise. Returns an error if it could not be opened.
+    #[cfg(feature = "remote")]
+    pub fn sync_remote(&mut self) -> std::io::Result<()> {
+        match (self.source == Source::Remote, self.remote.is_some()) {
+            (true, false) => self.remote = Some(Remote::from_env()?),
+            (false, true) => self.remote = None,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "remote"))]
+    pub fn sync_remote(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// The parameters to play next from the current source, after `dt`
+    /// seconds. Without the `remote` feature, the remote source plays the
+    /// embedded world; before the first activation, the world sources play
+    /// the host's values.
+    fn next_params(&mut self, dt: f64) -> AudioParams {
+        match self.source {
+            Source::Host => self.host_params,
+            #[cfg(feature = "remote")]
+            Source::Remote => self
+                .remote
+                .as_ref()
+                .map_or(self.host_params, |remote| remote.params()),
+            _ => self.embedded.as_ref().map_or(self.host_params, |embedded| {
+                embedded.advance(dt);
+                embedded.params()
+            }),
+        }
+    }
+
+    /// Renders `left.len()` frames into `left` and `right`, which must be
+    /// the same length. Writes silence while inactive.
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let frames = left.len().min(right.len());
+        let params = self.next_params(frames as f64 / self.sample_rate);
+        self.values.store(self.source, params);
+        let Some(mixer) = self.mixer.as_mut() else {
+            left.fill(0.0);
+            right.fill(0.0);
+            return;
+        };
+        let block = (self.scratch.len() / 2).max(1);
+        for start in (0..frames).step_by(block) {
+            let end = (start + block).min(frames);
+            let scratch = &mut self.scratch[..(end - start) * 2];
+            mixer.process(scratch, &params, 2);
+            for (i, frame) in scratch.chunks_exact(2).enumerate() {
+                left[start + i] = frame[0];
+                right[start + i] = frame[1];
+            }
+        }
+    }
+
+    /// The source and the host's values, for the host's session.
+    pub fn save_state(&self) -> SavedState {
+        SavedState::new(self.source, &self.host_params)
+    }
+
+    pub fn load_state(&mut self, state: &SavedState) {
+        self.source = state.source;
+        self.host_params = state.audio_params();
+        self.values.store(self.source, self.host_params);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(plugin: &mut Plugin, frames: usize) -> (Vec<f32>, Vec<f32>) {
+        let (mut left, mut right) = (vec![0.0; frames], vec![0.0; frames]);
+        plugin.process(&mut left, &mut right);
+        (left, right)
+    }
+
+    #[test]
+    fn test_host_values_play_and_are_reported() {
+        let mut plugin = Plugin::new();
+        let values = plugin.values();
+        plugin.set_param(params::SOURCE_ID, Source::Host.value());
+        plugin.set_param(1, 0.3);
+        plugin.set_param(2, 220.0);
+        assert_eq!(values.get(params::SOURCE_ID), Some(0.0));
+        assert!((values.get(2).unwrap() - 220.0).abs() < 1e-6);
+
+        // Inactive, it is silent
+        assert!(render(&mut plugin, 64).0.iter().all(|s| *s == 0.0));
+
+        plugin.activate(48_000.0, 256).unwrap();
+        let mut tail = Vec::new();
+        for _ in 0..100 {
+            // Larger than the declared maximum, rendered in pieces
+            let (left, right) = render(&mut plugin, 600);
+            assert!(left.iter().chain(&right).all(|s| s.is_finite()));
+            tail = left;
+        }
+        assert!(tail.iter().any(|s| s.abs() > 1e-4));
+
+        let saved = plugin.save_state();
+        let mut restored = Plugin::new();
+        restored.load_state(&saved);
+        assert_eq!(restored.source(), Source::Host);
+        assert!((restored.values().get(1).unwrap() - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_embedded_world_drives_the_params() {
+        let mut plugin = Plugin::new();
+        assert_eq!(plugin.source(), Source::Embedded);
+        assert!(!plugin.remote_out_of_sync());
+        plugin.activate(48_000.0, 512).unwrap();
+        // The host's values are kept but not played
+        plugin.set_param(1, 0.9);
+        for _ in 0..10 {
+            render(&mut plugin, 512);
+        }
+        let gain = plugin.values().get(1).unwrap();
+        assert!(gain < 0.5, "{}", gain);
+        // The world catches up with the audio on its own thread
+        let embedded = plugin.embedded.as_ref().unwrap();
+        for _ in 0..200 {
+            if embedded.sim_time() > 0.1 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert!(embedded.sim_time() > 0.1);
+
+        plugin.set_param(params::SOURCE_ID, Source::Host.value());
+        assert!((plugin.values().get(1).unwrap() - 0.9).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,82 @@
+//! The remote source: a running server's world, followed on a thread of its
+//! own so the audio thread only ever reads atomics.
+
+use crate::params::defaults;
+use ambient_client::Client;
+use audio::mapping::{MappingProfile, WorldInputs};
+use audio::params::{AudioParams, SharedAudioParams};
+use futures_util::StreamExt;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use tokio::sync::oneshot;
+
+/// Server followed when `AMBIENT_URL` is unset.
+pub const DEFAULT_URL: &str = "http://localhost:3000";
+
+/// A session with the server at `AMBIENT_URL`, authorized with
+/// `AMBIENT_TOKEN` when set. Dropping it closes the session.
+pub struct Remote {
+    params: Arc<SharedAudioParams>,
+    stop: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Remote {
+    /// Starts following the server named by the environment. Until its
+    /// first snapshot arrives, a world at rest plays.
+    pub fn from_env() -> std::io::Result<Self> {
+        let url = std::env::var("AMBIENT_URL").unwrap_or_else(|_| DEFAULT_URL.to_string());
+        let mut client = Client::new(&url);
+        if let Ok(token) = std::env::var("AMBIENT_TOKEN") {
+            client = client.with_token(token);
+        }
+        Self::follow(client)
+    }
+
+    fn follow(client: Client) -> std::io::Result<Self> {
+        let params = Arc::new(SharedAudioParams::new(defaults()));
+        let target = Arc::clone(&params);
+        let (stop, stopped) = oneshot::channel();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let thread = std::thread::Builder::new()
+            .name("ambient-remote".to_string())
+            .spawn(move || {
+                runtime.block_on(async move {
+                    let profile = MappingProfile::default();
+                    let mut snapshots = Box::pin(client.subscribe_snapshots());
+                    let follow = async {
+                        while let Some(snapshot) = snapshots.next().await {
+                            target.set(profile.map(&WorldInputs::from_snapshot(&snapshot)));
+                        }
+                    };
+                    tokio::select! {
+                        _ = stopped => {}
+                        _ = follow => {}
+                    }
+                })
+            })?;
+        Ok(Self {
+            params,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// The parameters the server's latest snapshot maps to.
+    pub fn params(&self) -> AudioParams {
+        self.params.get()
+    }
+}
+
+impl Drop for Remote {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Typed Rust client for a running ambient instance.
+//!
+//! Wraps the REST and WebSocket protocol so consumers deal in
+//! [`PerformAction`]s and [`WorldSnapshot`]s rather than JSON:
+//!
+//! ```no_run
+//! use ambient_client::Client;
+//! use ambient_core::events::PerformAction;
+//! use futures_util::StreamExt;
+//!
+//! # async fn run() -> Result<(), ambient_client::ClientError> {
+//! let client = Client::new("http://localhost:3000").with_token("secret");
+//! let ack = client.perform(&PerformAction::Pulse { intensity: 0.7 }).await?;
+//! println!("energy is now {:.3}", ack.resulting_snapshot.energy());
+//!
+//! let mut snapshots = Box::pin(client.subscribe_snapshots());
+//! while let Some(snapshot) = snapshots.next().await {
+//!     println!("density {:.3}", snapshot.density());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! One-off calls go over REST. [`Client::connect`] opens a WebSocket session
+//! that reconnects with backoff and matches acks to requests by request id.
+
+mod ws;
+
+pub use ws::{Backoff, Connection};
+
+use ambient_core::events::PerformAction;
+use ambient_core::world::WorldSnapshot;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Snapshot schema the client asks for and decodes.
+pub const SCHEMA_VERSION: &str = "2.0";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The server answered a REST request with an error status. `code` is
+    /// the machine-readable code from the body, the same codes a WebSocket
+    /// [`ClientError::Rejected`] carries.
+    #[error("server returned {status}: {message}")]
+    Status {
+        status: u16,
+        code: Option<String>,
+        message: String,
+    },
+    #[error("websocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    /// The server answered a WebSocket request with an error message.
+    #[error("server rejected the request ({code}): {message}")]
+    Rejected { code: String, message: String },
+    #[error("invalid message from server: {0}")]
+    Decode(#[from] serde_json::Error),
+    /// The connection closed before the request was answered. The request
+    /// may or may not have been applied.
+    #[error("connection closed")]
+    Closed,
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for ClientError {
+    fn from(error: tokio_tungstenite::tungstenite::Error) -> Self {
+        ClientError::WebSocket(Box::new(error))
+    }
+}
+
+/// Server's answer to a perform action.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ack {
+    /// Id of the request this answers; only set over the WebSocket.
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// False when the action had no effect (e.g. an unknown scene).
+    pub applied: bool,
+    /// World fields the action would have pushed out of range.
+    pub clamped_fields: Vec<String>,
+    /// The world right after the action.
+    pub resulting_snapshot: WorldSnapshot,
+}
+
+/// Handle on one server. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Client {
+    base: String,
+    token: Option<String>,
+    backoff: Backoff,
+    http: reqwest::Client,
+}
+
+impl Client {
+    /// `base_url` is the server's HTTP root, e.g. `http://localhost:3000`.
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base: base_url.trim_end_matches('/').to_string(),
+            token: None,
+            backoff: Backoff::default(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// API token, required when the server has auth enabled.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Reconnect delays for WebSocket sessions.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Applies an action with `POST /event`.
+    pub async fn perform(&self, action: &PerformAction) -> Result<Ack, ClientError> {
+        let request = self
+            .http
+            .post(format!("{}/event", self.base))
+            .json(&perform_body(action)?);
+        let response = self.authorized(request).send().await?;
+        Ok(check_status(response).await?.json().await?)
+    }
+
+    /// Current world state from `GET /state`.
+    pub async fn state(&self) -> Result<WorldSnapshot, ClientError> {
+        let request = self.http.get(format!("{}/state", self.base));
+        let response = self.authorized(request).send().await?;
+        Ok(check_status(response).await?.json().await?)
+    }
+
+    /// Opens a WebSocket session in the background.
+    ///
+    /// Returns at once; the session connects, and reconnects after any drop,
+    /// on its own. Must be called from within a tokio runtime.
+    pub fn connect(&self) -> Connection {
+        Connection::open(self.ws_url(), self.token.clone(), self.backoff)
+    }
+
+    /// Live world snapshots, reconnecting as needed. Snapshots published
+    /// while disconnected, or while the consumer lags, are skipped.
+    pub fn subscribe_snapshots(&self) -> impl Stream<Item = WorldSnapshot> + Send + 'static {
+        self.connect().into_snapshots()
+    }
+
+    fn ws_url(&self) -> String {
+        format!("{}/ws", self.base.replacen("http", "ws", 1))
+    }
+
+    fn authorized(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let request = request.header("X-Schema-Version", SCHEMA_VERSION);
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+/// Builds the `POST /event` body for a perform action.
+pub fn perform_body(action: &PerformAction) -> Result<Value, ClientError> {
+    let mut body = serde_json::to_value(action)?;
+    if let Value::Object(fields) = &mut body {
+        fields.insert("type".to_string(), Value::from("perform"));
+    }
+    Ok(body)
+}
+
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(status_error(status.as_u16(), body))
+}
+
+/// Body of a REST error response.
+#[derive(Deserialize)]
+struct ErrorBody {
+    code: String,
+    message: String,
+}
+
+/// Reads the code and message out of an error body, or keeps the body as
+/// the message when it is not one (a proxy's error page, say).
+fn status_error(status: u16, body: String) -> ClientError {
+    match serde_json::from_str::<ErrorBody>(&body) {
+        Ok(error) => ClientError::Status {
+            status,
+            code: Some(error.code),
+            message: error.message,
+        },
+        Err(_) => ClientError::Status {
+            status,
+            code: None,
+            message: body,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perform_body_matches_event_request() {
+        let body = perform_body(&PerformAction::Pulse { intensity: 0.7 }).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"type": "perform", "Pulse": {"intensity": 0.7}})
+        );
+    }
+
+    #[test]
+    fn test_status_errors_carry_the_code() {
+        let body = r#"{"code":"NOT_FOUND","message":"Unknown preset 'dusk'","request_id":"r1"}"#;
+        let ClientError::Status { code, message, .. } = status_error(404, body.to_string()) else {
+            panic!("expected a status error");
+        };
+        assert_eq!(code.as_deref(), Some("NOT_FOUND"));
+        assert_eq!(message, "Unknown preset 'dusk'");
+
+        let ClientError::Status { code, message, .. } = status_error(502, "Bad Gateway".into())
+        else {
+            panic!("expected a status error");
+        };
+        assert_eq!(code, None);
+        assert_eq!(message, "Bad Gateway");
+    }
+
+    #[test]
+    fn test_ws_url() {
+        assert_eq!(
+            Client::new("http://localhost:3000/").ws_url(),
+            "ws://localhost:3000/ws"
+        );
+        assert_eq!(
+            Client::new("https://ambient.example").ws_url(),
+            "wss://ambient.example/ws"
+        );
+    }
+}
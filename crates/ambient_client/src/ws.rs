@@ -0,0 +1,426 @@
+//! WebSocket sessions: one background task per [`Connection`] that keeps the
+//! socket open, reconnecting with backoff, fans snapshots out to subscribers
+//! and routes each ack or error back to the request with its id.
+
+use crate::{Ack, ClientError, SCHEMA_VERSION};
+use ambient_core::events::PerformAction;
+use ambient_core::world::WorldSnapshot;
+use futures_util::{SinkExt, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tracing::{debug, warn};
+
+type WsStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Snapshots buffered per subscriber before the oldest are skipped (~6 s at 10 Hz).
+const SNAPSHOT_BUFFER: usize = 64;
+
+/// Reconnect delays: `initial` after the first failure, doubling up to `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before reconnect attempt `attempt` (0-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max)
+    }
+}
+
+struct Request {
+    id: String,
+    action: PerformAction,
+    reply: oneshot::Sender<Result<Ack, ClientError>>,
+}
+
+/// A WebSocket session that survives disconnects. Dropping it closes the
+/// socket.
+pub struct Connection {
+    requests: mpsc::UnboundedSender<Request>,
+    snapshots: broadcast::Sender<WorldSnapshot>,
+    next_id: AtomicU64,
+}
+
+impl Connection {
+    pub(crate) fn open(url: String, token: Option<String>, backoff: Backoff) -> Self {
+        let (requests, receiver) = mpsc::unbounded_channel();
+        let (snapshots, _) = broadcast::channel(SNAPSHOT_BUFFER);
+        tokio::spawn(run_session(
+            url,
+            token,
+            backoff,
+            receiver,
+            snapshots.clone(),
+        ));
+        Self {
+            requests,
+            snapshots,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Applies an action and waits for the server's ack.
+    ///
+    /// Requests made while reconnecting are sent once the socket is back. If
+    /// the socket drops after sending, this fails with
+    /// [`ClientError::Closed`] rather than resending, since the server may
+    /// already have applied it.
+    pub async fn perform(&self, action: &PerformAction) -> Result<Ack, ClientError> {
+        let (reply, response) = oneshot::channel();
+        let request = Request {
+            id: format!("client-{}", self.next_id.fetch_add(1, Ordering::Relaxed)),
+            action: action.clone(),
+            reply,
+        };
+        self.requests
+            .send(request)
+            .map_err(|_| ClientError::Closed)?;
+        response.await.map_err(|_| ClientError::Closed)?
+    }
+
+    /// Snapshots received from now on. Ends when the connection is dropped.
+    pub fn snapshots(&self) -> impl Stream<Item = WorldSnapshot> + Send + 'static {
+        snapshot_stream(self.snapshots.subscribe(), ())
+    }
+
+    /// Snapshots from a stream that owns the connection, keeping it open.
+    pub(crate) fn into_snapshots(self) -> impl Stream<Item = WorldSnapshot> + Send + 'static {
+        let receiver = self.snapshots.subscribe();
+        snapshot_stream(receiver, self)
+    }
+}
+
+/// Streams `receiver`, skipping anything missed while lagging. `owner` lives
+/// as long as the stream.
+fn snapshot_stream<O: Send + 'static>(
+    receiver: broadcast::Receiver<WorldSnapshot>,
+    owner: O,
+) -> impl Stream<Item = WorldSnapshot> + Send + 'static {
+    futures_util::stream::unfold((receiver, owner), |(mut receiver, owner)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(snapshot) => return Some((snapshot, (receiver, owner))),
+                Err(RecvError::Lagged(skipped)) => debug!(skipped, "snapshot subscriber lagged"),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Snapshot {
+        payload: SnapshotPayload,
+    },
+    EventAck {
+        payload: Ack,
+    },
+    Error {
+        payload: ErrorPayload,
+    },
+    /// Hello, negotiated, config_reloaded, alert and any added later.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct SnapshotPayload {
+    world: WorldSnapshot,
+}
+
+#[derive(Deserialize)]
+struct ErrorPayload {
+    code: String,
+    message: String,
+    #[serde(default)]
+    request_id: Option<String>,
+}
+
+/// How a socket's session ended.
+enum Ended {
+    /// The `Connection` was dropped; stop for good.
+    Dropped,
+    /// The socket failed or the server closed it; reconnect.
+    Lost(ClientError),
+}
+
+async fn run_session(
+    url: String,
+    token: Option<String>,
+    backoff: Backoff,
+    mut requests: mpsc::UnboundedReceiver<Request>,
+    snapshots: broadcast::Sender<WorldSnapshot>,
+) {
+    let mut attempt = 0;
+    loop {
+        match open_socket(&url, token.as_deref()).await {
+            Ok(socket) => {
+                attempt = 0;
+                match serve(socket, &mut requests, &snapshots).await {
+                    Ended::Dropped => return,
+                    Ended::Lost(error) => warn!(url, %error, "websocket disconnected"),
+                }
+            }
+            Err(error) => warn!(url, %error, "websocket connect failed"),
+        }
+        tokio::time::sleep(backoff.delay(attempt)).await;
+        attempt = attempt.saturating_add(1);
+        if requests.is_closed() {
+            return;
+        }
+    }
+}
+
+/// Connects, authenticating with the token if given, and says hello.
+async fn open_socket(url: &str, token: Option<&str>) -> Result<WsStream, ClientError> {
+    let mut request = url.into_client_request()?;
+    if let Some(token) = token {
+        let value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(tokio_tungstenite::tungstenite::Error::from)?;
+        request.headers_mut().insert("Authorization", value);
+    }
+    let (mut socket, _) = tokio_tungstenite::connect_async(request).await?;
+    let hello = json!({
+        "type": "hello",
+        "version": SCHEMA_VERSION,
+        "payload": {"schema_version": SCHEMA_VERSION},
+    });
+    socket.send(Message::text(hello.to_string())).await?;
+    Ok(socket)
+}
+
+fn perform_message(request_id: &str, action: &PerformAction) -> Value {
+    json!({
+        "type": "perform",
+        "version": SCHEMA_VERSION,
+        "payload": {"request_id": request_id, "action": action},
+    })
+}
+
+/// Runs one socket until it drops or the connection does. Requests still
+/// waiting for an answer fail with [`ClientError::Closed`].
+async fn serve(
+    socket: WsStream,
+    requests: &mut mpsc::UnboundedReceiver<Request>,
+    snapshots: &broadcast::Sender<WorldSnapshot>,
+) -> Ended {
+    let (mut sink, mut stream) = socket.split();
+    let mut pending = HashMap::new();
+    let ended = loop {
+        tokio::select! {
+            request = requests.recv() => {
+                let Some(Request { id, action, reply }) = request else {
+                    let _ = sink.close().await;
+                    break Ended::Dropped;
+                };
+                let message = perform_message(&id, &action);
+                if let Err(error) = sink.send(Message::text(message.to_string())).await {
+                    let _ = reply.send(Err(ClientError::Closed));
+                    break Ended::Lost(error.into());
+                }
+                pending.insert(id, reply);
+            }
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(text))) => handle_message(&text, &mut pending, snapshots),
+                Some(Ok(Message::Close(_))) | None => break Ended::Lost(ClientError::Closed),
+                Some(Ok(_)) => {}
+                Some(Err(error)) => break Ended::Lost(error.into()),
+            }
+        }
+    };
+    for (_, reply) in pending.drain() {
+        let _ = reply.send(Err(ClientError::Closed));
+    }
+    ended
+}
+
+fn handle_message(
+    text: &str,
+    pending: &mut HashMap<String, oneshot::Sender<Result<Ack, ClientError>>>,
+    snapshots: &broadcast::Sender<WorldSnapshot>,
+) {
+    let message = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(error) => {
+            warn!(%error, "ignoring undecodable server message");
+            return;
+        }
+    };
+    match message {
+        ServerMessage::Snapshot { payload } => {
+            // No subscribers is fine
+            let _ = snapshots.send(payload.world);
+        }
+        ServerMessage::EventAck { payload } => {
+            if let Some(reply) = payload
+                .request_id
+                .as_ref()
+                .and_then(|id| pending.remove(id))
+            {
+                let _ = reply.send(Ok(payload));
+            }
+        }
+        ServerMessage::Error { payload } => {
+            let reply = payload
+                .request_id
+                .as_ref()
+                .and_then(|id| pending.remove(id));
+            let error = ClientError::Rejected {
+                code: payload.code,
+                message: payload.message,
+            };
+            match reply {
+                Some(reply) => {
+                    let _ = reply.send(Err(error));
+                }
+                None => warn!(%error, "server error"),
+            }
+        }
+        ServerMessage::Other => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Client;
+    use ambient_core::world::WorldState;
+    use tokio::net::TcpListener;
+
+    fn snapshot(tick: u64) -> WorldSnapshot {
+        WorldSnapshot::from_world_state(&WorldState::default()).with_tick(tick)
+    }
+
+    async fn next_json(
+        socket: &mut tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>,
+    ) -> Value {
+        loop {
+            if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let backoff = Backoff::default();
+        let delays: Vec<u64> = (0..7).map(|n| backoff.delay(n).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+        assert_eq!(backoff.delay(u32::MAX), backoff.max);
+    }
+
+    #[test]
+    fn test_perform_message_matches_ws_protocol() {
+        let message = perform_message("r1", &PerformAction::Calm { intensity: 0.5 });
+        assert_eq!(
+            message,
+            json!({
+                "type": "perform",
+                "version": "2.0",
+                "payload": {"request_id": "r1", "action": {"Calm": {"intensity": 0.5}}},
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_acks_are_correlated_and_sessions_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            // First session: a snapshot, an ack, a rejection, then a drop
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            assert_eq!(next_json(&mut socket).await["type"], "hello");
+            let snapshot_message = |tick| {
+                json!({"type": "snapshot", "version": "2.0", "payload": {
+                    "world": snapshot(tick), "audio": {}, "analysis": {},
+                }})
+                .to_string()
+            };
+            socket
+                .send(Message::text(snapshot_message(1)))
+                .await
+                .unwrap();
+            let perform = next_json(&mut socket).await;
+            let ack = json!({"type": "event_ack", "version": "2.0", "payload": {
+                "request_id": perform["payload"]["request_id"],
+                "action": "Pulse",
+                "intensity": 0.7,
+                "applied": true,
+                "clamped_fields": ["energy"],
+                "resulting_snapshot": snapshot(2),
+            }});
+            socket.send(Message::text(ack.to_string())).await.unwrap();
+            let perform = next_json(&mut socket).await;
+            let error = json!({"type": "error", "version": "2.0", "payload": {
+                "code": "VALIDATION_ERROR",
+                "message": "Intensity must be between -1.0 and 1.0, got 3",
+                "request_id": perform["payload"]["request_id"],
+            }});
+            socket.send(Message::text(error.to_string())).await.unwrap();
+            drop(socket);
+
+            // Second session, after the client reconnects
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            next_json(&mut socket).await;
+            socket
+                .send(Message::text(snapshot_message(3)))
+                .await
+                .unwrap();
+            socket
+        });
+
+        let client = Client::new(&base).with_backoff(Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(10),
+        });
+        let connection = client.connect();
+        let mut snapshots = Box::pin(connection.snapshots());
+        assert_eq!(snapshots.next().await.unwrap().tick(), 1);
+
+        let ack = connection
+            .perform(&PerformAction::Pulse { intensity: 0.7 })
+            .await
+            .unwrap();
+        assert_eq!(ack.request_id.as_deref(), Some("client-1"));
+        assert_eq!(ack.clamped_fields, ["energy"]);
+        assert_eq!(ack.resulting_snapshot, snapshot(2));
+
+        let rejected = connection
+            .perform(&PerformAction::Pulse { intensity: 3.0 })
+            .await;
+        assert!(matches!(
+            rejected,
+            Err(ClientError::Rejected { code, .. }) if code == "VALIDATION_ERROR"
+        ));
+
+        assert_eq!(snapshots.next().await.unwrap().tick(), 3);
+        let _socket = server.await.unwrap();
+        drop(connection);
+        assert!(snapshots.next().await.is_none());
+    }
+}
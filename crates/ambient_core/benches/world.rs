@@ -0,0 +1,73 @@
+//! World simulation benchmarks: drift per simulated second at common tick
+//! rates, and event throughput through `WorldEngine::apply`.
+//!
+//! Run with `cargo bench -p ambient_core`.
+
+use ambient_core::engine::WorldEngine;
+use ambient_core::events::{Event, PerformAction};
+use ambient_core::world::WorldState;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::hint::black_box;
+
+const TICK_RATES_HZ: [u32; 4] = [10, 20, 60, 120];
+
+/// One simulated second of drift: `hz` calls with `dt = 1 / hz`.
+fn drift(c: &mut Criterion) {
+    let mut group = c.benchmark_group("world_drift");
+    for hz in TICK_RATES_HZ {
+        group.throughput(Throughput::Elements(hz as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(hz), &hz, |b, &hz| {
+            let mut rng = StdRng::seed_from_u64(7);
+            let mut state = WorldState::new();
+            let df = 1.0 / hz as f64;
+            b.iter(|| {
+                for _ in 0..hz {
+                    state.drift(black_box(df), &mut rng);
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+/// Ticks at each rate, split into fixed steps at the default step rate.
+fn apply_ticks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engine_apply_tick");
+    group.throughput(Throughput::Elements(1));
+    for hz in TICK_RATES_HZ {
+        group.bench_with_input(BenchmarkId::from_parameter(hz), &hz, |b, &hz| {
+            let mut engine = WorldEngine::with_seed(7);
+            let dt = 1.0 / hz as f64;
+            b.iter(|| engine.apply(black_box(Event::Tick { dt })));
+        });
+    }
+    group.finish();
+}
+
+/// Perform actions, which apply immediately without stepping.
+fn apply_performs(c: &mut Criterion) {
+    let actions = [
+        PerformAction::Pulse { intensity: 0.7 },
+        PerformAction::Stir { intensity: 0.5 },
+        PerformAction::Calm { intensity: 0.4 },
+        PerformAction::Scene {
+            name: "peaceful".to_string(),
+        },
+    ];
+    let mut group = c.benchmark_group("engine_apply_perform");
+    group.throughput(Throughput::Elements(actions.len() as u64));
+    group.bench_function("mixed", |b| {
+        let mut engine = WorldEngine::with_seed(7);
+        b.iter(|| {
+            for action in &actions {
+                black_box(engine.apply(Event::Perform(action.clone())));
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, drift, apply_ticks, apply_performs);
+criterion_main!(benches);
@@ -0,0 +1,319 @@
+//! An ecosystem of agents behind density and energy.
+//!
+//! With agents on, density is the population as a share of `capacity` and
+//! energy the agents' mean excitement. The world drifts, decays toward its
+//! targets and takes actions as before; each step the agents absorb whatever
+//! moved those two parameters since the last one, as births or deaths and as
+//! a shared rise or fall in excitement, and the parameters are then read back
+//! off the agents. Between those, each agent wanders the unit square at a
+//! pace set by its excitement, its excitement wavers, and it dies when its
+//! lifespan runs out, so the population thins until the pull toward the
+//! density target brings new births. Births and deaths fire the world's
+//! sparkles in place of the sparkle process.
+
+use crate::world::WorldState;
+use rand::Rng;
+
+/// How far an agent's heading turns, in radians per root second.
+const TURN: f64 = 2.0;
+
+/// How far an agent's excitement wavers, per root second.
+const JITTER: f64 = 0.05;
+
+/// Share of full speed an agent keeps at excitement 0.
+const RESTING_PACE: f64 = 0.2;
+
+/// The agent layer (`[world.agents]`).
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AgentSettings {
+    pub enabled: bool,
+    /// Population at density 1.
+    pub capacity: u32,
+    /// Mean lifespan in simulated seconds; each agent lives half to one and
+    /// a half times this.
+    pub lifespan_secs: f64,
+    /// Distance a fully excited agent covers per second, the square being 1
+    /// across.
+    pub speed: f64,
+}
+
+impl Default for AgentSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 32,
+            lifespan_secs: 90.0,
+            speed: 0.05,
+        }
+    }
+}
+
+/// One agent, as carried in a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Agent {
+    /// Unique for the life of the engine.
+    pub id: u64,
+    /// Position in the unit square, wrapping at the edges.
+    pub x: f64,
+    pub y: f64,
+    /// Direction of travel in radians, counterclockwise from +x.
+    pub heading: f64,
+    /// 0 to 1.
+    pub excitement: f64,
+    pub age_secs: f64,
+    pub lifespan_secs: f64,
+}
+
+/// A birth or death during one [`Ecosystem::step`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AgentEvent {
+    Born(Agent),
+    Died(Agent),
+}
+
+impl AgentEvent {
+    pub fn agent(&self) -> &Agent {
+        match self {
+            AgentEvent::Born(agent) | AgentEvent::Died(agent) => agent,
+        }
+    }
+}
+
+/// The running population.
+#[derive(Debug, Clone, Default)]
+pub struct Ecosystem {
+    settings: AgentSettings,
+    agents: Vec<Agent>,
+    next_id: u64,
+    /// Density and energy as last read off the agents.
+    density: f64,
+    energy: f64,
+    /// Births (positive) or deaths (negative) owed but not yet whole.
+    pending: f64,
+}
+
+impl Ecosystem {
+    pub fn new(settings: AgentSettings) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+
+    pub fn settings(&self) -> AgentSettings {
+        self.settings
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// Swaps the settings. Turning agents on populates the world from its
+    /// density and energy, without births; turning them off clears it.
+    pub fn set_settings(
+        &mut self,
+        settings: AgentSettings,
+        state: &WorldState,
+        rng: &mut impl Rng,
+    ) {
+        let was_enabled = self.settings.enabled;
+        self.settings = settings;
+        if settings.enabled && !was_enabled {
+            self.populate(state, rng);
+        } else if !settings.enabled {
+            self.agents.clear();
+        }
+    }
+
+    pub fn agents(&self) -> &[Agent] {
+        &self.agents
+    }
+
+    /// Adopts agents from a snapshot of `state`.
+    pub fn restore(&mut self, agents: &[Agent], state: &WorldState) {
+        self.agents = agents.to_vec();
+        self.next_id = agents.iter().map(|a| a.id + 1).max().unwrap_or(0);
+        self.density = state.density();
+        self.energy = state.energy();
+        self.pending = 0.0;
+    }
+
+    fn capacity(&self) -> f64 {
+        self.settings.capacity.max(1) as f64
+    }
+
+    /// Replaces the population with one matching `state`, of all ages.
+    fn populate(&mut self, state: &WorldState, rng: &mut impl Rng) {
+        self.agents.clear();
+        let count = (state.density() * self.capacity()).round() as usize;
+        for _ in 0..count {
+            let mut agent = self.spawn(state.energy(), rng);
+            agent.age_secs = agent.lifespan_secs * rng.random::<f64>();
+            self.agents.push(agent);
+        }
+        self.pending = 0.0;
+        self.read_back(state);
+    }
+
+    fn spawn(&mut self, excitement: f64, rng: &mut impl Rng) -> Agent {
+        let id = self.next_id;
+        self.next_id += 1;
+        Agent {
+            id,
+            x: rng.random(),
+            y: rng.random(),
+            heading: std::f64::consts::TAU * rng.random::<f64>(),
+            excitement: excitement.clamp(0.0, 1.0),
+            age_secs: 0.0,
+            lifespan_secs: self.settings.lifespan_secs * (0.5 + rng.random::<f64>()),
+        }
+    }
+
+    /// Density and energy of the population; an empty one leaves energy
+    /// where `state` has it.
+    fn read_back(&mut self, state: &WorldState) {
+        self.density = self.agents.len() as f64 / self.capacity();
+        self.energy = if self.agents.is_empty() {
+            state.energy()
+        } else {
+            self.agents.iter().map(|a| a.excitement).sum::<f64>() / self.agents.len() as f64
+        };
+    }
+
+    /// Advances `dt` seconds: absorbs what moved `state`'s density and
+    /// energy since the last step, ages, moves and culls the agents, then
+    /// sets density and energy from them. Returns the births and deaths,
+    /// oldest first.
+    pub fn step(&mut self, dt: f64, state: &mut WorldState, rng: &mut impl Rng) -> Vec<AgentEvent> {
+        let capacity = self.capacity();
+        let mut events = Vec::new();
+        // Owed births or deaths never run past an empty or full world
+        self.pending = (self.pending + (state.density() - self.density) * capacity)
+            .clamp(-(self.agents.len() as f64) - 1.0, capacity + 1.0);
+        let lift = state.energy() - self.energy;
+
+        for agent in &mut self.agents {
+            agent.age_secs += dt;
+        }
+        self.agents.retain(|agent| {
+            let alive = agent.age_secs < agent.lifespan_secs;
+            if !alive {
+                events.push(AgentEvent::Died(*agent));
+            }
+            alive
+        });
+        while self.pending <= -1.0 && !self.agents.is_empty() {
+            let oldest = (0..self.agents.len())
+                .max_by(|&a, &b| self.agents[a].age_secs.total_cmp(&self.agents[b].age_secs))
+                .unwrap_or(0);
+            events.push(AgentEvent::Died(self.agents.remove(oldest)));
+            self.pending += 1.0;
+        }
+        // Newborns take the world's energy as it stands, so lift only those
+        // already here
+        for agent in &mut self.agents {
+            agent.excitement = (agent.excitement + lift).clamp(0.0, 1.0);
+        }
+        while self.pending >= 1.0 && (self.agents.len() as f64) < capacity {
+            let agent = self.spawn(state.energy(), rng);
+            self.agents.push(agent);
+            events.push(AgentEvent::Born(agent));
+            self.pending -= 1.0;
+        }
+
+        let root_dt = dt.sqrt();
+        for agent in &mut self.agents {
+            let waver = JITTER * root_dt * (2.0 * rng.random::<f64>() - 1.0);
+            agent.excitement = (agent.excitement + waver).clamp(0.0, 1.0);
+            agent.heading = (agent.heading + TURN * root_dt * (2.0 * rng.random::<f64>() - 1.0))
+                .rem_euclid(std::f64::consts::TAU);
+            let pace =
+                self.settings.speed * (RESTING_PACE + (1.0 - RESTING_PACE) * agent.excitement);
+            agent.x = (agent.x + pace * dt * agent.heading.cos()).rem_euclid(1.0);
+            agent.y = (agent.y + pace * dt * agent.heading.sin()).rem_euclid(1.0);
+        }
+
+        self.read_back(state);
+        state.set_density(self.density);
+        state.set_energy(self.energy);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    const DT: f64 = 1.0 / 60.0;
+
+    fn ecosystem(state: &WorldState, rng: &mut StdRng) -> Ecosystem {
+        let mut ecosystem = Ecosystem::default();
+        let settings = AgentSettings {
+            enabled: true,
+            ..AgentSettings::default()
+        };
+        ecosystem.set_settings(settings, state, rng);
+        ecosystem
+    }
+
+    #[test]
+    fn test_population_and_excitement_set_density_and_energy() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut state = WorldState::new();
+        let mut ecosystem = ecosystem(&state, &mut rng);
+        assert_eq!(ecosystem.agents().len(), 16);
+
+        // A pulse's worth of energy excites everyone; a stir's density is born
+        state.set_energy(0.8);
+        state.set_density(0.75);
+        let events = ecosystem.step(DT, &mut state, &mut rng);
+        let born = events
+            .iter()
+            .filter(|e| matches!(e, AgentEvent::Born(_)))
+            .count();
+        assert!(born >= 8, "{} born", born);
+        assert_eq!(state.density(), ecosystem.agents().len() as f64 / 32.0);
+        assert!((state.energy() - 0.8).abs() < 0.01, "{}", state.energy());
+        let mean = ecosystem.agents().iter().map(|a| a.excitement).sum::<f64>()
+            / ecosystem.agents().len() as f64;
+        assert_eq!(state.energy(), mean);
+
+        // Fewer agents, fewer of them
+        state.set_density(0.25);
+        ecosystem.step(DT, &mut state, &mut rng);
+        assert_eq!(ecosystem.agents().len(), 8);
+    }
+
+    #[test]
+    fn test_agents_age_out_and_wander() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut state = WorldState::new();
+        let mut ecosystem = ecosystem(&state, &mut rng);
+        let first: Vec<u64> = ecosystem.agents().iter().map(|a| a.id).collect();
+        let start = ecosystem.agents()[0];
+
+        let mut deaths = 0;
+        for _ in 0..(30.0 / DT) as usize {
+            let events = ecosystem.step(DT, &mut state, &mut rng);
+            deaths += events
+                .iter()
+                .filter(|e| matches!(e, AgentEvent::Died(_)))
+                .count();
+        }
+        // Nothing refills the world here, so it thins
+        assert!(deaths > 0);
+        assert_eq!(ecosystem.agents().len(), 16 - deaths);
+        assert!(state.density() < 0.5);
+
+        if let Some(moved) = ecosystem.agents().iter().find(|a| a.id == start.id) {
+            assert!((moved.x - start.x).abs() + (moved.y - start.y).abs() > 0.01);
+        }
+        for agent in ecosystem.agents() {
+            assert!(first.contains(&agent.id));
+            assert!((0.0..1.0).contains(&agent.x) && (0.0..1.0).contains(&agent.y));
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Long-form structure: arcs that build, peak and release.
+//!
+//! Left alone the world drifts around its targets without going anywhere.
+//! The composer is a [`Modulator`] that plans one arc at a time and steers
+//! the decay targets along it: half the arc building from a quiet floor to a
+//! peak, a stretch holding there and the rest releasing back down, then the
+//! next arc. Intensity lifts energy, density and rhythm together; tension
+//! climbs with it through the build and resolves in the release, which also
+//! warms. Each arc draws its own length, peak and colour from the
+//! composer's own RNG, so no two are alike yet the same seed plans the same
+//! arcs. While it runs the composer owns the targets; a scene or preset
+//! recall moves the parameters but the next step steers the targets back.
+
+use crate::curves::Curve;
+use crate::modulator::Modulator;
+use crate::world::WorldState;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Intensity at the start and end of every arc.
+pub const FLOOR: f64 = 0.15;
+
+/// Shares of an arc spent building and at the peak; the rest releases.
+const BUILD_SHARE: f64 = 0.5;
+const PEAK_SHARE: f64 = 0.15;
+
+/// At full randomness, how far an arc's length strays either side of
+/// `arc_secs`, how far below the ceiling its peak may fall, and how far its
+/// warmth leans either way.
+const LENGTH_SPREAD: f64 = 0.5;
+const PEAK_SPREAD: f64 = 0.3;
+const COLOUR_SPREAD: f64 = 0.15;
+
+/// Shape of the arcs (`[composer]`).
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ArcSettings {
+    /// Simulated seconds from one arc's start to the next.
+    pub arc_secs: f64,
+    /// Highest intensity a peak reaches, 0 to 1.
+    pub ceiling: f64,
+    /// How much arcs vary in length, peak and colour, 0 (all alike) to 1.
+    pub randomness: f64,
+}
+
+impl Default for ArcSettings {
+    fn default() -> Self {
+        Self {
+            arc_secs: 7200.0,
+            ceiling: 0.85,
+            randomness: 0.3,
+        }
+    }
+}
+
+/// Where an arc stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArcPhase {
+    Build,
+    Peak,
+    Release,
+}
+
+/// One planned arc.
+#[derive(Debug, Clone, Copy)]
+struct ArcPlan {
+    duration: f64,
+    peak: f64,
+    /// Lean of the warmth targets, plus or minus.
+    colour: f64,
+    elapsed: f64,
+}
+
+/// Plans arcs and steers the world's targets along them.
+pub struct Composer {
+    settings: ArcSettings,
+    rng: StdRng,
+    arc: ArcPlan,
+    /// Arcs begun, counting the current one.
+    arcs: u64,
+    phase: ArcPhase,
+}
+
+impl Composer {
+    pub fn new(settings: ArcSettings, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let arc = plan(&settings, &mut rng);
+        Self {
+            settings,
+            rng,
+            arc,
+            arcs: 1,
+            phase: ArcPhase::Build,
+        }
+    }
+
+    pub fn phase(&self) -> ArcPhase {
+        self.phase
+    }
+
+    /// Arcs begun, counting the current one.
+    pub fn arcs(&self) -> u64 {
+        self.arcs
+    }
+
+    /// Progress through the current arc, 0 to 1.
+    pub fn progress(&self) -> f64 {
+        (self.arc.elapsed / self.arc.duration).clamp(0.0, 1.0)
+    }
+
+    /// Current intensity, from [`FLOOR`] up to the arc's peak.
+    pub fn intensity(&self) -> f64 {
+        let progress = self.progress();
+        let ArcPlan { peak, .. } = self.arc;
+        let ease = |t: f64| Curve::SmoothStep.apply(t);
+        match phase_at(progress) {
+            ArcPhase::Build => FLOOR + (peak - FLOOR) * ease(progress / BUILD_SHARE),
+            ArcPhase::Peak => peak,
+            ArcPhase::Release => {
+                let release_start = BUILD_SHARE + PEAK_SHARE;
+                let t = (progress - release_start) / (1.0 - release_start);
+                peak + (FLOOR - peak) * ease(t)
+            }
+        }
+    }
+
+    /// Targets for the current point of the arc.
+    fn steer(&self, state: &mut WorldState) {
+        let intensity = self.intensity();
+        let releasing = self.phase == ArcPhase::Release;
+        state.set_target_energy(intensity);
+        state.set_target_density(0.2 + 0.7 * intensity);
+        state.set_target_rhythm(0.25 + 0.6 * intensity);
+        state.set_target_tension(if releasing {
+            0.1 + 0.4 * intensity
+        } else {
+            0.1 + 0.8 * intensity
+        });
+        let afterglow = if releasing { 0.15 } else { 0.0 };
+        state.set_target_warmth(0.55 - 0.25 * intensity + afterglow + self.arc.colour);
+    }
+}
+
+/// The phase at `progress` through an arc.
+fn phase_at(progress: f64) -> ArcPhase {
+    if progress < BUILD_SHARE {
+        ArcPhase::Build
+    } else if progress < BUILD_SHARE + PEAK_SHARE {
+        ArcPhase::Peak
+    } else {
+        ArcPhase::Release
+    }
+}
+
+/// Draws the next arc: three numbers from `rng`.
+fn plan(settings: &ArcSettings, rng: &mut StdRng) -> ArcPlan {
+    let randomness = settings.randomness.clamp(0.0, 1.0);
+    let spread = |rng: &mut StdRng| 2.0 * rng.random::<f64>() - 1.0;
+    let duration = settings.arc_secs * (1.0 + LENGTH_SPREAD * randomness * spread(rng));
+    let peak = settings.ceiling * (1.0 - PEAK_SPREAD * randomness * rng.random::<f64>());
+    ArcPlan {
+        duration,
+        peak: peak.max(FLOOR),
+        colour: COLOUR_SPREAD * randomness * spread(rng),
+        elapsed: 0.0,
+    }
+}
+
+impl Modulator for Composer {
+    fn name(&self) -> &str {
+        "composer"
+    }
+
+    fn modulate(&mut self, dt: f64, state: &mut WorldState) {
+        self.arc.elapsed += dt;
+        if self.arc.elapsed >= self.arc.duration {
+            self.arc = plan(&self.settings, &mut self.rng);
+            self.arcs += 1;
+            tracing::info!(
+                "Composer: arc {} planned, {:.0}s peaking at {:.2}",
+                self.arcs,
+                self.arc.duration,
+                self.arc.peak
+            );
+        }
+        let phase = phase_at(self.progress());
+        if phase != self.phase {
+            tracing::info!("Composer: arc {} enters {:?}", self.arcs, phase);
+            self.phase = phase;
+        }
+        self.steer(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f64 = 1.0;
+
+    /// Phase, intensity and energy target at every step over `secs`.
+    fn run(composer: &mut Composer, secs: f64) -> Vec<(ArcPhase, f64, f64)> {
+        let mut state = WorldState::new();
+        (0..(secs / DT) as usize)
+            .map(|_| {
+                composer.modulate(DT, &mut state);
+                let target = state.preset().target_energy;
+                (composer.phase(), composer.intensity(), target)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_arc_builds_peaks_and_releases() {
+        let settings = ArcSettings {
+            arc_secs: 1000.0,
+            ceiling: 0.8,
+            randomness: 0.0,
+        };
+        let mut composer = Composer::new(settings, 1);
+        let steps = run(&mut composer, 999.0);
+        let phases: Vec<ArcPhase> = steps.iter().map(|s| s.0).collect();
+        let first = |phase| phases.iter().position(|p| *p == phase).unwrap();
+        assert_eq!(
+            (first(ArcPhase::Peak), first(ArcPhase::Release)),
+            (499, 649)
+        );
+
+        let peak = steps.iter().map(|s| s.1).fold(0.0, f64::max);
+        assert!((peak - 0.8).abs() < 1e-9);
+        assert!(steps[..499].windows(2).all(|w| w[1].1 >= w[0].1));
+        assert!(steps[649..].windows(2).all(|w| w[1].1 <= w[0].1));
+        assert!((steps[998].1 - FLOOR).abs() < 0.01);
+        assert!(steps.iter().all(|s| s.1 == s.2));
+
+        // The next arc starts from the floor
+        run(&mut composer, 2.0);
+        assert_eq!(composer.arcs(), 2);
+        assert_eq!(composer.phase(), ArcPhase::Build);
+    }
+
+    #[test]
+    fn test_randomness_varies_arcs_within_the_ceiling() {
+        let settings = ArcSettings {
+            arc_secs: 500.0,
+            ceiling: 0.7,
+            randomness: 1.0,
+        };
+        let mut composer = Composer::new(settings, 9);
+        let steps = run(&mut composer, 10_000.0);
+        assert!(steps.iter().all(|s| (FLOOR..=0.7).contains(&s.1)));
+        assert!((10..=40).contains(&composer.arcs()), "{}", composer.arcs());
+
+        // Peaks differ from arc to arc, and the seed fixes them
+        let mut peaks: Vec<f64> = steps
+            .windows(2)
+            .filter(|w| w[0].0 == ArcPhase::Build && w[1].0 == ArcPhase::Peak)
+            .map(|w| w[1].1)
+            .collect();
+        let again = run(&mut Composer::new(settings, 9), 10_000.0);
+        assert_eq!(steps, again);
+        peaks.dedup();
+        assert!(peaks.len() > 5);
+    }
+}
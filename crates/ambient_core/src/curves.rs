@@ -0,0 +1,168 @@
+//! Easing curves shared by scene transitions and world → audio mapping.
+//!
+//! Every curve maps progress `t` in [0, 1] onto [0, 1], starting at 0 and
+//! ending at 1 (piecewise curves may end elsewhere). In config files a curve
+//! is either a name (`"smoothstep"`) or a one-key table carrying its shape
+//! (`{ exponential = 3.0 }`, `{ piecewise = [[0.0, 0.0], [0.3, 0.8], [1.0, 1.0]] }`).
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Curve {
+    #[default]
+    Linear,
+    /// Zero slope at both ends.
+    #[serde(rename = "smoothstep")]
+    SmoothStep,
+    /// Slow start (quadratic).
+    EaseIn,
+    /// Slow finish (quadratic).
+    EaseOut,
+    /// `(e^(k t) - 1) / (e^k - 1)`: slow start for positive `k`, fast start
+    /// for negative; 0 is linear.
+    Exponential(f64),
+    /// Logistic S-curve with the given steepness (> 0), rescaled to hit both
+    /// endpoints. Sharper than smoothstep for large values.
+    Sigmoid(f64),
+    /// Straight segments through `[t, value]` points, `t` strictly increasing
+    /// within [0, 1]. Held flat outside the first and last points.
+    Piecewise(Vec<[f64; 2]>),
+}
+
+impl Curve {
+    /// Maps progress `t` (clamped to [0, 1]) through the curve.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => t,
+            Curve::SmoothStep => t * t * (3.0 - 2.0 * t),
+            Curve::EaseIn => t * t,
+            Curve::EaseOut => t * (2.0 - t),
+            Curve::Exponential(k) => {
+                if k.abs() < 1e-6 {
+                    t
+                } else {
+                    (k * t).exp_m1() / k.exp_m1()
+                }
+            }
+            Curve::Sigmoid(steepness) => {
+                let logistic = |x: f64| 1.0 / (1.0 + (-steepness * (x - 0.5)).exp());
+                let (low, high) = (logistic(0.0), logistic(1.0));
+                (logistic(t) - low) / (high - low)
+            }
+            Curve::Piecewise(points) => piecewise(points, t),
+        }
+    }
+
+    /// Checks the curve's parameters.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Curve::Exponential(k) if !k.is_finite() || k.abs() > 50.0 => {
+                Err(format!("exponential rate must be in [-50, 50], got {}", k))
+            }
+            Curve::Sigmoid(steepness) if !(*steepness > 0.0 && *steepness <= 100.0) => Err(
+                format!("sigmoid steepness must be in (0, 100], got {}", steepness),
+            ),
+            Curve::Piecewise(points) => {
+                if points.len() < 2 {
+                    return Err("piecewise curve needs at least two points".to_string());
+                }
+                if points.iter().flatten().any(|v| !v.is_finite()) {
+                    return Err("piecewise points must be finite".to_string());
+                }
+                if points.iter().any(|[t, _]| !(0.0..=1.0).contains(t))
+                    || points.windows(2).any(|pair| pair[0][0] >= pair[1][0])
+                {
+                    return Err(
+                        "piecewise points need t in [0, 1], strictly increasing".to_string()
+                    );
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn piecewise(points: &[[f64; 2]], t: f64) -> f64 {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return t;
+    };
+    if t <= first[0] {
+        return first[1];
+    }
+    for pair in points.windows(2) {
+        let ([t0, v0], [t1, v1]) = (pair[0], pair[1]);
+        if t <= t1 {
+            return v0 + (v1 - v0) * (t - t0) / (t1 - t0);
+        }
+    }
+    last[1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curves_hit_endpoints() {
+        let curves = [
+            Curve::Linear,
+            Curve::SmoothStep,
+            Curve::EaseIn,
+            Curve::EaseOut,
+            Curve::Exponential(3.0),
+            Curve::Exponential(-3.0),
+            Curve::Sigmoid(10.0),
+            Curve::Piecewise(vec![[0.0, 0.0], [0.3, 0.8], [1.0, 1.0]]),
+        ];
+        for curve in curves {
+            assert!(curve.validate().is_ok());
+            assert!(curve.apply(0.0).abs() < 1e-9, "{:?}", curve);
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-9, "{:?}", curve);
+            let mut previous = 0.0;
+            for i in 1..=100 {
+                let value = curve.apply(i as f64 / 100.0);
+                assert!(value >= previous - 1e-12, "{:?} not monotonic", curve);
+                previous = value;
+            }
+        }
+        assert!(Curve::Exponential(3.0).apply(0.5) < 0.5);
+        assert!((Curve::Sigmoid(10.0).apply(0.5) - 0.5).abs() < 1e-9);
+        let knee = Curve::Piecewise(vec![[0.0, 0.0], [0.3, 0.8], [1.0, 1.0]]);
+        assert!((knee.apply(0.15) - 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_invalid_curves_rejected() {
+        assert!(Curve::Sigmoid(0.0).validate().is_err());
+        assert!(Curve::Exponential(f64::NAN).validate().is_err());
+        assert!(Curve::Piecewise(vec![[0.0, 0.0]]).validate().is_err());
+        assert!(
+            Curve::Piecewise(vec![[0.5, 0.0], [0.2, 1.0]])
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_curve_serialization() {
+        let curves: Vec<Curve> = serde_json::from_str(
+            r#"["smoothstep", "ease_in", {"exponential": 2.0}, {"piecewise": [[0, 0], [1, 1]]}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            curves,
+            vec![
+                Curve::SmoothStep,
+                Curve::EaseIn,
+                Curve::Exponential(2.0),
+                Curve::Piecewise(vec![[0.0, 0.0], [1.0, 1.0]]),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&Curve::Sigmoid(8.0)).unwrap(),
+            r#"{"sigmoid":8.0}"#
+        );
+    }
+}
@@ -1,15 +1,159 @@
+use crate::agents::{AgentEvent, Ecosystem};
 use crate::events::{Event, PerformAction, TriggerKind};
-use crate::world::{WorldSnapshot, WorldState};
+use crate::field::SpatialField;
+use crate::invariants::InvariantError;
+use crate::modulator::Modulator;
+use crate::mood::{MoodChange, MoodTracker};
+use crate::pulse::{BeatEvent, PulseGrid, PulseSettings};
+use crate::scene::{SceneChange, SceneTargets, builtin_scenes};
+use crate::sparkles::SparkleProcess;
+use crate::world::{RunState, WorldDynamics, WorldPreset, WorldSnapshot, WorldState};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+
+/// Outcome of applying one event.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ApplyResult {
+    /// False when the event had no effect (e.g. an unimplemented freeze).
+    pub applied: bool,
+    /// World fields whose requested value fell outside [0, 1] and was clamped.
+    pub clamped_fields: Vec<&'static str>,
+    /// World state right after the event.
+    pub resulting_snapshot: WorldSnapshot,
+}
+
+/// A sparkle the world fired, for clients to flash in step with the audio.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SparkleEvent {
+    /// Tick during which it fired.
+    pub tick: u64,
+    /// Simulated time at the end of that tick.
+    pub sim_time_secs: f64,
+    /// Impulse strength, 0.5 to 1.0, rising with energy.
+    pub strength: f64,
+    /// Suggested pitch from 0 (low) to 1 (high): higher in cool, tense worlds.
+    pub pitch: f64,
+    /// Suggested pan from -1 (left) to 1 (right), sweeping at a rate set by
+    /// rhythm.
+    pub pan: f64,
+}
+
+/// Sparkles, beats and mood changes kept for [`WorldEngine::take_sparkles`],
+/// [`WorldEngine::take_beats`] and [`WorldEngine::take_mood_changes`]; older
+/// ones are dropped when nothing collects them.
+const MAX_PENDING_EVENTS: usize = 64;
+
+/// Appends `event`, dropping the oldest if `events` is full.
+fn push_capped<T>(events: &mut Vec<T>, event: T) {
+    if events.len() == MAX_PENDING_EVENTS {
+        events.remove(0);
+    }
+    events.push(event);
+}
+
+/// Adds `delta` to `value`, noting `field` if the sum leaves [0, 1]. A
+/// non-finite `delta` leaves `value` as it is.
+///
+/// The setters do the clamping themselves.
+fn nudge(value: f64, delta: f64, field: &'static str, clamped: &mut Vec<&'static str>) -> f64 {
+    if !delta.is_finite() {
+        return value;
+    }
+    let requested = value + delta;
+    if !(0.0..=1.0).contains(&requested) && !clamped.contains(&field) {
+        clamped.push(field);
+    }
+    requested
+}
+
+/// Default rate of the fixed internal timestep.
+pub const DEFAULT_STEP_HZ: f64 = 60.0;
+
+/// Range accepted by [`WorldEngine::set_step_hz`].
+pub const STEP_HZ_RANGE: std::ops::RangeInclusive<f64> = 1.0..=1000.0;
+
+/// Range accepted by [`WorldEngine::set_time_scale`].
+pub const TIME_SCALE_RANGE: std::ops::RangeInclusive<f64> = 0.1..=10.0;
+
+/// Most fixed steps run for a single tick; time beyond that is dropped.
+const MAX_STEPS_PER_TICK: u32 = 1_000;
+
+/// Perform actions kept for [`PerformAction::Undo`]; older ones can no
+/// longer be undone.
+pub const UNDO_DEPTH: usize = 32;
+
+/// Seconds of simulated time over which an undo eases the world back.
+pub const UNDO_RAMP_SECS: f64 = 0.5;
+
+/// What one perform action changed, for taking it back.
+struct UndoEntry {
+    before: WorldPreset,
+    after: WorldPreset,
+    /// The scene before a scene action, which an undo restores. Other
+    /// actions leave the scene alone.
+    scene: Option<Option<SceneChange>>,
+}
+
+/// A recall in progress: the world moves from `from` to `to` over `duration`
+/// seconds of simulated time.
+struct Ramp {
+    from: WorldPreset,
+    to: WorldPreset,
+    elapsed: f64,
+    duration: f64,
+}
 
 /// The engine that updates the world state over time.
+///
+/// Ticks feed an accumulator that is drained in fixed steps, so the world
+/// evolves the same however the ticks are spaced. Snapshots interpolate
+/// between the last two steps by the time left in the accumulator.
+///
+/// Drift, harmony, sparkles and agents draw on the engine's own RNG, so two
+/// engines built with the same seed and fed the same events evolve
+/// identically.
 /// TODO: Consider adding drift parameter here
-/// TODO: For deterministic mode/testing: inject RNG instead of using rand::rng()
-/// TODO: Add WorldEngine::new_with_rng(rng) and WorldEngine::new_deterministic(seed) constructors
 pub struct WorldEngine {
     state: WorldState,
+    /// State before the most recent fixed step.
+    previous: WorldState,
+    step_dt: f64,
+    /// Tick time not yet consumed by a fixed step.
+    accumulator: f64,
     sparkle_phase: f64,
+    sparkle_process: SparkleProcess,
+    /// Strength of a sparkle waiting for the next grid point.
+    held_sparkle: Option<f64>,
+    pulse: PulseGrid,
+    pulse_settings: PulseSettings,
+    agents: Ecosystem,
+    field: SpatialField,
+    mood: MoodTracker,
+    scenes: HashMap<String, SceneTargets>,
+    scene: Option<SceneChange>,
+    tick: u64,
+    sim_time: f64,
+    run_state: RunState,
+    time_scale: f64,
+    /// Preset recall or undo in progress, overriding drift until it
+    /// completes.
+    ramp: Option<Ramp>,
+    /// Recent perform actions, oldest first, for [`PerformAction::Undo`].
+    history: Vec<UndoEntry>,
+    rng: StdRng,
+    /// Run on every fixed step after drift, in registration order.
+    modulators: Vec<Box<dyn Modulator>>,
+    /// Sparkles fired since the last [`WorldEngine::take_sparkles`].
+    sparkles: Vec<SparkleEvent>,
+    /// Beats since the last [`WorldEngine::take_beats`].
+    beats: Vec<BeatEvent>,
+    /// Mood changes since the last [`WorldEngine::take_mood_changes`].
+    mood_changes: Vec<MoodChange>,
 }
 
+#[cfg(feature = "thread-rng")]
 impl Default for WorldEngine {
     fn default() -> Self {
         Self::new()
@@ -17,153 +161,603 @@ impl Default for WorldEngine {
 }
 
 impl WorldEngine {
-    /// Initializes the world engine with a default state.
-    /// TODO: Add new_with_rng(rng) and new_deterministic(seed) for testing/replay
+    /// Initializes the world engine with a default state and a random seed.
+    #[cfg(feature = "thread-rng")]
     pub fn new() -> Self {
+        Self::with_rng(StdRng::from_rng(&mut rand::rng()))
+    }
+
+    /// Initializes the world engine with a default state and a fixed seed.
+    /// This is the only constructor without the `thread-rng` feature, e.g.
+    /// on wasm32, which has no entropy source by default.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rng: StdRng) -> Self {
+        let state = WorldState::new();
         Self {
-            state: WorldState::new(),
+            mood: MoodTracker::new(state.mood()),
+            state,
+            previous: WorldState::new(),
+            step_dt: 1.0 / DEFAULT_STEP_HZ,
+            accumulator: 0.0,
             sparkle_phase: 0.0,
+            sparkle_process: SparkleProcess::default(),
+            held_sparkle: None,
+            pulse: PulseGrid::new(),
+            pulse_settings: PulseSettings::default(),
+            agents: Ecosystem::default(),
+            field: SpatialField::default(),
+            scenes: builtin_scenes(),
+            scene: None,
+            tick: 0,
+            sim_time: 0.0,
+            run_state: RunState::Running,
+            time_scale: 1.0,
+            ramp: None,
+            history: Vec::new(),
+            rng,
+            modulators: Vec::new(),
+            sparkles: Vec::new(),
+            beats: Vec::new(),
+            mood_changes: Vec::new(),
+        }
+    }
+
+    /// Registers a modulator to run on every fixed step, after any already
+    /// registered.
+    pub fn add_modulator(&mut self, modulator: impl Modulator + 'static) {
+        tracing::info!("Registered world modulator '{}'", modulator.name());
+        self.modulators.push(Box::new(modulator));
+    }
+
+    /// Names of the registered modulators, in the order they run.
+    pub fn modulator_names(&self) -> Vec<&str> {
+        self.modulators.iter().map(|m| m.name()).collect()
+    }
+
+    /// Adopts another engine's snapshot, typically the server's, so a local
+    /// copy can predict the world between snapshots. Parameters, clock, run
+    /// state, time scale, pulse grid, harmony and mood come from the
+    /// snapshot, and agents and grids too when this engine runs them (grids
+    /// a serialized snapshot lacks are laid evenly over it); decay targets
+    /// from its scene, looked up in this engine's scenes. Dynamics are kept.
+    pub fn sync_to(&mut self, snapshot: &WorldSnapshot) {
+        let mut state = WorldState::from_snapshot(snapshot);
+        state.set_dynamics(self.state.dynamics());
+        if let Some(scene) = snapshot.scene() {
+            let targets = self.scenes.get(&scene.name).cloned().unwrap_or_default();
+            state.set_targets(&targets);
+        }
+        self.previous = state.clone();
+        self.state = state;
+        self.accumulator = 0.0;
+        self.ramp = None;
+        self.history.clear();
+        self.scene = snapshot.scene().cloned();
+        self.tick = snapshot.tick();
+        self.sim_time = snapshot.sim_time_secs();
+        self.run_state = snapshot.run_state();
+        self.set_time_scale(snapshot.time_scale());
+        self.pulse = PulseGrid::from_pulse(snapshot.pulse());
+        if self.agents.enabled() {
+            self.agents.restore(snapshot.agents(), &self.state);
         }
+        if self.field.enabled() {
+            self.field.restore(snapshot.field(), &self.state);
+        }
+        self.mood = MoodTracker::new(snapshot.mood());
     }
 
-    /// Apply event.
-    pub fn apply(&mut self, event: Event) {
+    /// Replaces the drift/decay rates used on each tick.
+    pub fn set_dynamics(&mut self, dynamics: WorldDynamics) {
+        self.state.set_dynamics(dynamics);
+        self.previous.set_dynamics(dynamics);
+        self.sparkle_process.set_model(dynamics.sparkles);
+        self.pulse_settings = dynamics.pulse;
+        self.agents
+            .set_settings(dynamics.agents, &self.state, &mut self.rng);
+        self.field.set_settings(dynamics.field, &self.state);
+    }
+
+    /// Sets the rate of the fixed internal timestep, clamped to
+    /// [`STEP_HZ_RANGE`]. A NaN rate is ignored.
+    pub fn set_step_hz(&mut self, hz: f64) {
+        if !hz.is_nan() {
+            self.step_dt = 1.0 / hz.clamp(*STEP_HZ_RANGE.start(), *STEP_HZ_RANGE.end());
+        }
+    }
+
+    /// Adds or replaces a named scene.
+    pub fn register_scene(&mut self, name: impl Into<String>, targets: SceneTargets) {
+        self.scenes.insert(name.into(), targets);
+    }
+
+    /// Pauses or resumes ticking.
+    pub fn set_run_state(&mut self, run_state: RunState) {
+        self.run_state = run_state;
+    }
+
+    pub fn run_state(&self) -> RunState {
+        self.run_state
+    }
+
+    /// Sets the multiplier applied to tick dt, clamped to [`TIME_SCALE_RANGE`].
+    /// Values above 1 fast-forward the world; below 1 slow it down.
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        self.time_scale = time_scale.clamp(*TIME_SCALE_RANGE.start(), *TIME_SCALE_RANGE.end());
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// Advances exactly `ticks` fixed steps, whether running or paused.
+    pub fn step(&mut self, ticks: u32) -> ApplyResult {
+        for _ in 0..ticks {
+            self.advance_tick(self.step_dt);
+        }
+        self.debug_validate();
+        ApplyResult {
+            applied: ticks > 0,
+            clamped_fields: Vec::new(),
+            resulting_snapshot: self.get_snapshot(),
+        }
+    }
+
+    /// Captures the current parameters and targets.
+    pub fn capture_preset(&self) -> WorldPreset {
+        self.state.preset()
+    }
+
+    /// Moves the world to `preset` over `ramp_secs` of simulated time, or at
+    /// once when zero. The preset's targets apply immediately; drift resumes
+    /// from the preset when the ramp ends. Any other event cancels the ramp.
+    pub fn recall_preset(&mut self, preset: WorldPreset, ramp_secs: f64) -> ApplyResult {
+        if ramp_secs > 0.0 {
+            self.ramp = Some(Ramp {
+                from: self.state.preset(),
+                to: preset,
+                elapsed: 0.0,
+                duration: ramp_secs,
+            });
+        } else {
+            self.ramp = None;
+            self.state.set_preset(&preset);
+            self.previous = self.state.clone();
+        }
+        ApplyResult {
+            applied: true,
+            clamped_fields: Vec::new(),
+            resulting_snapshot: self.get_snapshot(),
+        }
+    }
+
+    /// Moves the world `fraction` of the way toward `reference`, parameters
+    /// and targets alike, over `ramp_secs` as in [`Self::recall_preset`].
+    pub fn morph_toward(
+        &mut self,
+        reference: &WorldPreset,
+        fraction: f64,
+        ramp_secs: f64,
+    ) -> ApplyResult {
+        let preset = self.state.preset().blend(reference, fraction);
+        self.recall_preset(preset, ramp_secs)
+    }
+
+    /// The world the named scene leads to, if there is such a scene; see
+    /// [`WorldPreset::from_targets`].
+    pub fn scene_preset(&self, name: &str) -> Option<WorldPreset> {
+        self.scenes.get(name).map(WorldPreset::from_targets)
+    }
+
+    /// Apply event and report what it did. Ticks are ignored while paused.
+    ///
+    /// Perform actions that change the world are kept, up to [`UNDO_DEPTH`],
+    /// for [`PerformAction::Undo`]; triggers, which come from sensors and
+    /// other automation, are not, nor are actions that changed nothing, such
+    /// as a freeze or a pulse with energy already at its ceiling.
+    pub fn apply(&mut self, event: Event) -> ApplyResult {
+        let mut applied = true;
+        let mut clamped = Vec::new();
+        let is_tick = event.is_tick();
+        let is_undo = matches!(event, Event::Perform(PerformAction::Undo));
+        let before = self.state.preset();
+        let undoable = match &event {
+            Event::Perform(PerformAction::Scene { .. }) => Some(Some(self.scene.clone())),
+            Event::Perform(_) if !is_undo => Some(None),
+            _ => None,
+        };
         match event {
-            Event::Tick { dt } => {
-                // TODO: For deterministic mode: use injected RNG instead of rand::rng()
-                self.state.drift(dt, &mut rand::rng());
-                self.update_sparkles(dt);
-            }
+            Event::Tick { .. } if self.run_state == RunState::Paused => applied = false,
+            Event::Tick { dt } => self.advance_tick(dt.max(0.0) * self.time_scale),
             Event::Trigger { kind, intensity } => match kind {
-                TriggerKind::Pulse => self.apply_pulse(intensity),
-                TriggerKind::Stir => self.apply_stir(intensity),
-                TriggerKind::Calm => self.apply_calm(intensity),
-                TriggerKind::Heat => self.apply_heat(intensity),
-                TriggerKind::Tense => self.apply_tense(intensity),
+                TriggerKind::Pulse => self.apply_pulse(intensity, &mut clamped),
+                TriggerKind::Stir => self.apply_stir(intensity, &mut clamped),
+                TriggerKind::Calm => self.apply_calm(intensity, &mut clamped),
+                TriggerKind::Heat => self.apply_heat(intensity, &mut clamped),
+                TriggerKind::Tense => self.apply_tense(intensity, &mut clamped),
+                TriggerKind::Expand => self.apply_expand(intensity, &mut clamped),
+                TriggerKind::Contract => self.apply_expand(-intensity, &mut clamped),
+                TriggerKind::Clarify => self.apply_clarify(intensity, &mut clamped),
+                TriggerKind::Roughen => self.apply_clarify(-intensity, &mut clamped),
             },
             Event::Perform(action) => match action {
-                PerformAction::Pulse { intensity } => self.apply_pulse(intensity),
-                PerformAction::Stir { intensity } => self.apply_stir(intensity),
-                PerformAction::Calm { intensity } => self.apply_calm(intensity),
-                PerformAction::Heat { intensity } => self.apply_heat(intensity),
-                PerformAction::Tense { intensity } => self.apply_tense(intensity),
+                PerformAction::Pulse { intensity } => self.apply_pulse(intensity, &mut clamped),
+                PerformAction::Stir { intensity } => self.apply_stir(intensity, &mut clamped),
+                PerformAction::Calm { intensity } => self.apply_calm(intensity, &mut clamped),
+                PerformAction::Heat { intensity } => self.apply_heat(intensity, &mut clamped),
+                PerformAction::Tense { intensity } => self.apply_tense(intensity, &mut clamped),
+                PerformAction::Expand { intensity } => self.apply_expand(intensity, &mut clamped),
+                PerformAction::Contract { intensity } => {
+                    self.apply_expand(-intensity, &mut clamped)
+                }
+                PerformAction::Clarify { intensity } => self.apply_clarify(intensity, &mut clamped),
+                PerformAction::Roughen { intensity } => {
+                    self.apply_clarify(-intensity, &mut clamped)
+                }
                 PerformAction::Scene { name } => self.apply_scene(name),
-                PerformAction::Freeze { seconds } => self.apply_freeze(seconds),
+                PerformAction::Freeze { seconds } => applied = self.apply_freeze(seconds),
+                PerformAction::Undo => applied = self.apply_undo(),
+                PerformAction::PulseAt { x, y, intensity } => {
+                    self.apply_pulse_at(x, y, intensity, &mut clamped)
+                }
             },
         }
+        if !is_tick {
+            // Discrete changes take effect at once rather than blending in;
+            // only an undo eases
+            self.previous = self.state.clone();
+            if !is_undo {
+                self.ramp = None;
+            }
+        }
+        if applied
+            && let Some(scene) = undoable
+            && (scene.is_some() || self.state.preset() != before)
+        {
+            if self.history.len() == UNDO_DEPTH {
+                self.history.remove(0);
+            }
+            self.history.push(UndoEntry {
+                before,
+                after: self.state.preset(),
+                scene,
+            });
+        }
+        self.debug_validate();
+        ApplyResult {
+            applied,
+            clamped_fields: clamped,
+            resulting_snapshot: self.get_snapshot(),
+        }
+    }
+
+    fn advance_tick(&mut self, dt: f64) {
+        self.tick += 1;
+        self.sim_time += dt;
+        self.advance(dt);
+    }
+
+    /// Runs as many fixed steps as the accumulated tick time allows.
+    fn advance(&mut self, dt: f64) {
+        self.accumulator += dt;
+        let mut steps = 0;
+        while self.accumulator >= self.step_dt {
+            if steps == MAX_STEPS_PER_TICK {
+                tracing::warn!("Dropping {:.3}s of world time", self.accumulator);
+                self.accumulator = 0.0;
+                break;
+            }
+            self.previous = self.state.clone();
+            self.state.drift(self.step_dt, &mut self.rng);
+            for modulator in &mut self.modulators {
+                modulator.modulate(self.step_dt, &mut self.state);
+            }
+            self.advance_ramp(self.step_dt);
+            let lifecycle = if self.agents.enabled() {
+                self.agents
+                    .step(self.step_dt, &mut self.state, &mut self.rng)
+            } else {
+                Vec::new()
+            };
+            self.field.step(self.step_dt, &mut self.state);
+            self.state.advance_harmony(self.step_dt, &mut self.rng);
+            let on_grid = self.update_pulse(self.step_dt);
+            self.update_sparkles(self.step_dt, on_grid, &lifecycle);
+            self.update_mood();
+            self.accumulator -= self.step_dt;
+            steps += 1;
+        }
+    }
+
+    /// Moves a preset recall along by one step, ending it when complete.
+    fn advance_ramp(&mut self, dt: f64) {
+        let Some(ramp) = &mut self.ramp else {
+            return;
+        };
+        ramp.elapsed += dt;
+        let progress = ramp.elapsed / ramp.duration;
+        self.state.set_preset(&ramp.from.lerp(&ramp.to, progress));
+        if progress >= 1.0 {
+            self.ramp = None;
+        }
     }
 
-    /// Apply pulse action: increases energy and slightly increases tension
-    fn apply_pulse(&mut self, intensity: f64) {
-        self.state.set_energy(self.state.energy() + intensity);
-        self.state
-            .set_tension(self.state.tension() + 0.1 * intensity);
+    /// Apply pulse action: increases energy and slightly increases tension.
+    /// Like every action, a negative intensity does the reverse.
+    fn apply_pulse(&mut self, intensity: f64, clamped: &mut Vec<&'static str>) {
+        let state = &mut self.state;
+        state.set_energy(nudge(state.energy(), intensity, "energy", clamped));
+        state.set_tension(nudge(state.tension(), 0.1 * intensity, "tension", clamped));
+    }
+
+    /// Apply a pulse at a point: with the field on, raises energy around it
+    /// and tension as a pulse would; otherwise an ordinary pulse.
+    fn apply_pulse_at(&mut self, x: f64, y: f64, intensity: f64, clamped: &mut Vec<&'static str>) {
+        if !self.field.enabled() {
+            return self.apply_pulse(intensity, clamped);
+        }
+        if self.field.pulse_at(x, y, intensity, &mut self.state) && !clamped.contains(&"energy") {
+            clamped.push("energy");
+        }
+        let state = &mut self.state;
+        state.set_tension(nudge(state.tension(), 0.1 * intensity, "tension", clamped));
     }
 
     /// Apply stir action: increases density and slightly increases tension
-    fn apply_stir(&mut self, intensity: f64) {
-        self.state.set_density(self.state.density() + intensity);
-        self.state
-            .set_tension(self.state.tension() + 0.1 * intensity);
+    fn apply_stir(&mut self, intensity: f64, clamped: &mut Vec<&'static str>) {
+        let state = &mut self.state;
+        state.set_density(nudge(state.density(), intensity, "density", clamped));
+        state.set_tension(nudge(state.tension(), 0.1 * intensity, "tension", clamped));
     }
 
     /// Apply calm action: decreases tension and slightly decreases density
-    fn apply_calm(&mut self, intensity: f64) {
-        self.state.set_tension(self.state.tension() - intensity);
-        self.state
-            .set_density(self.state.density() - 0.1 * intensity);
+    fn apply_calm(&mut self, intensity: f64, clamped: &mut Vec<&'static str>) {
+        let state = &mut self.state;
+        state.set_tension(nudge(state.tension(), -intensity, "tension", clamped));
+        state.set_density(nudge(state.density(), -0.1 * intensity, "density", clamped));
     }
 
     /// Apply heat action: increases warmth and slightly increases energy
-    fn apply_heat(&mut self, intensity: f64) {
-        self.state.set_warmth(self.state.warmth() + intensity);
-        self.state.set_energy(self.state.energy() + 0.1 * intensity);
+    fn apply_heat(&mut self, intensity: f64, clamped: &mut Vec<&'static str>) {
+        let state = &mut self.state;
+        state.set_warmth(nudge(state.warmth(), intensity, "warmth", clamped));
+        state.set_energy(nudge(state.energy(), 0.1 * intensity, "energy", clamped));
     }
 
     /// Apply tense action: directly increases tension
-    fn apply_tense(&mut self, intensity: f64) {
-        self.state.set_tension(self.state.tension() + intensity);
+    fn apply_tense(&mut self, intensity: f64, clamped: &mut Vec<&'static str>) {
+        let state = &mut self.state;
+        state.set_tension(nudge(state.tension(), intensity, "tension", clamped));
     }
 
-    /// Apply scene change
+    /// Apply expand action: opens up space; contract is the same, negated
+    fn apply_expand(&mut self, intensity: f64, clamped: &mut Vec<&'static str>) {
+        let state = &mut self.state;
+        state.set_space(nudge(state.space(), intensity, "space", clamped));
+    }
+
+    /// Apply clarify action: cleans up the sound; roughen is the same, negated
+    fn apply_clarify(&mut self, intensity: f64, clamped: &mut Vec<&'static str>) {
+        let state = &mut self.state;
+        state.set_clarity(nudge(state.clarity(), intensity, "clarity", clamped));
+    }
+
+    /// Apply scene change. Unknown scene names fall back to neutral targets.
     fn apply_scene(&mut self, name: String) {
-        match name.as_str() {
-            "peaceful" => {
-                self.state.set_target_density(0.3);
-                self.state.set_target_rhythm(0.4);
-                self.state.set_target_tension(0.2);
-                self.state.set_target_energy(0.3);
-                self.state.set_target_warmth(0.8);
-            }
-            "energetic" => {
-                self.state.set_target_density(0.7);
-                self.state.set_target_rhythm(0.9);
-                self.state.set_target_tension(0.6);
-                self.state.set_target_energy(0.9);
-                self.state.set_target_warmth(0.6);
-            }
-            "mysterious" => {
-                self.state.set_target_density(0.2);
-                self.state.set_target_rhythm(0.3);
-                self.state.set_target_tension(0.8);
-                self.state.set_target_energy(0.4);
-                self.state.set_target_warmth(0.2);
-            }
-            _ => {
-                self.state.set_target_density(0.5);
-                self.state.set_target_rhythm(0.5);
-                self.state.set_target_tension(0.5);
-                self.state.set_target_energy(0.5);
-                self.state.set_target_warmth(0.5);
-            }
-        }
+        let targets = self.scenes.get(&name).cloned().unwrap_or_default();
+        self.state.set_targets(&targets);
         tracing::info!("Scene changed to: {}", name);
+        let sequence = self.scene.as_ref().map_or(0, |scene| scene.sequence) + 1;
+        self.scene = Some(SceneChange {
+            name,
+            transition_secs: targets.transition_secs,
+            transition_curve: targets.transition_curve,
+            sequence,
+        });
     }
 
-    /// Apply freeze action (placeholder for future implementation)
-    fn apply_freeze(&mut self, seconds: f64) {
+    /// Apply freeze action (placeholder for future implementation).
+    /// Returns whether the world changed, which for now is never.
+    fn apply_freeze(&mut self, seconds: f64) -> bool {
         // For now, just log the freeze request
         // TODO: Implement freeze functionality
         tracing::info!("Freeze requested for {} seconds", seconds);
+        false
+    }
+
+    /// Takes back the most recent action in the history: the change it made
+    /// to the parameters and targets eases out over [`UNDO_RAMP_SECS`],
+    /// leaving drift and other changes since in place, and a scene action
+    /// restores the scene before it. Returns false when there is nothing to
+    /// undo.
+    fn apply_undo(&mut self) -> bool {
+        let Some(entry) = self.history.pop() else {
+            tracing::info!("Nothing to undo");
+            return false;
+        };
+        let from = self.state.preset();
+        self.ramp = Some(Ramp {
+            from,
+            to: from.reverted(&entry.before, &entry.after),
+            elapsed: 0.0,
+            duration: UNDO_RAMP_SECS,
+        });
+        if let Some(scene) = entry.scene {
+            // A new sequence, so clients see the restore as a scene change
+            let sequence = self.scene.as_ref().map_or(0, |scene| scene.sequence) + 1;
+            self.scene = scene.map(|scene| SceneChange { sequence, ..scene });
+            let name = self.scene.as_ref().map(|scene| scene.name.as_str());
+            tracing::info!("Undo restored scene: {}", name.unwrap_or("none"));
+        }
+        tracing::info!(
+            "Undid the last action; {} more in history",
+            self.history.len()
+        );
+        true
     }
 
-    /// Update sparkle generation based on rhythm and density
-    fn update_sparkles(&mut self, dt: f64) {
-        // Advance sparkle phase based on rhythm (higher rhythm = faster sparkle rate)
+    /// Moves the pulse grid on, noting any beat, and returns whether the step
+    /// crossed a grid point.
+    fn update_pulse(&mut self, dt: f64) -> bool {
+        let step = self.pulse.advance(
+            dt,
+            self.state.rhythm(),
+            self.state.energy(),
+            self.pulse_settings.subdivision,
+        );
+        if let Some(beat) = step.beat {
+            let beat = BeatEvent {
+                tick: self.tick,
+                sim_time_secs: self.sim_time,
+                ..beat
+            };
+            push_capped(&mut self.beats, beat);
+        }
+        step.grid_point
+    }
+
+    /// Update sparkle generation based on rhythm and density, or with agents
+    /// on from their births and deaths (`lifecycle`). With sparkles
+    /// quantized, one that fires off the grid waits for the next grid point;
+    /// several in between merge into the strongest.
+    fn update_sparkles(&mut self, dt: f64, on_grid: bool, lifecycle: &[AgentEvent]) {
+        // Advance sparkle phase based on rhythm (higher rhythm = faster sweep)
         let rhythm_factor = self.state.rhythm() * 2.0 + 0.5; // 0.5 to 2.5
         self.sparkle_phase += dt * rhythm_factor;
 
-        // Check if we should generate a sparkle
-        // Base probability modulated by density (higher density = more sparkles)
-        let base_probability = 0.3; // Base sparkle rate per second
-        let density_factor = self.state.density() * 2.0 + 0.5; // 0.5 to 2.5
-        let sparkle_probability = base_probability * density_factor * dt;
-
-        if rand::random::<f64>() < sparkle_probability {
-            // TODO: For deterministic mode: use injected RNG instead of rand::random()
-            // Generate a sparkle impulse
+        let (density, rhythm) = (self.state.density(), self.state.rhythm());
+        self.sparkle_process
+            .set_rate_scale(self.state.sparkle_rate());
+        let fired = if self.agents.enabled() {
+            // As strong as the most excited agent born or lost
+            lifecycle
+                .iter()
+                .map(|event| 0.5 + event.agent().excitement * 0.5)
+                .reduce(f64::max)
+        } else if self
+            .sparkle_process
+            .step(dt, density, rhythm, &mut self.rng)
+        {
             // Strength based on current energy level
-            let strength = 0.5 + self.state.energy() * 0.5; // 0.5 to 1.0
-            self.state.set_sparkle_impulse(strength);
-            tracing::debug!("Sparkle generated with strength {:.3}", strength);
+            Some(0.5 + self.state.energy() * 0.5) // 0.5 to 1.0
+        } else {
+            None
+        };
+        if let Some(strength) = fired {
+            self.held_sparkle = Some(self.held_sparkle.map_or(strength, |h| h.max(strength)));
+        }
+        if (on_grid || !self.pulse_settings.quantize_sparkles)
+            && let Some(strength) = self.held_sparkle.take()
+        {
+            self.fire_sparkle(strength);
         }
     }
 
-    /// Retrieves the current world state snapshot.
+    fn fire_sparkle(&mut self, strength: f64) {
+        self.state.set_sparkle_impulse(strength);
+        tracing::debug!("Sparkle generated with strength {:.3}", strength);
+
+        let state = &self.state;
+        let sparkle = SparkleEvent {
+            tick: self.tick,
+            sim_time_secs: self.sim_time,
+            strength,
+            pitch: (0.2 + 0.6 * (1.0 - state.warmth()) + 0.2 * state.tension()).clamp(0.0, 1.0),
+            pan: (self.sparkle_phase * std::f64::consts::TAU).sin(),
+        };
+        push_capped(&mut self.sparkles, sparkle);
+    }
+
+    /// Notes a change of mood once the world has clearly left the last one.
+    fn update_mood(&mut self) {
+        let (arousal, valence) = (self.state.arousal(), self.state.valence());
+        if let Some(from) = self.mood.update(arousal, valence) {
+            tracing::debug!("Mood changed from {:?} to {:?}", from, self.mood.mood());
+            let change = MoodChange {
+                tick: self.tick,
+                sim_time_secs: self.sim_time,
+                from,
+                to: self.mood.mood(),
+                arousal,
+                valence,
+            };
+            push_capped(&mut self.mood_changes, change);
+        }
+    }
+
+    /// Sparkles fired since the last call, oldest first.
+    pub fn take_sparkles(&mut self) -> Vec<SparkleEvent> {
+        std::mem::take(&mut self.sparkles)
+    }
+
+    /// Beats of the pulse grid since the last call, oldest first.
+    pub fn take_beats(&mut self) -> Vec<BeatEvent> {
+        std::mem::take(&mut self.beats)
+    }
+
+    /// Mood changes since the last call, oldest first.
+    pub fn take_mood_changes(&mut self) -> Vec<MoodChange> {
+        std::mem::take(&mut self.mood_changes)
+    }
+
+    /// Checks the world against its invariants; see [`WorldState::validate`].
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.state.validate()
+    }
+
+    /// Panics in debug builds if an update broke an invariant, so a dynamics
+    /// regression shows up where it happens rather than as odd audio later.
+    fn debug_validate(&self) {
+        if cfg!(debug_assertions)
+            && let Err(e) = self.validate()
+        {
+            panic!("World invariant broken: {}", e);
+        }
+    }
+
+    /// Number of Tick events applied so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Simulated seconds elapsed, summed from the dt of every tick.
+    pub fn sim_time(&self) -> f64 {
+        self.sim_time
+    }
+
+    /// Retrieves the current world state snapshot, interpolated between the
+    /// last two fixed steps.
     pub fn get_snapshot(&self) -> WorldSnapshot {
-        WorldSnapshot::from_world_state(&self.state)
+        let alpha = self.accumulator / self.step_dt;
+        WorldSnapshot::from_world_state(&self.previous.interpolate(&self.state, alpha))
+            .with_tick(self.tick)
+            .with_sim_time_secs(self.sim_time)
+            .with_run_state(self.run_state)
+            .with_time_scale(self.time_scale)
+            .with_scene(self.scene.clone())
+            .with_pulse(self.pulse.pulse())
+            .with_mood(self.mood.mood())
+            .with_agents(self.agents.agents().to_vec())
+            .with_field(self.field.field().cloned())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::agents::AgentSettings;
+    use crate::field::FieldSettings;
+    use crate::mood::Mood;
+    use crate::world::DriftRates;
     use rand::SeedableRng;
     use rand::rngs::StdRng;
 
     #[test]
     fn test_tick_event_bounds() {
-        let mut engine = WorldEngine::new();
+        let mut engine = WorldEngine::with_seed(0);
         let _rng = StdRng::from_seed([0; 32]);
         for _ in 0..100 {
             engine.apply(Event::Tick { dt: 0.05 });
@@ -178,7 +772,7 @@ mod tests {
 
     #[test]
     fn test_trigger_pulse() {
-        let mut engine = WorldEngine::new();
+        let mut engine = WorldEngine::with_seed(0);
         let intensity = 0.3;
         engine.apply(Event::Trigger {
             kind: TriggerKind::Pulse,
@@ -195,7 +789,7 @@ mod tests {
 
     #[test]
     fn test_trigger_stir() {
-        let mut engine = WorldEngine::new();
+        let mut engine = WorldEngine::with_seed(0);
         let intensity = 0.2;
         engine.apply(Event::Trigger {
             kind: TriggerKind::Stir,
@@ -212,7 +806,7 @@ mod tests {
 
     #[test]
     fn test_trigger_calm() {
-        let mut engine = WorldEngine::new();
+        let mut engine = WorldEngine::with_seed(0);
         let intensity = 0.4;
         engine.apply(Event::Trigger {
             kind: TriggerKind::Calm,
@@ -229,7 +823,7 @@ mod tests {
 
     #[test]
     fn test_trigger_heat() {
-        let mut engine = WorldEngine::new();
+        let mut engine = WorldEngine::with_seed(0);
         let intensity = 0.25;
         engine.apply(Event::Trigger {
             kind: TriggerKind::Heat,
@@ -246,7 +840,7 @@ mod tests {
 
     #[test]
     fn test_trigger_tense() {
-        let mut engine = WorldEngine::new();
+        let mut engine = WorldEngine::with_seed(0);
         let intensity = 0.6;
         engine.apply(Event::Trigger {
             kind: TriggerKind::Tense,
@@ -261,22 +855,101 @@ mod tests {
         assert_eq!(snapshot.warmth(), 0.5);
     }
 
+    #[test]
+    fn test_negative_intensities_push_the_other_way() {
+        let mut engine = WorldEngine::with_seed(0);
+        engine.apply(Event::Perform(PerformAction::Heat { intensity: -0.25 }));
+        engine.apply(Event::Perform(PerformAction::Stir { intensity: -0.2 }));
+        let snapshot = engine.get_snapshot();
+        assert_eq!(snapshot.warmth(), 0.5 - 0.25); // 0.25
+        assert_eq!(snapshot.energy(), 0.5 - 0.1 * 0.25); // 0.475
+        assert_eq!(snapshot.density(), 0.5 - 0.2); // 0.3
+        assert_eq!(snapshot.tension(), 0.5 - 0.1 * 0.2); // 0.48
+
+        // Negative calm agitates; held at the floor, it reports the clamp
+        let result = engine.apply(Event::Perform(PerformAction::Calm { intensity: -0.3 }));
+        assert!(result.clamped_fields.is_empty());
+        assert!((engine.get_snapshot().tension() - 0.78).abs() < 1e-9);
+        let result = engine.apply(Event::Perform(PerformAction::Heat { intensity: -1.0 }));
+        assert_eq!(engine.get_snapshot().warmth(), 0.0);
+        assert_eq!(result.clamped_fields, vec!["warmth"]);
+    }
+
+    #[test]
+    fn test_expand_and_contract_move_space_alone() {
+        let mut engine = still_engine();
+        engine.apply(Event::Perform(PerformAction::Expand { intensity: 0.3 }));
+        let snapshot = engine.get_snapshot();
+        assert_eq!(snapshot.space(), 0.8);
+        assert_eq!(snapshot.density(), 0.5);
+        assert_eq!(snapshot.energy(), 0.5);
+
+        engine.apply(Event::Trigger {
+            kind: TriggerKind::Contract,
+            intensity: 0.6,
+        });
+        assert!((engine.get_snapshot().space() - 0.2).abs() < 1e-9);
+        let result = engine.apply(Event::Perform(PerformAction::Contract { intensity: 0.5 }));
+        assert_eq!(result.clamped_fields, vec!["space"]);
+        assert_eq!(result.resulting_snapshot.space(), 0.0);
+
+        // Undo restores space like any other parameter
+        engine.apply(Event::Perform(PerformAction::Undo));
+        engine.apply(Event::Tick { dt: 1.0 });
+        assert!((engine.get_snapshot().space() - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_clarify_and_roughen_move_clarity_alone() {
+        let mut engine = still_engine();
+        engine.apply(Event::Perform(PerformAction::Clarify { intensity: 0.4 }));
+        let snapshot = engine.get_snapshot();
+        assert!((snapshot.clarity() - 0.9).abs() < 1e-9);
+        assert_eq!(snapshot.space(), 0.5);
+        assert_eq!(snapshot.tension(), 0.5);
+
+        engine.apply(Event::Trigger {
+            kind: TriggerKind::Roughen,
+            intensity: 0.5,
+        });
+        assert!((engine.get_snapshot().clarity() - 0.4).abs() < 1e-9);
+        let result = engine.apply(Event::Perform(PerformAction::Roughen { intensity: 0.6 }));
+        assert_eq!(result.clamped_fields, vec!["clarity"]);
+        assert_eq!(result.resulting_snapshot.clarity(), 0.0);
+    }
+
     #[test]
     fn test_trigger_bounds_clamping() {
-        let mut engine = WorldEngine::new();
+        let mut engine = WorldEngine::with_seed(0);
         // Apply high intensity to test clamping
-        engine.apply(Event::Trigger {
+        let result = engine.apply(Event::Trigger {
             kind: TriggerKind::Pulse,
             intensity: 2.0, // Should clamp energy to 1.0
         });
         let snapshot = engine.get_snapshot();
         assert_eq!(snapshot.energy(), 1.0);
         assert_eq!(snapshot.tension(), 0.5 + 0.1 * 2.0); // 0.7
+        assert!(result.applied);
+        assert_eq!(result.clamped_fields, vec!["energy"]);
+        assert_eq!(result.resulting_snapshot.energy(), 1.0);
+    }
+
+    #[test]
+    fn test_apply_result_reports_ignored_freeze() {
+        let mut engine = WorldEngine::with_seed(0);
+        let result = engine.apply(Event::Perform(PerformAction::Calm { intensity: 0.2 }));
+        assert!(result.applied);
+        assert!(result.clamped_fields.is_empty());
+        assert_eq!(result.resulting_snapshot.tension(), 0.5 - 0.2);
+
+        let result = engine.apply(Event::Perform(PerformAction::Freeze { seconds: 5.0 }));
+        assert!(!result.applied);
+        assert_eq!(result.resulting_snapshot.tension(), 0.5 - 0.2);
     }
 
     #[test]
     fn test_perform_pulse() {
-        let mut engine = WorldEngine::new();
+        let mut engine = WorldEngine::with_seed(0);
         let intensity = 0.3;
         engine.apply(Event::Perform(PerformAction::Pulse { intensity }));
         let snapshot = engine.get_snapshot();
@@ -290,7 +963,7 @@ mod tests {
 
     #[test]
     fn test_perform_stir() {
-        let mut engine = WorldEngine::new();
+        let mut engine = WorldEngine::with_seed(0);
         let intensity = 0.2;
         engine.apply(Event::Perform(PerformAction::Stir { intensity }));
         let snapshot = engine.get_snapshot();
@@ -304,7 +977,7 @@ mod tests {
 
     #[test]
     fn test_perform_calm() {
-        let mut engine = WorldEngine::new();
+        let mut engine = WorldEngine::with_seed(0);
         let intensity = 0.4;
         engine.apply(Event::Perform(PerformAction::Calm { intensity }));
         let snapshot = engine.get_snapshot();
@@ -318,7 +991,7 @@ mod tests {
 
     #[test]
     fn test_perform_scene() {
-        let mut engine = WorldEngine::new();
+        let mut engine = WorldEngine::with_seed(0);
         engine.apply(Event::Perform(PerformAction::Scene {
             name: "sunrise".to_string(),
         }));
@@ -330,4 +1003,494 @@ mod tests {
         assert_eq!(snapshot.energy(), 0.5);
         assert_eq!(snapshot.warmth(), 0.5);
     }
+
+    #[test]
+    fn test_tick_sequence_counts_only_ticks() {
+        let mut engine = WorldEngine::with_seed(0);
+        engine.apply(Event::Tick { dt: 0.05 });
+        engine.apply(Event::Perform(PerformAction::Pulse { intensity: 0.1 }));
+        engine.apply(Event::Tick { dt: 0.05 });
+        assert_eq!(engine.tick(), 2);
+        assert_eq!(engine.get_snapshot().tick(), 2);
+        assert!((engine.get_snapshot().sim_time_secs() - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_registered_scene_sets_targets() {
+        let mut engine = WorldEngine::with_seed(0);
+        engine.set_dynamics(WorldDynamics {
+            drift_factor: 0.0,
+            decay_factor: 1.0,
+            ..WorldDynamics::default()
+        });
+        engine.register_scene(
+            "sunrise",
+            SceneTargets {
+                warmth: 1.0,
+                ..SceneTargets::default()
+            },
+        );
+        engine.apply(Event::Perform(PerformAction::Scene {
+            name: "sunrise".to_string(),
+        }));
+        for _ in 0..20 {
+            engine.apply(Event::Tick { dt: 0.05 });
+        }
+        let snapshot = engine.get_snapshot();
+        assert!(snapshot.warmth() > 0.5);
+        assert_eq!(snapshot.density(), 0.5);
+    }
+
+    #[test]
+    fn test_world_evolution_ignores_tick_jitter() {
+        let calm = WorldDynamics {
+            drift_factor: 0.0,
+            decay_factor: 0.5,
+            ..WorldDynamics::default()
+        };
+        // Binary fractions keep the accumulator exact
+        let run = |dts: &[f64]| {
+            let mut engine = WorldEngine::with_seed(0);
+            engine.set_dynamics(calm);
+            engine.set_step_hz(16.0);
+            engine.apply(Event::Perform(PerformAction::Scene {
+                name: "energetic".to_string(),
+            }));
+            for &dt in dts.iter().cycle().take(32) {
+                engine.apply(Event::Tick { dt });
+            }
+            engine.get_snapshot()
+        };
+        let steady = run(&[0.0625]);
+        let jittery = run(&[0.03125, 0.09375]);
+        assert!(steady.energy() > 0.5);
+        assert!((steady.energy() - jittery.energy()).abs() < 1e-9);
+        assert!((steady.rhythm() - jittery.rhythm()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_snapshot_interpolates_between_steps() {
+        let mut engine = WorldEngine::with_seed(0);
+        engine.set_dynamics(WorldDynamics {
+            drift_factor: 0.0,
+            decay_factor: 1.0,
+            ..WorldDynamics::default()
+        });
+        engine.set_step_hz(10.0);
+        engine.apply(Event::Perform(PerformAction::Scene {
+            name: "energetic".to_string(),
+        }));
+        engine.apply(Event::Tick { dt: 0.1 });
+        let first = engine.get_snapshot().energy();
+        engine.apply(Event::Tick { dt: 0.05 });
+        let halfway = engine.get_snapshot().energy();
+        engine.apply(Event::Tick { dt: 0.05 });
+        let second = engine.get_snapshot().energy();
+        // Snapshots trail the newest step by up to one step, blending in
+        // proportion to the time accumulated toward the next
+        assert_eq!(first, 0.5);
+        assert!(second > first);
+        assert!((halfway - (first + second) / 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_paused_engine_ignores_ticks_but_steps() {
+        let mut engine = WorldEngine::with_seed(0);
+        engine.set_run_state(RunState::Paused);
+        let result = engine.apply(Event::Tick { dt: 0.05 });
+        assert!(!result.applied);
+        assert_eq!(engine.tick(), 0);
+        assert_eq!(result.resulting_snapshot.run_state(), RunState::Paused);
+
+        let stepped = engine.step(3);
+        assert_eq!(engine.tick(), 3);
+        assert!((stepped.resulting_snapshot.sim_time_secs() - 3.0 / DEFAULT_STEP_HZ).abs() < 1e-12);
+
+        engine.set_run_state(RunState::Running);
+        assert!(engine.apply(Event::Tick { dt: 0.05 }).applied);
+        assert_eq!(engine.tick(), 4);
+    }
+
+    #[test]
+    fn test_time_scale_multiplies_dt() {
+        let mut engine = WorldEngine::with_seed(0);
+        engine.set_time_scale(4.0);
+        engine.apply(Event::Tick { dt: 0.25 });
+        let snapshot = engine.get_snapshot();
+        assert_eq!(snapshot.sim_time_secs(), 1.0);
+        assert_eq!(snapshot.time_scale(), 4.0);
+
+        engine.set_time_scale(100.0);
+        assert_eq!(engine.time_scale(), 10.0);
+    }
+
+    #[test]
+    fn test_step_rate_is_clamped() {
+        let mut engine = WorldEngine::with_seed(0);
+        for (hz, step_dt) in [
+            (0.0, 1.0),
+            (-60.0, 1.0),
+            (f64::INFINITY, 0.001),
+            (f64::NAN, 0.001),
+            (20.0, 0.05),
+        ] {
+            engine.set_step_hz(hz);
+            assert_eq!(engine.step_dt, step_dt, "{} Hz", hz);
+        }
+        engine.step(2);
+        assert!((engine.sim_time() - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_scene_change_reported_in_snapshot() {
+        let mut engine = WorldEngine::with_seed(0);
+        assert!(engine.get_snapshot().scene().is_none());
+
+        let scene = |name: &str| {
+            Event::Perform(PerformAction::Scene {
+                name: name.to_string(),
+            })
+        };
+        engine.apply(scene("energetic"));
+        engine.apply(scene("energetic"));
+        let snapshot = engine.get_snapshot();
+        let change = snapshot.scene().unwrap();
+        assert_eq!(change.name, "energetic");
+        assert_eq!(change.transition_secs, 2.0);
+        assert_eq!(change.sequence, 2);
+    }
+
+    fn still_engine() -> WorldEngine {
+        let mut engine = WorldEngine::with_seed(0);
+        engine.set_dynamics(WorldDynamics {
+            drift_factor: 0.0,
+            decay_factor: 0.0,
+            space: DriftRates {
+                drift_factor: 0.0,
+                decay_factor: 0.0,
+            },
+            clarity: DriftRates {
+                drift_factor: 0.0,
+                decay_factor: 0.0,
+            },
+            ..WorldDynamics::default()
+        });
+        engine
+    }
+
+    #[test]
+    fn test_recall_preset_ramps_over_sim_time() {
+        let mut engine = still_engine();
+        let preset = engine.capture_preset();
+        engine.apply(Event::Perform(PerformAction::Stir { intensity: 0.4 }));
+        assert!((engine.get_snapshot().density() - 0.9).abs() < 1e-9);
+
+        // Checked on the live state; snapshots trail it by one step
+        engine.recall_preset(preset, 1.0);
+        engine.step(30);
+        assert!((engine.capture_preset().density - 0.7).abs() < 1e-9);
+        engine.step(31);
+        assert!((engine.capture_preset().density - 0.5).abs() < 1e-9);
+        assert!(engine.ramp.is_none());
+
+        // Instant recall
+        engine.apply(Event::Perform(PerformAction::Stir { intensity: 0.4 }));
+        let result = engine.recall_preset(preset, 0.0);
+        assert!((result.resulting_snapshot.density() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_event_cancels_preset_ramp() {
+        let mut engine = still_engine();
+        let mut preset = engine.capture_preset();
+        preset.energy = 0.0;
+        engine.recall_preset(preset, 1.0);
+        engine.step(30);
+        engine.apply(Event::Perform(PerformAction::Pulse { intensity: 0.1 }));
+        let energy = engine.capture_preset().energy;
+        engine.step(30);
+        assert!((engine.capture_preset().energy - energy).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_seeded_engines_evolve_identically() {
+        let run = |seed| {
+            let mut engine = WorldEngine::with_seed(seed);
+            for _ in 0..200 {
+                engine.apply(Event::Tick { dt: 0.1 });
+            }
+            engine.get_snapshot()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn test_sync_to_adopts_snapshot_and_scene_targets() {
+        let mut server = WorldEngine::with_seed(1);
+        server.apply(Event::Perform(PerformAction::Scene {
+            name: "peaceful".to_string(),
+        }));
+        server.set_time_scale(2.0);
+        for _ in 0..50 {
+            server.apply(Event::Tick { dt: 0.1 });
+        }
+        let snapshot = server.get_snapshot();
+
+        let mut local = WorldEngine::with_seed(2);
+        local.sync_to(&snapshot);
+        assert_eq!(local.get_snapshot(), snapshot);
+        // Prediction decays toward the scene's targets, as the server does
+        let peaceful = builtin_scenes()["peaceful"].clone();
+        let mut state = local.state.clone();
+        state.set_targets(&peaceful);
+        assert_eq!(local.state.preset(), state.preset());
+    }
+
+    #[test]
+    fn test_modulators_run_each_step_in_order() {
+        use crate::modulator::FnModulator;
+
+        let mut engine = still_engine();
+        engine.add_modulator(FnModulator::new("warm", |dt, state: &mut WorldState| {
+            state.set_warmth(state.warmth() + dt);
+        }));
+        engine.add_modulator(FnModulator::new("follow", |_, state: &mut WorldState| {
+            state.set_energy(state.warmth());
+        }));
+        assert_eq!(engine.modulator_names(), ["warm", "follow"]);
+
+        engine.step(6);
+        let preset = engine.capture_preset();
+        assert!((preset.warmth - 0.6).abs() < 1e-9);
+        // The second modulator sees the first's change within the same step
+        assert_eq!(preset.energy, preset.warmth);
+
+        engine.set_run_state(RunState::Paused);
+        engine.apply(Event::Tick { dt: 1.0 });
+        assert_eq!(engine.capture_preset().warmth, preset.warmth);
+    }
+
+    #[test]
+    fn test_sparkles_are_collected_once() {
+        let mut engine = WorldEngine::with_seed(3);
+        engine.recall_preset(
+            WorldPreset {
+                density: 1.0,
+                energy: 1.0,
+                ..engine.capture_preset()
+            },
+            0.0,
+        );
+        for _ in 0..600 {
+            engine.apply(Event::Tick { dt: 0.05 });
+        }
+        let sparkles = engine.take_sparkles();
+        assert!(!sparkles.is_empty());
+        assert!(sparkles.len() <= MAX_PENDING_EVENTS);
+        assert!(sparkles.windows(2).all(|w| w[0].tick <= w[1].tick));
+        for sparkle in &sparkles {
+            assert!((0.5..=1.0).contains(&sparkle.strength));
+            assert!((0.0..=1.0).contains(&sparkle.pitch));
+            assert!((-1.0..=1.0).contains(&sparkle.pan));
+        }
+        assert!(engine.take_sparkles().is_empty());
+    }
+
+    #[test]
+    fn test_mood_changes_are_reported_once() {
+        let mut engine = still_engine();
+        // The tracker starts from the world's own mood
+        assert_eq!(engine.get_snapshot().mood(), engine.state.mood());
+        assert_eq!(engine.get_snapshot().mood(), Mood::Serene);
+        engine.recall_preset(
+            WorldPreset {
+                density: 0.9,
+                rhythm: 0.9,
+                energy: 0.9,
+                tension: 0.8,
+                warmth: 0.2,
+                ..engine.capture_preset()
+            },
+            0.0,
+        );
+        for _ in 0..20 {
+            engine.apply(Event::Tick { dt: 0.05 });
+        }
+        let changes = engine.take_mood_changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            (changes[0].from, changes[0].to),
+            (Mood::Serene, Mood::Restless)
+        );
+        assert!((changes[0].valence + 0.6).abs() < 1e-9);
+        assert_eq!(engine.get_snapshot().mood(), Mood::Restless);
+        assert!(engine.take_mood_changes().is_empty());
+    }
+
+    #[test]
+    fn test_quantized_sparkles_land_on_the_grid() {
+        let mut engine = WorldEngine::with_seed(5);
+        engine.set_dynamics(WorldDynamics {
+            pulse: PulseSettings {
+                quantize_sparkles: true,
+                subdivision: 1,
+            },
+            ..WorldDynamics::default()
+        });
+        let mut on_beat = 0;
+        for _ in 0..3000 {
+            engine.apply(Event::Tick { dt: 0.05 });
+            let beats: Vec<u64> = engine.take_beats().iter().map(|b| b.tick).collect();
+            for sparkle in engine.take_sparkles() {
+                // A tick spans three fixed steps, so the beat falls in it
+                assert!(beats.contains(&sparkle.tick), "{:?}", sparkle);
+                on_beat += 1;
+            }
+        }
+        assert!(on_beat > 10);
+        let pulse = engine.get_snapshot().pulse();
+        assert!(pulse.beat > 100 && (0.0..1.0).contains(&pulse.phase));
+    }
+
+    #[test]
+    fn test_agents_set_density_and_spark_on_birth() {
+        let mut engine = WorldEngine::with_seed(6);
+        let agents = AgentSettings {
+            enabled: true,
+            ..AgentSettings::default()
+        };
+        engine.set_dynamics(WorldDynamics {
+            agents,
+            ..WorldDynamics::default()
+        });
+        assert_eq!(engine.get_snapshot().agents().len(), 16);
+
+        engine.apply(Event::Perform(PerformAction::Stir { intensity: 1.0 }));
+        engine.apply(Event::Tick { dt: 0.05 });
+        let snapshot = engine.get_snapshot();
+        let population = snapshot.agents().len();
+        assert!(population > 16, "{} agents", population);
+        assert_eq!(engine.capture_preset().density, population as f64 / 32.0);
+        // Births spark in place of the sparkle process
+        assert!(!engine.take_sparkles().is_empty());
+
+        // Turning agents off hands the world back to the process
+        engine.set_dynamics(WorldDynamics::default());
+        assert!(engine.get_snapshot().agents().is_empty());
+    }
+
+    #[test]
+    fn test_pulse_at_raises_energy_locally_with_the_field_on() {
+        let pulse_at = Event::Perform(PerformAction::PulseAt {
+            x: 0.5,
+            y: 0.5,
+            intensity: 0.4,
+        });
+        // Off, it is an ordinary pulse
+        let mut engine = still_engine();
+        engine.apply(pulse_at.clone());
+        assert!((engine.capture_preset().energy - 0.9).abs() < 1e-9);
+        assert!(engine.get_snapshot().field().is_none());
+
+        let mut engine = still_engine();
+        engine.set_dynamics(WorldDynamics {
+            drift_factor: 0.0,
+            decay_factor: 0.0,
+            field: FieldSettings {
+                enabled: true,
+                ..FieldSettings::default()
+            },
+            ..WorldDynamics::default()
+        });
+        let result = engine.apply(pulse_at);
+        let energy = engine.capture_preset().energy;
+        assert!(energy > 0.5 && energy < 0.7, "{}", energy);
+        assert!(result.clamped_fields.is_empty());
+        let snapshot = engine.get_snapshot();
+        let field = snapshot.field().unwrap();
+        let centre = field.energy()[4 * 16 + 8];
+        assert!(centre > 0.85, "{}", centre);
+        assert!((field.energy()[0] - 0.5).abs() < 0.01);
+        assert_eq!(field.energy().iter().sum::<f64>() / 144.0, energy);
+
+        // Undo evens the grid back down by what the pulse added overall
+        engine.apply(Event::Perform(PerformAction::Undo));
+        engine.step((UNDO_RAMP_SECS * DEFAULT_STEP_HZ) as u32 + 1);
+        assert!((engine.capture_preset().energy - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_undo_eases_out_the_last_action() {
+        let mut engine = WorldEngine::with_seed(2);
+        engine.apply(Event::Perform(PerformAction::Stir { intensity: 0.2 }));
+        engine.apply(Event::Perform(PerformAction::Pulse { intensity: 1.0 }));
+        // A sensor trigger since the pulse is kept
+        engine.apply(Event::Trigger {
+            kind: TriggerKind::Heat,
+            intensity: 0.1,
+        });
+        assert_eq!(engine.get_snapshot().energy(), 1.0);
+
+        let result = engine.apply(Event::Perform(PerformAction::Undo));
+        assert!(result.applied);
+        // The change eases out rather than jumping
+        assert_eq!(result.resulting_snapshot.energy(), 1.0);
+        engine.step((UNDO_RAMP_SECS * DEFAULT_STEP_HZ) as u32 + 1);
+        let snapshot = engine.get_snapshot();
+        // Energy went from 0.5 to 1.0 (clamped from 1.5), plus 0.01 of heat
+        assert!((snapshot.energy() - 0.51).abs() < 0.05, "{:?}", snapshot);
+        assert!((snapshot.density() - 0.7).abs() < 0.05);
+        assert!((snapshot.warmth() - 0.6).abs() < 0.05);
+
+        // Then the stir, then nothing
+        engine.apply(Event::Perform(PerformAction::Undo));
+        engine.step((UNDO_RAMP_SECS * DEFAULT_STEP_HZ) as u32 + 1);
+        assert!((engine.get_snapshot().density() - 0.5).abs() < 0.05);
+        assert!(!engine.apply(Event::Perform(PerformAction::Undo)).applied);
+    }
+
+    #[test]
+    fn test_undo_skips_actions_that_changed_nothing() {
+        let mut engine = WorldEngine::with_seed(2);
+        engine.apply(Event::Perform(PerformAction::Stir { intensity: 0.2 }));
+        let freeze = engine.apply(Event::Perform(PerformAction::Freeze { seconds: 5.0 }));
+        assert!(!freeze.applied);
+        engine.apply(Event::Perform(PerformAction::Calm { intensity: 0.0 }));
+
+        // The undo takes back the stir, and then there is nothing left
+        assert!(engine.apply(Event::Perform(PerformAction::Undo)).applied);
+        engine.step((UNDO_RAMP_SECS * DEFAULT_STEP_HZ) as u32 + 1);
+        assert!((engine.get_snapshot().density() - 0.5).abs() < 0.05);
+        assert!(!engine.apply(Event::Perform(PerformAction::Undo)).applied);
+    }
+
+    #[test]
+    fn test_undo_restores_the_previous_scene_and_history_is_bounded() {
+        let mut engine = WorldEngine::with_seed(2);
+        let scene = |name: &str| {
+            Event::Perform(PerformAction::Scene {
+                name: name.to_string(),
+            })
+        };
+        engine.apply(scene("peaceful"));
+        engine.apply(scene("energetic"));
+        engine.apply(Event::Perform(PerformAction::Undo));
+        let restored = engine.get_snapshot().scene().cloned().unwrap();
+        assert_eq!((restored.name.as_str(), restored.sequence), ("peaceful", 3));
+        engine.step((UNDO_RAMP_SECS * DEFAULT_STEP_HZ) as u32 + 1);
+        let peaceful = &builtin_scenes()["peaceful"];
+        assert!((engine.capture_preset().target_warmth - peaceful.warmth).abs() < 1e-9);
+
+        engine.apply(Event::Perform(PerformAction::Undo));
+        assert_eq!(engine.get_snapshot().scene(), None);
+
+        for _ in 0..UNDO_DEPTH + 5 {
+            engine.apply(Event::Perform(PerformAction::Calm { intensity: 0.01 }));
+        }
+        let undone = (0..UNDO_DEPTH + 5)
+            .take_while(|_| engine.apply(Event::Perform(PerformAction::Undo)).applied)
+            .count();
+        assert_eq!(undone, UNDO_DEPTH);
+    }
 }
@@ -1,5 +1,12 @@
 //! Defines the events that can occur in the world.
 
+use std::ops::RangeInclusive;
+
+/// Range of action and trigger intensities. Intensities are signed: a
+/// negative one pushes the opposite way, so `Heat` at -0.5 cools by as much
+/// as `Heat` at 0.5 warms, and one slider centred on zero can drive both.
+pub const INTENSITY_RANGE: RangeInclusive<f64> = -1.0..=1.0;
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum Event {
     Tick { dt: f64 },
@@ -7,24 +14,120 @@ pub enum Event {
     Perform(PerformAction),
 }
 
+impl Event {
+    /// Attaches the origin of this event.
+    pub fn with_source(self, source: EventSource) -> SourcedEvent {
+        SourcedEvent {
+            event: self,
+            source: Some(source),
+        }
+    }
+
+    pub fn is_tick(&self) -> bool {
+        matches!(self, Event::Tick { .. })
+    }
+}
+
+/// Who or what produced an event.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum EventSource {
+    /// A WebSocket session, by session id.
+    Session(String),
+    /// An authenticated API client, by key name.
+    ApiKey(String),
+    /// An MQTT command, by topic.
+    Mqtt(String),
+    /// A physical sensor, by configured name.
+    Sensor(String),
+    /// The periodic tick task.
+    Tick,
+    /// Server-side automation (schedules, scripted arcs).
+    Automation,
+}
+
+/// An event together with its origin, if known.
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SourcedEvent {
+    pub event: Event,
+    pub source: Option<EventSource>,
+}
+
+impl From<Event> for SourcedEvent {
+    fn from(event: Event) -> Self {
+        Self {
+            event,
+            source: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum TriggerKind {
     Pulse,
     Stir,
     Calm,
     Heat,
     Tense,
+    Expand,
+    Contract,
+    Clarify,
+    Roughen,
 }
 
+/// Something a performer does to the world. Intensities are within
+/// [`INTENSITY_RANGE`].
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum PerformAction {
-    Pulse { intensity: f64 },
-    Stir { intensity: f64 },
-    Calm { intensity: f64 },
-    Heat { intensity: f64 },
-    Tense { intensity: f64 },
-    Scene { name: String },
-    Freeze { seconds: f64 },
+    Pulse {
+        intensity: f64,
+    },
+    Stir {
+        intensity: f64,
+    },
+    Calm {
+        intensity: f64,
+    },
+    Heat {
+        intensity: f64,
+    },
+    Tense {
+        intensity: f64,
+    },
+    /// Opens the world's acoustic space toward cavernous.
+    Expand {
+        intensity: f64,
+    },
+    /// Closes the world's acoustic space toward intimate.
+    Contract {
+        intensity: f64,
+    },
+    /// Cleans the sound toward pure tones.
+    Clarify {
+        intensity: f64,
+    },
+    /// Dirties the sound toward grit and roughness.
+    Roughen {
+        intensity: f64,
+    },
+    Scene {
+        name: String,
+    },
+    Freeze {
+        seconds: f64,
+    },
+    /// Takes back the most recent perform action still in the history.
+    Undo,
+    /// A pulse around one point of the unit square, (0, 0) top left. With
+    /// the field off it is an ordinary pulse; a negative one drains energy
+    /// around the point.
+    PulseAt {
+        x: f64,
+        y: f64,
+        intensity: f64,
+    },
 }
 
 #[cfg(test)]
@@ -66,6 +169,22 @@ mod tests {
         assert_eq!(scene_event, deserialized);
     }
 
+    #[test]
+    fn test_event_source_serialization() {
+        let event = Event::Perform(PerformAction::Calm { intensity: 0.4 })
+            .with_source(EventSource::Session("ws-1".to_string()));
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json["source"],
+            serde_json::json!({"kind": "session", "id": "ws-1"})
+        );
+        let deserialized: SourcedEvent = serde_json::from_value(json).unwrap();
+        assert_eq!(event, deserialized);
+
+        let unsourced = SourcedEvent::from(Event::Tick { dt: 0.05 });
+        assert_eq!(unsourced.source, None);
+    }
+
     #[test]
     fn test_trigger_kind_serialization() {
         let kinds = vec![
@@ -0,0 +1,311 @@
+//! A spatial world: density, energy and warmth as small grids.
+//!
+//! With the field on, each of the three parameters is also a grid of cells
+//! laid over the unit square, and the scalar the audio hears is the grid's
+//! mean. The world drifts, decays and takes actions as before; each step the
+//! grids absorb whatever moved their scalars since the last one as an even
+//! shift, spread toward their neighbours, and the scalars are then read back
+//! as their means. A local action such as [`PerformAction::PulseAt`] raises a
+//! soft bump around one point instead, which spreads out and evens into the
+//! rest of the world. Visual clients get the grids; the audio only the means.
+//!
+//! [`PerformAction::PulseAt`]: crate::events::PerformAction::PulseAt
+
+use crate::world::WorldState;
+
+/// The spatial layer (`[world.field]`).
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct FieldSettings {
+    pub enabled: bool,
+    /// Cells across and down.
+    pub width: u32,
+    pub height: u32,
+    /// How fast cells even out with their neighbours, per second; the share
+    /// of a step's spread is capped at one.
+    pub diffusion: f64,
+    /// Radius of a local action's bump, the square being 1 across.
+    pub radius: f64,
+}
+
+impl Default for FieldSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            width: 16,
+            height: 9,
+            diffusion: 0.5,
+            radius: 0.15,
+        }
+    }
+}
+
+/// The grids, row by row from the top left, each cell 0 to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    width: usize,
+    height: usize,
+    density: Vec<f64>,
+    energy: Vec<f64>,
+    warmth: Vec<f64>,
+}
+
+impl Field {
+    /// A `width` by `height` field standing evenly at `state`'s values.
+    pub fn uniform(width: usize, height: usize, state: &WorldState) -> Self {
+        let cells = width * height;
+        Self {
+            width,
+            height,
+            density: vec![state.density(); cells],
+            energy: vec![state.energy(); cells],
+            warmth: vec![state.warmth(); cells],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn density(&self) -> &[f64] {
+        &self.density
+    }
+
+    pub fn energy(&self) -> &[f64] {
+        &self.energy
+    }
+
+    pub fn warmth(&self) -> &[f64] {
+        &self.warmth
+    }
+
+    /// Centre of cell `index` in the unit square.
+    fn centre(&self, index: usize) -> (f64, f64) {
+        let (column, row) = (index % self.width, index / self.width);
+        (
+            (column as f64 + 0.5) / self.width as f64,
+            (row as f64 + 0.5) / self.height as f64,
+        )
+    }
+}
+
+fn mean(cells: &[f64]) -> f64 {
+    cells.iter().sum::<f64>() / cells.len().max(1) as f64
+}
+
+/// Adds `delta` to every cell.
+fn shift(cells: &mut [f64], delta: f64) {
+    for cell in cells {
+        *cell = (*cell + delta).clamp(0.0, 1.0);
+    }
+}
+
+/// Moves each cell a quarter of `share` of the way toward each neighbour.
+/// Pairs trade equally, so the mean holds.
+fn diffuse(cells: &mut [f64], width: usize, height: usize, share: f64) {
+    let before = cells.to_vec();
+    let rate = share / 4.0;
+    for row in 0..height {
+        for column in 0..width {
+            let index = row * width + column;
+            let mut flow = 0.0;
+            if column > 0 {
+                flow += before[index - 1] - before[index];
+            }
+            if column + 1 < width {
+                flow += before[index + 1] - before[index];
+            }
+            if row > 0 {
+                flow += before[index - width] - before[index];
+            }
+            if row + 1 < height {
+                flow += before[index + width] - before[index];
+            }
+            cells[index] = before[index] + rate * flow;
+        }
+    }
+}
+
+/// The running field.
+#[derive(Debug, Clone, Default)]
+pub struct SpatialField {
+    settings: FieldSettings,
+    field: Option<Field>,
+    /// Density, energy and warmth as last read off the grids.
+    means: [f64; 3],
+}
+
+impl SpatialField {
+    pub fn new(settings: FieldSettings) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+
+    pub fn settings(&self) -> FieldSettings {
+        self.settings
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    pub fn field(&self) -> Option<&Field> {
+        self.field.as_ref()
+    }
+
+    /// Swaps the settings. Turning the field on, or resizing it, lays it
+    /// evenly over the world as it stands; turning it off drops it.
+    pub fn set_settings(&mut self, settings: FieldSettings, state: &WorldState) {
+        let resized =
+            (settings.width, settings.height) != (self.settings.width, self.settings.height);
+        let was_enabled = self.settings.enabled;
+        self.settings = settings;
+        if !settings.enabled {
+            self.field = None;
+        } else if !was_enabled || resized || self.field.is_none() {
+            self.restore(None, state);
+        }
+    }
+
+    /// Adopts `field` if it has this field's size, else lays the field
+    /// evenly over `state`.
+    pub fn restore(&mut self, field: Option<&Field>, state: &WorldState) {
+        let (width, height) = (
+            self.settings.width.max(1) as usize,
+            self.settings.height.max(1) as usize,
+        );
+        let field = match field {
+            Some(field) if (field.width, field.height) == (width, height) => field.clone(),
+            _ => Field::uniform(width, height, state),
+        };
+        self.means = [
+            mean(&field.density),
+            mean(&field.energy),
+            mean(&field.warmth),
+        ];
+        self.field = Some(field);
+    }
+
+    /// Advances `dt` seconds: shifts the grids by what moved `state`'s
+    /// density, energy and warmth since the last step, spreads them, then
+    /// sets those three from the grids' means.
+    pub fn step(&mut self, dt: f64, state: &mut WorldState) {
+        let Some(field) = self.field.as_mut() else {
+            return;
+        };
+        let share = (self.settings.diffusion * dt).clamp(0.0, 1.0);
+        let (width, height) = (field.width, field.height);
+        let scalars = [state.density(), state.energy(), state.warmth()];
+        let grids = [&mut field.density, &mut field.energy, &mut field.warmth];
+        for ((cells, scalar), last) in grids.into_iter().zip(scalars).zip(&mut self.means) {
+            shift(cells, scalar - *last);
+            diffuse(cells, width, height, share);
+            *last = mean(cells);
+        }
+        let [density, energy, warmth] = self.means;
+        state.set_density(density);
+        state.set_energy(energy);
+        state.set_warmth(warmth);
+    }
+
+    /// Raises energy by up to `intensity` (lowers it, when negative) in a
+    /// bump of the configured radius around (`x`, `y`), and the world's
+    /// energy by the bump's share of the whole. Returns whether any cell was
+    /// held at 0 or 1.
+    pub fn pulse_at(&mut self, x: f64, y: f64, intensity: f64, state: &mut WorldState) -> bool {
+        let Some(field) = self.field.as_mut() else {
+            return false;
+        };
+        if ![x, y, intensity].iter().all(|v| v.is_finite()) {
+            return false;
+        }
+        let spread = 2.0 * self.settings.radius.max(1e-3).powi(2);
+        let mut clamped = false;
+        for index in 0..field.energy.len() {
+            let (cx, cy) = field.centre(index);
+            let weight = (-((cx - x).powi(2) + (cy - y).powi(2)) / spread).exp();
+            let requested = field.energy[index] + intensity * weight;
+            clamped |= !(0.0..=1.0).contains(&requested);
+            field.energy[index] = requested.clamp(0.0, 1.0);
+        }
+        self.means[1] = mean(&field.energy);
+        state.set_energy(self.means[1]);
+        clamped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f64 = 1.0 / 60.0;
+
+    fn spatial(state: &WorldState) -> SpatialField {
+        let mut spatial = SpatialField::default();
+        let settings = FieldSettings {
+            enabled: true,
+            ..FieldSettings::default()
+        };
+        spatial.set_settings(settings, state);
+        spatial
+    }
+
+    #[test]
+    fn test_scalar_changes_shift_the_grids_evenly() {
+        let mut state = WorldState::new();
+        let mut spatial = spatial(&state);
+        let field = spatial.field().unwrap();
+        assert_eq!(field.density().len(), 16 * 9);
+
+        state.set_density(0.7);
+        state.set_warmth(0.2);
+        spatial.step(DT, &mut state);
+        let field = spatial.field().unwrap();
+        assert!(field.density().iter().all(|c| (c - 0.7).abs() < 1e-9));
+        assert!(field.warmth().iter().all(|c| (c - 0.2).abs() < 1e-9));
+        assert!((state.density() - 0.7).abs() < 1e-9);
+
+        spatial.set_settings(FieldSettings::default(), &state);
+        assert!(spatial.field().is_none());
+    }
+
+    #[test]
+    fn test_a_local_pulse_spreads_and_keeps_its_mean() {
+        let mut state = WorldState::new();
+        let mut spatial = spatial(&state);
+        // Top left, in the cell at column 1, row 1
+        spatial.pulse_at(0.1, 0.15, 0.4, &mut state);
+        let field = spatial.field().unwrap();
+        let near = field.energy()[16 + 1];
+        let far = field.energy()[16 * 9 - 1];
+        assert!(near > 0.85 && far < 0.51, "{} {}", near, far);
+        let raised = state.energy();
+        assert!(raised > 0.5 && raised < 0.6, "{}", raised);
+
+        for _ in 0..(20.0 / DT) as usize {
+            spatial.step(DT, &mut state);
+        }
+        let field = spatial.field().unwrap();
+        assert!(field.energy()[16 + 1] < near - 0.1);
+        assert!(field.energy()[16 * 9 - 1] > far);
+        assert!((state.energy() - raised).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_a_negative_pulse_drains_around_the_point() {
+        let mut state = WorldState::new();
+        let mut spatial = spatial(&state);
+        assert!(spatial.pulse_at(0.1, 0.15, -0.8, &mut state));
+        let field = spatial.field().unwrap();
+        assert_eq!(field.energy()[16 + 1], 0.0);
+        assert!(field.energy()[16 * 9 - 1] > 0.49);
+        let lowered = state.energy();
+        assert!(lowered < 0.5 && lowered > 0.4, "{}", lowered);
+    }
+}
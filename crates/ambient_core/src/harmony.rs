@@ -0,0 +1,355 @@
+//! A slow harmonic narrative under the drone.
+//!
+//! The world holds a key, a root note and a mode, and a chord within it
+//! named by its scale degree. Every so often the chord moves on along the
+//! paths of common-practice harmony: tonic chords lead anywhere, predominant
+//! chords toward the dominant, dominant chords home. Tension shortens the
+//! time between chords and leans the choice toward the dominant; relaxed
+//! worlds come home more often. Warmth and tension pick the mode, brighter
+//! (Lydian, Ionian) in warm, relaxed worlds and darker (Aeolian, Phrygian) in
+//! cool or tense ones, but the mode only changes when the progression lands
+//! on the tonic, and the key now and then moves by a fifth from there too.
+
+use rand::Rng;
+
+/// Seconds a chord lasts at tension 0 and 1.
+const RELAXED_CHORD_SECS: f64 = 40.0;
+const TENSE_CHORD_SECS: f64 = 15.0;
+
+/// Chance of moving the key by a fifth on arriving at the tonic.
+const MODULATION_CHANCE: f64 = 0.25;
+
+/// A church mode, from brightest to darkest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Mode {
+    Lydian,
+    Ionian,
+    Mixolydian,
+    #[default]
+    Dorian,
+    Aeolian,
+    Phrygian,
+}
+
+impl Mode {
+    const ALL: [Mode; 6] = [
+        Mode::Lydian,
+        Mode::Ionian,
+        Mode::Mixolydian,
+        Mode::Dorian,
+        Mode::Aeolian,
+        Mode::Phrygian,
+    ];
+
+    /// Semitones above the root of each scale degree.
+    pub fn intervals(self) -> [u8; 7] {
+        match self {
+            Mode::Lydian => [0, 2, 4, 6, 7, 9, 11],
+            Mode::Ionian => [0, 2, 4, 5, 7, 9, 11],
+            Mode::Mixolydian => [0, 2, 4, 5, 7, 9, 10],
+            Mode::Dorian => [0, 2, 3, 5, 7, 9, 10],
+            Mode::Aeolian => [0, 2, 3, 5, 7, 8, 10],
+            Mode::Phrygian => [0, 1, 3, 5, 7, 8, 10],
+        }
+    }
+
+    /// The mode for a world: brightness rises with warmth and falls with
+    /// tension, Dorian when they balance.
+    pub fn for_world(tension: f64, warmth: f64) -> Self {
+        let darkness = (1.0 + tension.clamp(0.0, 1.0) - warmth.clamp(0.0, 1.0)) / 2.0;
+        let index = (darkness * Self::ALL.len() as f64) as usize;
+        Self::ALL[index.min(Self::ALL.len() - 1)]
+    }
+}
+
+/// Key and chord, as carried in a snapshot. Snapshots with a root or degree
+/// out of range are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "HarmonyFields")]
+pub struct Harmony {
+    /// Pitch class of the key's root, 0 (C) to 11 (B).
+    pub root: u8,
+    pub mode: Mode,
+    /// Scale degree of the current chord, 1 (tonic) to 7.
+    pub degree: u8,
+}
+
+impl Default for Harmony {
+    fn default() -> Self {
+        Self {
+            // E, nearest the 160 Hz the drone used to hold at warmth 0.5
+            root: 4,
+            mode: Mode::default(),
+            degree: 1,
+        }
+    }
+}
+
+/// The fields of a [`Harmony`] as deserialized, before their ranges are
+/// checked.
+#[derive(serde::Deserialize)]
+struct HarmonyFields {
+    root: u8,
+    mode: Mode,
+    degree: u8,
+}
+
+impl TryFrom<HarmonyFields> for Harmony {
+    type Error = String;
+
+    fn try_from(fields: HarmonyFields) -> Result<Self, String> {
+        if fields.root >= 12 {
+            return Err(format!(
+                "harmony root must be in [0, 11], got {}",
+                fields.root
+            ));
+        }
+        if !(1..=7).contains(&fields.degree) {
+            return Err(format!(
+                "harmony degree must be in [1, 7], got {}",
+                fields.degree
+            ));
+        }
+        Ok(Self {
+            root: fields.root,
+            mode: fields.mode,
+            degree: fields.degree,
+        })
+    }
+}
+
+impl Harmony {
+    /// The chord's degree, kept in 1 to 7 should the field have been set
+    /// out of range.
+    fn degree(&self) -> u8 {
+        self.degree.clamp(1, 7)
+    }
+
+    /// Semitones from the key's root to scale degree `degree` (1 to 11),
+    /// counted upward and wrapping past the seventh.
+    fn interval(&self, degree: u8) -> u8 {
+        let degree = degree.max(1) as usize;
+        let index = (degree + 6) % 7;
+        let octaves = (degree - 1) / 7;
+        self.mode.intervals()[index] + 12 * octaves as u8
+    }
+
+    /// Pitch class of the current chord's root.
+    pub fn chord_root(&self) -> u8 {
+        (self.root % 12 + self.interval(self.degree()) % 12) % 12
+    }
+
+    /// Semitones from the chord's root to its third and fifth, stacked from
+    /// the mode.
+    pub fn chord_intervals(&self) -> [u8; 2] {
+        let degree = self.degree();
+        let base = self.interval(degree);
+        [
+            self.interval(degree + 2) - base,
+            self.interval(degree + 4) - base,
+        ]
+    }
+
+    /// Frequency ratios of the chord's root, third and fifth, for a layer to
+    /// voice the chord over the drone's pitch.
+    pub fn chord_ratios(&self) -> [f64; 3] {
+        let [third, fifth] = self.chord_intervals();
+        let ratio = |semitones: u8| (semitones as f64 / 12.0).exp2();
+        [1.0, ratio(third), ratio(fifth)]
+    }
+
+    /// How far the chord pulls away from rest, 0 to 1: tonic chords (I, iii,
+    /// vi) 0, predominant ones (ii, IV) 0.35, dominant ones (V, vii) 0.7,
+    /// with a diminished fifth adding 0.3.
+    pub fn dissonance(&self) -> f64 {
+        let function: f64 = match self.degree() {
+            2 | 4 => 0.35,
+            5 | 7 => 0.7,
+            _ => 0.0,
+        };
+        let diminished = if self.chord_intervals()[1] == 6 {
+            0.3
+        } else {
+            0.0
+        };
+        (function + diminished).min(1.0)
+    }
+}
+
+/// Degrees each chord may move to, with weights before tension's lean.
+fn successors(degree: u8) -> &'static [(u8, f64)] {
+    match degree {
+        1 => &[(4, 3.0), (5, 2.0), (6, 2.0), (2, 1.0), (3, 1.0)],
+        2 => &[(5, 3.0), (7, 1.0), (4, 1.0)],
+        3 => &[(6, 3.0), (4, 1.0)],
+        4 => &[(5, 3.0), (1, 2.0), (2, 1.0), (7, 1.0)],
+        5 => &[(1, 4.0), (6, 2.0), (4, 1.0)],
+        6 => &[(2, 2.0), (4, 2.0), (5, 1.0)],
+        _ => &[(1, 3.0), (3, 1.0)],
+    }
+}
+
+/// Seconds the chord holds at `tension`.
+pub fn chord_secs(tension: f64) -> f64 {
+    RELAXED_CHORD_SECS + (TENSE_CHORD_SECS - RELAXED_CHORD_SECS) * tension.clamp(0.0, 1.0)
+}
+
+/// A running [`Harmony`]: the chord and how long it has sounded.
+#[derive(Debug, Clone, Default)]
+pub struct Progression {
+    harmony: Harmony,
+    elapsed: f64,
+}
+
+impl Progression {
+    /// A progression standing on `harmony`, its chord just struck.
+    pub fn new(harmony: Harmony) -> Self {
+        Self {
+            harmony,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn harmony(&self) -> Harmony {
+        self.harmony
+    }
+
+    /// Advances `dt` seconds of a world at `tension` and `warmth`, returning
+    /// whether the chord changed. Draws from `rng` only on a change: one
+    /// number to pick the next chord and, on arriving at the tonic, one to
+    /// decide on a modulation.
+    pub fn advance(&mut self, dt: f64, tension: f64, warmth: f64, rng: &mut impl Rng) -> bool {
+        self.elapsed += dt;
+        if self.elapsed < chord_secs(tension) {
+            return false;
+        }
+        self.elapsed = 0.0;
+
+        let tension = tension.clamp(0.0, 1.0);
+        let lean = |degree: u8, weight: f64| match degree {
+            5 | 7 => weight * (0.5 + 2.0 * tension),
+            1 => weight * (1.5 - tension),
+            _ => weight,
+        };
+        let options = successors(self.harmony.degree);
+        let total: f64 = options.iter().map(|(d, w)| lean(*d, *w)).sum();
+        let mut draw = rng.random::<f64>() * total;
+        let mut next = options[options.len() - 1].0;
+        for (degree, weight) in options {
+            draw -= lean(*degree, *weight);
+            if draw < 0.0 {
+                next = *degree;
+                break;
+            }
+        }
+        self.harmony.degree = next;
+
+        if next == 1 {
+            self.harmony.mode = Mode::for_world(tension, warmth);
+            if rng.random::<f64>() < MODULATION_CHANCE {
+                // Sharpward when tense, flatward when relaxed
+                let fifth = if tension > 0.5 { 7 } else { 5 };
+                self.harmony.root = (self.harmony.root + fifth) % 12;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    const DT: f64 = 1.0 / 60.0;
+
+    /// Chords over `secs` at fixed tension and warmth.
+    fn run(secs: f64, tension: f64, warmth: f64) -> Vec<Harmony> {
+        let mut progression = Progression::default();
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut chords = Vec::new();
+        for _ in 0..(secs / DT) as usize {
+            if progression.advance(DT, tension, warmth, &mut rng) {
+                chords.push(progression.harmony());
+            }
+        }
+        chords
+    }
+
+    #[test]
+    fn test_chords_stack_from_the_mode() {
+        let tonic = Harmony {
+            root: 0,
+            mode: Mode::Ionian,
+            degree: 1,
+        };
+        assert_eq!(tonic.chord_intervals(), [4, 7]);
+        assert_eq!(tonic.dissonance(), 0.0);
+        let leading = Harmony { degree: 7, ..tonic };
+        assert_eq!(leading.chord_root(), 11);
+        assert_eq!(leading.chord_intervals(), [3, 6]);
+        assert_eq!(leading.dissonance(), 1.0);
+        let minor_six = Harmony { degree: 6, ..tonic };
+        assert_eq!(minor_six.chord_root(), 9);
+        assert_eq!(minor_six.chord_intervals(), [3, 7]);
+        assert!((minor_six.chord_ratios()[2] - 1.4983).abs() < 1e-4);
+
+        assert_eq!(Mode::for_world(0.0, 1.0), Mode::Lydian);
+        assert_eq!(Mode::for_world(0.5, 0.5), Mode::Dorian);
+        assert_eq!(Mode::for_world(1.0, 0.0), Mode::Phrygian);
+    }
+
+    #[test]
+    fn test_out_of_range_harmony_is_rejected() {
+        let parse = |json: &str| serde_json::from_str::<Harmony>(json);
+        let harmony = parse(r#"{"root": 11, "mode": "aeolian", "degree": 7}"#).unwrap();
+        assert_eq!(harmony.chord_root(), 9);
+        assert!(parse(r#"{"root": 12, "mode": "aeolian", "degree": 1}"#).is_err());
+        assert!(parse(r#"{"root": 0, "mode": "aeolian", "degree": 0}"#).is_err());
+        assert!(parse(r#"{"root": 0, "mode": "aeolian", "degree": 8}"#).is_err());
+
+        // Set directly, the fields still never panic
+        let wild = Harmony {
+            root: 255,
+            mode: Mode::Ionian,
+            degree: 0,
+        };
+        assert_eq!(wild.chord_root(), 3);
+        assert_eq!(wild.chord_intervals(), [4, 7]);
+        let wild = Harmony {
+            degree: 255,
+            ..wild
+        };
+        assert_eq!(wild.chord_intervals(), [3, 6]);
+    }
+
+    #[test]
+    fn test_chords_change_slowly_and_faster_under_tension() {
+        let relaxed = run(3_600.0, 0.0, 0.5);
+        let tense = run(3_600.0, 1.0, 0.5);
+        assert!((85..=95).contains(&relaxed.len()), "{}", relaxed.len());
+        assert!(tense.len() > 2 * relaxed.len());
+        assert!(relaxed.iter().all(|h| (1..=7).contains(&h.degree)));
+        assert!(relaxed.windows(2).all(|w| w[0].degree != w[1].degree));
+    }
+
+    #[test]
+    fn test_tension_leans_toward_the_dominant() {
+        let dominant_share = |chords: &[Harmony]| {
+            chords.iter().filter(|h| matches!(h.degree, 5 | 7)).count() as f64 / chords.len() as f64
+        };
+        let relaxed = run(20_000.0, 0.0, 0.5);
+        let tense = run(20_000.0, 1.0, 0.5);
+        assert!(dominant_share(&tense) > dominant_share(&relaxed) + 0.1);
+
+        // The mode follows the world, changing only on the tonic
+        let first_tonic = tense.iter().position(|h| h.degree == 1).unwrap();
+        assert!(tense[..first_tonic].iter().all(|h| h.mode == Mode::Dorian));
+        assert!(tense[first_tonic..].iter().all(|h| h.mode == Mode::Aeolian));
+        // Keys wander
+        assert!(relaxed.iter().any(|h| h.root != Harmony::default().root));
+    }
+}
@@ -0,0 +1,273 @@
+//! Invariants the world dynamics must keep, checked by
+//! [`WorldState::validate`] after every engine update in debug builds and
+//! exercised with property tests below.
+//!
+//! - Every parameter and target is finite and within [0, 1]; the sparkle
+//!   impulse is finite and non-negative.
+//! - One drift call moves a parameter by at most [`max_drift_step`].
+//! - Trigger actions are monotone in intensity: more intensity never moves a
+//!   parameter less far in the action's direction.
+//! - A paused world does not evolve on ticks.
+//!
+//! [`WorldState::validate`]: crate::world::WorldState::validate
+
+use crate::world::{DriftRates, WorldDynamics};
+
+/// A broken world invariant.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum InvariantError {
+    #[error("{field} is {value}, outside [0, 1]")]
+    OutOfRange { field: &'static str, value: f64 },
+    #[error("sparkle impulse is {0}, expected a finite value >= 0")]
+    SparkleImpulse(f64),
+}
+
+/// Checks that `value` is finite and within [0, 1].
+pub(crate) fn check_unit(field: &'static str, value: f64) -> Result<(), InvariantError> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(())
+    } else {
+        Err(InvariantError::OutOfRange { field, value })
+    }
+}
+
+/// Furthest one call to `drift(df, ..)` can move a parameter: a full random
+/// walk step plus the largest pull toward a target, which is at most 1 away,
+/// at whichever of the shared, space and clarity rates is fastest.
+pub fn max_drift_step(dynamics: &WorldDynamics, df: f64) -> f64 {
+    let step = |rates: DriftRates| (rates.drift_factor + 2.0 * rates.decay_factor) * df;
+    let shared = DriftRates {
+        drift_factor: dynamics.drift_factor,
+        decay_factor: dynamics.decay_factor,
+    };
+    step(shared)
+        .max(step(dynamics.space))
+        .max(step(dynamics.clarity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::WorldEngine;
+    use crate::events::{Event, PerformAction};
+    use crate::world::{RunState, WorldPreset, WorldState};
+    use proptest::prelude::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    /// Tolerance for float rounding in the bounds.
+    const EPSILON: f64 = 1e-9;
+
+    fn unit() -> impl Strategy<Value = f64> {
+        0.0..=1.0f64
+    }
+
+    fn preset() -> impl Strategy<Value = WorldPreset> {
+        (prop::array::uniform7(unit()), prop::array::uniform7(unit())).prop_map(|(p, t)| {
+            WorldPreset {
+                density: p[0],
+                rhythm: p[1],
+                tension: p[2],
+                energy: p[3],
+                warmth: p[4],
+                space: p[5],
+                clarity: p[6],
+                target_density: t[0],
+                target_rhythm: t[1],
+                target_tension: t[2],
+                target_energy: t[3],
+                target_warmth: t[4],
+                target_space: t[5],
+                target_clarity: t[6],
+            }
+        })
+    }
+
+    fn dynamics() -> impl Strategy<Value = WorldDynamics> {
+        (
+            prop::array::uniform2(0.0..=1.0f64),
+            prop::array::uniform2(0.0..=1.0f64),
+            prop::array::uniform2(0.0..=1.0f64),
+        )
+            .prop_map(|(shared, space, clarity)| WorldDynamics {
+                drift_factor: shared[0],
+                decay_factor: shared[1],
+                space: DriftRates {
+                    drift_factor: space[0],
+                    decay_factor: space[1],
+                },
+                clarity: DriftRates {
+                    drift_factor: clarity[0],
+                    decay_factor: clarity[1],
+                },
+                ..WorldDynamics::default()
+            })
+    }
+
+    /// Any float, NaN and infinities included.
+    fn any_float() -> impl Strategy<Value = f64> {
+        prop::num::f64::ANY | prop::num::f64::QUIET_NAN
+    }
+
+    /// Any event, including out-of-range and non-finite intensities and dt.
+    fn event() -> impl Strategy<Value = Event> {
+        let intensity = prop_oneof![-2.0..=2.0f64, any_float()];
+        prop_oneof![
+            prop_oneof![0.0..=0.5f64, any_float()].prop_map(|dt| Event::Tick { dt }),
+            intensity
+                .clone()
+                .prop_map(|intensity| Event::Perform(PerformAction::Pulse { intensity })),
+            intensity
+                .clone()
+                .prop_map(|intensity| Event::Perform(PerformAction::Stir { intensity })),
+            intensity
+                .clone()
+                .prop_map(|intensity| Event::Perform(PerformAction::Calm { intensity })),
+            intensity
+                .clone()
+                .prop_map(|intensity| Event::Perform(PerformAction::Heat { intensity })),
+            intensity
+                .clone()
+                .prop_map(|intensity| Event::Perform(PerformAction::Tense { intensity })),
+            intensity
+                .clone()
+                .prop_map(|intensity| Event::Perform(PerformAction::Expand { intensity })),
+            intensity
+                .clone()
+                .prop_map(|intensity| Event::Perform(PerformAction::Contract { intensity })),
+            intensity
+                .clone()
+                .prop_map(|intensity| Event::Perform(PerformAction::Clarify { intensity })),
+            intensity
+                .clone()
+                .prop_map(|intensity| Event::Perform(PerformAction::Roughen { intensity })),
+            prop::sample::select(vec!["peaceful", "energetic", "mysterious", "unknown"]).prop_map(
+                |name| Event::Perform(PerformAction::Scene {
+                    name: name.to_string()
+                })
+            ),
+            (0.0..=10.0f64).prop_map(|seconds| Event::Perform(PerformAction::Freeze { seconds })),
+            Just(Event::Perform(PerformAction::Undo)),
+            (any_float(), any_float(), intensity).prop_map(|(x, y, intensity)| {
+                Event::Perform(PerformAction::PulseAt { x, y, intensity })
+            }),
+        ]
+    }
+
+    /// The parameter each action pushes hardest, and whether it pushes up.
+    fn primary_effect(action: &PerformAction, state: &WorldState) -> (f64, bool) {
+        match action {
+            PerformAction::Pulse { .. } => (state.energy(), true),
+            PerformAction::Stir { .. } => (state.density(), true),
+            PerformAction::Calm { .. } => (state.tension(), false),
+            PerformAction::Heat { .. } => (state.warmth(), true),
+            PerformAction::Tense { .. } => (state.tension(), true),
+            PerformAction::Expand { .. } => (state.space(), true),
+            PerformAction::Contract { .. } => (state.space(), false),
+            PerformAction::Clarify { .. } => (state.clarity(), true),
+            PerformAction::Roughen { .. } => (state.clarity(), false),
+            _ => unreachable!("only intensity actions are generated"),
+        }
+    }
+
+    fn with_intensity(kind: usize, intensity: f64) -> PerformAction {
+        match kind {
+            0 => PerformAction::Pulse { intensity },
+            1 => PerformAction::Stir { intensity },
+            2 => PerformAction::Calm { intensity },
+            3 => PerformAction::Heat { intensity },
+            4 => PerformAction::Tense { intensity },
+            5 => PerformAction::Expand { intensity },
+            6 => PerformAction::Contract { intensity },
+            7 => PerformAction::Clarify { intensity },
+            _ => PerformAction::Roughen { intensity },
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_params_stay_in_unit_range(
+            seed in any::<u64>(),
+            events in prop::collection::vec(event(), 1..64),
+        ) {
+            let mut engine = WorldEngine::with_seed(seed);
+            for event in events {
+                engine.apply(event);
+                prop_assert_eq!(engine.validate(), Ok(()));
+            }
+        }
+
+        #[test]
+        fn prop_drift_is_bounded_by_dt(
+            seed in any::<u64>(),
+            preset in preset(),
+            dynamics in dynamics(),
+            df in 0.0..=1.0f64,
+        ) {
+            let mut state = WorldState::new();
+            state.set_preset(&preset);
+            state.set_dynamics(dynamics);
+            let before = state.clone();
+            state.drift(df, &mut StdRng::seed_from_u64(seed));
+            prop_assert_eq!(state.validate(), Ok(()));
+
+            let bound = max_drift_step(&dynamics, df) + EPSILON;
+            for (a, b) in [
+                (before.density(), state.density()),
+                (before.rhythm(), state.rhythm()),
+                (before.tension(), state.tension()),
+                (before.energy(), state.energy()),
+                (before.warmth(), state.warmth()),
+                (before.space(), state.space()),
+                (before.clarity(), state.clarity()),
+            ] {
+                prop_assert!((b - a).abs() <= bound, "moved {} > {}", (b - a).abs(), bound);
+            }
+            prop_assert!(state.sparkle_impulse() <= before.sparkle_impulse());
+        }
+
+        #[test]
+        fn prop_actions_are_monotone_in_intensity(
+            preset in preset(),
+            kind in 0usize..9,
+            low in -1.5..=1.5f64,
+            extra in 0.0..=1.5f64,
+        ) {
+            let apply = |intensity: f64| {
+                let action = with_intensity(kind, intensity);
+                let mut engine = WorldEngine::with_seed(0);
+                engine.recall_preset(preset, 0.0);
+                let snapshot = engine.apply(Event::Perform(action.clone())).resulting_snapshot;
+                primary_effect(&action, &WorldState::from_snapshot(&snapshot))
+            };
+            let (weak, up) = apply(low);
+            let (strong, _) = apply(low + extra);
+            if up {
+                prop_assert!(strong >= weak, "{} < {}", strong, weak);
+            } else {
+                prop_assert!(strong <= weak, "{} > {}", strong, weak);
+            }
+        }
+
+        #[test]
+        fn prop_paused_world_does_not_evolve(
+            seed in any::<u64>(),
+            preset in preset(),
+            ticks in prop::collection::vec(0.0..=1.0f64, 1..32),
+            freeze in 0.0..=10.0f64,
+        ) {
+            let mut engine = WorldEngine::with_seed(seed);
+            engine.recall_preset(preset, 0.0);
+            engine.set_run_state(RunState::Paused);
+            let before = engine.capture_preset();
+            let snapshot = engine.get_snapshot();
+            engine.apply(Event::Perform(PerformAction::Freeze { seconds: freeze }));
+            for dt in ticks {
+                let result = engine.apply(Event::Tick { dt });
+                prop_assert!(!result.applied);
+            }
+            prop_assert_eq!(engine.capture_preset(), before);
+            prop_assert_eq!(engine.get_snapshot(), snapshot);
+        }
+    }
+}
@@ -1,3 +1,16 @@
+pub mod agents;
+pub mod composer;
+pub mod curves;
 pub mod engine;
 pub mod events;
+pub mod field;
+pub mod harmony;
+pub mod invariants;
+pub mod modulator;
+pub mod mood;
+pub mod noise;
+pub mod pulse;
+pub mod scene;
+pub mod seasons;
+pub mod sparkles;
 pub mod world;
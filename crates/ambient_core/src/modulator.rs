@@ -0,0 +1,50 @@
+//! External influences on the world that run inside the engine's tick loop.
+//!
+//! A modulator (a circadian driver, weather, a script, a sensor feed) is
+//! registered once with [`WorldEngine::add_modulator`](crate::engine::WorldEngine::add_modulator)
+//! and then called on every fixed step, right after drift. Modulators run in
+//! registration order, each seeing the previous one's changes, so several
+//! compose without racing each other through the event channel.
+
+use crate::world::WorldState;
+
+/// Adjusts the world on every fixed step.
+pub trait Modulator: Send {
+    /// Short name for logs and introspection.
+    fn name(&self) -> &str;
+
+    /// Called once per fixed step of `dt` simulated seconds. May move the
+    /// parameters or their targets; the setters clamp to [0, 1].
+    fn modulate(&mut self, dt: f64, state: &mut WorldState);
+}
+
+/// A modulator from a closure.
+pub struct FnModulator<F> {
+    name: String,
+    f: F,
+}
+
+impl<F> FnModulator<F>
+where
+    F: FnMut(f64, &mut WorldState) + Send,
+{
+    pub fn new(name: impl Into<String>, f: F) -> Self {
+        Self {
+            name: name.into(),
+            f,
+        }
+    }
+}
+
+impl<F> Modulator for FnModulator<F>
+where
+    F: FnMut(f64, &mut WorldState) + Send,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn modulate(&mut self, dt: f64, state: &mut WorldState) {
+        (self.f)(dt, state);
+    }
+}
@@ -0,0 +1,149 @@
+//! A qualitative reading of the world, for clients that would rather react
+//! to a mood than threshold five floats.
+//!
+//! Mood places the world on two axes: arousal, the mean of energy, rhythm
+//! and density, and valence, warmth less tension. Each quadrant is a mood:
+//! calm and pleasant is serene, calm and unpleasant brooding, lively and
+//! pleasant euphoric, lively and unpleasant restless. A world has to cross an
+//! axis by [`HYSTERESIS`] before its mood changes, so one hovering on a
+//! boundary does not flap between two.
+
+/// How far past an axis the world must move to change mood.
+pub const HYSTERESIS: f64 = 0.08;
+
+/// Arousal at the boundary between calm and lively moods.
+const AROUSAL_MIDPOINT: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Mood {
+    /// Calm and warm.
+    #[default]
+    Serene,
+    /// Calm but cool or tense.
+    Brooding,
+    /// Lively and warm.
+    Euphoric,
+    /// Lively but cool or tense.
+    Restless,
+}
+
+impl Mood {
+    fn from_axes(lively: bool, pleasant: bool) -> Self {
+        match (lively, pleasant) {
+            (false, true) => Mood::Serene,
+            (false, false) => Mood::Brooding,
+            (true, true) => Mood::Euphoric,
+            (true, false) => Mood::Restless,
+        }
+    }
+
+    fn is_lively(self) -> bool {
+        matches!(self, Mood::Euphoric | Mood::Restless)
+    }
+
+    fn is_pleasant(self) -> bool {
+        matches!(self, Mood::Serene | Mood::Euphoric)
+    }
+
+    /// The mood of a world with no history, its ties going to the calm and
+    /// pleasant side.
+    pub fn classify(arousal: f64, valence: f64) -> Self {
+        Self::from_axes(arousal > AROUSAL_MIDPOINT, valence >= 0.0)
+    }
+}
+
+/// Mean of energy, rhythm and density, 0 to 1.
+pub fn arousal(density: f64, rhythm: f64, energy: f64) -> f64 {
+    (density + rhythm + energy) / 3.0
+}
+
+/// Warmth less tension, -1 to 1.
+pub fn valence(tension: f64, warmth: f64) -> f64 {
+    warmth - tension
+}
+
+/// A change of mood, for clients to react to.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MoodChange {
+    /// Tick during which it changed.
+    pub tick: u64,
+    /// Simulated time at the end of that tick.
+    pub sim_time_secs: f64,
+    pub from: Mood,
+    pub to: Mood,
+    /// Where the world stood when it changed.
+    pub arousal: f64,
+    pub valence: f64,
+}
+
+/// The current mood, held until the world clearly leaves it. Starts from the
+/// mood of the world it tracks, so its first change is a real one.
+#[derive(Debug, Clone)]
+pub struct MoodTracker {
+    mood: Mood,
+}
+
+impl MoodTracker {
+    pub fn new(mood: Mood) -> Self {
+        Self { mood }
+    }
+
+    pub fn mood(&self) -> Mood {
+        self.mood
+    }
+
+    /// Moves to the mood of a world at `arousal` and `valence` if it has
+    /// cleared the hysteresis band, returning the mood it left.
+    pub fn update(&mut self, arousal: f64, valence: f64) -> Option<Mood> {
+        let mut lively = self.mood.is_lively();
+        if arousal > AROUSAL_MIDPOINT + HYSTERESIS {
+            lively = true;
+        } else if arousal < AROUSAL_MIDPOINT - HYSTERESIS {
+            lively = false;
+        }
+        let mut pleasant = self.mood.is_pleasant();
+        if valence > HYSTERESIS {
+            pleasant = true;
+        } else if valence < -HYSTERESIS {
+            pleasant = false;
+        }
+
+        let mood = Mood::from_axes(lively, pleasant);
+        (mood != self.mood).then(|| std::mem::replace(&mut self.mood, mood))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quadrants_name_moods() {
+        assert_eq!(Mood::classify(0.2, 0.5), Mood::Serene);
+        assert_eq!(Mood::classify(0.2, -0.5), Mood::Brooding);
+        assert_eq!(Mood::classify(0.8, 0.5), Mood::Euphoric);
+        assert_eq!(Mood::classify(0.8, -0.5), Mood::Restless);
+        assert_eq!(arousal(0.3, 0.6, 0.9), 0.6);
+        assert_eq!(valence(0.25, 0.75), 0.5);
+    }
+
+    #[test]
+    fn test_hysteresis_holds_a_mood_near_the_boundary() {
+        let mut tracker = MoodTracker::new(Mood::classify(0.5, 0.3));
+        // Wobbling about the arousal midpoint changes nothing
+        for arousal in [0.55, 0.45, 0.57, 0.43] {
+            assert_eq!(tracker.update(arousal, 0.3), None);
+        }
+        assert_eq!(tracker.update(0.6, 0.3), Some(Mood::Serene));
+        assert_eq!(tracker.mood(), Mood::Euphoric);
+        assert_eq!(tracker.update(0.45, 0.0), None);
+        assert_eq!(tracker.update(0.45, -0.1), Some(Mood::Euphoric));
+        assert_eq!(tracker.mood(), Mood::Restless);
+        // Both axes can flip at once
+        assert_eq!(tracker.update(0.1, 0.5), Some(Mood::Restless));
+        assert_eq!(tracker.mood(), Mood::Serene);
+    }
+}
@@ -0,0 +1,212 @@
+//! Coherent noise for visuals to texture the parameters with.
+//!
+//! Snapshots carry each parameter as one number at the snapshot rate; a
+//! visual wanting organic grain across its surface and between snapshots
+//! draws it from here instead. [`NoiseField`] is smooth value noise over the
+//! unit square and simulated time, one independent stream per parameter,
+//! wholly determined by its seed. The server publishes its seed, so every
+//! client that samples at the snapshots' `sim_time_secs` draws the same
+//! texture without it ever crossing the wire.
+//!
+//! The algorithm is part of the protocol and does not change: lattice values
+//! come from a SplitMix64 hash of the seed, the parameter's index and the
+//! lattice point; they are blended with the quintic fade `6t⁵ - 15t⁴ + 10t³`
+//! and summed over [`OCTAVES`] octaves, each at twice the frequency and half
+//! the weight of the last, the first having [`CELLS`] lattice cells across the
+//! square and moving [`CELLS_PER_SEC`] cells per simulated second.
+
+/// Lattice cells across the unit square in the first octave.
+pub const CELLS: f64 = 4.0;
+
+/// Lattice cells the first octave moves through per simulated second.
+pub const CELLS_PER_SEC: f64 = 0.5;
+
+/// Octaves summed, each finer and fainter than the last.
+pub const OCTAVES: u32 = 3;
+
+/// A parameter with its own noise stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Parameter {
+    Density,
+    Rhythm,
+    Tension,
+    Energy,
+    Warmth,
+    Space,
+    Clarity,
+}
+
+impl Parameter {
+    pub const ALL: [Parameter; 7] = [
+        Parameter::Density,
+        Parameter::Rhythm,
+        Parameter::Tension,
+        Parameter::Energy,
+        Parameter::Warmth,
+        Parameter::Space,
+        Parameter::Clarity,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Parameter::Density => "density",
+            Parameter::Rhythm => "rhythm",
+            Parameter::Tension => "tension",
+            Parameter::Energy => "energy",
+            Parameter::Warmth => "warmth",
+            Parameter::Space => "space",
+            Parameter::Clarity => "clarity",
+        }
+    }
+
+    /// Index mixed into the hash, in the order of [`Parameter::ALL`].
+    fn index(self) -> u64 {
+        self as u64
+    }
+}
+
+/// SplitMix64's finalizer.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Noise over the unit square and simulated time, seeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoiseField {
+    seed: u64,
+}
+
+impl NoiseField {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Value at a lattice point, -1 to 1.
+    fn lattice(&self, stream: u64, x: i64, y: i64, t: i64) -> f64 {
+        let mut h = mix(self.seed ^ mix(stream.wrapping_add(0x9e37_79b9_7f4a_7c15)));
+        for coordinate in [x, y, t] {
+            h = mix(h ^ (coordinate as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        }
+        (h >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+
+    /// One octave of value noise at lattice coordinates, -1 to 1.
+    fn octave(&self, stream: u64, x: f64, y: f64, t: f64) -> f64 {
+        let (x0, y0, t0) = (x.floor(), y.floor(), t.floor());
+        let (fx, fy, ft) = (fade(x - x0), fade(y - y0), fade(t - t0));
+        let (x0, y0, t0) = (x0 as i64, y0 as i64, t0 as i64);
+        let plane = |t: i64| {
+            let top = lerp(
+                self.lattice(stream, x0, y0, t),
+                self.lattice(stream, x0 + 1, y0, t),
+                fx,
+            );
+            let bottom = lerp(
+                self.lattice(stream, x0, y0 + 1, t),
+                self.lattice(stream, x0 + 1, y0 + 1, t),
+                fx,
+            );
+            lerp(top, bottom, fy)
+        };
+        lerp(plane(t0), plane(t0 + 1), ft)
+    }
+
+    /// Noise for `parameter` at (`x`, `y`) in the unit square and `time`
+    /// simulated seconds, -1 to 1. Points and times close together give
+    /// values close together.
+    pub fn sample_at(&self, parameter: Parameter, x: f64, y: f64, time: f64) -> f64 {
+        let (mut sum, mut weight, mut total, mut scale) = (0.0, 1.0, 0.0, 1.0);
+        for octave in 0..OCTAVES {
+            let stream = parameter.index() * OCTAVES as u64 + octave as u64;
+            sum += weight
+                * self.octave(
+                    stream,
+                    x * CELLS * scale,
+                    y * CELLS * scale,
+                    time * CELLS_PER_SEC * scale,
+                );
+            total += weight;
+            weight *= 0.5;
+            scale *= 2.0;
+        }
+        sum / total
+    }
+
+    /// Noise for `parameter` at `time`, as at the centre of the square.
+    pub fn sample(&self, parameter: Parameter, time: f64) -> f64 {
+        self.sample_at(parameter, 0.5, 0.5, time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_is_bounded_smooth_and_seeded() {
+        let noise = NoiseField::new(11);
+        let mut previous = noise.sample(Parameter::Energy, 0.0);
+        let (mut low, mut high) = (0.0f64, 0.0f64);
+        for step in 1..6000 {
+            let value = noise.sample(Parameter::Energy, step as f64 / 60.0);
+            assert!((-1.0..=1.0).contains(&value));
+            // A frame apart, values stay close
+            assert!((value - previous).abs() < 0.05, "step {}", step);
+            (low, high) = (low.min(value), high.max(value));
+            previous = value;
+        }
+        assert!(high - low > 0.5, "{} to {}", low, high);
+
+        let same = NoiseField::new(11);
+        assert_eq!(
+            noise.sample_at(Parameter::Warmth, 0.3, 0.7, 12.5),
+            same.sample_at(Parameter::Warmth, 0.3, 0.7, 12.5)
+        );
+        let other = NoiseField::new(12);
+        assert_ne!(
+            noise.sample_at(Parameter::Warmth, 0.3, 0.7, 12.5),
+            other.sample_at(Parameter::Warmth, 0.3, 0.7, 12.5)
+        );
+    }
+
+    #[test]
+    fn test_parameters_have_their_own_streams() {
+        let noise = NoiseField::new(3);
+        let values: Vec<f64> = Parameter::ALL
+            .iter()
+            .map(|p| noise.sample_at(*p, 0.2, 0.4, 7.0))
+            .collect();
+        for (i, a) in values.iter().enumerate() {
+            assert!(values[i + 1..].iter().all(|b| a != b));
+        }
+        assert_eq!(Parameter::parse("tension"), Some(Parameter::Tension));
+        assert_eq!(Parameter::parse("sparkle"), None);
+
+        // Across the square, the texture varies too
+        let row: Vec<f64> = (0..20)
+            .map(|i| noise.sample_at(Parameter::Density, i as f64 / 20.0, 0.5, 7.0))
+            .collect();
+        let spread = row.iter().cloned().fold(f64::MIN, f64::max)
+            - row.iter().cloned().fold(f64::MAX, f64::min);
+        assert!(spread > 0.2, "{}", spread);
+    }
+}
@@ -0,0 +1,271 @@
+//! A soft pulse grid that emerges from rhythm and energy.
+//!
+//! Rhythm sets the tempo, 60 BPM at rest to 120 at full rhythm, and energy
+//! pushes it 5% either side. The tempo glides toward that target over a few
+//! seconds, so the grid breathes with the world rather than jumping. Energy
+//! also picks the meter: calm worlds sway in three, lively ones walk in
+//! four, switching only on a downbeat. The grid advances in simulated time,
+//! so it pauses and follows the time scale with the rest of the world.
+
+/// Tempo at rhythm 0 and 1, before energy's push, in beats per minute.
+const SLOW_BPM: f64 = 60.0;
+const FAST_BPM: f64 = 120.0;
+
+/// How far energy moves the tempo either side of rhythm's.
+const ENERGY_SWING: f64 = 0.05;
+
+/// Time constant of the tempo glide.
+const GLIDE_SECS: f64 = 4.0;
+
+/// Energy below which the meter falls to three and above which it rises to
+/// four; the gap keeps it from flapping.
+const TRIPLE_BELOW: f64 = 0.35;
+const QUADRUPLE_ABOVE: f64 = 0.45;
+
+/// Whether sparkles wait for the grid (`[world.pulse]`).
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PulseSettings {
+    /// Hold each sparkle until the next grid point.
+    pub quantize_sparkles: bool,
+    /// Grid points per beat for quantized sparkles: 1 for beats, 2 for
+    /// eighth notes and so on.
+    pub subdivision: u32,
+}
+
+impl Default for PulseSettings {
+    fn default() -> Self {
+        Self {
+            quantize_sparkles: false,
+            subdivision: 2,
+        }
+    }
+}
+
+/// Where the grid stands, as carried in a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Pulse {
+    pub bpm: f64,
+    pub beats_per_bar: u32,
+    /// Beats completed since the engine started.
+    pub beat: u64,
+    /// Progress through the current beat, 0 to 1.
+    pub phase: f64,
+}
+
+impl Default for Pulse {
+    fn default() -> Self {
+        Self {
+            bpm: target_bpm(0.5, 0.5),
+            beats_per_bar: 4,
+            beat: 0,
+            phase: 0.0,
+        }
+    }
+}
+
+/// A beat of the grid, for clients to move in time.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BeatEvent {
+    /// Tick during which it fell.
+    pub tick: u64,
+    /// Simulated time at the end of that tick.
+    pub sim_time_secs: f64,
+    /// Beats since the engine started; the first is beat 1.
+    pub beat: u64,
+    /// Bars before this one since the engine started.
+    pub bar: u64,
+    /// Position in the bar, 0 on the downbeat.
+    pub beat_in_bar: u32,
+    pub beats_per_bar: u32,
+    pub bpm: f64,
+    /// 1 on the downbeat, 0.75 on the half-bar of a bar in four, else 0.5.
+    pub accent: f64,
+}
+
+/// Tempo the grid glides toward for a world's rhythm and energy.
+pub fn target_bpm(rhythm: f64, energy: f64) -> f64 {
+    let bpm = SLOW_BPM + (FAST_BPM - SLOW_BPM) * rhythm.clamp(0.0, 1.0);
+    bpm * (1.0 - ENERGY_SWING + 2.0 * ENERGY_SWING * energy.clamp(0.0, 1.0))
+}
+
+/// What happened during one [`PulseGrid::advance`].
+#[derive(Debug)]
+pub struct PulseStep {
+    /// The beat crossed, if any.
+    pub beat: Option<BeatEvent>,
+    /// Whether a grid point of `subdivision` per beat was crossed.
+    pub grid_point: bool,
+}
+
+/// The running grid.
+#[derive(Debug, Clone)]
+pub struct PulseGrid {
+    bpm: f64,
+    beats_per_bar: u32,
+    /// Beats elapsed, whole and fractional.
+    position: f64,
+    /// Beat index of the current bar's downbeat.
+    bar_start: u64,
+    bar: u64,
+}
+
+impl Default for PulseGrid {
+    fn default() -> Self {
+        Self {
+            bpm: Pulse::default().bpm,
+            beats_per_bar: 4,
+            position: 0.0,
+            bar_start: 0,
+            bar: 0,
+        }
+    }
+}
+
+impl PulseGrid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A grid standing where `pulse` says, its bars counted from the start
+    /// as if the meter had never changed.
+    pub fn from_pulse(pulse: Pulse) -> Self {
+        let beats_per_bar = pulse.beats_per_bar.max(1);
+        Self {
+            bpm: pulse.bpm,
+            beats_per_bar,
+            position: pulse.beat as f64 + pulse.phase,
+            bar_start: pulse.beat - pulse.beat % beats_per_bar as u64,
+            bar: pulse.beat / beats_per_bar as u64,
+        }
+    }
+
+    pub fn pulse(&self) -> Pulse {
+        Pulse {
+            bpm: self.bpm,
+            beats_per_bar: self.beats_per_bar,
+            beat: self.position.floor() as u64,
+            phase: self.position.fract(),
+        }
+    }
+
+    /// Moves the grid on by `dt` seconds of a world at `rhythm` and
+    /// `energy`. A step longer than a beat reports only the last beat it
+    /// crossed. The beat's tick and time are left for the engine to fill in.
+    pub fn advance(&mut self, dt: f64, rhythm: f64, energy: f64, subdivision: u32) -> PulseStep {
+        self.bpm += (target_bpm(rhythm, energy) - self.bpm) * (1.0 - (-dt / GLIDE_SECS).exp());
+        let before = self.position;
+        self.position += dt * self.bpm / 60.0;
+
+        let subdivision = subdivision.max(1) as f64;
+        let grid_point = (self.position * subdivision).floor() > (before * subdivision).floor();
+        let beat = self.position.floor() as u64;
+        if beat == before.floor() as u64 {
+            return PulseStep {
+                beat: None,
+                grid_point,
+            };
+        }
+
+        if beat - self.bar_start >= self.beats_per_bar as u64 {
+            self.bar_start = beat;
+            self.bar += 1;
+            // The meter only changes on a downbeat
+            if energy < TRIPLE_BELOW {
+                self.beats_per_bar = 3;
+            } else if energy > QUADRUPLE_ABOVE {
+                self.beats_per_bar = 4;
+            }
+        }
+        let beat_in_bar = (beat - self.bar_start) as u32;
+        let accent = if beat_in_bar == 0 {
+            1.0
+        } else if self.beats_per_bar == 4 && beat_in_bar == 2 {
+            0.75
+        } else {
+            0.5
+        };
+        PulseStep {
+            beat: Some(BeatEvent {
+                tick: 0,
+                sim_time_secs: 0.0,
+                beat,
+                bar: self.bar,
+                beat_in_bar,
+                beats_per_bar: self.beats_per_bar,
+                bpm: self.bpm,
+                accent,
+            }),
+            grid_point,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f64 = 1.0 / 60.0;
+
+    /// Beats over `secs` at fixed rhythm and energy.
+    fn run(grid: &mut PulseGrid, secs: f64, rhythm: f64, energy: f64) -> Vec<BeatEvent> {
+        (0..(secs / DT) as usize)
+            .filter_map(|_| grid.advance(DT, rhythm, energy, 2).beat)
+            .collect()
+    }
+
+    #[test]
+    fn test_tempo_follows_rhythm_and_energy() {
+        assert_eq!(target_bpm(0.0, 0.5), 60.0);
+        assert_eq!(target_bpm(1.0, 0.5), 120.0);
+        assert!(target_bpm(0.5, 1.0) > target_bpm(0.5, 0.0));
+
+        // Settled at 120 BPM, a minute holds 120 beats
+        let mut grid = PulseGrid::new();
+        run(&mut grid, 60.0, 1.0, 0.5);
+        assert!((grid.pulse().bpm - 120.0).abs() < 0.1);
+        let beats = run(&mut grid, 60.0, 1.0, 0.5);
+        assert!((119..=121).contains(&beats.len()), "{} beats", beats.len());
+        assert!(beats.windows(2).all(|w| w[1].beat == w[0].beat + 1));
+    }
+
+    #[test]
+    fn test_bars_accent_downbeats_and_meter_follows_energy() {
+        let mut grid = PulseGrid::new();
+        let lively = run(&mut grid, 30.0, 0.5, 0.8);
+        assert!(lively.iter().all(|b| b.beats_per_bar == 4));
+        for beat in &lively {
+            let expected = match beat.beat_in_bar {
+                0 => 1.0,
+                2 => 0.75,
+                _ => 0.5,
+            };
+            assert_eq!(beat.accent, expected);
+        }
+
+        let calm = run(&mut grid, 30.0, 0.5, 0.1);
+        let first_triple = calm.iter().position(|b| b.beats_per_bar == 3).unwrap();
+        // The switch waits for a downbeat, then every bar is in three
+        assert_eq!(calm[first_triple].beat_in_bar, 0);
+        assert!(calm[first_triple..].iter().all(|b| b.beat_in_bar < 3));
+        let bars: Vec<u64> = calm[first_triple..]
+            .iter()
+            .filter(|b| b.beat_in_bar == 0)
+            .map(|b| b.bar)
+            .collect();
+        assert!(bars.windows(2).all(|w| w[1] == w[0] + 1));
+    }
+
+    #[test]
+    fn test_grid_points_subdivide_beats() {
+        let mut grid = PulseGrid::new();
+        let (mut points, mut beats) = (0, 0);
+        for _ in 0..(60.0 / DT) as usize {
+            let step = grid.advance(DT, 0.5, 0.5, 2);
+            points += step.grid_point as u32;
+            beats += step.beat.is_some() as u32;
+        }
+        assert!(points.abs_diff(2 * beats) <= 1);
+    }
+}
@@ -0,0 +1,128 @@
+//! Scene definitions: named sets of targets the world decays toward.
+
+use crate::curves::Curve;
+use std::collections::HashMap;
+
+/// Seconds the audio crossfades over when a scene does not specify its own.
+pub const DEFAULT_TRANSITION_SECS: f64 = 4.0;
+
+/// Longest accepted scene transition.
+pub const MAX_TRANSITION_SECS: f64 = 120.0;
+
+/// Target values a scene sets for the world parameters.
+///
+/// Missing fields default to the neutral 0.5 (and the default transition
+/// time) when deserialized.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SceneTargets {
+    pub density: f64,
+    pub rhythm: f64,
+    pub tension: f64,
+    pub energy: f64,
+    pub warmth: f64,
+    /// From intimate (0) to cavernous (1).
+    pub space: f64,
+    /// From gritty (0) to pure (1).
+    pub clarity: f64,
+    /// Seconds the audio crossfades from the outgoing to the incoming scene.
+    pub transition_secs: f64,
+    /// Shape of that crossfade.
+    pub transition_curve: Curve,
+}
+
+impl Default for SceneTargets {
+    fn default() -> Self {
+        Self {
+            density: 0.5,
+            rhythm: 0.5,
+            tension: 0.5,
+            energy: 0.5,
+            warmth: 0.5,
+            space: 0.5,
+            clarity: 0.5,
+            transition_secs: DEFAULT_TRANSITION_SECS,
+            transition_curve: Curve::SmoothStep,
+        }
+    }
+}
+
+impl SceneTargets {
+    /// Returns true if every target lies within the world bounds and the
+    /// transition time and curve are valid.
+    pub fn is_valid(&self) -> bool {
+        (0.0..=MAX_TRANSITION_SECS).contains(&self.transition_secs)
+            && self.transition_curve.validate().is_ok()
+            && [
+                self.density,
+                self.rhythm,
+                self.tension,
+                self.energy,
+                self.warmth,
+                self.space,
+                self.clarity,
+            ]
+            .iter()
+            .all(|v| (0.0..=1.0).contains(v))
+    }
+}
+
+/// The most recent scene change, as reported in world snapshots.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SceneChange {
+    pub name: String,
+    pub transition_secs: f64,
+    /// Absent from V1 snapshots, which predate curves.
+    #[serde(default)]
+    pub transition_curve: Curve,
+    /// Increments on every scene change, including re-applying the same scene.
+    pub sequence: u64,
+}
+
+/// Returns the scenes that ship with the engine.
+pub fn builtin_scenes() -> HashMap<String, SceneTargets> {
+    HashMap::from([
+        (
+            "peaceful".to_string(),
+            SceneTargets {
+                density: 0.3,
+                rhythm: 0.4,
+                tension: 0.2,
+                energy: 0.3,
+                warmth: 0.8,
+                space: 0.6,
+                clarity: 0.7,
+                transition_secs: 6.0,
+                transition_curve: Curve::SmoothStep,
+            },
+        ),
+        (
+            "energetic".to_string(),
+            SceneTargets {
+                density: 0.7,
+                rhythm: 0.9,
+                tension: 0.6,
+                energy: 0.9,
+                warmth: 0.6,
+                space: 0.3,
+                clarity: 0.4,
+                transition_secs: 2.0,
+                transition_curve: Curve::SmoothStep,
+            },
+        ),
+        (
+            "mysterious".to_string(),
+            SceneTargets {
+                density: 0.2,
+                rhythm: 0.3,
+                tension: 0.8,
+                energy: 0.4,
+                warmth: 0.2,
+                space: 0.9,
+                clarity: 0.3,
+                transition_secs: 8.0,
+                transition_curve: Curve::SmoothStep,
+            },
+        ),
+    ])
+}
@@ -0,0 +1,367 @@
+//! Seasons: a slow bias on the world that follows the calendar.
+//!
+//! An installation running all year can sound different in winter than in
+//! summer. Each season is a [`SeasonProfile`] pinned to a day of the year;
+//! the [`Seasons`] modulator holds one profile until `fade_days` before the
+//! next season's day, then crossfades into it. A profile caps warmth,
+//! shifts where density, energy, warmth, space and clarity settle, and
+//! scales the background sparkle rate. The shifts are a steady pull against
+//! the decay toward the targets, so they ride on top of scenes, presets and
+//! the composer instead of overwriting their targets.
+//!
+//! The position in the year is a [`SeasonClock`] that the modulator only
+//! reads. Seasons follow the calendar, not the world's simulated time, so
+//! whoever owns the wall clock sets it: pausing, stepping or fast-forwarding
+//! the world leaves the season where the date puts it.
+
+use crate::curves::Curve;
+use crate::modulator::Modulator;
+use crate::world::WorldState;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Days in the seasonal year. Leap days share February 28th's place.
+pub const YEAR_DAYS: f64 = 365.0;
+
+/// Days in each month of the seasonal year.
+const MONTH_DAYS: [u32; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+/// The day of the seasonal year a date falls on, from 0 for January 1st,
+/// or `None` if there is no such date. February 29th counts as the 28th.
+pub fn day_of_year(month: u32, day: u32) -> Option<f64> {
+    let index = month.checked_sub(1)? as usize;
+    let days = *MONTH_DAYS.get(index)?;
+    if day == 0 || day > days + u32::from(month == 2) {
+        return None;
+    }
+    let before: u32 = MONTH_DAYS[..index].iter().sum();
+    Some(f64::from(before + day.min(days) - 1))
+}
+
+/// How a season bends the world (`[seasons.profiles.<name>]`).
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeasonProfile {
+    /// Highest warmth allowed, 0 to 1.
+    pub warmth_ceiling: f64,
+    /// Shifts of where each parameter settles, -1 to 1.
+    pub density: f64,
+    pub energy: f64,
+    pub warmth: f64,
+    pub space: f64,
+    pub clarity: f64,
+    /// Multiplier on the background sparkle rate.
+    pub sparkle_rate: f64,
+}
+
+impl Default for SeasonProfile {
+    fn default() -> Self {
+        Self {
+            warmth_ceiling: 1.0,
+            density: 0.0,
+            energy: 0.0,
+            warmth: 0.0,
+            space: 0.0,
+            clarity: 0.0,
+            sparkle_rate: 1.0,
+        }
+    }
+}
+
+impl SeasonProfile {
+    /// Built-in profiles by name: winter, spring, summer and autumn.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let neutral = Self::default();
+        let profile = match name {
+            // Cold, clear and sparse
+            "winter" => Self {
+                warmth_ceiling: 0.55,
+                density: -0.1,
+                energy: -0.1,
+                space: 0.15,
+                clarity: 0.15,
+                sparkle_rate: 0.5,
+                ..neutral
+            },
+            "spring" => Self {
+                warmth_ceiling: 0.85,
+                density: 0.05,
+                energy: 0.05,
+                clarity: 0.05,
+                sparkle_rate: 1.3,
+                ..neutral
+            },
+            // Warm and full
+            "summer" => Self {
+                density: 0.1,
+                energy: 0.1,
+                warmth: 0.1,
+                space: -0.05,
+                sparkle_rate: 1.2,
+                ..neutral
+            },
+            "autumn" => Self {
+                warmth_ceiling: 0.8,
+                energy: -0.05,
+                warmth: 0.05,
+                space: 0.05,
+                clarity: -0.1,
+                sparkle_rate: 0.8,
+                ..neutral
+            },
+            _ => return None,
+        };
+        Some(profile)
+    }
+
+    /// Blends toward `next` by `alpha` (0 = `self`, 1 = `next`).
+    pub fn lerp(&self, next: &SeasonProfile, alpha: f64) -> SeasonProfile {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let lerp = |a: f64, b: f64| a + (b - a) * alpha;
+        SeasonProfile {
+            warmth_ceiling: lerp(self.warmth_ceiling, next.warmth_ceiling),
+            density: lerp(self.density, next.density),
+            energy: lerp(self.energy, next.energy),
+            warmth: lerp(self.warmth, next.warmth),
+            space: lerp(self.space, next.space),
+            clarity: lerp(self.clarity, next.clarity),
+            sparkle_rate: lerp(self.sparkle_rate, next.sparkle_rate),
+        }
+    }
+}
+
+/// A season and the day of the year it is fully in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Season {
+    pub name: String,
+    pub day: f64,
+    pub profile: SeasonProfile,
+}
+
+/// Day of the seasonal year, 0 to [`YEAR_DAYS`], shared between the
+/// [`Seasons`] modulator that reads it and whatever keeps it on the date.
+#[derive(Debug, Clone, Default)]
+pub struct SeasonClock(Arc<AtomicU64>);
+
+impl SeasonClock {
+    pub fn new(day: f64) -> Self {
+        let clock = Self::default();
+        clock.set(day);
+        clock
+    }
+
+    pub fn day(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Sets the day, wrapped into the year.
+    pub fn set(&self, day: f64) {
+        let day = day.rem_euclid(YEAR_DAYS);
+        self.0.store(day.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Crossfades through a calendar of seasons as the clock moves.
+pub struct Seasons {
+    /// Sorted by day; never empty.
+    calendar: Vec<Season>,
+    fade_days: f64,
+    clock: SeasonClock,
+    /// Index of the season holding the larger share.
+    current: usize,
+}
+
+impl Seasons {
+    /// Runs `calendar` from `clock`. Panics if the calendar is empty.
+    pub fn new(mut calendar: Vec<Season>, fade_days: f64, clock: SeasonClock) -> Self {
+        assert!(!calendar.is_empty(), "a calendar needs a season");
+        calendar.sort_by(|a, b| a.day.total_cmp(&b.day));
+        let mut seasons = Self {
+            calendar,
+            fade_days: fade_days.max(0.0),
+            clock,
+            current: 0,
+        };
+        seasons.current = seasons.position().dominant();
+        seasons
+    }
+
+    /// The season holding the larger share right now.
+    pub fn season(&self) -> &str {
+        &self.calendar[self.current].name
+    }
+
+    /// The blended profile for the clock's day.
+    pub fn profile(&self) -> SeasonProfile {
+        let Position { from, to, alpha } = self.position();
+        self.calendar[from]
+            .profile
+            .lerp(&self.calendar[to].profile, alpha)
+    }
+
+    /// The seasons either side of the clock's day and how far the fade
+    /// between them has gone.
+    fn position(&self) -> Position {
+        let day = self.clock.day();
+        let count = self.calendar.len();
+        let from = self
+            .calendar
+            .iter()
+            .rposition(|season| season.day <= day)
+            .unwrap_or(count - 1);
+        let to = (from + 1) % count;
+        if to == from {
+            return Position {
+                from,
+                to,
+                alpha: 0.0,
+            };
+        }
+        let gap = (self.calendar[to].day - self.calendar[from].day).rem_euclid(YEAR_DAYS);
+        let since = (day - self.calendar[from].day).rem_euclid(YEAR_DAYS);
+        let fade = self.fade_days.min(gap);
+        let alpha = if fade > 0.0 {
+            Curve::SmoothStep.apply(((since - (gap - fade)) / fade).clamp(0.0, 1.0))
+        } else {
+            0.0
+        };
+        Position { from, to, alpha }
+    }
+}
+
+struct Position {
+    from: usize,
+    to: usize,
+    alpha: f64,
+}
+
+impl Position {
+    fn dominant(&self) -> usize {
+        if self.alpha < 0.5 { self.from } else { self.to }
+    }
+}
+
+impl Modulator for Seasons {
+    fn name(&self) -> &str {
+        "seasons"
+    }
+
+    fn modulate(&mut self, dt: f64, state: &mut WorldState) {
+        let current = self.position().dominant();
+        if current != self.current {
+            self.current = current;
+            tracing::info!("Seasons: {} takes over", self.season());
+        }
+
+        // A pull of 2 * decay * shift per second balances the decay toward
+        // the target `shift` away from it, so the parameter settles there
+        let profile = self.profile();
+        let dynamics = state.dynamics();
+        let pull = |shift: f64, decay: f64| 2.0 * decay * shift * dt;
+        let shared = dynamics.decay_factor;
+        state.set_density(state.density() + pull(profile.density, shared));
+        state.set_energy(state.energy() + pull(profile.energy, shared));
+        state.set_space(state.space() + pull(profile.space, shared));
+        state.set_clarity(state.clarity() + pull(profile.clarity, dynamics.clarity.decay_factor));
+        let warmth = state.warmth() + pull(profile.warmth, shared);
+        state.set_warmth(warmth.min(profile.warmth_ceiling));
+        state.set_sparkle_rate(profile.sparkle_rate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    const DT: f64 = 1.0;
+
+    fn calendar() -> Vec<Season> {
+        ["summer", "winter"]
+            .into_iter()
+            .zip([180.0, 0.0])
+            .map(|(name, day)| Season {
+                name: name.to_string(),
+                day,
+                profile: SeasonProfile::builtin(name).unwrap(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_day_of_year() {
+        assert_eq!(day_of_year(1, 1), Some(0.0));
+        assert_eq!(day_of_year(3, 1), Some(59.0));
+        assert_eq!(day_of_year(2, 29), day_of_year(2, 28));
+        assert_eq!(day_of_year(12, 31), Some(YEAR_DAYS - 1.0));
+        assert_eq!(day_of_year(4, 31), None);
+        assert_eq!(day_of_year(13, 1), None);
+        assert_eq!(day_of_year(0, 1), None);
+    }
+
+    #[test]
+    fn test_seasons_hold_then_crossfade() {
+        let winter = SeasonProfile::builtin("winter").unwrap();
+        let summer = SeasonProfile::builtin("summer").unwrap();
+        let clock = SeasonClock::new(100.0);
+        let seasons = Seasons::new(calendar(), 30.0, clock.clone());
+        assert_eq!(seasons.season(), "winter");
+        assert_eq!(seasons.profile(), winter);
+
+        clock.set(165.0);
+        let halfway = seasons.profile();
+        assert!((halfway.sparkle_rate - 0.85).abs() < 1e-9);
+        clock.set(180.0);
+        assert_eq!(seasons.profile(), summer);
+
+        // Winter returns across the new year
+        clock.set(364.0);
+        assert!(seasons.profile().sparkle_rate < 1.0);
+        clock.set(10.0);
+        assert_eq!(seasons.profile(), winter);
+    }
+
+    #[test]
+    fn test_winter_caps_warmth_and_thins_sparkles() {
+        let clock = SeasonClock::new(1.0);
+        let mut seasons = Seasons::new(calendar(), 0.0, clock.clone());
+        let mut state = WorldState::new();
+        state.set_target_warmth(0.9);
+        state.set_warmth(0.9);
+        for _ in 0..1_000 {
+            seasons.modulate(DT, &mut state);
+        }
+        // Simulated time leaves the day alone
+        assert_eq!(clock.day(), 1.0);
+        assert_eq!(seasons.season(), "winter");
+        assert_eq!(state.warmth(), 0.55);
+        assert_eq!(state.preset().target_warmth, 0.9);
+        assert_eq!(state.sparkle_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_shifts_move_where_the_world_settles() {
+        let profile = SeasonProfile {
+            density: 0.2,
+            ..SeasonProfile::default()
+        };
+        let calendar = vec![Season {
+            name: "dense".to_string(),
+            day: 0.0,
+            profile,
+        }];
+        let mut seasons = Seasons::new(calendar, 30.0, SeasonClock::default());
+        let mut state = WorldState::new();
+        let mut dynamics = state.dynamics();
+        dynamics.drift_factor = 0.0;
+        state.set_dynamics(dynamics);
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..10_000 {
+            state.drift(0.1, &mut rng);
+            seasons.modulate(0.1, &mut state);
+        }
+        assert!((state.density() - 0.7).abs() < 1e-3, "{}", state.density());
+        assert_eq!(state.preset().target_density, 0.5);
+    }
+}
@@ -0,0 +1,211 @@
+//! When sparkles fire.
+//!
+//! Sparkles come from a Poisson process whose background rate follows
+//! density. Each sparkle briefly raises the hazard of another (a self-exciting,
+//! or Hawkes, process), so they arrive in showers rather than as independent
+//! pops, and a short refractory period after each keeps a shower from
+//! collapsing into a buzz. Rhythm sets the tempo of a shower: faster rhythm
+//! makes the boost stronger and shorter-lived and the refractory period
+//! shorter, leaving the average shower size unchanged.
+
+use rand::Rng;
+
+/// Shape of sparkle timing. Each sparkle goes on to cause up to
+/// `burst_hz * burst_secs` more on average (fewer the longer the refractory
+/// period), so that product must stay below 1 for showers to die out; with
+/// `burst_hz` and `refractory_secs` at 0, sparkles are a plain Poisson
+/// process.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SparkleModel {
+    /// Background sparkles per second at density 0.5: a third of that at
+    /// density 0 and five thirds at density 1.
+    pub rate_hz: f64,
+    /// Hazard each sparkle adds, in sparkles per second, at rhythm 0.5.
+    pub burst_hz: f64,
+    /// Time constant of the added hazard's decay, at rhythm 0.5.
+    pub burst_secs: f64,
+    /// Time after a sparkle during which no other fires, at rhythm 0.5.
+    pub refractory_secs: f64,
+}
+
+impl Default for SparkleModel {
+    fn default() -> Self {
+        Self {
+            rate_hz: 0.2,
+            burst_hz: 4.0,
+            burst_secs: 0.15,
+            refractory_secs: 0.05,
+        }
+    }
+}
+
+impl SparkleModel {
+    /// Sparkles each one goes on to cause on average, ignoring the refractory
+    /// period.
+    pub fn branching_ratio(&self) -> f64 {
+        self.burst_hz * self.burst_secs
+    }
+}
+
+/// Scale for a density or rhythm value: 1/3 at 0, 1 at 0.5, 5/3 at 1.
+fn factor(value: f64) -> f64 {
+    (0.5 + 2.0 * value) / 1.5
+}
+
+/// A running [`SparkleModel`]: the boost left by recent sparkles and the
+/// refractory time remaining.
+#[derive(Debug, Clone)]
+pub struct SparkleProcess {
+    model: SparkleModel,
+    /// Multiplier on `model.rate_hz`.
+    rate_scale: f64,
+    /// Hazard above the background rate, in sparkles per second.
+    excitation: f64,
+    /// Seconds until another sparkle may fire.
+    refractory: f64,
+}
+
+impl Default for SparkleProcess {
+    fn default() -> Self {
+        Self::new(SparkleModel::default())
+    }
+}
+
+impl SparkleProcess {
+    pub fn new(model: SparkleModel) -> Self {
+        Self {
+            model,
+            rate_scale: 1.0,
+            excitation: 0.0,
+            refractory: 0.0,
+        }
+    }
+
+    /// Swaps the model, keeping a shower in progress going.
+    pub fn set_model(&mut self, model: SparkleModel) {
+        self.model = model;
+    }
+
+    /// Scales the background rate by `scale`, leaving showers as they are.
+    pub fn set_rate_scale(&mut self, scale: f64) {
+        self.rate_scale = scale.max(0.0);
+    }
+
+    /// Current hazard in sparkles per second, 0 while refractory.
+    pub fn hazard(&self, density: f64) -> f64 {
+        if self.refractory > 0.0 {
+            return 0.0;
+        }
+        self.model.rate_hz * self.rate_scale * factor(density) + self.excitation
+    }
+
+    /// Advances `dt` seconds and returns whether a sparkle fired. Draws one
+    /// number from `rng` per call.
+    pub fn step(&mut self, dt: f64, density: f64, rhythm: f64, rng: &mut impl Rng) -> bool {
+        let tempo = factor(rhythm);
+        let hazard = self.hazard(density);
+        let draw = rng.random::<f64>();
+        self.refractory = (self.refractory - dt).max(0.0);
+        if self.model.burst_secs > 0.0 {
+            self.excitation *= (-dt * tempo / self.model.burst_secs).exp();
+        } else {
+            self.excitation = 0.0;
+        }
+
+        // Chance of at least one arrival in dt; at most one fires per step
+        let fired = draw < 1.0 - (-hazard * dt).exp();
+        if fired {
+            self.excitation += self.model.burst_hz * tempo;
+            self.refractory = self.model.refractory_secs / tempo;
+        }
+        fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    const DT: f64 = 1.0 / 60.0;
+
+    /// Long enough for rates to settle within a few percent.
+    const SECS: f64 = 10_000.0;
+
+    /// Times of the sparkles fired over `secs` at fixed density and rhythm.
+    fn run(model: SparkleModel, secs: f64, density: f64, rhythm: f64) -> Vec<f64> {
+        let mut process = SparkleProcess::new(model);
+        let mut rng = StdRng::seed_from_u64(11);
+        (0..(secs / DT) as usize)
+            .filter(|_| process.step(DT, density, rhythm, &mut rng))
+            .map(|step| step as f64 * DT)
+            .collect()
+    }
+
+    /// Coefficient of variation of the gaps between sparkles: about 1 for a
+    /// Poisson process, above 1 when they cluster.
+    fn gap_cv(times: &[f64]) -> f64 {
+        let gaps: Vec<f64> = times.windows(2).map(|w| w[1] - w[0]).collect();
+        let mean = gaps.iter().sum::<f64>() / gaps.len() as f64;
+        let var = gaps.iter().map(|g| (g - mean).powi(2)).sum::<f64>() / gaps.len() as f64;
+        var.sqrt() / mean
+    }
+
+    #[test]
+    fn test_plain_poisson_rate_follows_density() {
+        let plain = SparkleModel {
+            rate_hz: 0.5,
+            burst_hz: 0.0,
+            refractory_secs: 0.0,
+            ..SparkleModel::default()
+        };
+        let mid = run(plain, SECS, 0.5, 0.5);
+        let rate = mid.len() as f64 / SECS;
+        assert!((rate - 0.5).abs() < 0.03, "rate {}", rate);
+        assert!((gap_cv(&mid) - 1.0).abs() < 0.1);
+
+        let dense = run(plain, SECS, 1.0, 0.5).len() as f64 / SECS;
+        assert!((dense - 0.5 * 5.0 / 3.0).abs() < 0.05, "rate {}", dense);
+
+        let mut thinned = SparkleProcess::new(plain);
+        thinned.set_rate_scale(0.5);
+        assert!((thinned.hazard(0.5) - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_bursts_cluster_and_respect_the_refractory_period() {
+        let model = SparkleModel::default();
+        let times = run(model, SECS, 0.5, 0.5);
+        assert!(gap_cv(&times) > 1.3, "cv {}", gap_cv(&times));
+        let shortest = times
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .fold(f64::INFINITY, f64::min);
+        assert!(shortest >= model.refractory_secs - 1e-9, "gap {}", shortest);
+
+        // Showers add to the background, though the refractory period keeps
+        // them below the 1 / (1 - n) sparkles per background one of a free
+        // Hawkes process
+        let rate = times.len() as f64 / SECS;
+        let unbounded = model.rate_hz / (1.0 - model.branching_ratio());
+        assert!(
+            rate > 1.3 * model.rate_hz && rate < unbounded,
+            "rate {}",
+            rate
+        );
+    }
+
+    #[test]
+    fn test_fast_rhythm_tightens_showers() {
+        let model = SparkleModel::default();
+        let median_gap = |rhythm: f64| {
+            let times = run(model, SECS, 0.5, rhythm);
+            let mut gaps: Vec<f64> = times.windows(2).map(|w| w[1] - w[0]).collect();
+            gaps.sort_by(f64::total_cmp);
+            gaps[gaps.len() / 2]
+        };
+        assert!(median_gap(1.0) < median_gap(0.0));
+    }
+}
@@ -1,37 +1,322 @@
 //! Core logic for the world state.
 
+use crate::agents::{Agent, AgentSettings};
+use crate::field::{Field, FieldSettings};
+use crate::harmony::{Harmony, Progression};
+use crate::invariants::{InvariantError, check_unit};
+use crate::mood::{self, Mood};
+use crate::pulse::{Pulse, PulseSettings};
+use crate::scene::{SceneChange, SceneTargets};
+use crate::sparkles::SparkleModel;
 use rand::{Rng, seq::IndexedRandom};
 
 const DRIFT_FACTOR: f64 = 0.2;
 const DECAY_FACTOR: f64 = 0.1;
+const CLARITY_DRIFT_FACTOR: f64 = 0.05;
+const CLARITY_DECAY_FACTOR: f64 = 0.05;
+
+/// Whether the simulation advances on ticks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum RunState {
+    #[default]
+    Running,
+    /// Ticks are ignored; the world only changes through events and explicit steps.
+    Paused,
+}
+
+/// Tunable rates for how the world evolves between events.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct WorldDynamics {
+    /// Random walk step per second applied to every parameter.
+    pub drift_factor: f64,
+    /// Pull per second back toward the current targets.
+    pub decay_factor: f64,
+    /// Drift and decay of space alone. The shared rates by default; slower
+    /// ones keep the room steady while the sound within it moves.
+    pub space: DriftRates,
+    /// Drift and decay of clarity alone, which by default wanders more
+    /// slowly than the rest so the grain of the sound changes over minutes.
+    pub clarity: DriftRates,
+    /// How sparkles are timed.
+    pub sparkles: SparkleModel,
+    /// Whether sparkles wait for the pulse grid.
+    pub pulse: PulseSettings,
+    /// Whether agents carry density and energy.
+    pub agents: AgentSettings,
+    /// Whether density, energy and warmth are also grids.
+    pub field: FieldSettings,
+}
+
+impl Default for WorldDynamics {
+    fn default() -> Self {
+        Self {
+            drift_factor: DRIFT_FACTOR,
+            decay_factor: DECAY_FACTOR,
+            space: DriftRates {
+                drift_factor: DRIFT_FACTOR,
+                decay_factor: DECAY_FACTOR,
+            },
+            clarity: DriftRates {
+                drift_factor: CLARITY_DRIFT_FACTOR,
+                decay_factor: CLARITY_DECAY_FACTOR,
+            },
+            sparkles: SparkleModel::default(),
+            pulse: PulseSettings::default(),
+            agents: AgentSettings::default(),
+            field: FieldSettings::default(),
+        }
+    }
+}
+
+/// Random walk step and pull toward the target, per second, for one
+/// parameter.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DriftRates {
+    pub drift_factor: f64,
+    pub decay_factor: f64,
+}
 
 /// Defines the current world state.
 ///
 /// The world state is used to affect audio and visuals.
+#[derive(Clone)]
 pub struct WorldState {
     density: f64,
     rhythm: f64,
     tension: f64,
     energy: f64,
     warmth: f64,
+    space: f64,
+    clarity: f64,
     sparkle_impulse: f64,
+    /// Multiplier on the background sparkle rate, set by modulators that
+    /// thin or thicken sparkles; 1 keeps the configured rate.
+    sparkle_rate: f64,
     // Target values that parameters decay toward
     target_density: f64,
     target_rhythm: f64,
     target_tension: f64,
     target_energy: f64,
     target_warmth: f64,
+    target_space: f64,
+    target_clarity: f64,
+    dynamics: WorldDynamics,
+    progression: Progression,
+}
+
+/// The seven world parameters and the targets they decay toward, captured
+/// from a running world so it can be returned to later.
+///
+/// Presets saved before space or clarity existed load with them and their
+/// targets at the neutral 0.5.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WorldPreset {
+    pub density: f64,
+    pub rhythm: f64,
+    pub tension: f64,
+    pub energy: f64,
+    pub warmth: f64,
+    #[serde(default = "neutral")]
+    pub space: f64,
+    #[serde(default = "neutral")]
+    pub clarity: f64,
+    pub target_density: f64,
+    pub target_rhythm: f64,
+    pub target_tension: f64,
+    pub target_energy: f64,
+    pub target_warmth: f64,
+    #[serde(default = "neutral")]
+    pub target_space: f64,
+    #[serde(default = "neutral")]
+    pub target_clarity: f64,
+}
+
+/// Where a parameter missing from older data starts.
+fn neutral() -> f64 {
+    0.5
+}
+
+impl WorldPreset {
+    /// Blends the parameters toward `next` by `alpha` (0 = `self`, 1 = `next`),
+    /// taking the targets from `next`.
+    pub fn lerp(&self, next: &WorldPreset, alpha: f64) -> WorldPreset {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let lerp = |a: f64, b: f64| a + (b - a) * alpha;
+        WorldPreset {
+            density: lerp(self.density, next.density),
+            rhythm: lerp(self.rhythm, next.rhythm),
+            tension: lerp(self.tension, next.tension),
+            energy: lerp(self.energy, next.energy),
+            warmth: lerp(self.warmth, next.warmth),
+            space: lerp(self.space, next.space),
+            clarity: lerp(self.clarity, next.clarity),
+            ..*next
+        }
+    }
+
+    /// Moves every parameter and target `fraction` of the way toward
+    /// `other` (0 = `self`, 1 = `other`).
+    pub fn blend(&self, other: &WorldPreset, fraction: f64) -> WorldPreset {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let blend = |a: f64, b: f64| a + (b - a) * fraction;
+        WorldPreset {
+            target_density: blend(self.target_density, other.target_density),
+            target_rhythm: blend(self.target_rhythm, other.target_rhythm),
+            target_tension: blend(self.target_tension, other.target_tension),
+            target_energy: blend(self.target_energy, other.target_energy),
+            target_warmth: blend(self.target_warmth, other.target_warmth),
+            target_space: blend(self.target_space, other.target_space),
+            target_clarity: blend(self.target_clarity, other.target_clarity),
+            ..self.lerp(other, fraction)
+        }
+    }
+
+    /// The world a scene leads to: its targets, with the parameters
+    /// settled on them.
+    pub fn from_targets(targets: &SceneTargets) -> WorldPreset {
+        WorldPreset {
+            density: targets.density,
+            rhythm: targets.rhythm,
+            tension: targets.tension,
+            energy: targets.energy,
+            warmth: targets.warmth,
+            space: targets.space,
+            clarity: targets.clarity,
+            target_density: targets.density,
+            target_rhythm: targets.rhythm,
+            target_tension: targets.tension,
+            target_energy: targets.energy,
+            target_warmth: targets.warmth,
+            target_space: targets.space,
+            target_clarity: targets.clarity,
+        }
+    }
+
+    /// Every parameter and target by name, parameters first.
+    pub fn fields(&self) -> [(&'static str, f64); 14] {
+        [
+            ("density", self.density),
+            ("rhythm", self.rhythm),
+            ("tension", self.tension),
+            ("energy", self.energy),
+            ("warmth", self.warmth),
+            ("space", self.space),
+            ("clarity", self.clarity),
+            ("target_density", self.target_density),
+            ("target_rhythm", self.target_rhythm),
+            ("target_tension", self.target_tension),
+            ("target_energy", self.target_energy),
+            ("target_warmth", self.target_warmth),
+            ("target_space", self.target_space),
+            ("target_clarity", self.target_clarity),
+        ]
+    }
+
+    /// Takes back the change from `before` to `after`, keeping whatever else
+    /// has moved since.
+    pub fn reverted(&self, before: &WorldPreset, after: &WorldPreset) -> WorldPreset {
+        let revert = |now: f64, before: f64, after: f64| (now + before - after).clamp(0.0, 1.0);
+        WorldPreset {
+            density: revert(self.density, before.density, after.density),
+            rhythm: revert(self.rhythm, before.rhythm, after.rhythm),
+            tension: revert(self.tension, before.tension, after.tension),
+            energy: revert(self.energy, before.energy, after.energy),
+            warmth: revert(self.warmth, before.warmth, after.warmth),
+            space: revert(self.space, before.space, after.space),
+            clarity: revert(self.clarity, before.clarity, after.clarity),
+            target_density: revert(
+                self.target_density,
+                before.target_density,
+                after.target_density,
+            ),
+            target_rhythm: revert(
+                self.target_rhythm,
+                before.target_rhythm,
+                after.target_rhythm,
+            ),
+            target_tension: revert(
+                self.target_tension,
+                before.target_tension,
+                after.target_tension,
+            ),
+            target_energy: revert(
+                self.target_energy,
+                before.target_energy,
+                after.target_energy,
+            ),
+            target_warmth: revert(
+                self.target_warmth,
+                before.target_warmth,
+                after.target_warmth,
+            ),
+            target_space: revert(self.target_space, before.target_space, after.target_space),
+            target_clarity: revert(
+                self.target_clarity,
+                before.target_clarity,
+                after.target_clarity,
+            ),
+        }
+    }
 }
 
 /// World state to share outwardly at a point in time.
-#[derive(Clone, serde::Serialize)]
+///
+/// Deserializes from its own serialized form (the latest wire snapshot), and
+/// from older ones, whose missing fields take their defaults.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct WorldSnapshot {
+    /// Number of ticks the engine had processed when the snapshot was taken.
+    #[serde(default)]
+    tick: u64,
+    /// Wall-clock time (ms since the Unix epoch) the snapshot was published; 0 if unset.
+    #[serde(default)]
+    timestamp_ms: u64,
+    /// Simulated seconds elapsed: the sum of every tick's dt.
+    #[serde(default)]
+    sim_time_secs: f64,
+    #[serde(default)]
+    run_state: RunState,
+    /// Multiplier applied to tick dt.
+    #[serde(default = "default_time_scale")]
+    time_scale: f64,
     density: f64,
     rhythm: f64,
     tension: f64,
     energy: f64,
     warmth: f64,
+    /// Absent from schema 1.0 and from servers that predate space.
+    #[serde(default = "neutral")]
+    space: f64,
+    /// Absent from schema 1.0 and from servers that predate clarity.
+    #[serde(default = "neutral")]
+    clarity: f64,
     sparkle_impulse: f64,
+    /// Most recent scene change, if any scene has been applied.
+    scene: Option<SceneChange>,
+    /// The pulse grid.
+    #[serde(default)]
+    pulse: Pulse,
+    /// Key and chord.
+    #[serde(default)]
+    harmony: Harmony,
+    /// Qualitative reading of the parameters.
+    #[serde(default)]
+    mood: Mood,
+    /// The agents, when they carry density and energy.
+    #[serde(default)]
+    agents: Vec<Agent>,
+    /// The grids, when the field is on. Never serialized; clients that want
+    /// them subscribe to the binary field channel.
+    #[serde(skip)]
+    field: Option<Field>,
+}
+
+fn default_time_scale() -> f64 {
+    1.0
 }
 
 impl Default for WorldState {
@@ -42,12 +327,19 @@ impl Default for WorldState {
             tension: 0.5,
             energy: 0.5,
             warmth: 0.5,
+            space: 0.5,
+            clarity: 0.5,
             sparkle_impulse: 0.0,
+            sparkle_rate: 1.0,
             target_density: 0.5,
             target_rhythm: 0.5,
             target_tension: 0.5,
             target_energy: 0.5,
             target_warmth: 0.5,
+            target_space: 0.5,
+            target_clarity: 0.5,
+            dynamics: WorldDynamics::default(),
+            progression: Progression::default(),
         }
     }
 }
@@ -58,33 +350,127 @@ impl WorldState {
         Self::default()
     }
 
+    /// Rebuilds a world from a snapshot: the seven parameters, the sparkle
+    /// impulse and the harmony, its chord just struck. Snapshots carry
+    /// neither decay targets nor dynamics, so those take their defaults, as
+    /// in [`WorldState::new`].
+    pub fn from_snapshot(snapshot: &WorldSnapshot) -> Self {
+        let mut state = Self::new();
+        state.set_density(snapshot.density());
+        state.set_rhythm(snapshot.rhythm());
+        state.set_tension(snapshot.tension());
+        state.set_energy(snapshot.energy());
+        state.set_warmth(snapshot.warmth());
+        state.set_space(snapshot.space());
+        state.set_clarity(snapshot.clarity());
+        state.set_sparkle_impulse(snapshot.sparkle_impulse());
+        state.progression = Progression::new(snapshot.harmony());
+        state
+    }
+
     /// Introduces a random drift to the world state parameters.
     /// TODO: This already takes RNG as parameter - good for deterministic mode.
     /// TODO: Future: Add WorldState::new_deterministic(seed) for testing.
     pub fn drift(&mut self, df: f64, rng: &mut impl Rng) {
         let drift_dir = [-1., 1.];
-        let mut compute_drift = |current: f64| {
+        let WorldDynamics {
+            drift_factor,
+            decay_factor,
+            space,
+            clarity,
+            ..
+        } = self.dynamics;
+        let shared = DriftRates {
+            drift_factor,
+            decay_factor,
+        };
+        let mut compute_drift = |current: f64, rates: DriftRates| {
             let dir = drift_dir.choose(rng).copied().unwrap_or(0.);
-            (current + DRIFT_FACTOR * df * dir).clamp(0., 1.)
+            (current + rates.drift_factor * df * dir).clamp(0., 1.)
         };
-        let compute_decay = |current: f64, target: f64| {
-            let decay: f64 = DECAY_FACTOR * df * (current - target) / 0.5;
+        let compute_decay = |current: f64, target: f64, rates: DriftRates| {
+            let decay: f64 = rates.decay_factor * df * (current - target) / 0.5;
             (current - decay).clamp(0., 1.)
         };
-        let mut apply_transform =
-            |value: f64, target: f64| compute_decay(compute_drift(value), target);
+        let mut apply_transform = |value: f64, target: f64, rates: DriftRates| {
+            compute_decay(compute_drift(value, rates), target, rates)
+        };
 
-        self.set_density(apply_transform(self.density(), self.target_density));
-        self.set_rhythm(apply_transform(self.rhythm(), self.target_rhythm));
-        self.set_tension(apply_transform(self.tension(), self.target_tension));
-        self.set_energy(apply_transform(self.energy(), self.target_energy));
-        self.set_warmth(apply_transform(self.warmth(), self.target_warmth));
+        self.set_density(apply_transform(self.density(), self.target_density, shared));
+        self.set_rhythm(apply_transform(self.rhythm(), self.target_rhythm, shared));
+        self.set_tension(apply_transform(self.tension(), self.target_tension, shared));
+        self.set_energy(apply_transform(self.energy(), self.target_energy, shared));
+        self.set_warmth(apply_transform(self.warmth(), self.target_warmth, shared));
+        self.set_space(apply_transform(self.space(), self.target_space, space));
+        self.set_clarity(apply_transform(
+            self.clarity(),
+            self.target_clarity,
+            clarity,
+        ));
 
         // Decay sparkle impulse over time
         let current_impulse = self.sparkle_impulse();
         self.set_sparkle_impulse((current_impulse - df * 2.0).max(0.0));
     }
 
+    /// Moves the harmony on by `df` seconds; see [`Progression::advance`].
+    /// Returns whether the chord changed.
+    pub fn advance_harmony(&mut self, df: f64, rng: &mut impl Rng) -> bool {
+        self.progression.advance(df, self.tension, self.warmth, rng)
+    }
+
+    /// Checks the invariants every state must keep: parameters and targets
+    /// finite and within [0, 1], and a finite, non-negative sparkle impulse.
+    /// The engine runs this after every update in debug builds.
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        check_unit("density", self.density)?;
+        check_unit("rhythm", self.rhythm)?;
+        check_unit("tension", self.tension)?;
+        check_unit("energy", self.energy)?;
+        check_unit("warmth", self.warmth)?;
+        check_unit("space", self.space)?;
+        check_unit("clarity", self.clarity)?;
+        check_unit("target_density", self.target_density)?;
+        check_unit("target_rhythm", self.target_rhythm)?;
+        check_unit("target_tension", self.target_tension)?;
+        check_unit("target_energy", self.target_energy)?;
+        check_unit("target_warmth", self.target_warmth)?;
+        check_unit("target_space", self.target_space)?;
+        check_unit("target_clarity", self.target_clarity)?;
+        if !(self.sparkle_impulse.is_finite() && self.sparkle_impulse >= 0.0) {
+            return Err(InvariantError::SparkleImpulse(self.sparkle_impulse));
+        }
+        Ok(())
+    }
+
+    /// Blends toward `next` by `alpha` (0 = `self`, 1 = `next`).
+    ///
+    /// Only the seven parameters are blended; the sparkle impulse, targets,
+    /// dynamics and harmony are taken from `next`.
+    pub fn interpolate(&self, next: &WorldState, alpha: f64) -> WorldState {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let lerp = |a: f64, b: f64| a + (b - a) * alpha;
+        WorldState {
+            density: lerp(self.density, next.density),
+            rhythm: lerp(self.rhythm, next.rhythm),
+            tension: lerp(self.tension, next.tension),
+            energy: lerp(self.energy, next.energy),
+            warmth: lerp(self.warmth, next.warmth),
+            space: lerp(self.space, next.space),
+            clarity: lerp(self.clarity, next.clarity),
+            ..next.clone()
+        }
+    }
+
+    /// Returns a drifted copy, leaving `self` untouched. Same semantics as [`drift`].
+    ///
+    /// [`drift`]: WorldState::drift
+    pub fn drifted(&self, df: f64, rng: &mut impl Rng) -> Self {
+        let mut next = self.clone();
+        next.drift(df, rng);
+        next
+    }
+
     // Getters
     pub fn density(&self) -> f64 {
         self.density
@@ -106,10 +492,48 @@ impl WorldState {
         self.warmth
     }
 
+    /// How large the world sounds, from intimate (0) to cavernous (1).
+    pub fn space(&self) -> f64 {
+        self.space
+    }
+
+    /// How clean the sound is, from gritty (0) to pure (1).
+    pub fn clarity(&self) -> f64 {
+        self.clarity
+    }
+
     pub fn sparkle_impulse(&self) -> f64 {
         self.sparkle_impulse
     }
 
+    pub fn sparkle_rate(&self) -> f64 {
+        self.sparkle_rate
+    }
+
+    pub fn harmony(&self) -> Harmony {
+        self.progression.harmony()
+    }
+
+    /// Mood of the parameters as they stand, without the hysteresis a
+    /// running engine applies.
+    pub fn mood(&self) -> Mood {
+        Mood::classify(self.arousal(), self.valence())
+    }
+
+    /// See [`mood::arousal`].
+    pub fn arousal(&self) -> f64 {
+        mood::arousal(self.density, self.rhythm, self.energy)
+    }
+
+    /// See [`mood::valence`].
+    pub fn valence(&self) -> f64 {
+        mood::valence(self.tension, self.warmth)
+    }
+
+    pub fn dynamics(&self) -> WorldDynamics {
+        self.dynamics
+    }
+
     // Setters
     pub fn set_density(&mut self, value: f64) {
         self.density = value.clamp(0., 1.);
@@ -131,10 +555,26 @@ impl WorldState {
         self.warmth = value.clamp(0., 1.);
     }
 
+    pub fn set_space(&mut self, value: f64) {
+        self.space = value.clamp(0., 1.);
+    }
+
+    pub fn set_clarity(&mut self, value: f64) {
+        self.clarity = value.clamp(0., 1.);
+    }
+
     pub fn set_sparkle_impulse(&mut self, value: f64) {
         self.sparkle_impulse = value.max(0.); // Allow values > 1.0 for impulses
     }
 
+    pub fn set_sparkle_rate(&mut self, value: f64) {
+        self.sparkle_rate = value.max(0.);
+    }
+
+    pub fn set_dynamics(&mut self, dynamics: WorldDynamics) {
+        self.dynamics = dynamics;
+    }
+
     // Target value setters
     pub fn set_target_density(&mut self, value: f64) {
         self.target_density = value.clamp(0., 1.);
@@ -155,22 +595,172 @@ impl WorldState {
     pub fn set_target_warmth(&mut self, value: f64) {
         self.target_warmth = value.clamp(0., 1.);
     }
+
+    pub fn set_target_space(&mut self, value: f64) {
+        self.target_space = value.clamp(0., 1.);
+    }
+
+    pub fn set_target_clarity(&mut self, value: f64) {
+        self.target_clarity = value.clamp(0., 1.);
+    }
+
+    /// Sets all decay targets at once from a scene definition.
+    pub fn set_targets(&mut self, targets: &SceneTargets) {
+        self.set_target_density(targets.density);
+        self.set_target_rhythm(targets.rhythm);
+        self.set_target_tension(targets.tension);
+        self.set_target_energy(targets.energy);
+        self.set_target_warmth(targets.warmth);
+        self.set_target_space(targets.space);
+        self.set_target_clarity(targets.clarity);
+    }
+
+    /// Captures the parameters and targets as a preset.
+    pub fn preset(&self) -> WorldPreset {
+        WorldPreset {
+            density: self.density,
+            rhythm: self.rhythm,
+            tension: self.tension,
+            energy: self.energy,
+            warmth: self.warmth,
+            space: self.space,
+            clarity: self.clarity,
+            target_density: self.target_density,
+            target_rhythm: self.target_rhythm,
+            target_tension: self.target_tension,
+            target_energy: self.target_energy,
+            target_warmth: self.target_warmth,
+            target_space: self.target_space,
+            target_clarity: self.target_clarity,
+        }
+    }
+
+    /// Sets the parameters and targets from a preset.
+    pub fn set_preset(&mut self, preset: &WorldPreset) {
+        self.set_density(preset.density);
+        self.set_rhythm(preset.rhythm);
+        self.set_tension(preset.tension);
+        self.set_energy(preset.energy);
+        self.set_warmth(preset.warmth);
+        self.set_space(preset.space);
+        self.set_clarity(preset.clarity);
+        self.set_target_density(preset.target_density);
+        self.set_target_rhythm(preset.target_rhythm);
+        self.set_target_tension(preset.target_tension);
+        self.set_target_energy(preset.target_energy);
+        self.set_target_warmth(preset.target_warmth);
+        self.set_target_space(preset.target_space);
+        self.set_target_clarity(preset.target_clarity);
+    }
 }
 
 impl WorldSnapshot {
     /// Creates a snapshot of the current world state.
     pub fn from_world_state(world_state: &WorldState) -> Self {
         Self {
+            tick: 0,
+            timestamp_ms: 0,
+            sim_time_secs: 0.0,
+            run_state: RunState::Running,
+            time_scale: 1.0,
             density: world_state.density(),
             rhythm: world_state.rhythm(),
             tension: world_state.tension(),
             energy: world_state.energy(),
             warmth: world_state.warmth(),
+            space: world_state.space(),
+            clarity: world_state.clarity(),
             sparkle_impulse: world_state.sparkle_impulse(),
+            scene: None,
+            pulse: Pulse::default(),
+            harmony: world_state.harmony(),
+            mood: world_state.mood(),
+            agents: Vec::new(),
+            field: None,
         }
     }
 
+    /// Sets the tick sequence number.
+    pub fn with_tick(mut self, tick: u64) -> Self {
+        self.tick = tick;
+        self
+    }
+
+    /// Sets the publish timestamp in milliseconds since the Unix epoch.
+    pub fn with_timestamp_ms(mut self, timestamp_ms: u64) -> Self {
+        self.timestamp_ms = timestamp_ms;
+        self
+    }
+
+    /// Sets the simulation time in seconds.
+    pub fn with_sim_time_secs(mut self, sim_time_secs: f64) -> Self {
+        self.sim_time_secs = sim_time_secs;
+        self
+    }
+
+    /// Sets whether the simulation is running or paused.
+    pub fn with_run_state(mut self, run_state: RunState) -> Self {
+        self.run_state = run_state;
+        self
+    }
+
+    /// Sets the multiplier applied to tick dt.
+    pub fn with_time_scale(mut self, time_scale: f64) -> Self {
+        self.time_scale = time_scale;
+        self
+    }
+
+    /// Sets the most recent scene change.
+    pub fn with_scene(mut self, scene: Option<SceneChange>) -> Self {
+        self.scene = scene;
+        self
+    }
+
+    /// Sets the mood.
+    pub fn with_mood(mut self, mood: Mood) -> Self {
+        self.mood = mood;
+        self
+    }
+
+    /// Sets the grids.
+    pub fn with_field(mut self, field: Option<Field>) -> Self {
+        self.field = field;
+        self
+    }
+
+    /// Sets the agents.
+    pub fn with_agents(mut self, agents: Vec<Agent>) -> Self {
+        self.agents = agents;
+        self
+    }
+
+    /// Sets where the pulse grid stands.
+    pub fn with_pulse(mut self, pulse: Pulse) -> Self {
+        self.pulse = pulse;
+        self
+    }
+
     // Getters
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn timestamp_ms(&self) -> u64 {
+        self.timestamp_ms
+    }
+
+    pub fn sim_time_secs(&self) -> f64 {
+        self.sim_time_secs
+    }
+
+    pub fn run_state(&self) -> RunState {
+        self.run_state
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
     pub fn density(&self) -> f64 {
         self.density
     }
@@ -191,9 +781,41 @@ impl WorldSnapshot {
         self.warmth
     }
 
+    pub fn space(&self) -> f64 {
+        self.space
+    }
+
+    pub fn clarity(&self) -> f64 {
+        self.clarity
+    }
+
     pub fn sparkle_impulse(&self) -> f64 {
         self.sparkle_impulse
     }
+
+    pub fn scene(&self) -> Option<&SceneChange> {
+        self.scene.as_ref()
+    }
+
+    pub fn pulse(&self) -> Pulse {
+        self.pulse
+    }
+
+    pub fn harmony(&self) -> Harmony {
+        self.harmony
+    }
+
+    pub fn mood(&self) -> Mood {
+        self.mood
+    }
+
+    pub fn agents(&self) -> &[Agent] {
+        &self.agents
+    }
+
+    pub fn field(&self) -> Option<&Field> {
+        self.field.as_ref()
+    }
 }
 
 #[cfg(test)]
@@ -214,6 +836,166 @@ mod tests {
         assert!((0.0..=1.0).contains(&state.tension()));
         assert!((0.0..=1.0).contains(&state.energy()));
         assert!((0.0..=1.0).contains(&state.warmth()));
+        assert!((0.0..=1.0).contains(&state.space()));
+        assert!((0.0..=1.0).contains(&state.clarity()));
         assert!(state.sparkle_impulse() >= 0.0);
     }
+
+    #[test]
+    fn test_drift_without_dynamics_is_static() {
+        let mut rng = StdRng::from_seed([0; 32]);
+        let mut state = WorldState::new();
+        state.set_dynamics(WorldDynamics {
+            drift_factor: 0.0,
+            decay_factor: 0.0,
+            ..WorldDynamics::default()
+        });
+        for _ in 0..100 {
+            state.drift(0.05, &mut rng);
+        }
+        assert_eq!(state.density(), 0.5);
+        assert_eq!(state.warmth(), 0.5);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut state = WorldState::new();
+        state.set_density(0.8);
+        state.set_warmth(0.25);
+        state.set_sparkle_impulse(1.5);
+        let snapshot = WorldSnapshot::from_world_state(&state)
+            .with_tick(42)
+            .with_timestamp_ms(1_000)
+            .with_run_state(RunState::Paused)
+            .with_time_scale(2.0)
+            .with_scene(Some(SceneChange {
+                name: "dusk".to_string(),
+                transition_secs: 4.0,
+                transition_curve: crate::curves::Curve::SmoothStep,
+                sequence: 3,
+            }));
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let decoded: WorldSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, snapshot);
+
+        let restored = WorldState::from_snapshot(&decoded);
+        assert_eq!(
+            WorldSnapshot::from_world_state(&restored),
+            WorldSnapshot::from_world_state(&state)
+        );
+        assert_eq!(restored.preset().target_density, 0.5);
+    }
+
+    #[test]
+    fn test_blend_moves_targets_too() {
+        let here = WorldState::new().preset();
+        let scene = SceneTargets {
+            density: 0.9,
+            warmth: 0.1,
+            ..SceneTargets::default()
+        };
+        let there = WorldPreset::from_targets(&scene);
+        let quarter = here.blend(&there, 0.25);
+        assert!((quarter.density - 0.6).abs() < 1e-12);
+        assert!((quarter.target_warmth - 0.4).abs() < 1e-12);
+        assert_eq!(quarter.tension, 0.5);
+        let whole = here.blend(&there, 1.0);
+        let mut fields = whole.fields().into_iter().zip(there.fields());
+        assert!(fields.all(|(a, b)| (a.1 - b.1).abs() < 1e-12));
+        assert_eq!(
+            quarter.fields()[7],
+            ("target_density", quarter.target_density)
+        );
+    }
+
+    #[test]
+    fn test_v1_snapshot_deserializes() {
+        let json = r#"{"tick":7,"timestamp_ms":5,"density":0.1,"rhythm":0.2,"tension":0.3,
+            "energy":0.4,"warmth":0.5,"sparkle_impulse":0.0,
+            "scene":{"name":"peaceful","transition_secs":6.0,"sequence":1}}"#;
+        let snapshot: WorldSnapshot = serde_json::from_str(json).unwrap();
+        assert_eq!(snapshot.tick(), 7);
+        assert_eq!(snapshot.run_state(), RunState::Running);
+        assert_eq!(snapshot.time_scale(), 1.0);
+        assert_eq!(snapshot.sim_time_secs(), 0.0);
+        assert_eq!(snapshot.space(), 0.5);
+        assert_eq!(
+            snapshot.scene().unwrap().transition_curve,
+            Default::default()
+        );
+    }
+
+    #[test]
+    fn test_snapshot_from_before_ticks_deserializes() {
+        let json = r#"{"density":0.1,"rhythm":0.2,"tension":0.3,"energy":0.4,"warmth":0.5,
+            "sparkle_impulse":0.0,"scene":null}"#;
+        let snapshot: WorldSnapshot = serde_json::from_str(json).unwrap();
+        assert_eq!((snapshot.tick(), snapshot.timestamp_ms()), (0, 0));
+        assert_eq!(snapshot.density(), 0.1);
+    }
+
+    #[test]
+    fn test_presets_from_before_space_load() {
+        let json = r#"{"density":0.1,"rhythm":0.2,"tension":0.3,"energy":0.4,"warmth":0.5,
+            "target_density":0.1,"target_rhythm":0.2,"target_tension":0.3,
+            "target_energy":0.4,"target_warmth":0.5}"#;
+        let preset: WorldPreset = serde_json::from_str(json).unwrap();
+        assert_eq!((preset.space, preset.target_space), (0.5, 0.5));
+        assert_eq!((preset.clarity, preset.target_clarity), (0.5, 0.5));
+    }
+
+    #[test]
+    fn test_space_drifts_on_its_own_rates() {
+        let mut state = WorldState::new();
+        state.set_target_space(1.0);
+        state.set_target_density(1.0);
+        state.set_dynamics(WorldDynamics {
+            drift_factor: 0.0,
+            decay_factor: 0.0,
+            space: DriftRates {
+                drift_factor: 0.0,
+                decay_factor: 0.2,
+            },
+            ..WorldDynamics::default()
+        });
+        let mut rng = StdRng::from_seed([0; 32]);
+        for _ in 0..200 {
+            state.drift(0.05, &mut rng);
+        }
+        assert!(state.space() > 0.9, "{}", state.space());
+        assert_eq!(state.density(), 0.5);
+    }
+
+    #[test]
+    fn test_clarity_drifts_on_its_own_rates() {
+        let mut state = WorldState::new();
+        state.set_target_clarity(1.0);
+        state.set_target_density(1.0);
+        state.set_dynamics(WorldDynamics {
+            drift_factor: 0.0,
+            decay_factor: 0.0,
+            clarity: DriftRates {
+                drift_factor: 0.0,
+                decay_factor: 0.2,
+            },
+            ..WorldDynamics::default()
+        });
+        let mut rng = StdRng::from_seed([0; 32]);
+        for _ in 0..200 {
+            state.drift(0.05, &mut rng);
+        }
+        assert!(state.clarity() > 0.9, "{}", state.clarity());
+        assert_eq!(state.density(), 0.5);
+    }
+
+    #[test]
+    fn test_drifted_matches_drift() {
+        let mut state = WorldState::new();
+        let next = state.drifted(0.05, &mut StdRng::from_seed([1; 32]));
+        assert_eq!(state.density(), 0.5);
+        state.drift(0.05, &mut StdRng::from_seed([1; 32]));
+        assert_eq!(next.density(), state.density());
+        assert_eq!(next.warmth(), state.warmth());
+    }
 }
@@ -0,0 +1,182 @@
+//! wasm-bindgen bindings to the world engine, so a web client can run a
+//! local copy of the world and predict it between the server's 10 Hz
+//! snapshots.
+//!
+//! ```js
+//! import init, { WorldSim } from "./pkg/ambient_wasm.js";
+//!
+//! await init();
+//! const sim = new WorldSim(BigInt(Date.now()));
+//! ws.onmessage = (event) => {
+//!   const message = JSON.parse(event.data);
+//!   if (message.type === "snapshot") sim.sync(JSON.stringify(message.payload.world));
+//! };
+//! function frame(dtSecs) {
+//!   sim.tick(dtSecs);
+//!   draw(sim.density, sim.energy);
+//! }
+//! ```
+//!
+//! [`Noise`] samples the server's noise field from the seed in its hello, so
+//! a visual's grain matches every other client's:
+//!
+//! ```js
+//! const noise = new Noise(hello.payload.noise_seed);
+//! const grain = noise.sample("energy", u, v, world.sim_time_secs);
+//! ```
+//!
+//! Build with `wasm-pack build crates/ambient_wasm --target web`.
+
+use ambient_core::engine::WorldEngine;
+use ambient_core::events::{Event, PerformAction};
+use ambient_core::noise::{NoiseField, Parameter};
+use ambient_core::world::WorldSnapshot;
+use wasm_bindgen::prelude::*;
+
+/// A local world engine, resynced from each server snapshot and ticked per
+/// animation frame in between.
+#[wasm_bindgen]
+pub struct WorldSim {
+    engine: WorldEngine,
+}
+
+#[wasm_bindgen]
+impl WorldSim {
+    /// Drift differs from the server's whatever the seed; syncing pulls the
+    /// two back together.
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u64) -> WorldSim {
+        WorldSim {
+            engine: WorldEngine::with_seed(seed),
+        }
+    }
+
+    /// Adopts a server snapshot, given as the JSON of a snapshot message's
+    /// `payload.world`.
+    pub fn sync(&mut self, snapshot_json: &str) -> Result<(), JsError> {
+        let snapshot: WorldSnapshot = serde_json::from_str(snapshot_json)?;
+        self.engine.sync_to(&snapshot);
+        Ok(())
+    }
+
+    /// Advances the world by `dt_secs` of wall time.
+    pub fn tick(&mut self, dt_secs: f64) {
+        self.engine.apply(Event::Tick { dt: dt_secs });
+    }
+
+    /// Applies a perform action locally, as JSON in the `POST /event` form
+    /// without the type (e.g. `{"Pulse": {"intensity": 0.7}}`), for instant
+    /// feedback before the server's snapshot confirms it. Returns whether
+    /// it took effect.
+    pub fn perform(&mut self, action_json: &str) -> Result<bool, JsError> {
+        let action: PerformAction = serde_json::from_str(action_json)?;
+        Ok(self.engine.apply(Event::Perform(action)).applied)
+    }
+
+    /// The predicted world, as JSON in the V2 snapshot shape.
+    pub fn snapshot(&self) -> String {
+        serde_json::to_string(&self.engine.get_snapshot()).expect("snapshots serialize")
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn density(&self) -> f64 {
+        self.engine.get_snapshot().density()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn rhythm(&self) -> f64 {
+        self.engine.get_snapshot().rhythm()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn tension(&self) -> f64 {
+        self.engine.get_snapshot().tension()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn energy(&self) -> f64 {
+        self.engine.get_snapshot().energy()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn warmth(&self) -> f64 {
+        self.engine.get_snapshot().warmth()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn space(&self) -> f64 {
+        self.engine.get_snapshot().space()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn clarity(&self) -> f64 {
+        self.engine.get_snapshot().clarity()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sparkle_impulse(&self) -> f64 {
+        self.engine.get_snapshot().sparkle_impulse()
+    }
+}
+
+/// The server's noise field.
+#[wasm_bindgen]
+pub struct Noise {
+    field: NoiseField,
+}
+
+#[wasm_bindgen]
+impl Noise {
+    /// `seed` is the hello's `noise_seed`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u32) -> Noise {
+        Noise {
+            field: NoiseField::new(seed.into()),
+        }
+    }
+
+    /// Noise for `parameter` (`density`, `rhythm`, `tension`, `energy`,
+    /// `warmth`, `space` or `clarity`) at (`x`, `y`) in the unit square and `time_secs` of
+    /// simulated time, -1 to 1.
+    pub fn sample(&self, parameter: &str, x: f64, y: f64, time_secs: f64) -> Result<f64, JsError> {
+        let parameter = Parameter::parse(parameter)
+            .ok_or_else(|| JsError::new(&format!("Unknown parameter '{}'", parameter)))?;
+        Ok(self.field.sample_at(parameter, x, y, time_secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambient_core::world::WorldState;
+
+    #[test]
+    fn test_sync_then_predict() {
+        let mut state = WorldState::new();
+        state.set_energy(0.9);
+        let server = WorldSnapshot::from_world_state(&state).with_tick(40);
+        let mut sim = WorldSim::new(3);
+        sim.sync(&serde_json::to_string(&server).unwrap()).unwrap();
+        assert_eq!(sim.energy(), 0.9);
+
+        for _ in 0..30 {
+            sim.tick(1.0 / 60.0);
+        }
+        let predicted: WorldSnapshot = serde_json::from_str(&sim.snapshot()).unwrap();
+        assert_eq!(predicted.tick(), 70);
+        // Energy decays back toward its target of 0.5
+        assert!(predicted.energy() < 0.9);
+
+        assert!(sim.perform(r#"{"Pulse": {"intensity": 0.05}}"#).unwrap());
+        assert!(sim.energy() > predicted.energy());
+    }
+
+    #[test]
+    fn test_noise_matches_the_core_field() {
+        let noise = Noise::new(7);
+        assert_eq!(
+            noise.sample("warmth", 0.25, 0.5, 3.0).unwrap(),
+            NoiseField::new(7).sample_at(Parameter::Warmth, 0.25, 0.5, 3.0)
+        );
+    }
+}
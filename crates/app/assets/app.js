@@ -0,0 +1,158 @@
+// Control page served at / by the app binary. Streams snapshots over the
+// WebSocket API and sends actions through it; time controls use REST.
+
+const WORLD_FIELDS = ["density", "rhythm", "tension", "energy", "warmth", "sparkle_impulse"];
+const AUDIO_FIELDS = ["master_gain", "brightness", "motion", "texture", "sparkle_impulse"];
+const RECONNECT_MS = 2000;
+
+const $ = (id) => document.getElementById(id);
+let socket = null;
+let requestCounter = 0;
+// Token of the session to pick back up after a drop, and the one this
+// connection was offered in case that fails
+let resumeToken = null;
+let freshToken = null;
+
+function buildMeters(container, fields) {
+  const fills = {};
+  for (const field of fields) {
+    const row = document.createElement("div");
+    row.className = "meter";
+    row.innerHTML = `<span>${field.replace("_", " ")}</span>
+      <div class="bar"><div class="fill"></div></div><span class="value">-</span>`;
+    container.appendChild(row);
+    fills[field] = { fill: row.querySelector(".fill"), value: row.querySelector(".value") };
+  }
+  return fills;
+}
+
+const worldMeters = buildMeters($("world"), WORLD_FIELDS);
+const audioMeters = buildMeters($("audio"), AUDIO_FIELDS);
+
+function setMeters(meters, values) {
+  for (const [field, { fill, value }] of Object.entries(meters)) {
+    const v = values[field] ?? 0;
+    fill.style.width = `${Math.max(0, Math.min(1, v)) * 100}%`;
+    value.textContent = v.toFixed(2);
+  }
+}
+
+function showMessage(text) {
+  $("message").textContent = text;
+}
+
+function token() {
+  return localStorage.getItem("ambient-token") || "";
+}
+
+function send(type, payload) {
+  if (!socket || socket.readyState !== WebSocket.OPEN) {
+    showMessage("Not connected");
+    return;
+  }
+  socket.send(JSON.stringify({ version: "2.0", type, payload }));
+}
+
+function perform(action) {
+  send("perform", { request_id: `ui-${++requestCounter}`, action });
+}
+
+async function post(path, body) {
+  const headers = { "Content-Type": "application/json" };
+  if (token()) headers.Authorization = `Bearer ${token()}`;
+  const response = await fetch(path, { method: "POST", headers, body: JSON.stringify(body ?? {}) });
+  if (!response.ok) showMessage(`${path}: ${response.status} ${await response.text()}`);
+}
+
+function handleMessage(message) {
+  switch (message.type) {
+    case "snapshot": {
+      const { world, audio, analysis } = message.payload;
+      setMeters(worldMeters, world);
+      setMeters(audioMeters, audio);
+      $("tick").textContent = world.tick;
+      $("run-state").textContent = world.run_state;
+      $("scene").textContent = world.scene ? world.scene.name : "none";
+      $("base-freq").textContent = audio.base_freq_hz.toFixed(1);
+      $("peak").textContent = analysis.peak_db.toFixed(1);
+      $("lufs").textContent = analysis.momentary_lufs.toFixed(1);
+      break;
+    }
+    case "hello":
+      freshToken = message.payload.resume_token;
+      resumeToken ??= freshToken;
+      break;
+    case "resumed":
+      resumeToken = message.payload.resume_token;
+      break;
+    case "negotiated":
+      $("status").textContent = message.payload.role ? `live · ${message.payload.role}` : "live";
+      break;
+    case "event_ack":
+      showMessage(`${message.payload.action}: ${message.payload.applied ? "applied" : "no effect"}`);
+      break;
+    case "error":
+      if (message.payload.code === "RESUME_FAILED") resumeToken = freshToken;
+      showMessage(`${message.payload.code}: ${message.payload.message}`);
+      break;
+  }
+}
+
+function connect() {
+  const scheme = location.protocol === "https:" ? "wss" : "ws";
+  socket = new WebSocket(`${scheme}://${location.host}/ws`);
+  socket.onopen = () => {
+    $("status").textContent = "live";
+    $("status").classList.add("live");
+    const payload = { schema_version: "2.0" };
+    if (token()) payload.token = token();
+    if (resumeToken) payload.resume_token = resumeToken;
+    send("hello", payload);
+  };
+  socket.onmessage = (event) => handleMessage(JSON.parse(event.data));
+  socket.onclose = () => {
+    $("status").textContent = "disconnected, retrying";
+    $("status").classList.remove("live");
+    setTimeout(connect, RECONNECT_MS);
+  };
+}
+
+$("intensity").addEventListener("input", (e) => {
+  $("intensity-value").textContent = Number(e.target.value).toFixed(2);
+});
+
+for (const button of document.querySelectorAll("[data-action]")) {
+  button.addEventListener("click", () => {
+    perform({ [button.dataset.action]: { intensity: Number($("intensity").value) } });
+  });
+}
+
+for (const button of document.querySelectorAll("[data-scene]")) {
+  button.addEventListener("click", () => perform({ Scene: { name: button.dataset.scene } }));
+}
+
+$("scene-form").addEventListener("submit", (e) => {
+  e.preventDefault();
+  const name = $("scene-name").value.trim();
+  if (name) perform({ Scene: { name } });
+});
+
+$("pause").addEventListener("click", () => post("/world/pause"));
+$("resume").addEventListener("click", () => post("/world/resume"));
+$("step").addEventListener("click", () => post("/world/step", { ticks: 1 }));
+
+// The slider is logarithmic: -1..1 maps to 0.1x..10x
+$("time-scale").addEventListener("input", (e) => {
+  $("time-scale-value").textContent = (10 ** Number(e.target.value)).toFixed(2);
+});
+$("time-scale").addEventListener("change", (e) => {
+  post("/world/time_scale", { time_scale: 10 ** Number(e.target.value) });
+});
+
+$("token-form").addEventListener("submit", (e) => {
+  e.preventDefault();
+  localStorage.setItem("ambient-token", $("token").value);
+  if (socket) socket.close(); // Reconnects with the new token
+});
+
+connect();
@@ -0,0 +1,12 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    {
+        // Use the bundled protoc so builds do not need one installed
+        // SAFETY: build scripts are single-threaded
+        unsafe { std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?) };
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["../../proto/ambient.proto"], &["../../proto"])?;
+    }
+    Ok(())
+}
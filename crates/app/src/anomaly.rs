@@ -0,0 +1,466 @@
+//! Stuck-state and output fault detection, so installation operators hear
+//! about problems remotely.
+//!
+//! The world task feeds every snapshot and processed tick into an
+//! [`AnomalyDetector`], and the output's health before each check, which it
+//! runs once a second. Alerts are raised once
+//! when a condition has held for its configured time and cleared when it
+//! stops holding; each change is logged, broadcast to WebSocket clients as an
+//! `alert` message and reflected in `GET /metrics`. With `restart_audio`,
+//! raising an output alert also asks the audio watchdog to rebuild the engine.
+
+use crate::api::ServerMessage;
+use crate::config::AlertsConfig;
+use crate::protocol::PROTOCOL_VERSION;
+use ambient_core::world::{RunState, WorldSnapshot};
+use audio::health::{HealthReading, SharedOutputHealth};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::{broadcast, watch};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// How often the world task checks for anomalies.
+pub const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Distance from 0 or 1 within which a parameter counts as pinned.
+const PINNED_EPSILON: f64 = 1e-3;
+
+const PARAMETERS: [&str; 7] = [
+    "density", "rhythm", "tension", "energy", "warmth", "space", "clarity",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// A parameter has sat at 0 or 1.
+    ParameterPinned,
+    /// The running world has not processed a tick.
+    TickStall,
+    /// The running world's parameters have not moved.
+    WorldUnchanged,
+    /// The output has been silent.
+    OutputSilent,
+    /// The output has had full-scale samples every second.
+    OutputFullScale,
+    /// The output has had NaN or infinite samples.
+    OutputNonFinite,
+}
+
+impl AlertKind {
+    /// Whether the alert is about the audio output rather than the world.
+    pub fn is_output(self) -> bool {
+        matches!(
+            self,
+            AlertKind::OutputSilent | AlertKind::OutputFullScale | AlertKind::OutputNonFinite
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Alert {
+    pub kind: AlertKind,
+    /// The pinned parameter, for `parameter_pinned`.
+    pub parameter: Option<&'static str>,
+    /// When the condition started holding, in Unix milliseconds.
+    pub since_ms: u64,
+    pub message: String,
+}
+
+/// An alert starting or ending.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertChange {
+    Raised(Alert),
+    Cleared(Alert),
+}
+
+/// Active alerts and a running count, served in `GET /metrics`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct AlertStatus {
+    pub active: Vec<Alert>,
+    /// Alerts raised since startup.
+    pub raised_total: u64,
+}
+
+/// Payload of the `alert` WebSocket message.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AlertPayload {
+    /// `raised` or `cleared`.
+    pub state: &'static str,
+    #[serde(flatten)]
+    pub alert: Alert,
+}
+
+/// Tracks how long each anomaly condition has held.
+#[derive(Debug)]
+pub struct AnomalyDetector {
+    config: AlertsConfig,
+    running: bool,
+    pinned_since: [Option<u64>; 7],
+    last_tick_ms: u64,
+    last_change_ms: u64,
+    last_values: Option<[f64; 7]>,
+    output: HealthReading,
+    full_scale_since: Option<u64>,
+    non_finite_since: Option<u64>,
+    active: Vec<Alert>,
+}
+
+impl AnomalyDetector {
+    pub fn new(config: AlertsConfig, now_ms: u64) -> Self {
+        Self {
+            config,
+            running: true,
+            pinned_since: [None; 7],
+            last_tick_ms: now_ms,
+            last_change_ms: now_ms,
+            last_values: None,
+            output: HealthReading::default(),
+            full_scale_since: None,
+            non_finite_since: None,
+            active: Vec::new(),
+        }
+    }
+
+    /// Notes a published snapshot.
+    pub fn observe(&mut self, snapshot: &WorldSnapshot, now_ms: u64) {
+        let running = snapshot.run_state() == RunState::Running;
+        if running && !self.running {
+            // Time spent paused is intentional stillness; start the clocks over
+            self.last_tick_ms = now_ms;
+            self.last_change_ms = now_ms;
+            self.pinned_since = self.pinned_since.map(|since| since.map(|_| now_ms));
+        }
+        self.running = running;
+
+        let values = [
+            snapshot.density(),
+            snapshot.rhythm(),
+            snapshot.tension(),
+            snapshot.energy(),
+            snapshot.warmth(),
+            snapshot.space(),
+            snapshot.clarity(),
+        ];
+        for (since, value) in self.pinned_since.iter_mut().zip(values) {
+            let pinned = value <= PINNED_EPSILON || value >= 1.0 - PINNED_EPSILON;
+            *since = match (pinned, *since) {
+                (true, Some(since)) => Some(since),
+                (true, None) => Some(now_ms),
+                (false, _) => None,
+            };
+        }
+        if self.last_values != Some(values) {
+            self.last_values = Some(values);
+            self.last_change_ms = now_ms;
+        }
+    }
+
+    /// Notes the output's health. Bad samples since the previous reading
+    /// start (or continue) their condition; a reading without clears it.
+    pub fn observe_output(&mut self, reading: HealthReading, now_ms: u64) {
+        let since = |seen: bool, since: Option<u64>| seen.then(|| since.unwrap_or(now_ms));
+        self.full_scale_since = since(
+            reading.full_scale > self.output.full_scale,
+            self.full_scale_since,
+        );
+        self.non_finite_since = since(
+            reading.non_finite > self.output.non_finite,
+            self.non_finite_since,
+        );
+        self.output = reading;
+    }
+
+    /// Notes a tick the world processed.
+    pub fn tick(&mut self, now_ms: u64) {
+        self.last_tick_ms = now_ms;
+    }
+
+    pub fn active(&self) -> &[Alert] {
+        &self.active
+    }
+
+    /// Compares the conditions holding at `now_ms` against the active alerts.
+    pub fn check(&mut self, now_ms: u64) -> Vec<AlertChange> {
+        let held = |since: u64, secs: f64| {
+            secs > 0.0 && now_ms.saturating_sub(since) as f64 >= secs * 1000.0
+        };
+        let mut current = Vec::new();
+        if self.running {
+            for (i, name) in PARAMETERS.iter().enumerate() {
+                if let Some(since) = self.pinned_since[i]
+                    && held(since, self.config.pinned_secs)
+                {
+                    let value = self.last_values.map_or(0.0, |values| values[i]);
+                    current.push(Alert {
+                        kind: AlertKind::ParameterPinned,
+                        parameter: Some(name),
+                        since_ms: since,
+                        message: format!(
+                            "{} has been pinned at {} for over {}s",
+                            name,
+                            value.round(),
+                            self.config.pinned_secs
+                        ),
+                    });
+                }
+            }
+            if held(self.last_tick_ms, self.config.stall_secs) {
+                current.push(Alert {
+                    kind: AlertKind::TickStall,
+                    parameter: None,
+                    since_ms: self.last_tick_ms,
+                    message: format!("No ticks processed for over {}s", self.config.stall_secs),
+                });
+            }
+            if held(self.last_change_ms, self.config.unchanged_secs) {
+                current.push(Alert {
+                    kind: AlertKind::WorldUnchanged,
+                    parameter: None,
+                    since_ms: self.last_change_ms,
+                    message: format!(
+                        "World parameters unchanged for over {}s",
+                        self.config.unchanged_secs
+                    ),
+                });
+            }
+        }
+
+        // The output plays on while the world is paused
+        let silence_secs = self.config.silence_secs;
+        if silence_secs > 0.0 && self.output.silent_secs >= silence_secs {
+            current.push(Alert {
+                kind: AlertKind::OutputSilent,
+                parameter: None,
+                since_ms: now_ms.saturating_sub((self.output.silent_secs * 1000.0) as u64),
+                message: format!("Audio output silent for over {}s", silence_secs),
+            });
+        }
+        if let Some(since) = self.full_scale_since
+            && held(since, self.config.full_scale_secs)
+        {
+            current.push(Alert {
+                kind: AlertKind::OutputFullScale,
+                parameter: None,
+                since_ms: since,
+                message: format!(
+                    "Audio output at full scale for over {}s",
+                    self.config.full_scale_secs
+                ),
+            });
+        }
+        if let Some(since) = self.non_finite_since
+            && self.config.non_finite
+        {
+            current.push(Alert {
+                kind: AlertKind::OutputNonFinite,
+                parameter: None,
+                since_ms: since,
+                message: format!(
+                    "Audio output has NaN or infinite samples ({} so far)",
+                    self.output.non_finite
+                ),
+            });
+        }
+
+        let same = |a: &Alert, b: &Alert| a.kind == b.kind && a.parameter == b.parameter;
+        let mut changes: Vec<AlertChange> = self
+            .active
+            .iter()
+            .filter(|alert| !current.iter().any(|c| same(c, alert)))
+            .cloned()
+            .map(AlertChange::Cleared)
+            .collect();
+        changes.extend(
+            current
+                .iter()
+                .filter(|alert| !self.active.iter().any(|a| same(a, alert)))
+                .cloned()
+                .map(AlertChange::Raised),
+        );
+        self.active = current;
+        changes
+    }
+}
+
+/// The detector plus where its readings come from and its alerts go.
+pub struct AnomalyMonitor {
+    pub detector: AnomalyDetector,
+    pub output: Arc<SharedOutputHealth>,
+    pub status_tx: watch::Sender<AlertStatus>,
+    pub broadcast_tx: broadcast::Sender<ServerMessage>,
+}
+
+impl AnomalyMonitor {
+    /// Checks for anomalies and reports any alerts raised or cleared.
+    pub fn check(&mut self, now_ms: u64) {
+        self.detector.observe_output(self.output.reading(), now_ms);
+        let changes = self.detector.check(now_ms);
+        if changes.is_empty() {
+            return;
+        }
+        let mut raised = 0;
+        for change in changes {
+            let (state, alert) = match change {
+                AlertChange::Raised(alert) => {
+                    warn!("Alert raised: {}", alert.message);
+                    raised += 1;
+                    if alert.kind.is_output() && self.detector.config.restart_audio {
+                        warn!("Restarting the audio engine");
+                        self.output.request_restart();
+                    }
+                    ("raised", alert)
+                }
+                AlertChange::Cleared(alert) => {
+                    info!("Alert cleared: {}", alert.message);
+                    ("cleared", alert)
+                }
+            };
+            let _ = self.broadcast_tx.send(ServerMessage::Alert {
+                version: PROTOCOL_VERSION.to_string(),
+                payload: AlertPayload { state, alert },
+            });
+        }
+        let active = self.detector.active().to_vec();
+        self.status_tx.send_modify(|status| {
+            status.active = active;
+            status.raised_total += raised;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambient_core::world::{WorldPreset, WorldState};
+
+    fn config() -> AlertsConfig {
+        AlertsConfig {
+            pinned_secs: 60.0,
+            stall_secs: 5.0,
+            unchanged_secs: 0.0,
+            silence_secs: 20.0,
+            full_scale_secs: 5.0,
+            non_finite: true,
+            restart_audio: false,
+        }
+    }
+
+    fn snapshot_with_density(density: f64) -> WorldSnapshot {
+        let mut state = WorldState::new();
+        state.set_preset(&WorldPreset {
+            density,
+            ..state.preset()
+        });
+        WorldSnapshot::from_world_state(&state)
+    }
+
+    #[test]
+    fn test_pinned_parameter_raises_then_clears() {
+        let mut detector = AnomalyDetector::new(config(), 0);
+        detector.observe(&snapshot_with_density(1.0), 0);
+        detector.tick(30_000);
+        assert!(detector.check(30_000).is_empty());
+
+        detector.tick(61_000);
+        let changes = detector.check(61_000);
+        assert_eq!(changes.len(), 1);
+        let AlertChange::Raised(alert) = &changes[0] else {
+            panic!("expected a raised alert");
+        };
+        assert_eq!(alert.kind, AlertKind::ParameterPinned);
+        assert_eq!(alert.parameter, Some("density"));
+        // Raised once, not on every check
+        detector.tick(62_000);
+        assert!(detector.check(62_000).is_empty());
+
+        detector.observe(&snapshot_with_density(0.6), 63_000);
+        detector.tick(63_000);
+        assert!(matches!(
+            detector.check(63_000).as_slice(),
+            [AlertChange::Cleared(_)]
+        ));
+    }
+
+    #[test]
+    fn test_tick_stall_ignored_while_paused() {
+        let mut detector = AnomalyDetector::new(config(), 0);
+        let changes = detector.check(6_000);
+        assert!(matches!(
+            changes.as_slice(),
+            [AlertChange::Raised(Alert {
+                kind: AlertKind::TickStall,
+                ..
+            })]
+        ));
+
+        let paused = snapshot_with_density(0.5).with_run_state(RunState::Paused);
+        detector.observe(&paused, 7_000);
+        assert!(matches!(
+            detector.check(60_000).as_slice(),
+            [AlertChange::Cleared(_)]
+        ));
+
+        // Resuming restarts the clock
+        detector.observe(&snapshot_with_density(0.5), 60_000);
+        assert!(detector.check(62_000).is_empty());
+    }
+
+    #[test]
+    fn test_output_faults_raise_and_clear() {
+        let mut detector = AnomalyDetector::new(config(), 0);
+        let mut reading = HealthReading {
+            frames: 48_000,
+            ..HealthReading::default()
+        };
+        detector.observe_output(reading, 0);
+        assert!(detector.check(0).is_empty());
+
+        // A NaN burst is alerted at once and cleared by a clean second
+        reading.non_finite = 3;
+        detector.observe_output(reading, 1_000);
+        let changes = detector.check(1_000);
+        assert!(matches!(
+            changes.as_slice(),
+            [AlertChange::Raised(Alert {
+                kind: AlertKind::OutputNonFinite,
+                ..
+            })]
+        ));
+        detector.observe_output(reading, 2_000);
+        assert!(matches!(
+            detector.check(2_000).as_slice(),
+            [AlertChange::Cleared(_)]
+        ));
+
+        // Clipping must last, silence must reach its length
+        reading.silent_secs = 30.0;
+        for secs in 3..=7 {
+            reading.full_scale += 10;
+            detector.observe_output(reading, secs * 1_000);
+            detector.tick(secs * 1_000);
+        }
+        let kinds: Vec<_> = detector
+            .check(7_000)
+            .into_iter()
+            .map(|change| match change {
+                AlertChange::Raised(alert) => alert.kind,
+                AlertChange::Cleared(alert) => panic!("cleared {:?}", alert.kind),
+            })
+            .collect();
+        assert_eq!(kinds, [AlertKind::OutputSilent]);
+        // Still clipping: the full-scale run started at 3 s
+        reading.full_scale += 10;
+        detector.observe_output(reading, 8_000);
+        detector.tick(8_000);
+        let changes = detector.check(8_000);
+        assert!(matches!(
+            changes.as_slice(),
+            [AlertChange::Raised(Alert {
+                kind: AlertKind::OutputFullScale,
+                since_ms: 3_000,
+                ..
+            })]
+        ));
+    }
+}
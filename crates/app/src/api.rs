@@ -1,20 +1,66 @@
-use ambient_core::events::{Event, PerformAction, TriggerKind};
-use ambient_core::world::WorldSnapshot;
-use audio::params::AudioParams;
+use crate::anomaly::{AlertPayload, AlertStatus};
+use crate::audio_recorder::{AudioRecorder, Take};
+use crate::audit::{AuditAction, AuditRecord};
+use crate::auth::{Auth, AuthError, Principal, Role, bearer_token};
+use crate::calibration::{CalibrationRequest, CalibrationStatus, Calibrator};
+use crate::config::LayerConfig;
+use crate::error::{ApiError, ApiJson, ApiQuery};
+use crate::fanout::{SnapshotFanout, SnapshotFeed};
+use crate::logging::{LogControl, LogSettings};
+use crate::openapi::{ApiDoc, DOCS_PATH, OPENAPI_PATH};
+use crate::patches::AudioPatch;
+use crate::presets::{PresetDiff, PresetStore, StoredPreset, is_valid_name};
+use crate::protocol::{
+    CAPABILITIES, CLIENT_MESSAGE_TYPES, Channel, ENCODINGS, Encoding, ErrorCode, Negotiated,
+    PROTOCOL_VERSION, SERVER_MESSAGE_TYPES, SUPPORTED_VERSIONS, Subscription, is_supported_version,
+    negotiate,
+};
+use crate::request_id::{REQUEST_ID_HEADER, assign_request_id, make_request_span};
+use crate::resume::{ResumeStore, new_token};
+use crate::runtime::{
+    ActiveMapping, AudioOverride, EventQueueStats, QueuedEvent, TICK_RATE_RANGE, TickStats,
+    WorldCommand, unix_time_ms,
+};
+use crate::schema::{SchemaVersion, SchemaVersionHeader, WireApplyResult, WireSnapshot};
+use crate::stats::{StatsSummary, parse_window};
+use crate::web;
+use crate::ws_schema::{WS_SCHEMA_PATH, ws_schema};
+use ambient_core::engine::{ApplyResult, SparkleEvent, TIME_SCALE_RANGE};
+use ambient_core::events::{
+    Event, EventSource, INTENSITY_RANGE, PerformAction, SourcedEvent, TriggerKind,
+};
+use ambient_core::mood::MoodChange;
+use ambient_core::pulse::BeatEvent;
+use ambient_core::world::{RunState, WorldPreset, WorldSnapshot, WorldState};
+use audio::effects::{EffectBus, EffectKind, SharedEffects};
+use audio::mapping::MappingProfile;
+use audio::master::{LevelReading, SharedMeter};
+use audio::mixer::{LayerGains, LayerSlot, SharedLayerGains};
+use audio::params::{AudioParams, LayerAmounts, MAX_PRE_DELAY_MS, ParamOverrides};
+use audio::registry::{LayerRegistry, LayerSpec, RegistryError};
+use audio::status::{EngineState, SharedAudioStatus};
+use audio::telemetry::{CallbackStats, CallbackTelemetry};
+use audio::waveform::{Envelope, HISTORY_SECS, SharedWaveform};
 use axum::extract::ws::{Message, WebSocket};
 use axum::{
     Json, Router,
-    extract::{State, WebSocketUpgrade},
-    http::{Method, StatusCode},
-    response::IntoResponse,
-    routing::{get, post},
+    extract::{FromRef, Path, State, WebSocketUpgrade},
+    http::{HeaderMap, HeaderValue, Method, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
 };
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc, watch};
-use tokio_stream::wrappers::UnboundedReceiverStream;
-use tower_http::cors::{Any, CorsLayer};
+use std::time::Instant;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{Mutex, RwLock, broadcast, mpsc, oneshot, watch};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::trace::TraceLayer;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
 
 /// Task that keeps the current snapshot updated from the watch channel.
 /// This allows async handlers to read the latest snapshot without blocking.
@@ -37,13 +83,78 @@ pub async fn start_snapshot_task(
 
 #[derive(Clone)]
 pub struct AppState {
-    pub event_tx: mpsc::Sender<Event>,
+    pub event_tx: mpsc::Sender<QueuedEvent>,
+    /// Backpressure counters for `event_tx`.
+    pub event_queue: Arc<EventQueueStats>,
+    /// Run-state control (pause, resume, step) for the world task.
+    pub world_command_tx: mpsc::Sender<WorldCommand>,
     pub current_snapshot: Arc<RwLock<WorldSnapshot>>,
     pub world_state_rx: watch::Receiver<WorldSnapshot>,
     pub audio_params_rx: watch::Receiver<AudioParams>,
+    /// Current world tick rate, advertised in the hello message.
+    pub tick_hz_rx: watch::Receiver<f64>,
+    /// Retunes the tick task, through `POST /admin/tick_rate`.
+    pub tick_hz_tx: watch::Sender<f64>,
+    /// Tick clock and measured tick rate, published by the tick task.
+    pub tick_stats_rx: watch::Receiver<TickStats>,
+    /// Rate at which WebSocket clients receive snapshots.
+    pub snapshot_hz_rx: watch::Receiver<f64>,
+    /// Server-initiated messages fanned out to every WebSocket client.
+    pub broadcast_tx: broadcast::Sender<ServerMessage>,
+    pub auth: Auth,
+    /// Output meter written by the audio callback.
+    pub meter: Arc<SharedMeter>,
+    /// Min/max envelope of the output, written by the audio callback.
+    pub waveform: Arc<SharedWaveform>,
+    /// Callback load and xruns recorded by the audio callback.
+    pub telemetry: Arc<CallbackTelemetry>,
+    /// Per-layer mix amounts set through `POST /audio/layers`.
+    pub layer_amounts_tx: watch::Sender<LayerAmounts>,
+    /// Manual parameter override set through `POST /audio/override`.
+    pub audio_override_tx: watch::Sender<Option<AudioOverride>>,
+    /// Mapping profiles selectable through `POST /audio/mapping`.
+    pub mapping_profiles: Arc<BTreeMap<String, MappingProfile>>,
+    pub mapping_tx: watch::Sender<ActiveMapping>,
+    /// Output engine health, kept current by the audio watchdog.
+    pub audio_status: Arc<SharedAudioStatus>,
+    /// Configured per-layer gains, shared with the mixer.
+    pub layer_gains: Arc<SharedLayerGains>,
+    /// Effect chains, switched on and off through `POST /audio/effects`.
+    pub effects: Arc<SharedEffects>,
+    /// The layer stack, changed through `/audio/stack`.
+    pub layers: Arc<LayerRegistry>,
+    /// Audio patches read from `audio.patches_dir`, by name.
+    pub patches: Arc<BTreeMap<String, AudioPatch>>,
+    /// Encoded snapshot feeds the WebSocket sessions stream.
+    pub snapshots: Arc<SnapshotFanout>,
+    /// Admin changes go to the audit log too, when one is kept.
+    pub audit_tx: Option<mpsc::Sender<AuditRecord>>,
+    /// Anomaly alerts kept current by the world task.
+    pub alerts_rx: watch::Receiver<AlertStatus>,
+    /// Presets captured through `POST /presets/{name}`.
+    pub presets: Arc<Mutex<PresetStore>>,
+    /// Dropped WebSocket sessions that can still be resumed.
+    pub resumes: Arc<std::sync::Mutex<ResumeStore<ParkedSession>>>,
+    /// Seed of the noise field advertised in the hello.
+    pub noise_seed: u32,
+    /// Runtime log filter.
+    pub log_control: LogControl,
+    /// The rolling recording saved through `POST /record/save`.
+    pub audio_recorder: Option<AudioRecorder>,
+    /// Speaker calibration, run through `/audio/calibration`.
+    pub calibration: Arc<Calibrator>,
+    /// The live output stream, when `stream.enabled` is set.
+    #[cfg(feature = "stream")]
+    pub stream: Option<crate::stream::StreamHub>,
+}
+
+impl FromRef<AppState> for Auth {
+    fn from_ref(state: &AppState) -> Self {
+        state.auth.clone()
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum EventRequest {
     #[serde(rename = "trigger")]
@@ -57,7 +168,7 @@ pub enum EventRequest {
 }
 
 // WebSocket message types
-#[derive(Serialize)]
+#[derive(Clone, Serialize, ToSchema)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ServerMessage {
     #[serde(rename = "hello")]
@@ -65,6 +176,11 @@ pub enum ServerMessage {
         version: String,
         payload: HelloPayload,
     },
+    #[serde(rename = "negotiated")]
+    Negotiated {
+        version: String,
+        payload: Negotiated,
+    },
     #[serde(rename = "snapshot")]
     Snapshot {
         version: String,
@@ -80,53 +196,224 @@ pub enum ServerMessage {
         version: String,
         payload: ErrorPayload,
     },
+    #[serde(rename = "config_reloaded")]
+    ConfigReloaded {
+        version: String,
+        payload: ConfigReloadedPayload,
+    },
+    #[serde(rename = "alert")]
+    Alert {
+        version: String,
+        payload: AlertPayload,
+    },
+    #[serde(rename = "sparkle")]
+    Sparkle {
+        version: String,
+        payload: SparklePayload,
+    },
+    #[serde(rename = "beat")]
+    Beat {
+        version: String,
+        payload: BeatPayload,
+    },
+    #[serde(rename = "mood_changed")]
+    MoodChanged {
+        version: String,
+        payload: MoodChangedPayload,
+    },
+    #[serde(rename = "subscribed")]
+    Subscribed {
+        version: String,
+        payload: SubscribedPayload,
+    },
+    #[serde(rename = "resumed")]
+    Resumed {
+        version: String,
+        payload: ResumedPayload,
+    },
+    #[serde(rename = "calibration")]
+    Calibration {
+        version: String,
+        payload: CalibrationPayload,
+    },
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize, ToSchema)]
 pub struct HelloPayload {
     pub session_id: String,
     pub schema_version: String,
+    pub supported_versions: Vec<String>,
     pub tick_rate_hz: f64,
+    pub snapshot_rate_hz: f64,
+    pub capabilities: Vec<String>,
+    /// Snapshot encodings a client may ask for; JSON unless it does.
+    pub encodings: Vec<String>,
+    pub message_types: MessageTypes,
+    /// Clients must send a token in their hello before receiving snapshots
+    /// unless the upgrade request carried a valid bearer token.
+    pub auth_required: bool,
+    /// Sent in a later connection's hello to pick this session back up.
+    pub resume_token: String,
+    /// Seed of the server's noise field (`ambient_core::noise`), the same
+    /// for every session so visuals sampling it stay in step.
+    pub noise_seed: u32,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize, ToSchema)]
+pub struct MessageTypes {
+    pub server: Vec<String>,
+    pub client: Vec<String>,
+}
+
+/// Parts the session did not subscribe to are left out.
+#[derive(Clone, Serialize, ToSchema)]
 pub struct SnapshotPayload {
-    pub world: WorldSnapshot,
-    pub audio: AudioParamsSnapshot,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub world: Option<WireSnapshot>,
+    /// World tick rate in effect, sent alongside `world`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tick_rate_hz: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioParamsSnapshot>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub analysis: Option<AnalysisSnapshot>,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize, ToSchema)]
 pub struct EventAckPayload {
     pub request_id: Option<String>,
     pub action: String,
     pub intensity: Option<f64>,
+    /// Whether it took effect, what was clamped, and the world right after.
+    #[serde(flatten)]
+    pub result: WireApplyResult,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize, ToSchema)]
 pub struct ErrorPayload {
-    pub code: String,
+    pub code: ErrorCode,
     pub message: String,
     pub request_id: Option<String>,
 }
 
-#[derive(Deserialize)]
+/// A sparkle the world fired, sent as it happens so visuals can flash with
+/// the audio.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct SparklePayload {
+    /// Wall-clock time of the tick that fired it.
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub sparkle: SparkleEvent,
+}
+
+/// A beat of the world's pulse grid.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct BeatPayload {
+    /// Wall-clock time of the tick it fell in.
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub beat: BeatEvent,
+}
+
+/// The world's mood changed.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct MoodChangedPayload {
+    /// Wall-clock time of the tick it changed in.
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub change: MoodChange,
+}
+
+/// Confirms a subscribe request with the subscription now in effect.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct SubscribedPayload {
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub subscription: Subscription,
+}
+
+/// Confirms a resumed session. Missed broadcasts follow.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct ResumedPayload {
+    pub session_id: String,
+    /// Token for resuming again; the same one the session started with.
+    pub resume_token: String,
+    /// The world now, in the negotiated schema.
+    pub world: WireSnapshot,
+}
+
+/// Answers a calibrate request with where the calibration now stands.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct CalibrationPayload {
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub status: CalibrationStatus,
+}
+
+/// Sent when the config file changes and live-tunable settings were applied.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct ConfigReloadedPayload {
+    /// Keys whose new values are now in effect.
+    pub applied: Vec<String>,
+    /// Keys that changed but only take effect after a restart.
+    pub requires_restart: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct PerformPayload {
     pub request_id: Option<String>,
     pub action: PerformAction,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+pub struct SubscribePayload {
+    pub request_id: Option<String>,
+    /// Channels to stream; every channel but `field` when omitted.
+    #[serde(default = "default_channels")]
+    pub channels: Vec<Channel>,
+    /// Fields to keep per snapshot channel.
+    #[serde(default)]
+    pub fields: BTreeMap<Channel, Vec<String>>,
+}
+
+fn default_channels() -> Vec<Channel> {
+    Channel::defaults()
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct SetScenePayload {
     pub request_id: Option<String>,
     pub scene_name: String,
 }
 
-#[derive(Deserialize)]
+/// Measures the next point of a manual calibration.
+#[derive(Deserialize, ToSchema)]
+pub struct CalibratePayload {
+    pub request_id: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ClientHelloPayload {
+    pub schema_version: String,
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// API token, for clients that cannot set an Authorization header.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Snapshot encoding: json (default), msgpack or cbor.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Token from an earlier session's hello, to resume that session.
+    #[serde(default)]
+    pub resume_token: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct PingPayload {
     pub timestamp: f64,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize, ToSchema)]
 pub struct AudioParamsSnapshot {
     pub master_gain: f32,
     pub base_freq_hz: f32,
@@ -135,12 +422,597 @@ pub struct AudioParamsSnapshot {
     pub motion: f32,
     pub texture: f32,
     pub sparkle_impulse: f32,
+    /// Pulse tempo the delay is locked to, 0 when it follows rhythm.
+    pub pulse_bpm: f32,
+    /// Reverb size (0 = a small room, 1 = a cavern), from space.
+    pub reverb_size: f32,
+    pub pre_delay_ms: f32,
+    /// Drone saturation and texture roughness (0 = clean, 1 = gritty), from
+    /// low clarity.
+    pub grit: f32,
+    pub layers: AudioLayersSnapshot,
+}
+
+/// How much of each layer is in the mix (0 = off, 1 = full).
+#[derive(Clone, Serialize, ToSchema)]
+pub struct AudioLayersSnapshot {
+    pub drone: f32,
+    pub texture: f32,
+    pub sparkle: f32,
+    pub samples: f32,
+    pub grains: f32,
+    pub wind: f32,
+}
+
+impl From<AudioParams> for AudioParamsSnapshot {
+    fn from(params: AudioParams) -> Self {
+        Self {
+            master_gain: params.master_gain,
+            base_freq_hz: params.base_freq_hz,
+            detune_ratio: params.detune_ratio,
+            brightness: params.brightness,
+            motion: params.motion,
+            texture: params.texture,
+            sparkle_impulse: params.sparkle_impulse,
+            pulse_bpm: params.pulse_bpm,
+            reverb_size: params.reverb_size,
+            pre_delay_ms: params.pre_delay_ms,
+            grit: params.grit,
+            layers: params.layers.into(),
+        }
+    }
+}
+
+impl From<LayerGains> for AudioLayersSnapshot {
+    fn from(gains: LayerGains) -> Self {
+        Self {
+            drone: gains.drone,
+            texture: gains.texture,
+            sparkle: gains.sparkle,
+            samples: gains.samples,
+            grains: gains.grains,
+            wind: gains.wind,
+        }
+    }
+}
+
+impl From<LayerAmounts> for AudioLayersSnapshot {
+    fn from(layers: LayerAmounts) -> Self {
+        Self {
+            drone: layers.drone,
+            texture: layers.texture,
+            sparkle: layers.sparkle,
+            samples: layers.samples,
+            grains: layers.grains,
+            wind: layers.wind,
+        }
+    }
+}
+
+/// A layer amount given either as on/off or as a level in [0, 1].
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum LayerAmount {
+    Enabled(bool),
+    Level(f32),
+}
+
+impl LayerAmount {
+    fn level(&self) -> f32 {
+        match self {
+            LayerAmount::Enabled(true) => 1.0,
+            LayerAmount::Enabled(false) => 0.0,
+            LayerAmount::Level(level) => *level,
+        }
+    }
+}
+
+/// Body of `POST /audio/layers`. Omitted layers keep their current amount.
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LayersRequest {
+    pub drone: Option<LayerAmount>,
+    pub texture: Option<LayerAmount>,
+    pub sparkle: Option<LayerAmount>,
+    pub samples: Option<LayerAmount>,
+    pub grains: Option<LayerAmount>,
+    pub wind: Option<LayerAmount>,
+}
+
+impl LayersRequest {
+    /// Applies the requested changes on top of `current`.
+    fn apply(&self, current: LayerAmounts) -> Result<LayerAmounts, String> {
+        let mut layers = current;
+        for (name, requested, value) in [
+            ("drone", &self.drone, &mut layers.drone),
+            ("texture", &self.texture, &mut layers.texture),
+            ("sparkle", &self.sparkle, &mut layers.sparkle),
+            ("samples", &self.samples, &mut layers.samples),
+            ("grains", &self.grains, &mut layers.grains),
+            ("wind", &self.wind, &mut layers.wind),
+        ] {
+            if let Some(requested) = requested {
+                let level = requested.level();
+                if !(0.0..=1.0).contains(&level) {
+                    return Err(format!(
+                        "{} must be a boolean or between 0.0 and 1.0, got {}",
+                        name, level
+                    ));
+                }
+                *value = level;
+            }
+        }
+        Ok(layers)
+    }
+}
+
+/// Most ticks a single `POST /world/step` may advance.
+const MAX_STEP_TICKS: u32 = 10_000;
+
+/// Body of `POST /world/step`.
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StepRequest {
+    #[serde(default = "default_step_ticks")]
+    pub ticks: u32,
+}
+
+fn default_step_ticks() -> u32 {
+    1
+}
+
+/// Body of `POST /world/time_scale`.
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TimeScaleRequest {
+    pub time_scale: f64,
+}
+
+/// Body of `POST /admin/log_level`. Subsystem levels merge into the current
+/// ones; `null` removes one.
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LogLevelRequest {
+    pub level: Option<String>,
+    #[serde(default)]
+    pub targets: BTreeMap<String, Option<String>>,
+}
+
+/// Body of `POST /admin/tick_rate`.
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TickRateRequest {
+    pub tick_hz: f64,
+}
+
+/// Response of `POST /admin/tick_rate`.
+#[derive(Serialize, ToSchema)]
+pub struct TickRateResponse {
+    pub previous_hz: f64,
+    pub tick_hz: f64,
+}
+
+/// Longest ramp a preset recall or morph may take.
+const MAX_RECALL_RAMP_SECS: f64 = 600.0;
+
+/// Query of `GET /state/stats`.
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct StatsQuery {
+    /// Trailing window such as `15m` or `2h`; defaults to an hour.
+    pub window: Option<String>,
+}
+
+/// Most points `GET /audio/waveform` returns.
+const MAX_WAVEFORM_POINTS: usize = 4000;
+
+/// Query of `GET /audio/waveform`.
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct WaveformQuery {
+    /// Seconds of recent output to cover.
+    #[serde(default = "default_waveform_seconds")]
+    pub seconds: f64,
+    /// Min/max pairs to reduce them to.
+    #[serde(default = "default_waveform_points")]
+    pub points: usize,
+}
+
+fn default_waveform_seconds() -> f64 {
+    60.0
+}
+
+fn default_waveform_points() -> usize {
+    600
+}
+
+/// Response of `GET /audio/waveform`: the seconds actually covered (less
+/// than asked for early on) and the envelope, oldest first.
+#[derive(Serialize, ToSchema)]
+pub struct WaveformResponse {
+    pub seconds: f64,
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+impl From<Envelope> for WaveformResponse {
+    fn from(envelope: Envelope) -> Self {
+        Self {
+            seconds: envelope.secs,
+            min: envelope.min,
+            max: envelope.max,
+        }
+    }
+}
+
+/// Query of `POST /presets/{name}/recall`.
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct RecallQuery {
+    /// Seconds of simulated time to morph over; 0 jumps straight there.
+    #[serde(default)]
+    pub ramp_seconds: f64,
+}
+
+/// Query of `GET /state/diff`: the preset or the scene to compare against.
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct DiffQuery {
+    pub preset: Option<String>,
+    pub scene: Option<String>,
+}
+
+/// Query of `POST /state/morph`.
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct MorphQuery {
+    /// The preset or the scene to move toward.
+    pub preset: Option<String>,
+    pub scene: Option<String>,
+    /// Share of the way to move, above 0 and at most 1.
+    pub fraction: f64,
+    /// Seconds of simulated time to move over; 0 jumps straight there.
+    #[serde(default)]
+    pub ramp_seconds: f64,
+}
+
+/// A preset or scene the live world is compared against or morphed toward.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "kind", content = "name", rename_all = "snake_case")]
+pub enum Reference {
+    Preset(String),
+    Scene(String),
+}
+
+/// Response of `GET /state/diff`.
+#[derive(Serialize, ToSchema)]
+pub struct DiffResponse {
+    pub reference: Reference,
+    #[serde(flatten)]
+    pub diff: PresetDiff,
+}
+
+/// Query of `POST /event`.
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub struct EventQuery {
+    /// Wait for the world task to apply the event and respond with the
+    /// result; when false, respond 202 as soon as the event is queued.
+    #[serde(default = "default_wait_for_apply")]
+    pub wait_for_apply: bool,
+}
+
+fn default_wait_for_apply() -> bool {
+    true
+}
+
+/// Body of `POST /audio/effects`.
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EffectToggleRequest {
+    /// `drone`, `sparkle` or `master`.
+    pub bus: String,
+    pub effect: String,
+    pub enabled: bool,
 }
 
-#[derive(Deserialize)]
+/// One member of an effect chain.
+#[derive(Serialize, ToSchema)]
+pub struct EffectStatus {
+    pub effect: &'static str,
+    pub enabled: bool,
+}
+
+/// Response of the `/audio/stack` endpoints: the layers playing, in the
+/// order they were added.
+#[derive(Serialize, ToSchema)]
+pub struct StackResponse {
+    pub layers: Vec<LayerConfig>,
+}
+
+impl From<&LayerRegistry> for StackResponse {
+    fn from(registry: &LayerRegistry) -> Self {
+        let samples = registry.samples();
+        Self {
+            layers: registry
+                .stack()
+                .iter()
+                .map(|spec| LayerConfig::from_spec(spec, samples))
+                .collect(),
+        }
+    }
+}
+
+/// Response of `GET /audio/effects` and `POST /audio/effects`: each bus's
+/// chain in processing order.
+#[derive(Serialize, ToSchema)]
+pub struct EffectsResponse {
+    pub drone: Vec<EffectStatus>,
+    pub sparkle: Vec<EffectStatus>,
+    pub master: Vec<EffectStatus>,
+}
+
+impl From<&SharedEffects> for EffectsResponse {
+    fn from(effects: &SharedEffects) -> Self {
+        let chains = effects.chains();
+        let chain = |bus: EffectBus| {
+            chains
+                .get(bus)
+                .iter()
+                .enumerate()
+                .map(|(i, kind)| EffectStatus {
+                    effect: kind.name(),
+                    enabled: effects.is_enabled(bus, i),
+                })
+                .collect()
+        };
+        Self {
+            drone: chain(EffectBus::Drone),
+            sparkle: chain(EffectBus::Sparkle),
+            master: chain(EffectBus::Master),
+        }
+    }
+}
+
+/// Body of `POST /audio/mapping`.
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MappingRequest {
+    pub profile: String,
+}
+
+/// Response of `GET /audio/mapping` and `POST /audio/mapping`.
+#[derive(Serialize, ToSchema)]
+pub struct MappingResponse {
+    /// Profile in use.
+    pub profile: String,
+    /// Every selectable profile, built-in and from the config.
+    pub available: Vec<String>,
+}
+
+/// Default and longest duration of a manual override.
+const DEFAULT_OVERRIDE_SECS: f64 = 60.0;
+const MAX_OVERRIDE_SECS: f64 = 3600.0;
+
+/// Body of `POST /audio/override`. Omitted fields stay world-driven.
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct OverrideRequest {
+    pub master_gain: Option<f32>,
+    pub base_freq_hz: Option<f32>,
+    pub detune_ratio: Option<f32>,
+    pub brightness: Option<f32>,
+    pub motion: Option<f32>,
+    pub texture: Option<f32>,
+    pub sparkle_impulse: Option<f32>,
+    pub reverb_size: Option<f32>,
+    pub pre_delay_ms: Option<f32>,
+    pub grit: Option<f32>,
+    /// How long the override lasts before params follow the world again.
+    #[serde(default = "default_override_secs")]
+    pub duration_secs: f64,
+}
+
+fn default_override_secs() -> f64 {
+    DEFAULT_OVERRIDE_SECS
+}
+
+impl OverrideRequest {
+    fn overrides(&self) -> Result<ParamOverrides, String> {
+        if !(self.duration_secs > 0.0 && self.duration_secs <= MAX_OVERRIDE_SECS) {
+            return Err(format!(
+                "duration_secs must be in (0, {}], got {}",
+                MAX_OVERRIDE_SECS, self.duration_secs
+            ));
+        }
+        for (name, value, min, max) in [
+            ("master_gain", self.master_gain, 0.0, 1.0),
+            ("base_freq_hz", self.base_freq_hz, 20.0, 20_000.0),
+            ("detune_ratio", self.detune_ratio, 0.5, 2.0),
+            ("brightness", self.brightness, 0.0, 1.0),
+            ("motion", self.motion, 0.0, 1.0),
+            ("texture", self.texture, 0.0, 1.0),
+            ("sparkle_impulse", self.sparkle_impulse, 0.0, 1.0),
+            ("reverb_size", self.reverb_size, 0.0, 1.0),
+            ("pre_delay_ms", self.pre_delay_ms, 0.0, MAX_PRE_DELAY_MS),
+            ("grit", self.grit, 0.0, 1.0),
+        ] {
+            if let Some(value) = value
+                && !(min..=max).contains(&value)
+            {
+                return Err(format!(
+                    "{} must be in [{}, {}], got {}",
+                    name, min, max, value
+                ));
+            }
+        }
+        let overrides = ParamOverrides {
+            master_gain: self.master_gain,
+            base_freq_hz: self.base_freq_hz,
+            detune_ratio: self.detune_ratio,
+            brightness: self.brightness,
+            motion: self.motion,
+            texture: self.texture,
+            sparkle_impulse: self.sparkle_impulse,
+            reverb_size: self.reverb_size,
+            pre_delay_ms: self.pre_delay_ms,
+            grit: self.grit,
+        };
+        if overrides.fields().is_empty() {
+            return Err("no audio params to override".to_string());
+        }
+        Ok(overrides)
+    }
+}
+
+/// Response of `POST /audio/override`.
+#[derive(Serialize, ToSchema)]
+pub struct OverrideResponse {
+    pub fields: Vec<&'static str>,
+    pub expires_in_secs: f64,
+}
+
+/// Response of `GET /audio/params`: the mapped parameters plus the mixer's
+/// configured layer gains.
+#[derive(Serialize, ToSchema)]
+pub struct AudioParamsResponse {
+    #[serde(flatten)]
+    pub params: AudioParamsSnapshot,
+    /// `audio.*_gain` from the config, applied on top of `layers`.
+    pub gains: AudioLayersSnapshot,
+}
+
+/// Response of `GET /metrics`.
+#[derive(Serialize, ToSchema)]
+pub struct MetricsResponse {
+    pub event_queue: EventQueueMetrics,
+    /// Active anomaly alerts and how many have been raised.
+    pub alerts: AlertStatus,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EventQueueMetrics {
+    pub capacity: usize,
+    /// Events waiting for the world task.
+    pub depth: usize,
+    /// Client events turned away with 503 / `BUSY` because the queue was full.
+    pub rejected: u64,
+    /// Ticks folded into a later tick because the queue was full.
+    pub coalesced_ticks: u64,
+}
+
+/// Response of `GET /audio/status`.
+#[derive(Serialize, ToSchema)]
+pub struct AudioStatusResponse {
+    /// One of `disabled`, `starting`, `running`, `restarting`.
+    pub state: &'static str,
+    pub device: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    pub callback: CallbackMetrics,
+    pub layers: LayerLevelsSnapshot,
+}
+
+/// How hard the audio callback is working.
+#[derive(Serialize, ToSchema)]
+pub struct CallbackMetrics {
+    /// Callbacks run since startup.
+    pub callbacks: u64,
+    /// Recent callbacks the load figures cover.
+    pub window: usize,
+    /// Fraction of the buffer deadline spent rendering: the latest callback,
+    /// and the mean and peak over the window. Above 1.0 the buffer was late.
+    pub load_last: f32,
+    pub load_mean: f32,
+    pub load_peak: f32,
+    /// Callbacks that overran their deadline, since startup.
+    pub deadline_misses: u64,
+    /// Underruns reported by the output device, since startup.
+    pub xruns: u64,
+}
+
+impl From<CallbackStats> for CallbackMetrics {
+    fn from(stats: CallbackStats) -> Self {
+        Self {
+            callbacks: stats.callbacks,
+            window: stats.window,
+            load_last: stats.load_last,
+            load_mean: stats.load_mean,
+            load_peak: stats.load_peak,
+            deadline_misses: stats.deadline_misses,
+            xruns: stats.xruns,
+        }
+    }
+}
+
+/// Peak and RMS of one layer in dBFS, measured after its gain, bus effects
+/// and ducking but before it is placed and mixed.
+#[derive(Clone, Copy, Default, Serialize, ToSchema)]
+pub struct LayerLevel {
+    pub peak_db: f32,
+    pub rms_db: f32,
+}
+
+impl From<LevelReading> for LayerLevel {
+    fn from(reading: LevelReading) -> Self {
+        Self {
+            peak_db: reading.peak_db,
+            rms_db: reading.rms_db,
+        }
+    }
+}
+
+/// Level of each layer. Layers not in the stack read the meter floor.
+#[derive(Clone, Default, Serialize, ToSchema)]
+pub struct LayerLevelsSnapshot {
+    pub drone: LayerLevel,
+    pub texture: LayerLevel,
+    pub sparkle: LayerLevel,
+    pub samples: LayerLevel,
+    pub grains: LayerLevel,
+    pub wind: LayerLevel,
+}
+
+impl From<[LevelReading; LayerSlot::ALL.len()]> for LayerLevelsSnapshot {
+    fn from(levels: [LevelReading; LayerSlot::ALL.len()]) -> Self {
+        let level = |slot: LayerSlot| levels[slot.index()].into();
+        Self {
+            drone: level(LayerSlot::Drone),
+            texture: level(LayerSlot::Texture),
+            sparkle: level(LayerSlot::Sparkle),
+            samples: level(LayerSlot::Samples),
+            grains: level(LayerSlot::Grains),
+            wind: level(LayerSlot::Wind),
+        }
+    }
+}
+
+/// Output metering from the master bus, and the callback's load.
+#[derive(Clone, Default, Serialize, ToSchema)]
+pub struct AnalysisSnapshot {
+    pub peak_db: f32,
+    pub gain_reduction_db: f32,
+    pub momentary_lufs: f32,
+    pub integrated_lufs: f32,
+    pub auto_gain_db: f32,
+    /// Mean and peak deadline fraction over recent callbacks.
+    pub callback_load: f32,
+    pub callback_load_peak: f32,
+    /// Underruns since startup.
+    pub xruns: u64,
+    pub layers: LayerLevelsSnapshot,
+}
+
+#[derive(Deserialize, ToSchema)]
 #[serde(tag = "type", rename_all = "lowercase")]
-#[allow(unused)]
 pub enum ClientMessage {
+    #[serde(rename = "hello")]
+    Hello {
+        version: String,
+        payload: ClientHelloPayload,
+    },
     #[serde(rename = "perform")]
     Perform {
         version: String,
@@ -156,23 +1028,55 @@ pub enum ClientMessage {
         version: String,
         payload: SetScenePayload,
     },
+    #[serde(rename = "subscribe")]
+    Subscribe {
+        version: String,
+        payload: SubscribePayload,
+    },
+    #[serde(rename = "calibrate")]
+    Calibrate {
+        version: String,
+        payload: CalibratePayload,
+    },
+}
+
+impl ClientMessage {
+    /// Client-chosen request id, for messages that carry one.
+    pub fn request_id(&self) -> Option<String> {
+        match self {
+            ClientMessage::Perform { payload, .. } => payload.request_id.clone(),
+            ClientMessage::SetScene { payload, .. } => payload.request_id.clone(),
+            ClientMessage::Subscribe { payload, .. } => payload.request_id.clone(),
+            ClientMessage::Calibrate { payload, .. } => payload.request_id.clone(),
+            ClientMessage::Hello { .. } | ClientMessage::Ping { .. } => None,
+        }
+    }
+
+    /// Envelope version the client sent.
+    pub fn version(&self) -> &str {
+        match self {
+            ClientMessage::Hello { version, .. }
+            | ClientMessage::Perform { version, .. }
+            | ClientMessage::Ping { version, .. }
+            | ClientMessage::SetScene { version, .. }
+            | ClientMessage::Subscribe { version, .. }
+            | ClientMessage::Calibrate { version, .. } => version,
+        }
+    }
 }
 
 /// Validates a PerformAction and returns an error message if invalid
-fn validate_perform_action(action: &PerformAction) -> Result<(), String> {
+pub(crate) fn validate_perform_action(action: &PerformAction) -> Result<(), String> {
     match action {
         PerformAction::Pulse { intensity }
         | PerformAction::Stir { intensity }
         | PerformAction::Calm { intensity }
         | PerformAction::Heat { intensity }
-        | PerformAction::Tense { intensity } => {
-            if *intensity < 0.0 || *intensity > 1.0 {
-                return Err(format!(
-                    "Intensity must be between 0.0 and 1.0, got {}",
-                    intensity
-                ));
-            }
-        }
+        | PerformAction::Tense { intensity }
+        | PerformAction::Expand { intensity }
+        | PerformAction::Contract { intensity }
+        | PerformAction::Clarify { intensity }
+        | PerformAction::Roughen { intensity } => validate_intensity(*intensity)?,
         PerformAction::Scene { name } => {
             if name.trim().is_empty() {
                 return Err("Scene name cannot be empty".to_string());
@@ -195,11 +1099,34 @@ fn validate_perform_action(action: &PerformAction) -> Result<(), String> {
                 ));
             }
         }
+        PerformAction::Undo => {}
+        PerformAction::PulseAt { x, y, intensity } => {
+            if !(0.0..=1.0).contains(x) || !(0.0..=1.0).contains(y) {
+                return Err(format!(
+                    "Pulse position must be within the unit square, got ({}, {})",
+                    x, y
+                ));
+            }
+            validate_intensity(*intensity)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks an action or trigger intensity is within [`INTENSITY_RANGE`].
+fn validate_intensity(intensity: f64) -> Result<(), String> {
+    if !INTENSITY_RANGE.contains(&intensity) {
+        return Err(format!(
+            "Intensity must be between {:.1} and {:.1}, got {}",
+            INTENSITY_RANGE.start(),
+            INTENSITY_RANGE.end(),
+            intensity
+        ));
     }
     Ok(())
 }
 
-fn default_intensity() -> f64 {
+pub(crate) fn default_intensity() -> f64 {
     0.5
 }
 
@@ -211,299 +1138,2040 @@ fn get_action_info(action: &PerformAction) -> (&str, Option<f64>) {
         PerformAction::Calm { intensity } => ("Calm", Some(*intensity)),
         PerformAction::Heat { intensity } => ("Heat", Some(*intensity)),
         PerformAction::Tense { intensity } => ("Tense", Some(*intensity)),
+        PerformAction::Expand { intensity } => ("Expand", Some(*intensity)),
+        PerformAction::Contract { intensity } => ("Contract", Some(*intensity)),
+        PerformAction::Clarify { intensity } => ("Clarify", Some(*intensity)),
+        PerformAction::Roughen { intensity } => ("Roughen", Some(*intensity)),
         PerformAction::Scene { .. } => ("Scene", None),
         PerformAction::Freeze { .. } => ("Freeze", None),
+        PerformAction::Undo => ("Undo", None),
+        PerformAction::PulseAt { intensity, .. } => ("PulseAt", Some(*intensity)),
     }
 }
 
-pub fn create_router(
-    event_tx: mpsc::Sender<Event>,
-    current_snapshot: Arc<RwLock<WorldSnapshot>>,
-    world_state_rx: watch::Receiver<WorldSnapshot>,
-    audio_params_rx: watch::Receiver<AudioParams>,
-) -> Router {
-    let state = AppState {
-        event_tx,
-        current_snapshot,
-        world_state_rx,
-        audio_params_rx,
-    };
+/// Browser origins allowed by `api.cors_origins`; `"*"` (the default, for the
+/// dev UI on localhost:5173) allows any. Entries are checked by config validation.
+fn cors_origins(origins: &[String]) -> AllowOrigin {
+    if origins.iter().any(|origin| origin == "*") {
+        return AllowOrigin::any();
+    }
+    AllowOrigin::list(
+        origins
+            .iter()
+            .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+    )
+}
 
-    // Configure CORS for development (allows UI on localhost:5173)
+pub fn create_router(state: AppState, allowed_origins: &[String]) -> Router {
     let cors = CorsLayer::new()
-        .allow_origin(Any) // Allow any origin for development
-        .allow_methods([Method::GET, Method::POST])
-        .allow_headers(Any);
+        .allow_origin(cors_origins(allowed_origins))
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers(Any)
+        .expose_headers([REQUEST_ID_HEADER]);
 
-    Router::new()
+    let router = Router::new()
+        .route("/", get(web::index))
+        .route("/ui/{*path}", get(web::asset))
         .route("/health", get(health))
         .route("/state", get(get_state))
+        .route("/state/stats", get(get_state_stats))
+        .route("/state/diff", get(get_state_diff))
+        .route("/state/morph", post(morph_state))
+        .route("/world/clock", get(get_world_clock))
+        .route("/world/pause", post(pause_world))
+        .route("/world/resume", post(resume_world))
+        .route("/world/step", post(step_world))
+        .route("/world/time_scale", post(set_time_scale))
+        .route("/presets", get(list_presets))
+        .route(
+            "/presets/{name}",
+            post(capture_preset).delete(delete_preset),
+        )
+        .route("/presets/{name}/recall", post(recall_preset))
+        .route("/metrics", get(get_metrics))
         .route("/event", post(event))
+        .route("/audio/params", get(get_audio_params))
+        .route("/audio/layers", post(set_audio_layers))
+        .route(
+            "/audio/effects",
+            get(get_audio_effects).post(set_audio_effect),
+        )
+        .route(
+            "/audio/mapping",
+            get(get_audio_mapping).post(set_audio_mapping),
+        )
+        .route(
+            "/audio/override",
+            post(set_audio_override).delete(clear_audio_override),
+        )
+        .route(
+            "/audio/stack",
+            get(get_audio_stack)
+                .post(add_stack_layer)
+                .put(replace_stack_layer),
+        )
+        .route("/audio/stack/{layer}", delete(remove_stack_layer))
+        .route("/audio/patch", get(get_audio_patch).put(play_audio_patch))
+        .route("/audio/patches", get(list_audio_patches))
+        .route("/audio/patches/{name}/load", post(load_audio_patch))
+        .route(
+            "/audio/calibration",
+            get(get_calibration)
+                .post(start_calibration)
+                .delete(cancel_calibration),
+        )
+        .route("/audio/calibration/step", post(step_calibration))
+        .route("/audio/status", get(get_audio_status))
+        .route("/audio/waveform", get(get_audio_waveform))
+        .route("/record/save", post(save_recording))
+        .route("/admin/log_level", get(get_log_level).post(set_log_level))
+        .route("/admin/tick_rate", post(set_tick_rate))
+        .route(WS_SCHEMA_PATH, get(get_ws_schema))
         .route("/ws", get(websocket_handler))
+        .merge(SwaggerUi::new(DOCS_PATH).url(OPENAPI_PATH, ApiDoc::openapi()));
+    #[cfg(feature = "stream")]
+    let router = router.route("/stream.ogg", get(crate::stream::stream_handler));
+    router
         .with_state(state)
         .layer(cors)
+        // A span per request, the root of the event flow traces
+        .layer(TraceLayer::new_for_http().make_span_with(make_request_span))
+        // Outermost, so the span and every response carry the id
+        .layer(middleware::from_fn(assign_request_id))
 }
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "admin",
+    security(()),
+    responses(
+        (status = 200, description = "The server is up", body = String),
+    )
+)]
 async fn health() -> impl IntoResponse {
     "ok"
 }
 
 #[axum::debug_handler]
-async fn get_state(State(app_state): State<AppState>) -> impl IntoResponse {
-    let snapshot = app_state.current_snapshot.read().await.clone();
-    Json(snapshot)
+#[utoipa::path(
+    get,
+    path = "/state",
+    tag = "world",
+    params(SchemaVersionHeader),
+    responses(
+        (status = 200, description = "The world now, in the requested schema", body = WireSnapshot),
+        (status = 400, description = "Unsupported schema version", body = ErrorPayload),
+    )
+)]
+async fn get_state(
+    _: Principal,
+    version: SchemaVersion,
+    State(app_state): State<AppState>,
+) -> impl IntoResponse {
+    let snapshot = app_state.current_snapshot.read().await;
+    Json(WireSnapshot::new(&snapshot, version))
 }
 
-async fn event(
+/// Per-parameter min/max/mean/stddev and event counts over a trailing window.
+#[utoipa::path(
+    get,
+    path = "/state/stats",
+    tag = "world",
+    params(StatsQuery),
+    responses(
+        (status = 200, body = StatsSummary),
+        (status = 400, description = "Malformed window", body = ErrorPayload),
+    )
+)]
+async fn get_state_stats(
+    _: Principal,
     State(app_state): State<AppState>,
-    Json(req): Json<EventRequest>,
-) -> impl IntoResponse {
-    let event = match req {
-        EventRequest::Trigger { kind, intensity } => Event::Trigger { kind, intensity },
-        EventRequest::Perform(action) => Event::Perform(action),
-    };
+    ApiQuery(query): ApiQuery<StatsQuery>,
+) -> Result<Json<StatsSummary>, ApiError> {
+    let window =
+        parse_window(query.window.as_deref().unwrap_or("1h")).map_err(ApiError::Validation)?;
+    let command = |reply| WorldCommand::Stats(window, reply);
+    Ok(Json(
+        world_command(&app_state.world_command_tx, command).await?,
+    ))
+}
 
-    match app_state.event_tx.send(event).await {
-        Ok(_) => (StatusCode::OK, "Event sent").into_response(),
-        Err(_) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to send event: channel closed",
-        )
-            .into_response(),
-    }
+/// Simulation clock and actual vs nominal tick rate.
+#[utoipa::path(
+    get,
+    path = "/world/clock",
+    tag = "world",
+    responses(
+        (status = 200, body = TickStats),
+    )
+)]
+async fn get_world_clock(_: Principal, State(app_state): State<AppState>) -> impl IntoResponse {
+    Json(*app_state.tick_stats_rx.borrow())
 }
 
-async fn websocket_handler(
-    ws: WebSocketUpgrade,
-    State(state): State<AppState>,
-) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_websocket(socket, state))
+/// Sends a command to the world task and waits for its reply.
+///
+/// Fails if the world task has gone away.
+async fn world_command<T>(
+    command_tx: &mpsc::Sender<WorldCommand>,
+    command: impl FnOnce(oneshot::Sender<T>) -> WorldCommand,
+) -> Result<T, ApiError> {
+    let (reply_tx, reply_rx) = oneshot::channel();
+    command_tx
+        .send(command(reply_tx))
+        .await
+        .map_err(|_| ApiError::world_stopped())?;
+    reply_rx.await.map_err(|_| ApiError::world_stopped())
 }
 
-async fn handle_websocket(socket: WebSocket, state: AppState) {
-    let (mut sender, receiver) = socket.split();
-    let (tx, rx) = mpsc::unbounded_channel();
+async fn set_run_state(
+    principal: Principal,
+    version: SchemaVersion,
+    app_state: AppState,
+    run_state: RunState,
+) -> Result<Json<WireSnapshot>, ApiError> {
+    principal.require(Role::Controller)?;
+    let command = |reply| WorldCommand::SetRunState(run_state, reply);
+    let snapshot = world_command(&app_state.world_command_tx, command).await?;
+    Ok(Json(WireSnapshot::new(&snapshot, version)))
+}
 
-    // Generate session ID
-    let session_id = format!(
-        "ws-{}",
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis()
-    );
+/// Stops the world from advancing on ticks; responds with the snapshot.
+#[utoipa::path(
+    post,
+    path = "/world/pause",
+    tag = "world",
+    params(SchemaVersionHeader),
+    responses(
+        (status = 200, body = WireSnapshot),
+    )
+)]
+async fn pause_world(
+    principal: Principal,
+    version: SchemaVersion,
+    State(app_state): State<AppState>,
+) -> Result<Json<WireSnapshot>, ApiError> {
+    set_run_state(principal, version, app_state, RunState::Paused).await
+}
 
-    // Send hello message immediately
-    let hello = ServerMessage::Hello {
-        version: "1.0".to_string(),
-        payload: HelloPayload {
-            session_id: session_id.clone(),
-            schema_version: "1.0".to_string(),
-            tick_rate_hz: 20.0, // From main.rs default
+/// Lets ticks advance the world again; responds with the snapshot.
+#[utoipa::path(
+    post,
+    path = "/world/resume",
+    tag = "world",
+    params(SchemaVersionHeader),
+    responses(
+        (status = 200, body = WireSnapshot),
+    )
+)]
+async fn resume_world(
+    principal: Principal,
+    version: SchemaVersion,
+    State(app_state): State<AppState>,
+) -> Result<Json<WireSnapshot>, ApiError> {
+    set_run_state(principal, version, app_state, RunState::Running).await
+}
+
+/// Advances the world a number of fixed steps, paused or not.
+#[utoipa::path(
+    post,
+    path = "/world/step",
+    tag = "world",
+    params(SchemaVersionHeader),
+    request_body = StepRequest,
+    responses(
+        (status = 200, body = WireApplyResult),
+        (status = 400, description = "Out-of-range tick count", body = ErrorPayload),
+    )
+)]
+async fn step_world(
+    principal: Principal,
+    version: SchemaVersion,
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<StepRequest>,
+) -> Result<Json<WireApplyResult>, ApiError> {
+    principal.require(Role::Controller)?;
+    if !(1..=MAX_STEP_TICKS).contains(&req.ticks) {
+        return Err(ApiError::Validation(format!(
+            "ticks must be in [1, {}], got {}",
+            MAX_STEP_TICKS, req.ticks
+        )));
+    }
+    let command = |reply| WorldCommand::Step(req.ticks, reply);
+    let result = world_command(&app_state.world_command_tx, command).await?;
+    Ok(Json(WireApplyResult::new(&result, version)))
+}
+
+/// Fast-forwards or slows the world; responds with the snapshot.
+#[utoipa::path(
+    post,
+    path = "/world/time_scale",
+    tag = "world",
+    params(SchemaVersionHeader),
+    request_body = TimeScaleRequest,
+    responses(
+        (status = 200, body = WireSnapshot),
+        (status = 400, description = "Out-of-range time scale", body = ErrorPayload),
+    )
+)]
+async fn set_time_scale(
+    principal: Principal,
+    version: SchemaVersion,
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<TimeScaleRequest>,
+) -> Result<Json<WireSnapshot>, ApiError> {
+    principal.require(Role::Controller)?;
+    if !TIME_SCALE_RANGE.contains(&req.time_scale) {
+        return Err(ApiError::Validation(format!(
+            "time_scale must be in [{}, {}], got {}",
+            TIME_SCALE_RANGE.start(),
+            TIME_SCALE_RANGE.end(),
+            req.time_scale
+        )));
+    }
+    let command = |reply| WorldCommand::SetTimeScale(req.time_scale, Some(reply));
+    let snapshot = world_command(&app_state.world_command_tx, command).await?;
+    Ok(Json(WireSnapshot::new(&snapshot, version)))
+}
+
+/// The log filter in effect.
+#[utoipa::path(
+    get,
+    path = "/admin/log_level",
+    tag = "admin",
+    responses(
+        (status = 200, body = LogSettings),
+    )
+)]
+async fn get_log_level(_: Principal, State(app_state): State<AppState>) -> impl IntoResponse {
+    Json(app_state.log_control.current())
+}
+
+/// Changes the log filter without a restart; responds with the new settings.
+#[utoipa::path(
+    post,
+    path = "/admin/log_level",
+    tag = "admin",
+    request_body = LogLevelRequest,
+    responses(
+        (status = 200, body = LogSettings),
+        (status = 400, description = "Invalid level or unknown subsystem", body = ErrorPayload),
+    )
+)]
+async fn set_log_level(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<LogLevelRequest>,
+) -> Result<Json<LogSettings>, ApiError> {
+    principal.require(Role::Controller)?;
+    let current = app_state.log_control.current();
+    let level = req.level.unwrap_or(current.level);
+    let mut targets = current.targets;
+    for (name, target_level) in req.targets {
+        match target_level {
+            Some(target_level) => targets.insert(name, target_level),
+            None => targets.remove(&name),
+        };
+    }
+    let settings = LogSettings::new(level, targets)
+        .and_then(|settings| {
+            app_state.log_control.set(settings)?;
+            Ok(app_state.log_control.current())
+        })
+        .map_err(ApiError::Validation)?;
+    tracing::info!("Log filter set to {}", settings.filter);
+    Ok(Json(settings))
+}
+
+/// Retunes the world tick rate without a restart, advertising it to new
+/// WebSocket sessions and in snapshots, and records the change in the audit
+/// log. A later edit to `world.tick_hz` in the config file overrides it.
+#[utoipa::path(
+    post,
+    path = "/admin/tick_rate",
+    tag = "admin",
+    request_body = TickRateRequest,
+    responses(
+        (status = 200, body = TickRateResponse),
+        (status = 400, description = "Tick rate outside 1 to 1000 Hz", body = ErrorPayload),
+    )
+)]
+async fn set_tick_rate(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<TickRateRequest>,
+) -> Result<Json<TickRateResponse>, ApiError> {
+    principal.require(Role::Controller)?;
+    if !TICK_RATE_RANGE.contains(&req.tick_hz) {
+        return Err(ApiError::Validation(format!(
+            "tick_hz must be in [{}, {}], got {}",
+            TICK_RATE_RANGE.start(),
+            TICK_RATE_RANGE.end(),
+            req.tick_hz
+        )));
+    }
+    let previous_hz = app_state.tick_hz_tx.send_replace(req.tick_hz);
+    tracing::info!(
+        "Tick rate set to {:.2} Hz (was {:.2} Hz)",
+        req.tick_hz,
+        previous_hz
+    );
+    if let Some(audit_tx) = &app_state.audit_tx {
+        let record = AuditRecord {
+            timestamp_ms: unix_time_ms(),
+            tick: app_state.current_snapshot.read().await.tick(),
+            source: principal.name.map(EventSource::ApiKey),
+            request_id: crate::request_id::current(),
+            action: AuditAction::TickRate {
+                from_hz: previous_hz,
+                to_hz: req.tick_hz,
+            },
+        };
+        if audit_tx.try_send(record).is_err() {
+            tracing::warn!("Audit log backlog full, dropping record");
+        }
+    }
+    Ok(Json(TickRateResponse {
+        previous_hz,
+        tick_hz: req.tick_hz,
+    }))
+}
+
+/// Captured presets, keyed by name.
+#[utoipa::path(
+    get,
+    path = "/presets",
+    tag = "presets",
+    responses(
+        (status = 200, body = BTreeMap<String, StoredPreset>),
+    )
+)]
+async fn list_presets(_: Principal, State(app_state): State<AppState>) -> impl IntoResponse {
+    Json(app_state.presets.lock().await.presets().clone())
+}
+
+/// Captures the current world parameters and targets under `name`,
+/// replacing any preset of that name; responds with the stored preset.
+#[utoipa::path(
+    post,
+    path = "/presets/{name}",
+    tag = "presets",
+    params(("name" = String, Path, description = "Preset name")),
+    responses(
+        (status = 200, body = StoredPreset),
+        (status = 400, description = "Invalid preset name", body = ErrorPayload),
+    )
+)]
+async fn capture_preset(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<StoredPreset>, ApiError> {
+    principal.require(Role::Controller)?;
+    if !is_valid_name(&name) {
+        return Err(ApiError::Validation(format!(
+            "Preset name must be 1-64 letters, digits, '-' or '_', got '{}'",
+            name
+        )));
+    }
+    let preset = world_command(&app_state.world_command_tx, WorldCommand::CapturePreset).await?;
+    let stored = StoredPreset {
+        captured_at_ms: unix_time_ms(),
+        preset,
+    };
+    app_state
+        .presets
+        .lock()
+        .await
+        .insert(name, stored)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to save presets: {}", e)))?;
+    Ok(Json(stored))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/presets/{name}",
+    tag = "presets",
+    params(("name" = String, Path, description = "Preset name")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "Unknown preset", body = ErrorPayload),
+    )
+)]
+async fn delete_preset(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    principal.require(Role::Controller)?;
+    match app_state.presets.lock().await.remove(&name).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(unknown_preset(&name)),
+        Err(e) => Err(ApiError::Internal(format!("Failed to save presets: {}", e))),
+    }
+}
+
+fn unknown_preset(name: &str) -> ApiError {
+    ApiError::NotFound(format!("Unknown preset '{}'", name))
+}
+
+/// Morphs the world back to a captured preset over `ramp_seconds` of
+/// simulated time; responds with the result as applied.
+#[utoipa::path(
+    post,
+    path = "/presets/{name}/recall",
+    tag = "presets",
+    params(SchemaVersionHeader, ("name" = String, Path, description = "Preset name"), RecallQuery),
+    responses(
+        (status = 200, body = WireApplyResult),
+        (status = 400, description = "Out-of-range ramp", body = ErrorPayload),
+        (status = 404, description = "Unknown preset", body = ErrorPayload),
+    )
+)]
+async fn recall_preset(
+    principal: Principal,
+    version: SchemaVersion,
+    State(app_state): State<AppState>,
+    Path(name): Path<String>,
+    ApiQuery(query): ApiQuery<RecallQuery>,
+) -> Result<Json<WireApplyResult>, ApiError> {
+    principal.require(Role::Controller)?;
+    check_ramp(query.ramp_seconds)?;
+    let Some(stored) = app_state.presets.lock().await.get(&name).copied() else {
+        return Err(unknown_preset(&name));
+    };
+    let command = |reply| WorldCommand::RecallPreset(stored.preset, query.ramp_seconds, reply);
+    let result = world_command(&app_state.world_command_tx, command).await?;
+    Ok(Json(WireApplyResult::new(&result, version)))
+}
+
+fn check_ramp(ramp_seconds: f64) -> Result<(), ApiError> {
+    if !(0.0..=MAX_RECALL_RAMP_SECS).contains(&ramp_seconds) {
+        return Err(ApiError::Validation(format!(
+            "ramp_seconds must be in [0, {}], got {}",
+            MAX_RECALL_RAMP_SECS, ramp_seconds
+        )));
+    }
+    Ok(())
+}
+
+/// Looks up the preset or scene a query names and the world it describes.
+async fn resolve_reference(
+    app_state: &AppState,
+    preset: Option<String>,
+    scene: Option<String>,
+) -> Result<(Reference, WorldPreset), ApiError> {
+    match (preset, scene) {
+        (Some(name), None) => {
+            let Some(stored) = app_state.presets.lock().await.get(&name).copied() else {
+                return Err(unknown_preset(&name));
+            };
+            Ok((Reference::Preset(name), stored.preset))
+        }
+        (None, Some(name)) => {
+            let command = |reply| WorldCommand::ScenePreset(name.clone(), reply);
+            match world_command(&app_state.world_command_tx, command).await? {
+                Some(preset) => Ok((Reference::Scene(name), preset)),
+                None => Err(ApiError::NotFound(format!("Unknown scene '{}'", name))),
+            }
+        }
+        _ => Err(ApiError::Validation(
+            "Give exactly one of preset or scene".to_string(),
+        )),
+    }
+}
+
+/// Per-field deltas from the live world to a preset or a scene.
+#[utoipa::path(
+    get,
+    path = "/state/diff",
+    tag = "world",
+    params(DiffQuery),
+    responses(
+        (status = 200, body = DiffResponse),
+        (status = 400, description = "Not exactly one of preset or scene", body = ErrorPayload),
+        (status = 404, description = "Unknown preset or scene", body = ErrorPayload),
+    )
+)]
+async fn get_state_diff(
+    _: Principal,
+    State(app_state): State<AppState>,
+    ApiQuery(query): ApiQuery<DiffQuery>,
+) -> Result<Json<DiffResponse>, ApiError> {
+    let (reference, preset) = resolve_reference(&app_state, query.preset, query.scene).await?;
+    let current = world_command(&app_state.world_command_tx, WorldCommand::CapturePreset).await?;
+    Ok(Json(DiffResponse {
+        reference,
+        diff: PresetDiff::new(&current, &preset),
+    }))
+}
+
+/// Moves the world `fraction` of the way toward a preset or a scene over
+/// `ramp_seconds` of simulated time; responds with the result as applied.
+#[utoipa::path(
+    post,
+    path = "/state/morph",
+    tag = "world",
+    params(SchemaVersionHeader, MorphQuery),
+    responses(
+        (status = 200, body = WireApplyResult),
+        (status = 400, description = "Out-of-range fraction or ramp", body = ErrorPayload),
+        (status = 404, description = "Unknown preset or scene", body = ErrorPayload),
+    )
+)]
+async fn morph_state(
+    principal: Principal,
+    version: SchemaVersion,
+    State(app_state): State<AppState>,
+    ApiQuery(query): ApiQuery<MorphQuery>,
+) -> Result<Json<WireApplyResult>, ApiError> {
+    principal.require(Role::Controller)?;
+    if !(query.fraction > 0.0 && query.fraction <= 1.0) {
+        return Err(ApiError::Validation(format!(
+            "fraction must be in (0, 1], got {}",
+            query.fraction
+        )));
+    }
+    check_ramp(query.ramp_seconds)?;
+    let (_, preset) = resolve_reference(&app_state, query.preset, query.scene).await?;
+    let command = |reply| WorldCommand::Morph(preset, query.fraction, query.ramp_seconds, reply);
+    let result = world_command(&app_state.world_command_tx, command).await?;
+    Ok(Json(WireApplyResult::new(&result, version)))
+}
+
+/// Event queue depth and backpressure counters.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "admin",
+    responses(
+        (status = 200, body = MetricsResponse),
+    )
+)]
+async fn get_metrics(_: Principal, State(app_state): State<AppState>) -> impl IntoResponse {
+    let event_tx = &app_state.event_tx;
+    Json(MetricsResponse {
+        event_queue: EventQueueMetrics {
+            capacity: event_tx.max_capacity(),
+            depth: event_tx.max_capacity() - event_tx.capacity(),
+            rejected: app_state.event_queue.rejected(),
+            coalesced_ticks: app_state.event_queue.coalesced_ticks(),
         },
+        alerts: app_state.alerts_rx.borrow().clone(),
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/event",
+    tag = "events",
+    params(SchemaVersionHeader, EventQuery),
+    request_body = EventRequest,
+    responses(
+        (status = 200, description = "Applied; the result and the world right after", body = WireApplyResult),
+        (status = 202, description = "Queued without waiting", body = String),
+        (status = 400, description = "Intensity outside -1 to 1, or another invalid action", body = ErrorPayload),
+        (status = 503, description = "Event queue full; retry after `Retry-After`", body = ErrorPayload),
+    )
+)]
+async fn event(
+    principal: Principal,
+    version: SchemaVersion,
+    State(app_state): State<AppState>,
+    ApiQuery(query): ApiQuery<EventQuery>,
+    ApiJson(req): ApiJson<EventRequest>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Controller)?;
+    let event = match req {
+        EventRequest::Trigger { kind, intensity } => {
+            validate_intensity(intensity).map_err(ApiError::Validation)?;
+            Event::Trigger { kind, intensity }
+        }
+        EventRequest::Perform(action) => {
+            validate_perform_action(&action).map_err(ApiError::Validation)?;
+            Event::Perform(action)
+        }
+    };
+    let event = match principal.name {
+        Some(name) => event.with_source(EventSource::ApiKey(name)),
+        None => event.into(),
     };
 
-    if let Ok(json) = serde_json::to_string(&hello) {
-        let _ = tx.send(Message::Text(json.into()));
+    if query.wait_for_apply {
+        let result = apply_event(&app_state.event_tx, &app_state.event_queue, event).await?;
+        Ok(Json(WireApplyResult::new(&result, version)).into_response())
+    } else {
+        queue_event(&app_state.event_tx, &app_state.event_queue, event.into())?;
+        Ok((StatusCode::ACCEPTED, "Event queued").into_response())
     }
+}
 
-    // Clone channels for tasks
-    let world_rx = state.world_state_rx;
-    let audio_rx = state.audio_params_rx;
-    let event_tx = state.event_tx;
-
-    // Spawn task to send messages from mpsc to WebSocket
-    let send_task = tokio::spawn(async move {
-        let mut rx_stream = UnboundedReceiverStream::new(rx);
-        while let Some(message) = rx_stream.next().await {
-            if sender.send(message).await.is_err() {
-                break; // Connection closed
+/// Seconds clients are asked to wait when the event queue is full.
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// Why an event could not be applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SubmitError {
+    /// The queue is saturated; the event was rejected rather than waiting.
+    Full,
+    /// The world task has gone away.
+    Closed,
+}
+
+impl From<SubmitError> for ApiError {
+    fn from(e: SubmitError) -> Self {
+        match e {
+            SubmitError::Full => ApiError::RateLimited {
+                message: "Event queue is full, retry shortly".to_string(),
+                retry_after_secs: Some(RETRY_AFTER_SECS),
+            },
+            SubmitError::Closed => {
+                ApiError::ChannelClosed("Failed to send event: channel closed".to_string())
             }
         }
+    }
+}
+
+/// Queues an event for the world task and waits for its result.
+///
+/// Never waits for queue space: a full queue is reported straight away and
+/// counted in `queue_stats`. The `event.submit` span covers the wait, so its
+/// `world.apply` child shows how much of it was spent queued.
+#[tracing::instrument(name = "event.submit", skip_all, fields(source = ?event.source))]
+pub(crate) async fn apply_event(
+    event_tx: &mpsc::Sender<QueuedEvent>,
+    queue_stats: &EventQueueStats,
+    event: SourcedEvent,
+) -> Result<ApplyResult, SubmitError> {
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let queued = QueuedEvent::new(event, Some(reply_tx));
+    queue_event(event_tx, queue_stats, queued)?;
+    reply_rx.await.map_err(|_| SubmitError::Closed)
+}
+
+/// Queues an event for the world task without waiting for it to apply.
+pub(crate) fn queue_event(
+    event_tx: &mpsc::Sender<QueuedEvent>,
+    queue_stats: &EventQueueStats,
+    queued: QueuedEvent,
+) -> Result<(), SubmitError> {
+    match event_tx.try_send(queued) {
+        Ok(()) => Ok(()),
+        Err(TrySendError::Full(_)) => {
+            queue_stats.record_rejected();
+            Err(SubmitError::Full)
+        }
+        Err(TrySendError::Closed(_)) => Err(SubmitError::Closed),
+    }
+}
+
+/// Turns layers on/off or sets their level; responds with the resulting amounts.
+#[utoipa::path(
+    post,
+    path = "/audio/layers",
+    tag = "audio",
+    request_body = LayersRequest,
+    responses(
+        (status = 200, body = AudioLayersSnapshot),
+        (status = 400, description = "Out-of-range level", body = ErrorPayload),
+    )
+)]
+async fn set_audio_layers(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<LayersRequest>,
+) -> Result<Json<AudioLayersSnapshot>, ApiError> {
+    principal.require(Role::Controller)?;
+    // Apply under the channel lock so concurrent requests do not lose updates
+    let mut result = Err(String::new());
+    app_state.layer_amounts_tx.send_if_modified(|layers| {
+        result = req.apply(*layers);
+        match &result {
+            Ok(new) if new != layers => {
+                *layers = *new;
+                true
+            }
+            _ => false,
+        }
+    });
+    let layers = result.map_err(ApiError::Validation)?;
+    tracing::info!("Audio layers set to {:?}", layers);
+    Ok(Json(AudioLayersSnapshot::from(layers)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/audio/params",
+    tag = "audio",
+    responses(
+        (status = 200, body = AudioParamsResponse),
+    )
+)]
+async fn get_audio_params(_: Principal, State(app_state): State<AppState>) -> impl IntoResponse {
+    let params = *app_state.audio_params_rx.borrow();
+    Json(AudioParamsResponse {
+        params: params.into(),
+        gains: app_state.layer_gains.get().into(),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/audio/effects",
+    tag = "audio",
+    responses(
+        (status = 200, body = EffectsResponse),
+    )
+)]
+async fn get_audio_effects(_: Principal, State(app_state): State<AppState>) -> impl IntoResponse {
+    Json(EffectsResponse::from(app_state.effects.as_ref()))
+}
+
+/// Switches one member of an effect chain on or off; responds with every chain.
+#[utoipa::path(
+    post,
+    path = "/audio/effects",
+    tag = "audio",
+    request_body = EffectToggleRequest,
+    responses(
+        (status = 200, body = EffectsResponse),
+        (status = 400, description = "Unknown bus or effect", body = ErrorPayload),
+        (status = 404, description = "Effect not on that bus", body = ErrorPayload),
+    )
+)]
+async fn set_audio_effect(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<EffectToggleRequest>,
+) -> Result<Json<EffectsResponse>, ApiError> {
+    principal.require(Role::Controller)?;
+    let bus = req.bus.parse::<EffectBus>().map_err(ApiError::Validation)?;
+    let kind = req
+        .effect
+        .parse::<EffectKind>()
+        .map_err(ApiError::Validation)?;
+    let effects = &app_state.effects;
+    let Some(index) = effects.chains().get(bus).iter().position(|k| *k == kind) else {
+        return Err(ApiError::NotFound(format!(
+            "no {} on the {} bus",
+            kind.name(),
+            bus.name()
+        )));
+    };
+    effects.set_enabled(bus, index, req.enabled);
+    tracing::info!(
+        "Effect {} on the {} bus {}",
+        kind.name(),
+        bus.name(),
+        if req.enabled { "enabled" } else { "disabled" }
+    );
+    Ok(Json(EffectsResponse::from(effects.as_ref())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/audio/stack",
+    tag = "audio",
+    responses(
+        (status = 200, body = StackResponse),
+    )
+)]
+async fn get_audio_stack(_: Principal, State(app_state): State<AppState>) -> impl IntoResponse {
+    Json(StackResponse::from(app_state.layers.as_ref()))
+}
+
+/// Builds `layer` and hands it to `change`; responds with the resulting stack.
+fn change_stack(
+    principal: Principal,
+    app_state: &AppState,
+    layer: &LayerConfig,
+    change: impl FnOnce(&LayerRegistry, LayerSpec) -> Result<(), RegistryError>,
+) -> Result<Json<StackResponse>, ApiError> {
+    principal.require(Role::Controller)?;
+    let registry = app_state.layers.as_ref();
+    let spec = layer
+        .spec(registry.samples())
+        .map_err(ApiError::Validation)?;
+    change(registry, spec)?;
+    tracing::info!("Audio layer stack changed: {:?}", layer);
+    Ok(Json(StackResponse::from(registry)))
+}
+
+/// Fades a layer in alongside those playing.
+#[utoipa::path(
+    post,
+    path = "/audio/stack",
+    tag = "audio",
+    request_body = LayerConfig,
+    responses(
+        (status = 200, body = StackResponse),
+        (status = 400, description = "Invalid layer", body = ErrorPayload),
+        (status = 409, description = "The stack is full", body = ErrorPayload),
+        (status = 503, description = "A crossfade is still running", body = ErrorPayload),
+    )
+)]
+async fn add_stack_layer(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    ApiJson(layer): ApiJson<LayerConfig>,
+) -> Result<Json<StackResponse>, ApiError> {
+    change_stack(principal, &app_state, &layer, LayerRegistry::add)
+}
+
+/// Crossfades from the layers in the new layer's slot to it.
+#[utoipa::path(
+    put,
+    path = "/audio/stack",
+    tag = "audio",
+    request_body = LayerConfig,
+    responses(
+        (status = 200, body = StackResponse),
+        (status = 400, description = "Invalid layer", body = ErrorPayload),
+        (status = 409, description = "The stack is full", body = ErrorPayload),
+        (status = 503, description = "A crossfade is still running", body = ErrorPayload),
+    )
+)]
+async fn replace_stack_layer(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    ApiJson(layer): ApiJson<LayerConfig>,
+) -> Result<Json<StackResponse>, ApiError> {
+    change_stack(principal, &app_state, &layer, LayerRegistry::replace)
+}
+
+/// Fades out every layer in a slot; responds with the resulting stack.
+#[utoipa::path(
+    delete,
+    path = "/audio/stack/{layer}",
+    tag = "audio",
+    params(("layer" = String, Path, description = "Layer slot, such as `drone`")),
+    responses(
+        (status = 200, body = StackResponse),
+        (status = 400, description = "Unknown layer", body = ErrorPayload),
+        (status = 404, description = "Nothing playing in that slot", body = ErrorPayload),
+    )
+)]
+async fn remove_stack_layer(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    Path(layer): Path<String>,
+) -> Result<Json<StackResponse>, ApiError> {
+    principal.require(Role::Controller)?;
+    let slot = layer.parse::<LayerSlot>().map_err(ApiError::Validation)?;
+    let registry = app_state.layers.as_ref();
+    registry.remove(slot)?;
+    tracing::info!("Audio layer {} removed", slot.name());
+    Ok(Json(StackResponse::from(registry)))
+}
+
+fn current_patch(app_state: &AppState) -> AudioPatch {
+    AudioPatch::capture(
+        &app_state.layers,
+        &app_state.effects,
+        app_state.layer_gains.get(),
+    )
+}
+
+/// The sound playing now, as a patch.
+#[utoipa::path(
+    get,
+    path = "/audio/patch",
+    tag = "audio",
+    responses(
+        (status = 200, body = AudioPatch),
+    )
+)]
+async fn get_audio_patch(_: Principal, State(app_state): State<AppState>) -> impl IntoResponse {
+    Json(current_patch(&app_state))
+}
+
+/// Switches to `patch`; responds with the sound now playing.
+fn play_patch(
+    principal: Principal,
+    app_state: &AppState,
+    patch: &AudioPatch,
+) -> Result<Json<AudioPatch>, ApiError> {
+    principal.require(Role::Controller)?;
+    patch.play(
+        &app_state.layers,
+        &app_state.effects,
+        &app_state.layer_gains,
+    )?;
+    Ok(Json(current_patch(app_state)))
+}
+
+/// Switches to the patch in the body.
+#[utoipa::path(
+    put,
+    path = "/audio/patch",
+    tag = "audio",
+    request_body = AudioPatch,
+    responses(
+        (status = 200, body = AudioPatch),
+        (status = 400, description = "Invalid patch", body = ErrorPayload),
+    )
+)]
+async fn play_audio_patch(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    ApiJson(patch): ApiJson<AudioPatch>,
+) -> Result<Json<AudioPatch>, ApiError> {
+    let playing = play_patch(principal, &app_state, &patch)?;
+    tracing::info!("Audio patch applied");
+    Ok(playing)
+}
+
+/// Patches read from `audio.patches_dir`, keyed by name.
+#[utoipa::path(
+    get,
+    path = "/audio/patches",
+    tag = "audio",
+    responses(
+        (status = 200, body = BTreeMap<String, AudioPatch>),
+    )
+)]
+async fn list_audio_patches(_: Principal, State(app_state): State<AppState>) -> impl IntoResponse {
+    Json(app_state.patches.as_ref().clone())
+}
+
+/// Switches to a patch read from `audio.patches_dir`.
+#[utoipa::path(
+    post,
+    path = "/audio/patches/{name}/load",
+    tag = "audio",
+    params(("name" = String, Path, description = "Patch name")),
+    responses(
+        (status = 200, body = AudioPatch),
+        (status = 404, description = "Unknown patch", body = ErrorPayload),
+    )
+)]
+async fn load_audio_patch(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<AudioPatch>, ApiError> {
+    let Some(patch) = app_state.patches.get(&name) else {
+        return Err(ApiError::NotFound(format!(
+            "no audio patch named '{}'",
+            name
+        )));
+    };
+    let playing = play_patch(principal, &app_state, patch)?;
+    tracing::info!("Audio patch '{}' loaded", name);
+    Ok(playing)
+}
+
+fn mapping_response(app_state: &AppState) -> MappingResponse {
+    MappingResponse {
+        profile: app_state.mapping_tx.borrow().name.clone(),
+        available: app_state.mapping_profiles.keys().cloned().collect(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/audio/mapping",
+    tag = "audio",
+    responses(
+        (status = 200, body = MappingResponse),
+    )
+)]
+async fn get_audio_mapping(_: Principal, State(app_state): State<AppState>) -> impl IntoResponse {
+    Json(mapping_response(&app_state))
+}
+
+/// Switches the world → audio mapping profile.
+#[utoipa::path(
+    post,
+    path = "/audio/mapping",
+    tag = "audio",
+    request_body = MappingRequest,
+    responses(
+        (status = 200, body = MappingResponse),
+        (status = 400, description = "Unknown profile", body = ErrorPayload),
+    )
+)]
+async fn set_audio_mapping(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<MappingRequest>,
+) -> Result<Json<MappingResponse>, ApiError> {
+    principal.require(Role::Controller)?;
+    let Some(profile) = app_state.mapping_profiles.get(&req.profile) else {
+        return Err(ApiError::Validation(format!(
+            "unknown mapping profile '{}'",
+            req.profile
+        )));
+    };
+    let mapping = ActiveMapping {
+        name: req.profile,
+        profile: profile.clone(),
+    };
+    app_state
+        .mapping_tx
+        .send_if_modified(|active| std::mem::replace(active, mapping.clone()) != mapping);
+    Ok(Json(mapping_response(&app_state)))
+}
+
+/// Pins audio params to the given values for a while, bypassing the world mapping.
+#[utoipa::path(
+    post,
+    path = "/audio/override",
+    tag = "audio",
+    request_body = OverrideRequest,
+    responses(
+        (status = 200, body = OverrideResponse),
+        (status = 400, description = "Out-of-range value or duration", body = ErrorPayload),
+        (status = 409, description = "A calibration holds the audio params", body = ErrorPayload),
+    )
+)]
+async fn set_audio_override(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<OverrideRequest>,
+) -> Result<Json<OverrideResponse>, ApiError> {
+    principal.require(Role::Controller)?;
+    check_not_calibrating(&app_state)?;
+    let overrides = req.overrides().map_err(ApiError::Validation)?;
+    let expires_at =
+        tokio::time::Instant::now() + tokio::time::Duration::from_secs_f64(req.duration_secs);
+    app_state
+        .audio_override_tx
+        .send_replace(Some(AudioOverride {
+            params: overrides,
+            expires_at,
+        }));
+    Ok(Json(OverrideResponse {
+        fields: overrides.fields(),
+        expires_in_secs: req.duration_secs,
+    }))
+}
+
+/// Writes the last minutes of output to a WAV file.
+#[utoipa::path(
+    post,
+    path = "/record/save",
+    tag = "audio",
+    responses(
+        (status = 200, body = Take),
+        (status = 404, description = "Audio recording is off", body = ErrorPayload),
+    )
+)]
+async fn save_recording(
+    principal: Principal,
+    State(app_state): State<AppState>,
+) -> Result<Json<Take>, ApiError> {
+    principal.require(Role::Controller)?;
+    let Some(recorder) = app_state.audio_recorder.clone() else {
+        return Err(ApiError::NotFound("Audio recording is off".to_string()));
+    };
+    let saved = tokio::task::spawn_blocking(move || recorder.save(unix_time_ms())).await;
+    match saved {
+        Ok(Ok(take)) => Ok(Json(take)),
+        Ok(Err(e)) => Err(ApiError::Internal(format!(
+            "Failed to save recording: {}",
+            e
+        ))),
+        Err(_) => Err(ApiError::Internal("Recorder task failed".to_string())),
+    }
+}
+
+/// Releases any override early.
+#[utoipa::path(
+    delete,
+    path = "/audio/override",
+    tag = "audio",
+    responses(
+        (status = 204, description = "Override released"),
+        (status = 409, description = "A calibration holds the audio params", body = ErrorPayload),
+    )
+)]
+async fn clear_audio_override(
+    principal: Principal,
+    State(app_state): State<AppState>,
+) -> Result<StatusCode, ApiError> {
+    principal.require(Role::Controller)?;
+    check_not_calibrating(&app_state)?;
+    app_state.audio_override_tx.send_replace(None);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A running calibration owns the override; changing it would spoil the
+/// measurements.
+fn check_not_calibrating(app_state: &AppState) -> Result<(), ApiError> {
+    if app_state.calibration.running() {
+        return Err(ApiError::Conflict(
+            "A calibration holds the audio params; cancel it first".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Where the speaker calibration stands, with the latest report.
+#[utoipa::path(
+    get,
+    path = "/audio/calibration",
+    tag = "audio",
+    responses(
+        (status = 200, body = CalibrationStatus),
+    )
+)]
+async fn get_calibration(
+    _: Principal,
+    State(app_state): State<AppState>,
+) -> Json<CalibrationStatus> {
+    Json(app_state.calibration.status())
+}
+
+/// Takes the audio off the world and starts sweeping its params.
+#[utoipa::path(
+    post,
+    path = "/audio/calibration",
+    tag = "audio",
+    request_body = CalibrationRequest,
+    responses(
+        (status = 200, body = CalibrationStatus),
+        (status = 400, description = "A param is listed twice", body = ErrorPayload),
+        (status = 409, description = "A calibration is already running", body = ErrorPayload),
+    )
+)]
+async fn start_calibration(
+    principal: Principal,
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<CalibrationRequest>,
+) -> Result<Json<CalibrationStatus>, ApiError> {
+    principal.require(Role::Controller)?;
+    app_state.calibration.start(req).map(Json)
+}
+
+/// Measures the next point of a manual calibration, answering once it has.
+#[utoipa::path(
+    post,
+    path = "/audio/calibration/step",
+    tag = "audio",
+    responses(
+        (status = 200, body = CalibrationStatus),
+        (status = 409, description = "No manual calibration is running", body = ErrorPayload),
+    )
+)]
+async fn step_calibration(
+    principal: Principal,
+    State(app_state): State<AppState>,
+) -> Result<Json<CalibrationStatus>, ApiError> {
+    principal.require(Role::Controller)?;
+    app_state.calibration.step().await.map(Json)
+}
+
+/// Stops the calibration without a report and hands the audio back to the
+/// world.
+#[utoipa::path(
+    delete,
+    path = "/audio/calibration",
+    tag = "audio",
+    responses(
+        (status = 204, description = "Calibration cancelled"),
+        (status = 409, description = "No calibration is running", body = ErrorPayload),
+    )
+)]
+async fn cancel_calibration(
+    principal: Principal,
+    State(app_state): State<AppState>,
+) -> Result<StatusCode, ApiError> {
+    principal.require(Role::Controller)?;
+    if !app_state.calibration.cancel() {
+        return Err(ApiError::Conflict("No calibration is running".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/audio/status",
+    tag = "audio",
+    responses(
+        (status = 200, body = AudioStatusResponse),
+    )
+)]
+async fn get_audio_status(_: Principal, State(app_state): State<AppState>) -> impl IntoResponse {
+    let status = app_state.audio_status.get();
+    Json(AudioStatusResponse {
+        state: match status.state {
+            EngineState::Disabled => "disabled",
+            EngineState::Starting => "starting",
+            EngineState::Running => "running",
+            EngineState::Restarting => "restarting",
+        },
+        device: status.device,
+        sample_rate: status.sample_rate,
+        channels: status.channels,
+        restarts: status.restarts,
+        last_error: status.last_error,
+        callback: app_state.telemetry.stats().into(),
+        layers: app_state.meter.layers().into(),
+    })
+}
+
+/// Min/max envelope of the recent output, for drawing a waveform.
+#[utoipa::path(
+    get,
+    path = "/audio/waveform",
+    tag = "audio",
+    params(WaveformQuery),
+    responses(
+        (status = 200, body = WaveformResponse),
+        (status = 400, description = "Out-of-range seconds or points", body = ErrorPayload),
+    )
+)]
+async fn get_audio_waveform(
+    _: Principal,
+    State(app_state): State<AppState>,
+    ApiQuery(query): ApiQuery<WaveformQuery>,
+) -> Result<Json<WaveformResponse>, ApiError> {
+    if !(query.seconds > 0.0 && query.seconds <= HISTORY_SECS) {
+        return Err(ApiError::Validation(format!(
+            "seconds must be in (0, {}], got {}",
+            HISTORY_SECS, query.seconds
+        )));
+    }
+    if !(1..=MAX_WAVEFORM_POINTS).contains(&query.points) {
+        return Err(ApiError::Validation(format!(
+            "points must be in [1, {}], got {}",
+            MAX_WAVEFORM_POINTS, query.points
+        )));
+    }
+    let envelope = app_state.waveform.envelope(query.seconds, query.points);
+    Ok(Json(WaveformResponse::from(envelope)))
+}
+
+/// JSON Schema of the WebSocket messages.
+async fn get_ws_schema() -> Json<serde_json::Value> {
+    Json(ws_schema())
+}
+
+async fn websocket_handler(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    // A header token is optional here (browsers cannot set one), but a bad one is rejected
+    let role = match state.auth.authenticate(bearer_token(&headers)) {
+        Ok(principal) => Some(principal.role),
+        Err(AuthError::Missing) => None,
+        Err(e) => return e.into_response(),
+    };
+    ws.on_upgrade(move |socket| handle_websocket(socket, state, role))
+}
+
+async fn handle_websocket(socket: WebSocket, state: AppState, role: Option<Role>) {
+    let incoming_state = state.clone();
+    let (mut sender, receiver) = socket.split();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    // Generate session ID
+    let session_id = format!(
+        "ws-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    );
+
+    let resume_token = new_token();
+
+    // Send hello message immediately, advertising what the server supports
+    let to_strings = |list: &[&str]| list.iter().map(|s| s.to_string()).collect();
+    let hello = ServerMessage::Hello {
+        version: PROTOCOL_VERSION.to_string(),
+        payload: HelloPayload {
+            session_id: session_id.clone(),
+            schema_version: PROTOCOL_VERSION.to_string(),
+            supported_versions: to_strings(SUPPORTED_VERSIONS),
+            tick_rate_hz: *state.tick_hz_rx.borrow(),
+            snapshot_rate_hz: *state.snapshot_hz_rx.borrow(),
+            capabilities: to_strings(CAPABILITIES),
+            encodings: to_strings(ENCODINGS),
+            message_types: MessageTypes {
+                server: to_strings(SERVER_MESSAGE_TYPES),
+                client: to_strings(CLIENT_MESSAGE_TYPES),
+            },
+            auth_required: state.auth.is_enabled(),
+            resume_token: resume_token.clone(),
+            noise_seed: state.noise_seed,
+        },
+    };
+    send_message(&tx, &hello);
+
+    // Clone channels for tasks
+    let snapshots = state.snapshots;
+    let broadcast_rx = state.broadcast_tx.subscribe();
+    let resumes = state.resumes;
+    let (resume_tx, resume_rx) = mpsc::channel(1);
+    let (session_tx, session_rx) = watch::channel(Session {
+        id: session_id,
+        resume_token,
+        role,
+        schema: SchemaVersion::default(),
+        encoding: Encoding::default(),
+        subscription: Subscription::default(),
     });
 
     // Spawn outgoing task (snapshots)
     let outgoing_tx = tx.clone();
+    let mut outgoing_session_rx = session_rx.clone();
     tokio::spawn(async move {
-        handle_outgoing_snapshots(world_rx, audio_rx, outgoing_tx).await;
+        if wait_for_viewer(&mut outgoing_session_rx).await {
+            forward_snapshots(snapshots, outgoing_tx, outgoing_session_rx).await;
+        }
+    });
+
+    // Spawn broadcast task (server-initiated notifications)
+    let broadcast_out_tx = tx.clone();
+    let mut broadcast_session_rx = session_rx.clone();
+    let broadcast_task = tokio::spawn(async move {
+        if !wait_for_viewer(&mut broadcast_session_rx).await {
+            return None;
+        }
+        forward_broadcasts(
+            broadcast_rx,
+            broadcast_out_tx,
+            broadcast_session_rx,
+            resume_rx,
+        )
+        .await
     });
 
     // Spawn incoming task (client messages)
     let incoming_tx = tx;
-    tokio::spawn(async move {
-        handle_incoming_messages(receiver, event_tx, incoming_tx, session_id).await;
+    let mut incoming_task = tokio::spawn(async move {
+        handle_incoming_messages(receiver, incoming_state, incoming_tx, session_tx, resume_tx)
+            .await;
     });
 
-    // Wait for the send task to finish (connection closed)
-    let _ = send_task.await;
+    // Send queued messages until either side closes the connection. A quiet
+    // session (subscribed to nothing) only notices a client leaving through
+    // the incoming side.
+    loop {
+        tokio::select! {
+            biased;
+            message = rx.recv() => match message {
+                Some(message) => {
+                    if sender.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                None => break,
+            },
+            _ = &mut incoming_task => {
+                // Flush replies to the client's last messages, such as an error before a close
+                while let Ok(message) = rx.try_recv() {
+                    if sender.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                break;
+            }
+        }
+    }
+    // Stops the snapshot and broadcast tasks
+    drop(rx);
+
+    // Park the session, with the broadcasts it has yet to see, for a resume
+    if let Ok(Some(broadcast_rx)) = broadcast_task.await {
+        let session = session_rx.borrow().clone();
+        tracing::debug!("Session {} dropped, resumable", session.id);
+        resumes.lock().expect("resume store lock poisoned").park(
+            session.resume_token.clone(),
+            ParkedSession {
+                session,
+                broadcast_rx,
+            },
+            Instant::now(),
+        );
+    }
+}
+
+/// A dropped WebSocket session, kept for a client to resume.
+pub struct ParkedSession {
+    session: Session,
+    /// Broadcasts sent since the drop queue up here, up to the channel's capacity.
+    broadcast_rx: broadcast::Receiver<ServerMessage>,
+}
+
+/// Waits until the session is authenticated. Returns false if it closed first.
+async fn wait_for_viewer(session_rx: &mut watch::Receiver<Session>) -> bool {
+    session_rx
+        .wait_for(|session| session.role.is_some())
+        .await
+        .is_ok()
 }
 
-async fn handle_outgoing_snapshots(
-    world_rx: watch::Receiver<WorldSnapshot>,
-    audio_rx: watch::Receiver<AudioParams>,
+/// Per-connection state settled during the handshake.
+#[derive(Debug, Clone, PartialEq)]
+struct Session {
+    /// Event source id; kept across resumes.
+    id: String,
+    resume_token: String,
+    /// None until the session authenticates (or always Some when auth is off).
+    role: Option<Role>,
+    /// Snapshot schema negotiated in the client's hello.
+    schema: SchemaVersion,
+    /// Snapshot encoding negotiated in the client's hello.
+    encoding: Encoding,
+    /// Channels and fields the client asked to stream.
+    subscription: Subscription,
+}
+
+/// Streams the session's snapshot feed to the connection, switching feeds
+/// when the session renegotiates. A session that falls behind its feed
+/// skips to the newest frames.
+async fn forward_snapshots(
+    fanout: Arc<SnapshotFanout>,
     tx: mpsc::UnboundedSender<Message>,
+    mut session_rx: watch::Receiver<Session>,
 ) {
-    let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(100)); // 10 Hz - sane update rate
+    let mut current: Option<(SnapshotFeed, broadcast::Receiver<Message>)> = None;
+    loop {
+        let feed = {
+            let session = session_rx.borrow_and_update();
+            SnapshotFeed::new(session.schema, session.encoding, &session.subscription)
+        };
+        if current.as_ref().is_none_or(|(joined, _)| *joined != feed) {
+            current = (!feed.is_empty()).then(|| {
+                let feed_rx = fanout.subscribe(feed.clone());
+                (feed, feed_rx)
+            });
+        }
+        let next_frame = async {
+            match &mut current {
+                Some((_, feed_rx)) => feed_rx.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            changed = session_rx.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+            _ = tx.closed() => return,
+            received = next_frame => match received {
+                Ok(frame) => {
+                    if tx.send(frame).is_err() {
+                        return; // Connection closed
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::debug!("WebSocket client lagged, skipped {} snapshot frames", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+}
 
+/// Forwards server-wide broadcast messages to a single WebSocket connection.
+///
+/// On a resume, switches to the parked session's receiver, replaying what it
+/// queued while dropped. Returns the receiver once the connection closes, for
+/// parking in turn.
+async fn forward_broadcasts(
+    mut broadcast_rx: broadcast::Receiver<ServerMessage>,
+    tx: mpsc::UnboundedSender<Message>,
+    session_rx: watch::Receiver<Session>,
+    mut resume_rx: mpsc::Receiver<broadcast::Receiver<ServerMessage>>,
+) -> Option<broadcast::Receiver<ServerMessage>> {
     loop {
         tokio::select! {
-            _ = interval.tick() => {
-                // Get latest world state
-                let world = world_rx.borrow().clone();
-
-                // Get latest audio params
-                let audio_params = *audio_rx.borrow();
-                let audio = AudioParamsSnapshot {
-                    master_gain: audio_params.master_gain,
-                    base_freq_hz: audio_params.base_freq_hz,
-                    detune_ratio: audio_params.detune_ratio,
-                    brightness: audio_params.brightness,
-                    motion: audio_params.motion,
-                    texture: audio_params.texture,
-                    sparkle_impulse: audio_params.sparkle_impulse,
-                };
-
-                let snapshot = ServerMessage::Snapshot {
-                    version: "1.0".to_string(),
-                    payload: SnapshotPayload { world, audio },
-                };
-                if let Ok(json) = serde_json::to_string(&snapshot)
-                    && tx.send(Message::Text(json.into())).is_err()
-                {
-                    break; // Connection closed
+            biased;
+            Some(parked_rx) = resume_rx.recv() => broadcast_rx = parked_rx,
+            _ = tx.closed() => return Some(broadcast_rx),
+            received = broadcast_rx.recv() => match received {
+                Ok(message) => {
+                    let channel = match message {
+                        ServerMessage::Sparkle { .. } => Channel::Sparkles,
+                        ServerMessage::Beat { .. } => Channel::Beats,
+                        _ => Channel::Events,
+                    };
+                    if !session_rx.borrow().subscription.includes(channel) {
+                        continue;
+                    }
+                    if let Ok(json) = serde_json::to_string(&message)
+                        && tx.send(Message::Text(json.into())).is_err()
+                    {
+                        return Some(broadcast_rx); // Connection closed
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("WebSocket client lagged, skipped {} broadcasts", skipped);
                 }
+                Err(broadcast::error::RecvError::Closed) => return None,
             }
         }
     }
 }
 
+/// Takes over the session parked under `token`: its id, token and
+/// subscription, and its role unless the hello authenticated afresh. Returns
+/// the broadcasts it queued while dropped, or None if the token is unknown
+/// or expired.
+fn resume_session(
+    state: &AppState,
+    token: &str,
+    session_tx: &watch::Sender<Session>,
+) -> Option<broadcast::Receiver<ServerMessage>> {
+    let ParkedSession {
+        session: parked,
+        broadcast_rx,
+    } = state
+        .resumes
+        .lock()
+        .expect("resume store lock poisoned")
+        .take(token, Instant::now())?;
+    tracing::debug!("Session {} resumed", parked.id);
+    session_tx.send_modify(|session| {
+        session.id = parked.id;
+        session.resume_token = parked.resume_token;
+        session.role = session.role.or(parked.role);
+        session.subscription = parked.subscription;
+    });
+    Some(broadcast_rx)
+}
+
+/// Names of the fields a snapshot channel carries in the given schema, for
+/// checking subscribe requests.
+fn snapshot_fields(channel: Channel, schema: SchemaVersion) -> Vec<String> {
+    let sample = match channel {
+        Channel::World => serde_json::to_value(WireSnapshot::new(
+            &WorldSnapshot::from_world_state(&WorldState::new()),
+            schema,
+        )),
+        Channel::Audio => serde_json::to_value(AudioParamsSnapshot::from(AudioParams::default())),
+        Channel::Analysis => serde_json::to_value(AnalysisSnapshot::default()),
+        Channel::Events | Channel::Sparkles | Channel::Beats | Channel::Field => {
+            return Vec::new();
+        }
+    };
+    match sample {
+        Ok(serde_json::Value::Object(fields)) => fields.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Serializes a server message and queues it on the connection.
+/// Returns false if the connection is closed.
+fn send_message(tx: &mpsc::UnboundedSender<Message>, message: &ServerMessage) -> bool {
+    match serde_json::to_string(message) {
+        Ok(json) => tx.send(Message::Text(json.into())).is_ok(),
+        Err(_) => true,
+    }
+}
+
+fn send_error(
+    tx: &mpsc::UnboundedSender<Message>,
+    code: ErrorCode,
+    message: impl Into<String>,
+    request_id: Option<String>,
+) {
+    send_message(
+        tx,
+        &ServerMessage::Error {
+            version: PROTOCOL_VERSION.to_string(),
+            payload: ErrorPayload {
+                code,
+                message: message.into(),
+                request_id,
+            },
+        },
+    );
+}
+
+/// Sends a perform action into the world and acknowledges it with the result.
+async fn submit_action(
+    event_tx: &mpsc::Sender<QueuedEvent>,
+    event_queue: &EventQueueStats,
+    schema: SchemaVersion,
+    tx: &mpsc::UnboundedSender<Message>,
+    session_id: &str,
+    action: PerformAction,
+    request_id: Option<String>,
+) {
+    let (action_name, intensity) = get_action_info(&action);
+    let action_name = action_name.to_string();
+    let event = Event::Perform(action).with_source(EventSource::Session(session_id.to_string()));
+    match apply_event(event_tx, event_queue, event).await {
+        Ok(result) => {
+            send_message(
+                tx,
+                &ServerMessage::EventAck {
+                    version: schema.as_str().to_string(),
+                    payload: EventAckPayload {
+                        request_id,
+                        action: action_name,
+                        intensity,
+                        result: WireApplyResult::new(&result, schema),
+                    },
+                },
+            );
+        }
+        Err(e) => {
+            // Same code and message as a REST caller gets
+            let e = ApiError::from(e);
+            send_error(tx, e.code(), e.to_string(), request_id);
+        }
+    }
+}
+
 async fn handle_incoming_messages(
     mut receiver: futures_util::stream::SplitStream<WebSocket>,
-    event_tx: mpsc::Sender<Event>,
+    state: AppState,
     tx: mpsc::UnboundedSender<Message>,
-    session_id: String,
+    session_tx: watch::Sender<Session>,
+    resume_tx: mpsc::Sender<broadcast::Receiver<ServerMessage>>,
 ) {
+    let AppState {
+        event_tx,
+        event_queue,
+        auth,
+        ..
+    } = &state;
     while let Some(msg) = receiver.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                match serde_json::from_str::<ClientMessage>(&text) {
-                    Ok(client_msg) => {
-                        match client_msg {
-                            ClientMessage::Perform { version, payload } => {
-                                let PerformPayload { request_id, action } = payload;
-                                // Validate the action before processing
-                                match validate_perform_action(&action) {
-                                    Ok(_) => {
-                                        let event = Event::Perform(action.clone());
-                                        if event_tx.send(event).await.is_ok() {
-                                            // Send acknowledgment
-                                            let (action_name, intensity) = get_action_info(&action);
-
-                                            let ack = ServerMessage::EventAck {
-                                                version: "1.0".to_string(),
-                                                payload: EventAckPayload {
-                                                    request_id,
-                                                    action: action_name.to_string(),
-                                                    intensity,
-                                                },
-                                            };
-
-                                            if let Ok(json) = serde_json::to_string(&ack) {
-                                                let _ = tx.send(Message::Text(json.into()));
-                                            }
-                                        } else {
-                                            let error = ServerMessage::Error {
-                                                version: "1.0".to_string(),
-                                                payload: ErrorPayload {
-                                                    code: "SEND_FAILED".to_string(),
-                                                    message: "Failed to send event".to_string(),
-                                                    request_id,
-                                                },
-                                            };
-                                            if let Ok(json) = serde_json::to_string(&error) {
-                                                let _ = tx.send(Message::Text(json.into()));
-                                            }
-                                        }
-                                    }
-                                    Err(validation_error) => {
-                                        let error = ServerMessage::Error {
-                                            version: "1.0".to_string(),
-                                            payload: ErrorPayload {
-                                                code: "VALIDATION_ERROR".to_string(),
-                                                message: validation_error,
-                                                request_id,
-                                            },
-                                        };
-                                        if let Ok(json) = serde_json::to_string(&error) {
-                                            let _ = tx.send(Message::Text(json.into()));
-                                        }
-                                    }
+        let text = match msg {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => break,
+            Ok(_) => continue, // Ignore other message types
+            Err(_) => break,
+        };
+        let client_msg = match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(client_msg) => client_msg,
+            Err(e) => {
+                send_error(
+                    &tx,
+                    ErrorCode::InvalidMessage,
+                    format!("Failed to parse message: {}", e),
+                    None,
+                );
+                continue;
+            }
+        };
+        if !is_supported_version(client_msg.version()) {
+            send_error(
+                &tx,
+                ErrorCode::VersionMismatch,
+                format!(
+                    "Unsupported message version {}, supported: {}",
+                    client_msg.version(),
+                    SUPPORTED_VERSIONS.join(", ")
+                ),
+                None,
+            );
+            continue;
+        }
+
+        // Perform-style messages need the controller role
+        let (session_id, role) = {
+            let session = session_tx.borrow();
+            (session.id.clone(), session.role)
+        };
+        if matches!(
+            client_msg,
+            ClientMessage::Perform { .. }
+                | ClientMessage::SetScene { .. }
+                | ClientMessage::Calibrate { .. }
+        ) && role < Some(Role::Controller)
+        {
+            let (code, message) = match role {
+                None => (
+                    ErrorCode::Unauthorized,
+                    "Authenticate with a token in a hello message first",
+                ),
+                Some(_) => (ErrorCode::Forbidden, "Requires the controller role"),
+            };
+            send_error(&tx, code, message, client_msg.request_id());
+            continue;
+        }
+
+        match client_msg {
+            ClientMessage::Hello { payload, .. } => {
+                match negotiate(
+                    &payload.schema_version,
+                    &payload.features,
+                    payload.encoding.as_deref(),
+                ) {
+                    Ok(mut negotiated) => {
+                        if let Some(token) = &payload.token {
+                            match auth.authenticate(Some(token)) {
+                                Ok(principal) => {
+                                    session_tx.send_modify(|session| {
+                                        session.role = Some(principal.role);
+                                    });
                                 }
-                            }
-                            ClientMessage::Ping {
-                                version: _,
-                                payload: _,
-                            } => {
-                                // Echo back ping (could add pong message type later)
-                                tracing::debug!("Received ping from session {}", session_id);
-                            }
-                            ClientMessage::SetScene { version, payload } => {
-                                let SetScenePayload {
-                                    request_id,
-                                    scene_name,
-                                } = payload;
-                                if scene_name.trim().is_empty() {
-                                    let error = ServerMessage::Error {
-                                        version: "1.0".to_string(),
-                                        payload: ErrorPayload {
-                                            request_id,
-                                            code: "VALIDATION_ERROR".to_string(),
-                                            message: "Scene name cannot be empty".to_string(),
-                                        },
-                                    };
-                                    if let Ok(json) = serde_json::to_string(&error) {
-                                        let _ = tx.send(Message::Text(json.into()));
-                                    }
-                                    continue;
+                                Err(e) => {
+                                    send_error(&tx, ErrorCode::Unauthorized, e.to_string(), None);
+                                    let _ = tx.send(Message::Close(None));
+                                    break;
                                 }
-
-                                // For now, treat as scene perform action
-                                let action = PerformAction::Scene { name: scene_name };
-                                let event = Event::Perform(action);
-                                if event_tx.send(event).await.is_ok() {
-                                    let ack = ServerMessage::EventAck {
-                                        version: "1.0".to_string(),
-                                        payload: EventAckPayload {
-                                            request_id,
-                                            action: "Scene".to_string(),
-                                            intensity: None,
+                            }
+                        }
+                        let schema =
+                            SchemaVersion::parse(&negotiated.schema_version).unwrap_or_default();
+                        session_tx.send_modify(|session| {
+                            session.schema = schema;
+                            session.encoding = negotiated.encoding;
+                        });
+                        let resumed = payload
+                            .resume_token
+                            .as_deref()
+                            .map(|token| resume_session(&state, token, &session_tx));
+                        negotiated.role = session_tx.borrow().role;
+                        tracing::debug!("Session {} negotiated {:?}", session_id, negotiated);
+                        send_message(
+                            &tx,
+                            &ServerMessage::Negotiated {
+                                version: PROTOCOL_VERSION.to_string(),
+                                payload: negotiated,
+                            },
+                        );
+                        match resumed {
+                            Some(Some(broadcast_rx)) => {
+                                let session = session_tx.borrow().clone();
+                                send_message(
+                                    &tx,
+                                    &ServerMessage::Resumed {
+                                        version: PROTOCOL_VERSION.to_string(),
+                                        payload: ResumedPayload {
+                                            world: WireSnapshot::new(
+                                                &state.world_state_rx.borrow(),
+                                                session.schema,
+                                            ),
+                                            session_id: session.id,
+                                            resume_token: session.resume_token,
                                         },
-                                    };
-                                    if let Ok(json) = serde_json::to_string(&ack) {
-                                        let _ = tx.send(Message::Text(json.into()));
-                                    }
+                                    },
+                                );
+                                // Replay what it missed, after the resumed message.
+                                // Full means a replay is already waiting from an
+                                // earlier resume; closed, that nothing forwards
+                                // broadcasts to this session any more.
+                                match resume_tx.try_send(broadcast_rx) {
+                                    Ok(()) => {}
+                                    Err(TrySendError::Full(_)) => tracing::warn!(
+                                        "Session {} resumed again before its replay started, \
+                                         dropped the later replay",
+                                        session_id
+                                    ),
+                                    Err(TrySendError::Closed(_)) => tracing::debug!(
+                                        "Session {} forwards no broadcasts, dropped its replay",
+                                        session_id
+                                    ),
                                 }
                             }
+                            Some(None) => send_error(
+                                &tx,
+                                ErrorCode::ResumeFailed,
+                                "Unknown or expired resume token, starting a new session",
+                                None,
+                            ),
+                            None => {}
                         }
                     }
-                    Err(e) => {
-                        let error = ServerMessage::Error {
-                            version: "1.0".to_string(),
-                            payload: ErrorPayload {
-                                code: "INVALID_MESSAGE".to_string(),
-                                message: format!("Failed to parse message: {}", e),
-                                request_id: None,
+                    Err(message) => {
+                        // Nothing else this client sends can be understood
+                        send_error(&tx, ErrorCode::VersionMismatch, message, None);
+                        let _ = tx.send(Message::Close(None));
+                        break;
+                    }
+                }
+            }
+            ClientMessage::Perform { payload, .. } => {
+                let PerformPayload { request_id, action } = payload;
+                // Validate the action before processing
+                match validate_perform_action(&action) {
+                    Ok(_) => {
+                        let schema = session_tx.borrow().schema;
+                        submit_action(
+                            event_tx,
+                            event_queue,
+                            schema,
+                            &tx,
+                            &session_id,
+                            action,
+                            request_id,
+                        )
+                        .await
+                    }
+                    Err(validation_error) => send_error(
+                        &tx,
+                        ErrorCode::ValidationError,
+                        validation_error,
+                        request_id,
+                    ),
+                }
+            }
+            ClientMessage::Subscribe { payload, .. } => {
+                let SubscribePayload {
+                    request_id,
+                    channels,
+                    fields,
+                } = payload;
+                let schema = session_tx.borrow().schema;
+                match Subscription::new(channels, fields, |channel| {
+                    snapshot_fields(channel, schema)
+                }) {
+                    Ok(subscription) => {
+                        tracing::debug!("Session {} subscribed to {:?}", session_id, subscription);
+                        session_tx.send_modify(|session| {
+                            session.subscription = subscription.clone();
+                        });
+                        send_message(
+                            &tx,
+                            &ServerMessage::Subscribed {
+                                version: PROTOCOL_VERSION.to_string(),
+                                payload: SubscribedPayload {
+                                    request_id,
+                                    subscription,
+                                },
                             },
-                        };
-                        if let Ok(json) = serde_json::to_string(&error) {
-                            let _ = tx.send(Message::Text(json.into()));
-                        }
+                        );
+                    }
+                    Err(message) => {
+                        send_error(&tx, ErrorCode::ValidationError, message, request_id)
                     }
                 }
             }
-            Ok(Message::Close(_)) => break,
-            Ok(_) => {} // Ignore other message types
-            Err(_) => break,
+            ClientMessage::Ping { payload, .. } => {
+                // Echo back ping (could add pong message type later)
+                tracing::debug!(
+                    "Received ping from session {} (timestamp {})",
+                    session_id,
+                    payload.timestamp
+                );
+            }
+            ClientMessage::SetScene { payload, .. } => {
+                let SetScenePayload {
+                    request_id,
+                    scene_name,
+                } = payload;
+                if scene_name.trim().is_empty() {
+                    send_error(
+                        &tx,
+                        ErrorCode::ValidationError,
+                        "Scene name cannot be empty",
+                        request_id,
+                    );
+                    continue;
+                }
+
+                // For now, treat as scene perform action
+                let action = PerformAction::Scene { name: scene_name };
+                let schema = session_tx.borrow().schema;
+                submit_action(
+                    event_tx,
+                    event_queue,
+                    schema,
+                    &tx,
+                    &session_id,
+                    action,
+                    request_id,
+                )
+                .await;
+            }
+            ClientMessage::Calibrate { payload, .. } => {
+                // A step holds its point for a while; keep reading meanwhile
+                let calibration = Arc::clone(&state.calibration);
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let request_id = payload.request_id;
+                    match calibration.step().await {
+                        Ok(status) => {
+                            send_message(
+                                &tx,
+                                &ServerMessage::Calibration {
+                                    version: PROTOCOL_VERSION.to_string(),
+                                    payload: CalibrationPayload { request_id, status },
+                                },
+                            );
+                        }
+                        Err(e) => send_error(&tx, e.code(), e.to_string(), request_id),
+                    }
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    #[tokio::test]
+    async fn test_quiet_session_stops_forwarding_when_the_connection_closes() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        // Subscribed to nothing, so no frame ever wakes the task
+        let (_session_tx, session_rx) = watch::channel(Session {
+            id: "quiet".to_string(),
+            resume_token: "token".to_string(),
+            role: Some(Role::Viewer),
+            schema: SchemaVersion::default(),
+            encoding: Encoding::default(),
+            subscription: Subscription {
+                channels: Vec::new(),
+                fields: Default::default(),
+            },
+        });
+        let task = tokio::spawn(forward_snapshots(
+            Arc::new(SnapshotFanout::new()),
+            tx,
+            session_rx,
+        ));
+        drop(rx);
+        tokio::time::timeout(std::time::Duration::from_secs(1), task)
+            .await
+            .expect("forwarding outlived the connection")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_layers_request_changes_only_the_named_layers() {
+        let request = |body: Value| serde_json::from_value::<LayersRequest>(body).unwrap();
+        let current = LayerAmounts {
+            grains: 0.4,
+            ..LayerAmounts::default()
+        };
+
+        let layers = request(json!({"drone": false, "texture": 0.25, "wind": true}))
+            .apply(current)
+            .unwrap();
+        assert_eq!(
+            (layers.drone, layers.texture, layers.wind),
+            (0.0, 0.25, 1.0)
+        );
+        assert_eq!(
+            (layers.sparkle, layers.samples, layers.grains),
+            (1.0, 1.0, 0.4)
+        );
+        assert_eq!(request(json!({})).apply(current).unwrap(), current);
+
+        // One bad level rejects the whole request, naming the layer
+        for body in [
+            json!({"drone": 0.5, "sparkle": 1.5}),
+            json!({"grains": -0.1}),
+        ] {
+            let error = request(body).apply(current).unwrap_err();
+            assert!(
+                error.contains("must be a boolean or between 0.0 and 1.0"),
+                "{}",
+                error
+            );
         }
+        assert!(
+            request(json!({"sparkle": 1.5}))
+                .apply(current)
+                .unwrap_err()
+                .starts_with("sparkle")
+        );
+        assert!(serde_json::from_value::<LayersRequest>(json!({"bass": true})).is_err());
     }
 }
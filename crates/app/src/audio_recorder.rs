@@ -0,0 +1,249 @@
+//! Rolling recording of the output, for keeping a moment after it happened.
+//!
+//! A thread drains the recorder's [`OutputTap`] into a [`RollingBuffer`]
+//! holding the last `buffer_minutes`, which `POST /record/save` writes to
+//! `take-<UTC time>.wav`. With `archive`, the thread also writes everything
+//! to `archive-<UTC time>.wav`, starting a new file each UTC hour, when the
+//! output format changes or before a file outgrows [`MAX_ARCHIVE_BYTES`],
+//! and keeping the newest `keep_files`.
+
+use crate::config::AudioRecorderConfig;
+use crate::runtime::unix_time_ms;
+use audio::backend::OutputFormat;
+use audio::rolling::{RollingBuffer, WavFile, write_wav};
+use audio::tap::OutputTap;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// How often the recorder drains its tap.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Most sample bytes in one archive file. WAV sizes are 32-bit, so a file
+/// past 4 GiB is broken; an hour of 32-bit float output reaches that at 7
+/// channels (48 kHz), and half of it keeps clear of readers that take the
+/// size as signed.
+const MAX_ARCHIVE_BYTES: u64 = 2 << 30;
+
+/// UTC time of `timestamp_ms` as `YYYY-MM-DDTHH-MM-SSZ`, safe in file names
+/// and sorting chronologically.
+fn file_time(timestamp_ms: u64) -> String {
+    time::OffsetDateTime::from_unix_timestamp((timestamp_ms / 1000) as i64)
+        .map(|t| {
+            format!(
+                "{}T{:02}-{:02}-{:02}Z",
+                t.date(),
+                t.hour(),
+                t.minute(),
+                t.second()
+            )
+        })
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// The UTC hour containing `timestamp_ms`, which an archive file covers.
+fn hour(timestamp_ms: u64) -> u64 {
+    timestamp_ms / 3_600_000
+}
+
+/// A saved take.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Take {
+    #[schema(value_type = String)]
+    pub path: PathBuf,
+    pub secs: f64,
+}
+
+/// The rolling buffer and where takes are saved, for the API.
+#[derive(Clone)]
+pub struct AudioRecorder {
+    dir: PathBuf,
+    buffer: Arc<Mutex<RollingBuffer>>,
+}
+
+impl AudioRecorder {
+    /// Writes what the buffer holds to a take stamped `timestamp_ms`.
+    /// Blocks while writing.
+    pub fn save(&self, timestamp_ms: u64) -> Result<Take, hound::Error> {
+        let (format, samples, secs) = {
+            let buffer = self.buffer.lock().unwrap();
+            (buffer.format(), buffer.samples(), buffer.secs())
+        };
+        let path = self
+            .dir
+            .join(format!("take-{}.wav", file_time(timestamp_ms)));
+        write_wav(&path, format, &samples)?;
+        info!("Saved {:.1} s of audio to {}", secs, path.display());
+        Ok(Take { path, secs })
+    }
+}
+
+/// The archive file being written and the hour it covers.
+struct Archive {
+    dir: PathBuf,
+    keep_files: usize,
+    /// Size a file is rotated at, [`MAX_ARCHIVE_BYTES`] outside tests.
+    max_bytes: u64,
+    current: Option<(u64, WavFile)>,
+}
+
+impl Archive {
+    /// Appends `samples` of `format` at `timestamp_ms`, starting a new file
+    /// for a new hour or format, or when the file would outgrow `max_bytes`.
+    fn write(
+        &mut self,
+        format: OutputFormat,
+        samples: &[f32],
+        timestamp_ms: u64,
+    ) -> Result<(), hound::Error> {
+        let hour = hour(timestamp_ms);
+        if self.current.as_ref().is_none_or(|(open, file)| {
+            *open != hour
+                || file.format() != format
+                || file.data_bytes() + 4 * samples.len() as u64 > self.max_bytes
+        }) {
+            if let Some((_, file)) = self.current.take() {
+                file.finalize()?;
+            }
+            let path = self
+                .dir
+                .join(format!("archive-{}.wav", file_time(timestamp_ms)));
+            info!("Archiving audio to {}", path.display());
+            self.current = Some((hour, WavFile::create(&path, format)?));
+            if self.keep_files > 0
+                && let Err(e) = prune_archive_files(&self.dir, self.keep_files)
+            {
+                warn!("Failed to prune old archive files: {}", e);
+            }
+        }
+        let Some((_, file)) = &mut self.current else {
+            return Ok(());
+        };
+        file.write(samples)?;
+        file.flush()
+    }
+}
+
+/// Deletes the oldest archive files beyond the newest `keep`.
+fn prune_archive_files(dir: &Path, keep: usize) -> std::io::Result<()> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name.starts_with("archive-") && name.ends_with(".wav") {
+            files.push(name);
+        }
+    }
+    // ISO times sort chronologically
+    files.sort();
+    let excess = files.len().saturating_sub(keep);
+    for name in &files[..excess] {
+        info!("Removing old archive file {}", name);
+        std::fs::remove_file(dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// Starts the recorder thread on `tap`, writing into `dir`.
+pub fn start_audio_recorder(
+    dir: PathBuf,
+    config: &AudioRecorderConfig,
+    tap: Arc<OutputTap>,
+) -> std::io::Result<AudioRecorder> {
+    std::fs::create_dir_all(&dir)?;
+    let recorder = AudioRecorder {
+        dir: dir.clone(),
+        buffer: Arc::new(Mutex::new(RollingBuffer::new(config.buffer_minutes * 60.0))),
+    };
+    let buffer = Arc::clone(&recorder.buffer);
+    let mut archive = config.archive.then(|| Archive {
+        dir: dir.clone(),
+        keep_files: config.keep_files,
+        max_bytes: MAX_ARCHIVE_BYTES,
+        current: None,
+    });
+    tap.set_listening(true);
+    std::thread::Builder::new()
+        .name("recorder".to_string())
+        .spawn(move || {
+            loop {
+                std::thread::sleep(DRAIN_INTERVAL);
+                let Some((format, samples)) = tap.take() else {
+                    continue;
+                };
+                buffer.lock().unwrap().push(&samples, format);
+                if let Some(writer) = &mut archive
+                    && let Err(e) = writer.write(format, &samples, unix_time_ms())
+                {
+                    warn!("Archiving stopped: {}", e);
+                    archive = None;
+                }
+            }
+        })?;
+    info!(
+        "Recording the last {:.0} minutes of audio to {}{}",
+        config.buffer_minutes,
+        dir.display(),
+        if config.archive { ", archiving" } else { "" }
+    );
+    Ok(recorder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_time_is_utc_and_sortable() {
+        assert_eq!(file_time(0), "1970-01-01T00-00-00Z");
+        assert_eq!(file_time(1_792_195_199_000), "2026-10-16T23-59-59Z");
+        assert_eq!(hour(1_792_195_199_000) + 1, hour(1_792_195_200_000));
+    }
+
+    #[test]
+    fn test_archive_rotates_hourly_and_prunes() {
+        let dir = std::env::temp_dir().join(format!("archive-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut archive = Archive {
+            dir: dir.clone(),
+            keep_files: 3,
+            max_bytes: 1024,
+            current: None,
+        };
+        let format = OutputFormat::default();
+        let start = 1_792_191_600_000; // 2026-10-16T23:00:00Z
+        for minutes in [0, 30, 60, 61, 120, 121, 122] {
+            archive
+                .write(format, &[0.1; 96], start + minutes * 60_000)
+                .unwrap();
+        }
+        let mut names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "archive-2026-10-17T00-00-00Z.wav",
+                "archive-2026-10-17T01-00-00Z.wav",
+                "archive-2026-10-17T01-02-00Z.wav"
+            ]
+        );
+        // The full first hour of the kept files, flushed as it was written
+        let reader = hound::WavReader::open(dir.join(&names[0])).unwrap();
+        assert_eq!(reader.len(), 192);
+
+        let recorder = AudioRecorder {
+            dir: dir.clone(),
+            buffer: Arc::new(Mutex::new(RollingBuffer::new(1.0))),
+        };
+        recorder.buffer.lock().unwrap().push(&[0.2; 960], format);
+        let take = recorder.save(start).unwrap();
+        assert!((take.secs - 0.01).abs() < 1e-9);
+        assert!(take.path.ends_with("take-2026-10-16T23-00-00Z.wav"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+//! Append-only audit log of non-tick events and admin changes.
+//!
+//! Each line is a JSON record of when an event was applied or a setting
+//! changed, at which world tick, and where it came from.
+
+use ambient_core::events::{Event, EventSource};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Records buffered between the world task and the writer before new ones are dropped.
+pub const AUDIT_CHANNEL_CAPACITY: usize = 256;
+
+/// One line of the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp_ms: u64,
+    /// World tick at which the event was applied.
+    pub tick: u64,
+    pub source: Option<EventSource>,
+    /// `X-Request-Id` of the REST request behind the record.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub action: AuditAction,
+}
+
+/// What an audit record is about, under its own key (`event`, `tick_rate`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// An event applied to the world.
+    Event(Event),
+    /// The tick rate set through `POST /admin/tick_rate`.
+    TickRate { from_hz: f64, to_hz: f64 },
+}
+
+/// Starts the audit writer task.
+///
+/// This task:
+/// - Opens the log file in append mode, creating it if needed.
+/// - Writes each received record as one JSON line and flushes it.
+/// - Exits when the record channel closes or the file cannot be opened.
+pub async fn start_audit_task(path: PathBuf, mut record_rx: mpsc::Receiver<AuditRecord>) {
+    let mut file = match OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await
+    {
+        Ok(file) => file,
+        Err(e) => {
+            warn!(
+                "Audit log disabled, failed to open {}: {}",
+                path.display(),
+                e
+            );
+            return;
+        }
+    };
+    info!("Writing audit log to {}", path.display());
+
+    while let Some(record) = record_rx.recv().await {
+        let Ok(mut line) = serde_json::to_string(&record) else {
+            continue;
+        };
+        line.push('\n');
+        if let Err(e) = file.write_all(line.as_bytes()).await {
+            warn!("Failed to write audit record: {}", e);
+            continue;
+        }
+        let _ = file.flush().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambient_core::events::PerformAction;
+
+    #[tokio::test]
+    async fn test_audit_task_appends_json_lines() {
+        let path = std::env::temp_dir().join(format!("ambient-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let record = AuditRecord {
+            timestamp_ms: 1_700_000_000_000,
+            tick: 42,
+            source: Some(EventSource::Session("ws-1".to_string())),
+            request_id: None,
+            action: AuditAction::Event(Event::Perform(PerformAction::Calm { intensity: 0.5 })),
+        };
+        let change = AuditRecord {
+            source: Some(EventSource::ApiKey("ops".to_string())),
+            request_id: Some("deploy-7".to_string()),
+            action: AuditAction::TickRate {
+                from_hz: 20.0,
+                to_hz: 60.0,
+            },
+            ..record.clone()
+        };
+        let line = serde_json::to_value(&change).unwrap();
+        assert_eq!(line["tick_rate"]["to_hz"], 60.0);
+        assert_eq!(line["request_id"], "deploy-7");
+        assert!(
+            serde_json::to_value(&record)
+                .unwrap()
+                .get("request_id")
+                .is_none()
+        );
+
+        for _ in 0..2 {
+            let (tx, rx) = mpsc::channel(4);
+            tx.send(record.clone()).await.unwrap();
+            tx.send(change.clone()).await.unwrap();
+            drop(tx);
+            start_audit_task(path.clone(), rx).await;
+        }
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<AuditRecord> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            records,
+            vec![record.clone(), change.clone(), record, change]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
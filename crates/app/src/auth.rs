@@ -0,0 +1,207 @@
+//! Static bearer-token authentication with viewer and controller roles.
+//!
+//! Tokens come from the `[[auth.tokens]]` config entries. With no tokens
+//! configured, auth is disabled and every caller is treated as a controller.
+
+use crate::error::ApiError;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::http::{HeaderMap, header, request::Parts};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// What a caller may do. Controllers can do everything viewers can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read state and subscribe to snapshots.
+    Viewer,
+    /// Also send events and change scenes.
+    Controller,
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Role::Viewer => "viewer",
+            Role::Controller => "controller",
+        })
+    }
+}
+
+/// One configured API token.
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenConfig {
+    /// Label recorded as the event source in the audit log.
+    pub name: String,
+    pub token: String,
+    pub role: Role,
+}
+
+// Keep secrets out of the startup config dump
+impl std::fmt::Debug for TokenConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenConfig")
+            .field("name", &self.name)
+            .field("token", &"<redacted>")
+            .field("role", &self.role)
+            .finish()
+    }
+}
+
+/// An authenticated caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// Token name, or None when auth is disabled.
+    pub name: Option<String>,
+    pub role: Role,
+}
+
+impl Principal {
+    pub fn require(&self, role: Role) -> Result<(), AuthError> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err(AuthError::Forbidden { required: role })
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum AuthError {
+    #[error("missing bearer token")]
+    Missing,
+    #[error("invalid token")]
+    Invalid,
+    #[error("requires the {required} role")]
+    Forbidden { required: Role },
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// Token registry shared by the HTTP and WebSocket handlers.
+#[derive(Debug, Clone, Default)]
+pub struct Auth {
+    tokens: Arc<Vec<TokenConfig>>,
+}
+
+impl Auth {
+    pub fn new(tokens: Vec<TokenConfig>) -> Self {
+        Self {
+            tokens: Arc::new(tokens),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Resolves a presented token to a principal.
+    pub fn authenticate(&self, token: Option<&str>) -> Result<Principal, AuthError> {
+        if !self.is_enabled() {
+            return Ok(Principal {
+                name: None,
+                role: Role::Controller,
+            });
+        }
+        let token = token.ok_or(AuthError::Missing)?;
+        // Check every entry so timing does not reveal which one matched
+        let mut found = None;
+        for entry in self.tokens.iter() {
+            if constant_time_eq(entry.token.as_bytes(), token.as_bytes()) {
+                found = Some(entry);
+            }
+        }
+        found
+            .map(|entry| Principal {
+                name: Some(entry.name.clone()),
+                role: entry.role,
+            })
+            .ok_or(AuthError::Invalid)
+    }
+}
+
+/// Extracts the token from an `Authorization: Bearer <token>` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+impl<S> FromRequestParts<S> for Principal
+where
+    Auth: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Auth::from_ref(state).authenticate(bearer_token(&parts.headers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> Auth {
+        Auth::new(vec![
+            TokenConfig {
+                name: "lobby-screen".to_string(),
+                token: "view-secret".to_string(),
+                role: Role::Viewer,
+            },
+            TokenConfig {
+                name: "installer".to_string(),
+                token: "control-secret".to_string(),
+                role: Role::Controller,
+            },
+        ])
+    }
+
+    #[test]
+    fn test_disabled_auth_allows_everything() {
+        let principal = Auth::default().authenticate(None).unwrap();
+        assert_eq!(principal.role, Role::Controller);
+        assert_eq!(principal.name, None);
+    }
+
+    #[test]
+    fn test_roles() {
+        let auth = auth();
+        assert_eq!(auth.authenticate(None), Err(AuthError::Missing));
+        assert_eq!(auth.authenticate(Some("nope")), Err(AuthError::Invalid));
+
+        let viewer = auth.authenticate(Some("view-secret")).unwrap();
+        assert_eq!(viewer.name.as_deref(), Some("lobby-screen"));
+        assert!(viewer.require(Role::Viewer).is_ok());
+        assert_eq!(
+            viewer.require(Role::Controller),
+            Err(AuthError::Forbidden {
+                required: Role::Controller
+            })
+        );
+
+        let controller = auth.authenticate(Some("control-secret")).unwrap();
+        assert!(controller.require(Role::Controller).is_ok());
+    }
+
+    #[test]
+    fn test_token_is_redacted_in_debug() {
+        let debug = format!("{:?}", auth());
+        assert!(!debug.contains("secret"));
+    }
+}
@@ -0,0 +1,731 @@
+//! Calibration: measuring a room's speakers instead of guessing at gains.
+//!
+//! `POST /audio/calibration` takes the audio off the world: every audio
+//! parameter is pinned to a neutral baseline, as a manual override renewed
+//! until the calibration ends, which `/audio/override` may not touch in the
+//! meantime, and the loudness auto-gain's trim is held so it cannot move
+//! under the measurements. Each parameter in turn is then swept across
+//! its range in `audio.calibration.steps` points. Every point is held for
+//! `settle_ms`, and the loudest output meter reading of the second half of
+//! the hold is recorded, while the `analysis` stream shows the levels live.
+//! In `auto` mode the sweep runs by itself; in `manual` mode each point waits
+//! for a `POST /audio/calibration/step` or a `calibrate` WebSocket message,
+//! so someone can walk the room between steps.
+//!
+//! When the last point is in, the [`CalibrationReport`] gives, for each
+//! parameter, the first value that clipped or drove the limiter, and
+//! suggests layer gains: today's gains trimmed by the same amount so the
+//! loudest point lands on the loudness target without its peaks clipping.
+//! With `audio.calibration.path` set the report is saved there and its
+//! gains take effect at once, and on every later start in place of the
+//! `audio.*_gain` values.
+
+use crate::error::ApiError;
+use crate::patches::PatchGains;
+use crate::runtime::{AudioOverride, unix_time_ms};
+use audio::master::{METER_FLOOR_DB, MeterReading, SharedMeter};
+use audio::mixer::{LayerGains, SharedLayerGains};
+use audio::params::{AudioParams, MAX_PRE_DELAY_MS, ParamOverrides};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// How long the override pinning the parameters lasts unless renewed, so
+/// the audio goes back to the world soon after a calibration is lost.
+const HOLD: Duration = Duration::from_secs(60);
+
+/// How often a running calibration renews its override.
+const RENEW_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How often the meter is read through the second half of a hold.
+const READ_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Limiter gain reduction that counts as clipping.
+const LIMITING_DB: f32 = 1.0;
+
+/// Largest trim a report suggests, either way.
+const MAX_TRIM_DB: f32 = 24.0;
+
+/// Highest layer gain the config accepts.
+const MAX_LAYER_GAIN: f32 = 2.0;
+
+/// How calibrations run (`[audio.calibration]`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalibrationConfig {
+    /// Points each parameter is swept across.
+    pub steps: usize,
+    /// How long each point is held before and while it is measured.
+    pub settle_ms: u64,
+    /// Peak level, in dBFS, at or above which a point counts as clipping.
+    pub clip_db: f32,
+    /// Master gain of the baseline the other parameters are swept from.
+    pub master_gain: f32,
+    /// JSON file reports are saved to. Its suggested gains replace the
+    /// `audio.*_gain` values. Reports are only returned when unset.
+    pub path: Option<PathBuf>,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            steps: 8,
+            settle_ms: 1500,
+            clip_db: -1.0,
+            master_gain: 0.5,
+            path: None,
+        }
+    }
+}
+
+impl CalibrationConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(2..=64).contains(&self.steps) {
+            return Err(format!(
+                "audio.calibration.steps must be in [2, 64], got {}",
+                self.steps
+            ));
+        }
+        if !(100..=60_000).contains(&self.settle_ms) {
+            return Err(format!(
+                "audio.calibration.settle_ms must be in [100, 60000], got {}",
+                self.settle_ms
+            ));
+        }
+        if !(-24.0..=0.0).contains(&self.clip_db) {
+            return Err(format!(
+                "audio.calibration.clip_db must be in [-24, 0], got {}",
+                self.clip_db
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.master_gain) {
+            return Err(format!(
+                "audio.calibration.master_gain must be in [0, 1], got {}",
+                self.master_gain
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// An audio parameter a calibration sweeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CalibrationParam {
+    MasterGain,
+    BaseFreqHz,
+    DetuneRatio,
+    Brightness,
+    Motion,
+    Texture,
+    ReverbSize,
+    PreDelayMs,
+    Grit,
+}
+
+impl CalibrationParam {
+    pub const ALL: [CalibrationParam; 9] = [
+        CalibrationParam::MasterGain,
+        CalibrationParam::BaseFreqHz,
+        CalibrationParam::DetuneRatio,
+        CalibrationParam::Brightness,
+        CalibrationParam::Motion,
+        CalibrationParam::Texture,
+        CalibrationParam::ReverbSize,
+        CalibrationParam::PreDelayMs,
+        CalibrationParam::Grit,
+    ];
+
+    /// Lowest and highest value swept. The base frequency covers the
+    /// default mapping's range; the drone never plays outside it.
+    fn range(self) -> (f32, f32) {
+        match self {
+            CalibrationParam::BaseFreqHz => (80.0, 240.0),
+            CalibrationParam::DetuneRatio => (0.5, 2.0),
+            CalibrationParam::PreDelayMs => (0.0, MAX_PRE_DELAY_MS),
+            _ => (0.0, 1.0),
+        }
+    }
+
+    /// The `steps` values a sweep visits, low to high. Frequencies are
+    /// spaced evenly in pitch, the rest evenly.
+    pub fn sweep(self, steps: usize) -> Vec<f32> {
+        let (low, high) = self.range();
+        (0..steps)
+            .map(|i| {
+                let t = i as f32 / (steps - 1).max(1) as f32;
+                match self {
+                    CalibrationParam::BaseFreqHz => low * (high / low).powf(t),
+                    _ => low + (high - low) * t,
+                }
+            })
+            .collect()
+    }
+
+    fn set(self, overrides: &mut ParamOverrides, value: f32) {
+        let field = match self {
+            CalibrationParam::MasterGain => &mut overrides.master_gain,
+            CalibrationParam::BaseFreqHz => &mut overrides.base_freq_hz,
+            CalibrationParam::DetuneRatio => &mut overrides.detune_ratio,
+            CalibrationParam::Brightness => &mut overrides.brightness,
+            CalibrationParam::Motion => &mut overrides.motion,
+            CalibrationParam::Texture => &mut overrides.texture,
+            CalibrationParam::ReverbSize => &mut overrides.reverb_size,
+            CalibrationParam::PreDelayMs => &mut overrides.pre_delay_ms,
+            CalibrationParam::Grit => &mut overrides.grit,
+        };
+        *field = Some(value);
+    }
+}
+
+/// Whether a calibration steps itself or waits to be stepped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CalibrationMode {
+    #[default]
+    Auto,
+    Manual,
+}
+
+/// Body of `POST /audio/calibration`.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalibrationRequest {
+    pub mode: CalibrationMode,
+    /// Parameters to sweep, in order; all of them when empty.
+    pub params: Vec<CalibrationParam>,
+}
+
+/// One measured point of a sweep.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CalibrationPoint {
+    pub param: CalibrationParam,
+    pub value: f32,
+    pub peak_db: f32,
+    pub momentary_lufs: f32,
+    pub gain_reduction_db: f32,
+}
+
+impl CalibrationPoint {
+    fn clips(&self, clip_db: f32) -> bool {
+        self.peak_db >= clip_db || self.gain_reduction_db >= LIMITING_DB
+    }
+}
+
+/// What one parameter's sweep found.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ParamReport {
+    pub param: CalibrationParam,
+    /// First value of the sweep that clipped or drove the limiter; none if
+    /// no value did.
+    pub clip_value: Option<f32>,
+    /// Loudest peak and momentary loudness over the sweep.
+    pub peak_db: f32,
+    pub momentary_lufs: f32,
+}
+
+/// The outcome of a calibration, as returned and saved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CalibrationReport {
+    pub finished_at_ms: u64,
+    /// Loudness the suggested gains aim the loudest point at.
+    pub target_lufs: f32,
+    pub clip_db: f32,
+    /// Change the suggestion makes to every layer gain, in dB.
+    pub trim_db: f32,
+    pub suggested_gains: PatchGains,
+    pub params: Vec<ParamReport>,
+    pub points: Vec<CalibrationPoint>,
+}
+
+impl CalibrationReport {
+    /// Sums up `points`, measured with the layers at `gains`.
+    pub fn new(
+        points: Vec<CalibrationPoint>,
+        gains: LayerGains,
+        target_lufs: f32,
+        clip_db: f32,
+        finished_at_ms: u64,
+    ) -> Self {
+        let mut params: Vec<ParamReport> = Vec::new();
+        for point in &points {
+            match params.iter_mut().find(|p| p.param == point.param) {
+                Some(report) => {
+                    report.peak_db = report.peak_db.max(point.peak_db);
+                    report.momentary_lufs = report.momentary_lufs.max(point.momentary_lufs);
+                }
+                None => params.push(ParamReport {
+                    param: point.param,
+                    clip_value: None,
+                    peak_db: point.peak_db,
+                    momentary_lufs: point.momentary_lufs,
+                }),
+            }
+            let report = params.iter_mut().find(|p| p.param == point.param).unwrap();
+            if report.clip_value.is_none() && point.clips(clip_db) {
+                report.clip_value = Some(point.value);
+            }
+        }
+        let loudest = |f: fn(&CalibrationPoint) -> f32| {
+            points.iter().map(f).fold(f32::NEG_INFINITY, f32::max)
+        };
+        let peak_db = loudest(|p| p.peak_db);
+        // Silence says nothing about the room
+        let trim_db = if peak_db > METER_FLOOR_DB {
+            let lufs = loudest(|p| p.momentary_lufs);
+            (target_lufs - lufs)
+                .min(clip_db - peak_db)
+                .clamp(-MAX_TRIM_DB, MAX_TRIM_DB)
+        } else {
+            0.0
+        };
+        let factor = 10f32.powf(trim_db / 20.0);
+        let trim = |gain: f32| (gain * factor).clamp(0.0, MAX_LAYER_GAIN);
+        let suggested = LayerGains {
+            drone: trim(gains.drone),
+            texture: trim(gains.texture),
+            sparkle: trim(gains.sparkle),
+            samples: trim(gains.samples),
+            grains: trim(gains.grains),
+            wind: trim(gains.wind),
+        };
+        Self {
+            finished_at_ms,
+            target_lufs,
+            clip_db,
+            trim_db,
+            suggested_gains: suggested.into(),
+            params,
+            points,
+        }
+    }
+}
+
+/// Reads a saved report, if there is one.
+pub fn load_report(path: &Path) -> Result<Option<CalibrationReport>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| format!("failed to parse {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Writes a report to a temporary file and renames it over the old one, so
+/// a crash mid-write never leaves a truncated file.
+async fn save_report(path: &Path, report: &CalibrationReport) -> std::io::Result<()> {
+    let json = serde_json::to_vec_pretty(report)?;
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, json).await?;
+    tokio::fs::rename(&tmp, path).await
+}
+
+/// Where calibration stands, as returned by `/audio/calibration` and sent in
+/// `calibration` messages.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CalibrationStatus {
+    pub running: bool,
+    /// Mode of the running calibration.
+    pub mode: Option<CalibrationMode>,
+    /// Points measured so far, and in all.
+    pub measured: usize,
+    pub total: usize,
+    /// The point the next step measures.
+    pub next_param: Option<CalibrationParam>,
+    pub next_value: Option<f32>,
+    /// The point measured last.
+    pub last: Option<CalibrationPoint>,
+    /// The latest finished calibration's report.
+    pub report: Option<CalibrationReport>,
+}
+
+struct Session {
+    id: u64,
+    mode: CalibrationMode,
+    plan: Vec<(CalibrationParam, f32)>,
+    points: Vec<CalibrationPoint>,
+}
+
+#[derive(Default)]
+struct State {
+    session: Option<Session>,
+    report: Option<CalibrationReport>,
+    started: u64,
+}
+
+/// Runs calibrations: pins the audio parameters, sweeps them and reads the
+/// meter.
+pub struct Calibrator {
+    config: CalibrationConfig,
+    target_lufs: f32,
+    meter: Arc<SharedMeter>,
+    gains: Arc<SharedLayerGains>,
+    override_tx: watch::Sender<Option<AudioOverride>>,
+    state: std::sync::Mutex<State>,
+    /// Held while a point is measured, so steps never overlap.
+    measuring: tokio::sync::Mutex<()>,
+}
+
+impl Calibrator {
+    pub fn new(
+        config: CalibrationConfig,
+        target_lufs: f32,
+        meter: Arc<SharedMeter>,
+        gains: Arc<SharedLayerGains>,
+        override_tx: watch::Sender<Option<AudioOverride>>,
+    ) -> Self {
+        Self {
+            config,
+            target_lufs,
+            meter,
+            gains,
+            override_tx,
+            state: std::sync::Mutex::new(State::default()),
+            measuring: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Whether a calibration holds the audio parameters.
+    pub fn running(&self) -> bool {
+        self.state.lock().unwrap().session.is_some()
+    }
+
+    pub fn status(&self) -> CalibrationStatus {
+        let state = self.state.lock().unwrap();
+        let session = state.session.as_ref();
+        let next = session.and_then(|s| s.plan.get(s.points.len()));
+        CalibrationStatus {
+            running: session.is_some(),
+            mode: session.map(|s| s.mode),
+            measured: session.map_or(0, |s| s.points.len()),
+            total: session.map_or(0, |s| s.plan.len()),
+            next_param: next.map(|(param, _)| *param),
+            next_value: next.map(|(_, value)| *value),
+            last: session.and_then(|s| s.points.last().copied()),
+            report: state.report.clone(),
+        }
+    }
+
+    /// Starts a calibration, pinning the parameters to the baseline. An
+    /// `auto` one then sweeps in the background.
+    pub fn start(
+        self: &Arc<Self>,
+        request: CalibrationRequest,
+    ) -> Result<CalibrationStatus, ApiError> {
+        let params = if request.params.is_empty() {
+            CalibrationParam::ALL.to_vec()
+        } else {
+            request.params
+        };
+        if let Some((i, param)) = params
+            .iter()
+            .enumerate()
+            .find(|(i, param)| params[..*i].contains(param))
+        {
+            return Err(ApiError::Validation(format!(
+                "params[{}] repeats {:?}",
+                i, param
+            )));
+        }
+        let plan = params
+            .into_iter()
+            .flat_map(|param| {
+                param
+                    .sweep(self.config.steps)
+                    .into_iter()
+                    .map(move |value| (param, value))
+            })
+            .collect();
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            if state.session.is_some() {
+                return Err(ApiError::Conflict(
+                    "A calibration is already running".to_string(),
+                ));
+            }
+            state.started += 1;
+            state.session = Some(Session {
+                id: state.started,
+                mode: request.mode,
+                plan,
+                points: Vec::new(),
+            });
+            self.meter.hold_trim(true);
+            self.pin(self.baseline());
+            state.started
+        };
+        info!("Calibration started ({:?})", request.mode);
+        tokio::spawn(Arc::clone(self).keep_pinned(id));
+        if request.mode == CalibrationMode::Auto {
+            let calibrator = Arc::clone(self);
+            tokio::spawn(async move {
+                while let Ok(status) = calibrator.measure_next(id).await
+                    && status.running
+                {}
+            });
+        }
+        Ok(self.status())
+    }
+
+    /// Measures the next point of a manual calibration.
+    pub async fn step(&self) -> Result<CalibrationStatus, ApiError> {
+        let id = {
+            let state = self.state.lock().unwrap();
+            match &state.session {
+                None => {
+                    return Err(ApiError::Conflict("No calibration is running".to_string()));
+                }
+                Some(session) if session.mode == CalibrationMode::Auto => {
+                    return Err(ApiError::Conflict(
+                        "An auto calibration steps itself".to_string(),
+                    ));
+                }
+                Some(session) => session.id,
+            }
+        };
+        self.measure_next(id).await
+    }
+
+    /// Stops the running calibration without a report and hands the audio
+    /// back to the world. Returns false if none was running.
+    pub fn cancel(&self) -> bool {
+        let cancelled = self.state.lock().unwrap().session.take().is_some();
+        if cancelled {
+            info!("Calibration cancelled");
+            self.release();
+        }
+        cancelled
+    }
+
+    /// Hands the audio back to the world and lets the auto-gain move again.
+    fn release(&self) {
+        self.override_tx.send_replace(None);
+        self.meter.hold_trim(false);
+    }
+
+    /// Renews the override while session `id` runs, however long a manual
+    /// calibration waits between steps.
+    async fn keep_pinned(self: Arc<Self>, id: u64) {
+        let mut interval = tokio::time::interval(RENEW_INTERVAL);
+        loop {
+            interval.tick().await;
+            let state = self.state.lock().unwrap();
+            if state.session.as_ref().is_none_or(|s| s.id != id) {
+                return;
+            }
+            // Under the state lock, so a finish or cancel cannot slip in
+            // and have its release undone
+            self.override_tx.send_modify(|pin| {
+                if let Some(pin) = pin {
+                    pin.expires_at = Instant::now() + HOLD;
+                }
+            });
+        }
+    }
+
+    /// Every parameter at its default, the master gain at the configured one
+    /// and no sparkles.
+    fn baseline(&self) -> ParamOverrides {
+        let defaults = AudioParams::default();
+        ParamOverrides {
+            master_gain: Some(self.config.master_gain),
+            base_freq_hz: Some(defaults.base_freq_hz),
+            detune_ratio: Some(defaults.detune_ratio),
+            brightness: Some(defaults.brightness),
+            motion: Some(defaults.motion),
+            texture: Some(defaults.texture),
+            reverb_size: Some(defaults.reverb_size),
+            pre_delay_ms: Some(defaults.pre_delay_ms),
+            grit: Some(defaults.grit),
+            sparkle_impulse: Some(0.0),
+        }
+    }
+
+    fn pin(&self, params: ParamOverrides) {
+        self.override_tx.send_replace(Some(AudioOverride {
+            params,
+            expires_at: Instant::now() + HOLD,
+        }));
+    }
+
+    /// Holds the next point of session `id`, reads the meter and records
+    /// it, finishing the calibration after the last.
+    async fn measure_next(&self, id: u64) -> Result<CalibrationStatus, ApiError> {
+        let _measuring = self.measuring.lock().await;
+        let cancelled = || ApiError::Conflict("The calibration was cancelled".to_string());
+        let (param, value) = {
+            let state = self.state.lock().unwrap();
+            let session = state.session.as_ref().filter(|s| s.id == id);
+            let (param, value) = *session
+                .and_then(|s| s.plan.get(s.points.len()))
+                .ok_or_else(cancelled)?;
+            let mut params = self.baseline();
+            param.set(&mut params, value);
+            // Under the state lock, so a cancel cannot be undone
+            self.pin(params);
+            (param, value)
+        };
+
+        // Let the smoothing and the meter's fall settle, then keep the
+        // loudest reading
+        let half = Duration::from_millis(self.config.settle_ms) / 2;
+        tokio::time::sleep(half).await;
+        let until = Instant::now() + half;
+        let mut reading = self.meter.get();
+        while Instant::now() < until {
+            tokio::time::sleep(READ_INTERVAL).await;
+            reading = louder(reading, self.meter.get());
+        }
+        let point = CalibrationPoint {
+            param,
+            value,
+            peak_db: reading.peak_db,
+            momentary_lufs: reading.momentary_lufs,
+            gain_reduction_db: reading.gain_reduction_db,
+        };
+
+        let finished = {
+            let mut state = self.state.lock().unwrap();
+            let session = state
+                .session
+                .as_mut()
+                .filter(|s| s.id == id)
+                .ok_or_else(cancelled)?;
+            session.points.push(point);
+            if session.points.len() < session.plan.len() {
+                None
+            } else {
+                let points = state.session.take().map(|s| s.points).unwrap_or_default();
+                let report = CalibrationReport::new(
+                    points,
+                    self.gains.get(),
+                    self.target_lufs,
+                    self.config.clip_db,
+                    unix_time_ms(),
+                );
+                state.report = Some(report.clone());
+                Some(report)
+            }
+        };
+        if let Some(report) = finished {
+            self.finish(&report).await;
+        }
+        let mut status = self.status();
+        status.last = Some(point);
+        Ok(status)
+    }
+
+    /// Hands the audio back to the world and saves the report, putting its
+    /// gains into effect.
+    async fn finish(&self, report: &CalibrationReport) {
+        self.release();
+        info!(
+            "Calibration finished: {} points, suggested trim {:+.1} dB",
+            report.points.len(),
+            report.trim_db
+        );
+        let Some(path) = &self.config.path else {
+            return;
+        };
+        match save_report(path, report).await {
+            Ok(()) => self.gains.set(report.suggested_gains.into()),
+            Err(e) => warn!(
+                "Failed to save the calibration to {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+}
+
+/// The louder of two readings, field by field.
+fn louder(a: MeterReading, b: MeterReading) -> MeterReading {
+    MeterReading {
+        peak_db: a.peak_db.max(b.peak_db),
+        gain_reduction_db: a.gain_reduction_db.max(b.gain_reduction_db),
+        momentary_lufs: a.momentary_lufs.max(b.momentary_lufs),
+        ..b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(param: CalibrationParam, value: f32, peak_db: f32, lufs: f32) -> CalibrationPoint {
+        CalibrationPoint {
+            param,
+            value,
+            peak_db,
+            momentary_lufs: lufs,
+            gain_reduction_db: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_sweeps_cover_each_range() {
+        let gains = CalibrationParam::MasterGain.sweep(5);
+        assert_eq!(gains, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+        let freqs = CalibrationParam::BaseFreqHz.sweep(3);
+        assert!((freqs[0] - 80.0).abs() < 1e-3 && (freqs[2] - 240.0).abs() < 1e-2);
+        // Evenly spaced in pitch
+        assert!((freqs[1] / freqs[0] - freqs[2] / freqs[1]).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_report_finds_clipping_and_trims_the_gains() {
+        use CalibrationParam::{Grit, MasterGain};
+        let points = vec![
+            point(MasterGain, 0.0, METER_FLOOR_DB, -120.0),
+            point(MasterGain, 0.5, -9.0, -20.0),
+            point(MasterGain, 1.0, -0.5, -14.0),
+            point(Grit, 0.0, -10.0, -22.0),
+            CalibrationPoint {
+                gain_reduction_db: 2.0,
+                ..point(Grit, 1.0, -3.0, -18.0)
+            },
+        ];
+        let gains = LayerGains::default();
+        let report = CalibrationReport::new(points, gains, -24.0, -1.0, 7);
+        assert_eq!(report.params.len(), 2);
+        assert_eq!(report.params[0].clip_value, Some(1.0));
+        assert_eq!(report.params[0].peak_db, -0.5);
+        assert_eq!(report.params[1].clip_value, Some(1.0));
+        assert_eq!(report.params[1].momentary_lufs, -18.0);
+
+        // 10 dB over the loudness target outweighs 0.5 dB over the clip level
+        assert!((report.trim_db + 10.0).abs() < 1e-5);
+        let drone = report.suggested_gains.drone;
+        assert!((drone / gains.drone - 10f32.powf(-0.5)).abs() < 1e-5);
+
+        let silent = CalibrationReport::new(
+            vec![point(MasterGain, 1.0, METER_FLOOR_DB, -120.0)],
+            gains,
+            -24.0,
+            -1.0,
+            7,
+        );
+        assert_eq!(silent.trim_db, 0.0);
+        assert_eq!(silent.suggested_gains, gains.into());
+    }
+
+    #[test]
+    fn test_calibration_section() {
+        let config: crate::config::Config =
+            toml::from_str("[audio.calibration]\nsteps = 4\npath = \"calibration.json\"\n")
+                .unwrap();
+        let calibration = &config.audio.calibration;
+        assert_eq!(calibration.steps, 4);
+        assert_eq!(calibration.settle_ms, 1500);
+        assert!(calibration.validate().is_ok());
+
+        let mut calibration = calibration.clone();
+        calibration.steps = 1;
+        assert!(calibration.validate().unwrap_err().contains("steps"));
+    }
+}
@@ -0,0 +1,2506 @@
+//! Layered application configuration.
+//!
+//! Values are resolved in order of increasing precedence:
+//! built-in defaults, the TOML config file, environment variables, CLI flags.
+
+use crate::auth::TokenConfig;
+use crate::calibration::{CalibrationConfig, load_report};
+use crate::room::RoomConfig;
+use crate::runtime::TICK_RATE_RANGE;
+use crate::seasons::SeasonsConfig;
+use crate::sensors::SensorConfig;
+use ambient_core::agents::AgentSettings;
+use ambient_core::composer::{self, ArcSettings};
+use ambient_core::curves::Curve;
+use ambient_core::engine::{DEFAULT_STEP_HZ, STEP_HZ_RANGE, TIME_SCALE_RANGE};
+use ambient_core::field::FieldSettings;
+use ambient_core::pulse::PulseSettings;
+use ambient_core::scene::SceneTargets;
+use ambient_core::sparkles::SparkleModel;
+use ambient_core::world::{DriftRates, WorldDynamics};
+use audio::ambisonics::AmbisonicDecoder;
+use audio::backend::OutputFormat;
+use audio::ducking::DuckingSettings;
+use audio::effects::{EffectBus, EffectChains, EffectKind, MAX_CHAIN_LEN};
+use audio::gate::GateSettings;
+use audio::layers::{DroneMode, MAX_PARTIALS};
+use audio::loudness::LoudnessSettings;
+use audio::mapping::{DEFAULT_PROFILE, FieldCurve, MappingProfile, WorldInput};
+use audio::mixer::LayerGains;
+use audio::noise::NoiseColor;
+use audio::registry::LayerSpec;
+use audio::sample::Sample;
+use audio::smoother::{SmoothingSettings, SmoothingTimes};
+use audio::spatial::{
+    LayerPlacements, MAX_SPEAKERS, Panner, Placement, SpatialSettings, SpeakerLayout,
+};
+use axum::http::HeaderValue;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
+
+/// Config file used when `--config` is not given, if it exists.
+const DEFAULT_CONFIG_PATH: &str = "ambient.toml";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid config: {0}")]
+    Invalid(String),
+}
+
+/// Command-line flags. Each flag can also be set through its environment variable.
+#[derive(Debug, Default, Parser)]
+#[command(name = "ambient", about = "Ambient world simulation and audio engine")]
+pub struct Cli {
+    /// Path to a TOML config file
+    #[arg(long, env = "AMBIENT_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// World tick rate in Hz
+    #[arg(long, env = "TICK_HZ")]
+    pub tick_hz: Option<f64>,
+    /// HTTP port for the API server
+    #[arg(long, env = "PORT")]
+    pub port: Option<u16>,
+    /// Address the API servers listen on (e.g. 127.0.0.1 for local only)
+    #[arg(long, env = "BIND_ADDRESS")]
+    pub bind: Option<IpAddr>,
+    /// Port for the gRPC API (off unless set here or in the config file)
+    #[arg(long, env = "GRPC_PORT")]
+    pub grpc_port: Option<u16>,
+    /// Run without opening an audio output device
+    #[arg(long, env = "NO_AUDIO")]
+    pub no_audio: bool,
+    /// Log filter directive (e.g. "info" or "app=debug,audio=warn")
+    #[arg(long, env = "LOG_LEVEL")]
+    pub log_level: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub world: WorldConfig,
+    pub api: ApiConfig,
+    pub audio: AudioConfig,
+    pub logging: LoggingConfig,
+    pub auth: AuthConfig,
+    pub mqtt: MqttConfig,
+    pub recording: RecordingConfig,
+    pub midi: MidiConfig,
+    pub stream: StreamConfig,
+    pub alerts: AlertsConfig,
+    /// Physical inputs mapped to perform actions.
+    pub sensors: Vec<SensorConfig>,
+    pub room: RoomConfig,
+    pub composer: ComposerConfig,
+    pub seasons: SeasonsConfig,
+    /// TOML file of additional scenes, keyed by scene name.
+    pub scenes_path: Option<PathBuf>,
+    /// JSON file that captured presets are saved to. Kept in memory only when unset.
+    pub presets_path: Option<PathBuf>,
+    /// JSONL file that non-tick events are appended to. Disabled when unset.
+    pub audit_log: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldConfig {
+    pub tick_hz: f64,
+    /// Rate of the fixed internal timestep that ticks are split into.
+    pub step_hz: f64,
+    /// Multiplier on tick dt: above 1 fast-forwards the world, below 1 slows it.
+    pub time_scale: f64,
+    /// Fold ticks together instead of waiting when the event queue is full.
+    pub coalesce_ticks: bool,
+    pub drift_factor: f64,
+    pub decay_factor: f64,
+    pub space: SpaceConfig,
+    pub clarity: ClarityConfig,
+    pub sparkles: SparkleConfig,
+    pub pulse: PulseConfig,
+    pub agents: AgentConfig,
+    pub field: FieldConfig,
+    /// Seed of the noise visuals texture the parameters with, sent in the
+    /// hello; a random one when unset. 32 bits, so browsers read it exactly.
+    pub noise_seed: Option<u32>,
+}
+
+/// How space wanders (`[world.space]`): its own drift and decay rates, the
+/// shared ones by default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpaceConfig {
+    pub drift_factor: f64,
+    pub decay_factor: f64,
+}
+
+/// How clarity wanders (`[world.clarity]`): its own drift and decay rates,
+/// slower than the shared ones by default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClarityConfig {
+    pub drift_factor: f64,
+    pub decay_factor: f64,
+}
+
+/// Density, energy and warmth as grids over the unit square
+/// (`[world.field]`): the scalars are their means, local actions raise
+/// bumps that spread, and the grids stream to clients on the binary `field`
+/// channel. Off by default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FieldConfig {
+    pub enabled: bool,
+    /// Cells across and down.
+    pub width: u32,
+    pub height: u32,
+    /// How fast cells even out with their neighbours, per second.
+    pub diffusion: f64,
+    /// Radius of a local action's bump across a unit square.
+    pub radius: f64,
+}
+
+/// Agents that carry density and energy (`[world.agents]`): density is the
+/// population, energy their mean excitement, and their births and deaths
+/// fire the sparkles. Off by default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentConfig {
+    pub enabled: bool,
+    /// Population at density 1.
+    pub capacity: u32,
+    /// Mean lifespan in simulated seconds.
+    pub lifespan_secs: f64,
+    /// Distance a fully excited agent covers per second across a unit square.
+    pub speed: f64,
+}
+
+/// The pulse grid rhythm and energy set up (`[world.pulse]`): what locks to
+/// it. It always runs and sends beats; nothing follows it by default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PulseConfig {
+    /// Hold each sparkle until the next grid point.
+    pub quantize_sparkles: bool,
+    /// Grid points per beat for quantized sparkles.
+    pub subdivision: u32,
+    /// Land delay echoes on the grid's tempo instead of rhythm's own.
+    pub quantize_delay: bool,
+}
+
+/// How sparkles are timed (`[world.sparkles]`): a background rate that
+/// follows density, bursts that each sparkle sets off, and a quiet period
+/// after each. Times and rates are at rhythm 0.5; faster rhythm tightens
+/// showers.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SparkleConfig {
+    /// Background sparkles per second at density 0.5.
+    pub rate_hz: f64,
+    /// Hazard each sparkle adds, in sparkles per second; 0 turns bursts off.
+    pub burst_hz: f64,
+    /// Time constant of that hazard's decay.
+    pub burst_secs: f64,
+    pub refractory_secs: f64,
+}
+
+/// Long-form arcs steering the world's targets (`[composer]`). Off by
+/// default; scenes and presets keep the targets while it is.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComposerConfig {
+    pub enabled: bool,
+    /// Simulated seconds per build, peak and release cycle.
+    pub arc_secs: f64,
+    /// Highest intensity a peak reaches.
+    pub ceiling: f64,
+    /// How much arcs vary in length, peak and colour.
+    pub randomness: f64,
+    /// Seed for planning arcs; a random one when unset.
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    /// Address the HTTP, HTTPS and gRPC listeners bind to.
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Rate at which WebSocket clients receive snapshots.
+    pub snapshot_hz: f64,
+    /// Port for the gRPC service. Disabled when unset.
+    pub grpc_port: Option<u16>,
+    /// Origins allowed to call the API from a browser. `"*"` allows any.
+    pub cors_origins: Vec<String>,
+    /// HTTPS listener next to the plain HTTP one. Disabled when unset.
+    pub tls: Option<TlsConfig>,
+    /// Unix domain socket the API is also served on. Disabled when unset.
+    pub unix_socket: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    #[serde(default = "default_tls_port")]
+    pub port: u16,
+    /// PEM certificate chain.
+    pub cert: PathBuf,
+    /// PEM private key.
+    pub key: PathBuf,
+}
+
+fn default_tls_port() -> u16 {
+    3443
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    pub enabled: bool,
+    pub drone_gain: f32,
+    pub texture_gain: f32,
+    pub sparkle_gain: f32,
+    pub samples_gain: f32,
+    /// Directory of WAV/FLAC field recordings to loop under the synth layers.
+    pub samples_dir: Option<PathBuf>,
+    pub grains_gain: f32,
+    /// Name (file stem) of the sample to granulate; defaults to the first one.
+    pub grain_source: Option<String>,
+    pub wind_gain: f32,
+    /// Directory of audio patches, one `<name>.json` file each.
+    pub patches_dir: Option<PathBuf>,
+    /// Patch in `patches_dir` to start with, in place of the layers, effects
+    /// and gains configured here.
+    pub patch: Option<String>,
+    pub drone_mode: DroneModeConfig,
+    /// Partials in the `additive` drone mode (1 to 16).
+    pub drone_partials: usize,
+    pub ducking: DuckingConfig,
+    pub gate: GateConfig,
+    pub smoothing: SmoothingConfig,
+    pub loudness: LoudnessConfig,
+    pub mapping: MappingConfig,
+    pub effects: EffectsConfig,
+    pub noise: NoiseConfig,
+    pub output: OutputConfig,
+    pub spatial: SpatialConfig,
+    pub recorder: AudioRecorderConfig,
+    pub calibration: CalibrationConfig,
+}
+
+/// Where the mix is sent (`[audio.output]`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub backend: OutputBackendConfig,
+    /// Format of the `null` and `buffer` backends; a device picks its own.
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Seconds of output the `buffer` backend keeps.
+    pub capture_secs: f32,
+    /// WAV file the `buffer` capture is written to on shutdown.
+    pub capture_path: Option<PathBuf>,
+    /// Render the `buffer` capture in real time instead of as fast as possible.
+    pub realtime: bool,
+}
+
+/// Rolling recording of the output (`[audio.recorder]`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioRecorderConfig {
+    /// Directory saved and archived WAV files go to. The recorder is off
+    /// when unset.
+    pub dir: Option<PathBuf>,
+    /// Minutes of output kept for `POST /record/save`.
+    pub buffer_minutes: f32,
+    /// Also write everything, one file per UTC hour.
+    pub archive: bool,
+    /// Hour files to keep, deleting the oldest beyond that. 0 keeps them all.
+    pub keep_files: usize,
+}
+
+/// `cpal` (the default output device), `null` (render and discard) or
+/// `buffer` (render into memory, saved to `capture_path`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputBackendConfig {
+    #[default]
+    Cpal,
+    Null,
+    Buffer,
+}
+
+/// Speaker layout and where each layer sits on it (`[audio.spatial]`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpatialConfig {
+    pub layout: LayoutConfig,
+    pub panner: PannerConfig,
+    /// Where the `ambisonic` panner decodes to.
+    pub decoder: DecoderConfig,
+    /// Speakers in the `ring` layout.
+    pub speakers: usize,
+    /// Channel azimuths of the `custom` layout, in degrees clockwise from the front.
+    pub azimuths: Vec<f32>,
+    pub drone: PlacementConfig,
+    pub texture: PlacementConfig,
+    pub sparkle: PlacementConfig,
+    pub samples: PlacementConfig,
+    pub grains: PlacementConfig,
+    pub wind: PlacementConfig,
+}
+
+impl Default for SpatialConfig {
+    fn default() -> Self {
+        Self {
+            layout: LayoutConfig::Mono,
+            panner: PannerConfig::Pairwise,
+            decoder: DecoderConfig::Speakers,
+            speakers: 8,
+            azimuths: Vec::new(),
+            drone: PlacementConfig::default(),
+            texture: PlacementConfig::default(),
+            sparkle: PlacementConfig::default(),
+            samples: PlacementConfig::default(),
+            grains: PlacementConfig::default(),
+            wind: PlacementConfig::default(),
+        }
+    }
+}
+
+/// `mono` (the same mix on every channel), `stereo`, `quad`, `5.1`, `7.1`,
+/// `ring` (`speakers` evenly spaced) or `custom` (one channel per azimuth).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutConfig {
+    #[default]
+    Mono,
+    Stereo,
+    Quad,
+    #[serde(rename = "5.1")]
+    Surround51,
+    #[serde(rename = "7.1")]
+    Surround71,
+    Ring,
+    Custom,
+}
+
+/// `pairwise` (between the two nearest speakers), `ambisonic` (first-order
+/// B-format, decoded to `decoder`) or `binaural` (headphone stereo with the
+/// sparkles and texture placed around the head; the layout is ignored).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PannerConfig {
+    #[default]
+    Pairwise,
+    Ambisonic,
+    Binaural,
+}
+
+/// `speakers` (the layout) or `binaural` (headphone stereo; the layout is ignored).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecoderConfig {
+    #[default]
+    Speakers,
+    Binaural,
+}
+
+/// Overrides of one layer's built-in placement (`[audio.spatial.<layer>]`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlacementConfig {
+    /// Home azimuth in degrees clockwise from the front.
+    pub azimuth: Option<f32>,
+    /// 0 (a point) to 1 (an even share on every speaker).
+    pub spread: Option<f32>,
+    /// Degrees per second around the ring at full motion.
+    pub orbit: Option<f32>,
+    /// Degrees either side of the orbit swung at full motion.
+    pub sway: Option<f32>,
+}
+
+impl PlacementConfig {
+    fn apply(&self, placement: &mut Placement) {
+        for (value, field) in [
+            (self.azimuth, &mut placement.azimuth),
+            (self.spread, &mut placement.spread),
+            (self.orbit, &mut placement.orbit),
+            (self.sway, &mut placement.sway),
+        ] {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+    }
+}
+
+/// Noise color of each noise-based layer (`[audio.noise]`).
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NoiseConfig {
+    pub texture: NoiseColorConfig,
+    pub sparkle: NoiseColorConfig,
+    pub wind: NoiseColorConfig,
+}
+
+/// `white`, `pink` (−3 dB/octave) or `brown` (−6 dB/octave).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseColorConfig {
+    #[default]
+    White,
+    Pink,
+    Brown,
+}
+
+impl From<NoiseColorConfig> for NoiseColor {
+    fn from(color: NoiseColorConfig) -> Self {
+        match color {
+            NoiseColorConfig::White => NoiseColor::White,
+            NoiseColorConfig::Pink => NoiseColor::Pink,
+            NoiseColorConfig::Brown => NoiseColor::Brown,
+        }
+    }
+}
+
+impl From<NoiseColor> for NoiseColorConfig {
+    fn from(color: NoiseColor) -> Self {
+        match color {
+            NoiseColor::White => NoiseColorConfig::White,
+            NoiseColor::Pink => NoiseColorConfig::Pink,
+            NoiseColor::Brown => NoiseColorConfig::Brown,
+        }
+    }
+}
+
+/// Effect chain per bus, in processing order (`[audio.effects]`). Each entry
+/// is `chorus`, `delay`, `reverb` or `limiter`, at most once per bus.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct EffectsConfig {
+    pub drone: Vec<String>,
+    pub sparkle: Vec<String>,
+    pub master: Vec<String>,
+}
+
+impl Default for EffectsConfig {
+    fn default() -> Self {
+        Self::from(&EffectChains::default())
+    }
+}
+
+impl From<&EffectChains> for EffectsConfig {
+    fn from(chains: &EffectChains) -> Self {
+        let names = |kinds: &[EffectKind]| kinds.iter().map(|k| k.name().to_string()).collect();
+        Self {
+            drone: names(&chains.drone),
+            sparkle: names(&chains.sparkle),
+            master: names(&chains.master),
+        }
+    }
+}
+
+impl EffectsConfig {
+    /// Parses the names into the chain for each bus. Errors start with the
+    /// bus they are on.
+    pub fn chains(&self) -> Result<EffectChains, String> {
+        let parse = |bus: EffectBus, names: &[String]| {
+            let invalid = |e: String| format!("{}: {}", bus.name(), e);
+            if names.len() > MAX_CHAIN_LEN {
+                return Err(invalid(format!("at most {} effects", MAX_CHAIN_LEN)));
+            }
+            let mut kinds: Vec<EffectKind> = Vec::new();
+            for name in names {
+                let kind: EffectKind = name.parse().map_err(invalid)?;
+                if kinds.contains(&kind) {
+                    return Err(invalid(format!("'{}' appears more than once", name)));
+                }
+                kinds.push(kind);
+            }
+            Ok(kinds)
+        };
+        Ok(EffectChains {
+            drone: parse(EffectBus::Drone, &self.drone)?,
+            sparkle: parse(EffectBus::Sparkle, &self.sparkle)?,
+            master: parse(EffectBus::Master, &self.master)?,
+        })
+    }
+}
+
+/// How the drone is synthesized (`audio.drone_mode`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DroneModeConfig {
+    /// Two detuned oscillators.
+    #[default]
+    Dual,
+    /// A harmonic series of `drone_partials` partials.
+    Additive,
+}
+
+fn default_drone_partials() -> usize {
+    8
+}
+
+/// One layer of the stack, as `/audio/stack` takes and lists it:
+/// `{"layer": "drone", "mode": "additive", "partials": 12}`,
+/// `{"layer": "wind", "noise": "brown"}`, `{"layer": "grains", "source": "rain"}`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(tag = "layer", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LayerConfig {
+    Drone {
+        #[serde(default)]
+        mode: DroneModeConfig,
+        /// Partials in the `additive` mode (1 to 16).
+        #[serde(default = "default_drone_partials")]
+        partials: usize,
+    },
+    Texture {
+        #[serde(default)]
+        noise: NoiseColorConfig,
+    },
+    Sparkle {
+        #[serde(default)]
+        noise: NoiseColorConfig,
+    },
+    Wind {
+        #[serde(default)]
+        noise: NoiseColorConfig,
+    },
+    Samples,
+    Grains {
+        /// Name (file stem) of the sample to granulate; defaults to the first one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+    },
+}
+
+impl LayerConfig {
+    /// The layer to build, with its grain source looked up among `samples`.
+    pub fn spec(&self, samples: &[Sample]) -> Result<LayerSpec, String> {
+        Ok(match self {
+            LayerConfig::Drone { mode, partials } => {
+                if !(1..=MAX_PARTIALS).contains(partials) {
+                    return Err(format!(
+                        "partials must be in [1, {}], got {}",
+                        MAX_PARTIALS, partials
+                    ));
+                }
+                LayerSpec::Drone(match mode {
+                    DroneModeConfig::Dual => DroneMode::Dual,
+                    DroneModeConfig::Additive => DroneMode::Additive(*partials),
+                })
+            }
+            LayerConfig::Texture { noise } => LayerSpec::Texture((*noise).into()),
+            LayerConfig::Sparkle { noise } => LayerSpec::Sparkle((*noise).into()),
+            LayerConfig::Wind { noise } => LayerSpec::Wind((*noise).into()),
+            LayerConfig::Samples => LayerSpec::Samples,
+            LayerConfig::Grains { source: None } => LayerSpec::Grains(0),
+            LayerConfig::Grains { source: Some(name) } => LayerSpec::Grains(
+                samples
+                    .iter()
+                    .position(|sample| sample.name() == name)
+                    .ok_or_else(|| format!("no sample named '{}'", name))?,
+            ),
+        })
+    }
+
+    /// Describes `spec`, naming its grain source from `samples`.
+    pub fn from_spec(spec: &LayerSpec, samples: &[Sample]) -> Self {
+        match *spec {
+            LayerSpec::Drone(DroneMode::Dual) => LayerConfig::Drone {
+                mode: DroneModeConfig::Dual,
+                partials: default_drone_partials(),
+            },
+            LayerSpec::Drone(DroneMode::Additive(partials)) => LayerConfig::Drone {
+                mode: DroneModeConfig::Additive,
+                partials,
+            },
+            LayerSpec::Texture(color) => LayerConfig::Texture {
+                noise: color.into(),
+            },
+            LayerSpec::Sparkle(color) => LayerConfig::Sparkle {
+                noise: color.into(),
+            },
+            LayerSpec::Wind(color) => LayerConfig::Wind {
+                noise: color.into(),
+            },
+            LayerSpec::Samples => LayerConfig::Samples,
+            LayerSpec::Grains(source) => LayerConfig::Grains {
+                source: samples.get(source).map(|sample| sample.name().to_string()),
+            },
+        }
+    }
+}
+
+/// World → audio mapping profiles (`[audio.mapping]`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MappingConfig {
+    /// Profile in use at startup: a built-in (`default`, `dark`, `bright`,
+    /// `minimal`, `cinematic`) or one defined under `profiles`.
+    pub profile: String,
+    /// Custom profiles by name (`[audio.mapping.profiles.<name>]`).
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+impl Default for MappingConfig {
+    fn default() -> Self {
+        Self {
+            profile: DEFAULT_PROFILE.to_string(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// A custom mapping profile. Omitted fields keep the default profile's curve.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub master_gain: Option<CurveConfig>,
+    pub base_freq_hz: Option<CurveConfig>,
+    pub detune_ratio: Option<CurveConfig>,
+    pub brightness: Option<CurveConfig>,
+    pub motion: Option<CurveConfig>,
+    pub texture: Option<CurveConfig>,
+    pub reverb_size: Option<CurveConfig>,
+    pub pre_delay_ms: Option<CurveConfig>,
+    pub grit: Option<CurveConfig>,
+}
+
+/// `{ input = "warmth", from = 80.0, to = 240.0, easing = "smoothstep" }`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CurveConfig {
+    /// World parameter: `density`, `rhythm`, `tension`, `energy`, `warmth`,
+    /// `space` or `clarity`.
+    pub input: String,
+    /// Output when the input is 0.
+    pub from: f32,
+    /// Output when the input is 1.
+    pub to: f32,
+    /// Any [`Curve`]: `"ease_in"`, `{ exponential = 3.0 }`, ... Defaults to linear.
+    #[serde(default)]
+    pub easing: Curve,
+}
+
+impl CurveConfig {
+    fn to_curve(&self) -> Result<FieldCurve, String> {
+        if !self.from.is_finite() || !self.to.is_finite() {
+            return Err("from and to must be finite".to_string());
+        }
+        let input: WorldInput = self.input.parse()?;
+        self.easing.validate()?;
+        Ok(FieldCurve::new(
+            input,
+            self.from,
+            self.to,
+            self.easing.clone(),
+        ))
+    }
+}
+
+/// Ducking of the drone and texture under sparkles (`[audio.ducking]`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DuckingConfig {
+    /// Largest fraction of bed gain removed; 0 disables ducking.
+    pub depth: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+/// Anti-click gate on the master gain (`[audio.gate]`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GateConfig {
+    /// Lowest master gain while the gate is open; 0 for none.
+    pub floor: f32,
+    /// Master gain below which the output fades to silence.
+    pub threshold: f32,
+    /// Margin above `threshold` the gain must climb to reopen the gate.
+    pub hysteresis: f32,
+    pub fade_ms: f32,
+}
+
+/// How quickly layers follow their parameters (`[audio.smoothing]`): one
+/// time constant per parameter, in milliseconds, for the sparkles (`fast`)
+/// and for the drone, texture and wind (`slow`).
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmoothingConfig {
+    pub fast: SmoothingTimesConfig,
+    pub slow: SmoothingTimesConfig,
+}
+
+/// Time constants of one set. Omitted parameters keep the built-in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmoothingTimesConfig {
+    pub master_gain_ms: Option<f32>,
+    pub base_freq_ms: Option<f32>,
+    pub detune_ms: Option<f32>,
+    pub brightness_ms: Option<f32>,
+    pub motion_ms: Option<f32>,
+    pub texture_ms: Option<f32>,
+    pub sparkle_ms: Option<f32>,
+}
+
+impl SmoothingTimesConfig {
+    fn fields(&self) -> [(&'static str, Option<f32>); 7] {
+        [
+            ("master_gain_ms", self.master_gain_ms),
+            ("base_freq_ms", self.base_freq_ms),
+            ("detune_ms", self.detune_ms),
+            ("brightness_ms", self.brightness_ms),
+            ("motion_ms", self.motion_ms),
+            ("texture_ms", self.texture_ms),
+            ("sparkle_ms", self.sparkle_ms),
+        ]
+    }
+
+    /// `defaults` with the configured times in place.
+    fn times(&self, defaults: SmoothingTimes) -> SmoothingTimes {
+        SmoothingTimes {
+            master_gain: self.master_gain_ms.unwrap_or(defaults.master_gain),
+            base_freq: self.base_freq_ms.unwrap_or(defaults.base_freq),
+            detune: self.detune_ms.unwrap_or(defaults.detune),
+            brightness: self.brightness_ms.unwrap_or(defaults.brightness),
+            motion: self.motion_ms.unwrap_or(defaults.motion),
+            texture: self.texture_ms.unwrap_or(defaults.texture),
+            sparkle: self.sparkle_ms.unwrap_or(defaults.sparkle),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Default filter directive; `RUST_LOG` takes precedence when set.
+    pub level: String,
+    pub format: LogFormat,
+    /// Level per subsystem (`world`, `audio`, `api`), overriding `level`.
+    pub targets: BTreeMap<String, String>,
+    /// OTLP/gRPC collector that spans are exported to (`otel` feature).
+    /// Disabled when unset.
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` of the exported spans.
+    pub service_name: String,
+}
+
+/// Log line format (`logging.format`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// API tokens. Auth is disabled when empty.
+    pub tokens: Vec<TokenConfig>,
+}
+
+/// Trajectory recorder: the snapshot sampled into daily CSV files.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordingConfig {
+    /// Directory the CSV files are written to. Recording is off when unset.
+    pub dir: Option<PathBuf>,
+    /// Rows written per second.
+    pub rate_hz: f64,
+    /// Day files to keep, deleting the oldest beyond that. 0 keeps them all.
+    pub keep_files: usize,
+}
+
+/// MIDI output of the harmony and sparkles to external synths.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MidiConfig {
+    pub enabled: bool,
+    /// Part of the output port's name, matched case-insensitively; the
+    /// first port when unset.
+    pub port: Option<String>,
+    /// Channels, 1 to 16.
+    pub drone_channel: u8,
+    pub pad_channel: u8,
+    pub sparkle_channel: u8,
+    /// Octaves the notes are placed in, middle C starting octave 4.
+    pub drone_octave: u8,
+    pub pad_octave: u8,
+    /// Sparkles span two octaves upward from here.
+    pub sparkle_octave: u8,
+    /// Run the built-in engine headless while MIDI plays.
+    pub mute_audio: bool,
+}
+
+/// Ogg/Opus stream of the output, served at `GET /stream.ogg`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamConfig {
+    pub enabled: bool,
+    /// Opus bitrate, 16 to 256 kbit/s.
+    pub bitrate_kbps: u32,
+}
+
+/// How long each anomaly must last before it is alerted on. 0 disables a check.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertsConfig {
+    /// A parameter stuck at 0 or 1.
+    pub pinned_secs: f64,
+    /// No ticks processed while running.
+    pub stall_secs: f64,
+    /// No parameter movement while running.
+    pub unchanged_secs: f64,
+    /// Output quieter than -80 dBFS.
+    pub silence_secs: f64,
+    /// Full-scale samples in the output every second.
+    pub full_scale_secs: f64,
+    /// Alert on any NaN or infinite sample in the output.
+    pub non_finite: bool,
+    /// Rebuild the audio engine when an output alert is raised.
+    pub restart_audio: bool,
+}
+
+/// MQTT bridge: retained snapshots out, commands in.
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    /// Broker host. The bridge is off when unset.
+    pub host: Option<String>,
+    pub port: u16,
+    pub client_id: String,
+    /// Snapshots go to `<prefix>/state`, commands come from `<prefix>/command/#`.
+    pub topic_prefix: String,
+    /// How often the retained state is republished, if the world changed.
+    pub publish_hz: f64,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+// Keep secrets out of the startup config dump
+impl std::fmt::Debug for MqttConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MqttConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("client_id", &self.client_id)
+            .field("topic_prefix", &self.topic_prefix)
+            .field("publish_hz", &self.publish_hz)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        let dynamics = WorldDynamics::default();
+        Self {
+            tick_hz: 20.0,
+            step_hz: DEFAULT_STEP_HZ,
+            time_scale: 1.0,
+            coalesce_ticks: true,
+            drift_factor: dynamics.drift_factor,
+            decay_factor: dynamics.decay_factor,
+            space: SpaceConfig::default(),
+            clarity: ClarityConfig::default(),
+            sparkles: SparkleConfig::default(),
+            pulse: PulseConfig::default(),
+            agents: AgentConfig::default(),
+            field: FieldConfig::default(),
+            noise_seed: None,
+        }
+    }
+}
+
+impl Default for PulseConfig {
+    fn default() -> Self {
+        let settings = PulseSettings::default();
+        Self {
+            quantize_sparkles: settings.quantize_sparkles,
+            subdivision: settings.subdivision,
+            quantize_delay: false,
+        }
+    }
+}
+
+impl Default for SpaceConfig {
+    fn default() -> Self {
+        let rates = WorldDynamics::default().space;
+        Self {
+            drift_factor: rates.drift_factor,
+            decay_factor: rates.decay_factor,
+        }
+    }
+}
+
+impl SpaceConfig {
+    pub fn rates(&self) -> DriftRates {
+        DriftRates {
+            drift_factor: self.drift_factor,
+            decay_factor: self.decay_factor,
+        }
+    }
+}
+
+impl Default for ClarityConfig {
+    fn default() -> Self {
+        let rates = WorldDynamics::default().clarity;
+        Self {
+            drift_factor: rates.drift_factor,
+            decay_factor: rates.decay_factor,
+        }
+    }
+}
+
+impl ClarityConfig {
+    pub fn rates(&self) -> DriftRates {
+        DriftRates {
+            drift_factor: self.drift_factor,
+            decay_factor: self.decay_factor,
+        }
+    }
+}
+
+impl Default for SparkleConfig {
+    fn default() -> Self {
+        let model = SparkleModel::default();
+        Self {
+            rate_hz: model.rate_hz,
+            burst_hz: model.burst_hz,
+            burst_secs: model.burst_secs,
+            refractory_secs: model.refractory_secs,
+        }
+    }
+}
+
+impl SparkleConfig {
+    pub fn model(&self) -> SparkleModel {
+        SparkleModel {
+            rate_hz: self.rate_hz,
+            burst_hz: self.burst_hz,
+            burst_secs: self.burst_secs,
+            refractory_secs: self.refractory_secs,
+        }
+    }
+}
+
+impl Default for ComposerConfig {
+    fn default() -> Self {
+        let settings = ArcSettings::default();
+        Self {
+            enabled: false,
+            arc_secs: settings.arc_secs,
+            ceiling: settings.ceiling,
+            randomness: settings.randomness,
+            seed: None,
+        }
+    }
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        let settings = AgentSettings::default();
+        Self {
+            enabled: settings.enabled,
+            capacity: settings.capacity,
+            lifespan_secs: settings.lifespan_secs,
+            speed: settings.speed,
+        }
+    }
+}
+
+impl Default for FieldConfig {
+    fn default() -> Self {
+        let settings = FieldSettings::default();
+        Self {
+            enabled: settings.enabled,
+            width: settings.width,
+            height: settings.height,
+            diffusion: settings.diffusion,
+            radius: settings.radius,
+        }
+    }
+}
+
+impl FieldConfig {
+    pub fn settings(&self) -> FieldSettings {
+        FieldSettings {
+            enabled: self.enabled,
+            width: self.width,
+            height: self.height,
+            diffusion: self.diffusion,
+            radius: self.radius,
+        }
+    }
+}
+
+impl AgentConfig {
+    pub fn settings(&self) -> AgentSettings {
+        AgentSettings {
+            enabled: self.enabled,
+            capacity: self.capacity,
+            lifespan_secs: self.lifespan_secs,
+            speed: self.speed,
+        }
+    }
+}
+
+impl ComposerConfig {
+    pub fn settings(&self) -> ArcSettings {
+        ArcSettings {
+            arc_secs: self.arc_secs,
+            ceiling: self.ceiling,
+            randomness: self.randomness,
+        }
+    }
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3000,
+            snapshot_hz: 10.0,
+            grpc_port: None,
+            cors_origins: vec!["*".to_string()],
+            tls: None,
+            unix_socket: None,
+        }
+    }
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        let gains = LayerGains::default();
+        Self {
+            enabled: true,
+            drone_gain: gains.drone,
+            texture_gain: gains.texture,
+            sparkle_gain: gains.sparkle,
+            samples_gain: gains.samples,
+            samples_dir: None,
+            grains_gain: gains.grains,
+            grain_source: None,
+            wind_gain: gains.wind,
+            patches_dir: None,
+            patch: None,
+            drone_mode: DroneModeConfig::Dual,
+            drone_partials: default_drone_partials(),
+            ducking: DuckingConfig::default(),
+            gate: GateConfig::default(),
+            smoothing: SmoothingConfig::default(),
+            loudness: LoudnessConfig::default(),
+            mapping: MappingConfig::default(),
+            effects: EffectsConfig::default(),
+            noise: NoiseConfig::default(),
+            output: OutputConfig::default(),
+            spatial: SpatialConfig::default(),
+            recorder: AudioRecorderConfig::default(),
+            calibration: CalibrationConfig::default(),
+        }
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        let format = OutputFormat::default();
+        Self {
+            backend: OutputBackendConfig::Cpal,
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+            capture_secs: 60.0,
+            capture_path: None,
+            realtime: true,
+        }
+    }
+}
+
+impl Default for AudioRecorderConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            buffer_minutes: 10.0,
+            archive: false,
+            keep_files: 0,
+        }
+    }
+}
+
+/// Loudness metering and auto-gain (`[audio.loudness]`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoudnessConfig {
+    /// Trim the output toward `target_lufs`. Metering runs either way.
+    pub auto_gain: bool,
+    pub target_lufs: f32,
+    /// Rolling window the integrated loudness is measured over.
+    pub window_secs: f32,
+    pub max_trim_db: f32,
+    pub rate_db_per_sec: f32,
+}
+
+impl Default for LoudnessConfig {
+    fn default() -> Self {
+        let loudness = LoudnessSettings::default();
+        Self {
+            auto_gain: loudness.auto_gain,
+            target_lufs: loudness.target_lufs,
+            window_secs: loudness.window_secs,
+            max_trim_db: loudness.max_trim_db,
+            rate_db_per_sec: loudness.rate_db_per_sec,
+        }
+    }
+}
+
+impl Default for DuckingConfig {
+    fn default() -> Self {
+        let ducking = DuckingSettings::default();
+        Self {
+            depth: ducking.depth,
+            attack_ms: ducking.attack_ms,
+            release_ms: ducking.release_ms,
+        }
+    }
+}
+
+impl Default for GateConfig {
+    fn default() -> Self {
+        let gate = GateSettings::default();
+        Self {
+            floor: gate.floor,
+            threshold: gate.threshold,
+            hysteresis: gate.hysteresis,
+            fade_ms: gate.fade_ms,
+        }
+    }
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: 1883,
+            client_id: "ambient-world".to_string(),
+            topic_prefix: "ambient".to_string(),
+            publish_hz: 1.0,
+            username: None,
+            password: None,
+        }
+    }
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            rate_hz: 1.0,
+            keep_files: 0,
+        }
+    }
+}
+
+impl Default for MidiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: None,
+            drone_channel: 1,
+            pad_channel: 2,
+            sparkle_channel: 3,
+            drone_octave: 2,
+            pad_octave: 3,
+            sparkle_octave: 5,
+            mute_audio: false,
+        }
+    }
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bitrate_kbps: 96,
+        }
+    }
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            pinned_secs: 600.0,
+            stall_secs: 10.0,
+            unchanged_secs: 600.0,
+            silence_secs: 60.0,
+            full_scale_secs: 5.0,
+            non_finite: true,
+            restart_audio: false,
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            targets: BTreeMap::new(),
+            otlp_endpoint: None,
+            service_name: "ambient-world".to_string(),
+        }
+    }
+}
+
+impl Cli {
+    /// The config file in use: `--config`, else `ambient.toml` if present.
+    pub fn config_path(&self) -> Option<PathBuf> {
+        match &self.config {
+            Some(path) => Some(path.clone()),
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Some(DEFAULT_CONFIG_PATH.into()),
+            None => None,
+        }
+    }
+}
+
+impl Config {
+    /// Resolves the full configuration from the config file and CLI/env overrides.
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let mut config = match cli.config_path() {
+            Some(path) => Self::from_file(&path)?,
+            None => Self::default(),
+        };
+        config.apply_calibration()?;
+        config.apply_overrides(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parses a TOML config file.
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Replaces the layer gains with those of the saved calibration, if
+    /// there is one.
+    fn apply_calibration(&mut self) -> Result<(), ConfigError> {
+        let Some(path) = &self.audio.calibration.path else {
+            return Ok(());
+        };
+        let Some(report) = load_report(path).map_err(ConfigError::Invalid)? else {
+            return Ok(());
+        };
+        let gains = LayerGains::from(report.suggested_gains);
+        self.audio.drone_gain = gains.drone;
+        self.audio.texture_gain = gains.texture;
+        self.audio.sparkle_gain = gains.sparkle;
+        self.audio.samples_gain = gains.samples;
+        self.audio.grains_gain = gains.grains;
+        self.audio.wind_gain = gains.wind;
+        Ok(())
+    }
+
+    fn apply_overrides(&mut self, cli: &Cli) {
+        if let Some(tick_hz) = cli.tick_hz {
+            self.world.tick_hz = tick_hz;
+        }
+        if let Some(port) = cli.port {
+            self.api.port = port;
+        }
+        if let Some(bind) = cli.bind {
+            self.api.bind = bind;
+        }
+        if let Some(port) = cli.grpc_port {
+            self.api.grpc_port = Some(port);
+        }
+        if cli.no_audio {
+            self.audio.enabled = false;
+        }
+        if let Some(level) = &cli.log_level {
+            self.logging.level = level.clone();
+        }
+    }
+
+    /// Checks ranges that would otherwise fail silently at runtime.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !TICK_RATE_RANGE.contains(&self.world.tick_hz) {
+            return Err(ConfigError::Invalid(format!(
+                "world.tick_hz must be in [{}, {}], got {}",
+                TICK_RATE_RANGE.start(),
+                TICK_RATE_RANGE.end(),
+                self.world.tick_hz
+            )));
+        }
+        if !STEP_HZ_RANGE.contains(&self.world.step_hz) {
+            return Err(ConfigError::Invalid(format!(
+                "world.step_hz must be in [{}, {}], got {}",
+                STEP_HZ_RANGE.start(),
+                STEP_HZ_RANGE.end(),
+                self.world.step_hz
+            )));
+        }
+        if !TIME_SCALE_RANGE.contains(&self.world.time_scale) {
+            return Err(ConfigError::Invalid(format!(
+                "world.time_scale must be in [{}, {}], got {}",
+                TIME_SCALE_RANGE.start(),
+                TIME_SCALE_RANGE.end(),
+                self.world.time_scale
+            )));
+        }
+        crate::logging::filter_directive(&self.logging.level, &self.logging.targets)
+            .map_err(|e| ConfigError::Invalid(format!("logging: {}", e)))?;
+        if let Some(endpoint) = &self.logging.otlp_endpoint
+            && !(endpoint.starts_with("http://") || endpoint.starts_with("https://"))
+        {
+            return Err(ConfigError::Invalid(format!(
+                "logging.otlp_endpoint must be an http:// or https:// URL, got {:?}",
+                endpoint
+            )));
+        }
+        if !(self.api.snapshot_hz > 0.0 && self.api.snapshot_hz <= 100.0) {
+            return Err(ConfigError::Invalid(format!(
+                "api.snapshot_hz must be in (0, 100], got {}",
+                self.api.snapshot_hz
+            )));
+        }
+        if self.api.grpc_port == Some(self.api.port) {
+            return Err(ConfigError::Invalid(format!(
+                "api.grpc_port must differ from api.port ({})",
+                self.api.port
+            )));
+        }
+        if let Some(tls) = &self.api.tls
+            && (tls.port == self.api.port || self.api.grpc_port == Some(tls.port))
+        {
+            return Err(ConfigError::Invalid(format!(
+                "api.tls.port must differ from api.port and api.grpc_port, got {}",
+                tls.port
+            )));
+        }
+        for origin in &self.api.cors_origins {
+            let valid = if origin == "*" {
+                self.api.cors_origins.len() == 1
+            } else {
+                (origin.starts_with("http://") || origin.starts_with("https://"))
+                    && !origin.ends_with('/')
+                    && HeaderValue::from_str(origin).is_ok()
+            };
+            if !valid {
+                return Err(ConfigError::Invalid(format!(
+                    "api.cors_origins entries must be \"*\" alone or origins like \"https://host:port\", got {:?}",
+                    origin
+                )));
+            }
+        }
+        for (name, value) in [
+            ("world.drift_factor", self.world.drift_factor),
+            ("world.decay_factor", self.world.decay_factor),
+            ("world.space.drift_factor", self.world.space.drift_factor),
+            ("world.space.decay_factor", self.world.space.decay_factor),
+            (
+                "world.clarity.drift_factor",
+                self.world.clarity.drift_factor,
+            ),
+            (
+                "world.clarity.decay_factor",
+                self.world.clarity.decay_factor,
+            ),
+        ] {
+            if !(0.0..=10.0).contains(&value) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be in [0, 10], got {}",
+                    name, value
+                )));
+            }
+        }
+        let sparkles = &self.world.sparkles;
+        for (name, value, max) in [
+            ("world.sparkles.rate_hz", sparkles.rate_hz, 20.0),
+            ("world.sparkles.burst_hz", sparkles.burst_hz, 100.0),
+            ("world.sparkles.burst_secs", sparkles.burst_secs, 10.0),
+            (
+                "world.sparkles.refractory_secs",
+                sparkles.refractory_secs,
+                10.0,
+            ),
+        ] {
+            if !(0.0..=max).contains(&value) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be in [0, {}], got {}",
+                    name, max, value
+                )));
+            }
+        }
+        if sparkles.model().branching_ratio() >= 1.0 {
+            return Err(ConfigError::Invalid(format!(
+                "world.sparkles.burst_hz * burst_secs must be below 1 for showers to end, got {}",
+                sparkles.model().branching_ratio()
+            )));
+        }
+        if !(1..=8).contains(&self.world.pulse.subdivision) {
+            return Err(ConfigError::Invalid(format!(
+                "world.pulse.subdivision must be in [1, 8], got {}",
+                self.world.pulse.subdivision
+            )));
+        }
+        let agents = &self.world.agents;
+        if !(1..=256).contains(&agents.capacity) {
+            return Err(ConfigError::Invalid(format!(
+                "world.agents.capacity must be in [1, 256], got {}",
+                agents.capacity
+            )));
+        }
+        if !(1.0..=3600.0).contains(&agents.lifespan_secs) {
+            return Err(ConfigError::Invalid(format!(
+                "world.agents.lifespan_secs must be in [1, 3600], got {}",
+                agents.lifespan_secs
+            )));
+        }
+        if !(0.0..=1.0).contains(&agents.speed) {
+            return Err(ConfigError::Invalid(format!(
+                "world.agents.speed must be in [0, 1], got {}",
+                agents.speed
+            )));
+        }
+        let field = &self.world.field;
+        for (name, cells) in [("width", field.width), ("height", field.height)] {
+            if !(1..=128).contains(&cells) {
+                return Err(ConfigError::Invalid(format!(
+                    "world.field.{} must be in [1, 128], got {}",
+                    name, cells
+                )));
+            }
+        }
+        if !(0.0..=10.0).contains(&field.diffusion) {
+            return Err(ConfigError::Invalid(format!(
+                "world.field.diffusion must be in [0, 10], got {}",
+                field.diffusion
+            )));
+        }
+        if !(field.radius > 0.0 && field.radius <= 1.0) {
+            return Err(ConfigError::Invalid(format!(
+                "world.field.radius must be in (0, 1], got {}",
+                field.radius
+            )));
+        }
+        for (name, value) in [
+            ("audio.drone_gain", self.audio.drone_gain),
+            ("audio.texture_gain", self.audio.texture_gain),
+            ("audio.sparkle_gain", self.audio.sparkle_gain),
+            ("audio.samples_gain", self.audio.samples_gain),
+            ("audio.grains_gain", self.audio.grains_gain),
+            ("audio.wind_gain", self.audio.wind_gain),
+        ] {
+            if !(0.0..=2.0).contains(&value) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be in [0, 2], got {}",
+                    name, value
+                )));
+            }
+        }
+        self.effect_chains()?;
+        if self.audio.patch.is_some() && self.audio.patches_dir.is_none() {
+            return Err(ConfigError::Invalid(
+                "audio.patch needs audio.patches_dir".to_string(),
+            ));
+        }
+        let output = &self.audio.output;
+        if !(8_000..=192_000).contains(&output.sample_rate) {
+            return Err(ConfigError::Invalid(format!(
+                "audio.output.sample_rate must be in [8000, 192000], got {}",
+                output.sample_rate
+            )));
+        }
+        if !(1..=8).contains(&output.channels) {
+            return Err(ConfigError::Invalid(format!(
+                "audio.output.channels must be in [1, 8], got {}",
+                output.channels
+            )));
+        }
+        if !(output.capture_secs > 0.0 && output.capture_secs <= 3600.0) {
+            return Err(ConfigError::Invalid(format!(
+                "audio.output.capture_secs must be in (0, 3600], got {}",
+                output.capture_secs
+            )));
+        }
+        if output.backend == OutputBackendConfig::Buffer && output.capture_path.is_none() {
+            return Err(ConfigError::Invalid(
+                "audio.output.capture_path is required with backend = \"buffer\"".to_string(),
+            ));
+        }
+        let buffer_minutes = self.audio.recorder.buffer_minutes;
+        if !(buffer_minutes > 0.0 && buffer_minutes <= 120.0) {
+            return Err(ConfigError::Invalid(format!(
+                "audio.recorder.buffer_minutes must be in (0, 120], got {}",
+                buffer_minutes
+            )));
+        }
+        self.audio
+            .calibration
+            .validate()
+            .map_err(ConfigError::Invalid)?;
+        let spatial = &self.audio.spatial;
+        if spatial.layout == LayoutConfig::Ring && !(2..=MAX_SPEAKERS).contains(&spatial.speakers) {
+            return Err(ConfigError::Invalid(format!(
+                "audio.spatial.speakers must be in [2, {}], got {}",
+                MAX_SPEAKERS, spatial.speakers
+            )));
+        }
+        if spatial.layout == LayoutConfig::Custom
+            && !(1..=MAX_SPEAKERS).contains(&spatial.azimuths.len())
+        {
+            return Err(ConfigError::Invalid(format!(
+                "audio.spatial.azimuths needs 1 to {} entries with layout = \"custom\", got {}",
+                MAX_SPEAKERS,
+                spatial.azimuths.len()
+            )));
+        }
+        if let Some(azimuth) = spatial.azimuths.iter().find(|a| !a.is_finite()) {
+            return Err(ConfigError::Invalid(format!(
+                "audio.spatial.azimuths must be finite, got {}",
+                azimuth
+            )));
+        }
+        let settings = self.spatial();
+        if settings.panner == Panner::Ambisonic(AmbisonicDecoder::Speakers)
+            && settings.layout.speakers().iter().flatten().count() < 2
+        {
+            return Err(ConfigError::Invalid(
+                "audio.spatial.panner = \"ambisonic\" needs a layout with at least 2 speakers, or decoder = \"binaural\"".to_string(),
+            ));
+        }
+        let placements = settings.placements;
+        for (name, placement) in [
+            ("drone", placements.drone),
+            ("texture", placements.texture),
+            ("sparkle", placements.sparkle),
+            ("samples", placements.samples),
+            ("grains", placements.grains),
+            ("wind", placements.wind),
+        ] {
+            for (field, value, min, max) in [
+                ("azimuth", placement.azimuth, -360.0, 360.0),
+                ("spread", placement.spread, 0.0, 1.0),
+                ("orbit", placement.orbit, -360.0, 360.0),
+                ("sway", placement.sway, 0.0, 180.0),
+            ] {
+                if !(min..=max).contains(&value) {
+                    return Err(ConfigError::Invalid(format!(
+                        "audio.spatial.{}.{} must be in [{}, {}], got {}",
+                        name, field, min, max, value
+                    )));
+                }
+            }
+        }
+        if !(1..=MAX_PARTIALS).contains(&self.audio.drone_partials) {
+            return Err(ConfigError::Invalid(format!(
+                "audio.drone_partials must be in [1, {}], got {}",
+                MAX_PARTIALS, self.audio.drone_partials
+            )));
+        }
+        if !(0.0..=1.0).contains(&self.audio.ducking.depth) {
+            return Err(ConfigError::Invalid(format!(
+                "audio.ducking.depth must be in [0, 1], got {}",
+                self.audio.ducking.depth
+            )));
+        }
+        for (name, value) in [
+            ("audio.ducking.attack_ms", self.audio.ducking.attack_ms),
+            ("audio.ducking.release_ms", self.audio.ducking.release_ms),
+        ] {
+            if !(0.0..=5000.0).contains(&value) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be in [0, 5000], got {}",
+                    name, value
+                )));
+            }
+        }
+        let gate = &self.audio.gate;
+        for (name, value) in [
+            ("audio.gate.floor", gate.floor),
+            ("audio.gate.threshold", gate.threshold),
+            ("audio.gate.hysteresis", gate.hysteresis),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be in [0, 1], got {}",
+                    name, value
+                )));
+            }
+        }
+        if !(0.0..=1000.0).contains(&gate.fade_ms) {
+            return Err(ConfigError::Invalid(format!(
+                "audio.gate.fade_ms must be in [0, 1000], got {}",
+                gate.fade_ms
+            )));
+        }
+        let smoothing = &self.audio.smoothing;
+        for (set, times) in [("fast", &smoothing.fast), ("slow", &smoothing.slow)] {
+            for (name, value) in times.fields() {
+                if let Some(value) = value
+                    && !(0.0..=2000.0).contains(&value)
+                {
+                    return Err(ConfigError::Invalid(format!(
+                        "audio.smoothing.{}.{} must be in [0, 2000], got {}",
+                        set, name, value
+                    )));
+                }
+            }
+        }
+        let loudness = &self.audio.loudness;
+        for (name, value, min, max) in [
+            (
+                "audio.loudness.target_lufs",
+                loudness.target_lufs,
+                -60.0,
+                0.0,
+            ),
+            (
+                "audio.loudness.window_secs",
+                loudness.window_secs,
+                1.0,
+                3600.0,
+            ),
+            (
+                "audio.loudness.max_trim_db",
+                loudness.max_trim_db,
+                0.0,
+                24.0,
+            ),
+            (
+                "audio.loudness.rate_db_per_sec",
+                loudness.rate_db_per_sec,
+                0.0,
+                10.0,
+            ),
+        ] {
+            if !(min..=max).contains(&value) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be in [{}, {}], got {}",
+                    name, min, max, value
+                )));
+            }
+        }
+        if !self
+            .mapping_profiles()?
+            .contains_key(&self.audio.mapping.profile)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "audio.mapping.profile '{}' is neither built in nor defined under audio.mapping.profiles",
+                self.audio.mapping.profile
+            )));
+        }
+        for (i, entry) in self.auth.tokens.iter().enumerate() {
+            if entry.name.trim().is_empty() || entry.token.trim().is_empty() {
+                return Err(ConfigError::Invalid(format!(
+                    "auth.tokens[{}] needs a non-empty name and token",
+                    i
+                )));
+            }
+            if self.auth.tokens[..i].iter().any(|t| t.token == entry.token) {
+                return Err(ConfigError::Invalid(format!(
+                    "auth.tokens[{}] ('{}') reuses another entry's token",
+                    i, entry.name
+                )));
+            }
+        }
+        if !(self.mqtt.publish_hz > 0.0 && self.mqtt.publish_hz <= 100.0) {
+            return Err(ConfigError::Invalid(format!(
+                "mqtt.publish_hz must be in (0, 100], got {}",
+                self.mqtt.publish_hz
+            )));
+        }
+        if !(self.recording.rate_hz > 0.0 && self.recording.rate_hz <= 100.0) {
+            return Err(ConfigError::Invalid(format!(
+                "recording.rate_hz must be in (0, 100], got {}",
+                self.recording.rate_hz
+            )));
+        }
+        let midi = &self.midi;
+        for (key, channel) in [
+            ("midi.drone_channel", midi.drone_channel),
+            ("midi.pad_channel", midi.pad_channel),
+            ("midi.sparkle_channel", midi.sparkle_channel),
+        ] {
+            if !(1..=16).contains(&channel) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be 1 to 16, got {}",
+                    key, channel
+                )));
+            }
+        }
+        for (key, octave) in [
+            ("midi.drone_octave", midi.drone_octave),
+            ("midi.pad_octave", midi.pad_octave),
+            ("midi.sparkle_octave", midi.sparkle_octave),
+        ] {
+            if octave > 8 {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be 0 to 8, got {}",
+                    key, octave
+                )));
+            }
+        }
+        if !(16..=256).contains(&self.stream.bitrate_kbps) {
+            return Err(ConfigError::Invalid(format!(
+                "stream.bitrate_kbps must be 16 to 256, got {}",
+                self.stream.bitrate_kbps
+            )));
+        }
+        let alerts = [
+            ("alerts.pinned_secs", self.alerts.pinned_secs),
+            ("alerts.stall_secs", self.alerts.stall_secs),
+            ("alerts.unchanged_secs", self.alerts.unchanged_secs),
+            ("alerts.silence_secs", self.alerts.silence_secs),
+            ("alerts.full_scale_secs", self.alerts.full_scale_secs),
+        ];
+        if let Some((key, secs)) = alerts
+            .iter()
+            .find(|(_, secs)| !(secs.is_finite() && *secs >= 0.0))
+        {
+            return Err(ConfigError::Invalid(format!(
+                "{} must be a non-negative number of seconds, got {}",
+                key, secs
+            )));
+        }
+        for (i, sensor) in self.sensors.iter().enumerate() {
+            sensor.validate(i).map_err(ConfigError::Invalid)?;
+            if self.sensors[..i].iter().any(|s| s.name == sensor.name) {
+                return Err(ConfigError::Invalid(format!(
+                    "sensors[{}] reuses the name '{}'",
+                    i, sensor.name
+                )));
+            }
+        }
+        self.room.validate().map_err(ConfigError::Invalid)?;
+        let arcs = &self.composer;
+        if !(arcs.arc_secs.is_finite() && arcs.arc_secs >= 60.0) {
+            return Err(ConfigError::Invalid(format!(
+                "composer.arc_secs must be at least 60 seconds, got {}",
+                arcs.arc_secs
+            )));
+        }
+        if !(composer::FLOOR..=1.0).contains(&arcs.ceiling) {
+            return Err(ConfigError::Invalid(format!(
+                "composer.ceiling must be in [{}, 1], got {}",
+                composer::FLOOR,
+                arcs.ceiling
+            )));
+        }
+        if !(0.0..=1.0).contains(&arcs.randomness) {
+            return Err(ConfigError::Invalid(format!(
+                "composer.randomness must be in [0, 1], got {}",
+                arcs.randomness
+            )));
+        }
+        self.seasons.validate().map_err(ConfigError::Invalid)?;
+        let prefix = &self.mqtt.topic_prefix;
+        if prefix.is_empty() || prefix.ends_with('/') || prefix.contains(['+', '#']) {
+            return Err(ConfigError::Invalid(format!(
+                "mqtt.topic_prefix must be a non-empty topic without wildcards or a trailing '/', got '{}'",
+                prefix
+            )));
+        }
+        Ok(())
+    }
+
+    /// Loads the scenes file, if configured.
+    pub fn load_scenes(&self) -> Result<HashMap<String, SceneTargets>, ConfigError> {
+        let Some(path) = &self.scenes_path else {
+            return Ok(HashMap::new());
+        };
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.clone(),
+            source,
+        })?;
+        let scenes: HashMap<String, SceneTargets> =
+            toml::from_str(&text).map_err(|source| ConfigError::Parse {
+                path: path.clone(),
+                source,
+            })?;
+        if let Some((name, _)) = scenes.iter().find(|(_, targets)| !targets.is_valid()) {
+            return Err(ConfigError::Invalid(format!(
+                "scene '{}' in {} has targets outside [0, 1], transition_secs outside [0, 120] or an invalid transition_curve",
+                name,
+                path.display()
+            )));
+        }
+        Ok(scenes)
+    }
+
+    pub fn dynamics(&self) -> WorldDynamics {
+        WorldDynamics {
+            drift_factor: self.world.drift_factor,
+            decay_factor: self.world.decay_factor,
+            space: self.world.space.rates(),
+            clarity: self.world.clarity.rates(),
+            sparkles: self.world.sparkles.model(),
+            pulse: PulseSettings {
+                quantize_sparkles: self.world.pulse.quantize_sparkles,
+                subdivision: self.world.pulse.subdivision,
+            },
+            agents: self.world.agents.settings(),
+            field: self.world.field.settings(),
+        }
+    }
+
+    pub fn layer_gains(&self) -> LayerGains {
+        LayerGains {
+            drone: self.audio.drone_gain,
+            texture: self.audio.texture_gain,
+            sparkle: self.audio.sparkle_gain,
+            samples: self.audio.samples_gain,
+            grains: self.audio.grains_gain,
+            wind: self.audio.wind_gain,
+        }
+    }
+
+    /// Built-in mapping profiles plus those defined in the config, which may
+    /// replace a built-in of the same name.
+    pub fn mapping_profiles(&self) -> Result<BTreeMap<String, MappingProfile>, ConfigError> {
+        let mut profiles: BTreeMap<String, MappingProfile> = MappingProfile::builtins()
+            .into_iter()
+            .map(|(name, profile)| (name.to_string(), profile))
+            .collect();
+        for (name, custom) in &self.audio.mapping.profiles {
+            let mut profile = MappingProfile::default();
+            for (field, curve, target) in [
+                ("master_gain", &custom.master_gain, &mut profile.master_gain),
+                (
+                    "base_freq_hz",
+                    &custom.base_freq_hz,
+                    &mut profile.base_freq_hz,
+                ),
+                (
+                    "detune_ratio",
+                    &custom.detune_ratio,
+                    &mut profile.detune_ratio,
+                ),
+                ("brightness", &custom.brightness, &mut profile.brightness),
+                ("motion", &custom.motion, &mut profile.motion),
+                ("texture", &custom.texture, &mut profile.texture),
+                ("reverb_size", &custom.reverb_size, &mut profile.reverb_size),
+                (
+                    "pre_delay_ms",
+                    &custom.pre_delay_ms,
+                    &mut profile.pre_delay_ms,
+                ),
+                ("grit", &custom.grit, &mut profile.grit),
+            ] {
+                if let Some(curve) = curve {
+                    *target = curve.to_curve().map_err(|e| {
+                        ConfigError::Invalid(format!(
+                            "audio.mapping.profiles.{}.{}: {}",
+                            name, field, e
+                        ))
+                    })?;
+                }
+            }
+            profiles.insert(name.clone(), profile);
+        }
+        Ok(profiles)
+    }
+
+    /// Parses `[audio.effects]` into the chain for each bus.
+    pub fn effect_chains(&self) -> Result<EffectChains, ConfigError> {
+        self.audio
+            .effects
+            .chains()
+            .map_err(|e| ConfigError::Invalid(format!("audio.effects.{}", e)))
+    }
+
+    pub fn drone_mode(&self) -> DroneMode {
+        match self.audio.drone_mode {
+            DroneModeConfig::Dual => DroneMode::Dual,
+            DroneModeConfig::Additive => DroneMode::Additive(self.audio.drone_partials),
+        }
+    }
+
+    pub fn noise_colors(&self) -> audio::noise::NoiseColors {
+        let noise = &self.audio.noise;
+        audio::noise::NoiseColors {
+            texture: noise.texture.into(),
+            sparkle: noise.sparkle.into(),
+            wind: noise.wind.into(),
+        }
+    }
+
+    pub fn spatial(&self) -> SpatialSettings {
+        let spatial = &self.audio.spatial;
+        let layout = match spatial.layout {
+            LayoutConfig::Mono => SpeakerLayout::mono(),
+            LayoutConfig::Stereo => SpeakerLayout::stereo(),
+            LayoutConfig::Quad => SpeakerLayout::quad(),
+            LayoutConfig::Surround51 => SpeakerLayout::surround_5_1(),
+            LayoutConfig::Surround71 => SpeakerLayout::surround_7_1(),
+            LayoutConfig::Ring => SpeakerLayout::ring(spatial.speakers),
+            LayoutConfig::Custom => SpeakerLayout::custom(spatial.azimuths.clone()),
+        };
+        let mut placements = LayerPlacements::default();
+        spatial.drone.apply(&mut placements.drone);
+        spatial.texture.apply(&mut placements.texture);
+        spatial.sparkle.apply(&mut placements.sparkle);
+        spatial.samples.apply(&mut placements.samples);
+        spatial.grains.apply(&mut placements.grains);
+        spatial.wind.apply(&mut placements.wind);
+        let panner = match (spatial.panner, spatial.decoder) {
+            (PannerConfig::Pairwise, _) => Panner::Pairwise,
+            (PannerConfig::Binaural, _) => Panner::Binaural,
+            (PannerConfig::Ambisonic, DecoderConfig::Speakers) => {
+                Panner::Ambisonic(AmbisonicDecoder::Speakers)
+            }
+            (PannerConfig::Ambisonic, DecoderConfig::Binaural) => {
+                Panner::Ambisonic(AmbisonicDecoder::Binaural)
+            }
+        };
+        SpatialSettings {
+            layout,
+            placements,
+            panner,
+        }
+    }
+
+    pub fn ducking(&self) -> DuckingSettings {
+        DuckingSettings {
+            depth: self.audio.ducking.depth,
+            attack_ms: self.audio.ducking.attack_ms,
+            release_ms: self.audio.ducking.release_ms,
+        }
+    }
+
+    pub fn gate(&self) -> GateSettings {
+        GateSettings {
+            floor: self.audio.gate.floor,
+            threshold: self.audio.gate.threshold,
+            hysteresis: self.audio.gate.hysteresis,
+            fade_ms: self.audio.gate.fade_ms,
+        }
+    }
+
+    pub fn smoothing(&self) -> SmoothingSettings {
+        let defaults = SmoothingSettings::default();
+        let smoothing = &self.audio.smoothing;
+        SmoothingSettings {
+            fast: smoothing.fast.times(defaults.fast),
+            slow: smoothing.slow.times(defaults.slow),
+        }
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        OutputFormat {
+            sample_rate: self.audio.output.sample_rate,
+            channels: self.audio.output.channels,
+        }
+    }
+
+    pub fn loudness(&self) -> LoudnessSettings {
+        let loudness = &self.audio.loudness;
+        LoudnessSettings {
+            auto_gain: loudness.auto_gain,
+            target_lufs: loudness.target_lufs,
+            window_secs: loudness.window_secs,
+            max_trim_db: loudness.max_trim_db,
+            rate_db_per_sec: loudness.rate_db_per_sec,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_file_keeps_defaults() {
+        let config: Config = toml::from_str("[world]\ntick_hz = 30.0\n").unwrap();
+        assert_eq!(config.world.tick_hz, 30.0);
+        assert_eq!(config.api.port, 3000);
+        assert!(config.audio.enabled);
+    }
+
+    #[test]
+    fn test_unknown_field_rejected() {
+        assert!(toml::from_str::<Config>("[world]\ntick_rate = 30.0\n").is_err());
+    }
+
+    #[test]
+    fn test_cli_overrides_file() {
+        let mut config: Config = toml::from_str("[api]\nport = 4000\n").unwrap();
+        let cli = Cli {
+            port: Some(5000),
+            no_audio: true,
+            ..Cli::default()
+        };
+        config.apply_overrides(&cli);
+        assert_eq!(config.api.port, 5000);
+        assert!(!config.audio.enabled);
+    }
+
+    #[test]
+    fn test_custom_mapping_profile() {
+        let text = r#"
+            [audio.mapping]
+            profile = "underwater"
+
+            [audio.mapping.profiles.underwater]
+            base_freq_hz = { input = "density", from = 40.0, to = 90.0, easing = "ease_in" }
+            motion = { input = "rhythm", from = 0.0, to = 0.5, easing = { sigmoid = 8.0 } }
+        "#;
+        let config: Config = toml::from_str(text).unwrap();
+        config.validate().unwrap();
+        let profiles = config.mapping_profiles().unwrap();
+        let underwater = &profiles["underwater"];
+        assert_eq!(underwater.base_freq_hz.input, WorldInput::Density);
+        assert_eq!(underwater.base_freq_hz.curve, Curve::EaseIn);
+        assert_eq!(underwater.motion.curve, Curve::Sigmoid(8.0));
+        assert_eq!(underwater.texture, MappingProfile::default().texture);
+        assert!(profiles.contains_key("cinematic"));
+
+        let mut unknown = config.clone();
+        unknown.audio.mapping.profile = "nope".to_string();
+        assert!(matches!(unknown.validate(), Err(ConfigError::Invalid(_))));
+
+        let bad_input = text.replace("\"density\"", "\"humidity\"");
+        let config: Config = toml::from_str(&bad_input).unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_tick_rate() {
+        let mut config = Config::default();
+        config.world.tick_hz = 0.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.world.tick_hz = 0.5;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.world.tick_hz = 1000.0;
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.world.step_hz = -60.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.world.time_scale = 20.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_grpc_port_must_differ_from_http_port() {
+        let mut config: Config = toml::from_str("[api]\ngrpc_port = 50051\n").unwrap();
+        assert!(config.validate().is_ok());
+        config.api.grpc_port = Some(config.api.port);
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_bind_cors_and_tls() {
+        let text = r#"
+            [api]
+            bind = "127.0.0.1"
+            cors_origins = ["https://ambient.example", "http://localhost:5173"]
+            tls = { cert = "cert.pem", key = "key.pem" }
+        "#;
+        let mut config: Config = toml::from_str(text).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.api.bind, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(config.api.tls.as_ref().unwrap().port, 3443);
+        assert_eq!(Config::default().api.cors_origins, ["*"]);
+
+        config.api.cors_origins.push("*".to_string());
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.api.cors_origins = vec!["ambient.example".to_string()];
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.api.cors_origins.clear();
+        config.api.grpc_port = Some(3443);
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_recording_section() {
+        let mut config: Config =
+            toml::from_str("[recording]\ndir = \"recordings\"\nkeep_files = 7\n").unwrap();
+        assert_eq!(config.recording.rate_hz, 1.0);
+        assert!(config.validate().is_ok());
+        config.recording.rate_hz = 0.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_additive_drone_mode() {
+        let mut config: Config =
+            toml::from_str("[audio]\ndrone_mode = \"additive\"\ndrone_partials = 12\n").unwrap();
+        assert_eq!(config.audio.drone_mode, DroneModeConfig::Additive);
+        assert!(config.validate().is_ok());
+        config.audio.drone_partials = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        assert!(toml::from_str::<Config>("[audio]\ndrone_mode = \"fm\"\n").is_err());
+    }
+
+    #[test]
+    fn test_gate_section() {
+        let text = "[audio.gate]\nfloor = 0.02\nfade_ms = 50.0\n";
+        let mut config: Config = toml::from_str(text).unwrap();
+        let gate = config.gate();
+        assert_eq!(gate.floor, 0.02);
+        assert_eq!(gate.fade_ms, 50.0);
+        assert_eq!(gate.threshold, GateSettings::default().threshold);
+        assert!(config.validate().is_ok());
+        config.audio.gate.hysteresis = -0.1;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_smoothing_section() {
+        let text = "[audio.smoothing]\nslow = { motion_ms = 80.0 }\nfast.sparkle_ms = 0.0\n";
+        let mut config: Config = toml::from_str(text).unwrap();
+        let defaults = SmoothingSettings::default();
+        let smoothing = config.smoothing();
+        assert_eq!(smoothing.slow.motion, 80.0);
+        assert_eq!(smoothing.slow.brightness, defaults.slow.brightness);
+        assert_eq!(smoothing.fast.sparkle, 0.0);
+        assert_eq!(smoothing.fast.motion, defaults.fast.motion);
+        assert!(config.validate().is_ok());
+        config.audio.smoothing.fast.detune_ms = Some(-1.0);
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_noise_colors() {
+        let config: Config = toml::from_str("[audio.noise]\ntexture = \"pink\"\n").unwrap();
+        assert_eq!(config.audio.noise.texture, NoiseColorConfig::Pink);
+        assert_eq!(config.audio.noise.wind, NoiseColorConfig::White);
+        assert!(toml::from_str::<Config>("[audio.noise]\nwind = \"blue\"\n").is_err());
+    }
+
+    #[test]
+    fn test_output_backend() {
+        let mut config: Config =
+            toml::from_str("[audio.output]\nbackend = \"null\"\nchannels = 1\n").unwrap();
+        assert_eq!(config.audio.output.backend, OutputBackendConfig::Null);
+        assert_eq!(config.output_format().sample_rate, 48_000);
+        assert!(config.validate().is_ok());
+        config.audio.output.channels = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        // A capture needs somewhere to go
+        config.audio.output.channels = 2;
+        config.audio.output.backend = OutputBackendConfig::Buffer;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.audio.output.capture_path = Some(PathBuf::from("capture.wav"));
+        assert!(config.validate().is_ok());
+        assert!(toml::from_str::<Config>("[audio.output]\nbackend = \"jack\"\n").is_err());
+    }
+
+    #[test]
+    fn test_sparkle_model() {
+        let mut config: Config =
+            toml::from_str("[world.sparkles]\nrate_hz = 0.5\nburst_hz = 0.0\n").unwrap();
+        assert_eq!(config.dynamics().sparkles.rate_hz, 0.5);
+        assert_eq!(
+            config.dynamics().sparkles.burst_secs,
+            SparkleModel::default().burst_secs
+        );
+        assert!(config.validate().is_ok());
+
+        // Showers that feed themselves forever are rejected
+        config.world.sparkles.burst_hz = 10.0;
+        config.world.sparkles.burst_secs = 0.2;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.world.sparkles.burst_hz = 4.0;
+        config.world.sparkles.refractory_secs = -1.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        assert!(toml::from_str::<Config>("[world.sparkles]\nrate = 1.0\n").is_err());
+    }
+
+    #[test]
+    fn test_space_section() {
+        let mut config: Config = toml::from_str("[world.space]\ndecay_factor = 0.02\n").unwrap();
+        assert_eq!(config.dynamics().space.decay_factor, 0.02);
+        assert_eq!(
+            config.dynamics().space.drift_factor,
+            WorldDynamics::default().space.drift_factor
+        );
+        assert!(config.validate().is_ok());
+        config.world.space.drift_factor = 11.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        assert!(toml::from_str::<Config>("[world.space]\nrate = 1.0\n").is_err());
+    }
+
+    #[test]
+    fn test_clarity_section() {
+        let mut config: Config = toml::from_str("[world.clarity]\ndrift_factor = 0.3\n").unwrap();
+        assert_eq!(config.dynamics().clarity.drift_factor, 0.3);
+        assert_eq!(
+            config.dynamics().clarity.decay_factor,
+            WorldDynamics::default().clarity.decay_factor
+        );
+        assert!(config.validate().is_ok());
+        config.world.clarity.decay_factor = -0.1;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        assert!(toml::from_str::<Config>("[world.clarity]\nrate = 1.0\n").is_err());
+    }
+
+    #[test]
+    fn test_pulse_section() {
+        let mut config: Config =
+            toml::from_str("[world.pulse]\nquantize_sparkles = true\nquantize_delay = true\n")
+                .unwrap();
+        assert!(config.dynamics().pulse.quantize_sparkles);
+        assert_eq!(config.dynamics().pulse.subdivision, 2);
+        assert!(config.validate().is_ok());
+        config.world.pulse.subdivision = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_composer_section() {
+        let mut config: Config =
+            toml::from_str("[composer]\nenabled = true\narc_secs = 3600.0\nseed = 4\n").unwrap();
+        assert!(config.validate().is_ok());
+        let settings = config.composer.settings();
+        assert_eq!(settings.arc_secs, 3600.0);
+        assert_eq!(settings.ceiling, ArcSettings::default().ceiling);
+
+        config.composer.ceiling = 0.05;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.composer.ceiling = 0.9;
+        config.composer.randomness = 1.5;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_agents_section() {
+        let mut config: Config = toml::from_str(
+            "[world.agents]
+enabled = true
+capacity = 48
+",
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        let agents = config.dynamics().agents;
+        assert!(agents.enabled);
+        assert_eq!(agents.capacity, 48);
+        assert_eq!(agents.speed, AgentSettings::default().speed);
+
+        config.world.agents.capacity = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.world.agents.capacity = 32;
+        config.world.agents.lifespan_secs = 0.5;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_field_section() {
+        let mut config: Config = toml::from_str(
+            "[world.field]
+enabled = true
+width = 32
+height = 18
+",
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        let field = config.dynamics().field;
+        assert!(field.enabled);
+        assert_eq!((field.width, field.height), (32, 18));
+        assert_eq!(field.radius, FieldSettings::default().radius);
+
+        config.world.field.height = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.world.field.height = 9;
+        config.world.field.radius = 0.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_spatial_layout() {
+        let mut config: Config = toml::from_str(
+            "[audio.spatial]\nlayout = \"5.1\"\n[audio.spatial.sparkle]\norbit = 40.0\n",
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        let spatial = config.spatial();
+        assert_eq!(spatial.layout, SpeakerLayout::surround_5_1());
+        // Overrides replace only the fields they name
+        let defaults = LayerPlacements::default();
+        assert_eq!(spatial.placements.sparkle.orbit, 40.0);
+        assert_eq!(spatial.placements.sparkle.sway, defaults.sparkle.sway);
+        assert_eq!(spatial.placements.wind, defaults.wind);
+
+        config.audio.spatial.sparkle.spread = Some(1.5);
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.audio.spatial.sparkle.spread = None;
+        config.audio.spatial.layout = LayoutConfig::Custom;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.audio.spatial.azimuths = vec![-90.0, 0.0, 90.0];
+        assert_eq!(config.spatial().layout.channels(), 3);
+        assert!(config.validate().is_ok());
+        assert!(toml::from_str::<Config>("[audio.spatial]\nlayout = \"hexagon\"\n").is_err());
+
+        // Ambisonics decode to the layout, which needs speakers, or to headphones
+        config.audio.spatial.panner = PannerConfig::Ambisonic;
+        assert!(config.validate().is_ok());
+        config.audio.spatial.layout = LayoutConfig::Mono;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.audio.spatial.decoder = DecoderConfig::Binaural;
+        assert!(config.validate().is_ok());
+        assert_eq!(config.spatial().channels(), 2);
+
+        let config: Config = toml::from_str("[audio.spatial]\npanner = \"binaural\"\n").unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.spatial().panner, Panner::Binaural);
+    }
+
+    #[test]
+    fn test_effect_chains() {
+        let text = "[audio.effects]\ndrone = [\"chorus\", \"reverb\"]\nmaster = [\"limiter\"]\n";
+        let config: Config = toml::from_str(text).unwrap();
+        let chains = config.effect_chains().unwrap();
+        assert_eq!(chains.drone, [EffectKind::Chorus, EffectKind::Reverb]);
+        assert_eq!(chains.sparkle, [EffectKind::Delay]);
+        assert_eq!(chains.master, [EffectKind::Limiter]);
+
+        for bad in ["drone = [\"flanger\"]", "master = [\"reverb\", \"reverb\"]"] {
+            let config: Config = toml::from_str(&format!("[audio.effects]\n{}\n", bad)).unwrap();
+            assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        }
+    }
+
+    #[test]
+    fn test_mqtt_section() {
+        let text = "[mqtt]\nhost = \"broker.local\"\npassword = \"hunter2\"\n";
+        let mut config: Config = toml::from_str(text).unwrap();
+        assert_eq!(config.mqtt.port, 1883);
+        assert!(config.validate().is_ok());
+        assert!(!format!("{:?}", config).contains("hunter2"));
+
+        config.mqtt.topic_prefix = "ambient/#".to_string();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_midi_section() {
+        let text = "[midi]\nenabled = true\nport = \"IAC\"\nsparkle_channel = 10\n";
+        let mut config: Config = toml::from_str(text).unwrap();
+        assert_eq!(config.midi.port.as_deref(), Some("IAC"));
+        assert_eq!(config.midi.pad_channel, 2);
+        assert!(config.validate().is_ok());
+
+        config.midi.drone_channel = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.midi.drone_channel = 1;
+        config.midi.pad_octave = 9;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_audio_recorder_section() {
+        let text = "[audio.recorder]\ndir = \"takes\"\narchive = true\n";
+        let mut config: Config = toml::from_str(text).unwrap();
+        assert_eq!(config.audio.recorder.dir, Some(PathBuf::from("takes")));
+        assert_eq!(config.audio.recorder.buffer_minutes, 10.0);
+        assert!(config.validate().is_ok());
+
+        config.audio.recorder.buffer_minutes = 0.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_saved_calibration_replaces_the_gains() {
+        let path = std::env::temp_dir().join(format!("calibrated-{}.json", std::process::id()));
+        let mut config = Config::default();
+        config.audio.calibration.path = Some(path.clone());
+        let _ = std::fs::remove_file(&path);
+        config.apply_calibration().unwrap();
+        assert_eq!(config.layer_gains(), LayerGains::default());
+
+        let gains = LayerGains {
+            drone: 0.25,
+            ..LayerGains::default()
+        };
+        let report = crate::calibration::CalibrationReport::new(Vec::new(), gains, -24.0, -1.0, 0);
+        std::fs::write(&path, serde_json::to_vec(&report).unwrap()).unwrap();
+        config.apply_calibration().unwrap();
+        assert_eq!(config.audio.drone_gain, 0.25);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            config.apply_calibration(),
+            Err(ConfigError::Invalid(_))
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_stream_section() {
+        let mut config: Config = toml::from_str("[stream]\nenabled = true\n").unwrap();
+        assert_eq!(config.stream.bitrate_kbps, 96);
+        assert!(config.validate().is_ok());
+
+        config.stream.bitrate_kbps = 8;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_auth_tokens_parse_and_must_be_unique() {
+        let text = r#"
+            [[auth.tokens]]
+            name = "lobby-screen"
+            token = "abc"
+            role = "viewer"
+
+            [[auth.tokens]]
+            name = "installer"
+            token = "abc"
+            role = "controller"
+        "#;
+        let config: Config = toml::from_str(text).unwrap();
+        assert_eq!(config.auth.tokens.len(), 2);
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_sensor_names_must_be_unique() {
+        let text = r#"
+            [[sensors]]
+            name = "door"
+            source = { kind = "gpio", pin = 17 }
+            action = "pulse"
+
+            [[sensors]]
+            name = "door"
+            source = { kind = "mic" }
+            action = "stir"
+        "#;
+        let mut config: Config = toml::from_str(text).unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.sensors[1].name = "room".to_string();
+        assert!(config.validate().is_ok());
+    }
+}
@@ -0,0 +1,219 @@
+//! Errors returned by the REST API.
+//!
+//! Every failed request answers with a JSON body in the shape of the
+//! WebSocket `error` message's payload, `{"code", "message", "request_id"}`,
+//! with the same [`ErrorCode`]s, so a client branches on `code` the same way
+//! whichever transport it used. `request_id` is the request's
+//! `X-Request-Id`.
+
+use crate::api::ErrorPayload;
+use crate::auth::AuthError;
+use crate::patches::PatchError;
+use crate::protocol::ErrorCode;
+use crate::request_id;
+use audio::registry::RegistryError;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts, Json, Query};
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+/// Why a request failed.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ApiError {
+    /// A parameter, header or body is malformed or out of range.
+    #[error("{0}")]
+    Validation(String),
+    /// The `X-Schema-Version` asked for is not one the server speaks.
+    #[error("{0}")]
+    VersionMismatch(String),
+    /// No such preset, scene, layer or patch, or the feature is off.
+    #[error("{0}")]
+    NotFound(String),
+    /// The request conflicts with the current state, such as a full stack.
+    #[error("{0}")]
+    Conflict(String),
+    /// No valid token, or one without the role the endpoint needs.
+    #[error(transparent)]
+    Unauthorized(#[from] AuthError),
+    /// Too busy to take the request now; retry shortly.
+    #[error("{message}")]
+    RateLimited {
+        message: String,
+        /// Sent as `Retry-After` when known.
+        retry_after_secs: Option<u64>,
+    },
+    /// A task the request has to reach, such as the world task, has stopped.
+    #[error("{0}")]
+    ChannelClosed(String),
+    /// Anything else that failed on the server's side, such as a disk write.
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    /// The world task stopped answering commands.
+    pub fn world_stopped() -> Self {
+        ApiError::ChannelClosed("World task is not running".to_string())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Validation(_) | ApiError::VersionMismatch(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Unauthorized(AuthError::Forbidden { .. }) => StatusCode::FORBIDDEN,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::RateLimited { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::ChannelClosed(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The code a WebSocket session gets for the same failure.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::Validation(_) => ErrorCode::ValidationError,
+            ApiError::VersionMismatch(_) => ErrorCode::VersionMismatch,
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::Conflict(_) => ErrorCode::Conflict,
+            ApiError::Unauthorized(AuthError::Forbidden { .. }) => ErrorCode::Forbidden,
+            ApiError::Unauthorized(_) => ErrorCode::Unauthorized,
+            ApiError::RateLimited { .. } => ErrorCode::Busy,
+            ApiError::ChannelClosed(_) => ErrorCode::SendFailed,
+            ApiError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// The body sent for it.
+    pub fn payload(&self) -> ErrorPayload {
+        ErrorPayload {
+            code: self.code(),
+            message: self.to_string(),
+            request_id: request_id::current(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status(), Json(self.payload())).into_response();
+        if let ApiError::RateLimited {
+            retry_after_secs: Some(secs),
+            ..
+        } = self
+        {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+impl From<RegistryError> for ApiError {
+    fn from(e: RegistryError) -> Self {
+        let message = e.to_string();
+        match e {
+            RegistryError::Full => ApiError::Conflict(message),
+            RegistryError::Missing(_) => ApiError::NotFound(message),
+            RegistryError::NoSamples(_) => ApiError::Validation(message),
+            RegistryError::Busy => ApiError::RateLimited {
+                message,
+                retry_after_secs: None,
+            },
+        }
+    }
+}
+
+impl From<PatchError> for ApiError {
+    fn from(e: PatchError) -> Self {
+        match e {
+            PatchError::Invalid(message) => ApiError::Validation(message),
+            PatchError::Registry(e) => e.into(),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::Validation(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::Validation(rejection.body_text())
+    }
+}
+
+/// [`Json`] that rejects a malformed body with an [`ApiError`].
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
+/// [`Query`] that rejects a malformed query string with an [`ApiError`].
+#[derive(FromRequestParts)]
+#[from_request(via(Query), rejection(ApiError))]
+pub struct ApiQuery<T>(pub T);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Role;
+
+    async fn body(error: ApiError) -> (StatusCode, serde_json::Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_errors_share_the_websocket_error_shape() {
+        let (status, body) = body(ApiError::NotFound("Unknown preset 'x'".to_string())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "code": "NOT_FOUND",
+                "message": "Unknown preset 'x'",
+                "request_id": null,
+            })
+        );
+
+        let forbidden = ApiError::from(AuthError::Forbidden {
+            required: Role::Controller,
+        });
+        assert_eq!(forbidden.status(), StatusCode::FORBIDDEN);
+        assert_eq!(forbidden.code(), ErrorCode::Forbidden);
+        assert_eq!(
+            ApiError::from(AuthError::Missing).status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(ApiError::world_stopped().code(), ErrorCode::SendFailed);
+    }
+
+    #[tokio::test]
+    async fn test_registry_errors_map_to_codes() {
+        assert_eq!(
+            ApiError::from(RegistryError::Full).code(),
+            ErrorCode::Conflict
+        );
+        assert_eq!(
+            ApiError::from(RegistryError::Missing("wind")).status(),
+            StatusCode::NOT_FOUND
+        );
+        let busy = ApiError::from(RegistryError::Busy);
+        assert_eq!(busy.code(), ErrorCode::Busy);
+        let response = busy.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.headers().get(header::RETRY_AFTER).is_none());
+
+        let limited = ApiError::RateLimited {
+            message: "Event queue is full, retry shortly".to_string(),
+            retry_after_secs: Some(1),
+        };
+        assert_eq!(limited.into_response().headers()[header::RETRY_AFTER], "1");
+    }
+}
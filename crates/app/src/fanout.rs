@@ -0,0 +1,278 @@
+//! Snapshot fan-out to WebSocket sessions.
+//!
+//! Sessions that stream the same thing (schema, encoding, snapshot channels
+//! and fields) share a [`SnapshotFeed`]. Once per `api.snapshot_hz` tick the
+//! fan-out task builds and encodes each feed somebody listens to, once, and
+//! sends the finished frames to all of its sessions through a broadcast
+//! channel, so a hundred visual clients on the defaults cost one snapshot
+//! clone and one serialization per tick rather than a hundred.
+
+use crate::api::{AnalysisSnapshot, AudioParamsSnapshot, ServerMessage, SnapshotPayload};
+use crate::protocol::{Channel, Encoding, Subscription, encode_field_frame};
+use crate::schema::{SchemaVersion, WireSnapshot};
+use ambient_core::world::WorldSnapshot;
+use audio::master::SharedMeter;
+use audio::params::AudioParams;
+use audio::telemetry::CallbackTelemetry;
+use axum::extract::ws::Message;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, watch};
+
+/// Frames buffered per feed. A session further behind skips to the newest.
+pub const FEED_CAPACITY: usize = 4;
+
+/// Channels carried by snapshot feeds; the rest are broadcasts.
+const SNAPSHOT_CHANNELS: [Channel; 4] = [
+    Channel::World,
+    Channel::Audio,
+    Channel::Analysis,
+    Channel::Field,
+];
+
+/// One way of streaming snapshots, shared by every session that asks for it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SnapshotFeed {
+    schema: SchemaVersion,
+    encoding: Encoding,
+    /// Only the snapshot channels, with their field selections.
+    subscription: Subscription,
+}
+
+impl SnapshotFeed {
+    /// The feed for a session's negotiated schema, encoding and subscription.
+    pub fn new(schema: SchemaVersion, encoding: Encoding, subscription: &Subscription) -> Self {
+        let mut subscription = subscription.clone();
+        subscription
+            .channels
+            .retain(|channel| SNAPSHOT_CHANNELS.contains(channel));
+        Self {
+            schema,
+            encoding,
+            subscription,
+        }
+    }
+
+    /// Whether the feed has anything to send.
+    pub fn is_empty(&self) -> bool {
+        self.subscription.channels.is_empty()
+    }
+}
+
+/// Feeds with at least one session, by what they carry.
+#[derive(Debug, Default)]
+pub struct SnapshotFanout {
+    feeds: Mutex<HashMap<SnapshotFeed, broadcast::Sender<Message>>>,
+}
+
+impl SnapshotFanout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Joins `feed`, starting it if no session streams it yet.
+    pub fn subscribe(&self, feed: SnapshotFeed) -> broadcast::Receiver<Message> {
+        self.feeds
+            .lock()
+            .expect("fan-out lock poisoned")
+            .entry(feed)
+            .or_insert_with(|| broadcast::channel(FEED_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Feeds somebody still listens to; the rest are dropped.
+    fn live_feeds(&self) -> Vec<(SnapshotFeed, broadcast::Sender<Message>)> {
+        let mut feeds = self.feeds.lock().expect("fan-out lock poisoned");
+        feeds.retain(|_, tx| tx.receiver_count() > 0);
+        feeds
+            .iter()
+            .map(|(feed, tx)| (feed.clone(), tx.clone()))
+            .collect()
+    }
+}
+
+/// What outgoing snapshots are built from.
+pub struct SnapshotSources {
+    pub world_rx: watch::Receiver<WorldSnapshot>,
+    pub tick_hz_rx: watch::Receiver<f64>,
+    pub audio_rx: watch::Receiver<AudioParams>,
+    pub meter: Arc<SharedMeter>,
+    pub telemetry: Arc<CallbackTelemetry>,
+}
+
+/// Starts the snapshot fan-out task.
+///
+/// This task:
+/// - Ticks at the snapshot rate, following changes to it.
+/// - Builds and encodes each live feed once per tick.
+/// - Drops feeds whose sessions have all gone.
+pub async fn start_fanout_task(
+    fanout: Arc<SnapshotFanout>,
+    sources: SnapshotSources,
+    mut snapshot_hz_rx: watch::Receiver<f64>,
+) {
+    let snapshot_interval =
+        |hz: f64| tokio::time::interval(tokio::time::Duration::from_secs_f64(1.0 / hz));
+    let mut interval = snapshot_interval(*snapshot_hz_rx.borrow_and_update());
+
+    loop {
+        tokio::select! {
+            Ok(()) = snapshot_hz_rx.changed() => {
+                interval = snapshot_interval(*snapshot_hz_rx.borrow_and_update());
+            }
+            _ = interval.tick() => {
+                for (feed, tx) in fanout.live_feeds() {
+                    for frame in feed_frames(&sources, &feed) {
+                        // No receivers left is fine; the feed goes next tick
+                        let _ = tx.send(frame);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// One tick of a feed: the field frame, then the snapshot, each if subscribed.
+fn feed_frames(sources: &SnapshotSources, feed: &SnapshotFeed) -> Vec<Message> {
+    let SnapshotFeed {
+        schema,
+        encoding,
+        subscription,
+    } = feed;
+    let mut frames = Vec::new();
+    // Grids go out on their own, as binary frames
+    if subscription.includes(Channel::Field) {
+        let world = sources.world_rx.borrow();
+        if let Some(field) = world.field() {
+            frames.push(Message::Binary(
+                encode_field_frame(world.tick(), field).into(),
+            ));
+        }
+    }
+    // Build only the parts the feed carries
+    let world = subscription
+        .includes(Channel::World)
+        .then(|| WireSnapshot::new(&sources.world_rx.borrow(), *schema));
+    let tick_rate_hz = world.is_some().then(|| *sources.tick_hz_rx.borrow());
+    let audio = subscription
+        .includes(Channel::Audio)
+        .then(|| AudioParamsSnapshot::from(*sources.audio_rx.borrow()));
+    let analysis = subscription.includes(Channel::Analysis).then(|| {
+        let reading = sources.meter.get();
+        let callback = sources.telemetry.stats();
+        AnalysisSnapshot {
+            peak_db: reading.peak_db,
+            gain_reduction_db: reading.gain_reduction_db,
+            momentary_lufs: reading.momentary_lufs,
+            integrated_lufs: reading.integrated_lufs,
+            auto_gain_db: reading.auto_gain_db,
+            callback_load: callback.load_mean,
+            callback_load_peak: callback.load_peak,
+            xruns: callback.xruns,
+            layers: sources.meter.layers().into(),
+        }
+    });
+    if world.is_none() && audio.is_none() && analysis.is_none() {
+        return frames;
+    }
+
+    let snapshot = ServerMessage::Snapshot {
+        version: schema.as_str().to_string(),
+        payload: SnapshotPayload {
+            world,
+            tick_rate_hz,
+            audio,
+            analysis,
+        },
+    };
+    let encoded = if subscription.selects_fields() {
+        serde_json::to_value(&snapshot)
+            .map_err(|e| e.to_string())
+            .and_then(|mut value| {
+                subscription.select_fields(&mut value["payload"]);
+                encoding.encode(&value)
+            })
+    } else {
+        encoding.encode(&snapshot)
+    };
+    match encoded {
+        Ok(bytes) if *encoding == Encoding::Json => {
+            if let Ok(json) = String::from_utf8(bytes) {
+                frames.push(Message::Text(json.into()));
+            }
+        }
+        Ok(bytes) => frames.push(Message::Binary(bytes.into())),
+        Err(e) => tracing::warn!("Failed to encode snapshot as {:?}: {}", encoding, e),
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambient_core::world::WorldState;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_sessions_on_one_feed_share_its_frames() {
+        let (_world_tx, world_rx) =
+            watch::channel(WorldSnapshot::from_world_state(&WorldState::new()));
+        let (_tick_hz_tx, tick_hz_rx) = watch::channel(30.0);
+        let (_audio_tx, audio_rx) = watch::channel(AudioParams::default());
+        let (_snapshot_hz_tx, snapshot_hz_rx) = watch::channel(100.0);
+        let fanout = Arc::new(SnapshotFanout::new());
+        tokio::spawn(start_fanout_task(
+            Arc::clone(&fanout),
+            SnapshotSources {
+                world_rx,
+                tick_hz_rx,
+                audio_rx,
+                meter: Arc::new(SharedMeter::new()),
+                telemetry: Arc::new(CallbackTelemetry::new()),
+            },
+            snapshot_hz_rx,
+        ));
+
+        // Broadcast-only channels do not split a feed
+        let defaults = Subscription::default();
+        let mut quiet = defaults.clone();
+        quiet.channels.retain(|channel| *channel != Channel::Events);
+        let json = SnapshotFeed::new(SchemaVersion::V2, Encoding::Json, &defaults);
+        assert_eq!(
+            SnapshotFeed::new(SchemaVersion::V2, Encoding::Json, &quiet),
+            json
+        );
+        let mut first = fanout.subscribe(json.clone());
+        let mut second = fanout.subscribe(json);
+        let cbor = fanout.subscribe(SnapshotFeed::new(
+            SchemaVersion::V2,
+            Encoding::Cbor,
+            &defaults,
+        ));
+        assert_eq!(fanout.live_feeds().len(), 2);
+
+        let frame = first.recv().await.unwrap();
+        assert_eq!(second.recv().await.unwrap(), frame);
+        let Message::Text(text) = frame else {
+            panic!("expected a text frame");
+        };
+        let snapshot: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(snapshot["payload"]["tick_rate_hz"], 30.0);
+
+        // A feed nobody streams stops
+        drop(cbor);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(fanout.live_feeds().len(), 1);
+        assert!(
+            SnapshotFeed::new(
+                SchemaVersion::V2,
+                Encoding::Json,
+                &Subscription {
+                    channels: vec![Channel::Events],
+                    ..defaults
+                }
+            )
+            .is_empty()
+        );
+    }
+}
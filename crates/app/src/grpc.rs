@@ -0,0 +1,287 @@
+//! gRPC service generated from `proto/ambient.proto`.
+//!
+//! Serves the same world as the HTTP API on a separate port, for integrations
+//! that only speak gRPC: `GetState`, a `StreamSnapshots` server stream and
+//! `SubmitEvent`. Requests authenticate with the HTTP API tokens, sent as
+//! `authorization: Bearer <token>` metadata.
+
+use crate::api::{AppState, SubmitError, apply_event, validate_perform_action};
+use crate::auth::{Auth, AuthError, Principal, Role};
+use crate::runtime::{EventQueueStats, QueuedEvent};
+use ambient_core::engine::ApplyResult;
+use ambient_core::events::{Event, EventSource, PerformAction};
+use ambient_core::world::{RunState, WorldSnapshot};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+pub mod pb {
+    tonic::include_proto!("ambient.v1");
+}
+
+use pb::ambient_world_server::{AmbientWorld, AmbientWorldServer};
+use pb::submit_event_request::Action;
+
+/// Highest snapshot rate a stream may request.
+const MAX_STREAM_HZ: f64 = 100.0;
+
+/// Lowest fixed snapshot rate a stream may request: one every 100 seconds.
+const MIN_STREAM_HZ: f64 = 0.01;
+
+/// Implements the `AmbientWorld` service over the runtime's channels.
+#[derive(Clone)]
+pub struct WorldService {
+    event_tx: mpsc::Sender<QueuedEvent>,
+    event_queue: Arc<EventQueueStats>,
+    world_state_rx: watch::Receiver<WorldSnapshot>,
+    snapshot_hz_rx: watch::Receiver<f64>,
+    auth: Auth,
+}
+
+impl WorldService {
+    pub fn new(state: &AppState) -> Self {
+        Self {
+            event_tx: state.event_tx.clone(),
+            event_queue: Arc::clone(&state.event_queue),
+            world_state_rx: state.world_state_rx.clone(),
+            snapshot_hz_rx: state.snapshot_hz_rx.clone(),
+            auth: state.auth.clone(),
+        }
+    }
+
+    /// Authenticates a request's bearer token and checks it carries `role`.
+    fn authorize<T>(&self, request: &Request<T>, role: Role) -> Result<Principal, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        let principal = self.auth.authenticate(token).map_err(auth_status)?;
+        principal.require(role).map_err(auth_status)?;
+        Ok(principal)
+    }
+}
+
+fn auth_status(error: AuthError) -> Status {
+    match error {
+        AuthError::Missing | AuthError::Invalid => Status::unauthenticated(error.to_string()),
+        AuthError::Forbidden { .. } => Status::permission_denied(error.to_string()),
+    }
+}
+
+/// A stream's `rate_hz` is 0, following `api.snapshot_hz`, or a fixed rate
+/// whose interval fits a `Duration`.
+fn check_stream_rate(rate_hz: f64) -> Result<(), Status> {
+    if rate_hz == 0.0 || (MIN_STREAM_HZ..=MAX_STREAM_HZ).contains(&rate_hz) {
+        return Ok(());
+    }
+    Err(Status::invalid_argument(format!(
+        "rate_hz must be 0 or in [{}, {}], got {}",
+        MIN_STREAM_HZ, MAX_STREAM_HZ, rate_hz
+    )))
+}
+
+#[tonic::async_trait]
+impl AmbientWorld for WorldService {
+    async fn get_state(
+        &self,
+        request: Request<pb::GetStateRequest>,
+    ) -> Result<Response<pb::WorldSnapshot>, Status> {
+        self.authorize(&request, Role::Viewer)?;
+        let snapshot = pb::WorldSnapshot::from(&*self.world_state_rx.borrow());
+        Ok(Response::new(snapshot))
+    }
+
+    type StreamSnapshotsStream = ReceiverStream<Result<pb::WorldSnapshot, Status>>;
+
+    async fn stream_snapshots(
+        &self,
+        request: Request<pb::StreamSnapshotsRequest>,
+    ) -> Result<Response<Self::StreamSnapshotsStream>, Status> {
+        self.authorize(&request, Role::Viewer)?;
+        let rate_hz = request.into_inner().rate_hz;
+        check_stream_rate(rate_hz)?;
+        // A fixed rate ignores config reloads; 0 follows api.snapshot_hz
+        let mut hz_rx = if rate_hz > 0.0 {
+            watch::channel(rate_hz).1
+        } else {
+            self.snapshot_hz_rx.clone()
+        };
+        let world_rx = self.world_state_rx.clone();
+        let (tx, rx) = mpsc::channel(4);
+
+        tokio::spawn(async move {
+            let snapshot_interval =
+                |hz: f64| tokio::time::interval(tokio::time::Duration::from_secs_f64(1.0 / hz));
+            let mut interval = snapshot_interval(*hz_rx.borrow_and_update());
+            loop {
+                tokio::select! {
+                    Ok(()) = hz_rx.changed() => {
+                        interval = snapshot_interval(*hz_rx.borrow_and_update());
+                    }
+                    _ = interval.tick() => {
+                        let snapshot = pb::WorldSnapshot::from(&*world_rx.borrow());
+                        if tx.send(Ok(snapshot)).await.is_err() {
+                            break; // Client went away
+                        }
+                    }
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn submit_event(
+        &self,
+        request: Request<pb::SubmitEventRequest>,
+    ) -> Result<Response<pb::SubmitEventResponse>, Status> {
+        let principal = self.authorize(&request, Role::Controller)?;
+        let action = request
+            .into_inner()
+            .action
+            .ok_or_else(|| Status::invalid_argument("action is required"))?;
+        let action = PerformAction::from(action);
+        validate_perform_action(&action).map_err(Status::invalid_argument)?;
+
+        let event = Event::Perform(action);
+        let event = match principal.name {
+            Some(name) => event.with_source(EventSource::ApiKey(name)),
+            None => event.into(),
+        };
+        match apply_event(&self.event_tx, &self.event_queue, event).await {
+            Ok(result) => Ok(Response::new(pb::SubmitEventResponse::from(&result))),
+            Err(SubmitError::Full) => Err(Status::resource_exhausted(
+                "Event queue is full, retry shortly",
+            )),
+            Err(SubmitError::Closed) => {
+                Err(Status::internal("Failed to send event: channel closed"))
+            }
+        }
+    }
+}
+
+/// Serves the gRPC API on `addr` until the process exits.
+pub async fn start_grpc_server(addr: SocketAddr, service: WorldService) {
+    if let Err(e) = tonic::transport::Server::builder()
+        .add_service(AmbientWorldServer::new(service))
+        .serve(addr)
+        .await
+    {
+        tracing::error!("gRPC server on {} failed: {}", addr, e);
+    }
+}
+
+impl From<Action> for PerformAction {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::Pulse(pb::Intensity { intensity }) => PerformAction::Pulse { intensity },
+            Action::Stir(pb::Intensity { intensity }) => PerformAction::Stir { intensity },
+            Action::Calm(pb::Intensity { intensity }) => PerformAction::Calm { intensity },
+            Action::Heat(pb::Intensity { intensity }) => PerformAction::Heat { intensity },
+            Action::Tense(pb::Intensity { intensity }) => PerformAction::Tense { intensity },
+            Action::Expand(pb::Intensity { intensity }) => PerformAction::Expand { intensity },
+            Action::Contract(pb::Intensity { intensity }) => PerformAction::Contract { intensity },
+            Action::Clarify(pb::Intensity { intensity }) => PerformAction::Clarify { intensity },
+            Action::Roughen(pb::Intensity { intensity }) => PerformAction::Roughen { intensity },
+            Action::Scene(pb::SceneAction { name }) => PerformAction::Scene { name },
+            Action::Freeze(pb::Freeze { seconds }) => PerformAction::Freeze { seconds },
+            Action::Undo(pb::Undo {}) => PerformAction::Undo,
+            Action::PulseAt(pb::PulseAt { x, y, intensity }) => {
+                PerformAction::PulseAt { x, y, intensity }
+            }
+        }
+    }
+}
+
+impl From<&WorldSnapshot> for pb::WorldSnapshot {
+    fn from(snapshot: &WorldSnapshot) -> Self {
+        let run_state = match snapshot.run_state() {
+            RunState::Running => pb::RunState::Running,
+            RunState::Paused => pb::RunState::Paused,
+        };
+        Self {
+            tick: snapshot.tick(),
+            timestamp_ms: snapshot.timestamp_ms(),
+            sim_time_secs: snapshot.sim_time_secs(),
+            run_state: run_state.into(),
+            time_scale: snapshot.time_scale(),
+            density: snapshot.density(),
+            rhythm: snapshot.rhythm(),
+            tension: snapshot.tension(),
+            energy: snapshot.energy(),
+            warmth: snapshot.warmth(),
+            sparkle_impulse: snapshot.sparkle_impulse(),
+            scene: snapshot.scene().map(|scene| pb::Scene {
+                name: scene.name.clone(),
+                transition_secs: scene.transition_secs,
+                sequence: scene.sequence,
+            }),
+            space: snapshot.space(),
+            clarity: snapshot.clarity(),
+        }
+    }
+}
+
+impl From<&ApplyResult> for pb::SubmitEventResponse {
+    fn from(result: &ApplyResult) -> Self {
+        Self {
+            applied: result.applied,
+            clamped_fields: result
+                .clamped_fields
+                .iter()
+                .map(|field| field.to_string())
+                .collect(),
+            snapshot: Some(pb::WorldSnapshot::from(&result.resulting_snapshot)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambient_core::engine::WorldEngine;
+
+    #[test]
+    fn test_snapshot_conversion() {
+        let mut engine = WorldEngine::new();
+        engine.set_run_state(RunState::Paused);
+        let result = engine.apply(Event::Perform(PerformAction::Scene {
+            name: "peaceful".to_string(),
+        }));
+
+        let response = pb::SubmitEventResponse::from(&result);
+        let snapshot = response.snapshot.unwrap();
+        assert_eq!(snapshot.run_state(), pb::RunState::Paused);
+        assert_eq!(snapshot.scene.unwrap().name, "peaceful");
+        assert_eq!(snapshot.density, result.resulting_snapshot.density());
+    }
+
+    #[test]
+    fn test_stream_rate_bounds() {
+        for rate_hz in [0.0, MIN_STREAM_HZ, 30.0, MAX_STREAM_HZ] {
+            assert!(check_stream_rate(rate_hz).is_ok(), "{}", rate_hz);
+        }
+        // Tiny rates would overflow the snapshot interval
+        for rate_hz in [5e-324, 1e-300, 0.001, -1.0, 100.5, f64::NAN] {
+            let status = check_stream_rate(rate_hz).unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[test]
+    fn test_action_conversion() {
+        let action = Action::Pulse(pb::Intensity { intensity: 0.4 });
+        assert_eq!(
+            PerformAction::from(action),
+            PerformAction::Pulse { intensity: 0.4 }
+        );
+        let action = Action::Freeze(pb::Freeze { seconds: 2.0 });
+        assert_eq!(
+            PerformAction::from(action),
+            PerformAction::Freeze { seconds: 2.0 }
+        );
+    }
+}
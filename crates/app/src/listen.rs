@@ -0,0 +1,138 @@
+//! Local listeners for the API: a Unix domain socket and sockets passed in by
+//! systemd socket activation.
+//!
+//! With `api.unix_socket` set, the API is also served on that path, so a
+//! controller process on the same machine needs no TCP port; access is
+//! governed by the socket file's permissions as well as the API tokens.
+//!
+//! Under systemd socket activation (`LISTEN_PID` / `LISTEN_FDS`), the passed
+//! TCP or Unix listeners are served instead of binding `api.port` and
+//! `api.unix_socket`, since systemd already holds those addresses.
+
+use std::io;
+use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use tokio::net::{TcpListener, UnixListener};
+
+/// First descriptor systemd passes (`SD_LISTEN_FDS_START`).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A listener handed over by systemd.
+pub enum Inherited {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// Takes the listeners systemd passed to this process, if any.
+pub fn systemd_listeners() -> io::Result<Vec<Inherited>> {
+    let count = listen_fds(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    )?;
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(inherit)
+        .collect()
+}
+
+/// How many descriptors systemd passed, given `LISTEN_PID` and `LISTEN_FDS`.
+/// None unless `LISTEN_PID` names this process, as the variables may have
+/// been inherited from a parent they were meant for.
+fn listen_fds(pid: Option<&str>, fds: Option<&str>, own_pid: u32) -> io::Result<RawFd> {
+    let for_us = pid.and_then(|pid| pid.parse::<u32>().ok()) == Some(own_pid);
+    match fds {
+        Some(count) if for_us => count
+            .parse::<RawFd>()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "LISTEN_FDS is not a number")),
+        _ => Ok(0),
+    }
+}
+
+fn inherit(fd: RawFd) -> io::Result<Inherited> {
+    // SAFETY: systemd passes each descriptor in this range open and owned by
+    // this process, and it is taken exactly once.
+    let tcp = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+    // Only an inet socket has an address std can read back.
+    if tcp.local_addr().is_ok() {
+        tcp.set_nonblocking(true)?;
+        return TcpListener::from_std(tcp).map(Inherited::Tcp);
+    }
+    // SAFETY: the descriptor was just released by `into_raw_fd`.
+    let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+    unix.local_addr()?;
+    unix.set_nonblocking(true)?;
+    UnixListener::from_std(unix).map(Inherited::Unix)
+}
+
+/// Binds `path`, replacing a socket left behind by an earlier run. Any other
+/// kind of file at `path` is an error rather than being deleted.
+pub fn bind_unix(path: &Path) -> io::Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    UnixListener::bind(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bind_unix_replaces_stale_socket_only() {
+        let dir = std::env::temp_dir().join(format!("ambient-listen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("api.sock");
+        drop(bind_unix(&path).unwrap());
+        assert!(path.exists());
+        drop(bind_unix(&path).unwrap());
+
+        let file = dir.join("not-a-socket");
+        std::fs::write(&file, "keep").unwrap();
+        assert!(bind_unix(&file).is_err());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_listen_fds_only_counts_descriptors_meant_for_us() {
+        assert_eq!(listen_fds(Some("42"), Some("2"), 42).unwrap(), 2);
+        assert_eq!(listen_fds(Some("41"), Some("2"), 42).unwrap(), 0);
+        assert_eq!(listen_fds(None, Some("2"), 42).unwrap(), 0);
+        assert_eq!(listen_fds(Some("42"), None, 42).unwrap(), 0);
+        assert!(listen_fds(Some("42"), Some("two"), 42).is_err());
+        // A bad count meant for another process is not ours to report
+        assert_eq!(listen_fds(Some("41"), Some("two"), 42).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_inherit_tells_tcp_from_unix_listeners() {
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcp.local_addr().unwrap();
+        match inherit(tcp.into_raw_fd()).unwrap() {
+            Inherited::Tcp(listener) => assert_eq!(listener.local_addr().unwrap(), addr),
+            Inherited::Unix(_) => panic!("tcp listener inherited as unix"),
+        }
+
+        let dir = std::env::temp_dir().join(format!("ambient-inherit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("api.sock");
+        let unix = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        match inherit(unix.into_raw_fd()).unwrap() {
+            Inherited::Unix(listener) => {
+                let local = listener.local_addr().unwrap();
+                assert_eq!(local.as_pathname(), Some(path.as_path()));
+            }
+            Inherited::Tcp(_) => panic!("unix listener inherited as tcp"),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
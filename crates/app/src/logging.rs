@@ -0,0 +1,270 @@
+//! Tracing setup: text or JSON lines, per-subsystem levels, and a filter that
+//! can be changed while running (`POST /admin/log_level`, config reload).
+//!
+//! Subsystems name groups of log targets, so `audio = "debug"` covers the
+//! audio crate and the mic input without spelling out module paths.
+//!
+//! With the `otel` feature and `logging.otlp_endpoint` set, spans are also
+//! exported over OTLP/gRPC: an event's trace runs from the HTTP request
+//! through `event.submit` and `world.apply` to `audio.update`. The export has
+//! its own filter, so lowering the log level does not drop spans.
+
+use crate::config::{LogFormat, LoggingConfig};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, reload};
+use utoipa::ToSchema;
+#[cfg(feature = "otel")]
+use {opentelemetry::trace::TracerProvider, opentelemetry_sdk::trace::SdkTracerProvider};
+
+/// Subsystem names and the log targets each one covers.
+pub const SUBSYSTEMS: &[(&str, &[&str])] = &[
+    ("world", &["ambient_core", "app::runtime", "app::anomaly"]),
+    ("audio", &["audio", "app::room"]),
+    (
+        "api",
+        &[
+            "app::api",
+            "app::grpc",
+            "app::tls",
+            "app::mqtt",
+            "axum",
+            "tower_http",
+        ],
+    ),
+];
+
+/// The adjustable part of the log filter.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct LogSettings {
+    /// Default directive, e.g. `info` or `info,app::reload=debug`.
+    pub level: String,
+    /// Level per subsystem, overriding `level` for its targets.
+    pub targets: BTreeMap<String, String>,
+    /// The full directive in effect.
+    pub filter: String,
+}
+
+impl LogSettings {
+    pub fn new(level: String, targets: BTreeMap<String, String>) -> Result<Self, String> {
+        let filter = filter_directive(&level, &targets)?;
+        Ok(Self {
+            level,
+            targets,
+            filter,
+        })
+    }
+}
+
+/// Expands `level` and the subsystem levels into one filter directive.
+pub fn filter_directive(level: &str, targets: &BTreeMap<String, String>) -> Result<String, String> {
+    EnvFilter::builder()
+        .parse(level)
+        .map_err(|e| format!("invalid log level {:?}: {}", level, e))?;
+    let mut directive = level.to_string();
+    for (name, target_level) in targets {
+        let Some((_, modules)) = SUBSYSTEMS.iter().find(|(subsystem, _)| subsystem == name) else {
+            let names: Vec<_> = SUBSYSTEMS.iter().map(|(subsystem, _)| *subsystem).collect();
+            return Err(format!(
+                "unknown log subsystem {:?}, expected one of {}",
+                name,
+                names.join(", ")
+            ));
+        };
+        target_level
+            .parse::<LevelFilter>()
+            .map_err(|_| format!("invalid level {:?} for {}", target_level, name))?;
+        for module in *modules {
+            directive.push_str(&format!(",{}={}", module, target_level));
+        }
+    }
+    Ok(directive)
+}
+
+/// Swaps the running log filter.
+#[derive(Clone)]
+pub struct LogControl {
+    handle: reload::Handle<EnvFilter, Registry>,
+    current: Arc<Mutex<LogSettings>>,
+    #[cfg(feature = "otel")]
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl LogControl {
+    pub fn current(&self) -> LogSettings {
+        self.current.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, settings: LogSettings) -> Result<(), String> {
+        let filter = EnvFilter::builder()
+            .parse(&settings.filter)
+            .map_err(|e| e.to_string())?;
+        self.handle.reload(filter).map_err(|e| e.to_string())?;
+        *self.current.lock().unwrap() = settings;
+        Ok(())
+    }
+
+    /// A control over a filter no subscriber uses, for tests that build an
+    /// `AppState` without installing logging.
+    #[cfg(test)]
+    pub fn detached() -> Self {
+        let (layer, handle) = reload::Layer::new(EnvFilter::new("info"));
+        // The handle only reloads while its layer lives
+        std::mem::forget(layer);
+        Self {
+            handle,
+            current: Arc::new(Mutex::new(
+                LogSettings::new("info".to_string(), BTreeMap::new()).unwrap(),
+            )),
+            #[cfg(feature = "otel")]
+            tracer_provider: None,
+        }
+    }
+
+    /// Flushes spans not yet exported.
+    pub fn shutdown(&self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = &self.tracer_provider
+            && let Err(e) = provider.shutdown()
+        {
+            tracing::warn!("Failed to flush spans: {}", e);
+        }
+    }
+}
+
+/// Installs the global subscriber. `RUST_LOG`, when set, replaces the
+/// configured filter until it is next changed.
+pub fn init(config: &LoggingConfig) -> LogControl {
+    let mut settings = LogSettings::new(config.level.clone(), config.targets.clone())
+        .unwrap_or_else(|_| LogSettings::new("info".to_string(), BTreeMap::new()).unwrap());
+    if let Ok(env) = std::env::var(EnvFilter::DEFAULT_ENV)
+        && EnvFilter::builder().parse(&env).is_ok()
+    {
+        settings.filter = env;
+    }
+    let filter = EnvFilter::builder()
+        .parse(&settings.filter)
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, handle) = reload::Layer::new(filter);
+    let timer = fmt::time::UtcTime::rfc_3339();
+    let output = match config.format {
+        LogFormat::Text => fmt::layer().with_timer(timer).boxed(),
+        LogFormat::Json => fmt::layer().json().with_timer(timer).boxed(),
+    };
+    let registry = tracing_subscriber::registry().with(output.with_filter(filter));
+
+    #[cfg(feature = "otel")]
+    {
+        let provider = config
+            .otlp_endpoint
+            .as_deref()
+            .map(|endpoint| otel::provider(endpoint, &config.service_name));
+        let spans = provider
+            .as_ref()
+            .and_then(|provider| provider.as_ref().ok())
+            .map(|provider| {
+                tracing_opentelemetry::layer()
+                    .with_tracer(provider.tracer("ambient-world"))
+                    .with_filter(otel::span_targets())
+            });
+        registry.with(spans).init();
+        let tracer_provider = match provider {
+            Some(Ok(provider)) => {
+                tracing::info!(
+                    "Exporting spans to {}",
+                    config.otlp_endpoint.as_deref().unwrap_or_default()
+                );
+                Some(provider)
+            }
+            Some(Err(e)) => {
+                tracing::warn!("Span export disabled: {}", e);
+                None
+            }
+            None => None,
+        };
+        LogControl {
+            handle,
+            current: Arc::new(Mutex::new(settings)),
+            tracer_provider,
+        }
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        registry.init();
+        if config.otlp_endpoint.is_some() {
+            tracing::warn!("Built without the otel feature, ignoring logging.otlp_endpoint");
+        }
+        LogControl {
+            handle,
+            current: Arc::new(Mutex::new(settings)),
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::Resource;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use tracing::level_filters::LevelFilter;
+    use tracing_subscriber::filter::Targets;
+
+    /// Batches spans to an OTLP/gRPC collector such as the OpenTelemetry
+    /// Collector, Jaeger or Tempo.
+    pub fn provider(endpoint: &str, service_name: &str) -> Result<SdkTracerProvider, String> {
+        let exporter = SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(service_name.to_string())
+                    .build(),
+            )
+            .build())
+    }
+
+    /// Spans from this workspace and the HTTP request spans, whatever the
+    /// log level.
+    pub fn span_targets() -> Targets {
+        Targets::new()
+            .with_target("app", LevelFilter::INFO)
+            .with_target("ambient_core", LevelFilter::INFO)
+            .with_target("audio", LevelFilter::INFO)
+            .with_target("tower_http", LevelFilter::INFO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subsystem_levels_expand_to_targets() {
+        let targets = BTreeMap::from([("audio".to_string(), "debug".to_string())]);
+        assert_eq!(
+            filter_directive("warn", &targets).unwrap(),
+            "warn,audio=debug,app::room=debug"
+        );
+        let settings = LogSettings::new("info".to_string(), BTreeMap::new()).unwrap();
+        assert_eq!(settings.filter, "info");
+
+        let unknown = BTreeMap::from([("video".to_string(), "debug".to_string())]);
+        assert!(
+            filter_directive("info", &unknown)
+                .unwrap_err()
+                .contains("video")
+        );
+        let bad_level = BTreeMap::from([("api".to_string(), "loud".to_string())]);
+        assert!(filter_directive("info", &bad_level).is_err());
+        assert!(filter_directive("info,[", &BTreeMap::new()).is_err());
+    }
+}
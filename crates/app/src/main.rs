@@ -1,112 +1,419 @@
+mod anomaly;
 mod api;
+mod audio_recorder;
+mod audit;
+mod auth;
+mod calibration;
+mod config;
+mod error;
+mod fanout;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(unix)]
+mod listen;
+mod logging;
+#[cfg(feature = "midi")]
+mod midi;
+mod mqtt;
+mod openapi;
+mod patches;
+mod presets;
+mod protocol;
+mod recorder;
+mod reload;
+mod request_id;
+mod resume;
+mod room;
 mod runtime;
+mod schema;
+mod seasons;
+mod sensors;
+mod stats;
+#[cfg(feature = "stream")]
+mod stream;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "tls")]
+mod tls;
+mod web;
+mod ws_schema;
 
-use crate::runtime::{start_audio_control_task, start_tick_task, start_world_task};
+use crate::anomaly::{AlertStatus, AnomalyDetector, AnomalyMonitor};
+use crate::auth::Auth;
+use crate::calibration::Calibrator;
+use crate::config::{Cli, Config, OutputBackendConfig};
+use crate::fanout::{SnapshotFanout, SnapshotSources};
+use crate::patches::load_patches;
+use crate::presets::PresetStore;
+use crate::reload::{LiveSettings, start_config_watcher_task};
+use crate::resume::ResumeStore;
+use crate::runtime::{
+    ActiveMapping, AudioControlInputs, EVENT_QUEUE_CAPACITY, EventQueueStats, PendingAudioSpan,
+    TickStats, WorldOutputs, map_snapshot, start_audio_control_task, start_tick_task,
+    start_world_task, unix_time_ms,
+};
+use ambient_core::composer::Composer;
+use ambient_core::engine::WorldEngine;
 use ambient_core::world::{WorldSnapshot, WorldState};
-use audio::engine::AudioEngine;
-use audio::params::{AudioParams, SharedAudioParams};
+use audio::backend::{BackendKind, CaptureBuffer, EngineSetup};
+use audio::effects::SharedEffects;
+use audio::health::SharedOutputHealth;
+use audio::master::SharedMeter;
+use audio::mixer::SharedLayerGains;
+use audio::params::{LayerAmounts, SharedAudioParams};
+use audio::registry::{LayerRegistry, default_stack};
+use audio::sample::{Sample, load_sample_dir};
+use audio::status::{AudioStatus, EngineState, SharedAudioStatus};
+use audio::tap::OutputTap;
+use audio::telemetry::CallbackTelemetry;
+use audio::transition::SharedTransition;
+use audio::watchdog::AudioWatchdog;
+use audio::waveform::SharedWaveform;
 use axum::serve;
+use clap::Parser;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::TcpListener;
-use tokio::sync::{RwLock, mpsc, watch};
+use tokio::sync::{Mutex, RwLock, broadcast, mpsc, watch};
 use tokio::time::interval;
 use tracing::{info, warn};
 
-#[derive(Debug)]
-struct Config {
-    tick_hz: f64,
-    port: u16,
+/// How long shutdown waits for a task to tidy up, such as releasing MIDI notes.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The configured output backend, or None if this build cannot provide it.
+fn output_backend(config: &Config, capture: Option<&CaptureBuffer>) -> Option<BackendKind> {
+    match config.audio.output.backend {
+        #[cfg(feature = "audio-output")]
+        OutputBackendConfig::Cpal => Some(BackendKind::Cpal),
+        #[cfg(not(feature = "audio-output"))]
+        OutputBackendConfig::Cpal => {
+            info!("Built without the audio-output feature, running headless");
+            None
+        }
+        OutputBackendConfig::Null => Some(BackendKind::Null(config.output_format())),
+        OutputBackendConfig::Buffer => Some(BackendKind::Buffer {
+            format: config.output_format(),
+            capture: capture?.clone(),
+            realtime: config.audio.output.realtime,
+        }),
+    }
+}
+
+/// Starts the audio output under a watchdog that rebuilds it on device loss.
+///
+/// Returns None (running without audio) only if the watchdog thread cannot be spawned;
+/// a missing device is retried in the background and reported through `status`.
+fn start_audio_output(
+    setup: EngineSetup,
+    kind: BackendKind,
+    status: Arc<SharedAudioStatus>,
+) -> Option<AudioWatchdog> {
+    status.update(|s| s.state = EngineState::Starting);
+    match AudioWatchdog::spawn(setup, kind, Arc::clone(&status)) {
+        Ok(watchdog) => Some(watchdog),
+        Err(e) => {
+            warn!(
+                "Audio watchdog failed to start ({}), continuing without audio output",
+                e
+            );
+            status.update(|s| {
+                s.state = EngineState::Disabled;
+                s.last_error = Some(e.to_string());
+            });
+            None
+        }
+    }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            tick_hz: 20.0,
-            port: 3000,
+/// Loads the configured field recordings, continuing without them on error.
+fn load_samples(dir: Option<&std::path::Path>) -> Arc<[Sample]> {
+    let Some(dir) = dir else {
+        return Arc::from(Vec::new());
+    };
+    match load_sample_dir(dir) {
+        Ok(samples) => {
+            info!("Loaded {} samples from {}", samples.len(), dir.display());
+            Arc::from(samples)
+        }
+        Err(e) => {
+            warn!("{}, continuing without samples", e);
+            Arc::from(Vec::new())
         }
     }
 }
 
-impl Config {
-    fn from_env() -> Self {
-        let tick_hz = std::env::var("TICK_HZ")
-            .unwrap_or_else(|_| "20.0".to_string())
-            .parse()
-            .unwrap_or(20.0);
-        let port = std::env::var("PORT")
-            .unwrap_or_else(|_| "3000".to_string())
-            .parse()
-            .unwrap_or(3000);
-        Self { tick_hz, port }
+/// Finds the sample to granulate, falling back to the first one loaded.
+fn find_grain_source(samples: &[Sample], name: Option<&str>) -> Option<usize> {
+    if samples.is_empty() {
+        return None;
     }
+    let Some(name) = name else {
+        return Some(0);
+    };
+    let found = samples.iter().position(|sample| sample.name() == name);
+    if found.is_none() {
+        warn!(
+            "Grain source '{}' not found, granulating '{}'",
+            name,
+            samples[0].name()
+        );
+    }
+    Some(found.unwrap_or(0))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Resolve config before logging so the log level can come from it
+    let cli = Cli::parse();
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Configuration error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let mapping_profiles = match config.mapping_profiles() {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            eprintln!("Configuration error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let effect_chains = match config.effect_chains() {
+        Ok(chains) => chains,
+        Err(e) => {
+            eprintln!("Configuration error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let scenes = match config.load_scenes() {
+        Ok(scenes) => scenes,
+        Err(e) => {
+            eprintln!("Configuration error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let preset_store = match PresetStore::load(config.presets_path.clone()) {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("Configuration error: presets_path {}", e);
+            std::process::exit(2);
+        }
+    };
+    let patches = match config.audio.patches_dir.as_deref().map(load_patches) {
+        Some(Ok(patches)) => patches,
+        Some(Err(e)) => {
+            eprintln!("Configuration error: audio.patches_dir {}", e);
+            std::process::exit(2);
+        }
+        None => BTreeMap::new(),
+    };
+    let startup_patch = config.audio.patch.as_ref().map(|name| {
+        let Some(patch) = patches.get(name) else {
+            eprintln!(
+                "Configuration error: audio.patch '{}' is not in audio.patches_dir",
+                name
+            );
+            std::process::exit(2);
+        };
+        (name, patch)
+    });
+
     // Setup tracing with timestamped logs
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .with_timer(tracing_subscriber::fmt::time::UtcTime::rfc_3339())
-        .init();
+    let log_control = logging::init(&config.logging);
 
     info!("Starting...");
-
-    let config = Config::from_env();
+    info!("Config: {:?}", config);
 
     // Create channels
-    let (event_tx, event_rx) = mpsc::channel(100);
+    let (event_tx, event_rx) = mpsc::channel(EVENT_QUEUE_CAPACITY);
+    let event_queue = Arc::new(EventQueueStats::new());
     let initial_state = WorldState::new();
     let initial_snapshot = WorldSnapshot::from_world_state(&initial_state);
     let (state_tx, state_rx) = watch::channel(initial_snapshot.clone());
 
     // Create initial audio params from initial world snapshot
-    let initial_audio_params = AudioParams::from_world_state(
-        initial_snapshot.density() as f32,
-        initial_snapshot.rhythm() as f32,
-        initial_snapshot.tension() as f32,
-        initial_snapshot.energy() as f32,
-        initial_snapshot.warmth() as f32,
-        initial_snapshot.sparkle_impulse() as f32,
-    );
+    let initial_mapping = ActiveMapping {
+        name: config.audio.mapping.profile.clone(),
+        profile: mapping_profiles[&config.audio.mapping.profile].clone(),
+    };
+    let initial_audio_params = map_snapshot(&initial_mapping.profile, &initial_snapshot);
     let shared_audio_params = Arc::new(SharedAudioParams::new(initial_audio_params));
     let (audio_params_tx, audio_params_rx) = watch::channel(initial_audio_params);
+    let (layer_amounts_tx, layer_amounts_rx) = watch::channel(LayerAmounts::default());
+    let (audio_override_tx, audio_override_rx) = watch::channel(None);
+    let (mapping_tx, mapping_rx) = watch::channel(initial_mapping);
+    let shared_layer_gains = Arc::new(SharedLayerGains::new(config.layer_gains()));
+    let shared_effects = Arc::new(SharedEffects::new(effect_chains));
+    let shared_transition = Arc::new(SharedTransition::new());
+    let shared_meter = Arc::new(SharedMeter::new());
+    let shared_waveform = Arc::new(SharedWaveform::new());
+    let output_health = Arc::new(SharedOutputHealth::new());
+    let callback_telemetry = Arc::new(CallbackTelemetry::new());
+    let stream_tap = Arc::new(OutputTap::default());
+    let recorder_tap = Arc::new(OutputTap::default());
+    let audio_status = Arc::new(SharedAudioStatus::new(AudioStatus::new(
+        EngineState::Disabled,
+    )));
 
-    // Start audio engine early (with error handling)
-    let audio_params_clone = Arc::clone(&shared_audio_params);
-    let audio_engine_result = AudioEngine::start(audio_params_clone);
-    let _audio_engine = match audio_engine_result {
-        Ok(engine) => {
-            info!("Audio engine started successfully");
-            Some(engine)
-        }
-        Err(e) => {
-            warn!(
-                "Audio engine failed to start ({}), continuing without audio output",
-                e
-            );
-            None
-        }
+    // Start audio output early (with error handling)
+    let capture = (config.audio.output.backend == OutputBackendConfig::Buffer)
+        .then(|| CaptureBuffer::new(config.output_format(), config.audio.output.capture_secs));
+    let backend = if !config.audio.enabled {
+        info!("Audio output disabled (--no-audio), running headless");
+        None
+    } else if config.midi.enabled && config.midi.mute_audio {
+        info!("Built-in audio muted for MIDI output, running headless");
+        None
+    } else {
+        output_backend(&config, capture.as_ref())
+    };
+    // Samples are only worth loading for a running engine
+    let samples = match backend {
+        Some(_) => load_samples(config.audio.samples_dir.as_deref()),
+        None => Arc::from(Vec::new()),
     };
+    let grain_source = find_grain_source(&samples, config.audio.grain_source.as_deref());
+    let mut initial_stack = default_stack(
+        config.drone_mode(),
+        config.noise_colors(),
+        grain_source,
+        !samples.is_empty(),
+    );
+    if let Some((name, patch)) = startup_patch {
+        match patch.resolve(&samples) {
+            Ok(resolved) => {
+                info!("Starting with audio patch '{}'", name);
+                initial_stack = resolved.stack;
+                shared_effects.set_chains(resolved.effects);
+                shared_layer_gains.set(resolved.gains);
+            }
+            Err(e) => warn!("Audio patch '{}' not used, {}", name, e),
+        }
+    }
+    let layer_registry = Arc::new(LayerRegistry::with_smoothing(
+        initial_stack,
+        samples,
+        config.smoothing(),
+    ));
+    let _audio_watchdog = backend.and_then(|kind| {
+        let setup = EngineSetup {
+            params: Arc::clone(&shared_audio_params),
+            gains: Arc::clone(&shared_layer_gains),
+            effects: Arc::clone(&shared_effects),
+            transition: Arc::clone(&shared_transition),
+            meter: Arc::clone(&shared_meter),
+            waveform: Arc::clone(&shared_waveform),
+            health: Arc::clone(&output_health),
+            telemetry: Arc::clone(&callback_telemetry),
+            layers: Arc::clone(&layer_registry),
+            ducking: config.ducking(),
+            gate: config.gate(),
+            loudness: config.loudness(),
+            spatial: config.spatial(),
+            taps: vec![Arc::clone(&stream_tap), Arc::clone(&recorder_tap)],
+        };
+        start_audio_output(setup, kind, Arc::clone(&audio_status))
+    });
+    // Saved on shutdown
+    let capture = capture
+        .zip(config.audio.output.capture_path.clone())
+        .map(|(capture, path)| (capture, path, config.output_format()));
 
-    // Default tick rate
-    let tick_hz = config.tick_hz;
+    let tick_hz = config.world.tick_hz;
     info!("Tick rate: {:.0} Hz", tick_hz);
 
+    let mut engine = WorldEngine::new();
+    engine.set_dynamics(config.dynamics());
+    engine.set_step_hz(config.world.step_hz);
+    engine.set_time_scale(config.world.time_scale);
+    for (name, targets) in scenes {
+        info!("Loaded scene '{}'", name);
+        engine.register_scene(name, targets);
+    }
+    room::start_room(&config.room, &mut engine, &event_tx, &event_queue);
+    if config.composer.enabled {
+        let seed = config.composer.seed.unwrap_or_else(rand::random);
+        info!(
+            "Composer: arcs of {:.0}s, seed {}",
+            config.composer.arc_secs, seed
+        );
+        engine.add_modulator(Composer::new(config.composer.settings(), seed));
+    }
+    if let Err(e) = seasons::start_seasons(&config.seasons, &mut engine) {
+        eprintln!("Configuration error: seasons {}", e);
+        std::process::exit(2);
+    }
+    let noise_seed = config.world.noise_seed.unwrap_or_else(rand::random);
+    info!("Noise seed {}", noise_seed);
+    if !preset_store.presets().is_empty() {
+        info!("Loaded {} presets", preset_store.presets().len());
+    }
+    if !patches.is_empty() {
+        info!("Loaded {} audio patches", patches.len());
+    }
+
+    // Live-tunable settings
+    let (world_command_tx, world_command_rx) = mpsc::channel(16);
+    let (tick_hz_tx, tick_hz_rx) = watch::channel(tick_hz);
+    let (tick_stats_tx, tick_stats_rx) = watch::channel(TickStats::new(tick_hz));
+    let (snapshot_hz_tx, snapshot_hz_rx) = watch::channel(config.api.snapshot_hz);
+    let (broadcast_tx, _) = broadcast::channel(64);
+
+    let audit_tx = config.audit_log.clone().map(|path| {
+        let (audit_tx, audit_rx) = mpsc::channel(audit::AUDIT_CHANNEL_CAPACITY);
+        tokio::spawn(audit::start_audit_task(path, audit_rx));
+        audit_tx
+    });
+
+    let (alerts_tx, alerts_rx) = watch::channel(AlertStatus::default());
+    let monitor = AnomalyMonitor {
+        detector: AnomalyDetector::new(config.alerts.clone(), unix_time_ms()),
+        output: output_health,
+        status_tx: alerts_tx,
+        broadcast_tx: broadcast_tx.clone(),
+    };
+
     // Spawn tasks
-    tokio::spawn(start_world_task(event_rx, state_tx));
-    tokio::spawn(start_tick_task(event_tx.clone(), tick_hz));
+    let audio_span = PendingAudioSpan::default();
+    tokio::spawn(start_world_task(
+        engine,
+        event_rx,
+        world_command_rx,
+        WorldOutputs {
+            state_tx,
+            broadcast_tx: broadcast_tx.clone(),
+        },
+        Arc::clone(&audio_span),
+        audit_tx.clone(),
+        monitor,
+    ));
+    tokio::spawn(start_tick_task(
+        event_tx.clone(),
+        tick_hz_rx.clone(),
+        tick_stats_tx,
+        Arc::clone(&event_queue),
+        config.world.coalesce_ticks,
+    ));
 
     // Start audio control task
     let state_rx_for_audio = state_rx.clone();
     let audio_params_for_control = Arc::clone(&shared_audio_params);
     let audio_params_tx_for_control = audio_params_tx.clone();
     tokio::spawn(start_audio_control_task(
-        state_rx_for_audio,
+        AudioControlInputs {
+            state_rx: state_rx_for_audio,
+            audio_span,
+            layer_amounts_rx,
+            override_rx: audio_override_rx,
+            mapping_rx,
+            quantize_delay: config.world.pulse.quantize_delay,
+            gate: config.gate(),
+        },
         audio_params_for_control,
+        shared_transition,
         audio_params_tx_for_control,
     ));
 
@@ -118,12 +425,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             interval.tick().await;
             let borrowed = state_rx_clone.borrow();
             info!(
-                "State: density={:.3}, rhythm={:.3}, tension={:.3}, energy={:.3}, warmth={:.3}",
+                "State: density={:.3}, rhythm={:.3}, tension={:.3}, energy={:.3}, warmth={:.3}, space={:.3}, clarity={:.3}",
                 borrowed.density(),
                 borrowed.rhythm(),
                 borrowed.tension(),
                 borrowed.energy(),
-                borrowed.warmth()
+                borrowed.warmth(),
+                borrowed.space(),
+                borrowed.clarity()
             );
         }
     });
@@ -141,15 +450,263 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         current_snapshot_for_task,
     ));
 
-    let app = api::create_router(event_tx, current_snapshot, state_rx, audio_params_rx);
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", config.port)).await?;
-    info!("API server listening on http://localhost:{}", config.port);
-    tokio::spawn(async move {
-        serve(listener, app).await.unwrap();
+    // Encode WebSocket snapshots once per feed, for all sessions on it
+    let snapshot_fanout = Arc::new(SnapshotFanout::new());
+    tokio::spawn(fanout::start_fanout_task(
+        Arc::clone(&snapshot_fanout),
+        SnapshotSources {
+            world_rx: state_rx.clone(),
+            tick_hz_rx: tick_hz_rx.clone(),
+            audio_rx: audio_params_rx.clone(),
+            meter: Arc::clone(&shared_meter),
+            telemetry: Arc::clone(&callback_telemetry),
+        },
+        snapshot_hz_rx.clone(),
+    ));
+
+    if let Some(host) = config.mqtt.host.clone() {
+        tokio::spawn(mqtt::start_mqtt_task(
+            host,
+            config.mqtt.clone(),
+            state_rx.clone(),
+            event_tx.clone(),
+            Arc::clone(&event_queue),
+        ));
+    }
+
+    sensors::start_sensors(&config.sensors, &event_tx, &event_queue);
+
+    // Set on shutdown, for tasks that must tidy up before the process exits
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    #[cfg_attr(not(feature = "midi"), allow(unused_mut))]
+    let mut shutdown_tasks: Vec<tokio::task::JoinHandle<()>> = Vec::new();
+    if config.midi.enabled {
+        #[cfg(feature = "midi")]
+        shutdown_tasks.push(tokio::spawn(midi::start_midi_task(
+            config.midi.clone(),
+            state_rx.clone(),
+            broadcast_tx.subscribe(),
+            shutdown_rx.clone(),
+        )));
+        #[cfg(not(feature = "midi"))]
+        warn!("Built without the midi feature, ignoring midi.enabled");
+    }
+    drop(shutdown_rx);
+
+    #[cfg(feature = "stream")]
+    let stream = if !config.stream.enabled {
+        None
+    } else {
+        if _audio_watchdog.is_none() {
+            warn!("Streaming with no audio output running, the stream will be silent");
+        }
+        stream::start_stream(stream_tap, config.stream.bitrate_kbps)
+            .inspect_err(|e| warn!("Failed to start the stream: {}", e))
+            .ok()
+    };
+    #[cfg(not(feature = "stream"))]
+    if config.stream.enabled {
+        warn!("Built without the stream feature, ignoring stream.enabled");
+    }
+
+    let audio_recorder = config.audio.recorder.dir.clone().and_then(|dir| {
+        audio_recorder::start_audio_recorder(dir, &config.audio.recorder, recorder_tap)
+            .inspect_err(|e| warn!("Audio recording disabled: {}", e))
+            .ok()
     });
 
+    if let Some(dir) = config.recording.dir.clone() {
+        tokio::spawn(recorder::start_recorder_task(
+            dir,
+            config.recording.clone(),
+            state_rx.clone(),
+        ));
+    }
+
+    let calibration = Arc::new(Calibrator::new(
+        config.audio.calibration.clone(),
+        config.loudness().target_lufs,
+        Arc::clone(&shared_meter),
+        Arc::clone(&shared_layer_gains),
+        audio_override_tx.clone(),
+    ));
+    let app_state = api::AppState {
+        event_tx,
+        event_queue,
+        world_command_tx: world_command_tx.clone(),
+        current_snapshot,
+        world_state_rx: state_rx,
+        audio_params_rx,
+        tick_hz_rx,
+        tick_hz_tx: tick_hz_tx.clone(),
+        tick_stats_rx,
+        snapshot_hz_rx,
+        broadcast_tx: broadcast_tx.clone(),
+        auth: Auth::new(config.auth.tokens.clone()),
+        meter: shared_meter,
+        waveform: shared_waveform,
+        telemetry: callback_telemetry,
+        layer_amounts_tx,
+        audio_override_tx,
+        mapping_profiles: Arc::new(mapping_profiles),
+        mapping_tx,
+        audio_status,
+        layer_gains: Arc::clone(&shared_layer_gains),
+        effects: shared_effects,
+        layers: layer_registry,
+        patches: Arc::new(patches),
+        alerts_rx,
+        presets: Arc::new(Mutex::new(preset_store)),
+        resumes: Arc::new(std::sync::Mutex::new(ResumeStore::new())),
+        noise_seed,
+        log_control: log_control.clone(),
+        snapshots: snapshot_fanout,
+        audit_tx,
+        audio_recorder,
+        calibration,
+        #[cfg(feature = "stream")]
+        stream,
+    };
+    let shutdown_logs = log_control.clone();
+    match config.api.grpc_port {
+        #[cfg(feature = "grpc")]
+        Some(port) => {
+            info!("gRPC server listening on {}:{}", config.api.bind, port);
+            tokio::spawn(grpc::start_grpc_server(
+                (config.api.bind, port).into(),
+                grpc::WorldService::new(&app_state),
+            ));
+        }
+        #[cfg(not(feature = "grpc"))]
+        Some(_) => warn!("Built without the grpc feature, ignoring api.grpc_port"),
+        None => {}
+    }
+    let app = api::create_router(app_state, &config.api.cors_origins);
+    if config.auth.tokens.is_empty() {
+        warn!("No API tokens configured, API is open to anyone who can reach it");
+    }
+    match &config.api.tls {
+        #[cfg(feature = "tls")]
+        Some(tls_config) => {
+            let rustls = tls::load(tls_config).await?;
+            info!(
+                "HTTPS server listening on https://{}:{}",
+                config.api.bind, tls_config.port
+            );
+            tokio::spawn(tls::start_https_server(
+                (config.api.bind, tls_config.port).into(),
+                rustls,
+                app.clone(),
+            ));
+        }
+        #[cfg(not(feature = "tls"))]
+        Some(_) => warn!("Built without the tls feature, ignoring api.tls"),
+        None => {}
+    }
+    #[cfg(unix)]
+    let inherited = listen::systemd_listeners()?;
+    #[cfg(not(unix))]
+    let inherited: Vec<()> = Vec::new();
+    // Removed on shutdown unless systemd owns it
+    let unix_socket = inherited
+        .is_empty()
+        .then(|| config.api.unix_socket.clone())
+        .flatten();
+    if inherited.is_empty() {
+        let listener = TcpListener::bind((config.api.bind, config.api.port)).await?;
+        info!(
+            "API server listening on http://{}:{}",
+            config.api.bind, config.api.port
+        );
+        match &config.api.unix_socket {
+            #[cfg(unix)]
+            Some(path) => {
+                let unix_listener = listen::bind_unix(path).map_err(|e| {
+                    anyhow::anyhow!("failed to bind api.unix_socket {}: {}", path.display(), e)
+                })?;
+                info!("API server listening on unix:{}", path.display());
+                let app = app.clone();
+                tokio::spawn(async move {
+                    serve(unix_listener, app).await.unwrap();
+                });
+            }
+            #[cfg(not(unix))]
+            Some(_) => warn!("Unix sockets are not supported here, ignoring api.unix_socket"),
+            None => {}
+        }
+        let app = app.clone();
+        tokio::spawn(async move {
+            serve(listener, app).await.unwrap();
+        });
+    }
+    #[cfg(unix)]
+    for listener in inherited {
+        let app = app.clone();
+        match listener {
+            listen::Inherited::Tcp(listener) => {
+                info!(
+                    "API server listening on systemd socket http://{}",
+                    listener.local_addr()?
+                );
+                tokio::spawn(async move {
+                    serve(listener, app).await.unwrap();
+                });
+            }
+            listen::Inherited::Unix(listener) => {
+                let addr = listener.local_addr()?;
+                let path = addr.as_pathname().unwrap_or(std::path::Path::new("?"));
+                info!(
+                    "API server listening on systemd socket unix:{}",
+                    path.display()
+                );
+                tokio::spawn(async move {
+                    serve(listener, app).await.unwrap();
+                });
+            }
+        }
+    }
+
+    // Watch the config file for live-tunable changes
+    if let Some(path) = cli.config_path() {
+        info!("Watching {} for config changes", path.display());
+    }
+    tokio::spawn(start_config_watcher_task(
+        cli,
+        config,
+        LiveSettings {
+            tick_hz_tx,
+            snapshot_hz_tx,
+            world_command_tx,
+            layer_gains: shared_layer_gains,
+            broadcast_tx,
+            log_control,
+        },
+    ));
+
     // Keep the main task alive
     tokio::signal::ctrl_c().await?;
     info!("Shutting down...");
+    shutdown_tx.send_replace(true);
+    for task in shutdown_tasks {
+        let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, task).await;
+    }
+    shutdown_logs.shutdown();
+    if let Some(path) = unix_socket {
+        let _ = std::fs::remove_file(path);
+    }
+    if let Some((capture, path, format)) = capture {
+        match capture.write_wav(&path, format) {
+            Ok(()) => info!(
+                "Wrote {:.1} s of captured audio to {}",
+                capture.len() as f32 / (format.sample_rate as f32 * format.channels as f32),
+                path.display()
+            ),
+            Err(e) => warn!(
+                "Failed to write captured audio to {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,305 @@
+//! MIDI output to external synthesizers.
+//!
+//! Plays the world's harmony and sparkles on a MIDI port, so hardware or
+//! software synths can voice them alongside the built-in engine or in its
+//! place:
+//!
+//! - drone: the key's root, held on `drone_channel` until the key changes
+//! - pad: the current chord's root, third and fifth, held on `pad_channel`
+//!   until the chord changes
+//! - sparkles: one short note each on `sparkle_channel`, picked from the
+//!   mode's scale by the sparkle's pitch, as loud as it is strong
+//!
+//! Held notes are struck at a velocity that follows energy. On shutdown every
+//! note sounding is released and each channel gets an All Notes Off.
+
+use crate::api::ServerMessage;
+use crate::config::MidiConfig;
+use ambient_core::engine::SparkleEvent;
+use ambient_core::harmony::Harmony;
+use ambient_core::world::WorldSnapshot;
+use midir::{MidiOutput, MidiOutputConnection};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{info, warn};
+
+/// How long a sparkle's note sounds.
+const SPARKLE_HOLD: Duration = Duration::from_millis(250);
+
+/// How often due sparkle notes are released.
+const RELEASE_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Scale steps a sparkle's pitch spans: two octaves, both ends included.
+const SPARKLE_STEPS: usize = 14;
+
+/// A three-byte channel message.
+pub type Message = [u8; 3];
+
+fn note_on(channel: u8, note: u8, velocity: u8) -> Message {
+    [0x90 | (channel - 1), note, velocity]
+}
+
+fn note_off(channel: u8, note: u8) -> Message {
+    [0x80 | (channel - 1), note, 0]
+}
+
+/// Control change 123, All Notes Off.
+fn all_notes_off(channel: u8) -> Message {
+    [0xB0 | (channel - 1), 123, 0]
+}
+
+/// MIDI note `semitones` above the C starting `octave`, middle C (60)
+/// starting octave 4.
+fn note(octave: u8, semitones: u16) -> u8 {
+    (12 * (octave as u16 + 1) + semitones).min(127) as u8
+}
+
+/// Velocity of a held note at `energy`.
+fn held_velocity(energy: f64) -> u8 {
+    (40.0 + 60.0 * energy.clamp(0.0, 1.0)).round() as u8
+}
+
+/// The notes sounding and the messages that move them.
+pub struct Player {
+    config: MidiConfig,
+    harmony: Harmony,
+    drone: Option<u8>,
+    pad: Vec<u8>,
+    /// Sparkle notes and when they are released, oldest first.
+    sparkles: VecDeque<(Instant, u8)>,
+}
+
+impl Player {
+    pub fn new(config: MidiConfig) -> Self {
+        Self {
+            config,
+            harmony: Harmony::default(),
+            drone: None,
+            pad: Vec::new(),
+            sparkles: VecDeque::new(),
+        }
+    }
+
+    /// Messages that bring the held notes to `snapshot`'s harmony.
+    pub fn on_snapshot(&mut self, snapshot: &WorldSnapshot) -> Vec<Message> {
+        let harmony = snapshot.harmony();
+        self.harmony = harmony;
+        let velocity = held_velocity(snapshot.energy());
+        let mut messages = Vec::new();
+
+        let drone = note(self.config.drone_octave, (harmony.root % 12).into());
+        if self.drone != Some(drone) {
+            let channel = self.config.drone_channel;
+            messages.extend(self.drone.map(|old| note_off(channel, old)));
+            messages.push(note_on(channel, drone, velocity));
+            self.drone = Some(drone);
+        }
+
+        let root = u16::from(harmony.chord_root());
+        let [third, fifth] = harmony.chord_intervals().map(u16::from);
+        let pad: Vec<u8> = [root, root + third, root + fifth]
+            .into_iter()
+            .map(|semitones| note(self.config.pad_octave, semitones))
+            .collect();
+        if self.pad != pad {
+            let channel = self.config.pad_channel;
+            messages.extend(self.pad.iter().map(|old| note_off(channel, *old)));
+            messages.extend(pad.iter().map(|new| note_on(channel, *new, velocity)));
+            self.pad = pad;
+        }
+        messages
+    }
+
+    /// The note-on for `sparkle`, its note-off due [`SPARKLE_HOLD`] after `now`.
+    pub fn on_sparkle(&mut self, sparkle: &SparkleEvent, now: Instant) -> Vec<Message> {
+        let step = ((sparkle.pitch.clamp(0.0, 1.0) * SPARKLE_STEPS as f64).round() as usize)
+            .min(SPARKLE_STEPS);
+        let semitones = u16::from(self.harmony.root % 12)
+            + u16::from(self.harmony.mode.intervals()[step % 7])
+            + 12 * (step / 7) as u16;
+        let note = note(self.config.sparkle_octave, semitones);
+        let velocity = (127.0 * sparkle.strength.clamp(0.0, 1.0)).round().max(1.0) as u8;
+        self.sparkles.push_back((now + SPARKLE_HOLD, note));
+        vec![note_on(self.config.sparkle_channel, note, velocity)]
+    }
+
+    /// Note-offs for the sparkles due by `now`.
+    pub fn release_due(&mut self, now: Instant) -> Vec<Message> {
+        let mut messages = Vec::new();
+        while let Some((due, note)) = self.sparkles.front().copied()
+            && due <= now
+        {
+            self.sparkles.pop_front();
+            messages.push(note_off(self.config.sparkle_channel, note));
+        }
+        messages
+    }
+
+    /// Note-offs for everything sounding.
+    pub fn release_all(&mut self) -> Vec<Message> {
+        let config = &self.config;
+        let mut messages: Vec<Message> = self
+            .drone
+            .take()
+            .map(|n| note_off(config.drone_channel, n))
+            .into_iter()
+            .collect();
+        messages.extend(self.pad.drain(..).map(|n| note_off(config.pad_channel, n)));
+        messages.extend(
+            self.sparkles
+                .drain(..)
+                .map(|(_, n)| note_off(config.sparkle_channel, n)),
+        );
+        messages
+    }
+
+    /// Note-offs for everything sounding, then All Notes Off on each
+    /// channel in case a synth missed one.
+    pub fn shut_down(&mut self) -> Vec<Message> {
+        let mut messages = self.release_all();
+        let config = &self.config;
+        let mut channels = vec![
+            config.drone_channel,
+            config.pad_channel,
+            config.sparkle_channel,
+        ];
+        channels.sort_unstable();
+        channels.dedup();
+        messages.extend(channels.into_iter().map(all_notes_off));
+        messages
+    }
+}
+
+/// Connects to the first output port whose name contains `port`, or the
+/// first port when unset.
+fn connect(port: Option<&str>) -> Result<MidiOutputConnection, String> {
+    let output = MidiOutput::new("ambient-world").map_err(|e| e.to_string())?;
+    let wanted = port.map(str::to_lowercase);
+    let found = output.ports().into_iter().find_map(|p| {
+        let name = output.port_name(&p).ok()?;
+        match &wanted {
+            Some(wanted) if !name.to_lowercase().contains(wanted) => None,
+            _ => Some((p, name)),
+        }
+    });
+    let Some((found, name)) = found else {
+        return Err(match port {
+            Some(port) => format!("no output port matching '{}'", port),
+            None => "no output ports".to_string(),
+        });
+    };
+    let connection = output
+        .connect(&found, "ambient-world")
+        .map_err(|e| e.to_string())?;
+    info!("MIDI output on '{}'", name);
+    Ok(connection)
+}
+
+/// Starts MIDI output, playing snapshots from `world_rx` and sparkles from
+/// `broadcast_rx` until either closes or `shutdown_rx` is set, then silences
+/// the synths.
+pub async fn start_midi_task(
+    config: MidiConfig,
+    mut world_rx: watch::Receiver<WorldSnapshot>,
+    mut broadcast_rx: broadcast::Receiver<ServerMessage>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    let mut connection = match connect(config.port.as_deref()) {
+        Ok(connection) => connection,
+        Err(e) => {
+            warn!("MIDI output unavailable: {}", e);
+            return;
+        }
+    };
+    let mut send = |messages: Vec<Message>| {
+        for message in messages {
+            if let Err(e) = connection.send(&message) {
+                warn!("MIDI send failed: {}", e);
+            }
+        }
+    };
+    let mut player = Player::new(config);
+    send(player.on_snapshot(&world_rx.borrow_and_update()));
+
+    let mut release = tokio::time::interval(RELEASE_INTERVAL);
+    release.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            changed = world_rx.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                let messages = player.on_snapshot(&world_rx.borrow_and_update());
+                send(messages);
+            }
+            message = broadcast_rx.recv() => match message {
+                Ok(ServerMessage::Sparkle { payload, .. }) => {
+                    send(player.on_sparkle(&payload.sparkle, Instant::now()));
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            _ = release.tick() => send(player.release_due(Instant::now())),
+            _ = shutdown_rx.wait_for(|shutdown| *shutdown) => break,
+        }
+    }
+    send(player.shut_down());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambient_core::world::WorldState;
+
+    fn sparkle(pitch: f64, strength: f64) -> SparkleEvent {
+        SparkleEvent {
+            tick: 0,
+            sim_time_secs: 0.0,
+            strength,
+            pitch,
+            pan: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_harmony_is_held_and_sparkles_are_released() {
+        let snapshot = WorldSnapshot::from_world_state(&WorldState::new());
+        let harmony = snapshot.harmony();
+        let mut player = Player::new(MidiConfig::default());
+
+        let messages = player.on_snapshot(&snapshot);
+        // The drone's note-on, then the pad's three
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0][..2], [0x90, 36 + harmony.root]);
+        let root = 48 + harmony.chord_root();
+        assert_eq!(messages[1][..2], [0x91, root]);
+        assert!(messages[1..].iter().all(|m| m[0] == 0x91 && m[2] > 0));
+        // Nothing more while the harmony holds
+        assert!(player.on_snapshot(&snapshot).is_empty());
+
+        let start = Instant::now();
+        let low = player.on_sparkle(&sparkle(0.0, 0.5), start);
+        assert_eq!(low, vec![[0x92, 72 + harmony.root, 64]]);
+        let high = player.on_sparkle(&sparkle(1.0, 1.0), start);
+        assert_eq!(high, vec![[0x92, 96 + harmony.root, 127]]);
+        assert!(player.release_due(start).is_empty());
+        let released = player.release_due(start + SPARKLE_HOLD);
+        assert_eq!(released, vec![[0x82, low[0][1], 0], [0x82, high[0][1], 0]]);
+
+        // Everything still held is let go
+        assert_eq!(player.release_all().len(), 4);
+        assert!(player.release_all().is_empty());
+
+        // Shutting down also sends All Notes Off on the three channels
+        player.on_snapshot(&snapshot);
+        let messages = player.shut_down();
+        assert_eq!(messages.len(), 7);
+        assert_eq!(
+            messages[4..],
+            [[0xB0, 123, 0], [0xB1, 123, 0], [0xB2, 123, 0]]
+        );
+    }
+}
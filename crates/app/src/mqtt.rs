@@ -0,0 +1,263 @@
+//! MQTT bridge for home-automation systems.
+//!
+//! Publishes the world snapshot, retained, to `<prefix>/state` and applies
+//! commands published under `<prefix>/command/`:
+//!
+//! - `pulse`, `stir`, `calm`, `heat`, `tense`, `expand`, `contract`,
+//!   `clarify`, `roughen`: payload is the intensity (-1 to 1, negative pushes
+//!   the other way), or empty for the API default
+//! - `scene`: payload is the scene name
+//! - `freeze`: payload is the duration in seconds
+//! - `undo`: payload is ignored
+//! - `perform`: payload is a JSON perform action, as in `POST /event`
+
+use crate::api::{SubmitError, default_intensity, queue_event, validate_perform_action};
+use crate::config::MqttConfig;
+use crate::runtime::{EventQueueStats, QueuedEvent};
+use crate::schema::{SchemaVersion, WireSnapshot};
+use ambient_core::events::{Event, EventSource, PerformAction};
+use ambient_core::world::WorldSnapshot;
+use rumqttc::{AsyncClient, Event as MqttEvent, MqttOptions, Packet, QoS};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::time::MissedTickBehavior;
+
+/// Wait before reconnecting after a broker error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Outgoing requests buffered between the client and its event loop.
+const REQUEST_CAPACITY: usize = 16;
+
+/// Parses a command topic suffix and payload into a validated perform action.
+pub fn parse_command(command: &str, payload: &[u8]) -> Result<PerformAction, String> {
+    let payload = std::str::from_utf8(payload)
+        .map_err(|_| "Payload is not UTF-8".to_string())?
+        .trim();
+    let number = |name: &str| {
+        payload
+            .parse::<f64>()
+            .map_err(|_| format!("{} must be a number, got '{}'", name, payload))
+    };
+    let intensity = || {
+        if payload.is_empty() {
+            Ok(default_intensity())
+        } else {
+            number("Intensity")
+        }
+    };
+    let action = match command {
+        "pulse" => PerformAction::Pulse {
+            intensity: intensity()?,
+        },
+        "stir" => PerformAction::Stir {
+            intensity: intensity()?,
+        },
+        "calm" => PerformAction::Calm {
+            intensity: intensity()?,
+        },
+        "heat" => PerformAction::Heat {
+            intensity: intensity()?,
+        },
+        "tense" => PerformAction::Tense {
+            intensity: intensity()?,
+        },
+        "expand" => PerformAction::Expand {
+            intensity: intensity()?,
+        },
+        "contract" => PerformAction::Contract {
+            intensity: intensity()?,
+        },
+        "clarify" => PerformAction::Clarify {
+            intensity: intensity()?,
+        },
+        "roughen" => PerformAction::Roughen {
+            intensity: intensity()?,
+        },
+        "scene" => PerformAction::Scene {
+            name: payload.to_string(),
+        },
+        "freeze" => PerformAction::Freeze {
+            seconds: number("Freeze seconds")?,
+        },
+        "undo" => PerformAction::Undo,
+        "perform" => {
+            serde_json::from_str(payload).map_err(|e| format!("Invalid perform action: {}", e))?
+        }
+        other => return Err(format!("Unknown command '{}'", other)),
+    };
+    validate_perform_action(&action)?;
+    Ok(action)
+}
+
+/// Starts the MQTT bridge to the broker at `host`.
+///
+/// This task:
+/// - Connects to the broker, retrying every few seconds after errors.
+/// - Subscribes to `<prefix>/command/#` on every (re)connect.
+/// - Queues each command as a perform action, sourced from its topic,
+///   without waiting for it to apply, so the broker is never kept waiting
+///   on the world.
+/// - Runs a publisher that sends the snapshot, retained, to `<prefix>/state`
+///   at `publish_hz` whenever the world changed since the last publish.
+pub async fn start_mqtt_task(
+    host: String,
+    config: MqttConfig,
+    world_rx: watch::Receiver<WorldSnapshot>,
+    event_tx: mpsc::Sender<QueuedEvent>,
+    event_queue: Arc<EventQueueStats>,
+) {
+    let mut options = MqttOptions::new(&config.client_id, &host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, REQUEST_CAPACITY);
+
+    let command_prefix = format!("{}/command/", config.topic_prefix);
+    tokio::spawn(publish_state(
+        client.clone(),
+        format!("{}/state", config.topic_prefix),
+        config.publish_hz,
+        world_rx,
+    ));
+
+    loop {
+        match eventloop.poll().await {
+            Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => {
+                tracing::info!("MQTT connected to {}:{}", host, config.port);
+                let filter = format!("{}#", command_prefix);
+                if let Err(e) = client.try_subscribe(&filter, QoS::AtLeastOnce) {
+                    tracing::warn!("MQTT subscribe to {} failed: {}", filter, e);
+                }
+            }
+            Ok(MqttEvent::Incoming(Packet::Publish(publish))) => {
+                let Some(command) = publish.topic.strip_prefix(&command_prefix) else {
+                    continue;
+                };
+                let action = match parse_command(command, &publish.payload) {
+                    Ok(action) => action,
+                    Err(e) => {
+                        tracing::warn!("Ignoring MQTT command on {}: {}", publish.topic, e);
+                        continue;
+                    }
+                };
+                let event =
+                    Event::Perform(action).with_source(EventSource::Mqtt(publish.topic.clone()));
+                let span = tracing::info_span!("event.submit", source = ?event.source);
+                match span.in_scope(|| queue_event(&event_tx, &event_queue, event.into())) {
+                    Ok(()) => tracing::debug!("MQTT {} queued", publish.topic),
+                    Err(SubmitError::Full) => {
+                        tracing::warn!("Event queue full, dropped MQTT {}", publish.topic)
+                    }
+                    Err(SubmitError::Closed) => break,
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::warn!("MQTT connection to {}:{} failed: {}", host, config.port, e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Publishes the latest snapshot as retained JSON whenever it changed.
+async fn publish_state(
+    client: AsyncClient,
+    topic: String,
+    publish_hz: f64,
+    mut world_rx: watch::Receiver<WorldSnapshot>,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / publish_hz));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    world_rx.mark_changed();
+    loop {
+        interval.tick().await;
+        match world_rx.has_changed() {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(_) => break, // World task gone
+        }
+        let snapshot = WireSnapshot::new(&world_rx.borrow_and_update(), SchemaVersion::default());
+        let Ok(payload) = serde_json::to_vec(&snapshot) else {
+            continue;
+        };
+        // Skip rather than queue stale snapshots while the broker is away
+        if let Err(e) = client.try_publish(&topic, QoS::AtLeastOnce, true, payload) {
+            tracing::debug!("MQTT publish to {} skipped: {}", topic, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_intensity_commands() {
+        assert_eq!(
+            parse_command("pulse", b" 0.7 "),
+            Ok(PerformAction::Pulse { intensity: 0.7 })
+        );
+        assert_eq!(
+            parse_command("calm", b""),
+            Ok(PerformAction::Calm {
+                intensity: default_intensity()
+            })
+        );
+        assert_eq!(
+            parse_command("contract", b"-0.2"),
+            Ok(PerformAction::Contract { intensity: -0.2 })
+        );
+        assert_eq!(
+            parse_command("roughen", b"0.4"),
+            Ok(PerformAction::Roughen { intensity: 0.4 })
+        );
+        assert!(parse_command("heat", b"1.5").is_err());
+        assert!(parse_command("stir", b"lots").is_err());
+    }
+
+    #[test]
+    fn test_parse_scene_freeze_and_perform() {
+        assert_eq!(
+            parse_command("scene", b"peaceful"),
+            Ok(PerformAction::Scene {
+                name: "peaceful".to_string()
+            })
+        );
+        assert!(parse_command("scene", b"").is_err());
+        assert_eq!(
+            parse_command("freeze", b"5"),
+            Ok(PerformAction::Freeze { seconds: 5.0 })
+        );
+        assert_eq!(
+            parse_command("perform", br#"{"Tense": {"intensity": 0.3}}"#),
+            Ok(PerformAction::Tense { intensity: 0.3 })
+        );
+        assert_eq!(parse_command("undo", b""), Ok(PerformAction::Undo));
+        assert_eq!(
+            parse_command("perform", br#""Undo""#),
+            Ok(PerformAction::Undo)
+        );
+        assert_eq!(
+            parse_command(
+                "perform",
+                br#"{"PulseAt": {"x": 0.25, "y": 0.75, "intensity": 0.5}}"#
+            ),
+            Ok(PerformAction::PulseAt {
+                x: 0.25,
+                y: 0.75,
+                intensity: 0.5
+            })
+        );
+        assert!(
+            parse_command(
+                "perform",
+                br#"{"PulseAt": {"x": 1.5, "y": 0.5, "intensity": 0.5}}"#
+            )
+            .is_err()
+        );
+        assert!(parse_command("teleport", b"").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! OpenAPI description of the REST API.
+//!
+//! Generated from the `#[utoipa::path]` annotations on the handlers in
+//! [`crate::api`] and the `ToSchema` derives on their request and response
+//! types, so it follows the serde shapes as they change. Served as
+//! `/openapi.json` and browsable with Swagger UI at `/docs`. The WebSocket
+//! protocol has its own schema, in [`crate::ws_schema`].
+
+use crate::api;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// Where the spec is served.
+pub const OPENAPI_PATH: &str = "/openapi.json";
+
+/// Where Swagger UI is served.
+pub const DOCS_PATH: &str = "/docs";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Ambient World",
+        description = "Drive and observe the ambient world simulation and its audio engine. \
+            Endpoints returning snapshots honour `X-Schema-Version`; every response \
+            carries an `X-Request-Id`."
+    ),
+    paths(
+        api::health,
+        api::get_state,
+        api::get_state_stats,
+        api::get_state_diff,
+        api::morph_state,
+        api::get_world_clock,
+        api::pause_world,
+        api::resume_world,
+        api::step_world,
+        api::set_time_scale,
+        api::list_presets,
+        api::capture_preset,
+        api::delete_preset,
+        api::recall_preset,
+        api::get_metrics,
+        api::event,
+        api::get_audio_params,
+        api::set_audio_layers,
+        api::get_audio_effects,
+        api::set_audio_effect,
+        api::get_audio_mapping,
+        api::set_audio_mapping,
+        api::set_audio_override,
+        api::clear_audio_override,
+        api::get_audio_stack,
+        api::add_stack_layer,
+        api::replace_stack_layer,
+        api::remove_stack_layer,
+        api::get_audio_patch,
+        api::play_audio_patch,
+        api::list_audio_patches,
+        api::load_audio_patch,
+        api::get_calibration,
+        api::start_calibration,
+        api::step_calibration,
+        api::cancel_calibration,
+        api::get_audio_status,
+        api::get_audio_waveform,
+        api::save_recording,
+        api::get_log_level,
+        api::set_log_level,
+        api::set_tick_rate,
+    ),
+    modifiers(&BearerAuth),
+    // Open unless `[[auth.tokens]]` are configured
+    security((), ("bearer" = [])),
+    tags(
+        (name = "world", description = "The world's state, clock and run state"),
+        (name = "events", description = "Triggers and perform actions"),
+        (name = "presets", description = "Presets captured from the running world"),
+        (name = "audio", description = "The audio engine: layers, effects, patches and output"),
+        (name = "admin", description = "Health, metrics and runtime settings"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Declares the `Authorization: Bearer <token>` scheme of `auth.tokens`.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+    }
+}
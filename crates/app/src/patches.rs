@@ -0,0 +1,268 @@
+//! Audio patches: the sound of the installation as data.
+//!
+//! A patch names the layers to play and their settings, the effect chain on
+//! each bus and the per-layer gains. Patches are JSON files in
+//! `audio.patches_dir`, one `<name>.json` each, read at startup; `audio.patch`
+//! picks one to start with. Switching to a patch while playing crossfades
+//! only the layers that change, fades the effects through dry when the
+//! chains differ, and ramps the gains over one block.
+
+use crate::config::{EffectsConfig, LayerConfig};
+use crate::presets::is_valid_name;
+use audio::effects::{EffectChains, SharedEffects};
+use audio::mixer::{LayerGains, SharedLayerGains};
+use audio::registry::{LayerRegistry, LayerSpec, RegistryError};
+use audio::sample::Sample;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use utoipa::ToSchema;
+
+/// Per-layer gains of a patch, each 0 to 2. Omitted layers keep the
+/// built-in gain.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct PatchGains {
+    pub drone: f32,
+    pub texture: f32,
+    pub sparkle: f32,
+    pub samples: f32,
+    pub grains: f32,
+    pub wind: f32,
+}
+
+impl Default for PatchGains {
+    fn default() -> Self {
+        LayerGains::default().into()
+    }
+}
+
+impl From<LayerGains> for PatchGains {
+    fn from(gains: LayerGains) -> Self {
+        Self {
+            drone: gains.drone,
+            texture: gains.texture,
+            sparkle: gains.sparkle,
+            samples: gains.samples,
+            grains: gains.grains,
+            wind: gains.wind,
+        }
+    }
+}
+
+impl From<PatchGains> for LayerGains {
+    fn from(gains: PatchGains) -> Self {
+        Self {
+            drone: gains.drone,
+            texture: gains.texture,
+            sparkle: gains.sparkle,
+            samples: gains.samples,
+            grains: gains.grains,
+            wind: gains.wind,
+        }
+    }
+}
+
+/// A patch as stored on disk and exchanged through the API.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AudioPatch {
+    /// Layers in the order they are built, as `POST /audio/stack` takes them.
+    pub layers: Vec<LayerConfig>,
+    /// Omitted buses get the built-in chain.
+    #[serde(default)]
+    pub effects: EffectsConfig,
+    #[serde(default)]
+    pub gains: PatchGains,
+}
+
+/// A patch checked and ready to play.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedPatch {
+    pub stack: Vec<LayerSpec>,
+    pub effects: EffectChains,
+    pub gains: LayerGains,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PatchError {
+    #[error("invalid patch: {0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+}
+
+impl AudioPatch {
+    /// The sound playing now.
+    pub fn capture(registry: &LayerRegistry, effects: &SharedEffects, gains: LayerGains) -> Self {
+        let samples = registry.samples();
+        Self {
+            layers: registry
+                .stack()
+                .iter()
+                .map(|spec| LayerConfig::from_spec(spec, samples))
+                .collect(),
+            effects: EffectsConfig::from(&effects.chains()),
+            gains: gains.into(),
+        }
+    }
+
+    /// Checks the patch, looking grain sources up among `samples`.
+    pub fn resolve(&self, samples: &[Sample]) -> Result<ResolvedPatch, PatchError> {
+        let invalid = PatchError::Invalid;
+        let stack = self
+            .layers
+            .iter()
+            .enumerate()
+            .map(|(i, layer)| {
+                layer
+                    .spec(samples)
+                    .map_err(|e| invalid(format!("layers[{}]: {}", i, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let effects = self
+            .effects
+            .chains()
+            .map_err(|e| invalid(format!("effects.{}", e)))?;
+        let gains = self.gains;
+        for (name, value) in [
+            ("drone", gains.drone),
+            ("texture", gains.texture),
+            ("sparkle", gains.sparkle),
+            ("samples", gains.samples),
+            ("grains", gains.grains),
+            ("wind", gains.wind),
+        ] {
+            if !(0.0..=2.0).contains(&value) {
+                return Err(invalid(format!(
+                    "gains.{} must be in [0, 2], got {}",
+                    name, value
+                )));
+            }
+        }
+        Ok(ResolvedPatch {
+            stack,
+            effects,
+            gains: gains.into(),
+        })
+    }
+
+    /// Checks the patch and switches the playing sound to it.
+    pub fn play(
+        &self,
+        registry: &LayerRegistry,
+        effects: &SharedEffects,
+        gains: &SharedLayerGains,
+    ) -> Result<(), PatchError> {
+        let resolved = self.resolve(registry.samples())?;
+        Ok(resolved.apply(registry, effects, gains)?)
+    }
+}
+
+impl ResolvedPatch {
+    /// Switches the playing sound to this patch. The gains and effects are
+    /// left alone if the layers cannot be switched.
+    fn apply(
+        &self,
+        registry: &LayerRegistry,
+        effects: &SharedEffects,
+        gains: &SharedLayerGains,
+    ) -> Result<(), RegistryError> {
+        let new_effects = (effects.chains() != self.effects).then_some(&self.effects);
+        registry.switch(self.stack.clone(), new_effects)?;
+        if let Some(chains) = new_effects {
+            effects.set_chains(chains.clone());
+        }
+        gains.set(self.gains);
+        Ok(())
+    }
+}
+
+/// Reads every `<name>.json` patch in `dir`. Other files are skipped.
+pub fn load_patches(dir: &Path) -> Result<BTreeMap<String, AudioPatch>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("failed to read {}: {}", dir.display(), e))?;
+    let mut patches = BTreeMap::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("failed to read {}: {}", dir.display(), e))?
+            .path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if !is_valid_name(name) {
+            continue;
+        }
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let patch = serde_json::from_str(&text)
+            .map_err(|e| format!("failed to parse {}: {}", path.display(), e))?;
+        patches.insert(name.to_string(), patch);
+    }
+    Ok(patches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audio::effects::EffectKind;
+    use audio::layers::DroneMode;
+    use audio::noise::NoiseColor;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_patches_load_resolve_and_switch() {
+        let dir = std::env::temp_dir().join(format!("patches-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dusk = r#"{
+            "layers": [
+                { "layer": "drone", "mode": "additive", "partials": 4 },
+                { "layer": "wind", "noise": "brown" }
+            ],
+            "effects": { "drone": ["reverb"], "sparkle": [], "master": ["limiter"] },
+            "gains": { "wind": 0.8 }
+        }"#;
+        std::fs::write(dir.join("dusk.json"), dusk).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a patch").unwrap();
+        let patches = load_patches(&dir).unwrap();
+        assert_eq!(patches.keys().collect::<Vec<_>>(), ["dusk"]);
+
+        let resolved = patches["dusk"].resolve(&[]).unwrap();
+        assert_eq!(
+            resolved.stack,
+            [
+                LayerSpec::Drone(DroneMode::Additive(4)),
+                LayerSpec::Wind(NoiseColor::Brown)
+            ]
+        );
+        assert_eq!(resolved.effects.master, [EffectKind::Limiter]);
+        assert_eq!(resolved.gains.wind, 0.8);
+        assert_eq!(resolved.gains.drone, LayerGains::default().drone);
+
+        let registry = LayerRegistry::new(vec![LayerSpec::Samples], Arc::from(Vec::new()));
+        let effects = SharedEffects::new(EffectChains::default());
+        let gains = SharedLayerGains::new(LayerGains::default());
+        resolved.apply(&registry, &effects, &gains).unwrap();
+        let captured = AudioPatch::capture(&registry, &effects, gains.get());
+        assert_eq!(captured.resolve(&[]).unwrap(), resolved);
+
+        let mut bad = patches["dusk"].clone();
+        bad.gains.drone = 3.0;
+        assert!(matches!(bad.resolve(&[]), Err(PatchError::Invalid(_))));
+        bad.gains.drone = 1.0;
+        bad.layers.push(LayerConfig::Grains {
+            source: Some("rain".to_string()),
+        });
+        assert_eq!(
+            bad.resolve(&[]).unwrap_err().to_string(),
+            "invalid patch: layers[2]: no sample named 'rain'"
+        );
+
+        std::fs::write(dir.join("broken.json"), "{ \"layers\": 3 }").unwrap();
+        assert!(load_patches(&dir).unwrap_err().contains("broken.json"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,201 @@
+//! Named presets captured from the running world.
+//!
+//! Scenes are authored ahead of time; presets are captured live through
+//! `POST /presets/{name}` and recalled later, optionally with a ramp. They are
+//! saved as JSON to `presets_path` so they survive restarts. The live world
+//! can also be compared against a preset or scene with `GET /state/diff`, and
+//! moved part of the way toward one with `POST /state/morph`.
+
+use ambient_core::world::WorldPreset;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use utoipa::ToSchema;
+
+/// Longest preset name accepted.
+const MAX_NAME_LEN: usize = 64;
+
+/// A preset as stored on disk and returned by the API.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct StoredPreset {
+    pub captured_at_ms: u64,
+    #[serde(flatten)]
+    pub preset: WorldPreset,
+}
+
+/// One parameter or target of a [`PresetDiff`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct FieldDiff {
+    pub current: f64,
+    pub reference: f64,
+    /// `reference - current`: how far the live world would have to move.
+    pub delta: f64,
+}
+
+/// The live world against a preset or scene, as returned by `GET /state/diff`.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct PresetDiff {
+    /// Every parameter and target, keyed by name.
+    pub fields: BTreeMap<&'static str, FieldDiff>,
+    /// Root mean square of the five parameter deltas, 0 to 1.
+    pub distance: f64,
+}
+
+impl PresetDiff {
+    pub fn new(current: &WorldPreset, reference: &WorldPreset) -> Self {
+        let fields: BTreeMap<_, _> = current
+            .fields()
+            .into_iter()
+            .zip(reference.fields())
+            .map(|((name, current), (_, reference))| {
+                let delta = reference - current;
+                (
+                    name,
+                    FieldDiff {
+                        current,
+                        reference,
+                        delta,
+                    },
+                )
+            })
+            .collect();
+        let parameters = &current.fields()[..5];
+        let squares: f64 = parameters
+            .iter()
+            .map(|(name, _)| fields[name].delta.powi(2))
+            .sum();
+        Self {
+            fields,
+            distance: (squares / parameters.len() as f64).sqrt(),
+        }
+    }
+}
+
+/// Presets keyed by name, written back to disk on every change.
+#[derive(Debug, Default)]
+pub struct PresetStore {
+    /// File the presets are saved to. In-memory only when unset.
+    path: Option<PathBuf>,
+    presets: BTreeMap<String, StoredPreset>,
+}
+
+/// Returns true for non-empty names of at most 64 ASCII letters, digits,
+/// `-` and `_`, so names are safe in URLs and file formats.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+impl PresetStore {
+    /// Loads the presets file, starting empty if it does not exist yet.
+    pub fn load(path: Option<PathBuf>) -> Result<Self, String> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let presets = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| format!("failed to parse {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("failed to read {}: {}", path.display(), e)),
+        };
+        Ok(Self {
+            path: Some(path),
+            presets,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StoredPreset> {
+        self.presets.get(name)
+    }
+
+    pub fn presets(&self) -> &BTreeMap<String, StoredPreset> {
+        &self.presets
+    }
+
+    /// Adds or replaces a preset and saves the store.
+    pub async fn insert(&mut self, name: String, preset: StoredPreset) -> std::io::Result<()> {
+        self.presets.insert(name, preset);
+        self.save().await
+    }
+
+    /// Removes a preset and saves the store. Returns false if it did not exist.
+    pub async fn remove(&mut self, name: &str) -> std::io::Result<bool> {
+        if self.presets.remove(name).is_none() {
+            return Ok(false);
+        }
+        self.save().await.map(|()| true)
+    }
+
+    /// Writes the presets to a temporary file and renames it over the old
+    /// one, so a crash mid-write never leaves a truncated file.
+    async fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec_pretty(&self.presets)?;
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambient_core::world::WorldState;
+
+    #[test]
+    fn test_preset_names() {
+        assert!(is_valid_name("dusk_2"));
+        assert!(is_valid_name("late-night"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("../etc"));
+        assert!(!is_valid_name("with space"));
+        assert!(!is_valid_name(&"a".repeat(MAX_NAME_LEN + 1)));
+    }
+
+    #[test]
+    fn test_diff_against_a_preset() {
+        let mut state = WorldState::new();
+        let current = state.preset();
+        state.set_energy(0.9);
+        state.set_target_warmth(0.2);
+        let diff = PresetDiff::new(&current, &state.preset());
+        let energy = diff.fields["energy"];
+        assert_eq!((energy.current, energy.reference), (0.5, 0.9));
+        assert!((energy.delta - 0.4).abs() < 1e-12);
+        assert!((diff.fields["target_warmth"].delta + 0.3).abs() < 1e-12);
+        assert_eq!(diff.fields["density"].delta, 0.0);
+        // Only the parameters count toward the distance
+        assert!((diff.distance - (0.16f64 / 5.0).sqrt()).abs() < 1e-12);
+    }
+
+    #[tokio::test]
+    async fn test_store_round_trips_through_disk() {
+        let path = std::env::temp_dir().join(format!("presets-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut store = PresetStore::load(Some(path.clone())).unwrap();
+        assert!(store.presets().is_empty());
+        let preset = StoredPreset {
+            captured_at_ms: 42,
+            preset: WorldState::new().preset(),
+        };
+        store.insert("dusk".to_string(), preset).await.unwrap();
+
+        let mut reloaded = PresetStore::load(Some(path.clone())).unwrap();
+        assert_eq!(reloaded.get("dusk"), Some(&preset));
+        assert!(reloaded.remove("dusk").await.unwrap());
+        assert!(!reloaded.remove("dusk").await.unwrap());
+        assert!(
+            PresetStore::load(Some(path.clone()))
+                .unwrap()
+                .presets()
+                .is_empty()
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,441 @@
+//! WebSocket protocol versioning and capability negotiation.
+
+use crate::auth::Role;
+use ambient_core::field::Field;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Protocol version the server speaks by default.
+pub const PROTOCOL_VERSION: &str = "2.0";
+
+/// Schema versions a client may request in its hello. See `schema.rs` for
+/// how snapshots differ between them.
+pub const SUPPORTED_VERSIONS: &[&str] = &["1.0", "2.0"];
+
+/// Optional features a client can opt into during negotiation.
+pub const CAPABILITIES: &[&str] = &[
+    "config_reloaded",
+    "alerts",
+    "sparkles",
+    "beats",
+    "mood",
+    "field",
+];
+
+/// Snapshot encodings a client may request in its hello.
+pub const ENCODINGS: &[&str] = &["json", "msgpack", "cbor"];
+
+/// Message types the server may send.
+pub const SERVER_MESSAGE_TYPES: &[&str] = &[
+    "hello",
+    "negotiated",
+    "snapshot",
+    "event_ack",
+    "error",
+    "config_reloaded",
+    "alert",
+    "sparkle",
+    "beat",
+    "mood_changed",
+    "subscribed",
+    "resumed",
+    "calibration",
+];
+
+/// Message types the server accepts from clients.
+pub const CLIENT_MESSAGE_TYPES: &[&str] = &[
+    "hello",
+    "perform",
+    "ping",
+    "set_scene",
+    "subscribe",
+    "calibrate",
+];
+
+/// Machine-readable error codes sent in `error` messages, and as the `code` of
+/// REST error bodies (see [`crate::error::ApiError`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    InvalidMessage,
+    ValidationError,
+    SendFailed,
+    /// The event queue is full; retry shortly.
+    Busy,
+    VersionMismatch,
+    Unauthorized,
+    Forbidden,
+    /// The resume token is unknown or expired; the connection is a new session.
+    ResumeFailed,
+    /// No such preset, scene, layer or patch.
+    NotFound,
+    /// Conflicts with the current state, such as a full layer stack.
+    Conflict,
+    /// The server failed at something other than reaching the world.
+    Internal,
+}
+
+/// How snapshot messages are serialized on a session.
+///
+/// Control messages (hello, acks, errors, broadcasts) are always JSON text
+/// frames; a binary encoding only changes the high-rate snapshot stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    /// Text frames.
+    #[default]
+    Json,
+    /// MessagePack binary frames, with field names kept.
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    /// CBOR binary frames.
+    Cbor,
+}
+
+impl Encoding {
+    pub fn parse(encoding: &str) -> Option<Self> {
+        match encoding {
+            "json" => Some(Encoding::Json),
+            "msgpack" => Some(Encoding::MessagePack),
+            "cbor" => Some(Encoding::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Serializes `value`. JSON output is UTF-8, for a text frame.
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Encoding::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Encoding::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+/// Streams a session can subscribe to. The first three are parts of the
+/// snapshot payload; `events` is the server-wide broadcasts (alerts and
+/// config reloads), `sparkles` and `beats` the world's sparkle and beat
+/// messages, and `field` the spatial grids as binary frames (see
+/// [`encode_field_frame`]), which only sessions that name it get. Acks and
+/// errors always go to the session that asked.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    World,
+    Audio,
+    Analysis,
+    Events,
+    Sparkles,
+    Beats,
+    Field,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 7] = [
+        Channel::World,
+        Channel::Audio,
+        Channel::Analysis,
+        Channel::Events,
+        Channel::Sparkles,
+        Channel::Beats,
+        Channel::Field,
+    ];
+
+    /// What a session streams before it subscribes, or when it subscribes
+    /// without naming channels: all but `field`, whose binary frames a
+    /// client has to ask for.
+    pub fn defaults() -> Vec<Channel> {
+        Self::ALL
+            .into_iter()
+            .filter(|channel| *channel != Channel::Field)
+            .collect()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::World => "world",
+            Channel::Audio => "audio",
+            Channel::Analysis => "analysis",
+            Channel::Events => "events",
+            Channel::Sparkles => "sparkles",
+            Channel::Beats => "beats",
+            Channel::Field => "field",
+        }
+    }
+}
+
+/// What a session streams: the default channels in full until it
+/// subscribes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, ToSchema)]
+pub struct Subscription {
+    pub channels: Vec<Channel>,
+    /// Fields kept per snapshot channel; a channel without an entry sends
+    /// all of its fields.
+    pub fields: BTreeMap<Channel, Vec<String>>,
+}
+
+impl Default for Subscription {
+    fn default() -> Self {
+        Self {
+            channels: Channel::defaults(),
+            fields: BTreeMap::new(),
+        }
+    }
+}
+
+impl Subscription {
+    /// Checks a subscribe request against the fields each snapshot channel
+    /// has (`known_fields`).
+    pub fn new(
+        mut channels: Vec<Channel>,
+        fields: BTreeMap<Channel, Vec<String>>,
+        known_fields: impl Fn(Channel) -> Vec<String>,
+    ) -> Result<Self, String> {
+        channels.sort();
+        channels.dedup();
+        for (channel, names) in &fields {
+            if matches!(
+                channel,
+                Channel::Events | Channel::Sparkles | Channel::Beats | Channel::Field
+            ) {
+                return Err(format!(
+                    "The {} channel has no fields to select",
+                    channel.name()
+                ));
+            }
+            if !channels.contains(channel) {
+                return Err(format!(
+                    "Fields given for {}, which is not subscribed",
+                    channel.name()
+                ));
+            }
+            let known = known_fields(*channel);
+            if let Some(unknown) = names.iter().find(|name| !known.contains(name)) {
+                return Err(format!(
+                    "Unknown {} field '{}', expected one of: {}",
+                    channel.name(),
+                    unknown,
+                    known.join(", ")
+                ));
+            }
+        }
+        Ok(Self { channels, fields })
+    }
+
+    pub fn includes(&self, channel: Channel) -> bool {
+        self.channels.contains(&channel)
+    }
+
+    /// Whether snapshot parts need trimming to selected fields.
+    pub fn selects_fields(&self) -> bool {
+        !self.fields.is_empty()
+    }
+
+    /// Trims a serialized snapshot payload, keyed by channel name, down to
+    /// the selected fields.
+    pub fn select_fields(&self, payload: &mut Value) {
+        for (channel, names) in &self.fields {
+            if let Some(Value::Object(part)) = payload.get_mut(channel.name()) {
+                part.retain(|key, _| names.contains(key));
+            }
+        }
+    }
+}
+
+/// First bytes of every `field` frame.
+pub const FIELD_FRAME_MAGIC: &[u8; 4] = b"AMBF";
+
+/// Bytes before a `field` frame's cells.
+pub const FIELD_FRAME_HEADER_LEN: usize = 16;
+
+/// Packs the grids into a binary `field` frame: the magic, width and height
+/// as little-endian u16s, the tick as a little-endian u64, then one byte
+/// each of density, energy and warmth (0 to 255) per cell, row by row from
+/// the top left, so the cells upload as an RGB8 texture as they are.
+pub fn encode_field_frame(tick: u64, field: &Field) -> Vec<u8> {
+    let cells = field.width() * field.height();
+    let mut frame = Vec::with_capacity(FIELD_FRAME_HEADER_LEN + 3 * cells);
+    frame.extend_from_slice(FIELD_FRAME_MAGIC);
+    frame.extend_from_slice(&(field.width() as u16).to_le_bytes());
+    frame.extend_from_slice(&(field.height() as u16).to_le_bytes());
+    frame.extend_from_slice(&tick.to_le_bytes());
+    let byte = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    for index in 0..cells {
+        frame.push(byte(field.density()[index]));
+        frame.push(byte(field.energy()[index]));
+        frame.push(byte(field.warmth()[index]));
+    }
+    frame
+}
+
+/// Result of a successful negotiation.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Negotiated {
+    pub schema_version: String,
+    /// Snapshot encoding in effect; JSON when the requested one is unknown.
+    pub encoding: Encoding,
+    /// Requested features the server supports and enabled.
+    pub features: Vec<String>,
+    /// Requested features the server does not know.
+    pub unsupported_features: Vec<String>,
+    /// Role granted to this session, if authenticated.
+    pub role: Option<Role>,
+}
+
+/// Returns true if the server can speak the given schema version.
+pub fn is_supported_version(version: &str) -> bool {
+    SUPPORTED_VERSIONS.contains(&version)
+}
+
+/// Negotiates a schema version, feature set and snapshot encoding from a client hello.
+pub fn negotiate(
+    schema_version: &str,
+    features: &[String],
+    encoding: Option<&str>,
+) -> Result<Negotiated, String> {
+    if !is_supported_version(schema_version) {
+        return Err(format!(
+            "Unsupported schema version {}, supported: {}",
+            schema_version,
+            SUPPORTED_VERSIONS.join(", ")
+        ));
+    }
+    let (features, unsupported_features) = features
+        .iter()
+        .cloned()
+        .partition(|f| CAPABILITIES.contains(&f.as_str()));
+    Ok(Negotiated {
+        schema_version: schema_version.to_string(),
+        encoding: encoding.and_then(Encoding::parse).unwrap_or_default(),
+        features,
+        unsupported_features,
+        role: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambient_core::world::WorldState;
+
+    #[test]
+    fn test_negotiate_splits_features() {
+        let negotiated = negotiate(
+            "1.0",
+            &["config_reloaded".to_string(), "teleport".to_string()],
+            None,
+        )
+        .unwrap();
+        assert_eq!(negotiated.features, vec!["config_reloaded"]);
+        assert_eq!(negotiated.unsupported_features, vec!["teleport"]);
+    }
+
+    #[test]
+    fn test_negotiate_rejects_unknown_version() {
+        assert!(negotiate("9.9", &[], None).is_err());
+    }
+
+    #[test]
+    fn test_negotiate_encoding_falls_back_to_json() {
+        let negotiated = negotiate("2.0", &[], Some("cbor")).unwrap();
+        assert_eq!(negotiated.encoding, Encoding::Cbor);
+        let negotiated = negotiate("2.0", &[], Some("protobuf")).unwrap();
+        assert_eq!(negotiated.encoding, Encoding::Json);
+        for name in ENCODINGS {
+            assert!(Encoding::parse(name).is_some());
+        }
+    }
+
+    #[test]
+    fn test_binary_encodings_round_trip() {
+        let message = serde_json::json!({
+            "type": "snapshot",
+            "version": "2.0",
+            "payload": {"world": {"tick": 7, "density": 0.25, "scene": null}},
+        });
+        let json = Encoding::Json.encode(&message).unwrap();
+        let msgpack = Encoding::MessagePack.encode(&message).unwrap();
+        let cbor = Encoding::Cbor.encode(&message).unwrap();
+        assert!(msgpack.len() < json.len() && cbor.len() < json.len());
+
+        let from_msgpack: serde_json::Value = rmp_serde::from_slice(&msgpack).unwrap();
+        let from_cbor: serde_json::Value = ciborium::from_reader(cbor.as_slice()).unwrap();
+        assert_eq!(from_msgpack, message);
+        assert_eq!(from_cbor, message);
+    }
+
+    #[test]
+    fn test_subscription_checks_and_selects_fields() {
+        let known = |channel: Channel| match channel {
+            Channel::World => vec![
+                "tick".to_string(),
+                "warmth".to_string(),
+                "energy".to_string(),
+            ],
+            _ => vec!["peak_db".to_string()],
+        };
+        let world_fields = BTreeMap::from([(
+            Channel::World,
+            vec!["warmth".to_string(), "energy".to_string()],
+        )]);
+        let subscription = Subscription::new(
+            vec![Channel::World, Channel::World],
+            world_fields.clone(),
+            known,
+        )
+        .unwrap();
+        assert_eq!(subscription.channels, vec![Channel::World]);
+        assert!(!subscription.includes(Channel::Audio));
+
+        let mut payload = serde_json::json!({
+            "world": {"tick": 3, "warmth": 0.4, "energy": 0.6},
+        });
+        subscription.select_fields(&mut payload);
+        assert_eq!(
+            payload,
+            serde_json::json!({"world": {"warmth": 0.4, "energy": 0.6}})
+        );
+
+        assert!(Subscription::new(vec![Channel::Audio], world_fields, known).is_err());
+        let typo = BTreeMap::from([(Channel::World, vec!["warmht".to_string()])]);
+        let error = Subscription::new(vec![Channel::World], typo, known).unwrap_err();
+        assert!(error.contains("'warmht'"), "{}", error);
+        let events = BTreeMap::from([(Channel::Events, vec![])]);
+        assert!(Subscription::new(vec![Channel::Events], events, known).is_err());
+        let sparkles = BTreeMap::from([(Channel::Sparkles, vec![])]);
+        assert!(Subscription::new(vec![Channel::Sparkles], sparkles, known).is_err());
+        let beats = BTreeMap::from([(Channel::Beats, vec![])]);
+        assert!(Subscription::new(vec![Channel::Beats], beats, known).is_err());
+        assert!(!Subscription::default().includes(Channel::Field));
+    }
+
+    #[test]
+    fn test_field_frame_layout() {
+        let mut state = WorldState::new();
+        state.set_density(1.0);
+        state.set_energy(0.0);
+        let field = Field::uniform(3, 2, &state);
+        let frame = encode_field_frame(0x0102, &field);
+        assert_eq!(frame.len(), FIELD_FRAME_HEADER_LEN + 3 * 6);
+        assert_eq!(&frame[..4], FIELD_FRAME_MAGIC);
+        assert_eq!(&frame[4..8], &[3, 0, 2, 0]);
+        assert_eq!(u64::from_le_bytes(frame[8..16].try_into().unwrap()), 0x0102);
+        assert_eq!(&frame[16..19], &[255, 0, 128]);
+    }
+
+    #[test]
+    fn test_error_code_wire_format() {
+        assert_eq!(
+            serde_json::to_string(&ErrorCode::VersionMismatch).unwrap(),
+            "\"VERSION_MISMATCH\""
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Trajectory recorder: samples the world snapshot into CSV files.
+//!
+//! One row per sample, one file per UTC day (`world-YYYY-MM-DD.csv`), so a
+//! week of operation can be loaded into a spreadsheet or dataframe and lined
+//! up against the audit log by `timestamp_ms`.
+
+use crate::config::RecordingConfig;
+use crate::runtime::unix_time_ms;
+use ambient_core::world::{RunState, WorldSnapshot};
+use std::path::{Path, PathBuf};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use tokio::time::{Duration, MissedTickBehavior};
+use tracing::{info, warn};
+
+/// Column names, in row order.
+pub const CSV_HEADER: &str = "timestamp_ms,tick,sim_time_secs,run_state,time_scale,density,rhythm,tension,energy,warmth,space,clarity,sparkle_impulse,scene,scene_sequence";
+
+/// File name for the day containing `timestamp_ms`.
+pub fn file_name(timestamp_ms: u64) -> String {
+    let date = time::OffsetDateTime::from_unix_timestamp((timestamp_ms / 1000) as i64)
+        .map(|t| t.date().to_string())
+        .unwrap_or_else(|_| "unknown".to_string());
+    format!("world-{}.csv", date)
+}
+
+/// Formats a snapshot as one CSV line, without the trailing newline.
+pub fn csv_row(snapshot: &WorldSnapshot) -> String {
+    let run_state = match snapshot.run_state() {
+        RunState::Running => "running",
+        RunState::Paused => "paused",
+    };
+    let (scene, sequence) = match snapshot.scene() {
+        Some(scene) => (csv_field(&scene.name), scene.sequence.to_string()),
+        None => (String::new(), String::new()),
+    };
+    format!(
+        "{},{},{:.3},{},{},{:.6},{:.6},{:.6},{:.6},{:.6},{:.6},{:.6},{:.6},{},{}",
+        snapshot.timestamp_ms(),
+        snapshot.tick(),
+        snapshot.sim_time_secs(),
+        run_state,
+        snapshot.time_scale(),
+        snapshot.density(),
+        snapshot.rhythm(),
+        snapshot.tension(),
+        snapshot.energy(),
+        snapshot.warmth(),
+        snapshot.space(),
+        snapshot.clarity(),
+        snapshot.sparkle_impulse(),
+        scene,
+        sequence
+    )
+}
+
+/// Quotes a field if it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Opens (or continues) a day file, writing the header if it is new.
+async fn open_day_file(path: &Path) -> std::io::Result<File> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    if file.metadata().await?.len() == 0 {
+        file.write_all(format!("{}\n", CSV_HEADER).as_bytes())
+            .await?;
+    }
+    Ok(file)
+}
+
+/// Deletes the oldest day files beyond the newest `keep`.
+async fn prune_day_files(dir: &Path, keep: usize) -> std::io::Result<()> {
+    let mut files = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with("world-") && name.ends_with(".csv") {
+            files.push(name);
+        }
+    }
+    // ISO dates sort chronologically
+    files.sort();
+    let excess = files.len().saturating_sub(keep);
+    for name in &files[..excess] {
+        info!("Removing old recording {}", name);
+        tokio::fs::remove_file(dir.join(name)).await?;
+    }
+    Ok(())
+}
+
+/// Starts the recorder writing into `dir`.
+///
+/// This task:
+/// - Samples the latest snapshot at `rate_hz`, paused or not, stamping each
+///   row with the time it was sampled.
+/// - Appends each sample as a CSV row to the file for its UTC day, starting
+///   a new file (with a header) when the day changes.
+/// - Keeps only the newest `keep_files` day files, if set.
+/// - Stops recording if the directory cannot be created or written.
+pub async fn start_recorder_task(
+    dir: PathBuf,
+    config: RecordingConfig,
+    world_rx: watch::Receiver<WorldSnapshot>,
+) {
+    if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+        warn!(
+            "Recording disabled, failed to create {}: {}",
+            dir.display(),
+            e
+        );
+        return;
+    }
+    info!(
+        "Recording world trajectory to {} at {} Hz",
+        dir.display(),
+        config.rate_hz
+    );
+
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / config.rate_hz));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut current: Option<(String, File)> = None;
+    loop {
+        interval.tick().await;
+        if world_rx.has_changed().is_err() {
+            break; // World task gone
+        }
+        // Stamp with the sample time; snapshots go stale while paused
+        let snapshot = world_rx.borrow().clone().with_timestamp_ms(unix_time_ms());
+        let name = file_name(snapshot.timestamp_ms());
+
+        if current.as_ref().is_none_or(|(open, _)| *open != name) {
+            let path = dir.join(&name);
+            match open_day_file(&path).await {
+                Ok(file) => current = Some((name, file)),
+                Err(e) => {
+                    warn!(
+                        "Recording stopped, failed to open {}: {}",
+                        path.display(),
+                        e
+                    );
+                    return;
+                }
+            }
+            if config.keep_files > 0
+                && let Err(e) = prune_day_files(&dir, config.keep_files).await
+            {
+                warn!("Failed to prune old recordings: {}", e);
+            }
+        }
+        let Some((name, file)) = &mut current else {
+            continue;
+        };
+        let line = format!("{}\n", csv_row(&snapshot));
+        if let Err(e) = file.write_all(line.as_bytes()).await {
+            warn!("Failed to write recording {}: {}", name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambient_core::engine::WorldEngine;
+    use ambient_core::events::{Event, PerformAction};
+
+    #[test]
+    fn test_file_name_is_utc_day() {
+        assert_eq!(file_name(0), "world-1970-01-01.csv");
+        // 2026-10-16T23:59:59Z and one second later
+        assert_eq!(file_name(1_792_195_199_000), "world-2026-10-16.csv");
+        assert_eq!(file_name(1_792_195_200_000), "world-2026-10-17.csv");
+    }
+
+    #[test]
+    fn test_csv_row_matches_header() {
+        let mut engine = WorldEngine::new();
+        let snapshot = engine.get_snapshot().with_timestamp_ms(1234);
+        let row = csv_row(&snapshot);
+        assert_eq!(row.split(',').count(), CSV_HEADER.split(',').count());
+        assert!(row.starts_with("1234,0,0.000,running,1,0.500000,"));
+        assert!(row.ends_with(",,"));
+
+        let result = engine.apply(Event::Perform(PerformAction::Scene {
+            name: "peaceful".to_string(),
+        }));
+        assert!(csv_row(&result.resulting_snapshot).ends_with(",peaceful,1"));
+    }
+
+    #[test]
+    fn test_csv_field_quoting() {
+        assert_eq!(csv_field("dusk"), "dusk");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,376 @@
+//! Config file watcher that applies live-tunable settings without a restart.
+
+use crate::api::{ConfigReloadedPayload, ServerMessage};
+use crate::config::{Cli, Config};
+use crate::logging::{LogControl, LogSettings};
+use crate::protocol::PROTOCOL_VERSION;
+use crate::runtime::WorldCommand;
+use audio::mixer::SharedLayerGains;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::time::{Duration, interval};
+use tracing::{info, warn};
+
+/// How often the config file's modification time is checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Handles into the running tasks that accept live setting changes.
+pub struct LiveSettings {
+    pub tick_hz_tx: watch::Sender<f64>,
+    pub snapshot_hz_tx: watch::Sender<f64>,
+    pub world_command_tx: mpsc::Sender<WorldCommand>,
+    pub layer_gains: Arc<SharedLayerGains>,
+    pub broadcast_tx: broadcast::Sender<ServerMessage>,
+    pub log_control: LogControl,
+}
+
+/// Changed config keys, split by whether they can be applied live.
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub applied: Vec<String>,
+    pub requires_restart: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub fn between(old: &Config, new: &Config) -> Self {
+        let mut diff = Self::default();
+        let mut check = |changed: bool, key: &str, live: bool| {
+            if changed {
+                let list = if live {
+                    &mut diff.applied
+                } else {
+                    &mut diff.requires_restart
+                };
+                list.push(key.to_string());
+            }
+        };
+        check(
+            old.world.tick_hz != new.world.tick_hz,
+            "world.tick_hz",
+            true,
+        );
+        check(
+            old.world.step_hz != new.world.step_hz,
+            "world.step_hz",
+            false,
+        );
+        check(
+            old.world.time_scale != new.world.time_scale,
+            "world.time_scale",
+            true,
+        );
+        check(
+            old.world.coalesce_ticks != new.world.coalesce_ticks,
+            "world.coalesce_ticks",
+            false,
+        );
+        check(
+            old.world.drift_factor != new.world.drift_factor,
+            "world.drift_factor",
+            true,
+        );
+        check(
+            old.world.decay_factor != new.world.decay_factor,
+            "world.decay_factor",
+            true,
+        );
+        check(old.world.space != new.world.space, "world.space", true);
+        check(
+            old.world.clarity != new.world.clarity,
+            "world.clarity",
+            true,
+        );
+        check(
+            old.world.sparkles != new.world.sparkles,
+            "world.sparkles",
+            true,
+        );
+        check(
+            old.world.pulse.quantize_sparkles != new.world.pulse.quantize_sparkles
+                || old.world.pulse.subdivision != new.world.pulse.subdivision,
+            "world.pulse",
+            true,
+        );
+        check(old.world.agents != new.world.agents, "world.agents", true);
+        check(old.world.field != new.world.field, "world.field", true);
+        check(
+            old.world.noise_seed != new.world.noise_seed,
+            "world.noise_seed",
+            false,
+        );
+        check(
+            old.world.pulse.quantize_delay != new.world.pulse.quantize_delay,
+            "world.pulse.quantize_delay",
+            false,
+        );
+        check(
+            old.api.snapshot_hz != new.api.snapshot_hz,
+            "api.snapshot_hz",
+            true,
+        );
+        check(
+            old.audio.drone_gain != new.audio.drone_gain,
+            "audio.drone_gain",
+            true,
+        );
+        check(
+            old.audio.texture_gain != new.audio.texture_gain,
+            "audio.texture_gain",
+            true,
+        );
+        check(
+            old.audio.sparkle_gain != new.audio.sparkle_gain,
+            "audio.sparkle_gain",
+            true,
+        );
+        check(
+            old.audio.samples_gain != new.audio.samples_gain,
+            "audio.samples_gain",
+            true,
+        );
+        check(old.api.port != new.api.port, "api.port", false);
+        check(old.api.bind != new.api.bind, "api.bind", false);
+        check(
+            old.api.cors_origins != new.api.cors_origins,
+            "api.cors_origins",
+            false,
+        );
+        check(old.api.tls != new.api.tls, "api.tls", false);
+        check(
+            old.api.unix_socket != new.api.unix_socket,
+            "api.unix_socket",
+            false,
+        );
+        check(
+            old.api.grpc_port != new.api.grpc_port,
+            "api.grpc_port",
+            false,
+        );
+        check(
+            old.audio.enabled != new.audio.enabled,
+            "audio.enabled",
+            false,
+        );
+        check(
+            old.logging.level != new.logging.level,
+            "logging.level",
+            true,
+        );
+        check(
+            old.logging.targets != new.logging.targets,
+            "logging.targets",
+            true,
+        );
+        check(
+            old.logging.format != new.logging.format,
+            "logging.format",
+            false,
+        );
+        check(
+            old.logging.otlp_endpoint != new.logging.otlp_endpoint
+                || old.logging.service_name != new.logging.service_name,
+            "logging.otlp_endpoint",
+            false,
+        );
+        check(old.mqtt != new.mqtt, "mqtt", false);
+        check(old.recording != new.recording, "recording", false);
+        check(old.midi != new.midi, "midi", false);
+        check(old.stream != new.stream, "stream", false);
+        check(old.alerts != new.alerts, "alerts", false);
+        check(old.sensors != new.sensors, "sensors", false);
+        check(old.room != new.room, "room", false);
+        check(old.composer != new.composer, "composer", false);
+        check(old.seasons != new.seasons, "seasons", false);
+        check(
+            old.audio.grains_gain != new.audio.grains_gain,
+            "audio.grains_gain",
+            true,
+        );
+        check(
+            old.audio.wind_gain != new.audio.wind_gain,
+            "audio.wind_gain",
+            true,
+        );
+        check(
+            old.audio.grain_source != new.audio.grain_source,
+            "audio.grain_source",
+            false,
+        );
+        check(
+            old.audio.drone_mode != new.audio.drone_mode
+                || old.audio.drone_partials != new.audio.drone_partials,
+            "audio.drone_mode",
+            false,
+        );
+        check(
+            old.audio.ducking != new.audio.ducking,
+            "audio.ducking",
+            false,
+        );
+        check(old.audio.gate != new.audio.gate, "audio.gate", false);
+        check(
+            old.audio.smoothing != new.audio.smoothing,
+            "audio.smoothing",
+            false,
+        );
+        check(
+            old.audio.effects != new.audio.effects,
+            "audio.effects",
+            false,
+        );
+        check(old.audio.noise != new.audio.noise, "audio.noise", false);
+        check(
+            old.audio.mapping != new.audio.mapping,
+            "audio.mapping",
+            false,
+        );
+        check(
+            old.audio.loudness != new.audio.loudness,
+            "audio.loudness",
+            false,
+        );
+        check(old.audio.output != new.audio.output, "audio.output", false);
+        check(
+            old.audio.recorder != new.audio.recorder,
+            "audio.recorder",
+            false,
+        );
+        check(
+            old.audio.calibration != new.audio.calibration,
+            "audio.calibration",
+            false,
+        );
+        check(
+            old.audio.spatial != new.audio.spatial,
+            "audio.spatial",
+            false,
+        );
+        check(
+            old.audio.samples_dir != new.audio.samples_dir,
+            "audio.samples_dir",
+            false,
+        );
+        check(
+            old.audio.patches_dir != new.audio.patches_dir || old.audio.patch != new.audio.patch,
+            "audio.patch",
+            false,
+        );
+        check(old.scenes_path != new.scenes_path, "scenes_path", false);
+        check(old.presets_path != new.presets_path, "presets_path", false);
+        check(old.audit_log != new.audit_log, "audit_log", false);
+        check(old.auth != new.auth, "auth.tokens", false);
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.requires_restart.is_empty()
+    }
+}
+
+/// Starts the config watcher task.
+///
+/// This task:
+/// - Polls the config file's modification time.
+/// - Reloads and validates it (CLI/env overrides still win) when it changes.
+/// - Pushes live-tunable settings into the running tasks.
+/// - Notifies WebSocket clients with a `config_reloaded` message.
+/// - Keeps the previous config if the new file is invalid.
+pub async fn start_config_watcher_task(cli: Cli, mut current: Config, live: LiveSettings) {
+    let mut last_modified = cli.config_path().and_then(|path| modified(&path));
+    let mut interval = interval(POLL_INTERVAL);
+    info!("Config watcher started");
+
+    loop {
+        interval.tick().await;
+        let modified_now = cli.config_path().and_then(|path| modified(&path));
+        if modified_now == last_modified {
+            continue;
+        }
+        last_modified = modified_now;
+
+        let new = match Config::load(&cli) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Ignoring config change: {}", e);
+                continue;
+            }
+        };
+        let diff = ConfigDiff::between(&current, &new);
+        if diff.is_empty() {
+            continue;
+        }
+
+        if new.world.tick_hz != current.world.tick_hz {
+            let _ = live.tick_hz_tx.send(new.world.tick_hz);
+        }
+        if new.api.snapshot_hz != current.api.snapshot_hz {
+            let _ = live.snapshot_hz_tx.send(new.api.snapshot_hz);
+        }
+        if new.world.time_scale != current.world.time_scale {
+            let _ = live
+                .world_command_tx
+                .send(WorldCommand::SetTimeScale(new.world.time_scale, None))
+                .await;
+        }
+        if new.dynamics() != current.dynamics() {
+            let _ = live
+                .world_command_tx
+                .send(WorldCommand::SetDynamics(new.dynamics()))
+                .await;
+        }
+        // Only on change, so a reload keeps gains set by an audio patch
+        if new.layer_gains() != current.layer_gains() {
+            live.layer_gains.set(new.layer_gains());
+        }
+        if new.logging.level != current.logging.level
+            || new.logging.targets != current.logging.targets
+        {
+            let settings = LogSettings::new(new.logging.level.clone(), new.logging.targets.clone())
+                .and_then(|settings| live.log_control.set(settings));
+            if let Err(e) = settings {
+                warn!("Keeping the log filter: {}", e);
+            }
+        }
+
+        info!(
+            "Config reloaded: applied {:?}, requires restart {:?}",
+            diff.applied, diff.requires_restart
+        );
+        let _ = live.broadcast_tx.send(ServerMessage::ConfigReloaded {
+            version: PROTOCOL_VERSION.to_string(),
+            payload: ConfigReloadedPayload {
+                applied: diff.applied,
+                requires_restart: diff.requires_restart,
+            },
+        });
+        current = new;
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_splits_live_and_restart_keys() {
+        let old = Config::default();
+        let mut new = Config::default();
+        new.world.tick_hz = 30.0;
+        new.audio.texture_gain = 0.2;
+        new.api.port = 4000;
+        let diff = ConfigDiff::between(&old, &new);
+        assert_eq!(diff.applied, vec!["world.tick_hz", "audio.texture_gain"]);
+        assert_eq!(diff.requires_restart, vec!["api.port"]);
+    }
+
+    #[test]
+    fn test_diff_empty_for_identical_configs() {
+        assert!(ConfigDiff::between(&Config::default(), &Config::default()).is_empty());
+    }
+}
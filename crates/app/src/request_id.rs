@@ -0,0 +1,111 @@
+//! Request ids for the REST API.
+//!
+//! Every HTTP request gets an id: the client's own `X-Request-Id` when it
+//! sends a usable one, a fresh one otherwise. The id is a field on the
+//! request's tracing span, is echoed back in the response's `X-Request-Id`,
+//! and is recorded with any audit record the request leads to, so a client's
+//! logs line up with the server's. WebSocket messages carry their own
+//! `request_id` in the payload instead.
+
+use axum::extract::Request;
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use rand::Rng;
+use tracing::{Level, Span};
+
+/// Header a request id is read from and echoed in.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client id kept; longer ones are replaced with a fresh id.
+pub const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// The id of the request being handled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// The id in `value`, if it is one worth keeping: 1 to
+    /// [`MAX_REQUEST_ID_LEN`] visible ASCII characters.
+    pub fn parse(value: &HeaderValue) -> Option<Self> {
+        let bytes = value.as_bytes();
+        let valid = !bytes.is_empty()
+            && bytes.len() <= MAX_REQUEST_ID_LEN
+            && bytes.iter().all(u8::is_ascii_graphic);
+        valid.then(|| Self(String::from_utf8_lossy(bytes).into_owned()))
+    }
+
+    /// A fresh random id.
+    pub fn generate() -> Self {
+        Self(format!("{:032x}", rand::rng().random::<u128>()))
+    }
+}
+
+/// The id of the request the current task is handling, if any.
+///
+/// Set for the whole of a REST handler, including the events it queues, but
+/// not for tasks it spawns (WebSocket sessions among them).
+pub fn current() -> Option<String> {
+    CURRENT.try_with(|id| id.0.clone()).ok()
+}
+
+/// Middleware that assigns the request id and echoes it in the response.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(RequestId::parse)
+        .unwrap_or_else(RequestId::generate);
+    request.extensions_mut().insert(id.clone());
+    let header = HeaderValue::from_str(&id.0).ok();
+    let mut response = CURRENT.scope(id, next.run(request)).await;
+    if let Some(header) = header {
+        response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    }
+    response
+}
+
+/// The span for an HTTP request, carrying its id.
+pub fn make_request_span(request: &Request) -> Span {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.as_str());
+    tracing::span!(
+        Level::INFO,
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_client_ids_are_kept_only_when_usable() {
+        let parse = |value: &str| RequestId::parse(&HeaderValue::from_str(value).unwrap());
+        assert_eq!(
+            parse("client-42:retry-1"),
+            Some(RequestId("client-42:retry-1".to_string()))
+        );
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("two words"), None);
+        assert_eq!(parse(&"x".repeat(MAX_REQUEST_ID_LEN + 1)), None);
+        assert_ne!(RequestId::generate(), RequestId::generate());
+
+        assert_eq!(current(), None);
+        let id = RequestId("abc".to_string());
+        assert_eq!(
+            CURRENT.scope(id, async { current() }).await.as_deref(),
+            Some("abc")
+        );
+    }
+}
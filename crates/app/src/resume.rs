@@ -0,0 +1,101 @@
+//! Resumable WebSocket sessions.
+//!
+//! The hello message carries a resume token. When a connection drops, its
+//! session (negotiated settings, subscription and the broadcasts still queued
+//! for it) is parked under that token for [`RESUME_WINDOW`]. A client that
+//! reconnects and sends the token in its hello picks the session back up: the
+//! broadcasts it missed are replayed and it gets the current snapshot, so a
+//! dashboard on flaky Wi-Fi carries on without a gap.
+
+use rand::Rng;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long a dropped session can be resumed.
+pub const RESUME_WINDOW: Duration = Duration::from_secs(120);
+
+/// Most sessions parked at once; the longest parked goes first.
+pub const MAX_PARKED_SESSIONS: usize = 256;
+
+/// Dropped sessions waiting to be resumed, by token.
+#[derive(Debug)]
+pub struct ResumeStore<T> {
+    parked: HashMap<String, (Instant, T)>,
+}
+
+impl<T> Default for ResumeStore<T> {
+    fn default() -> Self {
+        Self {
+            parked: HashMap::new(),
+        }
+    }
+}
+
+impl<T> ResumeStore<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parks a session dropped at `now`.
+    pub fn park(&mut self, token: String, session: T, now: Instant) {
+        self.expire(now);
+        if self.parked.len() >= MAX_PARKED_SESSIONS
+            && let Some(oldest) = self
+                .parked
+                .iter()
+                .min_by_key(|(_, (parked_at, _))| *parked_at)
+                .map(|(token, _)| token.clone())
+        {
+            self.parked.remove(&oldest);
+        }
+        self.parked.insert(token, (now, session));
+    }
+
+    /// Takes back the session parked under `token`, if it has not expired.
+    pub fn take(&mut self, token: &str, now: Instant) -> Option<T> {
+        self.expire(now);
+        self.parked.remove(token).map(|(_, session)| session)
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.parked
+            .retain(|_, (parked_at, _)| now.duration_since(*parked_at) < RESUME_WINDOW);
+    }
+}
+
+/// A fresh unguessable token, since it stands in for the session's role.
+pub fn new_token() -> String {
+    format!("{:032x}", rand::rng().random::<u128>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_resume_once_within_the_window() {
+        let start = Instant::now();
+        let mut store = ResumeStore::new();
+        store.park("a".to_string(), 1, start);
+        store.park("b".to_string(), 2, start);
+        assert_eq!(store.take("a", start + Duration::from_secs(5)), Some(1));
+        assert_eq!(store.take("a", start + Duration::from_secs(5)), None);
+        assert_eq!(store.take("b", start + RESUME_WINDOW), None);
+        assert!(store.parked.is_empty());
+    }
+
+    #[test]
+    fn test_oldest_session_makes_way() {
+        let start = Instant::now();
+        let mut store = ResumeStore::new();
+        for n in 0..=MAX_PARKED_SESSIONS {
+            store.park(n.to_string(), n, start + Duration::from_millis(n as u64));
+        }
+        assert_eq!(store.parked.len(), MAX_PARKED_SESSIONS);
+        let later = start + Duration::from_secs(1);
+        assert_eq!(store.take("0", later), None);
+        assert_eq!(store.take("1", later), Some(1));
+        assert_ne!(new_token(), new_token());
+        assert_eq!(new_token().len(), 32);
+    }
+}
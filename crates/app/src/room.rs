@@ -0,0 +1,282 @@
+//! Microphone-reactive mode: the soundscape swells when the room gets lively.
+//!
+//! With `room.enabled`, the microphone's RMS level is measured against an
+//! adaptive noise floor, so loudness means "louder than this room usually
+//! is" wherever the mic sits. `range_db` above the floor reads as full level.
+//!
+//! - `mode = "events"`: at or above `threshold` the level fires Stir at most
+//!   once per `debounce_ms`, and each onset (a clap, a door) fires Pulse, both
+//!   with intensity `level * gain`. They go through the event queue and the
+//!   audit log like any sensor.
+//! - `mode = "energy"`: a modulator in the tick loop raises energy by
+//!   `energy_rate * level` per second and lifts the sparkle impulse on onsets,
+//!   for a continuous response without events.
+
+use crate::runtime::{EventQueueStats, QueuedEvent};
+use ambient_core::engine::WorldEngine;
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoomConfig {
+    pub enabled: bool,
+    /// Input device name; the default input when unset.
+    pub device: Option<String>,
+    pub mode: RoomMode,
+    /// Loudness above the noise floor that reads as full level.
+    pub range_db: f64,
+    /// How slowly the noise floor follows a louder room. Longer keeps a
+    /// lively room reading loud for longer.
+    pub floor_rise_secs: f64,
+    /// Events mode: level at or above which Stir and Pulse fire.
+    pub threshold: f64,
+    /// Events mode: intensity per unit of level, clamped to 1.
+    pub gain: f64,
+    /// Events mode: shortest time between two Stirs, or two Pulses.
+    pub debounce_ms: u64,
+    /// Energy mode: energy added per second at full level.
+    pub energy_rate: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomMode {
+    #[default]
+    Events,
+    Energy,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: None,
+            mode: RoomMode::Events,
+            range_db: 30.0,
+            floor_rise_secs: 300.0,
+            threshold: 0.3,
+            gain: 1.0,
+            debounce_ms: 2000,
+            energy_rate: 0.2,
+        }
+    }
+}
+
+impl RoomConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.range_db > 0.0 && self.range_db <= 100.0) {
+            return Err(format!(
+                "room.range_db must be in (0, 100], got {}",
+                self.range_db
+            ));
+        }
+        if !(self.floor_rise_secs.is_finite() && self.floor_rise_secs > 0.0) {
+            return Err(format!(
+                "room.floor_rise_secs must be a positive number of seconds, got {}",
+                self.floor_rise_secs
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.threshold) {
+            return Err(format!(
+                "room.threshold must be in [0, 1], got {}",
+                self.threshold
+            ));
+        }
+        if !(0.0..=10.0).contains(&self.gain) {
+            return Err(format!("room.gain must be in [0, 10], got {}", self.gain));
+        }
+        if !(0.0..=10.0).contains(&self.energy_rate) {
+            return Err(format!(
+                "room.energy_rate must be in [0, 10], got {}",
+                self.energy_rate
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Starts the microphone and, depending on the mode, the event task or the
+/// energy modulator. Failing to open the input is logged, not fatal.
+#[cfg(feature = "audio-output")]
+pub fn start_room(
+    config: &RoomConfig,
+    engine: &mut WorldEngine,
+    event_tx: &mpsc::Sender<QueuedEvent>,
+    event_queue: &Arc<EventQueueStats>,
+) {
+    use audio::input::{InputMeter, MicInput};
+    use tracing::{info, warn};
+
+    if !config.enabled {
+        return;
+    }
+    let meter = Arc::new(InputMeter::new());
+    let input = match MicInput::spawn(config.device.clone(), Arc::clone(&meter)) {
+        Ok(input) => input,
+        Err(e) => {
+            warn!("Room mode disabled: {}", e);
+            return;
+        }
+    };
+    info!("Room mode: {:?}", config.mode);
+    match config.mode {
+        RoomMode::Events => {
+            tokio::spawn(capture::start_room_task(
+                config.clone(),
+                meter,
+                input,
+                event_tx.clone(),
+                Arc::clone(event_queue),
+            ));
+        }
+        RoomMode::Energy => {
+            engine.add_modulator(capture::RoomModulator::new(config, meter, input));
+        }
+    }
+}
+
+#[cfg(not(feature = "audio-output"))]
+pub fn start_room(
+    config: &RoomConfig,
+    _engine: &mut WorldEngine,
+    _event_tx: &mpsc::Sender<QueuedEvent>,
+    _event_queue: &Arc<EventQueueStats>,
+) {
+    if config.enabled {
+        tracing::warn!("Room mode needs the audio-output feature, disabled");
+    }
+}
+
+#[cfg(feature = "audio-output")]
+mod capture {
+    use super::RoomConfig;
+    use crate::api::{SubmitError, apply_event};
+    use crate::runtime::{EventQueueStats, QueuedEvent};
+    use ambient_core::events::{Event, EventSource, PerformAction};
+    use ambient_core::modulator::Modulator;
+    use ambient_core::world::WorldState;
+    use audio::input::{InputMeter, MicInput, RoomLevel};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use tokio::time::{Instant, MissedTickBehavior};
+    use tracing::{debug, warn};
+
+    /// Rate at which events mode reads the meter.
+    const POLL_HZ: f64 = 20.0;
+
+    /// Audit source name for room events.
+    const SOURCE: &str = "room";
+
+    /// Reads the meter and fires Stir on sustained loudness and Pulse on
+    /// onsets. Holds `input` so the capture runs as long as the task.
+    pub async fn start_room_task(
+        config: RoomConfig,
+        meter: Arc<InputMeter>,
+        _input: MicInput,
+        event_tx: mpsc::Sender<QueuedEvent>,
+        event_queue: Arc<EventQueueStats>,
+    ) {
+        let mut level = RoomLevel::new(config.range_db as f32, config.floor_rise_secs as f32);
+        let debounce = Duration::from_millis(config.debounce_ms);
+        let mut last_stir: Option<Instant> = None;
+        let mut last_pulse: Option<Instant> = None;
+        let mut seen_onsets = meter.onsets();
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / POLL_HZ));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+            let value = level.update(meter.rms(), (1.0 / POLL_HZ) as f32) as f64;
+            let onsets = meter.onsets();
+            let onset = onsets > seen_onsets;
+            seen_onsets = onsets;
+            if value < config.threshold {
+                continue;
+            }
+            let ready =
+                |last: Option<Instant>| last.is_none_or(|t| now.duration_since(t) >= debounce);
+            let intensity = (value * config.gain).min(1.0);
+            let mut actions = Vec::new();
+            if onset && ready(last_pulse) {
+                last_pulse = Some(now);
+                actions.push(PerformAction::Pulse { intensity });
+            }
+            if ready(last_stir) {
+                last_stir = Some(now);
+                actions.push(PerformAction::Stir { intensity });
+            }
+            for action in actions {
+                let event =
+                    Event::Perform(action).with_source(EventSource::Sensor(SOURCE.to_string()));
+                match apply_event(&event_tx, &event_queue, event).await {
+                    Ok(result) => debug!(
+                        "Room level {:.2} (floor {:.1} dB) applied: {}",
+                        value,
+                        level.floor_db().unwrap_or_default(),
+                        result.applied
+                    ),
+                    Err(SubmitError::Full) => warn!("Event queue full, dropped room event"),
+                    Err(SubmitError::Closed) => return,
+                }
+            }
+        }
+    }
+
+    /// Raises energy with the room's level on every fixed step.
+    pub struct RoomModulator {
+        level: RoomLevel,
+        energy_rate: f64,
+        meter: Arc<InputMeter>,
+        seen_onsets: u64,
+        _input: MicInput,
+    }
+
+    impl RoomModulator {
+        pub fn new(config: &RoomConfig, meter: Arc<InputMeter>, input: MicInput) -> Self {
+            Self {
+                level: RoomLevel::new(config.range_db as f32, config.floor_rise_secs as f32),
+                energy_rate: config.energy_rate,
+                seen_onsets: meter.onsets(),
+                meter,
+                _input: input,
+            }
+        }
+    }
+
+    impl Modulator for RoomModulator {
+        fn name(&self) -> &str {
+            "room"
+        }
+
+        fn modulate(&mut self, dt: f64, state: &mut WorldState) {
+            let value = self.level.update(self.meter.rms(), dt as f32) as f64;
+            state.set_energy(state.energy() + self.energy_rate * value * dt);
+            let onsets = self.meter.onsets();
+            if onsets > self.seen_onsets {
+                state.set_sparkle_impulse(state.sparkle_impulse().max(value));
+            }
+            self.seen_onsets = onsets;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_room_section() {
+        let config: Config = toml::from_str("[room]\nenabled = true\nmode = \"energy\"\n").unwrap();
+        assert_eq!(config.room.mode, RoomMode::Energy);
+        assert_eq!(config.room.range_db, 30.0);
+        assert!(config.room.validate().is_ok());
+
+        let mut room = config.room;
+        room.range_db = 0.0;
+        assert!(room.validate().unwrap_err().contains("room.range_db"));
+    }
+}
@@ -1,115 +1,663 @@
-use ambient_core::engine::WorldEngine;
-use ambient_core::events::Event;
-use ambient_core::world::WorldSnapshot;
-use audio::params::{AudioParams, SharedAudioParams};
+use crate::anomaly::{AnomalyMonitor, CHECK_INTERVAL};
+use crate::api::{BeatPayload, MoodChangedPayload, ServerMessage, SparklePayload};
+use crate::audit::{AuditAction, AuditRecord};
+use crate::protocol::PROTOCOL_VERSION;
+use crate::request_id;
+use crate::stats::{StatsHistory, StatsSummary};
+use ambient_core::engine::{ApplyResult, WorldEngine};
+use ambient_core::events::{Event, EventSource, SourcedEvent};
+use ambient_core::world::{RunState, WorldDynamics, WorldPreset, WorldSnapshot};
+use audio::gate::{GainGate, GateSettings};
+use audio::mapping::{MappingProfile, WorldInputs};
+use audio::params::{AudioParams, LayerAmounts, ParamOverrides, SharedAudioParams};
+use audio::transition::{CurveTable, SharedTransition};
+use serde::Serialize;
 use std::sync::Arc;
-use tokio::sync::{mpsc, watch};
-use tokio::time::{Duration, Instant, interval};
-use tracing::info;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio::time::{Duration, Instant, MissedTickBehavior, interval, sleep_until};
+use tracing::{Span, info, info_span, warn};
+use utoipa::ToSchema;
+
+/// Tick rates the world runs at, in Hz, whether set in the config file or
+/// through `POST /admin/tick_rate`.
+pub const TICK_RATE_RANGE: std::ops::RangeInclusive<f64> = 1.0..=1000.0;
+
+/// Longest dt a single tick may carry, as a multiple of the nominal interval.
+const MAX_TICK_DT_FACTOR: f64 = 1.5;
+
+/// Most ticks sent in one go to catch up after a stall; time beyond that is dropped.
+const MAX_CATCH_UP_TICKS: u32 = 10;
+
+/// How often the measured tick rate is refreshed.
+const TICK_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// An event queued for the world task, with an optional reply channel for
+/// the [`ApplyResult`].
+pub struct QueuedEvent {
+    pub event: SourcedEvent,
+    pub reply: Option<oneshot::Sender<ApplyResult>>,
+    /// The submitter's span, which the world apply span is parented to.
+    pub span: Span,
+    pub queued_at: Instant,
+    /// The REST request that queued the event, for the audit log.
+    pub request_id: Option<String>,
+}
+
+impl QueuedEvent {
+    /// Queues `event` from the current span and request.
+    pub fn new(event: SourcedEvent, reply: Option<oneshot::Sender<ApplyResult>>) -> Self {
+        Self {
+            event,
+            reply,
+            span: Span::current(),
+            queued_at: Instant::now(),
+            request_id: request_id::current(),
+        }
+    }
+}
+
+impl From<SourcedEvent> for QueuedEvent {
+    fn from(event: SourcedEvent) -> Self {
+        Self::new(event, None)
+    }
+}
+
+/// The `audio.update` span of the latest applied event, waiting for the audio
+/// control task. Taken rather than shared, so the trace closes once the audio
+/// parameters are set.
+pub type PendingAudioSpan = Arc<std::sync::Mutex<Option<Span>>>;
+
+/// Capacity of the world event queue.
+pub const EVENT_QUEUE_CAPACITY: usize = 100;
+
+/// Backpressure counters for the world event queue, served by `GET /metrics`.
+#[derive(Debug, Default)]
+pub struct EventQueueStats {
+    rejected: AtomicU64,
+    coalesced_ticks: AtomicU64,
+}
+
+impl EventQueueStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts an event turned away because the queue was full.
+    pub fn record_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a tick folded into the next one because the queue was full.
+    pub fn record_coalesced_tick(&self) {
+        self.coalesced_ticks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    pub fn coalesced_ticks(&self) -> u64 {
+        self.coalesced_ticks.load(Ordering::Relaxed)
+    }
+}
+
+/// Manual audio parameters set through `POST /audio/override`, in force until
+/// `expires_at`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioOverride {
+    pub params: ParamOverrides,
+    pub expires_at: Instant,
+}
+
+/// The world → audio mapping profile in use, by name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveMapping {
+    pub name: String,
+    pub profile: MappingProfile,
+}
+
+/// Maps a world snapshot through `profile`, following its harmony, with
+/// default layer amounts.
+pub fn map_snapshot(profile: &MappingProfile, snapshot: &WorldSnapshot) -> AudioParams {
+    profile.map(&WorldInputs::from_snapshot(snapshot))
+}
+
+/// Out-of-band commands for the world task that are not world events.
+#[derive(Debug)]
+pub enum WorldCommand {
+    SetDynamics(WorldDynamics),
+    /// Pauses or resumes ticking; replies with the resulting snapshot.
+    SetRunState(RunState, oneshot::Sender<WorldSnapshot>),
+    /// Sets the tick dt multiplier; replies with the resulting snapshot.
+    SetTimeScale(f64, Option<oneshot::Sender<WorldSnapshot>>),
+    /// Advances a number of fixed steps, even while paused.
+    Step(u32, oneshot::Sender<ApplyResult>),
+    /// Replies with the current parameters and targets.
+    CapturePreset(oneshot::Sender<WorldPreset>),
+    /// Moves the world to a preset over a number of seconds of simulated time.
+    RecallPreset(WorldPreset, f64, oneshot::Sender<ApplyResult>),
+    /// Replies with the world a named scene leads to, if the scene exists.
+    ScenePreset(String, oneshot::Sender<Option<WorldPreset>>),
+    /// Moves the world a fraction of the way toward a preset over a number
+    /// of seconds of simulated time.
+    Morph(WorldPreset, f64, f64, oneshot::Sender<ApplyResult>),
+    /// Replies with parameter and event statistics over a trailing window.
+    Stats(Duration, oneshot::Sender<StatsSummary>),
+}
+
+/// Where the world task publishes what it produces.
+pub struct WorldOutputs {
+    /// Snapshots after each change.
+    pub state_tx: watch::Sender<WorldSnapshot>,
+    /// Sparkle, beat and mood messages for WebSocket clients.
+    pub broadcast_tx: broadcast::Sender<ServerMessage>,
+}
 
 /// Starts the world task that processes events and sends state snapshots.
 ///
 /// This task:
 /// - Receives events from the event channel.
-/// - Applies them to the WorldEngine.
-/// - Sends updated snapshots to the state channel.
+/// - Applies them to the WorldEngine and replies with the result if asked.
+/// - Forwards non-tick events to the audit log, if enabled.
+/// - Applies each non-tick event in a `world.apply` span under its submitter's
+///   span, and leaves an `audio.update` child in `audio_span` for the audio
+///   control task.
+/// - Sends updated snapshots to the state channel, except for ticks ignored
+///   while paused, and folds them and the events into rolling statistics.
+/// - Broadcasts a `sparkle` message for each sparkle the world fires and a
+///   `beat` message for each beat of its pulse grid.
+/// - Applies control commands (reloaded dynamics, pause/resume, time scale,
+///   single steps, preset capture, recall and morphs, scene lookups, stats
+///   queries) between events.
+/// - Checks for stuck states once a second and reports alerts through `monitor`.
 /// - Exits gracefully if the event channel closes.
 pub async fn start_world_task(
-    mut event_rx: mpsc::Receiver<Event>,
-    state_tx: watch::Sender<WorldSnapshot>,
+    mut engine: WorldEngine,
+    mut event_rx: mpsc::Receiver<QueuedEvent>,
+    mut command_rx: mpsc::Receiver<WorldCommand>,
+    outputs: WorldOutputs,
+    audio_span: PendingAudioSpan,
+    audit_tx: Option<mpsc::Sender<AuditRecord>>,
+    mut monitor: AnomalyMonitor,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut engine = WorldEngine::new();
+    let WorldOutputs {
+        state_tx,
+        broadcast_tx,
+    } = outputs;
     info!("World task started");
+    let mut stats = StatsHistory::new();
+    let mut anomaly_check = interval(CHECK_INTERVAL);
 
     loop {
-        match event_rx.recv().await {
-            Some(event) => {
-                engine.apply(event);
-                let snapshot = engine.get_snapshot();
-                state_tx.send(snapshot)?;
-            }
-            None => {
-                info!("Event channel closed, exiting world task");
-                break;
-            }
+        tokio::select! {
+            event = event_rx.recv() => match event {
+                Some(QueuedEvent {
+                    event: SourcedEvent { event, source },
+                    reply,
+                    span,
+                    queued_at,
+                    request_id,
+                }) => {
+                    let is_tick = event.is_tick();
+                    // Ticks arrive too often to be worth a span each
+                    let apply_span = if is_tick {
+                        Span::none()
+                    } else {
+                        info_span!(
+                            parent: &span,
+                            "world.apply",
+                            event = ?event,
+                            tick = engine.tick(),
+                            queue_wait_us = queued_at.elapsed().as_micros() as u64,
+                        )
+                    };
+                    let _entered = apply_span.enter();
+                    let timestamp_ms = unix_time_ms();
+                    if let Some(audit_tx) = &audit_tx
+                        && !event.is_tick()
+                    {
+                        let record = AuditRecord {
+                            timestamp_ms,
+                            tick: engine.tick(),
+                            source,
+                            request_id,
+                            action: AuditAction::Event(event.clone()),
+                        };
+                        // Never stall the simulation on disk I/O
+                        if audit_tx.try_send(record).is_err() {
+                            warn!("Audit log backlog full, dropping record");
+                        }
+                    }
+                    stats.count_event(&event, timestamp_ms);
+                    let mut result = engine.apply(event);
+                    result.resulting_snapshot =
+                        result.resulting_snapshot.with_timestamp_ms(timestamp_ms);
+                    if is_tick && result.applied {
+                        monitor.detector.tick(timestamp_ms);
+                    }
+                    broadcast_engine_events(&mut engine, &broadcast_tx, timestamp_ms);
+                    if result.applied || !is_tick {
+                        stats.record(&result.resulting_snapshot);
+                        monitor.detector.observe(&result.resulting_snapshot, timestamp_ms);
+                        if !is_tick {
+                            let tick = result.resulting_snapshot.tick();
+                            *audio_span.lock().unwrap() =
+                                Some(info_span!(parent: &apply_span, "audio.update", tick));
+                        }
+                        state_tx.send(result.resulting_snapshot.clone())?;
+                    }
+                    if let Some(reply) = reply {
+                        // The requester may have given up waiting
+                        let _ = reply.send(result);
+                    }
+                }
+                None => {
+                    info!("Event channel closed, exiting world task");
+                    break;
+                }
+            },
+            Some(command) = command_rx.recv() => match command {
+                WorldCommand::SetDynamics(dynamics) => {
+                    info!("World dynamics updated: {:?}", dynamics);
+                    engine.set_dynamics(dynamics);
+                }
+                WorldCommand::SetRunState(run_state, reply) => {
+                    info!("World run state set to {:?}", run_state);
+                    engine.set_run_state(run_state);
+                    let snapshot = engine.get_snapshot().with_timestamp_ms(unix_time_ms());
+                    monitor.detector.observe(&snapshot, snapshot.timestamp_ms());
+                    state_tx.send(snapshot.clone())?;
+                    let _ = reply.send(snapshot);
+                }
+                WorldCommand::SetTimeScale(time_scale, reply) => {
+                    info!("World time scale set to {}x", time_scale);
+                    engine.set_time_scale(time_scale);
+                    let snapshot = engine.get_snapshot().with_timestamp_ms(unix_time_ms());
+                    state_tx.send(snapshot.clone())?;
+                    if let Some(reply) = reply {
+                        let _ = reply.send(snapshot);
+                    }
+                }
+                WorldCommand::Step(ticks, reply) => {
+                    let timestamp_ms = unix_time_ms();
+                    let mut result = engine.step(ticks);
+                    broadcast_engine_events(&mut engine, &broadcast_tx, timestamp_ms);
+                    result.resulting_snapshot =
+                        result.resulting_snapshot.with_timestamp_ms(timestamp_ms);
+                    state_tx.send(result.resulting_snapshot.clone())?;
+                    let _ = reply.send(result);
+                }
+                WorldCommand::CapturePreset(reply) => {
+                    let _ = reply.send(engine.capture_preset());
+                }
+                WorldCommand::RecallPreset(preset, ramp_secs, reply) => {
+                    info!("Recalling preset over {}s", ramp_secs);
+                    let mut result = engine.recall_preset(preset, ramp_secs);
+                    result.resulting_snapshot =
+                        result.resulting_snapshot.with_timestamp_ms(unix_time_ms());
+                    state_tx.send(result.resulting_snapshot.clone())?;
+                    let _ = reply.send(result);
+                }
+                WorldCommand::ScenePreset(name, reply) => {
+                    let _ = reply.send(engine.scene_preset(&name));
+                }
+                WorldCommand::Morph(reference, fraction, ramp_secs, reply) => {
+                    info!("Morphing {:.0}% of the way over {}s", fraction * 100.0, ramp_secs);
+                    let mut result = engine.morph_toward(&reference, fraction, ramp_secs);
+                    result.resulting_snapshot =
+                        result.resulting_snapshot.with_timestamp_ms(unix_time_ms());
+                    state_tx.send(result.resulting_snapshot.clone())?;
+                    let _ = reply.send(result);
+                }
+                WorldCommand::Stats(window, reply) => {
+                    let _ = reply.send(stats.summary(window, unix_time_ms()));
+                }
+            },
+            _ = anomaly_check.tick() => monitor.check(unix_time_ms()),
         }
     }
 
     Ok(())
 }
 
+/// Sends a `beat` message for each beat, a `sparkle` message for each
+/// sparkle and a `mood_changed` message for each mood change since the last
+/// call, in that order. Nobody listening is not an error.
+fn broadcast_engine_events(
+    engine: &mut WorldEngine,
+    broadcast_tx: &broadcast::Sender<ServerMessage>,
+    timestamp_ms: u64,
+) {
+    for beat in engine.take_beats() {
+        let _ = broadcast_tx.send(ServerMessage::Beat {
+            version: PROTOCOL_VERSION.to_string(),
+            payload: BeatPayload { timestamp_ms, beat },
+        });
+    }
+    for sparkle in engine.take_sparkles() {
+        let _ = broadcast_tx.send(ServerMessage::Sparkle {
+            version: PROTOCOL_VERSION.to_string(),
+            payload: SparklePayload {
+                timestamp_ms,
+                sparkle,
+            },
+        });
+    }
+    for change in engine.take_mood_changes() {
+        let _ = broadcast_tx.send(ServerMessage::MoodChanged {
+            version: PROTOCOL_VERSION.to_string(),
+            payload: MoodChangedPayload {
+                timestamp_ms,
+                change,
+            },
+        });
+    }
+}
+
+/// Current wall-clock time in milliseconds since the Unix epoch.
+pub fn unix_time_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Tick clock state published by the tick task, served by `GET /world/clock`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct TickStats {
+    /// Ticks sent since startup, including catch-up ticks.
+    pub tick_index: u64,
+    /// Simulated seconds sent as tick dt since startup.
+    pub sim_time_secs: f64,
+    /// Configured tick rate.
+    pub nominal_hz: f64,
+    /// Ticks actually sent per wall-clock second, over the last second.
+    pub actual_hz: f64,
+    /// Extra ticks sent to catch up after stalls.
+    pub catch_up_ticks: u64,
+    /// Wall-clock seconds dropped because a stall outran the catch-up limit.
+    pub dropped_secs: f64,
+}
+
+impl TickStats {
+    pub fn new(nominal_hz: f64) -> Self {
+        Self {
+            tick_index: 0,
+            sim_time_secs: 0.0,
+            nominal_hz,
+            actual_hz: nominal_hz,
+            catch_up_ticks: 0,
+            dropped_secs: 0.0,
+        }
+    }
+}
+
+/// Ticks to send for one wakeup of the tick task.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TickBatch {
+    pub ticks: u32,
+    /// dt carried by each tick.
+    pub dt: f64,
+    /// Elapsed time not covered by the ticks.
+    pub dropped_secs: f64,
+}
+
+/// Splits wall-clock time between wakeups into ticks of bounded dt.
+///
+/// A normal wakeup becomes one tick carrying the measured dt, so jitter is
+/// kept. After a stall the elapsed time is spread over several nominal-sized
+/// ticks instead of one huge step, up to [`MAX_CATCH_UP_TICKS`].
+#[derive(Debug, Clone, Copy)]
+pub struct TickClock {
+    nominal_dt: f64,
+}
+
+impl TickClock {
+    pub fn new(hz: f64) -> Self {
+        Self {
+            nominal_dt: 1.0 / hz,
+        }
+    }
+
+    pub fn set_rate(&mut self, hz: f64) {
+        self.nominal_dt = 1.0 / hz;
+    }
+
+    pub fn batch(&self, elapsed: f64) -> TickBatch {
+        let max_dt = self.nominal_dt * MAX_TICK_DT_FACTOR;
+        if elapsed <= max_dt {
+            return TickBatch {
+                ticks: 1,
+                dt: elapsed.max(0.0),
+                dropped_secs: 0.0,
+            };
+        }
+        let ticks = ((elapsed / self.nominal_dt).round() as u32).clamp(1, MAX_CATCH_UP_TICKS);
+        let dt = (elapsed / ticks as f64).min(max_dt);
+        TickBatch {
+            ticks,
+            dt,
+            dropped_secs: (elapsed - dt * ticks as f64).max(0.0),
+        }
+    }
+}
+
 /// Starts the tick sender task that periodically sends Tick events.
 ///
 /// This task:
-/// - Runs at the specified frequency (Hz).
+/// - Runs at the frequency (Hz) published on the tick rate channel.
+/// - Restarts its interval whenever the tick rate changes.
 /// - Computes the time delta (dt) since the last tick.
-/// - Sends Event::Tick to the event channel.
+/// - After a stall, catches up with several ticks of capped dt instead of one
+///   huge step, dropping any time beyond the catch-up limit.
+/// - Sends Event::Tick to the event channel. If `coalesce` is set and the
+///   channel is full, the tick's dt is folded into the next one instead of
+///   waiting for room.
+/// - Publishes the tick index, simulation time and actual tick rate.
 /// - Keeps running separately to avoid blocking the world task.
 pub async fn start_tick_task(
-    event_tx: mpsc::Sender<Event>,
-    hz: f64,
+    event_tx: mpsc::Sender<QueuedEvent>,
+    mut tick_hz_rx: watch::Receiver<f64>,
+    stats_tx: watch::Sender<TickStats>,
+    queue_stats: Arc<EventQueueStats>,
+    coalesce: bool,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let interval_secs = 1.0 / hz;
-    let mut interval = interval(Duration::from_secs_f64(interval_secs));
+    let hz = *tick_hz_rx.borrow_and_update();
+    let mut pending_dt = 0.0;
+    let mut clock = TickClock::new(hz);
+    let mut interval = tick_interval(hz);
     let mut last_time = Instant::now();
+    let mut window_start = last_time;
+    let mut window_ticks = 0u32;
+    stats_tx.send_modify(|stats| stats.nominal_hz = hz);
     info!(
         "Tick task started with frequency {:.2} Hz (interval {:.3}s)",
-        hz, interval_secs
+        hz,
+        1.0 / hz
     );
 
     loop {
-        interval.tick().await;
+        tokio::select! {
+            _ = interval.tick() => {}
+            changed = tick_hz_rx.changed() => {
+                if changed.is_err() {
+                    info!("Tick rate channel closed, stopping tick task");
+                    break;
+                }
+                let hz = *tick_hz_rx.borrow_and_update();
+                interval = tick_interval(hz);
+                clock.set_rate(hz);
+                stats_tx.send_modify(|stats| stats.nominal_hz = hz);
+                info!("Tick rate changed to {:.2} Hz", hz);
+                continue;
+            }
+        }
         let now = Instant::now();
-        let dt = now.duration_since(last_time).as_secs_f64();
+        let batch = clock.batch(now.duration_since(last_time).as_secs_f64());
         last_time = now;
+        if batch.ticks > 1 {
+            warn!(
+                "Tick task stalled, catching up with {} ticks of {:.3}s ({:.3}s dropped)",
+                batch.ticks, batch.dt, batch.dropped_secs
+            );
+        }
 
-        let event = Event::Tick { dt };
-        if event_tx.send(event).await.is_err() {
-            info!("Event channel closed, stopping tick task");
-            break;
+        for _ in 0..batch.ticks {
+            let dt = batch.dt + pending_dt;
+            let event = Event::Tick { dt }.with_source(EventSource::Tick).into();
+            let sent = if coalesce {
+                match event_tx.try_send(event) {
+                    Ok(()) => Ok(()),
+                    Err(TrySendError::Full(_)) => {
+                        queue_stats.record_coalesced_tick();
+                        pending_dt = dt;
+                        continue;
+                    }
+                    Err(TrySendError::Closed(_)) => Err(()),
+                }
+            } else {
+                event_tx.send(event).await.map_err(|_| ())
+            };
+            if sent.is_err() {
+                info!("Event channel closed, stopping tick task");
+                return Ok(());
+            }
+            pending_dt = 0.0;
         }
+
+        window_ticks += batch.ticks;
+        let window = now.duration_since(window_start);
+        let actual_hz = (window >= TICK_RATE_WINDOW).then(|| {
+            let rate = window_ticks as f64 / window.as_secs_f64();
+            window_start = now;
+            window_ticks = 0;
+            rate
+        });
+        stats_tx.send_modify(|stats| {
+            stats.tick_index += batch.ticks as u64;
+            stats.sim_time_secs += batch.dt * batch.ticks as f64;
+            stats.catch_up_ticks += batch.ticks as u64 - 1;
+            stats.dropped_secs += batch.dropped_secs;
+            if let Some(actual_hz) = actual_hz {
+                stats.actual_hz = actual_hz;
+            }
+        });
     }
 
     Ok(())
 }
 
+/// Tick interval that skips, rather than bursts, wakeups missed during a stall;
+/// the stall is made up by catch-up ticks instead.
+fn tick_interval(hz: f64) -> tokio::time::Interval {
+    let mut interval = interval(Duration::from_secs_f64(1.0 / hz));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    interval
+}
+
+/// What the audio control task follows.
+pub struct AudioControlInputs {
+    pub state_rx: watch::Receiver<WorldSnapshot>,
+    pub audio_span: PendingAudioSpan,
+    pub layer_amounts_rx: watch::Receiver<LayerAmounts>,
+    pub override_rx: watch::Receiver<Option<AudioOverride>>,
+    pub mapping_rx: watch::Receiver<ActiveMapping>,
+    /// Lock the delay to the world's pulse grid.
+    pub quantize_delay: bool,
+    /// Hysteresis and floor on the master gain.
+    pub gate: GateSettings,
+}
+
 /// Starts the audio control task that maps world state to audio parameters.
 ///
 /// This task:
-/// - Subscribes to world state snapshots.
-/// - Computes audio parameters from the latest snapshot.
+/// - Subscribes to world state snapshots, per-layer amount changes, overrides
+///   and mapping profile switches.
+/// - Maps the latest snapshot through the active profile, adds the layer
+///   amounts and, if the delay is locked to it, the pulse tempo, then
+///   applies any unexpired manual override on top.
+/// - Traces the update in the pending `audio.update` span, if an event left
+///   one, so it closes when the new parameters are in place.
+/// - Releases an override back to world-driven values when it expires.
+/// - Gates the master gain: closed to zero near silence, with hysteresis so
+///   it does not flutter, and held above the floor while open.
 /// - Updates the shared audio parameters for real-time control.
+/// - Starts an audio crossfade when the snapshot reports a new scene.
 /// - Sends updates to the audio params watch channel for WebSocket clients.
-/// - Runs continuously, updating whenever the world state changes.
+/// - Runs continuously, updating whenever any of its inputs change.
 pub async fn start_audio_control_task(
-    mut state_rx: watch::Receiver<WorldSnapshot>,
+    inputs: AudioControlInputs,
     shared_audio_params: Arc<SharedAudioParams>,
+    shared_transition: Arc<SharedTransition>,
     audio_params_tx: watch::Sender<AudioParams>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let AudioControlInputs {
+        mut state_rx,
+        audio_span,
+        mut layer_amounts_rx,
+        mut override_rx,
+        mut mapping_rx,
+        quantize_delay,
+        gate,
+    } = inputs;
     info!("Audio control task started");
+    let mut last_scene_sequence = 0;
+
+    let mut active_override: Option<AudioOverride> = None;
+    let mut gain_gate = GainGate::new(gate);
 
     loop {
-        // Wait for a new snapshot
-        if state_rx.changed().await.is_err() {
-            info!("State channel closed, stopping audio control task");
-            break;
+        // Wait for a new snapshot, layer or override change, or the override to lapse
+        let expires_at = active_override.map(|o| o.expires_at);
+        tokio::select! {
+            changed = state_rx.changed() => {
+                if changed.is_err() {
+                    info!("State channel closed, stopping audio control task");
+                    break;
+                }
+            }
+            Ok(()) = layer_amounts_rx.changed() => {}
+            Ok(()) = mapping_rx.changed() => {
+                info!("Audio mapping profile set to {}", mapping_rx.borrow().name);
+            }
+            Ok(()) = override_rx.changed() => {
+                active_override = *override_rx.borrow_and_update();
+            }
+            _ = sleep_until(expires_at.unwrap_or_else(Instant::now)), if expires_at.is_some() => {
+                info!("Audio override expired, back to world-driven params");
+                active_override = None;
+            }
         }
 
         // Get the latest snapshot
         let snapshot = state_rx.borrow();
+        let update_span = audio_span.lock().unwrap().take().unwrap_or_else(Span::none);
+        let _entered = update_span.enter();
 
         // Compute audio params from world state
-        let audio_params = AudioParams::from_world_state(
-            snapshot.density() as f32,
-            snapshot.rhythm() as f32,
-            snapshot.tension() as f32,
-            snapshot.energy() as f32,
-            snapshot.warmth() as f32,
-            snapshot.sparkle_impulse() as f32,
-        );
+        let mut audio_params = map_snapshot(&mapping_rx.borrow_and_update().profile, &snapshot);
+        audio_params.layers = *layer_amounts_rx.borrow_and_update();
+        if quantize_delay {
+            audio_params.pulse_bpm = snapshot.pulse().bpm as f32;
+        }
+        if let Some(active) = active_override
+            && active.expires_at > Instant::now()
+        {
+            active.params.apply_to(&mut audio_params);
+        }
+        audio_params.master_gain = gain_gate.apply(audio_params.master_gain);
 
         // Update shared audio params (atomic, non-blocking)
         shared_audio_params.set(audio_params);
+        if let Some(scene) = snapshot.scene()
+            && scene.sequence != last_scene_sequence
+        {
+            last_scene_sequence = scene.sequence;
+            let curve = CurveTable::from_curve(&scene.transition_curve);
+            shared_transition.start(scene.transition_secs as f32, &curve);
+        }
 
         // Send to watch channel for WebSocket clients
         let _ = audio_params_tx.send(audio_params);
@@ -126,14 +674,29 @@ mod tests {
     #[tokio::test]
     async fn test_tick_task_sends_events() {
         let (event_tx, mut event_rx) = mpsc::channel(10);
-        let hz = 10.0; // 10 Hz for faster testing
-        let handle = tokio::spawn(start_tick_task(event_tx, hz));
+        let (_tick_hz_tx, tick_hz_rx) = watch::channel(10.0); // 10 Hz for faster testing
+        let (stats_tx, stats_rx) = watch::channel(TickStats::new(10.0));
+        let handle = tokio::spawn(start_tick_task(
+            event_tx,
+            tick_hz_rx,
+            stats_tx,
+            Arc::new(EventQueueStats::new()),
+            true,
+        ));
 
         // Wait for a few ticks
         let mut count = 0;
         while count < 3 {
             match timeout(Duration::from_millis(200), event_rx.recv()).await {
-                Ok(Some(Event::Tick { dt })) => {
+                Ok(Some(QueuedEvent {
+                    event:
+                        SourcedEvent {
+                            event: Event::Tick { dt },
+                            source: Some(EventSource::Tick),
+                        },
+                    reply: None,
+                    ..
+                })) => {
                     assert!(dt > 0.0 && dt < 0.2); // dt should be around 0.1s
                     count += 1;
                 }
@@ -145,5 +708,121 @@ mod tests {
         handle.abort();
         let _ = handle.await;
         assert_eq!(count, 3);
+        assert!(stats_rx.borrow().tick_index >= 3);
+        assert!(stats_rx.borrow().sim_time_secs > 0.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tick_task_coalesces_when_queue_full() {
+        let (event_tx, mut event_rx) = mpsc::channel(1);
+        let (_tick_hz_tx, tick_hz_rx) = watch::channel(100.0);
+        let (stats_tx, _stats_rx) = watch::channel(TickStats::new(100.0));
+        let queue_stats = Arc::new(EventQueueStats::new());
+        let handle = tokio::spawn(start_tick_task(
+            event_tx,
+            tick_hz_rx,
+            stats_tx,
+            Arc::clone(&queue_stats),
+            true,
+        ));
+        let step = || async {
+            tokio::time::advance(Duration::from_millis(10)).await;
+            tokio::task::yield_now().await;
+        };
+
+        // The first tick fills the queue, and the five after it fold together
+        tokio::task::yield_now().await;
+        for _ in 0..5 {
+            step().await;
+        }
+        assert_eq!(queue_stats.coalesced_ticks(), 5);
+        let _first = event_rx.recv().await.unwrap();
+        step().await;
+        let merged = event_rx.try_recv().unwrap();
+        let Event::Tick { dt } = merged.event.event else {
+            panic!("expected a tick");
+        };
+        assert!(
+            (dt - 0.06).abs() < 1e-9,
+            "coalesced dt {} should cover the missed ticks",
+            dt
+        );
+
+        handle.abort();
+        let _ = handle.await;
+    }
+
+    #[test]
+    fn test_tick_clock_catches_up_with_capped_dt() {
+        let clock = TickClock::new(20.0);
+        let normal = clock.batch(0.06);
+        assert_eq!(
+            (normal.ticks, normal.dt, normal.dropped_secs),
+            (1, 0.06, 0.0)
+        );
+
+        let stall = clock.batch(0.3);
+        assert_eq!(stall.ticks, 6);
+        assert!((stall.dt - 0.05).abs() < 1e-9);
+        assert!(stall.dropped_secs < 1e-9);
+
+        let pause = clock.batch(5.0);
+        assert_eq!(pause.ticks, MAX_CATCH_UP_TICKS);
+        assert!((pause.dt - 0.075).abs() < 1e-9);
+        assert!((pause.dropped_secs - 4.25).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_audio_override_releases_after_duration() {
+        let world = WorldSnapshot::from_world_state(&Default::default());
+        let pulse_bpm = world.pulse().bpm as f32;
+        let mapped = map_snapshot(&MappingProfile::default(), &world);
+        let (_state_tx, state_rx) = watch::channel(world);
+        let (_layers_tx, layers_rx) = watch::channel(LayerAmounts::default());
+        let (override_tx, override_rx) = watch::channel(None);
+        let (_mapping_tx, mapping_rx) = watch::channel(ActiveMapping {
+            name: "default".to_string(),
+            profile: MappingProfile::default(),
+        });
+        let (params_tx, mut params_rx) = watch::channel(mapped);
+        let handle = tokio::spawn(start_audio_control_task(
+            AudioControlInputs {
+                state_rx,
+                audio_span: PendingAudioSpan::default(),
+                layer_amounts_rx: layers_rx,
+                override_rx,
+                mapping_rx,
+                quantize_delay: true,
+                gate: GateSettings::default(),
+            },
+            Arc::new(SharedAudioParams::new(mapped)),
+            Arc::new(SharedTransition::new()),
+            params_tx,
+        ));
+
+        override_tx.send_replace(Some(AudioOverride {
+            params: ParamOverrides {
+                base_freq_hz: Some(330.0),
+                ..ParamOverrides::default()
+            },
+            expires_at: Instant::now() + Duration::from_millis(100),
+        }));
+        timeout(Duration::from_secs(1), params_rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        let overridden = *params_rx.borrow_and_update();
+        assert_eq!(overridden.base_freq_hz, 330.0);
+        assert_eq!(overridden.master_gain, mapped.master_gain);
+        assert_eq!(overridden.pulse_bpm, pulse_bpm);
+
+        timeout(Duration::from_secs(1), params_rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(params_rx.borrow().base_freq_hz, mapped.base_freq_hz);
+
+        handle.abort();
+        let _ = handle.await;
     }
 }
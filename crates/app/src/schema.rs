@@ -0,0 +1,400 @@
+//! Versioned wire shapes for world snapshots.
+//!
+//! [`WorldSnapshot`] grows as the engine does; what goes on the wire is one of
+//! the `SnapshotV*` structs below, picked by the schema version a WebSocket
+//! client negotiated in its hello or a REST caller sent in the
+//! `X-Schema-Version` header. A new field joins the latest version, since
+//! clients ignore fields they don't know and the shared type reads a missing
+//! one as its default. Only a breaking change (a field removed, renamed or
+//! retyped) means a new version, built from the latest one by an explicit
+//! conversion, so older clients keep their shape.
+
+use crate::error::ApiError;
+use crate::protocol::SUPPORTED_VERSIONS;
+use ambient_core::agents::Agent;
+use ambient_core::curves::Curve;
+use ambient_core::engine::ApplyResult;
+use ambient_core::harmony::{Harmony, Mode};
+use ambient_core::mood::Mood;
+use ambient_core::pulse::Pulse;
+use ambient_core::scene::SceneChange;
+use ambient_core::world::{RunState, WorldSnapshot};
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use serde::Serialize;
+use utoipa::openapi::schema::{AnyOfBuilder, Schema};
+use utoipa::openapi::{Ref, RefOr};
+use utoipa::{IntoParams, PartialSchema, ToSchema};
+
+/// Header REST callers use to pick a snapshot schema.
+pub const SCHEMA_VERSION_HEADER: &str = "x-schema-version";
+
+/// A snapshot schema the server can produce.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SchemaVersion {
+    /// Tick, timestamp, the world parameters and the last scene.
+    V1,
+    /// Adds simulation time, run state, time scale and the scene's transition
+    /// curve, and since grew space, clarity, the pulse grid, the harmony, the
+    /// mood and the agents.
+    #[default]
+    V2,
+}
+
+impl SchemaVersion {
+    pub fn parse(version: &str) -> Option<Self> {
+        match version {
+            "1.0" => Some(SchemaVersion::V1),
+            "2.0" => Some(SchemaVersion::V2),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SchemaVersion::V1 => "1.0",
+            SchemaVersion::V2 => "2.0",
+        }
+    }
+}
+
+/// The schema header as an OpenAPI parameter of the endpoints that return
+/// snapshots. Only describes it: [`SchemaVersion`] is what reads it.
+#[derive(IntoParams)]
+#[allow(dead_code)]
+#[into_params(parameter_in = Header)]
+pub struct SchemaVersionHeader {
+    /// `1.0` or `2.0`; the latest when omitted.
+    #[param(rename = "x-schema-version")]
+    pub version: Option<String>,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for SchemaVersion {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(SCHEMA_VERSION_HEADER) else {
+            return Ok(SchemaVersion::default());
+        };
+        value
+            .to_str()
+            .ok()
+            .and_then(SchemaVersion::parse)
+            .ok_or_else(|| {
+                ApiError::VersionMismatch(format!(
+                    "Unsupported schema version, supported: {}",
+                    SUPPORTED_VERSIONS.join(", ")
+                ))
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SceneV1 {
+    pub name: String,
+    pub transition_secs: f64,
+    pub sequence: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SnapshotV1 {
+    pub tick: u64,
+    pub timestamp_ms: u64,
+    pub density: f64,
+    pub rhythm: f64,
+    pub tension: f64,
+    pub energy: f64,
+    pub warmth: f64,
+    pub sparkle_impulse: f64,
+    pub scene: Option<SceneV1>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SceneV2 {
+    pub name: String,
+    pub transition_secs: f64,
+    pub transition_curve: Curve,
+    pub sequence: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct PulseV2 {
+    pub bpm: f64,
+    pub beats_per_bar: u32,
+    pub beat: u64,
+    pub phase: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct HarmonyV2 {
+    pub root: u8,
+    pub mode: Mode,
+    pub degree: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct AgentV2 {
+    pub id: u64,
+    pub x: f64,
+    pub y: f64,
+    pub heading: f64,
+    pub excitement: f64,
+    pub age_secs: f64,
+    pub lifespan_secs: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SnapshotV2 {
+    pub tick: u64,
+    pub timestamp_ms: u64,
+    pub sim_time_secs: f64,
+    pub run_state: RunState,
+    pub time_scale: f64,
+    pub density: f64,
+    pub rhythm: f64,
+    pub tension: f64,
+    pub energy: f64,
+    pub warmth: f64,
+    pub space: f64,
+    pub clarity: f64,
+    pub sparkle_impulse: f64,
+    pub scene: Option<SceneV2>,
+    pub pulse: PulseV2,
+    pub harmony: HarmonyV2,
+    pub mood: Mood,
+    pub agents: Vec<AgentV2>,
+}
+
+impl From<&SceneChange> for SceneV2 {
+    fn from(scene: &SceneChange) -> Self {
+        Self {
+            name: scene.name.clone(),
+            transition_secs: scene.transition_secs,
+            transition_curve: scene.transition_curve.clone(),
+            sequence: scene.sequence,
+        }
+    }
+}
+
+impl From<Pulse> for PulseV2 {
+    fn from(pulse: Pulse) -> Self {
+        Self {
+            bpm: pulse.bpm,
+            beats_per_bar: pulse.beats_per_bar,
+            beat: pulse.beat,
+            phase: pulse.phase,
+        }
+    }
+}
+
+impl From<Harmony> for HarmonyV2 {
+    fn from(harmony: Harmony) -> Self {
+        Self {
+            root: harmony.root,
+            mode: harmony.mode,
+            degree: harmony.degree,
+        }
+    }
+}
+
+impl From<&Agent> for AgentV2 {
+    fn from(agent: &Agent) -> Self {
+        Self {
+            id: agent.id,
+            x: agent.x,
+            y: agent.y,
+            heading: agent.heading,
+            excitement: agent.excitement,
+            age_secs: agent.age_secs,
+            lifespan_secs: agent.lifespan_secs,
+        }
+    }
+}
+
+impl From<&WorldSnapshot> for SnapshotV2 {
+    fn from(snapshot: &WorldSnapshot) -> Self {
+        Self {
+            tick: snapshot.tick(),
+            timestamp_ms: snapshot.timestamp_ms(),
+            sim_time_secs: snapshot.sim_time_secs(),
+            run_state: snapshot.run_state(),
+            time_scale: snapshot.time_scale(),
+            density: snapshot.density(),
+            rhythm: snapshot.rhythm(),
+            tension: snapshot.tension(),
+            energy: snapshot.energy(),
+            warmth: snapshot.warmth(),
+            space: snapshot.space(),
+            clarity: snapshot.clarity(),
+            sparkle_impulse: snapshot.sparkle_impulse(),
+            scene: snapshot.scene().map(SceneV2::from),
+            pulse: snapshot.pulse().into(),
+            harmony: snapshot.harmony().into(),
+            mood: snapshot.mood(),
+            agents: snapshot.agents().iter().map(AgentV2::from).collect(),
+        }
+    }
+}
+
+impl From<SceneV2> for SceneV1 {
+    fn from(scene: SceneV2) -> Self {
+        Self {
+            name: scene.name,
+            transition_secs: scene.transition_secs,
+            sequence: scene.sequence,
+        }
+    }
+}
+
+impl From<SnapshotV2> for SnapshotV1 {
+    fn from(snapshot: SnapshotV2) -> Self {
+        Self {
+            tick: snapshot.tick,
+            timestamp_ms: snapshot.timestamp_ms,
+            density: snapshot.density,
+            rhythm: snapshot.rhythm,
+            tension: snapshot.tension,
+            energy: snapshot.energy,
+            warmth: snapshot.warmth,
+            sparkle_impulse: snapshot.sparkle_impulse,
+            scene: snapshot.scene.map(SceneV1::from),
+        }
+    }
+}
+
+/// A world snapshot in the shape of one schema version.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum WireSnapshot {
+    V1(SnapshotV1),
+    V2(SnapshotV2),
+}
+
+impl WireSnapshot {
+    pub fn new(snapshot: &WorldSnapshot, version: SchemaVersion) -> Self {
+        let latest = SnapshotV2::from(snapshot);
+        match version {
+            SchemaVersion::V1 => WireSnapshot::V1(latest.into()),
+            SchemaVersion::V2 => WireSnapshot::V2(latest),
+        }
+    }
+}
+
+// Written out rather than derived: a later version's snapshot is also a
+// valid earlier one, so the versions are `anyOf` where the derive would say
+// `oneOf`, which no snapshot passes.
+impl PartialSchema for WireSnapshot {
+    fn schema() -> RefOr<Schema> {
+        AnyOfBuilder::new()
+            .description(Some(
+                "A world snapshot in the shape of the negotiated schema version.",
+            ))
+            .item(Ref::from_schema_name(SnapshotV1::name()))
+            .item(Ref::from_schema_name(SnapshotV2::name()))
+            .into()
+    }
+}
+
+impl ToSchema for WireSnapshot {
+    fn schemas(schemas: &mut Vec<(String, RefOr<Schema>)>) {
+        fn add<T: ToSchema>(schemas: &mut Vec<(String, RefOr<Schema>)>) {
+            schemas.push((T::name().into(), T::schema()));
+            T::schemas(schemas);
+        }
+        add::<SnapshotV1>(schemas);
+        add::<SnapshotV2>(schemas);
+    }
+}
+
+/// [`ApplyResult`] with its snapshot in a given schema version.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct WireApplyResult {
+    pub applied: bool,
+    pub clamped_fields: Vec<&'static str>,
+    pub resulting_snapshot: WireSnapshot,
+}
+
+impl WireApplyResult {
+    pub fn new(result: &ApplyResult, version: SchemaVersion) -> Self {
+        Self {
+            applied: result.applied,
+            clamped_fields: result.clamped_fields.clone(),
+            resulting_snapshot: WireSnapshot::new(&result.resulting_snapshot, version),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambient_core::engine::WorldEngine;
+    use ambient_core::events::{Event, PerformAction};
+
+    #[test]
+    fn test_v1_keeps_original_fields() {
+        let mut engine = WorldEngine::new();
+        engine.apply(Event::Perform(PerformAction::Scene {
+            name: "peaceful".to_string(),
+        }));
+        let snapshot = engine.get_snapshot();
+
+        let v1 = serde_json::to_value(WireSnapshot::new(&snapshot, SchemaVersion::V1)).unwrap();
+        assert_eq!(v1.as_object().unwrap().len(), 9);
+        for added in ["sim_time_secs", "run_state", "time_scale"] {
+            assert!(v1.get(added).is_none(), "{} leaked into 1.0", added);
+        }
+        assert_eq!(v1["scene"]["name"], "peaceful");
+        assert!(v1["scene"].get("transition_curve").is_none());
+
+        let v2 = serde_json::to_value(WireSnapshot::new(&snapshot, SchemaVersion::V2)).unwrap();
+        assert_eq!(v2["run_state"], "running");
+        assert_eq!(v2["scene"]["transition_curve"], "smoothstep");
+        assert_eq!(v2["pulse"]["beats_per_bar"], 4);
+        assert_eq!(v2["harmony"]["mode"], "dorian");
+        assert_eq!(v2["harmony"]["degree"], 1);
+        assert_eq!(v2["mood"], "serene");
+        assert_eq!(v2["agents"], serde_json::json!([]));
+        assert_eq!(v2["space"], 0.5);
+        assert_eq!(v2["clarity"], 0.5);
+    }
+
+    #[test]
+    fn test_wire_snapshots_decode_as_world_snapshot() {
+        let mut engine = WorldEngine::new();
+        engine.apply(Event::Perform(PerformAction::Scene {
+            name: "peaceful".to_string(),
+        }));
+        let snapshot = engine.get_snapshot();
+
+        // Rust clients can read any schema with the shared type
+        let v2 = serde_json::to_string(&WireSnapshot::new(&snapshot, SchemaVersion::V2)).unwrap();
+        assert_eq!(
+            serde_json::from_str::<WorldSnapshot>(&v2).unwrap(),
+            snapshot
+        );
+        let v1 = serde_json::to_string(&WireSnapshot::new(&snapshot, SchemaVersion::V1)).unwrap();
+        let decoded: WorldSnapshot = serde_json::from_str(&v1).unwrap();
+        assert_eq!(decoded.density(), snapshot.density());
+        assert_eq!(decoded.scene().map(|s| s.sequence), Some(1));
+
+        // A 2.0 snapshot from a server that predates the later fields still reads
+        let mut early =
+            serde_json::to_value(WireSnapshot::new(&snapshot, SchemaVersion::V2)).unwrap();
+        for added in ["space", "clarity", "pulse", "harmony", "mood", "agents"] {
+            early.as_object_mut().unwrap().remove(added);
+        }
+        let decoded: WorldSnapshot = serde_json::from_value(early).unwrap();
+        assert_eq!(decoded.time_scale(), snapshot.time_scale());
+        assert_eq!(decoded.space(), 0.5);
+        assert_eq!(decoded.harmony(), Default::default());
+    }
+
+    #[test]
+    fn test_schema_versions_round_trip() {
+        for version in [SchemaVersion::V1, SchemaVersion::V2] {
+            assert_eq!(SchemaVersion::parse(version.as_str()), Some(version));
+            assert!(SUPPORTED_VERSIONS.contains(&version.as_str()));
+        }
+        assert_eq!(SchemaVersion::parse("3.0"), None);
+    }
+}
@@ -0,0 +1,291 @@
+//! Seasonal profiles for installations that run all year.
+//!
+//! With `seasons.enabled`, the [`Seasons`] modulator bends the world toward
+//! the season of the calendar: each `[[seasons.calendar]]` entry names a
+//! profile and the date (`MM-DD`) it is fully in, and the world crossfades
+//! into the next over the `fade_days` before its date. Profiles are the
+//! built-in winter, spring, summer and autumn or ones defined under
+//! `[seasons.profiles.<name>]`, which may also replace a built-in.
+//!
+//! The day of the year is always today's date on the wall clock, moved by
+//! the configured `offset_days`: simulated time (pauses, `time_scale`,
+//! manual steps) never moves it, and nothing has to be saved for a restart
+//! to pick up where the calendar stands.
+
+use crate::runtime::unix_time_ms;
+use ambient_core::engine::WorldEngine;
+use ambient_core::seasons::{Season, SeasonClock, SeasonProfile, Seasons, YEAR_DAYS, day_of_year};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::info;
+
+/// How often the day is set from the wall clock.
+const SYNC_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeasonsConfig {
+    pub enabled: bool,
+    /// Days the crossfade into each season takes, ending on its date.
+    pub fade_days: f64,
+    /// Seasons and the dates they are fully in.
+    pub calendar: Vec<SeasonDate>,
+    /// Profiles by name, added to or replacing the built-in ones.
+    pub profiles: BTreeMap<String, SeasonProfile>,
+    /// Days added to today's date, as for a southern-hemisphere site
+    /// (about 182) or to preview a season.
+    pub offset_days: f64,
+}
+
+/// One `[[seasons.calendar]]` entry.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeasonDate {
+    /// Month and day, `MM-DD`.
+    pub date: String,
+    pub profile: String,
+}
+
+impl Default for SeasonsConfig {
+    fn default() -> Self {
+        let calendar = [
+            ("01-15", "winter"),
+            ("04-15", "spring"),
+            ("07-15", "summer"),
+            ("10-15", "autumn"),
+        ]
+        .into_iter()
+        .map(|(date, profile)| SeasonDate {
+            date: date.to_string(),
+            profile: profile.to_string(),
+        })
+        .collect();
+        Self {
+            enabled: false,
+            fade_days: 45.0,
+            calendar,
+            profiles: BTreeMap::new(),
+            offset_days: 0.0,
+        }
+    }
+}
+
+impl SeasonsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=YEAR_DAYS).contains(&self.fade_days) {
+            return Err(format!(
+                "seasons.fade_days must be in [0, {}], got {}",
+                YEAR_DAYS, self.fade_days
+            ));
+        }
+        if !(-YEAR_DAYS..=YEAR_DAYS).contains(&self.offset_days) {
+            return Err(format!(
+                "seasons.offset_days must be in [-{}, {}], got {}",
+                YEAR_DAYS, YEAR_DAYS, self.offset_days
+            ));
+        }
+        for (name, profile) in &self.profiles {
+            validate_profile(name, profile)?;
+        }
+        let calendar = self.seasons()?;
+        if calendar.is_empty() {
+            return Err("seasons.calendar needs at least one season".to_string());
+        }
+        for (i, season) in calendar.iter().enumerate() {
+            if calendar[..i].iter().any(|s| s.day == season.day) {
+                return Err(format!(
+                    "seasons.calendar[{}] reuses the date '{}'",
+                    i, self.calendar[i].date
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The calendar with its dates and profiles resolved.
+    pub fn seasons(&self) -> Result<Vec<Season>, String> {
+        self.calendar
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let day = parse_date(&entry.date).ok_or_else(|| {
+                    format!(
+                        "seasons.calendar[{}].date must be a date as MM-DD, got '{}'",
+                        i, entry.date
+                    )
+                })?;
+                let profile = self
+                    .profiles
+                    .get(&entry.profile)
+                    .copied()
+                    .or_else(|| SeasonProfile::builtin(&entry.profile))
+                    .ok_or_else(|| {
+                        format!(
+                            "seasons.calendar[{}].profile '{}' is neither built in nor in seasons.profiles",
+                            i, entry.profile
+                        )
+                    })?;
+                Ok(Season {
+                    name: entry.profile.clone(),
+                    day,
+                    profile,
+                })
+            })
+            .collect()
+    }
+}
+
+fn validate_profile(name: &str, profile: &SeasonProfile) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&profile.warmth_ceiling) {
+        return Err(format!(
+            "seasons.profiles.{}.warmth_ceiling must be in [0, 1], got {}",
+            name, profile.warmth_ceiling
+        ));
+    }
+    let shifts = [
+        ("density", profile.density),
+        ("energy", profile.energy),
+        ("warmth", profile.warmth),
+        ("space", profile.space),
+        ("clarity", profile.clarity),
+    ];
+    for (field, shift) in shifts {
+        if !(-1.0..=1.0).contains(&shift) {
+            return Err(format!(
+                "seasons.profiles.{}.{} must be in [-1, 1], got {}",
+                name, field, shift
+            ));
+        }
+    }
+    if !(0.0..=10.0).contains(&profile.sparkle_rate) {
+        return Err(format!(
+            "seasons.profiles.{}.sparkle_rate must be in [0, 10], got {}",
+            name, profile.sparkle_rate
+        ));
+    }
+    Ok(())
+}
+
+/// Parses `MM-DD` into a day of the seasonal year.
+fn parse_date(date: &str) -> Option<f64> {
+    let (month, day) = date.split_once('-')?;
+    if month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    day_of_year(month.parse().ok()?, day.parse().ok()?)
+}
+
+/// The day of the seasonal year at `timestamp_ms`, with the time of day as
+/// its fraction.
+pub fn calendar_day(timestamp_ms: u64) -> f64 {
+    let Ok(time) = time::OffsetDateTime::from_unix_timestamp((timestamp_ms / 1000) as i64) else {
+        return 0.0;
+    };
+    let day = day_of_year(u8::from(time.month()).into(), time.day().into()).unwrap_or(0.0);
+    let (hours, minutes, seconds) = time.to_hms();
+    let secs = f64::from(hours) * 3600.0 + f64::from(minutes) * 60.0 + f64::from(seconds);
+    day + secs / 86_400.0
+}
+
+/// The day of the seasonal year at `timestamp_ms`, moved by `offset_days`.
+pub fn seasonal_day(timestamp_ms: u64, offset_days: f64) -> f64 {
+    (calendar_day(timestamp_ms) + offset_days).rem_euclid(YEAR_DAYS)
+}
+
+/// Adds the seasons modulator at today's date and keeps its clock on the
+/// date while the app runs. Does nothing when seasons are off.
+pub fn start_seasons(config: &SeasonsConfig, engine: &mut WorldEngine) -> Result<(), String> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let offset = config.offset_days;
+    let clock = SeasonClock::new(seasonal_day(unix_time_ms(), offset));
+    let seasons = Seasons::new(config.seasons()?, config.fade_days, clock.clone());
+    info!(
+        "Seasons: day {:.1} of the year, {}",
+        clock.day(),
+        seasons.season()
+    );
+    engine.add_modulator(seasons);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            interval.tick().await;
+            clock.set(seasonal_day(unix_time_ms(), offset));
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_seasons_section() {
+        let config: Config = toml::from_str(
+            "[seasons]
+enabled = true
+fade_days = 20.0
+calendar = [
+    { date = \"06-01\", profile = \"monsoon\" },
+    { date = \"12-01\", profile = \"winter\" },
+]
+
+[seasons.profiles.monsoon]
+warmth = 0.1
+sparkle_rate = 2.0
+",
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        let seasons = config.seasons.seasons().unwrap();
+        assert_eq!(seasons[0].day, 151.0);
+        assert_eq!(seasons[0].profile.sparkle_rate, 2.0);
+        assert_eq!(seasons[0].profile.warmth_ceiling, 1.0);
+        assert_eq!(
+            seasons[1].profile,
+            SeasonProfile::builtin("winter").unwrap()
+        );
+
+        let mut seasons = config.seasons;
+        seasons.calendar[1].date = "06-01".to_string();
+        assert!(seasons.validate().unwrap_err().contains("reuses the date"));
+        seasons.calendar[1].date = "6-1".to_string();
+        assert!(seasons.validate().unwrap_err().contains("MM-DD"));
+        seasons.calendar[1].date = "12-01".to_string();
+        seasons.calendar[1].profile = "monsoon2".to_string();
+        assert!(seasons.validate().unwrap_err().contains("monsoon2"));
+        seasons.calendar.clear();
+        assert!(seasons.validate().is_err());
+        seasons.profiles.get_mut("monsoon").unwrap().sparkle_rate = -1.0;
+        assert!(seasons.validate().unwrap_err().contains("sparkle_rate"));
+    }
+
+    #[test]
+    fn test_calendar_day() {
+        // 2024-03-01T12:00:00Z, after a leap day
+        assert_eq!(calendar_day(1_709_294_400_000), 59.5);
+        // 2025-01-01T00:00:00Z
+        assert_eq!(calendar_day(1_735_689_600_000), 0.0);
+    }
+
+    #[test]
+    fn test_seasonal_day_follows_the_calendar_with_an_offset() {
+        // 2025-01-01T00:00:00Z
+        let new_year = 1_735_689_600_000;
+        assert_eq!(seasonal_day(new_year, 0.0), 0.0);
+        assert_eq!(seasonal_day(new_year, 182.0), 182.0);
+        assert_eq!(seasonal_day(new_year, -1.0), YEAR_DAYS - 1.0);
+
+        let mut seasons = SeasonsConfig {
+            offset_days: 400.0,
+            ..SeasonsConfig::default()
+        };
+        assert!(seasons.validate().unwrap_err().contains("offset_days"));
+        seasons.offset_days = -182.5;
+        assert!(seasons.validate().is_ok());
+    }
+}
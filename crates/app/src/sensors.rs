@@ -0,0 +1,476 @@
+//! Physical inputs that act on the world, so visitors walking by stir it.
+//!
+//! Each configured sensor (`[[sensors]]`) is polled by its own task. A
+//! reading is scaled from the sensor's `range` onto 0-1; at or above
+//! `threshold` it fires the sensor's action with intensity `level * gain`, at
+//! most once per `debounce_ms`. A reading held high fires again each time the
+//! debounce runs out.
+//!
+//! Sources:
+//! - `gpio`: a Linux sysfs GPIO line (`/sys/class/gpio/gpio<pin>/value`,
+//!   exported beforehand), e.g. a PIR motion sensor
+//! - `mic`: the microphone's peak level (0-1) from an envelope follower
+//! - `serial`: the latest number printed by a serial device, one per line;
+//!   set the port up first (`stty -F /dev/ttyUSB0 115200 raw`)
+
+use crate::api::{SubmitError, apply_event};
+use crate::runtime::{EventQueueStats, QueuedEvent};
+use ambient_core::events::{Event, EventSource, PerformAction};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+/// Wait before reopening a serial device that closed or failed.
+const SERIAL_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// One `[[sensors]]` entry.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SensorConfig {
+    /// Label recorded as the event source in the audit log.
+    pub name: String,
+    pub source: SensorSource,
+    pub action: SensorAction,
+    /// Raw readings mapped onto 0 and 1. Reverse it for active-low inputs.
+    #[serde(default = "default_range")]
+    pub range: [f64; 2],
+    /// Scaled level (0-1) at or above which the action fires.
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    /// Intensity per unit of scaled level, clamped to 1.
+    #[serde(default = "default_gain")]
+    pub gain: f64,
+    /// Shortest time between two firings.
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+    #[serde(default = "default_poll_hz")]
+    pub poll_hz: f64,
+}
+
+/// Where a sensor's readings come from.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SensorSource {
+    Gpio {
+        pin: u32,
+        /// Value file to read instead of the sysfs one for `pin`.
+        path: Option<PathBuf>,
+    },
+    Mic {
+        /// Input device name; the default input when unset.
+        device: Option<String>,
+    },
+    Serial {
+        path: PathBuf,
+    },
+}
+
+impl SensorSource {
+    fn name(&self) -> &'static str {
+        match self {
+            SensorSource::Gpio { .. } => "gpio",
+            SensorSource::Mic { .. } => "mic",
+            SensorSource::Serial { .. } => "serial",
+        }
+    }
+}
+
+/// Perform action a sensor fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorAction {
+    Pulse,
+    Stir,
+    Calm,
+    Heat,
+    Tense,
+    Expand,
+    Contract,
+    Clarify,
+    Roughen,
+}
+
+impl SensorAction {
+    pub fn with_intensity(self, intensity: f64) -> PerformAction {
+        match self {
+            SensorAction::Pulse => PerformAction::Pulse { intensity },
+            SensorAction::Stir => PerformAction::Stir { intensity },
+            SensorAction::Calm => PerformAction::Calm { intensity },
+            SensorAction::Heat => PerformAction::Heat { intensity },
+            SensorAction::Tense => PerformAction::Tense { intensity },
+            SensorAction::Expand => PerformAction::Expand { intensity },
+            SensorAction::Contract => PerformAction::Contract { intensity },
+            SensorAction::Clarify => PerformAction::Clarify { intensity },
+            SensorAction::Roughen => PerformAction::Roughen { intensity },
+        }
+    }
+}
+
+fn default_range() -> [f64; 2] {
+    [0.0, 1.0]
+}
+
+fn default_threshold() -> f64 {
+    0.5
+}
+
+fn default_gain() -> f64 {
+    1.0
+}
+
+fn default_debounce_ms() -> u64 {
+    2000
+}
+
+fn default_poll_hz() -> f64 {
+    20.0
+}
+
+impl SensorConfig {
+    /// Checks the ranges, naming the entry by its index.
+    pub fn validate(&self, index: usize) -> Result<(), String> {
+        let key = format!("sensors[{}]", index);
+        if self.name.trim().is_empty() {
+            return Err(format!("{} needs a non-empty name", key));
+        }
+        let [low, high] = self.range;
+        if !(low.is_finite() && high.is_finite() && low != high) {
+            return Err(format!(
+                "{}.range must be two different finite numbers, got [{}, {}]",
+                key, low, high
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.threshold) {
+            return Err(format!(
+                "{}.threshold must be in [0, 1], got {}",
+                key, self.threshold
+            ));
+        }
+        if !(0.0..=10.0).contains(&self.gain) {
+            return Err(format!(
+                "{}.gain must be in [0, 10], got {}",
+                key, self.gain
+            ));
+        }
+        if !(self.poll_hz > 0.0 && self.poll_hz <= 1000.0) {
+            return Err(format!(
+                "{}.poll_hz must be in (0, 1000], got {}",
+                key, self.poll_hz
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Turns raw readings into debounced actions.
+#[derive(Debug, Clone)]
+pub struct SensorMapper {
+    range: [f64; 2],
+    threshold: f64,
+    gain: f64,
+    debounce: Duration,
+    action: SensorAction,
+    last_fired: Option<Instant>,
+}
+
+impl SensorMapper {
+    pub fn new(config: &SensorConfig) -> Self {
+        Self {
+            range: config.range,
+            threshold: config.threshold,
+            gain: config.gain,
+            debounce: Duration::from_millis(config.debounce_ms),
+            action: config.action,
+            last_fired: None,
+        }
+    }
+
+    /// Scales a raw reading onto 0-1 by the configured range.
+    pub fn level(&self, raw: f64) -> f64 {
+        let [low, high] = self.range;
+        ((raw - low) / (high - low)).clamp(0.0, 1.0)
+    }
+
+    /// The action to fire for a reading taken at `now`, if any.
+    pub fn update(&mut self, raw: f64, now: Instant) -> Option<PerformAction> {
+        let level = self.level(raw);
+        if level < self.threshold {
+            return None;
+        }
+        if self
+            .last_fired
+            .is_some_and(|last| now.duration_since(last) < self.debounce)
+        {
+            return None;
+        }
+        self.last_fired = Some(now);
+        Some(self.action.with_intensity((level * self.gain).min(1.0)))
+    }
+}
+
+/// Where the polling loop gets its latest reading.
+enum Reader {
+    File(PathBuf),
+    Latest(watch::Receiver<Option<f64>>),
+    /// Holds the capture open for as long as the sensor polls it.
+    #[cfg(feature = "audio-output")]
+    Mic {
+        meter: Arc<audio::input::InputMeter>,
+        _input: audio::input::MicInput,
+    },
+}
+
+impl Reader {
+    async fn read(&mut self) -> Result<Option<f64>, String> {
+        match self {
+            Reader::File(path) => {
+                let text = tokio::fs::read_to_string(&path)
+                    .await
+                    .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+                parse_reading(&text).map(Some)
+            }
+            Reader::Latest(latest) => Ok(*latest.borrow()),
+            #[cfg(feature = "audio-output")]
+            Reader::Mic { meter, .. } => Ok(Some(meter.peak() as f64)),
+        }
+    }
+}
+
+/// Parses the first whitespace-separated token of a reading as a number.
+pub fn parse_reading(text: &str) -> Result<f64, String> {
+    let token = text.split_whitespace().next().unwrap_or("");
+    token
+        .parse()
+        .map_err(|_| format!("expected a number, got '{}'", text.trim()))
+}
+
+/// Starts a task per configured sensor. Sensors that cannot start (a mic in
+/// a build without audio, or no input device) are logged and skipped.
+pub fn start_sensors(
+    sensors: &[SensorConfig],
+    event_tx: &mpsc::Sender<QueuedEvent>,
+    event_queue: &Arc<EventQueueStats>,
+) {
+    for config in sensors {
+        let reader = match open_reader(config) {
+            Ok(reader) => reader,
+            Err(e) => {
+                warn!("Sensor '{}' disabled: {}", config.name, e);
+                continue;
+            }
+        };
+        info!(
+            "Sensor '{}' ({}) fires {:?} at {} Hz",
+            config.name,
+            config.source.name(),
+            config.action,
+            config.poll_hz
+        );
+        tokio::spawn(start_sensor_task(
+            config.clone(),
+            reader,
+            event_tx.clone(),
+            Arc::clone(event_queue),
+        ));
+    }
+}
+
+fn open_reader(config: &SensorConfig) -> Result<Reader, String> {
+    match &config.source {
+        SensorSource::Gpio { pin, path } => {
+            Ok(Reader::File(path.clone().unwrap_or_else(|| {
+                format!("/sys/class/gpio/gpio{}/value", pin).into()
+            })))
+        }
+        SensorSource::Serial { path } => {
+            let (latest_tx, latest_rx) = watch::channel(None);
+            tokio::spawn(read_serial(config.name.clone(), path.clone(), latest_tx));
+            Ok(Reader::Latest(latest_rx))
+        }
+        #[cfg(feature = "audio-output")]
+        SensorSource::Mic { device } => {
+            let meter = Arc::new(audio::input::InputMeter::new());
+            let input = audio::input::MicInput::spawn(device.clone(), Arc::clone(&meter))
+                .map_err(|e| e.to_string())?;
+            Ok(Reader::Mic {
+                meter,
+                _input: input,
+            })
+        }
+        #[cfg(not(feature = "audio-output"))]
+        SensorSource::Mic { .. } => Err("built without the audio-output feature".to_string()),
+    }
+}
+
+/// Polls one sensor and submits the actions it fires.
+async fn start_sensor_task(
+    config: SensorConfig,
+    mut reader: Reader,
+    event_tx: mpsc::Sender<QueuedEvent>,
+    event_queue: Arc<EventQueueStats>,
+) {
+    let mut mapper = SensorMapper::new(&config);
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / config.poll_hz));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut failing = false;
+    loop {
+        interval.tick().await;
+        let raw = match reader.read().await {
+            Ok(Some(raw)) => {
+                if failing {
+                    info!("Sensor '{}' readable again", config.name);
+                    failing = false;
+                }
+                raw
+            }
+            Ok(None) => continue,
+            Err(e) => {
+                // Warn once per outage rather than at the poll rate
+                if !failing {
+                    warn!("Sensor '{}': {}", config.name, e);
+                    failing = true;
+                }
+                continue;
+            }
+        };
+        let Some(action) = mapper.update(raw, Instant::now()) else {
+            continue;
+        };
+        let event = Event::Perform(action).with_source(EventSource::Sensor(config.name.clone()));
+        match apply_event(&event_tx, &event_queue, event).await {
+            Ok(result) => debug!("Sensor '{}' fired: {}", config.name, result.applied),
+            Err(SubmitError::Full) => {
+                warn!("Event queue full, dropped sensor '{}'", config.name)
+            }
+            Err(SubmitError::Closed) => break,
+        }
+    }
+}
+
+/// Keeps `latest` at the last number read from a serial device, reopening
+/// it whenever it closes or fails.
+async fn read_serial(name: String, path: PathBuf, latest: watch::Sender<Option<f64>>) {
+    loop {
+        match tokio::fs::File::open(&path).await {
+            Ok(file) => {
+                let mut lines = BufReader::new(file).lines();
+                loop {
+                    match lines.next_line().await {
+                        Ok(Some(line)) => match parse_reading(&line) {
+                            Ok(value) => {
+                                latest.send_replace(Some(value));
+                            }
+                            Err(e) => debug!("Sensor '{}' skipped a line: {}", name, e),
+                        },
+                        Ok(None) => {
+                            warn!("Sensor '{}': {} closed", name, path.display());
+                            break;
+                        }
+                        Err(e) => {
+                            warn!(
+                                "Sensor '{}': reading {} failed: {}",
+                                name,
+                                path.display(),
+                                e
+                            );
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => warn!(
+                "Sensor '{}': failed to open {}: {}",
+                name,
+                path.display(),
+                e
+            ),
+        }
+        if latest.is_closed() {
+            return;
+        }
+        // Stale readings must not keep firing while the device is away
+        latest.send_replace(None);
+        tokio::time::sleep(SERIAL_RETRY_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn parse(text: &str) -> SensorConfig {
+        toml::from_str::<Config>(text).unwrap().sensors.remove(0)
+    }
+
+    #[test]
+    fn test_sensor_config() {
+        let config = parse(
+            "[[sensors]]\nname = \"hall\"\nsource = { kind = \"gpio\", pin = 17 }\naction = \"stir\"\n",
+        );
+        assert_eq!(
+            config.source,
+            SensorSource::Gpio {
+                pin: 17,
+                path: None
+            }
+        );
+        assert_eq!(config.range, [0.0, 1.0]);
+        assert_eq!(config.debounce_ms, 2000);
+        assert!(config.validate(0).is_ok());
+
+        let config = parse(
+            "[[sensors]]\nname = \"room\"\nsource = { kind = \"mic\" }\naction = \"pulse\"\nrange = [0.02, 0.3]\nthreshold = 1.5\n",
+        );
+        assert_eq!(config.source, SensorSource::Mic { device: None });
+        assert!(
+            config
+                .validate(2)
+                .unwrap_err()
+                .contains("sensors[2].threshold")
+        );
+    }
+
+    #[test]
+    fn test_mapper_scales_thresholds_and_debounces() {
+        let mut config = parse(
+            "[[sensors]]\nname = \"door\"\nsource = { kind = \"serial\", path = \"/dev/ttyUSB0\" }\naction = \"pulse\"\nrange = [100, 500]\nthreshold = 0.25\ngain = 0.5\ndebounce_ms = 1000\n",
+        );
+        let start = Instant::now();
+        let mut mapper = SensorMapper::new(&config);
+        assert_eq!(mapper.level(300.0), 0.5);
+        assert_eq!(mapper.update(150.0, start), None);
+        assert_eq!(
+            mapper.update(500.0, start),
+            Some(PerformAction::Pulse { intensity: 0.5 })
+        );
+        // Held high: quiet until the debounce runs out
+        assert_eq!(
+            mapper.update(500.0, start + Duration::from_millis(999)),
+            None
+        );
+        assert!(
+            mapper
+                .update(500.0, start + Duration::from_millis(1000))
+                .is_some()
+        );
+
+        // An active-low line, reversed
+        config.range = [1.0, 0.0];
+        let mut mapper = SensorMapper::new(&config);
+        assert_eq!(mapper.update(1.0, start), None);
+        assert!(mapper.update(0.0, start).is_some());
+    }
+
+    #[test]
+    fn test_parse_reading() {
+        assert_eq!(parse_reading("1\n"), Ok(1.0));
+        assert_eq!(parse_reading(" 0.42 lux"), Ok(0.42));
+        assert!(parse_reading("").is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! Rolling statistics over recent world history, served by `GET /state/stats`.
+//!
+//! The world task folds every snapshot it publishes into one-minute buckets
+//! and keeps a day of them, so a summary over any window up to 24 hours costs
+//! at most 1440 bucket merges rather than a replay of the snapshot stream.
+
+use ambient_core::events::{Event, PerformAction, TriggerKind};
+use ambient_core::world::WorldSnapshot;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// Width of one bucket; windows are rounded up to whole buckets.
+const BUCKET_MS: u64 = 60_000;
+
+/// Longest window kept and summarized.
+pub const MAX_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Parameters summarized, in snapshot order.
+const PARAMETERS: [&str; 7] = [
+    "density", "rhythm", "tension", "energy", "warmth", "space", "clarity",
+];
+
+/// Running min/max/mean/variance of one parameter.
+#[derive(Debug, Clone, Copy)]
+struct Accumulator {
+    count: u64,
+    sum: f64,
+    sum_sq: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            sum: 0.0,
+            sum_sq: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Accumulator {
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.sum_sq += value * value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn merge(&mut self, other: &Accumulator) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.sum_sq += other.sum_sq;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    fn summary(&self) -> Option<ParamStats> {
+        if self.count == 0 {
+            return None;
+        }
+        let n = self.count as f64;
+        let mean = self.sum / n;
+        // Parameters live in [0, 1], so the sum-of-squares form is precise enough
+        let variance = (self.sum_sq / n - mean * mean).max(0.0);
+        Some(ParamStats {
+            min: self.min,
+            max: self.max,
+            mean,
+            stddev: variance.sqrt(),
+        })
+    }
+}
+
+/// One minute of history.
+#[derive(Debug)]
+struct Bucket {
+    minute: u64,
+    params: [Accumulator; 7],
+    events: BTreeMap<&'static str, u64>,
+}
+
+/// Summary of one parameter over a window.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct ParamStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+/// Response of `GET /state/stats`.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct StatsSummary {
+    pub window_secs: u64,
+    /// Snapshots the parameter stats were computed from.
+    pub samples: u64,
+    /// Per-parameter stats, empty when the window holds no samples.
+    pub parameters: BTreeMap<&'static str, ParamStats>,
+    /// Non-tick events by action, triggers and performs counted together.
+    pub events: BTreeMap<&'static str, u64>,
+}
+
+/// One-minute buckets of snapshot samples and event counts, newest last.
+#[derive(Debug, Default)]
+pub struct StatsHistory {
+    buckets: VecDeque<Bucket>,
+}
+
+/// Parses a window such as `90s`, `30m` or `2h` (1 minute to 24 hours).
+pub fn parse_window(window: &str) -> Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "window must be a number followed by s, m or h (1m to 24h), got '{}'",
+            window
+        )
+    };
+    let split = window.len().saturating_sub(1);
+    let (number, unit) = window.split_at_checked(split).ok_or_else(invalid)?;
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let secs = match unit {
+        "s" => number,
+        "m" => number.saturating_mul(60),
+        "h" => number.saturating_mul(3600),
+        _ => return Err(invalid()),
+    };
+    let duration = Duration::from_secs(secs);
+    if duration < Duration::from_secs(60) || duration > MAX_WINDOW {
+        return Err(invalid());
+    }
+    Ok(duration)
+}
+
+/// Counter key for a non-tick event.
+fn event_kind(event: &Event) -> Option<&'static str> {
+    let kind = match event {
+        Event::Tick { .. } => return None,
+        Event::Trigger { kind, .. } => match kind {
+            TriggerKind::Pulse => "pulse",
+            TriggerKind::Stir => "stir",
+            TriggerKind::Calm => "calm",
+            TriggerKind::Heat => "heat",
+            TriggerKind::Tense => "tense",
+            TriggerKind::Expand => "expand",
+            TriggerKind::Contract => "contract",
+            TriggerKind::Clarify => "clarify",
+            TriggerKind::Roughen => "roughen",
+        },
+        Event::Perform(action) => match action {
+            PerformAction::Pulse { .. } => "pulse",
+            PerformAction::Stir { .. } => "stir",
+            PerformAction::Calm { .. } => "calm",
+            PerformAction::Heat { .. } => "heat",
+            PerformAction::Tense { .. } => "tense",
+            PerformAction::Expand { .. } => "expand",
+            PerformAction::Contract { .. } => "contract",
+            PerformAction::Clarify { .. } => "clarify",
+            PerformAction::Roughen { .. } => "roughen",
+            PerformAction::Scene { .. } => "scene",
+            PerformAction::Freeze { .. } => "freeze",
+            PerformAction::Undo => "undo",
+            PerformAction::PulseAt { .. } => "pulse_at",
+        },
+    };
+    Some(kind)
+}
+
+impl StatsHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a published snapshot, stamped with its wall-clock time.
+    pub fn record(&mut self, snapshot: &WorldSnapshot) {
+        let values = [
+            snapshot.density(),
+            snapshot.rhythm(),
+            snapshot.tension(),
+            snapshot.energy(),
+            snapshot.warmth(),
+            snapshot.space(),
+            snapshot.clarity(),
+        ];
+        let bucket = self.bucket(snapshot.timestamp_ms());
+        for (acc, value) in bucket.params.iter_mut().zip(values) {
+            acc.add(value);
+        }
+    }
+
+    /// Counts a non-tick event applied at `timestamp_ms`.
+    pub fn count_event(&mut self, event: &Event, timestamp_ms: u64) {
+        if let Some(kind) = event_kind(event) {
+            *self.bucket(timestamp_ms).events.entry(kind).or_default() += 1;
+        }
+    }
+
+    /// Summarizes the buckets overlapping the `window` ending at `now_ms`.
+    pub fn summary(&self, window: Duration, now_ms: u64) -> StatsSummary {
+        let minutes = (window.as_millis() as u64).div_ceil(BUCKET_MS);
+        let first = (now_ms / BUCKET_MS).saturating_sub(minutes.saturating_sub(1));
+        let mut params = [Accumulator::default(); 7];
+        let mut events = BTreeMap::new();
+        for bucket in self.buckets.iter().rev() {
+            if bucket.minute < first {
+                break;
+            }
+            for (total, acc) in params.iter_mut().zip(&bucket.params) {
+                total.merge(acc);
+            }
+            for (kind, count) in &bucket.events {
+                *events.entry(*kind).or_default() += count;
+            }
+        }
+        StatsSummary {
+            window_secs: window.as_secs(),
+            samples: params[0].count,
+            parameters: PARAMETERS
+                .iter()
+                .zip(&params)
+                .filter_map(|(name, acc)| Some((*name, acc.summary()?)))
+                .collect(),
+            events,
+        }
+    }
+
+    /// The bucket for `timestamp_ms`, starting a new one and dropping those
+    /// older than `MAX_WINDOW` when the minute rolls over. Samples from a
+    /// clock that stepped backwards land in the newest bucket.
+    fn bucket(&mut self, timestamp_ms: u64) -> &mut Bucket {
+        let minute = timestamp_ms / BUCKET_MS;
+        if self.buckets.back().is_none_or(|last| last.minute < minute) {
+            let keep_from = minute.saturating_sub(MAX_WINDOW.as_millis() as u64 / BUCKET_MS);
+            while self
+                .buckets
+                .front()
+                .is_some_and(|first| first.minute < keep_from)
+            {
+                self.buckets.pop_front();
+            }
+            self.buckets.push_back(Bucket {
+                minute,
+                params: [Accumulator::default(); 7],
+                events: BTreeMap::new(),
+            });
+        }
+        self.buckets.back_mut().expect("bucket just ensured")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambient_core::engine::WorldEngine;
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_window("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_window("24h"), Ok(MAX_WINDOW));
+        assert!(parse_window("30s").is_err());
+        assert!(parse_window("25h").is_err());
+        assert!(parse_window("h").is_err());
+        assert!(parse_window("").is_err());
+        assert!(parse_window("5d").is_err());
+    }
+
+    #[test]
+    fn test_summary_over_window() {
+        let engine = WorldEngine::new();
+        let mut history = StatsHistory::new();
+        let minute = 60_000;
+        // An old sample outside a 2-minute window, then two inside it
+        history.record(&engine.get_snapshot().with_timestamp_ms(0));
+        history.record(&engine.get_snapshot().with_timestamp_ms(5 * minute));
+        history.record(&engine.get_snapshot().with_timestamp_ms(6 * minute));
+        history.count_event(
+            &Event::Perform(PerformAction::Pulse { intensity: 0.5 }),
+            6 * minute,
+        );
+        history.count_event(&Event::Tick { dt: 0.05 }, 6 * minute);
+
+        let summary = history.summary(Duration::from_secs(120), 6 * minute + 1);
+        assert_eq!(summary.samples, 2);
+        let density = summary.parameters["density"];
+        assert_eq!((density.min, density.max), (0.5, 0.5));
+        assert!((density.mean - 0.5).abs() < 1e-12);
+        assert!(density.stddev < 1e-6);
+        assert_eq!(summary.events.get("pulse"), Some(&1));
+        assert_eq!(summary.events.len(), 1);
+
+        let summary = history.summary(Duration::from_secs(3600), 6 * minute);
+        assert_eq!(summary.samples, 3);
+    }
+
+    #[test]
+    fn test_old_buckets_are_dropped() {
+        let engine = WorldEngine::new();
+        let mut history = StatsHistory::new();
+        history.record(&engine.get_snapshot().with_timestamp_ms(0));
+        let day_later = MAX_WINDOW.as_millis() as u64 + 2 * BUCKET_MS;
+        history.record(&engine.get_snapshot().with_timestamp_ms(day_later));
+        assert_eq!(history.buckets.len(), 1);
+
+        let empty = history.summary(Duration::from_secs(60), day_later + 10 * BUCKET_MS);
+        assert_eq!(empty.samples, 0);
+        assert!(empty.parameters.is_empty());
+    }
+}
@@ -0,0 +1,309 @@
+//! The output as a live Ogg/Opus stream at `GET /stream.ogg`.
+//!
+//! An encoder thread drains the engine's [`OutputTap`], resamples it to
+//! 48 kHz stereo and encodes 20 ms Opus packets, five to an Ogg page. Each
+//! page goes to every listener; a new listener first gets the two header
+//! pages, then joins at the next page, the way an Icecast mount works. The
+//! tap only copies while someone listens, so an idle stream costs nothing on
+//! the audio thread.
+
+use crate::api::AppState;
+use crate::auth::{AuthError, bearer_token};
+use crate::error::ApiError;
+use audio::backend::OutputFormat;
+use audio::tap::OutputTap;
+use audiopus::coder::Encoder;
+use audiopus::{Application, Bitrate, Channels, SampleRate};
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::{HeaderMap, header};
+use axum::response::{IntoResponse, Response};
+use futures_util::{Stream, StreamExt, stream};
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+/// Opus' sample rate; the output is resampled to it.
+const RATE: u32 = 48_000;
+
+/// Frames per Opus packet: 20 ms.
+const PACKET_FRAMES: usize = 960;
+
+/// Packets per Ogg page: 100 ms.
+const PAGE_PACKETS: u32 = 5;
+
+/// Pages held for a slow listener before it skips ahead.
+const PAGE_BACKLOG: usize = 64;
+
+/// How often the encoder drains the tap.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The stream's header pages and its live pages, for the HTTP handler.
+#[derive(Clone)]
+pub struct StreamHub {
+    headers: Bytes,
+    pages: broadcast::Sender<Bytes>,
+}
+
+impl StreamHub {
+    fn new(headers: Bytes) -> Self {
+        Self {
+            headers,
+            pages: broadcast::channel(PAGE_BACKLOG).0,
+        }
+    }
+
+    /// The stream from the header pages on. Pages missed while the listener
+    /// lagged are skipped.
+    pub fn listen(&self) -> impl Stream<Item = Result<Bytes, Infallible>> + Send + 'static {
+        let pages = stream::unfold(self.pages.subscribe(), |mut pages_rx| async move {
+            loop {
+                match pages_rx.recv().await {
+                    Ok(page) => return Some((Ok(page), pages_rx)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        stream::iter([Ok(self.headers.clone())]).chain(pages)
+    }
+}
+
+/// Starts the encoder thread on `tap` at `bitrate_kbps`.
+pub fn start_stream(tap: Arc<OutputTap>, bitrate_kbps: u32) -> std::io::Result<StreamHub> {
+    let (mut encoder, headers) = OggOpus::new(bitrate_kbps, rand::random())
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let hub = StreamHub::new(headers);
+    let pages = hub.pages.clone();
+    std::thread::Builder::new()
+        .name("stream".to_string())
+        .spawn(move || {
+            loop {
+                std::thread::sleep(DRAIN_INTERVAL);
+                let listening = pages.receiver_count() > 0;
+                tap.set_listening(listening);
+                let Some((format, samples)) = tap.take() else {
+                    continue;
+                };
+                for page in encoder.encode(format, &samples) {
+                    let _ = pages.send(page);
+                }
+            }
+        })?;
+    info!("Streaming Ogg/Opus at {} kbit/s", bitrate_kbps);
+    Ok(hub)
+}
+
+/// Serves the stream. Like the WebSocket, it needs no token (audio elements
+/// cannot send one), but a bad one is rejected.
+pub async fn stream_handler(headers: HeaderMap, State(state): State<AppState>) -> Response {
+    match state.auth.authenticate(bearer_token(&headers)) {
+        Ok(_) | Err(AuthError::Missing) => {}
+        Err(e) => return e.into_response(),
+    }
+    let Some(hub) = state.stream else {
+        return ApiError::NotFound("Streaming is off".to_string()).into_response();
+    };
+    (
+        [
+            (header::CONTENT_TYPE, "audio/ogg"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        Body::from_stream(hub.listen()),
+    )
+        .into_response()
+}
+
+/// Linear resampling of interleaved output to 48 kHz stereo. Mono is
+/// doubled; channels past the second are dropped.
+struct Resampler {
+    from: u32,
+    /// Position of the next output frame, counting the last frame of the
+    /// previous block as 0.
+    pos: f64,
+    last: [f32; 2],
+}
+
+impl Resampler {
+    fn new() -> Self {
+        Self {
+            from: RATE,
+            pos: 0.0,
+            last: [0.0; 2],
+        }
+    }
+
+    /// Appends `samples` of `format`, resampled, to `out`.
+    fn push(&mut self, format: OutputFormat, samples: &[f32], out: &mut Vec<f32>) {
+        if format.sample_rate != self.from {
+            *self = Self::new();
+            self.from = format.sample_rate;
+        }
+        let channels = usize::from(format.channels.max(1));
+        let frames: Vec<[f32; 2]> = std::iter::once(self.last)
+            .chain(
+                samples
+                    .chunks_exact(channels)
+                    .map(|frame| [frame[0], *frame.get(1).unwrap_or(&frame[0])]),
+            )
+            .collect();
+        let step = self.from as f64 / RATE as f64;
+        while self.pos + 1.0 < frames.len() as f64 {
+            let i = self.pos as usize;
+            let t = (self.pos - i as f64) as f32;
+            let (a, b) = (frames[i], frames[i + 1]);
+            out.push(a[0] + (b[0] - a[0]) * t);
+            out.push(a[1] + (b[1] - a[1]) * t);
+            self.pos += step;
+        }
+        self.pos -= (frames.len() - 1) as f64;
+        self.last = frames[frames.len() - 1];
+    }
+}
+
+/// An Opus encoder writing Ogg pages.
+struct OggOpus {
+    encoder: Encoder,
+    writer: PacketWriter<Vec<u8>>,
+    serial: u32,
+    /// 48 kHz frames encoded so far.
+    granule: u64,
+    packets: u32,
+    resampler: Resampler,
+    /// Resampled stereo not yet a whole packet.
+    pcm: Vec<f32>,
+    packet: Vec<u8>,
+}
+
+impl OggOpus {
+    /// The encoder and its OpusHead and OpusTags header pages.
+    fn new(bitrate_kbps: u32, serial: u32) -> audiopus::Result<(Self, Bytes)> {
+        let mut encoder = Encoder::new(SampleRate::Hz48000, Channels::Stereo, Application::Audio)?;
+        encoder.set_bitrate(Bitrate::BitsPerSecond(bitrate_kbps as i32 * 1000))?;
+        let pre_skip = encoder.lookahead()? as u16;
+
+        let mut head = b"OpusHead".to_vec();
+        head.push(1); // version
+        head.push(2); // channels
+        head.extend(pre_skip.to_le_bytes());
+        head.extend(RATE.to_le_bytes());
+        head.extend(0i16.to_le_bytes()); // output gain
+        head.push(0); // mapping family: mono or stereo
+        let vendor = b"ambient";
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend((vendor.len() as u32).to_le_bytes());
+        tags.extend(vendor);
+        tags.extend(0u32.to_le_bytes()); // no comments
+
+        let mut writer = PacketWriter::new(Vec::new());
+        for header in [head, tags] {
+            writer
+                .write_packet(header.into(), serial, PacketWriteEndInfo::EndPage, 0)
+                .expect("writes to memory");
+        }
+        let headers = Bytes::from(std::mem::take(writer.inner_mut()));
+        let ogg = Self {
+            encoder,
+            writer,
+            serial,
+            granule: 0,
+            packets: 0,
+            resampler: Resampler::new(),
+            pcm: Vec::new(),
+            packet: vec![0; 4000],
+        };
+        Ok((ogg, headers))
+    }
+
+    /// Encodes `samples` of `format`, returning the pages completed.
+    fn encode(&mut self, format: OutputFormat, samples: &[f32]) -> Vec<Bytes> {
+        self.resampler.push(format, samples, &mut self.pcm);
+        let mut pages = Vec::new();
+        let whole = self.pcm.len() / (PACKET_FRAMES * 2) * PACKET_FRAMES * 2;
+        let pcm = std::mem::take(&mut self.pcm);
+        for frame in pcm[..whole].chunks_exact(PACKET_FRAMES * 2) {
+            let len = match self.encoder.encode_float(frame, &mut self.packet) {
+                Ok(len) => len,
+                Err(e) => {
+                    warn!("Opus encoding failed: {}", e);
+                    continue;
+                }
+            };
+            self.granule += PACKET_FRAMES as u64;
+            self.packets += 1;
+            let end = if self.packets.is_multiple_of(PAGE_PACKETS) {
+                PacketWriteEndInfo::EndPage
+            } else {
+                PacketWriteEndInfo::NormalPacket
+            };
+            self.writer
+                .write_packet(self.packet[..len].into(), self.serial, end, self.granule)
+                .expect("writes to memory");
+            if !self.writer.inner().is_empty() {
+                pages.push(Bytes::from(std::mem::take(self.writer.inner_mut())));
+            }
+        }
+        self.pcm = pcm[whole..].to_vec();
+        pages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resampler_doubles_mono_and_keeps_the_rate() {
+        let mut resampler = Resampler::new();
+        let mut out = Vec::new();
+        let mono = OutputFormat {
+            sample_rate: 24_000,
+            channels: 1,
+        };
+        for _ in 0..10 {
+            resampler.push(mono, &[0.5; 240], &mut out);
+        }
+        // 100 ms at 48 kHz stereo, give or take the frame held back
+        assert!((out.len() as i64 - 9600).abs() <= 4, "{}", out.len());
+        assert!(out[100..].iter().all(|s| (*s - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_ogg_opus_writes_headers_then_pages() {
+        let (mut ogg, headers) = OggOpus::new(96, 7).unwrap();
+        assert_eq!(&headers[..4], b"OggS");
+        let head = headers.windows(8).position(|w| w == b"OpusHead").unwrap();
+        assert_eq!(headers[head + 9], 2);
+        assert!(headers.windows(8).any(|w| w == b"OpusTags"));
+
+        let format = OutputFormat::default();
+        let tone: Vec<f32> = (0..4800)
+            .flat_map(|i| {
+                let s = (i as f32 * 0.05).sin() * 0.3;
+                [s, s]
+            })
+            .collect();
+        // 100 ms: one page of five packets
+        let pages = ogg.encode(format, &tone);
+        assert_eq!(pages.len(), 1);
+        assert_eq!(&pages[0][..4], b"OggS");
+        // Granule position: five packets of 960
+        let granule = u64::from_le_bytes(pages[0][6..14].try_into().unwrap());
+        assert_eq!(granule, 4800);
+    }
+
+    #[tokio::test]
+    async fn test_listeners_get_the_headers_then_live_pages() {
+        let hub = StreamHub::new(Bytes::from_static(b"head"));
+        let mut listener = Box::pin(hub.listen());
+        hub.pages.send(Bytes::from_static(b"page")).unwrap();
+        assert_eq!(listener.next().await.unwrap().unwrap(), "head");
+        assert_eq!(listener.next().await.unwrap().unwrap(), "page");
+        drop(hub);
+        assert!(listener.next().await.is_none());
+    }
+}
@@ -0,0 +1,1187 @@
+//! End-to-end test harness: the router, world task, tick task and audio
+//! control task wired together as in `main`, served on an ephemeral port.
+//!
+//! No audio device is opened. The mocked engine reports itself as running, and
+//! the parameters it would play are read back with [`TestApp::audio_params`].
+//! The MQTT bridge, sensors, recorder, audit log and config watcher are left
+//! out.
+
+use crate::anomaly::{AlertStatus, AnomalyDetector, AnomalyMonitor};
+use crate::api::{self, AppState};
+use crate::auth::Auth;
+use crate::calibration::Calibrator;
+use crate::config::Config;
+use crate::fanout::{SnapshotFanout, SnapshotSources, start_fanout_task};
+use crate::logging::LogControl;
+use crate::patches::load_patches;
+use crate::presets::PresetStore;
+use crate::resume::ResumeStore;
+use crate::runtime::{
+    ActiveMapping, AudioControlInputs, EVENT_QUEUE_CAPACITY, EventQueueStats, PendingAudioSpan,
+    TickStats, WorldOutputs, map_snapshot, start_audio_control_task, start_tick_task,
+    start_world_task, unix_time_ms,
+};
+use ambient_core::engine::WorldEngine;
+use ambient_core::world::{WorldSnapshot, WorldState};
+use audio::effects::SharedEffects;
+use audio::health::SharedOutputHealth;
+use audio::master::SharedMeter;
+use audio::mixer::SharedLayerGains;
+use audio::params::{AudioParams, LayerAmounts, SharedAudioParams};
+use audio::registry::{LayerRegistry, default_stack};
+use audio::status::{AudioStatus, EngineState, SharedAudioStatus};
+use audio::telemetry::CallbackTelemetry;
+use audio::transition::SharedTransition;
+use audio::waveform::SharedWaveform;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex, RwLock, broadcast, mpsc, watch};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// Longest a helper waits for the server before failing the test.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Seed for the world engine, so runs drift the same way.
+const SEED: u64 = 7;
+
+/// A running app and an HTTP client pointed at it.
+pub struct TestApp {
+    pub addr: SocketAddr,
+    /// Bearer token sent with every request: the first configured token, if any.
+    pub token: Option<String>,
+    /// Tick and snapshot rate senders, held so the tasks watching them keep
+    /// running.
+    _rates: (watch::Sender<f64>, watch::Sender<f64>),
+    audio_params: Arc<SharedAudioParams>,
+    /// Output envelope, recorded into by tests as the audio callback would.
+    pub waveform: Arc<SharedWaveform>,
+    /// Output meter, published into by tests as the audio callback would.
+    pub meter: Arc<SharedMeter>,
+    client: reqwest::Client,
+}
+
+impl TestApp {
+    /// Starts the app with the default config.
+    pub async fn spawn() -> Self {
+        Self::spawn_with(Config::default()).await
+    }
+
+    /// Starts the app with `config`. Only `api.port` and `api.bind` are
+    /// ignored: the app always listens on 127.0.0.1 on a free port.
+    pub async fn spawn_with(config: Config) -> Self {
+        let mapping_profiles = config.mapping_profiles().unwrap();
+        let (event_tx, event_rx) = mpsc::channel(EVENT_QUEUE_CAPACITY);
+        let event_queue = Arc::new(EventQueueStats::new());
+        let initial_snapshot = WorldSnapshot::from_world_state(&WorldState::new());
+        let (state_tx, state_rx) = watch::channel(initial_snapshot.clone());
+
+        let initial_mapping = ActiveMapping {
+            name: config.audio.mapping.profile.clone(),
+            profile: mapping_profiles[&config.audio.mapping.profile].clone(),
+        };
+        let initial_audio_params = map_snapshot(&initial_mapping.profile, &initial_snapshot);
+        let audio_params = Arc::new(SharedAudioParams::new(initial_audio_params));
+        let (audio_params_tx, audio_params_rx) = watch::channel(initial_audio_params);
+        let (layer_amounts_tx, layer_amounts_rx) = watch::channel(LayerAmounts::default());
+        let (audio_override_tx, audio_override_rx) = watch::channel(None);
+        let (mapping_tx, mapping_rx) = watch::channel(initial_mapping);
+        let audio_status = Arc::new(SharedAudioStatus::new(AudioStatus {
+            device: Some("mock".to_string()),
+            sample_rate: Some(48_000),
+            channels: Some(2),
+            ..AudioStatus::new(EngineState::Running)
+        }));
+
+        let mut engine = WorldEngine::with_seed(SEED);
+        engine.set_dynamics(config.dynamics());
+        engine.set_step_hz(config.world.step_hz);
+        engine.set_time_scale(config.world.time_scale);
+        for (name, targets) in config.load_scenes().unwrap() {
+            engine.register_scene(name, targets);
+        }
+
+        let (world_command_tx, world_command_rx) = mpsc::channel(16);
+        let (tick_hz_tx, tick_hz_rx) = watch::channel(config.world.tick_hz);
+        let (tick_stats_tx, tick_stats_rx) = watch::channel(TickStats::new(config.world.tick_hz));
+        let (snapshot_hz_tx, snapshot_hz_rx) = watch::channel(config.api.snapshot_hz);
+        let (broadcast_tx, _) = broadcast::channel(64);
+        let (alerts_tx, alerts_rx) = watch::channel(AlertStatus::default());
+        let monitor = AnomalyMonitor {
+            detector: AnomalyDetector::new(config.alerts.clone(), unix_time_ms()),
+            output: Arc::new(SharedOutputHealth::new()),
+            status_tx: alerts_tx,
+            broadcast_tx: broadcast_tx.clone(),
+        };
+
+        let audio_span = PendingAudioSpan::default();
+        tokio::spawn(start_world_task(
+            engine,
+            event_rx,
+            world_command_rx,
+            WorldOutputs {
+                state_tx,
+                broadcast_tx: broadcast_tx.clone(),
+            },
+            Arc::clone(&audio_span),
+            None,
+            monitor,
+        ));
+        tokio::spawn(start_tick_task(
+            event_tx.clone(),
+            tick_hz_rx.clone(),
+            tick_stats_tx,
+            Arc::clone(&event_queue),
+            config.world.coalesce_ticks,
+        ));
+        tokio::spawn(start_audio_control_task(
+            AudioControlInputs {
+                state_rx: state_rx.clone(),
+                audio_span,
+                layer_amounts_rx,
+                override_rx: audio_override_rx,
+                mapping_rx,
+                quantize_delay: config.world.pulse.quantize_delay,
+                gate: config.gate(),
+            },
+            Arc::clone(&audio_params),
+            Arc::new(SharedTransition::new()),
+            audio_params_tx,
+        ));
+        let current_snapshot = Arc::new(RwLock::new(initial_snapshot));
+        tokio::spawn(api::start_snapshot_task(
+            state_rx.clone(),
+            Arc::clone(&current_snapshot),
+        ));
+
+        let waveform = Arc::new(SharedWaveform::new());
+        let meter = Arc::new(SharedMeter::new());
+        let telemetry = Arc::new(CallbackTelemetry::new());
+        let snapshots = Arc::new(SnapshotFanout::new());
+        tokio::spawn(start_fanout_task(
+            Arc::clone(&snapshots),
+            SnapshotSources {
+                world_rx: state_rx.clone(),
+                tick_hz_rx: tick_hz_rx.clone(),
+                audio_rx: audio_params_rx.clone(),
+                meter: Arc::clone(&meter),
+                telemetry: Arc::clone(&telemetry),
+            },
+            snapshot_hz_rx.clone(),
+        ));
+        let layer_gains = Arc::new(SharedLayerGains::new(config.layer_gains()));
+        let calibration = Arc::new(Calibrator::new(
+            config.audio.calibration.clone(),
+            config.loudness().target_lufs,
+            Arc::clone(&meter),
+            Arc::clone(&layer_gains),
+            audio_override_tx.clone(),
+        ));
+        let app_state = AppState {
+            event_tx,
+            event_queue,
+            world_command_tx,
+            current_snapshot,
+            world_state_rx: state_rx,
+            audio_params_rx,
+            tick_hz_rx,
+            tick_hz_tx: tick_hz_tx.clone(),
+            tick_stats_rx,
+            snapshot_hz_rx,
+            broadcast_tx,
+            auth: Auth::new(config.auth.tokens.clone()),
+            meter: Arc::clone(&meter),
+            waveform: Arc::clone(&waveform),
+            telemetry,
+            layer_amounts_tx,
+            audio_override_tx,
+            mapping_profiles: Arc::new(mapping_profiles),
+            mapping_tx,
+            audio_status,
+            layer_gains,
+            effects: Arc::new(SharedEffects::new(config.effect_chains().unwrap())),
+            layers: Arc::new(LayerRegistry::new(
+                default_stack(config.drone_mode(), config.noise_colors(), None, false),
+                Arc::from(Vec::new()),
+            )),
+            patches: Arc::new(
+                config
+                    .audio
+                    .patches_dir
+                    .as_deref()
+                    .map(|dir| load_patches(dir).unwrap())
+                    .unwrap_or_default(),
+            ),
+            alerts_rx,
+            presets: Arc::new(Mutex::new(PresetStore::load(None).unwrap())),
+            resumes: Arc::new(std::sync::Mutex::new(ResumeStore::new())),
+            noise_seed: config.world.noise_seed.unwrap_or_default(),
+            log_control: LogControl::detached(),
+            snapshots,
+            audit_tx: None,
+            audio_recorder: None,
+            calibration,
+            #[cfg(feature = "stream")]
+            stream: None,
+        };
+        let app = api::create_router(app_state, &config.api.cors_origins);
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        Self {
+            addr,
+            token: config.auth.tokens.first().map(|t| t.token.clone()),
+            _rates: (tick_hz_tx, snapshot_hz_tx),
+            audio_params,
+            waveform,
+            meter,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    fn authorized(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    pub async fn get(&self, path: &str) -> reqwest::Response {
+        self.authorized(self.client.get(self.url(path)))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post(&self, path: &str, body: Value) -> reqwest::Response {
+        self.authorized(self.client.post(self.url(path)).json(&body))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn put(&self, path: &str, body: Value) -> reqwest::Response {
+        self.authorized(self.client.put(self.url(path)).json(&body))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn delete(&self, path: &str) -> reqwest::Response {
+        self.authorized(self.client.delete(self.url(path)))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    /// Posts `body` to `/event`, expecting success, and returns the response.
+    pub async fn post_event(&self, body: Value) -> Value {
+        let response = self.post("/event", body).await;
+        assert!(
+            response.status().is_success(),
+            "POST /event failed: {}",
+            response.status()
+        );
+        response.json().await.unwrap()
+    }
+
+    /// The parameters the mocked audio engine would be playing.
+    pub fn audio_params(&self) -> AudioParams {
+        self.audio_params.get()
+    }
+
+    /// Waits until the audio parameters satisfy `predicate`.
+    pub async fn wait_for_audio(&self, predicate: impl Fn(&AudioParams) -> bool) -> AudioParams {
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                let params = self.audio_params();
+                if predicate(&params) {
+                    return params;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("timed out waiting for audio params")
+    }
+
+    /// Opens a WebSocket and reads the server's hello.
+    pub async fn ws(&self) -> WsClient {
+        let (stream, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", self.addr))
+            .await
+            .unwrap();
+        let mut client = WsClient {
+            stream,
+            hello: Value::Null,
+        };
+        client.hello = client.next_of_type("hello").await;
+        client
+    }
+}
+
+/// A WebSocket session with the app, reading JSON text frames.
+pub struct WsClient {
+    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// The hello the server opened with.
+    pub hello: Value,
+}
+
+impl WsClient {
+    pub async fn send(&mut self, message: Value) {
+        self.stream
+            .send(Message::text(message.to_string()))
+            .await
+            .unwrap();
+    }
+
+    /// Next JSON message, skipping control frames.
+    pub async fn next_message(&mut self) -> Value {
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                match self.stream.next().await {
+                    Some(Ok(Message::Text(text))) => return serde_json::from_str(&text).unwrap(),
+                    Some(Ok(Message::Close(_))) | None => panic!("WebSocket closed"),
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => panic!("WebSocket error: {}", e),
+                }
+            }
+        })
+        .await
+        .expect("timed out waiting for a WebSocket message")
+    }
+
+    /// Next binary frame, skipping text and control frames.
+    pub async fn next_binary(&mut self) -> Vec<u8> {
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                match self.stream.next().await {
+                    Some(Ok(Message::Binary(bytes))) => return bytes.to_vec(),
+                    Some(Ok(Message::Close(_))) | None => panic!("WebSocket closed"),
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => panic!("WebSocket error: {}", e),
+                }
+            }
+        })
+        .await
+        .expect("timed out waiting for a binary frame")
+    }
+
+    /// Next message of type `kind`, skipping others.
+    pub async fn next_of_type(&mut self, kind: &str) -> Value {
+        loop {
+            let message = self.next_message().await;
+            if message["type"] == kind {
+                return message;
+            }
+        }
+    }
+
+    /// Waits for a snapshot whose world part satisfies `predicate`.
+    pub async fn wait_for_snapshot(&mut self, predicate: impl Fn(&Value) -> bool) -> Value {
+        loop {
+            let snapshot = self.next_of_type("snapshot").await;
+            if predicate(&snapshot["payload"]["world"]) {
+                return snapshot;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Role, TokenConfig};
+    use crate::protocol::{FIELD_FRAME_HEADER_LEN, FIELD_FRAME_MAGIC};
+    use audio::master::{LevelReading, MeterReading};
+    use audio::mixer::LayerSlot;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_posted_event_reaches_snapshots_and_audio() {
+        let app = TestApp::spawn().await;
+        let mut ws = app.ws().await;
+        assert_eq!(ws.hello["payload"]["auth_required"], false);
+        let quiet = app.audio_params();
+
+        let response = app
+            .post_event(json!({"type": "trigger", "kind": "Pulse", "intensity": 0.4}))
+            .await;
+        let energy = response["resulting_snapshot"]["energy"].as_f64().unwrap();
+        assert!(energy > 0.8, "energy {}", energy);
+
+        ws.wait_for_snapshot(|world| world["energy"].as_f64().unwrap() > 0.8)
+            .await;
+        app.wait_for_audio(|params| params.master_gain > quiet.master_gain)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_posted_event_can_skip_waiting_for_the_result() {
+        let app = TestApp::spawn().await;
+        let mut ws = app.ws().await;
+        let scene = json!({"type": "perform", "Scene": {"name": "energetic"}});
+
+        let response = app.post("/event?wait_for_apply=false", scene.clone()).await;
+        assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+        assert_eq!(response.text().await.unwrap(), "Event queued");
+        // Applied all the same, just not reported
+        ws.wait_for_snapshot(|world| world["scene"]["name"] == "energetic")
+            .await;
+
+        // Waiting is the default, and can be asked for by name
+        let response = app.post("/event?wait_for_apply=true", scene).await;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let result: Value = response.json().await.unwrap();
+        assert_eq!(result["applied"], true);
+        assert_eq!(result["resulting_snapshot"]["scene"]["sequence"], 2);
+
+        let response = app
+            .post(
+                "/event?wait=false",
+                json!({"type": "trigger", "kind": "Pulse", "intensity": 0.4}),
+            )
+            .await;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_websocket_perform_is_acked() {
+        let app = TestApp::spawn().await;
+        let mut ws = app.ws().await;
+        ws.send(json!({
+            "type": "perform",
+            "version": "2.0",
+            "payload": {"request_id": "r1", "action": {"Calm": {"intensity": 0.3}}},
+        }))
+        .await;
+        let ack = ws.next_of_type("event_ack").await;
+        assert_eq!(ack["payload"]["request_id"], "r1");
+        assert_eq!(ack["payload"]["applied"], true);
+
+        let state: Value = app.get("/state").await.json().await.unwrap();
+        assert!(state["tension"].as_f64().unwrap() < 0.3);
+
+        // The tick task keeps the world moving
+        let tick = ack["payload"]["resulting_snapshot"]["tick"]
+            .as_u64()
+            .unwrap();
+        ws.wait_for_snapshot(|world| world["tick"].as_u64().unwrap() > tick + 5)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_undo_takes_back_a_posted_action() {
+        let app = TestApp::spawn().await;
+        app.post("/world/pause", json!({})).await;
+        app.post_event(json!({"type": "perform", "Pulse": {"intensity": 1.0}}))
+            .await;
+        let response = app
+            .post_event(json!({"type": "perform", "Undo": null}))
+            .await;
+        assert_eq!(response["applied"], true);
+
+        // The undo eases out over half a second of world time
+        app.post("/world/step", json!({"ticks": 40})).await;
+        let state: Value = app.get("/state").await.json().await.unwrap();
+        let energy = state["energy"].as_f64().unwrap();
+        assert!(energy < 0.6, "energy {}", energy);
+        let response = app
+            .post_event(json!({"type": "perform", "Undo": null}))
+            .await;
+        assert_eq!(response["applied"], false);
+    }
+
+    #[tokio::test]
+    async fn test_negative_intensities_push_the_other_way() {
+        let app = TestApp::spawn().await;
+        app.post("/world/pause", json!({})).await;
+        let before: Value = app.get("/state").await.json().await.unwrap();
+        let response = app
+            .post_event(json!({"type": "perform", "Heat": {"intensity": -0.3}}))
+            .await;
+        let warmth = response["resulting_snapshot"]["warmth"].as_f64().unwrap();
+        let cooled = before["warmth"].as_f64().unwrap() - warmth;
+        assert!((cooled - 0.3).abs() < 1e-9, "cooled by {}", cooled);
+        let response = app
+            .post_event(json!({"type": "trigger", "kind": "Stir", "intensity": -0.2}))
+            .await;
+        let density = response["resulting_snapshot"]["density"].as_f64().unwrap();
+        let thinned = before["density"].as_f64().unwrap() - density;
+        assert!((thinned - 0.2).abs() < 1e-9, "thinned by {}", thinned);
+
+        for body in [
+            json!({"type": "perform", "Heat": {"intensity": -1.5}}),
+            json!({"type": "trigger", "kind": "Pulse", "intensity": 1.2}),
+        ] {
+            let response = app.post("/event", body).await;
+            assert_eq!(response.status(), 400);
+            let error: Value = response.json().await.unwrap();
+            assert_eq!(error["code"], "VALIDATION_ERROR");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expanding_space_opens_the_reverb() {
+        let app = TestApp::spawn().await;
+        app.post("/world/pause", json!({})).await;
+        let response = app
+            .post_event(json!({"type": "perform", "Expand": {"intensity": 0.4}}))
+            .await;
+        let space = response["resulting_snapshot"]["space"].as_f64().unwrap();
+        assert!(space > 0.85, "space {}", space);
+        app.wait_for_audio(|params| params.reverb_size > 0.85 && params.pre_delay_ms > 68.0)
+            .await;
+
+        let response = app
+            .post_event(json!({"type": "trigger", "kind": "Contract", "intensity": 0.8}))
+            .await;
+        let space = response["resulting_snapshot"]["space"].as_f64().unwrap();
+        assert!(space < 0.15, "space {}", space);
+        app.wait_for_audio(|params| params.reverb_size < 0.15).await;
+
+        // Clients on 1.0 never see it
+        let response = app
+            .client
+            .get(app.url("/state"))
+            .header("x-schema-version", "1.0")
+            .send()
+            .await
+            .unwrap();
+        let state: Value = response.json().await.unwrap();
+        assert!(state.get("space").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_clarity_trades_texture_for_grit() {
+        let app = TestApp::spawn().await;
+        app.post("/world/pause", json!({})).await;
+        let response = app
+            .post_event(json!({"type": "perform", "Roughen": {"intensity": 0.5}}))
+            .await;
+        let clarity = response["resulting_snapshot"]["clarity"].as_f64().unwrap();
+        assert!(clarity < 0.05, "clarity {}", clarity);
+        app.wait_for_audio(|params| params.grit > 0.9).await;
+
+        let response = app
+            .post_event(json!({"type": "trigger", "kind": "Clarify", "intensity": 1.0}))
+            .await;
+        let clarity = response["resulting_snapshot"]["clarity"].as_f64().unwrap();
+        assert!(clarity > 0.95, "clarity {}", clarity);
+        app.wait_for_audio(|params| params.grit == 0.0 && params.texture < 0.05)
+            .await;
+
+        // Clients on 1.0 never see it
+        let response = app
+            .client
+            .get(app.url("/state"))
+            .header("x-schema-version", "1.0")
+            .send()
+            .await
+            .unwrap();
+        let state: Value = response.json().await.unwrap();
+        assert!(state.get("clarity").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_diff_and_morph_toward_a_scene() {
+        let app = TestApp::spawn().await;
+        app.post("/world/pause", json!({})).await;
+        let diff: Value = app
+            .get("/state/diff?scene=energetic")
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            diff["reference"],
+            json!({"kind": "scene", "name": "energetic"})
+        );
+        let energy = &diff["fields"]["energy"];
+        assert_eq!(energy["reference"], 0.9);
+        let delta = energy["delta"].as_f64().unwrap();
+        assert!(delta > 0.0, "delta {}", delta);
+
+        let response = app
+            .post("/state/morph?scene=energetic&fraction=0.5", json!({}))
+            .await;
+        assert!(response.status().is_success());
+        let diff: Value = app
+            .get("/state/diff?scene=energetic")
+            .await
+            .json()
+            .await
+            .unwrap();
+        let halved = diff["fields"]["energy"]["delta"].as_f64().unwrap();
+        assert!((halved - delta / 2.0).abs() < 1e-9, "delta {}", halved);
+
+        assert_eq!(app.get("/state/diff?scene=nowhere").await.status(), 404);
+        assert_eq!(app.get("/state/diff").await.status(), 400);
+        let response = app
+            .post("/state/morph?preset=none&fraction=1.5", json!({}))
+            .await;
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_sparkles_reach_subscribers() {
+        let app = TestApp::spawn().await;
+        let mut ws = app.ws().await;
+        ws.send(json!({
+            "type": "subscribe",
+            "version": "2.0",
+            "payload": {"channels": ["sparkles"]},
+        }))
+        .await;
+        ws.next_of_type("subscribed").await;
+
+        // A minute of world time fires a few sparkles
+        let response = app.post("/world/step", json!({"ticks": 3600})).await;
+        assert!(response.status().is_success());
+        let sparkle = ws.next_of_type("sparkle").await;
+        let payload = &sparkle["payload"];
+        assert!(payload["timestamp_ms"].as_u64().unwrap() > 0);
+        assert!(payload["tick"].as_u64().unwrap() > 0);
+        let strength = payload["strength"].as_f64().unwrap();
+        assert!((0.5..=1.0).contains(&strength), "strength {}", strength);
+        assert!(payload["pan"].as_f64().unwrap().abs() <= 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_beats_reach_subscribers() {
+        let app = TestApp::spawn().await;
+        let mut ws = app.ws().await;
+        ws.send(json!({
+            "type": "subscribe",
+            "version": "2.0",
+            "payload": {"channels": ["beats"]},
+        }))
+        .await;
+        ws.next_of_type("subscribed").await;
+
+        app.post("/world/step", json!({"ticks": 120})).await;
+        let first = ws.next_of_type("beat").await;
+        let second = ws.next_of_type("beat").await;
+        assert_eq!(
+            second["payload"]["beat"].as_u64().unwrap(),
+            first["payload"]["beat"].as_u64().unwrap() + 1
+        );
+        let bpm = first["payload"]["bpm"].as_f64().unwrap();
+        assert!((55.0..=130.0).contains(&bpm), "bpm {}", bpm);
+        assert_eq!(first["payload"]["beats_per_bar"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_hello_carries_the_noise_seed() {
+        let mut config = Config::default();
+        config.world.noise_seed = Some(4_000_000_000);
+        let app = TestApp::spawn_with(config).await;
+        let ws = app.ws().await;
+        assert_eq!(ws.hello["payload"]["noise_seed"], 4_000_000_000u32);
+    }
+
+    #[tokio::test]
+    async fn test_tick_rate_changes_through_the_api() {
+        let app = TestApp::spawn().await;
+        let response: Value = app
+            .post("/admin/tick_rate", json!({"tick_hz": 60.0}))
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(response["tick_hz"], 60.0);
+        assert_eq!(response["previous_hz"], Config::default().world.tick_hz);
+        let tick_hz = json!({"tick_hz": 2000.0});
+        assert_eq!(app.post("/admin/tick_rate", tick_hz).await.status(), 400);
+
+        let mut ws = app.ws().await;
+        assert_eq!(ws.hello["payload"]["tick_rate_hz"], 60.0);
+        let snapshot = ws.next_of_type("snapshot").await;
+        assert_eq!(snapshot["payload"]["tick_rate_hz"], 60.0);
+    }
+
+    #[tokio::test]
+    async fn test_log_level_changes_through_the_api() {
+        let app = TestApp::spawn().await;
+        let current: Value = app.get("/admin/log_level").await.json().await.unwrap();
+        assert_eq!(current["filter"], "info");
+
+        let body = json!({"targets": {"audio": "debug", "api": "warn"}});
+        let settings: Value = app
+            .post("/admin/log_level", body)
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(settings["level"], "info");
+        assert_eq!(
+            settings["targets"],
+            json!({"audio": "debug", "api": "warn"})
+        );
+        assert!(
+            settings["filter"]
+                .as_str()
+                .unwrap()
+                .contains(",audio=debug")
+        );
+
+        // Targets merge into the current ones, and null removes one
+        let body = json!({"level": "warn", "targets": {"api": null}});
+        let settings: Value = app
+            .post("/admin/log_level", body)
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(settings["level"], "warn");
+        assert_eq!(settings["targets"], json!({"audio": "debug"}));
+        let current: Value = app.get("/admin/log_level").await.json().await.unwrap();
+        assert_eq!(current, settings);
+
+        let bad_level = json!({"targets": {"audio": "loud"}});
+        assert_eq!(app.post("/admin/log_level", bad_level).await.status(), 400);
+        let unknown = json!({"targets": {"lighting": "debug"}});
+        assert_eq!(app.post("/admin/log_level", unknown).await.status(), 400);
+        let current: Value = app.get("/admin/log_level").await.json().await.unwrap();
+        assert_eq!(current, settings);
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_allows_configured_origins_every_method() {
+        let mut config = Config::default();
+        config.api.cors_origins = vec!["https://ambient.example".to_string()];
+        let app = TestApp::spawn_with(config).await;
+        let preflight = |origin: &'static str, method: &'static str| {
+            app.client
+                .request(reqwest::Method::OPTIONS, app.url("/presets/dusk"))
+                .header("origin", origin)
+                .header("access-control-request-method", method)
+                .send()
+        };
+
+        for method in ["GET", "POST", "PUT", "DELETE"] {
+            let response = preflight("https://ambient.example", method).await.unwrap();
+            assert!(response.status().is_success());
+            assert_eq!(
+                response.headers()["access-control-allow-origin"],
+                "https://ambient.example"
+            );
+            let allowed = response.headers()["access-control-allow-methods"]
+                .to_str()
+                .unwrap();
+            assert!(
+                allowed.split(',').any(|m| m.trim() == method),
+                "{}",
+                allowed
+            );
+        }
+        let response = preflight("https://elsewhere.example", "DELETE")
+            .await
+            .unwrap();
+        assert!(
+            !response
+                .headers()
+                .contains_key("access-control-allow-origin")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_ids_are_echoed_or_generated() {
+        let app = TestApp::spawn().await;
+        let response = app
+            .client
+            .post(app.url("/event"))
+            .header("x-request-id", "kiosk-3:tap-17")
+            .json(&json!({"type": "perform", "Pulse": {"intensity": 0.5}}))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()["x-request-id"], "kiosk-3:tap-17");
+
+        let response = app.get("/no/such/route").await;
+        assert_eq!(response.status(), 404);
+        let generated = response.headers()["x-request-id"].to_str().unwrap();
+        assert_eq!(generated.len(), 32);
+        assert_ne!(
+            app.get("/health").await.headers()["x-request-id"],
+            generated
+        );
+    }
+
+    #[tokio::test]
+    async fn test_errors_have_typed_json_bodies() {
+        let mut config = Config::default();
+        config.auth.tokens = vec![TokenConfig {
+            name: "lobby-screen".to_string(),
+            token: "view-secret".to_string(),
+            role: Role::Viewer,
+        }];
+        let app = TestApp::spawn_with(config).await;
+        let error = |response: reqwest::Response| async move {
+            let status = response.status().as_u16();
+            let request_id = response.headers()["x-request-id"]
+                .to_str()
+                .unwrap()
+                .to_string();
+            let body: Value = response.json().await.unwrap();
+            assert_eq!(body["request_id"], request_id);
+            (status, body["code"].as_str().unwrap().to_string())
+        };
+
+        let (status, code) = error(app.get("/state/diff?preset=nowhere").await).await;
+        assert_eq!((status, code.as_str()), (404, "NOT_FOUND"));
+        let (status, code) = error(app.get("/audio/waveform?points=0").await).await;
+        assert_eq!((status, code.as_str()), (400, "VALIDATION_ERROR"));
+        let (status, code) = error(app.get("/audio/waveform?points=many").await).await;
+        assert_eq!((status, code.as_str()), (400, "VALIDATION_ERROR"));
+        let (status, code) = error(app.post("/world/pause", json!({})).await).await;
+        assert_eq!((status, code.as_str()), (403, "FORBIDDEN"));
+
+        let response = app
+            .client
+            .get(app.url("/state"))
+            .header("x-schema-version", "9.0")
+            .bearer_auth("view-secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(error(response).await, (400, "VERSION_MISMATCH".to_string()));
+        let response = app.client.get(app.url("/state")).send().await.unwrap();
+        assert_eq!(error(response).await, (401, "UNAUTHORIZED".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_openapi_spec_and_docs_are_served() {
+        let app = TestApp::spawn().await;
+        let spec: Value = app.get("/openapi.json").await.json().await.unwrap();
+        for path in [
+            "/state",
+            "/event",
+            "/presets/{name}/recall",
+            "/admin/tick_rate",
+        ] {
+            assert!(spec["paths"][path].is_object(), "{} missing", path);
+        }
+        let event = &spec["paths"]["/event"]["post"];
+        assert_eq!(
+            event["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/EventRequest"
+        );
+        assert_eq!(event["parameters"][0]["name"], "x-schema-version");
+        assert!(spec["components"]["schemas"]["SnapshotV2"].is_object());
+        assert!(spec["components"]["securitySchemes"]["bearer"].is_object());
+
+        let docs = app.get("/docs/").await;
+        assert_eq!(docs.status(), 200);
+        assert!(docs.text().await.unwrap().contains("swagger"));
+    }
+
+    #[tokio::test]
+    async fn test_live_messages_conform_to_the_ws_schema() {
+        let app = TestApp::spawn().await;
+        let schema: Value = app.get("/ws/schema").await.json().await.unwrap();
+        let validator = jsonschema::validator_for(&schema).unwrap();
+        let check = |message: &Value| {
+            let errors: Vec<String> = validator
+                .iter_errors(message)
+                .map(|error| error.to_string())
+                .collect();
+            assert!(errors.is_empty(), "{}: {:?}", message["type"], errors);
+        };
+
+        let mut ws = app.ws().await;
+        check(&ws.hello);
+        ws.send(json!({
+            "type": "hello",
+            "version": "2.0",
+            "payload": {"schema_version": "2.0", "features": ["beats", "warp"]},
+        }))
+        .await;
+        check(&ws.next_of_type("negotiated").await);
+        check(&ws.next_of_type("snapshot").await);
+        ws.send(json!({
+            "type": "perform",
+            "version": "2.0",
+            "payload": {"request_id": "r1", "action": {"Pulse": {"intensity": 0.4}}},
+        }))
+        .await;
+        check(&ws.next_of_type("event_ack").await);
+        ws.send(json!({"type": "ping", "version": "2.0", "payload": {}}))
+            .await;
+        check(&ws.next_of_type("error").await);
+        ws.send(json!({
+            "type": "subscribe",
+            "version": "2.0",
+            "payload": {"channels": ["world", "beats"], "fields": {"world": ["tick"]}},
+        }))
+        .await;
+        check(&ws.next_of_type("subscribed").await);
+        app.post("/world/step", json!({"ticks": 120})).await;
+        check(&ws.next_of_type("beat").await);
+    }
+
+    #[tokio::test]
+    async fn test_field_frames_show_a_local_pulse() {
+        let mut config = Config::default();
+        config.world.field.enabled = true;
+        let app = TestApp::spawn_with(config).await;
+        let mut ws = app.ws().await;
+        ws.send(json!({
+            "type": "subscribe",
+            "version": "2.0",
+            "payload": {"channels": ["field"]},
+        }))
+        .await;
+        ws.next_of_type("subscribed").await;
+
+        let frame = ws.next_binary().await;
+        assert_eq!(&frame[..4], FIELD_FRAME_MAGIC);
+        assert_eq!(frame.len(), FIELD_FRAME_HEADER_LEN + 3 * 16 * 9);
+
+        // Bottom right, far from the top left corner
+        app.post_event(json!({
+            "type": "perform",
+            "PulseAt": {"x": 0.95, "y": 0.9, "intensity": 0.5},
+        }))
+        .await;
+        let energy = |frame: &[u8], cell: usize| frame[FIELD_FRAME_HEADER_LEN + 3 * cell + 1];
+        let frame = loop {
+            let frame = ws.next_binary().await;
+            if energy(&frame, 16 * 9 - 1) > 200 {
+                break frame;
+            }
+        };
+        assert!(energy(&frame, 0) < 160, "{}", energy(&frame, 0));
+    }
+
+    #[tokio::test]
+    async fn test_mood_changes_reach_event_subscribers() {
+        let app = TestApp::spawn().await;
+        let mut ws = app.ws().await;
+        ws.send(json!({
+            "type": "subscribe",
+            "version": "2.0",
+            "payload": {"channels": ["events"]},
+        }))
+        .await;
+        ws.next_of_type("subscribed").await;
+
+        app.post_event(json!({"type": "trigger", "kind": "Tense", "intensity": 0.5}))
+            .await;
+        app.post("/world/step", json!({"ticks": 1})).await;
+        let change = ws.next_of_type("mood_changed").await;
+        assert_eq!(change["payload"]["from"], "serene");
+        let to = change["payload"]["to"].as_str().unwrap();
+        assert!(["brooding", "restless"].contains(&to), "mood {}", to);
+        assert!(change["payload"]["valence"].as_f64().unwrap() < 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_waveform_reduces_recent_output() {
+        let app = TestApp::spawn().await;
+        let empty: Value = app.get("/audio/waveform").await.json().await.unwrap();
+        assert_eq!(empty, json!({"seconds": 0.0, "min": [], "max": []}));
+
+        // 2 s of bins, louder in the second
+        for bin in 0..200 {
+            let level = if bin < 100 { 0.25 } else { 0.5 };
+            app.waveform.record(-level, level);
+        }
+        let waveform: Value = app
+            .get("/audio/waveform?seconds=1.5&points=3")
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(waveform["seconds"], 1.5);
+        assert_eq!(waveform["min"], json!([-0.25, -0.5, -0.5]));
+        assert_eq!(waveform["max"], json!([0.25, 0.5, 0.5]));
+
+        assert_eq!(app.get("/audio/waveform?seconds=0").await.status(), 400);
+        assert_eq!(app.get("/audio/waveform?points=5000").await.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_audio_status_reports_layer_levels() {
+        let app = TestApp::spawn().await;
+        let mut levels = [LevelReading::default(); LayerSlot::ALL.len()];
+        levels[LayerSlot::Drone.index()] = LevelReading {
+            peak_db: -12.0,
+            rms_db: -18.0,
+        };
+        app.meter.set_layers(&levels);
+
+        let status: Value = app.get("/audio/status").await.json().await.unwrap();
+        assert_eq!(
+            status["layers"]["drone"],
+            json!({"peak_db": -12.0, "rms_db": -18.0})
+        );
+        assert_eq!(status["layers"]["wind"]["peak_db"], -120.0);
+    }
+
+    #[tokio::test]
+    async fn test_manual_calibration_reports_and_saves_gains() {
+        let path = std::env::temp_dir().join(format!("calibration-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut config = Config::default();
+        config.audio.calibration.steps = 2;
+        config.audio.calibration.settle_ms = 100;
+        config.audio.calibration.path = Some(path.clone());
+        let app = TestApp::spawn_with(config).await;
+        app.meter.set(MeterReading {
+            peak_db: -6.0,
+            gain_reduction_db: 0.0,
+            momentary_lufs: -14.0,
+            integrated_lufs: -16.0,
+            auto_gain_db: 0.0,
+        });
+
+        assert_eq!(
+            app.post("/audio/calibration/step", json!({}))
+                .await
+                .status(),
+            409
+        );
+        let body = json!({"mode": "manual", "params": ["master_gain", "grit"]});
+        let status: Value = app
+            .post("/audio/calibration", body.clone())
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(status["total"], 4);
+        assert_eq!(status["next_param"], "master_gain");
+        assert_eq!(app.post("/audio/calibration", body).await.status(), 409);
+        // The world no longer drives the audio, nor may anyone else
+        app.wait_for_audio(|params| params.master_gain == 0.5).await;
+        assert!(app.meter.trim_held());
+        let pin = json!({"master_gain": 0.9, "duration_secs": 10.0});
+        assert_eq!(app.post("/audio/override", pin).await.status(), 409);
+        assert_eq!(app.delete("/audio/override").await.status(), 409);
+
+        for measured in 1..=3 {
+            let status: Value = app
+                .post("/audio/calibration/step", json!({}))
+                .await
+                .json()
+                .await
+                .unwrap();
+            assert_eq!(status["measured"], measured);
+        }
+        let mut ws = app.ws().await;
+        ws.send(json!({"type": "calibrate", "version": "2.0", "payload": {"request_id": "c1"}}))
+            .await;
+        let done = ws.next_of_type("calibration").await;
+        assert_eq!(done["payload"]["request_id"], "c1");
+        assert_eq!(done["payload"]["running"], false);
+        assert_eq!(done["payload"]["last"]["param"], "grit");
+
+        // 10 dB over the loudness target
+        let report = &done["payload"]["report"];
+        assert_eq!(report["params"].as_array().unwrap().len(), 2);
+        assert!((report["trim_db"].as_f64().unwrap() + 10.0).abs() < 1e-4);
+        let saved: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(&saved, report);
+        assert!(!app.meter.trim_held());
+        assert_eq!(app.delete("/audio/calibration").await.status(), 409);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_layer_stack_changes_through_the_api() {
+        let app = TestApp::spawn().await;
+        let stack: Value = app.get("/audio/stack").await.json().await.unwrap();
+        assert_eq!(
+            stack["layers"][0],
+            json!({"layer": "drone", "mode": "dual", "partials": 8})
+        );
+        // Texture, wind and sparkle; no samples are loaded, so no samples layer
+        assert_eq!(stack["layers"].as_array().unwrap().len(), 4);
+
+        let response = app
+            .put(
+                "/audio/stack",
+                json!({"layer": "drone", "mode": "additive", "partials": 12}),
+            )
+            .await;
+        assert_eq!(response.status(), 200);
+        let stack: Value = app.delete("/audio/stack/wind").await.json().await.unwrap();
+        assert_eq!(
+            stack["layers"],
+            json!([
+                {"layer": "drone", "mode": "additive", "partials": 12},
+                {"layer": "texture", "noise": "white"},
+                {"layer": "sparkle", "noise": "white"}
+            ])
+        );
+
+        assert_eq!(app.delete("/audio/stack/wind").await.status(), 404);
+        assert_eq!(app.delete("/audio/stack/pad").await.status(), 400);
+        let grains = app.post("/audio/stack", json!({"layer": "grains"})).await;
+        assert_eq!(grains.status(), 400);
+        let partials = json!({"layer": "drone", "mode": "additive", "partials": 40});
+        assert_eq!(app.post("/audio/stack", partials).await.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_audio_patches_switch_through_the_api() {
+        let dir = std::env::temp_dir().join(format!("api-patches-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let night = json!({
+            "layers": [{"layer": "wind", "noise": "brown"}],
+            "effects": {"drone": [], "sparkle": ["reverb"], "master": []},
+            "gains": {"wind": 0.9}
+        });
+        std::fs::write(dir.join("night.json"), night.to_string()).unwrap();
+        let mut config = Config::default();
+        config.audio.patches_dir = Some(dir.clone());
+        let app = TestApp::spawn_with(config).await;
+
+        let patches: Value = app.get("/audio/patches").await.json().await.unwrap();
+        assert_eq!(patches["night"]["gains"]["wind"], json!(0.9));
+        assert_eq!(
+            app.post("/audio/patches/dawn/load", json!({}))
+                .await
+                .status(),
+            404
+        );
+
+        let patch: Value = app
+            .post("/audio/patches/night/load", json!({}))
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(patch["layers"], night["layers"]);
+        assert_eq!(patch["effects"], night["effects"]);
+        assert_eq!(
+            app.get("/audio/patch").await.json::<Value>().await.unwrap(),
+            patch
+        );
+        let effects: Value = app.get("/audio/effects").await.json().await.unwrap();
+        assert_eq!(
+            effects["sparkle"],
+            json!([{"effect": "reverb", "enabled": true}])
+        );
+
+        let mut loud = night.clone();
+        loud["gains"]["wind"] = json!(5.0);
+        assert_eq!(app.put("/audio/patch", loud).await.status(), 400);
+        let samples = json!({"layers": [{"layer": "samples"}]});
+        assert_eq!(app.put("/audio/patch", samples).await.status(), 400);
+        let stack: Value = app.get("/audio/stack").await.json().await.unwrap();
+        assert_eq!(stack["layers"], night["layers"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
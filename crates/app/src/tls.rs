@@ -0,0 +1,36 @@
+//! HTTPS listener for the API, built on rustls.
+//!
+//! Serves the same router as the plain HTTP listener, on `api.tls.port`, with
+//! the PEM certificate chain and key from `api.tls`. WebSockets work over it
+//! as `wss://`.
+
+use crate::config::TlsConfig;
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use std::net::SocketAddr;
+
+/// Loads the certificate and key, so a bad path fails at startup.
+pub async fn load(config: &TlsConfig) -> anyhow::Result<RustlsConfig> {
+    // Several rustls backends can end up in the dependency tree; pick ring.
+    // Fails harmlessly when a provider is already installed.
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    RustlsConfig::from_pem_file(&config.cert, &config.key)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "failed to load api.tls cert {} / key {}: {}",
+                config.cert.display(),
+                config.key.display(),
+                e
+            )
+        })
+}
+
+pub async fn start_https_server(addr: SocketAddr, rustls: RustlsConfig, app: Router) {
+    if let Err(e) = axum_server::bind_rustls(addr, rustls)
+        .serve(app.into_make_service())
+        .await
+    {
+        tracing::error!("HTTPS server on {} failed: {}", addr, e);
+    }
+}
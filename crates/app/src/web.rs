@@ -0,0 +1,51 @@
+//! Control page embedded in the binary.
+//!
+//! `assets/` is compiled in with rust-embed, so `GET /` works without a
+//! separate frontend deploy. The page talks to the same WebSocket and REST
+//! API as any other client; the React app in `ui/` remains the full UI.
+
+use axum::extract::Path;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use rust_embed::RustEmbed;
+
+#[derive(RustEmbed)]
+#[folder = "assets/"]
+struct Assets;
+
+/// Serves the control page.
+pub async fn index() -> Response {
+    serve("index.html")
+}
+
+/// Serves the page's scripts and styles under `/ui/`.
+pub async fn asset(Path(path): Path<String>) -> Response {
+    serve(&path)
+}
+
+fn serve(path: &str) -> Response {
+    match Assets::get(path) {
+        Some(file) => (
+            [(header::CONTENT_TYPE, file.metadata.mimetype().to_string())],
+            file.data,
+        )
+            .into_response(),
+        None => (StatusCode::NOT_FOUND, "Not found").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_references_embedded_assets() {
+        let index = Assets::get("index.html").unwrap();
+        let html = std::str::from_utf8(&index.data).unwrap();
+        for asset in ["app.js", "style.css"] {
+            assert!(html.contains(&format!("/ui/{}", asset)));
+            assert!(Assets::get(asset).is_some(), "{} not embedded", asset);
+        }
+        assert_eq!(index.metadata.mimetype(), "text/html");
+    }
+}
@@ -0,0 +1,230 @@
+//! JSON Schema of the WebSocket protocol.
+//!
+//! Built from the same `ToSchema` derives as the OpenAPI spec (see
+//! [`crate::openapi`]), so the schema follows the serde shapes of
+//! [`ClientMessage`] and [`ServerMessage`] as they change. Served as
+//! `/ws/schema`: a draft 2020-12 document that accepts any message either
+//! side may send, with one definition per message type under `$defs`
+//! (`client_hello`, `server_snapshot` and so on) for validating a single
+//! type. Binary `field` frames are not JSON and are not covered.
+//!
+//! Not schemars, though that is the usual derive for JSON Schema: every type
+//! a message carries already derives `ToSchema` for the OpenAPI spec, the
+//! core ones behind `ambient_core`'s `openapi` feature, and utoipa's schemas
+//! are JSON Schema once their `$ref`s point at `$defs`. A second derive would
+//! mean a second feature on the core crate and a second set of field
+//! attributes on every type to keep in step with the first, and the REST and
+//! WebSocket schemas could drift apart. The conformance tests below validate
+//! the golden fixtures against the served document, so it is checked as JSON
+//! Schema either way.
+
+use crate::api::{ClientMessage, ServerMessage};
+use crate::protocol::PROTOCOL_VERSION;
+use serde_json::{Map, Value, json};
+use utoipa::OpenApi;
+
+/// Where the schema is served.
+pub const WS_SCHEMA_PATH: &str = "/ws/schema";
+
+const COMPONENTS_PREFIX: &str = "#/components/schemas/";
+const DEFS_PREFIX: &str = "#/$defs/";
+
+#[derive(OpenApi)]
+#[openapi(components(schemas(ClientMessage, ServerMessage)))]
+struct WsMessages;
+
+/// The protocol's schema document.
+pub fn ws_schema() -> Value {
+    let components = WsMessages::openapi()
+        .components
+        .map(|components| components.schemas)
+        .unwrap_or_default();
+    let mut defs = Map::new();
+    for (name, schema) in components {
+        let mut schema = serde_json::to_value(schema).expect("schemas serialize");
+        rewrite_refs(&mut schema);
+        defs.insert(name, schema);
+    }
+    split_variants(&mut defs, "ClientMessage", "client");
+    split_variants(&mut defs, "ServerMessage", "server");
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": WS_SCHEMA_PATH,
+        "title": "Ambient World WebSocket protocol",
+        "description": format!(
+            "Messages of protocol version {PROTOCOL_VERSION}. Every message is a JSON \
+             text frame tagged by `type`; `$defs/client_<type>` and \
+             `$defs/server_<type>` describe each one."
+        ),
+        "anyOf": [
+            { "$ref": format!("{DEFS_PREFIX}ClientMessage") },
+            { "$ref": format!("{DEFS_PREFIX}ServerMessage") },
+        ],
+        "$defs": defs,
+    })
+}
+
+/// Points component references at `$defs`.
+fn rewrite_refs(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    Value::String(target) if key == "$ref" => {
+                        if let Some(name) = target.strip_prefix(COMPONENTS_PREFIX) {
+                            *target = format!("{DEFS_PREFIX}{name}");
+                        }
+                    }
+                    _ => rewrite_refs(value),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(rewrite_refs),
+        _ => {}
+    }
+}
+
+/// Moves each variant of a tagged message enum into its own definition,
+/// `<side>_<type>`, leaving the enum as a `oneOf` of references.
+fn split_variants(defs: &mut Map<String, Value>, name: &str, side: &str) {
+    let Some(Value::Array(variants)) = defs.get_mut(name).and_then(|def| def.get_mut("oneOf"))
+    else {
+        return;
+    };
+    let variants = std::mem::take(variants);
+    let mut refs = Vec::with_capacity(variants.len());
+    for variant in variants {
+        let Some(tag) = message_type(&variant) else {
+            refs.push(variant);
+            continue;
+        };
+        let def_name = format!("{side}_{tag}");
+        refs.push(json!({ "$ref": format!("{DEFS_PREFIX}{def_name}") }));
+        defs.insert(def_name, variant);
+    }
+    defs[name]["oneOf"] = Value::Array(refs);
+}
+
+/// The `type` tag a variant's schema pins.
+fn message_type(variant: &Value) -> Option<String> {
+    variant
+        .pointer("/properties/type/enum/0")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{CLIENT_MESSAGE_TYPES, SERVER_MESSAGE_TYPES};
+    use jsonschema::Validator;
+    use std::path::PathBuf;
+
+    const SIDES: [(&str, &[&str]); 2] = [
+        ("client", CLIENT_MESSAGE_TYPES),
+        ("server", SERVER_MESSAGE_TYPES),
+    ];
+
+    fn fixture_dir(side: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/ws")
+            .join(side)
+    }
+
+    /// The golden message of type `kind` sent by `side`.
+    fn fixture(side: &str, kind: &str) -> Value {
+        let path = fixture_dir(side).join(format!("{kind}.json"));
+        let text =
+            std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        serde_json::from_str(&text).unwrap()
+    }
+
+    /// Validates a single message type, `<side>_<type>`.
+    fn validator(schema: &Value, def: &str) -> Validator {
+        let mut schema = schema.clone();
+        let document = schema.as_object_mut().unwrap();
+        document.remove("anyOf");
+        document.insert("$ref".to_string(), json!(format!("{DEFS_PREFIX}{def}")));
+        jsonschema::validator_for(&schema).unwrap()
+    }
+
+    fn errors(validator: &Validator, message: &Value) -> Vec<String> {
+        validator
+            .iter_errors(message)
+            .map(|error| format!("{} at {}", error, error.instance_path()))
+            .collect()
+    }
+
+    #[test]
+    fn test_every_message_type_has_a_definition() {
+        let schema = ws_schema();
+        for (side, kinds) in SIDES {
+            for kind in kinds {
+                let def = format!("{side}_{kind}");
+                assert!(schema["$defs"][&def].is_object(), "{} missing", def);
+            }
+        }
+        let server_refs = schema["$defs"]["ServerMessage"]["oneOf"]
+            .as_array()
+            .unwrap();
+        assert_eq!(server_refs.len(), SERVER_MESSAGE_TYPES.len());
+        assert_eq!(server_refs[2]["$ref"], "#/$defs/server_snapshot");
+    }
+
+    #[test]
+    fn test_golden_fixtures_conform_to_the_schema() {
+        let schema = ws_schema();
+        let any_message = jsonschema::validator_for(&schema).unwrap();
+        for (side, kinds) in SIDES {
+            for kind in kinds {
+                let message = fixture(side, kind);
+                assert_eq!(message["type"], *kind);
+                let def = format!("{side}_{kind}");
+                let errors = errors(&validator(&schema, &def), &message);
+                assert!(errors.is_empty(), "{}: {:?}", def, errors);
+                assert!(any_message.is_valid(&message), "{}", def);
+            }
+            // No stray fixtures for types the protocol does not have
+            for entry in std::fs::read_dir(fixture_dir(side)).unwrap() {
+                let path = entry.unwrap().path();
+                let kind = path.file_stem().unwrap().to_str().unwrap().to_string();
+                assert!(kinds.contains(&kind.as_str()), "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn test_client_fixtures_are_accepted_by_the_server() {
+        for kind in CLIENT_MESSAGE_TYPES {
+            let message: ClientMessage = serde_json::from_value(fixture("client", kind))
+                .unwrap_or_else(|e| panic!("{}: {}", kind, e));
+            assert_eq!(message.version(), PROTOCOL_VERSION);
+        }
+    }
+
+    #[test]
+    fn test_malformed_messages_are_rejected() {
+        let schema = ws_schema();
+        let any_message = jsonschema::validator_for(&schema).unwrap();
+
+        let mut unknown = fixture("client", "ping");
+        unknown["type"] = json!("warp");
+        assert!(!any_message.is_valid(&unknown));
+
+        let mut no_payload = fixture("server", "hello");
+        no_payload.as_object_mut().unwrap().remove("payload");
+        assert!(!any_message.is_valid(&no_payload));
+
+        let mut bad_tick = fixture("server", "snapshot");
+        bad_tick["payload"]["world"]["tick"] = json!("three");
+        assert!(!validator(&schema, "server_snapshot").is_valid(&bad_tick));
+
+        let mut bad_channel = fixture("client", "subscribe");
+        bad_channel["payload"]["channels"] = json!(["weather"]);
+        assert!(!validator(&schema, "client_subscribe").is_valid(&bad_channel));
+
+        let mut bad_code = fixture("server", "error");
+        bad_code["payload"]["code"] = json!("OOPS");
+        assert!(!validator(&schema, "server_error").is_valid(&bad_code));
+    }
+}
@@ -0,0 +1,108 @@
+//! Sample generation cost per layer and for the full mix, per block of the
+//! size the mixer renders in. Compare runs with and without `--no-default-features
+//! --features cpal` to see what the SIMD kernels buy.
+//!
+//! Run with `cargo bench -p audio`.
+
+use audio::grain::GrainLayer;
+use audio::layers::{DroneLayer, DroneMode, Layer, SparkleLayer, TextureLayer};
+use audio::mapping::WorldInputs;
+use audio::mixer::Mixer;
+use audio::params::AudioParams;
+use audio::sample::{Sample, SampleLayer};
+use audio::wind::WindLayer;
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use std::f32::consts::TAU;
+use std::hint::black_box;
+use std::sync::Arc;
+
+const SAMPLE_RATE: f32 = 48_000.0;
+
+/// Frames per block, as rendered by the mixer.
+const BLOCK_FRAMES: usize = 256;
+
+/// A busy world, so every layer has work to do.
+fn params() -> AudioParams {
+    AudioParams::from_world_state(&WorldInputs {
+        density: 0.8,
+        rhythm: 0.7,
+        tension: 0.6,
+        energy: 0.8,
+        sparkle_impulse: 1.0,
+        ..WorldInputs::default()
+    })
+}
+
+/// Ten seconds of a sine, standing in for a field recording.
+fn samples() -> Arc<[Sample]> {
+    let rate = SAMPLE_RATE as u32;
+    let frames = (0..rate * 10)
+        .map(|i| (TAU * 220.0 * i as f32 / SAMPLE_RATE).sin() * 0.5)
+        .collect();
+    Arc::from(vec![Sample::new("sine", frames, rate)])
+}
+
+fn layers(c: &mut Criterion) {
+    let mut cases: Vec<(&str, Box<dyn Layer>)> = vec![
+        ("drone_dual", Box::new(DroneLayer::new(SAMPLE_RATE))),
+        (
+            "drone_additive_16",
+            Box::new(DroneLayer::with_mode(SAMPLE_RATE, DroneMode::Additive(16))),
+        ),
+        ("texture", Box::new(TextureLayer::new(SAMPLE_RATE))),
+        ("wind", Box::new(WindLayer::new(SAMPLE_RATE))),
+        (
+            "samples",
+            Box::new(SampleLayer::new(SAMPLE_RATE, samples())),
+        ),
+        (
+            "grains",
+            Box::new(GrainLayer::new(SAMPLE_RATE, samples(), 0)),
+        ),
+    ];
+    let params = params();
+    let mut group = c.benchmark_group("layer_block");
+    group.throughput(Throughput::Elements(BLOCK_FRAMES as u64));
+    for (name, layer) in &mut cases {
+        let mut out = vec![0.0f32; BLOCK_FRAMES];
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                layer.process_block(&mut out, black_box(&params));
+                black_box(&out);
+            });
+        });
+    }
+    // Sparkles sound on a rising impulse and are silent in between, so time
+    // the first block of a burst
+    let mut out = vec![0.0f32; BLOCK_FRAMES];
+    group.bench_function("sparkle_burst", |b| {
+        b.iter_batched_ref(
+            || SparkleLayer::new(SAMPLE_RATE),
+            |layer| {
+                layer.process_block(&mut out, black_box(&params));
+                black_box(&out);
+            },
+            BatchSize::SmallInput,
+        );
+    });
+    group.finish();
+}
+
+/// All layers, effects and the master bus into interleaved stereo.
+fn mix(c: &mut Criterion) {
+    let params = params();
+    let mut group = c.benchmark_group("mixer_block");
+    group.throughput(Throughput::Elements(BLOCK_FRAMES as u64));
+    group.bench_function("stereo", |b| {
+        let mut mixer = Mixer::new(SAMPLE_RATE);
+        let mut out = vec![0.0f32; BLOCK_FRAMES * 2];
+        b.iter(|| {
+            mixer.process(&mut out, black_box(&params), 2);
+            black_box(&out);
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, layers, mix);
+criterion_main!(benches);
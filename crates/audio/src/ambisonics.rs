@@ -0,0 +1,239 @@
+//! First-order ambisonics on the horizontal plane.
+//!
+//! Layers are encoded into B-format (W, X, Y, Z, FuMa weighting, X to the
+//! front and Y to the left), summed, and decoded once per block: either to
+//! the speakers of a [`SpeakerLayout`] or, through a ring of virtual speakers
+//! each rendered with [`BinauralPanner`], to headphones. Sources sit on the
+//! horizon, so Z stays silent; it is carried so the bus is standard B-format.
+//!
+//! Speaker decoding projects the sound field onto each speaker with max-rE
+//! weighting, which trades a little localisation for a smoother image
+//! between speakers. It assumes a roughly even ring; layouts with large gaps
+//! (such as 5.1 behind the listener) still work but image less precisely.
+
+use crate::binaural::BinauralPanner;
+use crate::spatial::SpeakerLayout;
+use std::f32::consts::{FRAC_1_SQRT_2, SQRT_2};
+
+/// B-format channels: W, X, Y, Z.
+pub const BFORMAT_CHANNELS: usize = 4;
+
+/// Order-one max-rE weight for a horizontal decode, cos(pi / 4).
+const MAX_RE: f32 = FRAC_1_SQRT_2;
+
+/// Virtual speakers the binaural decoder renders through.
+const VIRTUAL_SPEAKERS: usize = 8;
+
+/// How B-format reaches the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AmbisonicDecoder {
+    /// To the speakers of the layout.
+    #[default]
+    Speakers,
+    /// To two ears, for headphones.
+    Binaural,
+}
+
+/// B-format gains for a source at `azimuth` (degrees clockwise from the
+/// front). `spread` narrows the directional part toward an omnidirectional
+/// field, raising W so the decoded power stays the same.
+pub fn encode(azimuth: f32, spread: f32, gains: &mut [f32]) {
+    let direction = 1.0 - spread.clamp(0.0, 1.0);
+    let omni = (1.0 + 2.0 * MAX_RE * MAX_RE * (1.0 - direction * direction)).sqrt();
+    let (sin, cos) = azimuth.to_radians().sin_cos();
+    gains[0] = omni * FRAC_1_SQRT_2;
+    gains[1] = direction * cos;
+    gains[2] = -direction * sin;
+    gains[3] = 0.0;
+}
+
+/// Projection of B-format onto a speaker at `azimuth`, for `count` speakers,
+/// scaled so a source decodes at unit power on an even ring.
+fn speaker_row(azimuth: f32, count: usize) -> [f32; BFORMAT_CHANNELS] {
+    let scale = (count as f32 / (1.0 + 2.0 * MAX_RE * MAX_RE)).sqrt() / count as f32;
+    let (sin, cos) = azimuth.to_radians().sin_cos();
+    [
+        scale * SQRT_2,
+        scale * 2.0 * MAX_RE * cos,
+        scale * 2.0 * MAX_RE * -sin,
+        0.0,
+    ]
+}
+
+fn decode_into(row: &[f32; BFORMAT_CHANNELS], bformat: &[Vec<f32>], out: &mut [f32]) {
+    for (i, out) in out.iter_mut().enumerate() {
+        *out = row
+            .iter()
+            .zip(bformat)
+            .map(|(gain, channel)| gain * channel[i])
+            .sum();
+    }
+}
+
+/// Decodes a block of B-format to output channels. Allocates on
+/// construction only.
+pub enum Decoder {
+    /// One row per layout channel; LFE rows are zero.
+    Speakers(Vec<[f32; BFORMAT_CHANNELS]>),
+    /// Virtual speakers, each heard through a fixed binaural panner.
+    Binaural {
+        speakers: Vec<([f32; BFORMAT_CHANNELS], BinauralPanner)>,
+        /// One virtual speaker's feed.
+        feed: Vec<f32>,
+    },
+}
+
+impl Decoder {
+    pub fn new(
+        sample_rate: f32,
+        decoder: AmbisonicDecoder,
+        layout: &SpeakerLayout,
+        max_block: usize,
+    ) -> Self {
+        match decoder {
+            AmbisonicDecoder::Speakers => {
+                let count = layout.speakers().iter().flatten().count().max(1);
+                Decoder::Speakers(
+                    layout
+                        .speakers()
+                        .iter()
+                        .map(|azimuth| match azimuth {
+                            Some(azimuth) => speaker_row(*azimuth, count),
+                            None => [0.0; BFORMAT_CHANNELS],
+                        })
+                        .collect(),
+                )
+            }
+            AmbisonicDecoder::Binaural => Decoder::Binaural {
+                speakers: SpeakerLayout::ring(VIRTUAL_SPEAKERS)
+                    .speakers()
+                    .iter()
+                    .flatten()
+                    .map(|azimuth| {
+                        (
+                            speaker_row(*azimuth, VIRTUAL_SPEAKERS),
+                            BinauralPanner::new(sample_rate, *azimuth),
+                        )
+                    })
+                    .collect(),
+                feed: vec![0.0; max_block],
+            },
+        }
+    }
+
+    /// Output channels: the layout's, or left and right.
+    pub fn channels(&self) -> usize {
+        match self {
+            Decoder::Speakers(rows) => rows.len(),
+            Decoder::Binaural { .. } => 2,
+        }
+    }
+
+    /// Decodes `frames` of `bformat` into `channels`, replacing their contents.
+    pub fn decode(&mut self, bformat: &[Vec<f32>], channels: &mut [Vec<f32>], frames: usize) {
+        match self {
+            Decoder::Speakers(rows) => {
+                for (row, channel) in rows.iter().zip(channels.iter_mut()) {
+                    decode_into(row, bformat, &mut channel[..frames]);
+                }
+            }
+            Decoder::Binaural { speakers, feed } => {
+                let [left, right] = channels else {
+                    return;
+                };
+                left[..frames].fill(0.0);
+                right[..frames].fill(0.0);
+                for (row, panner) in speakers {
+                    decode_into(row, bformat, &mut feed[..frames]);
+                    panner.process(&feed[..frames], &mut left[..frames], &mut right[..frames]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decoded speaker gains for a unit source at `azimuth`.
+    fn decode_source(layout: &SpeakerLayout, azimuth: f32) -> Vec<f32> {
+        let mut decoder = Decoder::new(48_000.0, AmbisonicDecoder::Speakers, layout, 1);
+        let mut gains = [0.0; BFORMAT_CHANNELS];
+        encode(azimuth, 0.0, &mut gains);
+        let bformat: Vec<Vec<f32>> = gains.iter().map(|g| vec![*g]).collect();
+        let mut channels = vec![vec![0.0]; layout.channels()];
+        decoder.decode(&bformat, &mut channels, 1);
+        channels.into_iter().map(|c| c[0]).collect()
+    }
+
+    fn power(gains: &[f32]) -> f32 {
+        gains.iter().map(|g| g * g).sum()
+    }
+
+    #[test]
+    fn test_even_ring_decodes_at_unit_power() {
+        for layout in [SpeakerLayout::quad(), SpeakerLayout::ring(6)] {
+            for azimuth in (0..360).step_by(15) {
+                let gains = decode_source(&layout, azimuth as f32);
+                assert!(
+                    (power(&gains) - 1.0).abs() < 1e-4,
+                    "{:?} {}",
+                    layout,
+                    azimuth
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_source_is_loudest_at_its_speaker() {
+        let layout = SpeakerLayout::quad();
+        // Rear right is channel 3
+        let gains = decode_source(&layout, 135.0);
+        let loudest = (0..4)
+            .max_by(|a, b| gains[*a].total_cmp(&gains[*b]))
+            .unwrap();
+        assert_eq!(loudest, 3);
+        // The opposite speaker is quietest
+        assert!(gains[0] < gains[1] && gains[0] < gains[2]);
+    }
+
+    #[test]
+    fn test_spread_keeps_power() {
+        let layout = SpeakerLayout::ring(8);
+        let mut decoder = Decoder::new(48_000.0, AmbisonicDecoder::Speakers, &layout, 1);
+        let mut channels = vec![vec![0.0]; 8];
+        for spread in [0.0, 0.5, 1.0] {
+            let mut gains = [0.0; BFORMAT_CHANNELS];
+            encode(30.0, spread, &mut gains);
+            let bformat: Vec<Vec<f32>> = gains.iter().map(|g| vec![*g]).collect();
+            decoder.decode(&bformat, &mut channels, 1);
+            let decoded: Vec<f32> = channels.iter().map(|c| c[0]).collect();
+            assert!((power(&decoded) - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_binaural_decode_favours_the_near_ear() {
+        let mut decoder = Decoder::new(
+            48_000.0,
+            AmbisonicDecoder::Binaural,
+            &SpeakerLayout::mono(),
+            512,
+        );
+        assert_eq!(decoder.channels(), 2);
+        let mut gains = [0.0; BFORMAT_CHANNELS];
+        encode(-90.0, 0.0, &mut gains);
+        let noise: Vec<f32> = (0..512)
+            .map(|i| ((i * 7919) % 113) as f32 / 56.0 - 1.0)
+            .collect();
+        let bformat: Vec<Vec<f32>> = gains
+            .iter()
+            .map(|g| noise.iter().map(|s| s * g).collect())
+            .collect();
+        let mut channels = vec![vec![0.0; 512]; 2];
+        decoder.decode(&bformat, &mut channels, 512);
+        assert!(power(&channels[0]) > 2.0 * power(&channels[1]));
+    }
+}
@@ -0,0 +1,489 @@
+//! Where the mixed output goes: a sound card, nowhere, or memory.
+//!
+//! Every backend drives the same [`Renderer`], so a headless server or a test
+//! runs exactly the layer pipeline a device would play:
+//!
+//! - [`BackendKind::Cpal`] plays through the default output device (`cpal`
+//!   feature; see [`crate::engine::AudioEngine`]).
+//! - [`NullBackend`] renders in real time and discards the output.
+//! - [`BufferBackend`] renders into a [`CaptureBuffer`], in real time or as
+//!   fast as it can, for tests and offline renders.
+//!
+//! Backends are started and restarted by the [`crate::watchdog`].
+
+use crate::ducking::DuckingSettings;
+use crate::effects::SharedEffects;
+use crate::gate::GateSettings;
+use crate::health::SharedOutputHealth;
+use crate::loudness::LoudnessSettings;
+use crate::master::SharedMeter;
+use crate::mixer::{Mixer, SharedLayerGains};
+use crate::params::SharedAudioParams;
+use crate::registry::{LayerLink, LayerRegistry};
+use crate::spatial::SpatialSettings;
+use crate::tap::OutputTap;
+use crate::telemetry::{CallbackTelemetry, callback_load};
+use crate::transition::SharedTransition;
+use crate::waveform::{BinFolder, SharedWaveform};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Frames rendered per block by the backends that keep their own clock.
+const BLOCK_FRAMES: usize = 512;
+
+/// Everything needed to build (and rebuild) the engine.
+/// The shared handles stay the same across restarts, so the control side never notices.
+#[derive(Clone)]
+pub struct EngineSetup {
+    pub params: Arc<SharedAudioParams>,
+    pub gains: Arc<SharedLayerGains>,
+    /// Effect chains and which members are switched on.
+    pub effects: Arc<SharedEffects>,
+    pub transition: Arc<SharedTransition>,
+    pub meter: Arc<SharedMeter>,
+    /// Min/max envelope of the recent output.
+    pub waveform: Arc<SharedWaveform>,
+    /// Silence and bad samples in the output, and restart requests.
+    pub health: Arc<SharedOutputHealth>,
+    /// Callback load and xruns, recorded by every callback.
+    pub telemetry: Arc<CallbackTelemetry>,
+    /// The layer stack, built afresh by each renderer and changeable while
+    /// it plays.
+    pub layers: Arc<LayerRegistry>,
+    pub ducking: DuckingSettings,
+    /// Anti-click gate on the master gain.
+    pub gate: GateSettings,
+    pub loudness: LoudnessSettings,
+    /// Speaker layout and where each layer sits on it.
+    pub spatial: SpatialSettings,
+    /// Copies of the output for the stream encoder and the recorder, while
+    /// they listen.
+    pub taps: Vec<Arc<OutputTap>>,
+}
+
+/// The mixer built from an [`EngineSetup`], and the per-callback work every
+/// backend shares.
+pub struct Renderer {
+    mixer: Mixer,
+    link: LayerLink,
+    setup: EngineSetup,
+    sample_rate: u32,
+    channels: u16,
+    transition_seen: u32,
+    bins: BinFolder,
+}
+
+impl Renderer {
+    pub fn new(setup: &EngineSetup, sample_rate: u32, channels: u16) -> Self {
+        let rate = sample_rate as f32;
+        let mut mixer = Mixer::with_gains(rate, setup.gains.get());
+        mixer.set_effects(rate, &setup.effects.chains());
+        mixer.set_spatial(rate, &setup.spatial);
+        let layout = setup.spatial.channels();
+        let (layers, link) = setup.layers.attach(rate, layout);
+        mixer.set_layers(layers);
+        if layout > 1 && layout != usize::from(channels) {
+            warn!(
+                "Spatial mix has {} channels but the output has {}",
+                layout, channels
+            );
+        }
+        mixer.set_ducking(setup.ducking);
+        mixer.set_gate(setup.gate);
+        mixer.set_loudness(setup.loudness);
+        Self {
+            mixer,
+            link,
+            setup: setup.clone(),
+            sample_rate,
+            channels,
+            transition_seen: 0,
+            bins: BinFolder::new(sample_rate),
+        }
+    }
+
+    /// Fills `output` (interleaved) from the latest layer changes,
+    /// parameters, gains, effects and scene crossfade, then publishes the meter, waveform,
+    /// output health and callback load and offers the block to the taps.
+    pub fn render(&mut self, output: &mut [f32]) {
+        let started = Instant::now();
+        let setup = &self.setup;
+        self.link.sync(&mut self.mixer);
+        self.mixer.set_gains(setup.gains.get());
+        self.mixer.set_effects_enabled(&setup.effects);
+        self.mixer.hold_loudness_trim(setup.meter.trim_held());
+        if let Some((secs, curve)) = setup.transition.poll(&mut self.transition_seen) {
+            self.mixer.start_transition(secs, curve);
+        }
+        self.mixer
+            .process(output, &setup.params.get(), self.channels);
+        setup.meter.set(self.mixer.meter());
+        setup.meter.set_layers(&self.mixer.layer_levels());
+        self.bins.fold(output, self.channels, &setup.waveform);
+        setup.health.record(output, self.channels, self.sample_rate);
+        let format = OutputFormat {
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+        };
+        for tap in &setup.taps {
+            tap.push(output, format);
+        }
+        let frames = output.len() / self.channels as usize;
+        setup
+            .telemetry
+            .record(callback_load(started.elapsed(), frames, self.sample_rate));
+    }
+}
+
+impl Drop for Renderer {
+    /// Hands the layers back to the registry, so the renderer of a rebuilt
+    /// engine plays on from here.
+    fn drop(&mut self) {
+        self.setup.layers.detach(&mut self.link, &mut self.mixer);
+    }
+}
+
+/// A running output, as the watchdog sees it.
+pub trait AudioBackend {
+    /// Device, or backend, name shown in the audio status.
+    fn device_name(&self) -> &str;
+    fn sample_rate(&self) -> u32;
+    fn channels(&self) -> u16;
+    /// Number of callbacks run so far, so a stalled output can be spotted.
+    fn callback_count(&self) -> u64;
+    /// The error that stopped the output, if it can no longer play.
+    fn fatal_error(&self) -> Option<String>;
+}
+
+/// Sample rate and channel count of a backend without a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48_000,
+            channels: 2,
+        }
+    }
+}
+
+/// Which backend the watchdog starts.
+#[derive(Clone)]
+pub enum BackendKind {
+    /// The default output device.
+    #[cfg(feature = "cpal")]
+    Cpal,
+    /// Rendered in real time and discarded.
+    Null(OutputFormat),
+    /// Rendered into `capture`; as fast as possible until it is full unless
+    /// `realtime`, then in real time.
+    Buffer {
+        format: OutputFormat,
+        capture: CaptureBuffer,
+        realtime: bool,
+    },
+}
+
+impl BackendKind {
+    /// Starts the backend.
+    pub fn start(&self, setup: &EngineSetup) -> Result<Box<dyn AudioBackend>, anyhow::Error> {
+        Ok(match self {
+            #[cfg(feature = "cpal")]
+            BackendKind::Cpal => Box::new(crate::engine::AudioEngine::start(setup)?),
+            BackendKind::Null(format) => Box::new(NullBackend::start(setup, *format)?),
+            BackendKind::Buffer {
+                format,
+                capture,
+                realtime,
+            } => Box::new(BufferBackend::start(
+                setup,
+                *format,
+                capture.clone(),
+                *realtime,
+            )?),
+        })
+    }
+}
+
+/// Interleaved samples captured by a [`BufferBackend`], up to a fixed
+/// capacity; later output is dropped. Shared, so it outlives restarts.
+#[derive(Clone, Debug)]
+pub struct CaptureBuffer {
+    samples: Arc<Mutex<Vec<f32>>>,
+    capacity: usize,
+}
+
+impl CaptureBuffer {
+    /// Room for `secs` seconds of `format`.
+    pub fn new(format: OutputFormat, secs: f32) -> Self {
+        let capacity =
+            (format.sample_rate as f32 * secs.max(0.0)) as usize * format.channels as usize;
+        Self {
+            samples: Arc::new(Mutex::new(Vec::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Appends what fits of `block`.
+    fn push(&self, block: &[f32]) {
+        let mut samples = self.samples.lock().unwrap();
+        let room = self.capacity - samples.len();
+        samples.extend_from_slice(&block[..block.len().min(room)]);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity
+    }
+
+    /// A copy of what has been captured so far.
+    pub fn samples(&self) -> Vec<f32> {
+        self.samples.lock().unwrap().clone()
+    }
+
+    /// Writes the capture as a 32-bit float WAV file.
+    pub fn write_wav(&self, path: &Path, format: OutputFormat) -> Result<(), hound::Error> {
+        crate::rolling::write_wav(path, format, &self.samples.lock().unwrap())
+    }
+}
+
+/// A thread that renders blocks on its own clock and hands each to `sink`.
+/// Dropping it stops the thread.
+struct ClockedOutput {
+    name: String,
+    format: OutputFormat,
+    callbacks: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ClockedOutput {
+    /// Starts rendering. Blocks are paced to real time while `pace` returns
+    /// true, and rendered back to back otherwise.
+    fn spawn(
+        name: &str,
+        setup: &EngineSetup,
+        format: OutputFormat,
+        mut sink: impl FnMut(&[f32]) + Send + 'static,
+        pace: impl Fn() -> bool + Send + 'static,
+    ) -> std::io::Result<Self> {
+        let mut renderer = Renderer::new(setup, format.sample_rate, format.channels);
+        let callbacks = Arc::new(AtomicU64::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_callbacks = Arc::clone(&callbacks);
+        let thread_stop = Arc::clone(&stop);
+        let block_secs = BLOCK_FRAMES as f64 / format.sample_rate as f64;
+        let block = Duration::from_secs_f64(block_secs);
+        let thread = std::thread::Builder::new()
+            .name(format!("audio-{}", name))
+            .spawn(move || {
+                let mut output = vec![0.0f32; BLOCK_FRAMES * format.channels as usize];
+                let mut next = Instant::now();
+                while !thread_stop.load(Ordering::Relaxed) {
+                    renderer.render(&mut output);
+                    thread_callbacks.fetch_add(1, Ordering::Relaxed);
+                    sink(&output);
+                    if !pace() {
+                        next = Instant::now();
+                        continue;
+                    }
+                    next += block;
+                    let now = Instant::now();
+                    if next > now {
+                        std::thread::sleep(next - now);
+                    } else if now - next > block * 4 {
+                        // Fell far behind (e.g. suspended); don't race to catch up
+                        next = now;
+                    }
+                }
+            })?;
+        Ok(Self {
+            name: name.to_string(),
+            format,
+            callbacks,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for ClockedOutput {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Backends built on a [`ClockedOutput`].
+trait Clocked {
+    fn output(&self) -> &ClockedOutput;
+}
+
+impl<T: Clocked> AudioBackend for T {
+    fn device_name(&self) -> &str {
+        &self.output().name
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.output().format.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.output().format.channels
+    }
+
+    fn callback_count(&self) -> u64 {
+        self.output().callbacks.load(Ordering::Relaxed)
+    }
+
+    /// Nothing outside the process can stop a clocked output.
+    fn fatal_error(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Renders in real time and throws the output away, for headless servers
+/// that still want the meter, loudness and callback telemetry.
+pub struct NullBackend(ClockedOutput);
+
+impl NullBackend {
+    pub fn start(setup: &EngineSetup, format: OutputFormat) -> std::io::Result<Self> {
+        ClockedOutput::spawn("null", setup, format, |_| {}, || true).map(Self)
+    }
+}
+
+impl Clocked for NullBackend {
+    fn output(&self) -> &ClockedOutput {
+        &self.0
+    }
+}
+
+/// Renders into a [`CaptureBuffer`].
+pub struct BufferBackend(ClockedOutput);
+
+impl BufferBackend {
+    /// Starts capturing into `capture`. Unless `realtime`, blocks are rendered
+    /// back to back until the capture is full, then in real time.
+    pub fn start(
+        setup: &EngineSetup,
+        format: OutputFormat,
+        capture: CaptureBuffer,
+        realtime: bool,
+    ) -> std::io::Result<Self> {
+        let full = capture.clone();
+        ClockedOutput::spawn(
+            "buffer",
+            setup,
+            format,
+            move |block| capture.push(block),
+            move || realtime || full.is_full(),
+        )
+        .map(Self)
+    }
+}
+
+impl Clocked for BufferBackend {
+    fn output(&self) -> &ClockedOutput {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::effects::EffectChains;
+    use crate::layers::DroneMode;
+    use crate::mapping::WorldInputs;
+    use crate::noise::NoiseColors;
+    use crate::params::AudioParams;
+    use crate::registry::default_stack;
+
+    /// A setup with the default stack and settings, for backend tests.
+    pub(crate) fn setup() -> EngineSetup {
+        EngineSetup {
+            params: Arc::new(SharedAudioParams::new(AudioParams::from_world_state(
+                &WorldInputs {
+                    energy: 1.0,
+                    ..WorldInputs::default()
+                },
+            ))),
+            gains: Arc::new(SharedLayerGains::new(Default::default())),
+            effects: Arc::new(SharedEffects::new(EffectChains::default())),
+            transition: Arc::new(SharedTransition::new()),
+            meter: Arc::new(SharedMeter::new()),
+            waveform: Arc::new(SharedWaveform::new()),
+            health: Arc::new(SharedOutputHealth::new()),
+            telemetry: Arc::new(CallbackTelemetry::new()),
+            layers: Arc::new(LayerRegistry::new(
+                default_stack(DroneMode::default(), NoiseColors::default(), None, false),
+                Arc::from(Vec::new()),
+            )),
+            ducking: DuckingSettings::default(),
+            gate: GateSettings::default(),
+            loudness: LoudnessSettings::default(),
+            spatial: SpatialSettings::default(),
+            taps: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_buffer_backend_captures_the_pipeline() {
+        let setup = setup();
+        let format = OutputFormat {
+            sample_rate: 8_000,
+            channels: 2,
+        };
+        let capture = CaptureBuffer::new(format, 1.0);
+        let backend = BufferBackend::start(&setup, format, capture.clone(), false).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !capture.is_full() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(backend.device_name(), "buffer");
+        assert_eq!(backend.channels(), 2);
+        assert!(backend.callback_count() > 0);
+        drop(backend);
+
+        let samples = capture.samples();
+        assert_eq!(samples.len(), 16_000);
+        assert!(samples.iter().all(|s| (-1.0..=1.0).contains(s)));
+        assert!(samples[8_000..].iter().any(|s| s.abs() > 0.0));
+        // The meter and telemetry saw the same callbacks
+        assert!(setup.meter.get().peak_db > crate::master::METER_FLOOR_DB);
+        assert!(setup.telemetry.stats().callbacks > 0);
+
+        let path = std::env::temp_dir().join(format!("ambient-capture-{}.wav", std::process::id()));
+        capture.write_wav(&path, format).unwrap();
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.len(), 16_000);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_null_backend_runs_in_real_time() {
+        let backend = NullBackend::start(&setup(), OutputFormat::default()).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        let callbacks = backend.callback_count();
+        // 512-frame blocks at 48 kHz: about 19 in 200 ms
+        assert!((5..=40).contains(&callbacks), "{} callbacks", callbacks);
+        assert_eq!(backend.fatal_error(), None);
+    }
+}
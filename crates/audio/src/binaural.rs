@@ -0,0 +1,182 @@
+//! Headphone rendering of a mono source from a direction, with the two cues
+//! that matter most for placing it on the horizontal plane: the time it takes
+//! sound to reach the far ear (ITD) and the shadow the head casts on it (ILD).
+//!
+//! Both follow the spherical-head model of Brown and Duda: the delay grows
+//! with the path around the head, and each ear has a one-pole, one-zero shelf
+//! that lifts high frequencies facing the source and cuts them behind the
+//! head. There is no pinna filtering, so front and back are not told apart.
+
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// Head radius in metres.
+const HEAD_RADIUS: f32 = 0.0875;
+
+/// Speed of sound in metres per second.
+const SPEED_OF_SOUND: f32 = 343.0;
+
+/// Shadow at its deepest, as high-frequency gain.
+const ALPHA_MIN: f32 = 0.1;
+
+/// Angle from the ear axis where the shadow is deepest.
+const THETA_MIN: f32 = 150.0 * PI / 180.0;
+
+/// Longest interaural delay: the path from one side of the head to the other.
+fn max_delay_secs() -> f32 {
+    HEAD_RADIUS / SPEED_OF_SOUND * (1.0 + FRAC_PI_2)
+}
+
+/// One ear: a fractional delay and the head-shadow shelf.
+struct Ear {
+    /// Direction the ear faces, in degrees clockwise from the front.
+    axis: f32,
+    delay: Vec<f32>,
+    pos: usize,
+    delay_samples: f32,
+    b0: f32,
+    b1: f32,
+    a1: f32,
+    last_in: f32,
+    last_out: f32,
+}
+
+impl Ear {
+    fn new(sample_rate: f32, axis: f32) -> Self {
+        let len = (max_delay_secs() * sample_rate).ceil() as usize + 2;
+        Self {
+            axis,
+            delay: vec![0.0; len],
+            pos: 0,
+            delay_samples: 0.0,
+            b0: 1.0,
+            b1: 0.0,
+            a1: 0.0,
+            last_in: 0.0,
+            last_out: 0.0,
+        }
+    }
+
+    fn set_azimuth(&mut self, sample_rate: f32, azimuth: f32) {
+        // Angle between the source and the ear axis, 0 to pi
+        let theta = ((azimuth - self.axis + 180.0).rem_euclid(360.0) - 180.0)
+            .abs()
+            .to_radians();
+        let delay = if theta < FRAC_PI_2 {
+            1.0 - theta.cos()
+        } else {
+            1.0 + theta - FRAC_PI_2
+        };
+        self.delay_samples = HEAD_RADIUS / SPEED_OF_SOUND * delay * sample_rate;
+
+        let alpha =
+            (1.0 + ALPHA_MIN / 2.0) + (1.0 - ALPHA_MIN / 2.0) * (theta / THETA_MIN * PI).cos();
+        // Bilinear transform of (alpha * s + beta) / (s + beta)
+        let beta = 2.0 * SPEED_OF_SOUND / HEAD_RADIUS;
+        let k = 2.0 * sample_rate;
+        self.b0 = (alpha * k + beta) / (k + beta);
+        self.b1 = (beta - alpha * k) / (k + beta);
+        self.a1 = (beta - k) / (k + beta);
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let len = self.delay.len();
+        self.delay[self.pos] = input;
+        let read = self.pos as f32 + len as f32 - self.delay_samples;
+        let whole = read.floor();
+        let frac = read - whole;
+        let a = self.delay[whole as usize % len];
+        let b = self.delay[(whole as usize + 1) % len];
+        self.pos = (self.pos + 1) % len;
+        let delayed = a + (b - a) * frac;
+
+        let output = self.b0 * delayed + self.b1 * self.last_in - self.a1 * self.last_out;
+        self.last_in = delayed;
+        self.last_out = output;
+        output
+    }
+}
+
+/// Renders a mono source to the left and right ears from an azimuth in
+/// degrees clockwise from the front. Allocates on construction only.
+pub struct BinauralPanner {
+    sample_rate: f32,
+    gain: f32,
+    left: Ear,
+    right: Ear,
+}
+
+impl BinauralPanner {
+    pub fn new(sample_rate: f32, azimuth: f32) -> Self {
+        let mut panner = Self {
+            sample_rate,
+            gain: 1.0,
+            left: Ear::new(sample_rate, -90.0),
+            right: Ear::new(sample_rate, 90.0),
+        };
+        panner.set_azimuth(azimuth);
+        panner
+    }
+
+    /// Moves the source, effective from the next sample.
+    pub fn set_azimuth(&mut self, azimuth: f32) {
+        self.left.set_azimuth(self.sample_rate, azimuth);
+        self.right.set_azimuth(self.sample_rate, azimuth);
+    }
+
+    /// Scales the source, effective from the next sample.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    /// Adds `input`, as heard by each ear, into `left` and `right`.
+    pub fn process(&mut self, input: &[f32], left: &mut [f32], right: &mut [f32]) {
+        for ((sample, l), r) in input.iter().zip(left.iter_mut()).zip(right.iter_mut()) {
+            let sample = sample * self.gain;
+            *l += self.left.process(sample);
+            *r += self.right.process(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn impulse_response(azimuth: f32) -> (Vec<f32>, Vec<f32>) {
+        let mut panner = BinauralPanner::new(48_000.0, azimuth);
+        let mut input = vec![0.0; 256];
+        input[0] = 1.0;
+        let mut left = vec![0.0; 256];
+        let mut right = vec![0.0; 256];
+        panner.process(&input, &mut left, &mut right);
+        (left, right)
+    }
+
+    fn onset(response: &[f32]) -> usize {
+        response.iter().position(|s| s.abs() > 1e-3).unwrap()
+    }
+
+    fn energy(response: &[f32]) -> f32 {
+        response.iter().map(|s| s * s).sum()
+    }
+
+    #[test]
+    fn test_source_on_the_right_reaches_the_right_ear_first_and_louder() {
+        let (left, right) = impulse_response(90.0);
+        assert_eq!(onset(&right), 0);
+        // About 0.66 ms round the head at 48 kHz
+        let lag = onset(&left);
+        assert!((28..=34).contains(&lag), "lag {}", lag);
+        assert!(energy(&right) > 4.0 * energy(&left));
+    }
+
+    #[test]
+    fn test_front_is_symmetric() {
+        let (left, right) = impulse_response(0.0);
+        for (l, r) in left.iter().zip(&right) {
+            assert!((l - r).abs() < 1e-6);
+        }
+        let (left, right) = impulse_response(-60.0);
+        assert!(energy(&left) > energy(&right));
+    }
+}
@@ -0,0 +1,117 @@
+//! Sidechain-style ducking of the bed layers under sparkles.
+//!
+//! An envelope follower tracks the sparkle layer's output and pulls the drone
+//! and texture down while a sparkle rings, so transients read clearly without
+//! raising their own gain.
+
+/// Sidechain envelope level at which the full ducking depth is reached.
+const FULL_DUCK_LEVEL: f32 = 0.25;
+
+/// How the bed reacts to sparkles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DuckingSettings {
+    /// Largest fraction of bed gain removed (0 disables ducking).
+    pub depth: f32,
+    /// Time for the envelope to rise toward a new peak.
+    pub attack_ms: f32,
+    /// Time for the envelope to fall back once the sparkle fades.
+    pub release_ms: f32,
+}
+
+impl Default for DuckingSettings {
+    fn default() -> Self {
+        Self {
+            depth: 0.3,
+            attack_ms: 5.0,
+            release_ms: 250.0,
+        }
+    }
+}
+
+/// Envelope follower that turns a sidechain signal into a bed gain.
+pub struct Ducker {
+    settings: DuckingSettings,
+    sample_rate: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    envelope: f32,
+}
+
+impl Ducker {
+    pub fn new(sample_rate: f32, settings: DuckingSettings) -> Self {
+        let mut ducker = Self {
+            settings,
+            sample_rate,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            envelope: 0.0,
+        };
+        ducker.set_settings(settings);
+        ducker
+    }
+
+    /// Replaces the settings, keeping the current envelope.
+    pub fn set_settings(&mut self, settings: DuckingSettings) {
+        self.settings = settings;
+        self.attack_coeff = Self::coeff(settings.attack_ms, self.sample_rate);
+        self.release_coeff = Self::coeff(settings.release_ms, self.sample_rate);
+    }
+
+    /// One-pole coefficient reaching ~63% of a step in `ms`.
+    fn coeff(ms: f32, sample_rate: f32) -> f32 {
+        let samples = ms.max(0.0) * 0.001 * sample_rate;
+        if samples < 1.0 {
+            0.0
+        } else {
+            (-1.0 / samples).exp()
+        }
+    }
+
+    /// Feeds one sidechain sample and returns the gain to apply to the bed.
+    pub fn process(&mut self, sidechain: f32) -> f32 {
+        let level = sidechain.abs();
+        let coeff = if level > self.envelope {
+            self.attack_coeff
+        } else {
+            self.release_coeff
+        };
+        self.envelope = level + (self.envelope - level) * coeff;
+
+        let amount = (self.envelope / FULL_DUCK_LEVEL).min(1.0);
+        1.0 - self.settings.depth.clamp(0.0, 1.0) * amount
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ducks_then_recovers() {
+        let mut ducker = Ducker::new(1_000.0, DuckingSettings::default());
+        assert_eq!(ducker.process(0.0), 1.0);
+
+        // A loud burst pulls the bed down by the full depth within the attack
+        let mut gain = 1.0;
+        for _ in 0..50 {
+            gain = ducker.process(0.5);
+        }
+        assert!((gain - 0.7).abs() < 1e-3);
+
+        // After several release times the bed is back at unity
+        for _ in 0..2_000 {
+            gain = ducker.process(0.0);
+        }
+        assert!(gain > 0.999);
+    }
+
+    #[test]
+    fn test_zero_depth_never_ducks() {
+        let settings = DuckingSettings {
+            depth: 0.0,
+            ..DuckingSettings::default()
+        };
+        let mut ducker = Ducker::new(1_000.0, settings);
+        assert!((0..100).all(|_| ducker.process(1.0) == 1.0));
+    }
+}
@@ -0,0 +1,802 @@
+//! Effects applied to mixer buses rather than to a single layer.
+//!
+//! Each bus (the drone, the sparkles, and the whole mix ahead of the master
+//! bus) runs an [`EffectChain`] whose members and order come from config, and
+//! whose members can be switched in and out while the stream runs.
+//!
+//! [`Chorus`] reads a few copies of the signal from a delay line at slowly
+//! wandering offsets, so the steady oscillators sound like an ensemble of
+//! slightly mistuned players rather than one. [`Delay`] lands its echoes on
+//! beat divisions of a tempo taken from rhythm, or of the world's pulse grid
+//! when it is locked to it, so sparkles trail off in time.
+//! [`Reverb`] adds a diffuse tail whose size and pre-delay follow the world's
+//! space, and [`Limiter`] holds peaks under a ceiling.
+
+use crate::master::LIMITER_CEILING;
+use crate::params::{AudioParams, MAX_PRE_DELAY_MS, MOTION_SCALE, TEXTURE_SCALE};
+use crate::smoother::Smoother;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// An effect inserted on a bus. Output replaces the input, dry signal included.
+pub trait Effect: Send {
+    /// Processes a block of the bus in place. `params` are the block's latest.
+    fn process_block(&mut self, block: &mut [f32], params: &AudioParams);
+}
+
+/// Effects that can be named in a chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EffectKind {
+    Chorus,
+    Delay,
+    Reverb,
+    Limiter,
+}
+
+impl EffectKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EffectKind::Chorus => "chorus",
+            EffectKind::Delay => "delay",
+            EffectKind::Reverb => "reverb",
+            EffectKind::Limiter => "limiter",
+        }
+    }
+
+    fn build(&self, sample_rate: f32) -> Box<dyn Effect> {
+        match self {
+            EffectKind::Chorus => Box::new(Chorus::new(sample_rate)),
+            EffectKind::Delay => Box::new(Delay::new(sample_rate)),
+            EffectKind::Reverb => Box::new(Reverb::new(sample_rate)),
+            EffectKind::Limiter => Box::new(Limiter::new(sample_rate)),
+        }
+    }
+}
+
+impl FromStr for EffectKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chorus" => Ok(EffectKind::Chorus),
+            "delay" => Ok(EffectKind::Delay),
+            "reverb" => Ok(EffectKind::Reverb),
+            "limiter" => Ok(EffectKind::Limiter),
+            _ => Err(format!(
+                "unknown effect '{}' (expected chorus, delay, reverb or limiter)",
+                s
+            )),
+        }
+    }
+}
+
+/// Buses that carry an effect chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EffectBus {
+    /// The drone, before it joins the rest of the bed.
+    Drone,
+    /// The sparkles, after they key the ducker.
+    Sparkle,
+    /// The whole mix, ahead of master gain and the master limiter.
+    Master,
+}
+
+impl EffectBus {
+    pub const ALL: [EffectBus; 3] = [EffectBus::Drone, EffectBus::Sparkle, EffectBus::Master];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EffectBus::Drone => "drone",
+            EffectBus::Sparkle => "sparkle",
+            EffectBus::Master => "master",
+        }
+    }
+}
+
+impl FromStr for EffectBus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EffectBus::ALL
+            .into_iter()
+            .find(|bus| bus.name() == s)
+            .ok_or_else(|| format!("unknown bus '{}' (expected drone, sparkle or master)", s))
+    }
+}
+
+/// Most effects on one bus.
+pub const MAX_CHAIN_LEN: usize = 8;
+
+/// Effects on each bus, in processing order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EffectChains {
+    pub drone: Vec<EffectKind>,
+    pub sparkle: Vec<EffectKind>,
+    pub master: Vec<EffectKind>,
+}
+
+impl Default for EffectChains {
+    fn default() -> Self {
+        Self {
+            drone: vec![EffectKind::Chorus],
+            sparkle: vec![EffectKind::Delay],
+            master: Vec::new(),
+        }
+    }
+}
+
+impl EffectChains {
+    pub fn get(&self, bus: EffectBus) -> &[EffectKind] {
+        match bus {
+            EffectBus::Drone => &self.drone,
+            EffectBus::Sparkle => &self.sparkle,
+            EffectBus::Master => &self.master,
+        }
+    }
+}
+
+/// The configured chains plus which of their members are switched on,
+/// shared between the control side and the audio callback. Only the masks
+/// are read from the callback; the chains are read when a renderer builds.
+#[derive(Debug)]
+pub struct SharedEffects {
+    chains: Mutex<EffectChains>,
+    /// Per bus, bit `i` set when member `i` is on.
+    enabled: [AtomicU32; 3],
+}
+
+impl SharedEffects {
+    /// Starts with every member switched on.
+    pub fn new(chains: EffectChains) -> Self {
+        Self {
+            chains: Mutex::new(chains),
+            enabled: std::array::from_fn(|_| AtomicU32::new(u32::MAX)),
+        }
+    }
+
+    pub fn chains(&self) -> EffectChains {
+        self.chains.lock().unwrap().clone()
+    }
+
+    /// Replaces the chains with every member switched on. The playing mixer
+    /// needs the new chains sent to it separately.
+    pub fn set_chains(&self, chains: EffectChains) {
+        *self.chains.lock().unwrap() = chains;
+        for mask in &self.enabled {
+            mask.store(u32::MAX, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_enabled(&self, bus: EffectBus, index: usize) -> bool {
+        self.mask(bus) & (1 << index) != 0
+    }
+
+    /// Switches member `index` of `bus` on or off, effective from the next callback.
+    pub fn set_enabled(&self, bus: EffectBus, index: usize, enabled: bool) {
+        let bit = 1 << index;
+        let mask = &self.enabled[bus as usize];
+        if enabled {
+            mask.fetch_or(bit, Ordering::Relaxed);
+        } else {
+            mask.fetch_and(!bit, Ordering::Relaxed);
+        }
+    }
+
+    pub fn mask(&self, bus: EffectBus) -> u32 {
+        self.enabled[bus as usize].load(Ordering::Relaxed)
+    }
+}
+
+/// Time for a member switched on or off to fade in or out, so toggling does
+/// not click.
+const BYPASS_FADE_SECS: f32 = 0.02;
+
+struct ChainMember {
+    effect: Box<dyn Effect>,
+    enabled: bool,
+    /// Current wet share, fading toward 1 when enabled and 0 when not.
+    amount: f32,
+}
+
+/// Effects run in series over a bus.
+pub struct EffectChain {
+    members: Vec<ChainMember>,
+    /// Dry copy of the block, for fading members in and out.
+    dry: Vec<f32>,
+    fade_step: f32,
+}
+
+impl EffectChain {
+    /// Builds the chain's effects. Allocates, so build it before the mixer
+    /// moves into the audio callback; `max_block` is the longest block
+    /// `process` will be given.
+    pub fn new(sample_rate: f32, kinds: &[EffectKind], max_block: usize) -> Self {
+        Self {
+            members: kinds
+                .iter()
+                .map(|kind| ChainMember {
+                    effect: kind.build(sample_rate),
+                    enabled: true,
+                    amount: 1.0,
+                })
+                .collect(),
+            dry: vec![0.0; max_block],
+            fade_step: 1.0 / (BYPASS_FADE_SECS * sample_rate).max(1.0),
+        }
+    }
+
+    /// Switches members on and off: bit `i` of `mask` for member `i`.
+    pub fn set_enabled(&mut self, mask: u32) {
+        for (i, member) in self.members.iter_mut().enumerate() {
+            member.enabled = mask & (1 << i) != 0;
+        }
+    }
+
+    /// Starts every member fully off, so the members switched on fade in
+    /// from the dry signal.
+    pub fn start_dry(&mut self) {
+        for member in &mut self.members {
+            member.amount = 0.0;
+        }
+    }
+
+    /// Whether every member is fully off, leaving the block dry.
+    pub fn is_dry(&self) -> bool {
+        self.members.iter().all(|member| member.amount == 0.0)
+    }
+
+    /// Runs `block` through each member in turn. Members that are fully off
+    /// are skipped, so their tails resume where they stopped.
+    pub fn process(&mut self, block: &mut [f32], params: &AudioParams) {
+        let fade_step = self.fade_step;
+        for member in &mut self.members {
+            let target = if member.enabled { 1.0 } else { 0.0 };
+            if member.amount == target {
+                if member.enabled {
+                    member.effect.process_block(block, params);
+                }
+                continue;
+            }
+            let dry = &mut self.dry[..block.len()];
+            dry.copy_from_slice(block);
+            member.effect.process_block(block, params);
+            for (out, dry) in block.iter_mut().zip(dry.iter()) {
+                member.amount = if target > member.amount {
+                    (member.amount + fade_step).min(target)
+                } else {
+                    (member.amount - fade_step).max(target)
+                };
+                *out = dry + (*out - dry) * member.amount;
+            }
+        }
+    }
+}
+
+/// Number of delayed voices summed with the dry signal.
+const VOICES: usize = 3;
+
+/// Center delay of each voice; staggered so they never coincide.
+const BASE_DELAYS_MS: [f32; VOICES] = [12.0, 17.0, 23.0];
+
+/// Delay swing either side of the center, at rest and at full motion.
+const MIN_DEPTH_MS: f32 = 1.0;
+const MAX_DEPTH_MS: f32 = 5.0;
+
+/// Modulation rate at rest and at full motion.
+const MIN_RATE_HZ: f32 = 0.08;
+const MAX_RATE_HZ: f32 = 0.6;
+
+/// Per-voice rate multipliers, so the voices drift in and out of step.
+const RATE_SPREAD: [f32; VOICES] = [1.0, 0.83, 1.21];
+
+/// Time constant of the glide between motion settings.
+const CHORUS_GLIDE_MS: f32 = 20.0;
+
+/// Share of the output taken from the delayed voices.
+const WET_MIX: f32 = 0.5;
+
+/// Multi-voice modulated-delay chorus. Allocates its delay line up front,
+/// so `process` is safe to call from the audio callback.
+pub struct Chorus {
+    sample_rate: f32,
+    buffer: Vec<f32>,
+    write: usize,
+    /// LFO phases in cycles (0-1), one per voice.
+    phases: [f32; VOICES],
+    smoothed_motion: Smoother,
+}
+
+impl Chorus {
+    pub fn new(sample_rate: f32) -> Self {
+        let longest_ms = BASE_DELAYS_MS[VOICES - 1] + MAX_DEPTH_MS;
+        // Two extra samples for interpolation and the write position
+        let len = (longest_ms / 1000.0 * sample_rate).ceil() as usize + 2;
+        Self {
+            sample_rate,
+            buffer: vec![0.0; len],
+            write: 0,
+            phases: [0.0, 1.0 / 3.0, 2.0 / 3.0],
+            smoothed_motion: Smoother::new(0.0, CHORUS_GLIDE_MS, sample_rate),
+        }
+    }
+
+    /// Reads the delay line `delay` samples behind the write position,
+    /// interpolating linearly between neighbours.
+    fn read(&self, delay: f32) -> f32 {
+        let len = self.buffer.len();
+        let delay = delay.clamp(1.0, (len - 2) as f32);
+        let whole = delay.floor();
+        let frac = delay - whole;
+        let a = (self.write + len - whole as usize) % len;
+        let b = (a + len - 1) % len;
+        self.buffer[a] * (1.0 - frac) + self.buffer[b] * frac
+    }
+
+    /// Processes one sample. Rhythm (through motion) sets both how fast and
+    /// how far the voices wander.
+    pub fn process_sample(&mut self, input: f32, params: &AudioParams) -> f32 {
+        let motion = (params.motion / MOTION_SCALE).clamp(0.0, 1.0);
+        let motion = self.smoothed_motion.step(motion);
+        let rate_hz = MIN_RATE_HZ + (MAX_RATE_HZ - MIN_RATE_HZ) * motion;
+        let depth_ms = MIN_DEPTH_MS + (MAX_DEPTH_MS - MIN_DEPTH_MS) * motion;
+        let ms_to_samples = self.sample_rate / 1000.0;
+
+        self.buffer[self.write] = if input.is_finite() { input } else { 0.0 };
+
+        let mut wet = 0.0;
+        for voice in 0..VOICES {
+            let phase = &mut self.phases[voice];
+            *phase = (*phase + rate_hz * RATE_SPREAD[voice] / self.sample_rate).fract();
+            let lfo = (*phase * std::f32::consts::TAU).sin();
+            let delay_ms = BASE_DELAYS_MS[voice] + lfo * depth_ms;
+            wet += self.read(delay_ms * ms_to_samples);
+        }
+        self.write = (self.write + 1) % self.buffer.len();
+
+        input * (1.0 - WET_MIX) + wet / VOICES as f32 * WET_MIX
+    }
+}
+
+impl Effect for Chorus {
+    fn process_block(&mut self, block: &mut [f32], params: &AudioParams) {
+        for sample in block {
+            *sample = self.process_sample(*sample, params);
+        }
+    }
+}
+
+/// Delay tempo at rest and at full rhythm, in beats per minute.
+const SLOW_BPM: f32 = 60.0;
+const FAST_BPM: f32 = 120.0;
+
+/// Tempo step, so small rhythm drifts leave the echoes where they are.
+const BPM_STEP: f32 = 5.0;
+
+/// Echo spacing as a fraction of a beat: quarter, dotted eighth and eighth
+/// notes as rhythm rises through thirds.
+const DIVISIONS: [f32; 3] = [1.0, 0.75, 0.5];
+
+/// Slowest pulse tempo the delay follows; the grid stays above it.
+const MIN_PULSE_BPM: f32 = 50.0;
+
+/// Longest echo spacing (a quarter note at the slowest pulse tempo).
+const MAX_DELAY_SECS: f32 = 60.0 / MIN_PULSE_BPM;
+
+/// Feedback and wet level when sparse and when dense.
+const MIN_FEEDBACK: f32 = 0.2;
+const MAX_FEEDBACK: f32 = 0.6;
+const MIN_SEND: f32 = 0.15;
+const MAX_SEND: f32 = 0.45;
+
+/// One-pole low-pass in the feedback path, so each repeat is darker.
+const DAMPING: f32 = 0.35;
+
+/// Time constant of the glide between spacings and feedback levels.
+const DELAY_GLIDE_MS: f32 = 40.0;
+
+/// Echo spacing for a world rhythm in [0, 1]: a quantized tempo and a beat
+/// division, both chosen by rhythm.
+pub fn delay_secs(rhythm: f32) -> f32 {
+    let rhythm = rhythm.clamp(0.0, 1.0);
+    let bpm = SLOW_BPM + (FAST_BPM - SLOW_BPM) * rhythm;
+    let bpm = (bpm / BPM_STEP).round() * BPM_STEP;
+    60.0 / bpm * division(rhythm)
+}
+
+/// Echo spacing locked to the world's pulse grid: the division rhythm
+/// picks, of the grid's tempo as it is.
+pub fn pulse_delay_secs(rhythm: f32, pulse_bpm: f32) -> f32 {
+    60.0 / pulse_bpm.max(MIN_PULSE_BPM) * division(rhythm.clamp(0.0, 1.0))
+}
+
+fn division(rhythm: f32) -> f32 {
+    DIVISIONS[((rhythm * DIVISIONS.len() as f32) as usize).min(DIVISIONS.len() - 1)]
+}
+
+/// Feedback delay whose echoes are added to the dry signal.
+pub struct Delay {
+    sample_rate: f32,
+    buffer: Vec<f32>,
+    write: usize,
+    /// Delay in samples, gliding toward the current division.
+    smoothed_delay: Smoother,
+    smoothed_density: Smoother,
+    damped: f32,
+}
+
+impl Delay {
+    pub fn new(sample_rate: f32) -> Self {
+        let len = (MAX_DELAY_SECS * sample_rate).ceil() as usize + 2;
+        Self {
+            sample_rate,
+            buffer: vec![0.0; len],
+            write: 0,
+            smoothed_delay: Smoother::new(
+                delay_secs(0.0) * sample_rate,
+                DELAY_GLIDE_MS,
+                sample_rate,
+            ),
+            smoothed_density: Smoother::new(0.0, DELAY_GLIDE_MS, sample_rate),
+            damped: 0.0,
+        }
+    }
+
+    /// Processes one sample. Rhythm (through motion) sets the echo spacing,
+    /// on the pulse grid's tempo if `pulse_bpm` is set; density (through
+    /// texture) sets feedback and echo level.
+    pub fn process_sample(&mut self, input: f32, params: &AudioParams) -> f32 {
+        let rhythm = params.motion / MOTION_SCALE;
+        let density = (params.texture / TEXTURE_SCALE).clamp(0.0, 1.0);
+        let target = if params.pulse_bpm > 0.0 {
+            pulse_delay_secs(rhythm, params.pulse_bpm)
+        } else {
+            delay_secs(rhythm)
+        };
+        // A slow glide between spacings, like a tape delay changing speed
+        let delay = self.smoothed_delay.step(target * self.sample_rate);
+        let density = self.smoothed_density.step(density);
+
+        let len = self.buffer.len();
+        let delay = delay.clamp(1.0, (len - 2) as f32);
+        let whole = delay.floor();
+        let frac = delay - whole;
+        let a = (self.write + len - whole as usize) % len;
+        let b = (a + len - 1) % len;
+        let echo = self.buffer[a] * (1.0 - frac) + self.buffer[b] * frac;
+
+        let feedback = MIN_FEEDBACK + (MAX_FEEDBACK - MIN_FEEDBACK) * density;
+        self.damped += (echo - self.damped) * (1.0 - DAMPING);
+        let input = if input.is_finite() { input } else { 0.0 };
+        self.buffer[self.write] = input + self.damped * feedback;
+        self.write = (self.write + 1) % len;
+
+        input + echo * (MIN_SEND + (MAX_SEND - MIN_SEND) * density)
+    }
+}
+
+impl Effect for Delay {
+    fn process_block(&mut self, block: &mut [f32], params: &AudioParams) {
+        for sample in block {
+            *sample = self.process_sample(*sample, params);
+        }
+    }
+}
+
+/// Comb delays of the reverb, in ms; mutually prime in samples at common rates.
+const COMB_DELAYS_MS: [f32; 4] = [29.7, 37.1, 41.1, 43.7];
+
+/// All-pass diffuser delays, in ms.
+const ALLPASS_DELAYS_MS: [f32; 2] = [5.0, 1.7];
+
+/// Comb feedback (tail length) at the smallest and largest size, and the
+/// low-pass damping in its loop. A size of 0.5 gives the original 0.84.
+const MIN_COMB_FEEDBACK: f32 = 0.72;
+const MAX_COMB_FEEDBACK: f32 = 0.96;
+const COMB_DAMPING: f32 = 0.3;
+
+/// All-pass coefficient.
+const ALLPASS_GAIN: f32 = 0.5;
+
+/// Share of the output taken from the reverb tail, smallest to largest size.
+const MIN_REVERB_MIX: f32 = 0.2;
+const MAX_REVERB_MIX: f32 = 0.4;
+
+/// Glide for size and pre-delay changes. Long enough that the pre-delay
+/// read position sweeping through the line doesn't zipper.
+const REVERB_GLIDE_MS: f32 = 300.0;
+
+/// Schroeder reverb: a pre-delay line feeding parallel damped combs into
+/// series all-passes.
+pub struct Reverb {
+    sample_rate: f32,
+    pre_delay: Vec<f32>,
+    write: usize,
+    combs: [(Vec<f32>, usize, f32); 4],
+    allpasses: [(Vec<f32>, usize); 2],
+    smoothed_size: Smoother,
+    /// Pre-delay in samples.
+    smoothed_pre_delay: Smoother,
+}
+
+impl Reverb {
+    pub fn new(sample_rate: f32) -> Self {
+        let line = |ms: f32| vec![0.0; ((ms / 1000.0 * sample_rate) as usize).max(1)];
+        let defaults = AudioParams::default();
+        Self {
+            sample_rate,
+            // Two extra samples for interpolation and the write position
+            pre_delay: vec![0.0; (MAX_PRE_DELAY_MS / 1000.0 * sample_rate).ceil() as usize + 2],
+            write: 0,
+            combs: COMB_DELAYS_MS.map(|ms| (line(ms), 0, 0.0)),
+            allpasses: ALLPASS_DELAYS_MS.map(|ms| (line(ms), 0)),
+            smoothed_size: Smoother::new(defaults.reverb_size, REVERB_GLIDE_MS, sample_rate),
+            smoothed_pre_delay: Smoother::new(
+                defaults.pre_delay_ms / 1000.0 * sample_rate,
+                REVERB_GLIDE_MS,
+                sample_rate,
+            ),
+        }
+    }
+
+    /// Processes one sample. Space (through reverb size) lengthens the tail
+    /// and raises its share of the output; pre-delay holds the tail back
+    /// from the dry signal, as the far walls of a larger room would.
+    pub fn process_sample(&mut self, input: f32, params: &AudioParams) -> f32 {
+        let input = if input.is_finite() { input } else { 0.0 };
+        let size = self.smoothed_size.step(params.reverb_size.clamp(0.0, 1.0));
+        let pre_delay_ms = params.pre_delay_ms.clamp(0.0, MAX_PRE_DELAY_MS);
+        let pre_delay = self
+            .smoothed_pre_delay
+            .step(pre_delay_ms / 1000.0 * self.sample_rate);
+
+        let len = self.pre_delay.len();
+        self.pre_delay[self.write] = input;
+        let delayed = if pre_delay < 1.0 {
+            input
+        } else {
+            let delay = pre_delay.min((len - 2) as f32);
+            let whole = delay.floor();
+            let frac = delay - whole;
+            let a = (self.write + len - whole as usize) % len;
+            let b = (a + len - 1) % len;
+            self.pre_delay[a] * (1.0 - frac) + self.pre_delay[b] * frac
+        };
+        self.write = (self.write + 1) % len;
+
+        let feedback = MIN_COMB_FEEDBACK + (MAX_COMB_FEEDBACK - MIN_COMB_FEEDBACK) * size;
+        let mut tail = 0.0;
+        for (buffer, pos, damped) in &mut self.combs {
+            let out = buffer[*pos];
+            *damped = out * (1.0 - COMB_DAMPING) + *damped * COMB_DAMPING;
+            buffer[*pos] = delayed + *damped * feedback;
+            *pos = (*pos + 1) % buffer.len();
+            tail += out;
+        }
+        tail /= COMB_DELAYS_MS.len() as f32;
+        for (buffer, pos) in &mut self.allpasses {
+            let delayed = buffer[*pos];
+            let out = delayed - tail * ALLPASS_GAIN;
+            buffer[*pos] = tail + delayed * ALLPASS_GAIN;
+            *pos = (*pos + 1) % buffer.len();
+            tail = out;
+        }
+        let mix = MIN_REVERB_MIX + (MAX_REVERB_MIX - MIN_REVERB_MIX) * size;
+        input * (1.0 - mix) + tail * mix
+    }
+}
+
+impl Effect for Reverb {
+    fn process_block(&mut self, block: &mut [f32], params: &AudioParams) {
+        for sample in block {
+            *sample = self.process_sample(*sample, params);
+        }
+    }
+}
+
+/// Time for the limiter's gain to recover after a peak.
+const LIMITER_RELEASE_SECS: f32 = 0.15;
+
+/// Peak limiter with instant attack, holding a bus under the master ceiling.
+/// Unlike the master bus limiter it has no lookahead, so it adds no latency.
+pub struct Limiter {
+    gain: f32,
+    release_coeff: f32,
+}
+
+impl Limiter {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            gain: 1.0,
+            release_coeff: 1.0 - (-1.0 / (LIMITER_RELEASE_SECS * sample_rate)).exp(),
+        }
+    }
+
+    pub fn process_sample(&mut self, input: f32) -> f32 {
+        let input = if input.is_finite() { input } else { 0.0 };
+        let level = input.abs();
+        let required = if level > LIMITER_CEILING {
+            LIMITER_CEILING / level
+        } else {
+            1.0
+        };
+        self.gain = (self.gain + (1.0 - self.gain) * self.release_coeff).min(required);
+        input * self.gain
+    }
+}
+
+impl Effect for Limiter {
+    fn process_block(&mut self, block: &mut [f32], _params: &AudioParams) {
+        for sample in block {
+            *sample = self.process_sample(*sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_silence_stays_silent_and_output_is_bounded() {
+        let mut chorus = Chorus::new(8_000.0);
+        let params = AudioParams {
+            motion: MOTION_SCALE,
+            ..AudioParams::default()
+        };
+        for _ in 0..1_000 {
+            assert_eq!(chorus.process_sample(0.0, &params), 0.0);
+        }
+        for n in 0..40_000 {
+            let input = (n as f32 * 0.07).sin();
+            let sample = chorus.process_sample(input, &params);
+            assert!(sample.is_finite() && sample.abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn test_voices_arrive_after_their_delay() {
+        let sample_rate = 8_000.0;
+        let mut chorus = Chorus::new(sample_rate);
+        let params = AudioParams::default();
+        // An impulse passes through dry, then the voices echo it back
+        let mut out = vec![chorus.process_sample(1.0, &params)];
+        out.extend((0..400).map(|_| chorus.process_sample(0.0, &params)));
+        assert!((out[0] - (1.0 - WET_MIX)).abs() < 1e-6);
+
+        let first_echo = out.iter().skip(1).position(|s| s.abs() > 1e-6).unwrap() + 1;
+        let earliest = ((BASE_DELAYS_MS[0] - MAX_DEPTH_MS) / 1000.0 * sample_rate) as usize;
+        let latest = ((BASE_DELAYS_MS[0] + MAX_DEPTH_MS) / 1000.0 * sample_rate) as usize + 1;
+        assert!((earliest..=latest).contains(&first_echo));
+        let echoed: f32 = out[1..].iter().sum();
+        assert!((echoed - WET_MIX).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_delay_time_follows_rhythm_divisions() {
+        // Quarter note at 60 BPM, dotted eighth at 90, eighth at 120
+        assert_eq!(delay_secs(0.0), 1.0);
+        assert_eq!(delay_secs(0.5), 60.0 / 90.0 * 0.75);
+        assert_eq!(delay_secs(1.0), 0.25);
+        // Small drifts keep the same spacing
+        assert_eq!(delay_secs(0.51), delay_secs(0.52));
+        assert!(delay_secs(0.2) > delay_secs(0.4));
+
+        // On the pulse grid the tempo is taken as it is
+        assert_eq!(pulse_delay_secs(0.0, 72.0), 60.0 / 72.0);
+        assert_eq!(pulse_delay_secs(1.0, 96.0), 60.0 / 96.0 * 0.5);
+        assert!(pulse_delay_secs(0.0, 10.0) <= MAX_DELAY_SECS);
+    }
+
+    #[test]
+    fn test_echoes_repeat_and_decay() {
+        let sample_rate = 1_000.0;
+        let mut delay = Delay::new(sample_rate);
+        let params = AudioParams {
+            motion: MOTION_SCALE,
+            texture: TEXTURE_SCALE,
+            ..AudioParams::default()
+        };
+        delay.smoothed_delay.set_time(0.0, sample_rate);
+        delay.smoothed_density.set_time(0.0, sample_rate);
+        let out: Vec<f32> = (0..2_000)
+            .map(|n| delay.process_sample(if n == 0 { 1.0 } else { 0.0 }, &params))
+            .collect();
+        assert_eq!(out[0], 1.0);
+        // Echoes every eighth note at 120 BPM, each quieter than the last
+        let spacing = (delay_secs(1.0) * sample_rate) as usize;
+        let peaks: Vec<f32> = (1..5).map(|n| out[n * spacing].abs()).collect();
+        assert!((peaks[0] - MAX_SEND).abs() < 1e-3);
+        assert!(peaks.windows(2).all(|w| w[1] < w[0] && w[1] > 0.0));
+        assert!(out.iter().all(|s| s.is_finite() && s.abs() <= 1.0));
+    }
+
+    #[test]
+    fn test_reverb_tail_decays() {
+        let params = AudioParams::default();
+        let mut reverb = Reverb::new(8_000.0);
+        let out: Vec<f32> = (0..16_000)
+            .map(|n| reverb.process_sample(if n == 0 { 1.0 } else { 0.0 }, &params))
+            .collect();
+        let energy = |range: std::ops::Range<usize>| out[range].iter().map(|s| s * s).sum::<f32>();
+        assert!(energy(1..4_000) > 0.0);
+        assert!(energy(12_000..16_000) < energy(1..4_000) * 0.01);
+    }
+
+    #[test]
+    fn test_larger_space_waits_longer_and_rings_longer() {
+        let impulse = |reverb_size: f32, pre_delay_ms: f32| {
+            let params = AudioParams {
+                reverb_size,
+                pre_delay_ms,
+                ..AudioParams::default()
+            };
+            let mut reverb = Reverb::new(8_000.0);
+            // Let size and pre-delay glide to the targets before the impulse
+            for _ in 0..16_000 {
+                reverb.process_sample(0.0, &params);
+            }
+            (0..16_000)
+                .map(|n| reverb.process_sample(if n == 0 { 1.0 } else { 0.0 }, &params))
+                .collect::<Vec<f32>>()
+        };
+        let onset = |out: &[f32]| out[1..].iter().position(|s| s.abs() > 1e-6).unwrap() + 1;
+        let late = |out: &[f32]| out[4_000..].iter().map(|s| s * s).sum::<f32>();
+
+        let intimate = impulse(0.0, 0.0);
+        let cavernous = impulse(1.0, MAX_PRE_DELAY_MS);
+        // 200 ms at 8 kHz is 1600 samples more before the first comb returns
+        assert!(onset(&cavernous) >= onset(&intimate) + 1_500);
+        assert!(late(&cavernous) > late(&intimate) * 10.0);
+    }
+
+    #[test]
+    fn test_limiter_holds_ceiling() {
+        let mut limiter = Limiter::new(8_000.0);
+        for n in 0..8_000 {
+            let input = 3.0 * (n as f32 * 0.05).sin();
+            assert!(limiter.process_sample(input).abs() <= LIMITER_CEILING + 1e-6);
+        }
+        // Recovers to unity once the peaks stop
+        let quiet: Vec<f32> = (0..8_000).map(|_| limiter.process_sample(0.5)).collect();
+        assert!((quiet[7_999] - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_chain_runs_in_order_and_bypasses() {
+        let params = AudioParams::default();
+        let mut chain = EffectChain::new(8_000.0, &[EffectKind::Reverb, EffectKind::Limiter], 64);
+        let mut loud = [4.0; 64];
+        chain.process(&mut loud, &params);
+        assert!(loud.iter().all(|s| *s <= LIMITER_CEILING + 1e-6));
+
+        // Switched off members fade out, then leave the signal untouched
+        chain.set_enabled(0);
+        for _ in 0..10 {
+            let mut block = [0.25; 64];
+            chain.process(&mut block, &params);
+        }
+        let mut block = [0.25; 64];
+        chain.process(&mut block, &params);
+        assert!(block.iter().all(|s| *s == 0.25));
+    }
+
+    #[test]
+    fn test_shared_effects_toggle() {
+        let shared = SharedEffects::new(EffectChains::default());
+        assert!(shared.is_enabled(EffectBus::Drone, 0));
+        shared.set_enabled(EffectBus::Drone, 0, false);
+        assert!(!shared.is_enabled(EffectBus::Drone, 0));
+        assert!(shared.is_enabled(EffectBus::Sparkle, 0));
+        let chains = EffectChains {
+            master: vec![EffectKind::Reverb],
+            ..EffectChains::default()
+        };
+        shared.set_chains(chains.clone());
+        assert_eq!(shared.chains(), chains);
+        assert!(shared.is_enabled(EffectBus::Drone, 0));
+        assert_eq!("sparkle".parse(), Ok(EffectBus::Sparkle));
+        assert!("pad".parse::<EffectBus>().is_err());
+        assert_eq!("reverb".parse(), Ok(EffectKind::Reverb));
+    }
+}
@@ -0,0 +1,164 @@
+//! Anti-click gate for the master gain.
+//!
+//! When energy collapses the mapped master gain heads for zero, and ramping
+//! it there over a single block can click on the drone. Two stages stop
+//! that. On the control side, [`GainGate`] closes the gain to exactly zero
+//! once it falls below a threshold and reopens it only once it climbs a
+//! margin above, so a world hovering near silence does not flutter on and
+//! off; while open it keeps the gain above an optional floor. On the audio
+//! side, [`FadeGate`] in the master bus holds the last audible gain as the
+//! gate closes and fades it out over a short raised-cosine ramp instead.
+
+use std::f32::consts::PI;
+
+/// How the master gain is gated near silence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GateSettings {
+    /// Lowest master gain while the gate is open (0 for none).
+    pub floor: f32,
+    /// Master gain below which the gate closes.
+    pub threshold: f32,
+    /// How far above `threshold` the gain must climb to reopen the gate.
+    pub hysteresis: f32,
+    /// Fade out as the gate closes, and back in as it opens.
+    pub fade_ms: f32,
+}
+
+impl Default for GateSettings {
+    fn default() -> Self {
+        Self {
+            floor: 0.0,
+            threshold: 0.002,
+            hysteresis: 0.004,
+            fade_ms: 30.0,
+        }
+    }
+}
+
+/// Hysteresis on the mapped master gain, run by the control task.
+#[derive(Clone, Copy, Debug)]
+pub struct GainGate {
+    settings: GateSettings,
+    open: bool,
+}
+
+impl GainGate {
+    pub fn new(settings: GateSettings) -> Self {
+        Self {
+            settings,
+            open: true,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// The gain to play for a mapped `gain`: zero while closed, at least the
+    /// floor while open.
+    pub fn apply(&mut self, gain: f32) -> f32 {
+        let GateSettings {
+            floor,
+            threshold,
+            hysteresis,
+            ..
+        } = self.settings;
+        if self.open && gain < threshold {
+            self.open = false;
+        } else if !self.open && gain >= threshold + hysteresis {
+            self.open = true;
+        }
+        if self.open { gain.max(floor) } else { 0.0 }
+    }
+}
+
+/// Short fade on the master bus as the gain target crosses the threshold.
+#[derive(Clone, Debug)]
+pub struct FadeGate {
+    threshold: f32,
+    /// Change in `level` per frame.
+    step: f32,
+    /// Position along the fade, 0 closed to 1 open.
+    level: f32,
+    /// Last gain played while open, faded out while closed.
+    held: f32,
+    sample_rate: f32,
+}
+
+impl FadeGate {
+    pub fn new(sample_rate: f32, settings: GateSettings) -> Self {
+        let mut gate = Self {
+            threshold: 0.0,
+            step: 1.0,
+            level: 1.0,
+            held: 0.0,
+            sample_rate,
+        };
+        gate.set_settings(settings);
+        gate
+    }
+
+    /// Replaces the settings, keeping the gate where it is.
+    pub fn set_settings(&mut self, settings: GateSettings) {
+        self.threshold = settings.threshold;
+        self.step = 1.0 / (settings.fade_ms.max(0.0) * 0.001 * self.sample_rate).max(1.0);
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.level > 0.0
+    }
+
+    /// The gain for one frame ramping toward `target`: `gain` while the
+    /// gate is open, the last open gain fading out once `target` drops
+    /// below the threshold.
+    pub fn process(&mut self, gain: f32, target: f32) -> f32 {
+        if target >= self.threshold {
+            self.held = gain;
+            self.level = (self.level + self.step).min(1.0);
+        } else {
+            self.level = (self.level - self.step).max(0.0);
+        }
+        if self.level >= 1.0 {
+            self.held
+        } else {
+            self.held * (0.5 - 0.5 * (PI * self.level).cos())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gate_holds_through_flutter_and_fades_out() {
+        let settings = GateSettings {
+            floor: 0.02,
+            ..GateSettings::default()
+        };
+        let mut gate = GainGate::new(settings);
+        assert_eq!(gate.apply(0.1), 0.1);
+        assert_eq!(gate.apply(0.01), 0.02);
+        assert_eq!(gate.apply(0.001), 0.0);
+        // Hovering just above the threshold keeps it shut
+        for gain in [0.003, 0.001, 0.004, 0.002] {
+            assert_eq!(gate.apply(gain), 0.0);
+        }
+        assert!(!gate.is_open());
+        assert_eq!(gate.apply(0.006), 0.02);
+        assert!(gate.is_open());
+
+        // The bus fades the last open gain out over the fade, without steps
+        let mut fade = FadeGate::new(1_000.0, settings);
+        assert_eq!(fade.process(0.5, 0.5), 0.5);
+        let mut last = 0.5;
+        for frame in 0..32 {
+            let gain = fade.process(0.0, 0.0);
+            assert!(gain <= last && last - gain < 0.05, "frame {}", frame);
+            last = gain;
+        }
+        assert_eq!(last, 0.0);
+        assert!(!fade.is_open());
+        assert!(fade.process(0.1, 0.1) > 0.0);
+    }
+}
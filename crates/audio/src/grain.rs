@@ -0,0 +1,211 @@
+//! Granular cloud over a loaded sample.
+//!
+//! Short windowed grains are read from around a slowly moving playhead in the
+//! source. World density sets how often grains start, motion shortens them and
+//! tension spreads their pitch. Grains live in a fixed pool so the audio
+//! callback never allocates.
+
+use crate::layers::Layer;
+use crate::noise::Rng;
+use crate::params::{AudioParams, DETUNE_SCALE, MOTION_SCALE, TEXTURE_SCALE};
+use crate::sample::Sample;
+use std::sync::Arc;
+
+/// Most grains that can sound at once; new grains are skipped when full.
+pub const MAX_GRAINS: usize = 64;
+
+/// Grains started per second at full density.
+const MAX_GRAIN_RATE_HZ: f32 = 30.0;
+
+/// Grain length at rest and at full motion.
+const LONG_GRAIN_SECS: f32 = 0.25;
+const SHORT_GRAIN_SECS: f32 = 0.04;
+
+/// Pitch spread either side of the source pitch at full tension.
+const MAX_PITCH_SPREAD_SEMITONES: f32 = 12.0;
+
+/// How far around the playhead a grain may start.
+const POSITION_SPRAY_SECS: f32 = 0.5;
+
+#[derive(Clone, Copy, Default)]
+struct Grain {
+    active: bool,
+    position: f64,
+    step: f64,
+    age: u32,
+    length: u32,
+}
+
+/// Layer that granulates one recording into a diffuse cloud.
+pub struct GrainLayer {
+    samples: Arc<[Sample]>,
+    source: usize,
+    grains: [Grain; MAX_GRAINS],
+    sample_rate: f32,
+    /// Read position the grains cluster around, in source frames.
+    playhead: f64,
+    /// Output samples until the next grain starts.
+    countdown: f32,
+    rng: Rng,
+}
+
+impl GrainLayer {
+    /// Granulates `samples[source]`.
+    ///
+    /// # Panics
+    /// If `source` is out of range.
+    pub fn new(sample_rate: f32, samples: Arc<[Sample]>, source: usize) -> Self {
+        assert!(source < samples.len(), "grain source out of range");
+        Self {
+            samples,
+            source,
+            grains: [Grain::default(); MAX_GRAINS],
+            sample_rate,
+            playhead: 0.0,
+            countdown: 0.0,
+            rng: Rng::new(0x9E37_79B9),
+        }
+    }
+
+    /// Number of grains currently sounding.
+    pub fn active_grains(&self) -> usize {
+        self.grains.iter().filter(|g| g.active).count()
+    }
+
+    fn spawn(&mut self, length_secs: f32, spread_semitones: f32) {
+        let Some(index) = self.grains.iter().position(|g| !g.active) else {
+            return;
+        };
+        let spray = (self.rng.next_f32() * 2.0 - 1.0) * POSITION_SPRAY_SECS;
+        let semitones = (self.rng.next_f32() * 2.0 - 1.0) * spread_semitones;
+
+        let sample = &self.samples[self.source];
+        let source_frames = sample.frames().len() as f64;
+        let rate_ratio = sample.sample_rate() as f64 / self.sample_rate as f64;
+        let offset = spray as f64 * sample.sample_rate() as f64;
+        let position = (self.playhead + offset).rem_euclid(source_frames);
+        let step = rate_ratio * 2f64.powf(semitones as f64 / 12.0);
+
+        self.grains[index] = Grain {
+            active: true,
+            position,
+            step,
+            age: 0,
+            length: ((length_secs * self.sample_rate) as u32).max(1),
+        };
+    }
+}
+
+impl Layer for GrainLayer {
+    fn process_block(&mut self, out: &mut [f32], params: &AudioParams) {
+        let density = (params.texture / TEXTURE_SCALE).clamp(0.0, 1.0);
+        let motion = (params.motion / MOTION_SCALE).clamp(0.0, 1.0);
+        let tension = ((params.detune_ratio - 1.0) / DETUNE_SCALE).clamp(0.0, 1.0);
+
+        let rate_hz = density * MAX_GRAIN_RATE_HZ;
+        let length_secs = LONG_GRAIN_SECS + (SHORT_GRAIN_SECS - LONG_GRAIN_SECS) * motion;
+        let spread_semitones = tension * MAX_PITCH_SPREAD_SEMITONES;
+        // Uncorrelated grains add in power, so scale by the root of the overlap
+        let scale = 1.0 / (rate_hz * length_secs).max(1.0).sqrt();
+
+        let source_frames = self.samples[self.source].frames().len() as f64;
+        let playhead_step =
+            self.samples[self.source].sample_rate() as f64 / self.sample_rate as f64;
+        for out in out.iter_mut() {
+            if rate_hz > 0.0 {
+                self.countdown -= 1.0;
+                if self.countdown <= 0.0 {
+                    self.spawn(length_secs, spread_semitones);
+                    // Jitter the interval so grains never lock into a pulse
+                    let jitter = 0.5 + self.rng.next_f32();
+                    self.countdown += jitter * self.sample_rate / rate_hz;
+                }
+            }
+
+            let sample = &self.samples[self.source];
+            let mut output = 0.0;
+            for grain in self.grains.iter_mut().filter(|g| g.active) {
+                // Hann window over the grain's life
+                let phase = grain.age as f32 / grain.length as f32;
+                let window = 0.5 - 0.5 * (phase * std::f32::consts::TAU).cos();
+                output += sample.frame_at(grain.position) * window;
+
+                grain.position = (grain.position + grain.step) % source_frames;
+                grain.age += 1;
+                if grain.age >= grain.length {
+                    grain.active = false;
+                }
+            }
+
+            // Drift the playhead through the recording at its natural speed
+            self.playhead = (self.playhead + playhead_step) % source_frames;
+            *out = output * scale;
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        // Grains keep their place in the source and their share of their
+        // life; lengths, ages and the countdown are in output samples
+        let ratio = sample_rate / self.sample_rate;
+        for grain in self.grains.iter_mut().filter(|g| g.active) {
+            grain.step /= ratio as f64;
+            grain.length = ((grain.length as f32 * ratio) as u32).max(1);
+            grain.age = ((grain.age as f32 * ratio) as u32).min(grain.length - 1);
+        }
+        self.countdown *= ratio;
+        self.sample_rate = sample_rate;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer() -> GrainLayer {
+        let frames = (0..8_000)
+            .map(|i| (i as f32 * 220.0 * std::f32::consts::TAU / 8_000.0).sin())
+            .collect();
+        GrainLayer::new(
+            8_000.0,
+            Arc::from(vec![Sample::new("sine", frames, 8_000)]),
+            0,
+        )
+    }
+
+    #[test]
+    fn test_silent_without_density() {
+        let mut layer = layer();
+        let params = AudioParams {
+            texture: 0.0,
+            ..AudioParams::default()
+        };
+        let mut block = [0.0; 8_000];
+        layer.process_block(&mut block, &params);
+        assert!(block.iter().all(|s| *s == 0.0));
+        assert_eq!(layer.active_grains(), 0);
+    }
+
+    #[test]
+    fn test_dense_cloud_stays_in_pool() {
+        let mut layer = layer();
+        let params = AudioParams {
+            texture: TEXTURE_SCALE,
+            motion: 0.0,
+            detune_ratio: 1.0 + DETUNE_SCALE,
+            ..AudioParams::default()
+        };
+        let mut peak: f32 = 0.0;
+        let mut block = [0.0; 64];
+        for _ in 0..250 {
+            layer.process_block(&mut block, &params);
+            for sample in block {
+                assert!(sample.is_finite());
+                peak = peak.max(sample.abs());
+            }
+            assert!(layer.active_grains() <= MAX_GRAINS);
+        }
+        // 30 grains/s of 0.25 s overlap around seven deep
+        assert!(layer.active_grains() > 1);
+        assert!(peak > 0.1 && peak < 4.0);
+    }
+}
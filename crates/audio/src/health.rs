@@ -0,0 +1,126 @@
+//! Signs that the output has gone wrong while the engine still runs:
+//! silence, non-finite samples and full-scale output.
+//!
+//! The renderer counts them into atomics as it goes, in frames of output
+//! rather than wall time, so a stalled engine (the [`crate::watchdog`]'s
+//! business) never reads as silent. Readers turn the counts into seconds and
+//! totals. Anyone may ask for the engine to be rebuilt; the watchdog picks
+//! the request up on its next check.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+/// Peak below which a block counts as silent: -80 dBFS.
+pub const SILENCE_THRESHOLD: f32 = 1e-4;
+
+/// Magnitude at or above which a sample counts as full scale. The master
+/// limiter keeps healthy output a decibel below it.
+pub const FULL_SCALE: f32 = 0.999;
+
+/// What the output looks like so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HealthReading {
+    /// Frames rendered since startup.
+    pub frames: u64,
+    /// Seconds of output since the last audible block.
+    pub silent_secs: f64,
+    /// NaN or infinite samples rendered since startup.
+    pub non_finite: u64,
+    /// Full-scale samples rendered since startup.
+    pub full_scale: u64,
+}
+
+/// Output health, written by the renderer. Shared across engine rebuilds.
+#[derive(Debug, Default)]
+pub struct SharedOutputHealth {
+    frames: AtomicU64,
+    /// `frames` at the end of the last audible block.
+    audible_at: AtomicU64,
+    sample_rate: AtomicU32,
+    non_finite: AtomicU64,
+    full_scale: AtomicU64,
+    restart: AtomicBool,
+}
+
+impl SharedOutputHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts interleaved `block` of `channels` at `sample_rate`.
+    pub fn record(&self, block: &[f32], channels: u16, sample_rate: u32) {
+        let (mut peak, mut non_finite, mut full_scale) = (0.0_f32, 0, 0);
+        for &sample in block {
+            if !sample.is_finite() {
+                non_finite += 1;
+                continue;
+            }
+            let magnitude = sample.abs();
+            peak = peak.max(magnitude);
+            if magnitude >= FULL_SCALE {
+                full_scale += 1;
+            }
+        }
+        let frames = self.frames.load(Ordering::Relaxed)
+            + (block.len() / usize::from(channels.max(1))) as u64;
+        self.frames.store(frames, Ordering::Relaxed);
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        if peak >= SILENCE_THRESHOLD {
+            self.audible_at.store(frames, Ordering::Relaxed);
+        }
+        if non_finite > 0 {
+            self.non_finite.fetch_add(non_finite, Ordering::Relaxed);
+        }
+        if full_scale > 0 {
+            self.full_scale.fetch_add(full_scale, Ordering::Relaxed);
+        }
+    }
+
+    pub fn reading(&self) -> HealthReading {
+        let frames = self.frames.load(Ordering::Relaxed);
+        let silent_frames = frames.saturating_sub(self.audible_at.load(Ordering::Relaxed));
+        let sample_rate = self.sample_rate.load(Ordering::Relaxed).max(1);
+        HealthReading {
+            frames,
+            silent_secs: silent_frames as f64 / sample_rate as f64,
+            non_finite: self.non_finite.load(Ordering::Relaxed),
+            full_scale: self.full_scale.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Asks the watchdog to rebuild the engine.
+    pub fn request_restart(&self) {
+        self.restart.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a rebuild was asked for since the last call.
+    pub fn take_restart_request(&self) -> bool {
+        self.restart.swap(false, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_counts_silence_and_bad_samples() {
+        let health = SharedOutputHealth::new();
+        assert_eq!(health.reading(), HealthReading::default());
+
+        health.record(&[0.5; 200], 2, 100);
+        health.record(&[0.0; 400], 2, 100);
+        let reading = health.reading();
+        assert_eq!(reading.frames, 300);
+        assert_eq!(reading.silent_secs, 2.0);
+
+        health.record(&[f32::NAN, 1.0, -1.0, 0.2], 2, 100);
+        let reading = health.reading();
+        assert_eq!(reading.silent_secs, 0.0);
+        assert_eq!((reading.non_finite, reading.full_scale), (1, 2));
+
+        assert!(!health.take_restart_request());
+        health.request_restart();
+        assert!(health.take_restart_request());
+        assert!(!health.take_restart_request());
+    }
+}
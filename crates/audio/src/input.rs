@@ -0,0 +1,462 @@
+//! Microphone analysis, for sensors and modes that react to the room.
+//!
+//! A capture stream runs an [`InputAnalyzer`] over the input and publishes
+//! its peak and RMS levels and an onset count through atomics, which the
+//! control side reads whenever it likes. Nothing is recorded or kept beyond
+//! those numbers.
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Peak follower rise and fall times: quick enough to catch a footstep or a
+/// voice, slow enough that the level does not flicker between polls.
+pub const ATTACK_SECS: f32 = 0.01;
+pub const RELEASE_SECS: f32 = 0.3;
+
+/// RMS averaging times: the room's loudness rather than its transients.
+pub const RMS_ATTACK_SECS: f32 = 0.05;
+pub const RMS_RELEASE_SECS: f32 = 0.5;
+
+/// Onset detection: a short-term power jump of about 6 dB over the
+/// background, above -60 dBFS, at most once per hold-off.
+const ONSET_FAST_SECS: f32 = 0.005;
+const ONSET_FAST_RELEASE_SECS: f32 = 0.05;
+const ONSET_SLOW_SECS: f32 = 0.4;
+const ONSET_RATIO: f32 = 4.0;
+const ONSET_GATE: f32 = 1e-6;
+const ONSET_HOLDOFF_SECS: f32 = 0.1;
+
+/// How quickly the noise floor follows a quieter room. It follows a louder
+/// one at the configured, much slower rate.
+pub const FLOOR_FALL_SECS: f32 = 2.0;
+
+/// Lowest level reported in dB, standing in for silence.
+pub const SILENCE_DB: f32 = -100.0;
+
+/// Latest input measurements, shared with the capture thread.
+#[derive(Debug, Default)]
+pub struct InputMeter {
+    peak: AtomicU32,
+    rms: AtomicU32,
+    onsets: AtomicU64,
+}
+
+impl InputMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Peak amplitude, 0-1.
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.peak.load(Ordering::Relaxed))
+    }
+
+    /// RMS amplitude, 0-1.
+    pub fn rms(&self) -> f32 {
+        f32::from_bits(self.rms.load(Ordering::Relaxed))
+    }
+
+    /// Onsets detected since capture started.
+    pub fn onsets(&self) -> u64 {
+        self.onsets.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, peak: f32, rms: f32, new_onsets: u32) {
+        self.peak.store(peak.to_bits(), Ordering::Relaxed);
+        self.rms.store(rms.to_bits(), Ordering::Relaxed);
+        if new_onsets > 0 {
+            self.onsets.fetch_add(new_onsets as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Peak envelope follower: rises toward a louder input at the attack rate
+/// and falls back at the release rate.
+#[derive(Debug, Clone)]
+pub struct EnvelopeFollower {
+    attack_coeff: f32,
+    release_coeff: f32,
+    level: f32,
+}
+
+impl EnvelopeFollower {
+    pub fn new(sample_rate: f32, attack_secs: f32, release_secs: f32) -> Self {
+        let coeff = |secs: f32| (-1.0 / (secs * sample_rate).max(1.0)).exp();
+        Self {
+            attack_coeff: coeff(attack_secs),
+            release_coeff: coeff(release_secs),
+            level: 0.0,
+        }
+    }
+
+    /// Follows one sample and returns the new level.
+    pub fn step(&mut self, sample: f32) -> f32 {
+        let magnitude = sample.abs();
+        let coeff = if magnitude > self.level {
+            self.attack_coeff
+        } else {
+            self.release_coeff
+        };
+        self.level = magnitude + coeff * (self.level - magnitude);
+        self.level
+    }
+
+    /// Follows a block of samples (channels interleaved, or not; only the
+    /// magnitudes matter) and returns the level at its end.
+    pub fn process(&mut self, samples: impl IntoIterator<Item = f32>) -> f32 {
+        for sample in samples {
+            self.step(sample);
+        }
+        self.level
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+}
+
+/// Spots sudden jumps in loudness (a clap, a door, a laugh) by comparing a
+/// fast power envelope against a slow one. After an onset it re-arms once
+/// the jump has died down, so one sound counts once.
+#[derive(Debug, Clone)]
+pub struct OnsetDetector {
+    fast: EnvelopeFollower,
+    slow: EnvelopeFollower,
+    holdoff: u32,
+    remaining: u32,
+    armed: bool,
+}
+
+impl OnsetDetector {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            fast: EnvelopeFollower::new(sample_rate, ONSET_FAST_SECS, ONSET_FAST_RELEASE_SECS),
+            slow: EnvelopeFollower::new(sample_rate, ONSET_SLOW_SECS, ONSET_SLOW_SECS),
+            holdoff: (ONSET_HOLDOFF_SECS * sample_rate) as u32,
+            // Learn the background before judging jumps against it
+            remaining: (ONSET_SLOW_SECS * sample_rate) as u32,
+            armed: true,
+        }
+    }
+
+    /// Follows one sample; true when it completes an onset.
+    pub fn step(&mut self, sample: f32) -> bool {
+        let power = sample * sample;
+        let fast = self.fast.step(power);
+        let slow = self.slow.step(power);
+        let jump = fast > ONSET_GATE && fast > slow * ONSET_RATIO;
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            return false;
+        }
+        if !jump {
+            self.armed = true;
+            return false;
+        }
+        if !self.armed {
+            return false;
+        }
+        self.armed = false;
+        self.remaining = self.holdoff;
+        true
+    }
+}
+
+/// Everything the capture stream measures, run once per sample.
+#[derive(Debug, Clone)]
+pub struct InputAnalyzer {
+    peak: EnvelopeFollower,
+    mean_square: EnvelopeFollower,
+    onsets: OnsetDetector,
+}
+
+impl InputAnalyzer {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            peak: EnvelopeFollower::new(sample_rate, ATTACK_SECS, RELEASE_SECS),
+            mean_square: EnvelopeFollower::new(sample_rate, RMS_ATTACK_SECS, RMS_RELEASE_SECS),
+            onsets: OnsetDetector::new(sample_rate),
+        }
+    }
+
+    /// Analyzes a block of samples and publishes the results to `meter`.
+    pub fn process(&mut self, samples: impl IntoIterator<Item = f32>, meter: &InputMeter) {
+        let mut new_onsets = 0;
+        for sample in samples {
+            self.peak.step(sample);
+            self.mean_square.step(sample * sample);
+            if self.onsets.step(sample) {
+                new_onsets += 1;
+            }
+        }
+        meter.set(
+            self.peak.level(),
+            self.mean_square.level().sqrt(),
+            new_onsets,
+        );
+    }
+}
+
+/// Amplitude in dBFS, bottoming out at [`SILENCE_DB`].
+pub fn amplitude_to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.log10()).max(SILENCE_DB)
+}
+
+/// The room's background level in dB, learned as it goes: it drops quickly
+/// to a quieter reading and creeps up slowly toward a louder one, so it
+/// settles on the hum of wherever the mic is placed while a lively room
+/// still reads as loud for a good while.
+#[derive(Debug, Clone)]
+pub struct NoiseFloor {
+    rise_secs: f32,
+    floor_db: Option<f32>,
+}
+
+impl NoiseFloor {
+    pub fn new(rise_secs: f32) -> Self {
+        Self {
+            rise_secs,
+            floor_db: None,
+        }
+    }
+
+    /// Adapts to a level `dt` seconds after the last one and returns the
+    /// floor. The first level calibrates it.
+    pub fn update(&mut self, level_db: f32, dt: f32) -> f32 {
+        let floor = match self.floor_db {
+            None => level_db,
+            Some(floor) => {
+                let secs = if level_db < floor {
+                    FLOOR_FALL_SECS
+                } else {
+                    self.rise_secs
+                };
+                floor + (level_db - floor) * (1.0 - (-dt / secs.max(f32::EPSILON)).exp())
+            }
+        };
+        self.floor_db = Some(floor);
+        floor
+    }
+
+    pub fn floor_db(&self) -> Option<f32> {
+        self.floor_db
+    }
+}
+
+/// Turns RMS readings into a 0-1 level above the room's noise floor, with
+/// `range_db` above the floor reading as full level.
+#[derive(Debug, Clone)]
+pub struct RoomLevel {
+    floor: NoiseFloor,
+    range_db: f32,
+}
+
+impl RoomLevel {
+    pub fn new(range_db: f32, floor_rise_secs: f32) -> Self {
+        Self {
+            floor: NoiseFloor::new(floor_rise_secs),
+            range_db,
+        }
+    }
+
+    /// Level for an RMS reading taken `dt` seconds after the last one.
+    pub fn update(&mut self, rms: f32, dt: f32) -> f32 {
+        let db = amplitude_to_db(rms);
+        let floor = self.floor.update(db, dt);
+        ((db - floor) / self.range_db).clamp(0.0, 1.0)
+    }
+
+    pub fn floor_db(&self) -> Option<f32> {
+        self.floor.floor_db()
+    }
+}
+
+#[cfg(feature = "cpal")]
+pub use capture::MicInput;
+
+#[cfg(feature = "cpal")]
+mod capture {
+    use super::{InputAnalyzer, InputMeter};
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+    use cpal::{SampleFormat, StreamError};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+    use tracing::{info, warn};
+
+    /// Handle to the capture thread. Dropping it closes the input stream.
+    pub struct MicInput {
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl MicInput {
+        /// Opens `device` (by name), or the default input, and keeps `meter`
+        /// updated until dropped. The stream lives on its own thread, since
+        /// cpal streams cannot move between threads.
+        pub fn spawn(device: Option<String>, meter: Arc<InputMeter>) -> anyhow::Result<Self> {
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread_stop = Arc::clone(&stop);
+            let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+            let thread = std::thread::Builder::new()
+                .name("mic-input".to_string())
+                .spawn(move || {
+                    let stream = match open_stream(device.as_deref(), meter) {
+                        Ok(stream) => {
+                            let _ = ready_tx.send(Ok(()));
+                            stream
+                        }
+                        Err(e) => {
+                            let _ = ready_tx.send(Err(e));
+                            return;
+                        }
+                    };
+                    while !thread_stop.load(Ordering::Relaxed) {
+                        std::thread::sleep(Duration::from_millis(100));
+                    }
+                    drop(stream);
+                })?;
+            ready_rx
+                .recv()
+                .map_err(|_| anyhow::anyhow!("mic input thread exited"))??;
+            Ok(Self {
+                stop,
+                thread: Some(thread),
+            })
+        }
+    }
+
+    impl Drop for MicInput {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    fn open_stream(device: Option<&str>, meter: Arc<InputMeter>) -> anyhow::Result<cpal::Stream> {
+        let host = cpal::default_host();
+        let device = match device {
+            Some(name) => host
+                .input_devices()?
+                .find(|d| d.description().is_ok_and(|desc| desc.to_string() == name))
+                .ok_or_else(|| anyhow::anyhow!("No input device named '{}'", name))?,
+            None => host
+                .default_input_device()
+                .ok_or_else(|| anyhow::anyhow!("No default input device"))?,
+        };
+        let supported = device.default_input_config()?;
+        let sample_format = supported.sample_format();
+        let config = supported.config();
+        info!(
+            "Mic input: {}, {} Hz, {} channels, format: {:?}",
+            device.description()?,
+            config.sample_rate,
+            config.channels,
+            sample_format
+        );
+
+        let mut analyzer = InputAnalyzer::new(config.sample_rate as f32);
+        let error_callback = |err: StreamError| warn!("Mic input error: {}", err);
+        let stream = match sample_format {
+            SampleFormat::F32 => device.build_input_stream(
+                &config,
+                move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                    analyzer.process(data.iter().copied(), &meter);
+                },
+                error_callback,
+                None,
+            )?,
+            SampleFormat::I16 => device.build_input_stream(
+                &config,
+                move |data: &[i16], _: &cpal::InputCallbackInfo| {
+                    let samples = data.iter().map(|&s| s as f32 / i16::MAX as f32);
+                    analyzer.process(samples, &meter);
+                },
+                error_callback,
+                None,
+            )?,
+            other => anyhow::bail!("Unsupported input sample format: {:?}", other),
+        };
+        stream.play()?;
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_follower_attacks_fast_and_releases_slowly() {
+        let mut follower = EnvelopeFollower::new(1_000.0, ATTACK_SECS, RELEASE_SECS);
+        let loud: Vec<f32> = (0..50)
+            .map(|n| if n % 2 == 0 { 0.8 } else { -0.8 })
+            .collect();
+        // Five attack time constants
+        assert!((follower.process(loud) - 0.8).abs() < 0.01);
+
+        // One release time constant of silence leaves about a third
+        let level = follower.process([0.0; 300]);
+        assert!((level - 0.8 * (-1.0f32).exp()).abs() < 0.01, "{}", level);
+
+        assert_eq!(follower.level(), level);
+    }
+
+    #[test]
+    fn test_analyzer_measures_rms_and_onsets() {
+        let meter = InputMeter::new();
+        let mut analyzer = InputAnalyzer::new(1_000.0);
+        // A steady hum is not an onset
+        let hum = (0..2_000).map(|n| if n % 2 == 0 { 0.01 } else { -0.01 });
+        analyzer.process(hum, &meter);
+        assert_eq!(meter.onsets(), 0);
+        assert!((meter.rms() - 0.01).abs() < 0.001, "{}", meter.rms());
+
+        // Two claps, then one held note: only the starts count
+        let clap = |len: usize| (0..len).map(|n| if n % 2 == 0 { 0.5 } else { -0.5 });
+        analyzer.process(clap(20).chain(std::iter::repeat_n(0.0, 980)), &meter);
+        analyzer.process(clap(20).chain(std::iter::repeat_n(0.0, 980)), &meter);
+        analyzer.process(clap(1_000), &meter);
+        assert_eq!(meter.onsets(), 3);
+        assert!(meter.peak() > 0.45);
+        assert!(meter.rms() > 0.4);
+    }
+
+    #[test]
+    fn test_noise_floor_falls_fast_and_rises_slowly() {
+        let mut floor = NoiseFloor::new(60.0);
+        assert_eq!(floor.floor_db(), None);
+        assert_eq!(floor.update(-40.0, 0.05), -40.0);
+
+        // Ten seconds of a louder room barely moves it
+        for _ in 0..200 {
+            floor.update(-20.0, 0.05);
+        }
+        let raised = floor.floor_db().unwrap();
+        assert!(raised > -40.0 && raised < -36.0, "{}", raised);
+
+        // Ten seconds of a quieter one and it has settled there
+        for _ in 0..200 {
+            floor.update(-60.0, 0.05);
+        }
+        assert!((floor.floor_db().unwrap() + 60.0).abs() < 0.5);
+
+        assert_eq!(amplitude_to_db(1.0), 0.0);
+        assert_eq!(amplitude_to_db(0.0), SILENCE_DB);
+    }
+
+    #[test]
+    fn test_room_level_is_measured_above_the_floor() {
+        let mut level = RoomLevel::new(20.0, 300.0);
+        // The first reading calibrates the floor at -50 dB
+        assert_eq!(level.update(0.00316, 0.05), 0.0);
+        // 10 dB up is half the range
+        assert!((level.update(0.01, 0.05) - 0.5).abs() < 0.01);
+        // 30 dB up is past it
+        assert_eq!(level.update(0.1, 0.05), 1.0);
+        // The floor moved only a little toward the louder room
+        assert!(level.floor_db().unwrap() < -49.9);
+    }
+}
@@ -1,111 +1,242 @@
-use crate::params::AudioParams;
+use crate::dsp;
+use crate::noise::{Noise, NoiseColor};
+use crate::params::{AudioParams, DETUNE_SCALE, MOTION_SCALE};
+use crate::smoother::{Smoother, SmoothingSettings};
 
 /// Trait for audio layers that generate samples.
 pub trait Layer: Send {
-    fn process(&mut self, params: &AudioParams) -> f32;
+    /// Fills `out` with the next `out.len()` samples. Parameters are smoothed
+    /// once per block (control rate) rather than once per sample.
+    fn process_block(&mut self, out: &mut [f32], params: &AudioParams);
+
+    /// Sets how quickly the layer follows its parameters. Layers start with
+    /// [`SmoothingSettings::default`]; those that smooth nothing ignore it.
+    fn set_smoothing(&mut self, _settings: &SmoothingSettings) {}
+
+    /// Moves the layer to a new output rate, keeping its phases, voices and
+    /// filter state so it plays on where it left off. Used when the engine
+    /// is rebuilt on a device running at a different rate.
+    fn set_sample_rate(&mut self, sample_rate: f32);
 }
 
-/// Drone layer that generates a continuous tone with two oscillators for richness.
+/// Most partials an additive drone can have.
+pub const MAX_PARTIALS: usize = 16;
+
+/// Independent partial detune at full tension, in cents either way.
+const MAX_PARTIAL_DETUNE_CENTS: f32 = 12.0;
+
+/// Partial amplitude rolloff exponent (amplitude = 1 / n^p) when dark and bright.
+const DARK_ROLLOFF: f32 = 2.5;
+const BRIGHT_ROLLOFF: f32 = 0.7;
+
+/// Extra saturation drive on the drone at full grit.
+const GRIT_DRIVE: f32 = 4.0;
+
+/// How the drone is synthesized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DroneMode {
+    /// Two detuned sine oscillators.
+    #[default]
+    Dual,
+    /// A harmonic series of this many partials (1 to `MAX_PARTIALS`).
+    Additive(usize),
+}
+
+/// Drone layer that generates a continuous tone, either from two oscillators
+/// or from a harmonic series of partials that drift and beat independently.
 pub struct DroneLayer {
+    mode: DroneMode,
+    /// Additive mode: phase of each partial, and of its slow amplitude LFO, in radians.
+    partial_phases: [f32; MAX_PARTIALS],
+    partial_lfo_phases: [f32; MAX_PARTIALS],
     phase_a: f32,      // Phase in radians for oscillator A
     phase_b: f32,      // Phase in radians for oscillator B
     phase_incr_a: f32, // Phase increment per sample for oscillator A (2π * freq / sample_rate)
     phase_incr_b: f32, // Phase increment per sample for oscillator B (2π * freq / sample_rate)
-    smoothed_master_gain: f32,
-    smoothed_base_freq_hz: f32,
-    smoothed_detune_ratio: f32,
-    smoothed_brightness: f32,
-    smoothed_motion: f32,
-    smoothed_texture: f32,
+    smoothed_master_gain: Smoother,
+    smoothed_base_freq_hz: Smoother,
+    smoothed_detune_ratio: Smoother,
+    smoothed_brightness: Smoother,
+    smoothed_motion: Smoother,
+    smoothed_texture: Smoother,
+    smoothed_grit: Smoother,
     sample_rate: f32,
-    smoothing_coeff: f32,
 }
 
 impl DroneLayer {
     pub fn new(sample_rate: f32) -> Self {
+        Self::with_mode(sample_rate, DroneMode::Dual)
+    }
+
+    pub fn with_mode(sample_rate: f32, mode: DroneMode) -> Self {
         let base_freq = 440.0;
         let two_pi = 2.0 * std::f32::consts::PI;
+        let mode = match mode {
+            DroneMode::Additive(partials) => DroneMode::Additive(partials.clamp(1, MAX_PARTIALS)),
+            DroneMode::Dual => DroneMode::Dual,
+        };
+        let times = SmoothingSettings::default().slow;
+        let smoother = |value, time_ms| Smoother::new(value, time_ms, sample_rate);
         Self {
+            mode,
+            partial_phases: [0.0; MAX_PARTIALS],
+            // Spread the LFOs so the partials do not swell in unison
+            partial_lfo_phases: std::array::from_fn(|n| n as f32 * 2.4),
             phase_a: 0.0,
             phase_b: 0.0,
             phase_incr_a: base_freq * two_pi / sample_rate,
             phase_incr_b: base_freq * two_pi / sample_rate, // Will be updated with detune
-            smoothed_master_gain: 0.0,
-            smoothed_base_freq_hz: base_freq,
-            smoothed_detune_ratio: 1.0,
-            smoothed_brightness: 0.0,
-            smoothed_motion: 0.0,
-            smoothed_texture: 0.0,
+            smoothed_master_gain: smoother(0.0, times.master_gain),
+            smoothed_base_freq_hz: smoother(base_freq, times.base_freq),
+            smoothed_detune_ratio: smoother(1.0, times.detune),
+            smoothed_brightness: smoother(0.0, times.brightness),
+            smoothed_motion: smoother(0.0, times.motion),
+            smoothed_texture: smoother(0.0, times.texture),
+            smoothed_grit: smoother(0.0, times.texture),
             sample_rate,
-            smoothing_coeff: 0.01, // Adjust for smoothing speed
         }
     }
 
-    fn smooth(current: &mut f32, target: f32, coeff: f32) {
-        *current += (target - *current) * coeff;
+    /// Soft-clips the block harder the more grit there is, normalized so a
+    /// full-scale peak stays full scale.
+    fn saturate(out: &mut [f32], grit: f32) {
+        if grit <= 0.0 {
+            return;
+        }
+        let drive = 1.0 + GRIT_DRIVE * grit;
+        let norm = 1.0 / drive.tanh();
+        for sample in out.iter_mut() {
+            *sample = (*sample * drive).tanh() * norm;
+        }
     }
-}
 
-impl Layer for DroneLayer {
-    fn process(&mut self, params: &AudioParams) -> f32 {
-        // Smooth parameters
-        Self::smooth(
-            &mut self.smoothed_master_gain,
-            params.master_gain,
-            self.smoothing_coeff,
-        );
-        Self::smooth(
-            &mut self.smoothed_base_freq_hz,
-            params.base_freq_hz,
-            self.smoothing_coeff,
-        );
-        Self::smooth(
-            &mut self.smoothed_detune_ratio,
-            params.detune_ratio,
-            self.smoothing_coeff,
-        );
-        Self::smooth(
-            &mut self.smoothed_brightness,
-            params.brightness,
-            self.smoothing_coeff,
-        );
-        Self::smooth(
-            &mut self.smoothed_motion,
-            params.motion,
-            self.smoothing_coeff,
-        );
-        Self::smooth(
-            &mut self.smoothed_texture,
-            params.texture,
-            self.smoothing_coeff,
-        );
+    /// Fixed offset in [-1, 1] for partial `n`, so each beats at its own rate.
+    fn partial_spread(n: usize) -> f32 {
+        ((n as f32 + 1.0) * 12.9898).sin()
+    }
 
+    /// Two sine oscillators, the second detuned by the detune ratio.
+    fn dual(&mut self, out: &mut [f32]) {
         // Update phase increments based on smoothed frequencies
         let two_pi = 2.0 * std::f32::consts::PI;
-        self.phase_incr_a = self.smoothed_base_freq_hz * two_pi / self.sample_rate;
+        let base_freq_hz = self.smoothed_base_freq_hz.value();
+        self.phase_incr_a = base_freq_hz * two_pi / self.sample_rate;
         self.phase_incr_b =
-            self.smoothed_base_freq_hz * self.smoothed_detune_ratio * two_pi / self.sample_rate;
-
-        // Generate samples from two oscillators (direct sin of phase in radians)
-        let sample_a = self.phase_a.sin();
-        let sample_b = self.phase_b.sin();
+            base_freq_hz * self.smoothed_detune_ratio.value() * two_pi / self.sample_rate;
 
         // Mix the two oscillators (equal volume)
-        let mixed_sample = (sample_a + sample_b) * 0.5;
+        out.fill(0.0);
+        dsp::add_sine(out, &mut self.phase_a, self.phase_incr_a, 0.5);
+        dsp::add_sine(out, &mut self.phase_b, self.phase_incr_b, 0.5);
+    }
+
+    /// Harmonic series: brightness sets how slowly the partials roll off,
+    /// tension detunes each by its own amount so they beat against each other,
+    /// and motion deepens slow per-partial swells. Partial frequencies and
+    /// amplitudes are worked out once per block.
+    fn additive(&mut self, out: &mut [f32], partials: usize) {
+        let two_pi = 2.0 * std::f32::consts::PI;
+        let tension = ((self.smoothed_detune_ratio.value() - 1.0) / DETUNE_SCALE).clamp(0.0, 1.0);
+        let motion = (self.smoothed_motion.value() / MOTION_SCALE).clamp(0.0, 1.0);
+        let brightness = self.smoothed_brightness.value().clamp(0.0, 1.0);
+        let rolloff = DARK_ROLLOFF + (BRIGHT_ROLLOFF - DARK_ROLLOFF) * brightness;
+        let nyquist_guard = self.sample_rate * 0.45;
+        let block_secs = out.len() as f32 / self.sample_rate;
+
+        let mut increments = [0.0; MAX_PARTIALS];
+        let mut amplitudes = [0.0; MAX_PARTIALS];
+        let mut total_amplitude = 0.0;
+        let mut active = 0;
+        for n in 0..partials {
+            let harmonic = (n + 1) as f32;
+            let cents = Self::partial_spread(n) * MAX_PARTIAL_DETUNE_CENTS * tension;
+            let freq = self.smoothed_base_freq_hz.value() * harmonic * (cents / 1200.0).exp2();
+            if freq >= nyquist_guard {
+                break;
+            }
+
+            // Slow swells between 0.02 and 0.1 Hz, deeper with motion
+            let lfo_hz = 0.02 + 0.08 * (Self::partial_spread(n + MAX_PARTIALS) * 0.5 + 0.5);
+            self.partial_lfo_phases[n] =
+                (self.partial_lfo_phases[n] + lfo_hz * two_pi * block_secs) % two_pi;
+            let swell = 1.0 - 0.6 * motion * (0.5 + 0.5 * self.partial_lfo_phases[n].sin());
+
+            increments[n] = freq * two_pi / self.sample_rate;
+            amplitudes[n] = harmonic.powf(-rolloff) * swell;
+            total_amplitude += amplitudes[n];
+            active = n + 1;
+        }
 
-        // Update phases (increment by pre-calculated radians per sample)
-        self.phase_a += self.phase_incr_a;
-        self.phase_b += self.phase_incr_b;
+        // Normalize so brightness changes timbre, not level
+        let norm = if total_amplitude > 0.0 {
+            1.0 / total_amplitude
+        } else {
+            0.0
+        };
+        for amplitude in &mut amplitudes[..active] {
+            *amplitude *= norm;
+        }
 
-        // Wrap phases at 2π to prevent floating point precision issues
-        if self.phase_a >= two_pi {
-            self.phase_a -= two_pi;
+        out.fill(0.0);
+        for n in 0..active {
+            dsp::add_sine(
+                out,
+                &mut self.partial_phases[n],
+                increments[n],
+                amplitudes[n],
+            );
         }
-        if self.phase_b >= two_pi {
-            self.phase_b -= two_pi;
+    }
+}
+
+impl Layer for DroneLayer {
+    fn process_block(&mut self, out: &mut [f32], params: &AudioParams) {
+        // Smooth parameters
+        let frames = out.len();
+        self.smoothed_master_gain
+            .advance(params.master_gain, frames);
+        self.smoothed_base_freq_hz
+            .advance(params.base_freq_hz, frames);
+        self.smoothed_detune_ratio
+            .advance(params.detune_ratio, frames);
+        self.smoothed_brightness.advance(params.brightness, frames);
+        self.smoothed_motion.advance(params.motion, frames);
+        self.smoothed_texture.advance(params.texture, frames);
+        let grit = self.smoothed_grit.advance(params.grit, frames);
+
+        match self.mode {
+            DroneMode::Dual => self.dual(out),
+            DroneMode::Additive(partials) => self.additive(out, partials),
         }
+        Self::saturate(out, grit);
+    }
+
+    fn set_smoothing(&mut self, settings: &SmoothingSettings) {
+        let (times, rate) = (settings.slow, self.sample_rate);
+        self.smoothed_master_gain.set_time(times.master_gain, rate);
+        self.smoothed_base_freq_hz.set_time(times.base_freq, rate);
+        self.smoothed_detune_ratio.set_time(times.detune, rate);
+        self.smoothed_brightness.set_time(times.brightness, rate);
+        self.smoothed_motion.set_time(times.motion, rate);
+        self.smoothed_texture.set_time(times.texture, rate);
+        self.smoothed_grit.set_time(times.texture, rate);
+    }
 
-        mixed_sample
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        // Phases are in radians, and the increments are worked out afresh
+        // each block
+        self.sample_rate = sample_rate;
+        for smoother in [
+            &mut self.smoothed_master_gain,
+            &mut self.smoothed_base_freq_hz,
+            &mut self.smoothed_detune_ratio,
+            &mut self.smoothed_brightness,
+            &mut self.smoothed_motion,
+            &mut self.smoothed_texture,
+            &mut self.smoothed_grit,
+        ] {
+            smoother.set_sample_rate(sample_rate);
+        }
     }
 }
 
@@ -116,29 +247,34 @@ pub struct SparkleLayer {
     envelope_phase: f32, // 0.0 to 1.0, where 1.0 means envelope complete
     envelope_duration_samples: f32,
     sample_rate: f32,
-    noise_seed: f32,
-    smoothed_sparkle_impulse: f32,
+    noise: Noise,
+    smoothed_sparkle_impulse: Smoother,
     prev_smoothed_impulse: f32,
-    smoothing_coeff: f32,
     // Smoothed parameters for musical influence
-    smoothed_tension: f32,
-    smoothed_motion: f32,
-    smoothed_brightness: f32,
+    smoothed_tension: Smoother,
+    smoothed_motion: Smoother,
+    smoothed_brightness: Smoother,
 }
 
 impl SparkleLayer {
     pub fn new(sample_rate: f32) -> Self {
+        Self::with_noise(sample_rate, NoiseColor::White)
+    }
+
+    pub fn with_noise(sample_rate: f32, color: NoiseColor) -> Self {
+        // Fast, to catch quick impulses
+        let times = SmoothingSettings::default().fast;
+        let smoother = |time_ms| Smoother::new(0.0, time_ms, sample_rate);
         Self {
             envelope_phase: 1.0, // Start with envelope complete (no sound)
             envelope_duration_samples: sample_rate * 0.1, // 100ms envelope
             sample_rate,
-            noise_seed: 0.0,
-            smoothed_sparkle_impulse: 0.0,
+            noise: Noise::new(0x5EED_0001, color),
+            smoothed_sparkle_impulse: smoother(times.sparkle),
             prev_smoothed_impulse: 0.0,
-            smoothing_coeff: 0.2, // Very fast smoothing for sparkles to catch quick impulses
-            smoothed_tension: 0.0,
-            smoothed_motion: 0.0,
-            smoothed_brightness: 0.0,
+            smoothed_tension: smoother(times.detune),
+            smoothed_motion: smoother(times.motion),
+            smoothed_brightness: smoother(times.brightness),
         }
     }
 
@@ -162,19 +298,13 @@ impl SparkleLayer {
         }
     }
 
-    // Generate white noise sample
-    fn noise(&mut self) -> f32 {
-        // Simple LCG noise generator
-        self.noise_seed = (self.noise_seed * 1103515245.0 + 12345.0) % (1 << 31) as f32;
-        let sample = (self.noise_seed / (1 << 31) as f32) * 2.0 - 1.0;
-        // Ensure no NaN or inf
-        if sample.is_finite() { sample } else { 0.0 }
-    }
-
     // Generate filtered noise burst influenced by brightness and motion
-    fn filtered_noise_burst(&mut self, envelope_value: f32, brightness: f32, motion: f32) -> f32 {
-        let base_noise = self.noise();
-
+    fn filtered_noise_burst(
+        base_noise: f32,
+        envelope_value: f32,
+        brightness: f32,
+        motion: f32,
+    ) -> f32 {
         // Motion affects the energy/pitch of the noise (higher motion = brighter/higher frequency)
         let motion_factor = 1.0 + motion * 1.5; // 1.0 to 2.5 (reduced from 3.0)
 
@@ -194,127 +324,144 @@ impl SparkleLayer {
 }
 
 impl Layer for SparkleLayer {
-    fn process(&mut self, params: &AudioParams) -> f32 {
+    fn process_block(&mut self, out: &mut [f32], params: &AudioParams) {
         // Smooth all parameters
-        self.prev_smoothed_impulse = self.smoothed_sparkle_impulse;
-        self.smoothed_sparkle_impulse +=
-            (params.sparkle_impulse - self.smoothed_sparkle_impulse) * self.smoothing_coeff;
+        let frames = out.len();
+        self.prev_smoothed_impulse = self.smoothed_sparkle_impulse.value();
+        let impulse = self
+            .smoothed_sparkle_impulse
+            .advance(params.sparkle_impulse, frames);
 
         // Smooth musical parameters
-        self.smoothed_tension +=
-            (params.detune_ratio - self.smoothed_tension) * self.smoothing_coeff;
-        self.smoothed_motion += (params.motion - self.smoothed_motion) * self.smoothing_coeff;
-        self.smoothed_brightness +=
-            (params.brightness - self.smoothed_brightness) * self.smoothing_coeff;
+        let tension = self.smoothed_tension.advance(params.detune_ratio, frames);
+        let motion = self.smoothed_motion.advance(params.motion, frames);
+        let brightness = self.smoothed_brightness.advance(params.brightness, frames);
 
         // Update envelope duration based on motion (higher motion = shorter, more rhythmic events)
-        self.envelope_duration_samples = self.sample_rate * (0.05 + self.smoothed_motion * 0.15); // 50-200ms
+        self.envelope_duration_samples = self.sample_rate * (0.05 + motion * 0.15); // 50-200ms
 
         // Trigger new envelope when smoothed impulse crosses threshold and we can start a new one
-        if self.smoothed_sparkle_impulse > 0.002
-            && self.envelope_phase >= 1.0
-            && self.prev_smoothed_impulse <= 0.002
-        {
+        if impulse > 0.002 && self.envelope_phase >= 1.0 && self.prev_smoothed_impulse <= 0.002 {
             self.envelope_phase = 0.0; // Start new envelope
         }
 
-        // If envelope is active, generate sparkle sound
-        if self.envelope_phase < 1.0 {
-            let envelope_value = self.envelope(self.envelope_phase, self.smoothed_tension);
+        // Silent between sparkles; otherwise shape a block of white noise
+        if self.envelope_phase >= 1.0 {
+            out.fill(0.0);
+            return;
+        }
+        self.noise.fill(out);
+
+        for sample in out.iter_mut() {
+            // No sound when envelope is complete
+            if self.envelope_phase >= 1.0 {
+                *sample = 0.0;
+                continue;
+            }
+            let envelope_value = self.envelope(self.envelope_phase, tension);
 
             // Generate filtered noise burst influenced by brightness and motion
-            let sparkle_sample = self.filtered_noise_burst(
-                envelope_value,
-                self.smoothed_brightness,
-                self.smoothed_motion,
-            );
+            let sparkle_sample =
+                Self::filtered_noise_burst(*sample, envelope_value, brightness, motion);
 
             // Use smoothed impulse for overall amplitude
-            let final_sample = sparkle_sample * self.smoothed_sparkle_impulse;
+            let final_sample = sparkle_sample * impulse;
 
             // Update envelope phase
             self.envelope_phase += 1.0 / self.envelope_duration_samples;
 
             // Ensure output is finite and apply gentle limiting
-            if final_sample.is_finite() {
+            *sample = if final_sample.is_finite() {
                 // Soft limit sparkle peaks to prevent crackling
                 final_sample.clamp(-0.8, 0.8)
             } else {
                 0.0
-            }
-        } else {
-            0.0 // No sound when envelope is complete
+            };
+        }
+    }
+
+    fn set_smoothing(&mut self, settings: &SmoothingSettings) {
+        let (times, rate) = (settings.fast, self.sample_rate);
+        self.smoothed_sparkle_impulse.set_time(times.sparkle, rate);
+        self.smoothed_tension.set_time(times.detune, rate);
+        self.smoothed_motion.set_time(times.motion, rate);
+        self.smoothed_brightness.set_time(times.brightness, rate);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        // The envelope runs in fractions of its length, which is recomputed
+        // each block
+        self.envelope_duration_samples *= sample_rate / self.sample_rate;
+        self.sample_rate = sample_rate;
+        for smoother in [
+            &mut self.smoothed_sparkle_impulse,
+            &mut self.smoothed_tension,
+            &mut self.smoothed_motion,
+            &mut self.smoothed_brightness,
+        ] {
+            smoother.set_sample_rate(sample_rate);
         }
     }
 }
 
 /// Texture layer that provides a subtle noise bed with slow modulation.
 pub struct TextureLayer {
-    noise_seed: f32,
+    noise: Noise,
     lfo_phase: f32,
-    smoothed_density: f32,
-    smoothed_warmth: f32,
-    smoothed_tension: f32,
-    smoothed_energy: f32,
+    smoothed_density: Smoother,
+    smoothed_warmth: Smoother,
+    smoothed_tension: Smoother,
+    smoothed_energy: Smoother,
+    smoothed_grit: Smoother,
     // Simple low-pass filter state
     filter_x1: f32,
     filter_y1: f32,
     sample_rate: f32,
-    smoothing_coeff: f32,
 }
 
 impl TextureLayer {
     pub fn new(sample_rate: f32) -> Self {
+        Self::with_noise(sample_rate, NoiseColor::White)
+    }
+
+    pub fn with_noise(sample_rate: f32, color: NoiseColor) -> Self {
+        let times = SmoothingSettings::default().slow;
+        let smoother = |time_ms| Smoother::new(0.0, time_ms, sample_rate);
         Self {
-            noise_seed: 0.0,
+            noise: Noise::new(0x5EED_0002, color),
             lfo_phase: 0.0,
-            smoothed_density: 0.0,
-            smoothed_warmth: 0.0,
-            smoothed_tension: 0.0,
-            smoothed_energy: 0.0,
+            smoothed_density: smoother(times.texture),
+            smoothed_warmth: smoother(times.brightness),
+            smoothed_tension: smoother(times.detune),
+            smoothed_energy: smoother(times.motion),
+            smoothed_grit: smoother(times.texture),
             filter_x1: 0.0,
             filter_y1: 0.0,
             sample_rate,
-            smoothing_coeff: 0.005, // Very slow smoothing for texture
         }
     }
 
-    fn smooth(current: &mut f32, target: f32, coeff: f32) {
-        *current += (target - *current) * coeff;
-    }
-
-    // Generate noise with tension-based roughness
-    fn noise(&mut self, tension: f32) -> f32 {
-        // Base LCG noise
-        self.noise_seed = (self.noise_seed * 1103515245.0 + 12345.0) % (1 << 31) as f32;
-        let base_noise = (self.noise_seed / (1 << 31) as f32) * 2.0 - 1.0;
-
-        // Add roughness based on tension (slight distortion)
-        let roughness = tension * 0.1;
-        let rough_noise = base_noise + roughness * base_noise.powi(3);
-
-        // Ensure finite
-        if rough_noise.is_finite() {
-            rough_noise
-        } else {
-            base_noise
-        }
+    // Add roughness based on tension (slight distortion) and grit (heavy)
+    fn roughen(base_noise: f32, tension: f32, grit: f32) -> f32 {
+        let roughness = tension * 0.1 + grit;
+        base_noise + roughness * base_noise.powi(3)
     }
 
     // Simple low-pass filter for warmth control
-    fn filter(&mut self, input: f32, cutoff: f32) -> f32 {
+    fn filter(&mut self, block: &mut [f32], cutoff: f32) {
         // Bilinear transform approximation of low-pass filter
         // cutoff is normalized (0.0 = no filtering, 1.0 = heavy filtering)
         let a = cutoff.clamp(0.001, 0.99);
         let b = 1.0 - a;
 
-        let output = a * input + b * self.filter_x1 - b * self.filter_y1;
-
-        // Update filter state
-        self.filter_x1 = input;
-        self.filter_y1 = output;
-
-        output
+        // y[n] = a*x[n] + b*x[n-1] - b*y[n-1]: the feedforward part here, the
+        // recursive part in the one-pole kernel
+        for sample in block.iter_mut() {
+            let input = *sample;
+            *sample = a * input + b * self.filter_x1;
+            self.filter_x1 = input;
+        }
+        dsp::one_pole(block, &mut self.filter_y1, -b);
     }
 
     // Slow LFO for amplitude modulation
@@ -341,47 +488,155 @@ impl TextureLayer {
 }
 
 impl Layer for TextureLayer {
-    fn process(&mut self, params: &AudioParams) -> f32 {
+    fn process_block(&mut self, out: &mut [f32], params: &AudioParams) {
         // Smooth parameters
-        Self::smooth(
+        let frames = out.len();
+        let density = self.smoothed_density.advance(params.texture, frames);
+        let warmth = self.smoothed_warmth.advance(params.brightness, frames);
+        let tension = self.smoothed_tension.advance(params.detune_ratio, frames);
+        let energy = self.smoothed_energy.advance(params.motion, frames);
+        let grit = self.smoothed_grit.advance(params.grit, frames);
+
+        // Generate base noise with tension- and grit-based roughness
+        self.noise.fill(out);
+        for sample in out.iter_mut() {
+            *sample = Self::roughen(*sample, tension, grit);
+        }
+
+        // Apply filtering based on warmth (0.0 = bright, 1.0 = warm/dark)
+        self.filter(out, warmth);
+
+        for sample in out.iter_mut() {
+            let filtered = *sample;
+
+            // Apply LFO modulation based on energy
+            let lfo = self.lfo(energy);
+            let modulated = filtered * (1.0 + lfo * 0.3); // ±30% modulation
+
+            // Scale by density and apply subtle gain
+            let texture_sample = modulated * density * 0.1;
+
+            // Ensure finite output
+            *sample = if texture_sample.is_finite() {
+                texture_sample
+            } else {
+                0.0
+            };
+        }
+    }
+
+    fn set_smoothing(&mut self, settings: &SmoothingSettings) {
+        let (times, rate) = (settings.slow, self.sample_rate);
+        self.smoothed_density.set_time(times.texture, rate);
+        self.smoothed_warmth.set_time(times.brightness, rate);
+        self.smoothed_tension.set_time(times.detune, rate);
+        self.smoothed_energy.set_time(times.motion, rate);
+        self.smoothed_grit.set_time(times.texture, rate);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for smoother in [
             &mut self.smoothed_density,
-            params.texture,
-            self.smoothing_coeff,
-        );
-        Self::smooth(
             &mut self.smoothed_warmth,
-            params.brightness,
-            self.smoothing_coeff,
-        );
-        Self::smooth(
             &mut self.smoothed_tension,
-            params.detune_ratio,
-            self.smoothing_coeff,
-        );
-        Self::smooth(
             &mut self.smoothed_energy,
-            params.motion,
-            self.smoothing_coeff,
-        );
+            &mut self.smoothed_grit,
+        ] {
+            smoother.set_sample_rate(sample_rate);
+        }
+    }
+}
 
-        // Generate base noise with tension-based roughness
-        let noise = self.noise(self.smoothed_tension);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Energy of the drone output's sample-to-sample differences relative to
+    /// its level, a rough measure of upper-partial content.
+    fn roughness(mode: DroneMode, params: &AudioParams) -> f32 {
+        let mut drone = DroneLayer::with_mode(8_000.0, mode);
+        drone.set_smoothing(&SmoothingSettings::uniform(0.0));
+        let mut out = [0.0; 8_000];
+        drone.process_block(&mut out, params);
+        let (mut prev, mut level, mut diff) = (0.0, 0.0, 0.0);
+        for sample in out {
+            assert!(sample.is_finite() && sample.abs() <= 1.0);
+            level += sample * sample;
+            diff += (sample - prev) * (sample - prev);
+            prev = sample;
+        }
+        diff / level
+    }
 
-        // Apply filtering based on warmth (0.0 = bright, 1.0 = warm/dark)
-        let filtered = self.filter(noise, self.smoothed_warmth);
+    #[test]
+    fn test_brightness_raises_upper_partials() {
+        let dark = AudioParams {
+            base_freq_hz: 110.0,
+            brightness: 0.0,
+            ..AudioParams::default()
+        };
+        let bright = AudioParams {
+            brightness: 1.0,
+            ..dark
+        };
+        let additive = DroneMode::Additive(12);
+        assert!(roughness(additive, &bright) > roughness(additive, &dark) * 2.0);
+    }
 
-        // Apply LFO modulation based on energy
-        let lfo = self.lfo(self.smoothed_energy);
-        let modulated = filtered * (1.0 + lfo * 0.3); // ±30% modulation
+    #[test]
+    fn test_grit_saturates_the_drone() {
+        let clean = AudioParams {
+            base_freq_hz: 110.0,
+            ..AudioParams::default()
+        };
+        let gritty = AudioParams { grit: 1.0, ..clean };
+        let dual = DroneMode::Dual;
+        assert!(roughness(dual, &gritty) > roughness(dual, &clean) * 1.5);
+    }
 
-        // Scale by density and apply subtle gain
-        let texture_sample = modulated * self.smoothed_density * 0.1;
+    #[test]
+    fn test_grit_roughens_the_texture() {
+        let level = |grit: f32| {
+            let mut texture = TextureLayer::new(8_000.0);
+            texture.set_smoothing(&SmoothingSettings::uniform(0.0));
+            let params = AudioParams {
+                texture: 0.3,
+                grit,
+                ..AudioParams::default()
+            };
+            let mut out = [0.0; 8_000];
+            texture.process_block(&mut out, &params);
+            out.iter().map(|s| s * s).sum::<f32>()
+        };
+        assert!(level(1.0) > level(0.0));
+    }
 
-        // Ensure finite output
-        if texture_sample.is_finite() {
-            texture_sample
-        } else {
-            0.0
-        }
+    #[test]
+    fn test_new_sample_rate_keeps_phase_and_pitch() {
+        let mut drone = DroneLayer::new(48_000.0);
+        drone.set_smoothing(&SmoothingSettings::uniform(0.0));
+        let params = AudioParams::default();
+        let mut before = [0.0; 100];
+        drone.process_block(&mut before, &params);
+
+        drone.set_sample_rate(44_100.0);
+        let mut after = vec![0.0; 44_100];
+        drone.process_block(&mut after, &params);
+        // Picks up where it stopped, and still plays 440 Hz
+        assert!((after[0] - before[99]).abs() < 0.07);
+        let crossings = after
+            .windows(2)
+            .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+            .count();
+        assert!((878..=882).contains(&crossings), "{}", crossings);
+    }
+
+    #[test]
+    fn test_partial_count_is_clamped() {
+        let drone = DroneLayer::with_mode(48_000.0, DroneMode::Additive(100));
+        assert_eq!(drone.mode, DroneMode::Additive(MAX_PARTIALS));
+        let drone = DroneLayer::with_mode(48_000.0, DroneMode::Additive(0));
+        assert_eq!(drone.mode, DroneMode::Additive(1));
     }
 }
@@ -1,3 +1,33 @@
+pub mod ambisonics;
+pub mod backend;
+pub mod binaural;
+pub mod dsp;
+pub mod ducking;
+pub mod effects;
+#[cfg(feature = "cpal")]
 pub mod engine;
+pub mod gate;
+pub mod grain;
+pub mod health;
+pub mod input;
 pub mod layers;
+pub mod loudness;
+pub mod mapping;
+pub mod master;
+pub mod mixer;
+pub mod noise;
 pub mod params;
+pub mod registry;
+pub mod render;
+pub mod rolling;
+pub mod sample;
+pub mod smoother;
+pub mod spatial;
+pub mod spsc;
+pub mod status;
+pub mod tap;
+pub mod telemetry;
+pub mod transition;
+pub mod watchdog;
+pub mod waveform;
+pub mod wind;
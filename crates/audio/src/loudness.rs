@@ -0,0 +1,461 @@
+//! Loudness metering (ITU-R BS.1770 style) and slow auto-gain.
+//!
+//! The output is K-weighted and measured in 400 ms blocks every 100 ms. A
+//! gated average over a rolling window stands in for integrated loudness,
+//! and the auto-gain nudges a trim toward the target at a few hundredths of
+//! a dB per block, so perceived volume stays steady over hours without any
+//! audible pumping.
+//!
+//! The window's blocks are also kept as running sums by loudness, in bins of
+//! [`GATE_BIN_LU`], so gating costs the same however long the window: the
+//! relative gate falls on a bin edge, close enough at a tenth of an LU.
+
+/// Sub-block length; four of them make a 400 ms measurement block.
+const SUB_BLOCK_SECS: f32 = 0.1;
+const SUB_BLOCKS_PER_BLOCK: usize = 4;
+
+/// Blocks quieter than this never count toward the average.
+const ABSOLUTE_GATE_LUFS: f32 = -70.0;
+
+/// Blocks this far below the ungated average are dropped as well.
+const RELATIVE_GATE_LU: f32 = -10.0;
+
+/// Reported when nothing has passed the gates yet.
+pub const LOUDNESS_FLOOR_LUFS: f32 = -120.0;
+
+/// Width of the loudness bins the gates are taken over.
+pub const GATE_BIN_LU: f32 = 0.1;
+
+/// Blocks louder than this share the top bin.
+const GATE_BIN_CEILING_LUFS: f32 = 20.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoudnessSettings {
+    /// Whether the auto-gain trims the output. Metering always runs.
+    pub auto_gain: bool,
+    pub target_lufs: f32,
+    /// Length of the rolling window the integrated loudness is taken over.
+    pub window_secs: f32,
+    /// Largest boost or cut the auto-gain may apply.
+    pub max_trim_db: f32,
+    /// How fast the trim may move.
+    pub rate_db_per_sec: f32,
+}
+
+impl Default for LoudnessSettings {
+    fn default() -> Self {
+        Self {
+            auto_gain: false,
+            target_lufs: -24.0,
+            window_secs: 60.0,
+            max_trim_db: 12.0,
+            rate_db_per_sec: 0.1,
+        }
+    }
+}
+
+fn power_to_lufs(power: f32) -> f32 {
+    if power > 0.0 {
+        (-0.691 + 10.0 * power.log10()).max(LOUDNESS_FLOOR_LUFS)
+    } else {
+        LOUDNESS_FLOOR_LUFS
+    }
+}
+
+/// Block count and power per loudness bin above the absolute gate, as
+/// Fenwick trees so either can be summed from any bin up in O(log bins).
+struct GateBins {
+    counts: Vec<i64>,
+    powers: Vec<f64>,
+    total_count: i64,
+    total_power: f64,
+}
+
+impl GateBins {
+    fn new() -> Self {
+        let bins = ((GATE_BIN_CEILING_LUFS - ABSOLUTE_GATE_LUFS) / GATE_BIN_LU).ceil() as usize;
+        Self {
+            counts: vec![0; bins + 1],
+            powers: vec![0.0; bins + 1],
+            total_count: 0,
+            total_power: 0.0,
+        }
+    }
+
+    /// The bin of a block at `lufs`, or None at or below the absolute gate.
+    fn bin(&self, lufs: f32) -> Option<usize> {
+        (lufs > ABSOLUTE_GATE_LUFS).then(|| {
+            (((lufs - ABSOLUTE_GATE_LUFS) / GATE_BIN_LU) as usize).min(self.counts.len() - 2)
+        })
+    }
+
+    /// Adds (`sign` 1) or removes (-1) a block of mean square `power`.
+    fn update(&mut self, power: f32, sign: i64) {
+        let Some(bin) = self.bin(power_to_lufs(power)) else {
+            return;
+        };
+        let power = sign as f64 * power as f64;
+        self.total_count += sign;
+        self.total_power += power;
+        let mut i = bin + 1;
+        while i < self.counts.len() {
+            self.counts[i] += sign;
+            self.powers[i] += power;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Count and power of the blocks in bins below `bin`.
+    fn below(&self, bin: usize) -> (i64, f64) {
+        let (mut count, mut power) = (0, 0.0);
+        let mut i = bin.min(self.counts.len() - 1);
+        while i > 0 {
+            count += self.counts[i];
+            power += self.powers[i];
+            i -= i & i.wrapping_neg();
+        }
+        (count, power)
+    }
+
+    /// Mean square of the blocks above the absolute gate, and of those in
+    /// bins from the relative gate up; None if there are none.
+    fn gated_mean(&self) -> Option<f32> {
+        if self.total_count <= 0 {
+            return None;
+        }
+        let ungated = (self.total_power / self.total_count as f64).max(0.0) as f32;
+        let gate = (power_to_lufs(ungated) + RELATIVE_GATE_LU).max(ABSOLUTE_GATE_LUFS);
+        let first = ((gate - ABSOLUTE_GATE_LUFS) / GATE_BIN_LU).ceil() as usize;
+        let (count, power) = self.below(first);
+        let count = self.total_count - count;
+        (count > 0).then(|| ((self.total_power - power) / count as f64).max(0.0) as f32)
+    }
+}
+
+/// Direct form I biquad.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl Biquad {
+    fn new(b: [f32; 3], a: [f32; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// K-weighting: a high shelf for head effects, then a high-pass (RLB curve).
+///
+/// Coefficients are derived for any sample rate from the 48 kHz reference filters.
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: f32) -> Self {
+        let fs = sample_rate as f64;
+
+        let f0 = 1_681.974_450_955_533;
+        let gain_db = 3.999_843_853_973_347;
+        let q = 0.707_175_236_955_419_6;
+        let k = (std::f64::consts::PI * f0 / fs).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.499_666_774_154_541_6);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                ((vh + vb * k / q + k * k) / a0) as f32,
+                (2.0 * (k * k - vh) / a0) as f32,
+                ((vh - vb * k / q + k * k) / a0) as f32,
+            ],
+            [
+                (2.0 * (k * k - 1.0) / a0) as f32,
+                ((1.0 - k / q + k * k) / a0) as f32,
+            ],
+        );
+
+        let f0 = 38.135_470_876_024_44;
+        let q = 0.500_327_037_323_877_3;
+        let k = (std::f64::consts::PI * f0 / fs).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [
+                (2.0 * (k * k - 1.0) / a0) as f32,
+                ((1.0 - k / q + k * k) / a0) as f32,
+            ],
+        );
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        self.high_pass.process(self.shelf.process(input))
+    }
+}
+
+/// Momentary and rolling integrated loudness of a mono signal, plus auto-gain trim.
+pub struct LoudnessMeter {
+    settings: LoudnessSettings,
+    weighting: KWeighting,
+    sub_block_len: usize,
+    sub_block_pos: usize,
+    sub_block_sum: f32,
+    /// Mean square of the most recent sub-blocks with the trim taken back
+    /// out, newest last.
+    sub_blocks: [f32; SUB_BLOCKS_PER_BLOCK],
+    /// Sub-blocks seen so far, up to a full block's worth.
+    sub_blocks_filled: usize,
+    /// Untrimmed mean square of each 400 ms block in the window (ring buffer).
+    blocks: Vec<f32>,
+    block_pos: usize,
+    block_count: usize,
+    /// The window's blocks binned for gating.
+    gate_bins: GateBins,
+    momentary_lufs: f32,
+    integrated_lufs: f32,
+    trim_db: f32,
+    trim_gain: f32,
+    /// Keeps the trim where it is, as while the output is being measured.
+    trim_held: bool,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: f32, settings: LoudnessSettings) -> Self {
+        let window_blocks = ((settings.window_secs / SUB_BLOCK_SECS) as usize).max(1);
+        Self {
+            settings,
+            weighting: KWeighting::new(sample_rate),
+            sub_block_len: ((SUB_BLOCK_SECS * sample_rate) as usize).max(1),
+            sub_block_pos: 0,
+            sub_block_sum: 0.0,
+            sub_blocks: [0.0; SUB_BLOCKS_PER_BLOCK],
+            sub_blocks_filled: 0,
+            blocks: vec![0.0; window_blocks],
+            block_pos: 0,
+            block_count: 0,
+            gate_bins: GateBins::new(),
+            momentary_lufs: LOUDNESS_FLOOR_LUFS,
+            integrated_lufs: LOUDNESS_FLOOR_LUFS,
+            trim_db: 0.0,
+            trim_gain: 1.0,
+            trim_held: false,
+        }
+    }
+
+    /// Loudness of the last 400 ms.
+    pub fn momentary_lufs(&self) -> f32 {
+        self.momentary_lufs
+    }
+
+    /// Gated loudness over the rolling window.
+    pub fn integrated_lufs(&self) -> f32 {
+        self.integrated_lufs
+    }
+
+    /// Current auto-gain trim in dB (0 when auto-gain is off).
+    pub fn trim_db(&self) -> f32 {
+        self.trim_db
+    }
+
+    /// Linear gain to apply ahead of the limiter.
+    pub fn trim_gain(&self) -> f32 {
+        self.trim_gain
+    }
+
+    /// Stops the auto-gain moving the trim, or lets it move again. Metering
+    /// goes on either way.
+    pub fn hold_trim(&mut self, held: bool) {
+        self.trim_held = held;
+    }
+
+    /// Measures one output sample.
+    pub fn process(&mut self, sample: f32) {
+        let weighted = self.weighting.process(sample);
+        self.sub_block_sum += weighted * weighted;
+        self.sub_block_pos += 1;
+        if self.sub_block_pos >= self.sub_block_len {
+            self.finish_sub_block();
+        }
+    }
+
+    fn finish_sub_block(&mut self) {
+        // Measure the untrimmed level so the auto-gain does not chase its
+        // own changes through the window
+        let mean_square =
+            self.sub_block_sum / self.sub_block_len as f32 / (self.trim_gain * self.trim_gain);
+        self.sub_block_sum = 0.0;
+        self.sub_block_pos = 0;
+        self.sub_blocks.rotate_left(1);
+        self.sub_blocks[SUB_BLOCKS_PER_BLOCK - 1] = mean_square;
+        // Skip the partial blocks at start-up, they would read low
+        self.sub_blocks_filled = (self.sub_blocks_filled + 1).min(SUB_BLOCKS_PER_BLOCK);
+        if self.sub_blocks_filled < SUB_BLOCKS_PER_BLOCK {
+            return;
+        }
+
+        // Each 400 ms block overlaps the previous one by 75%
+        let block = self.sub_blocks.iter().sum::<f32>() / SUB_BLOCKS_PER_BLOCK as f32;
+        if self.block_count == self.blocks.len() {
+            self.gate_bins.update(self.blocks[self.block_pos], -1);
+        }
+        self.gate_bins.update(block, 1);
+        self.blocks[self.block_pos] = block;
+        self.block_pos = (self.block_pos + 1) % self.blocks.len();
+        self.block_count = (self.block_count + 1).min(self.blocks.len());
+        let untrimmed = self
+            .gate_bins
+            .gated_mean()
+            .map_or(LOUDNESS_FLOOR_LUFS, power_to_lufs);
+
+        if self.settings.auto_gain && !self.trim_held && untrimmed > LOUDNESS_FLOOR_LUFS {
+            let max_step = self.settings.rate_db_per_sec * SUB_BLOCK_SECS;
+            let wanted = (self.settings.target_lufs - untrimmed)
+                .clamp(-self.settings.max_trim_db, self.settings.max_trim_db);
+            self.trim_db += (wanted - self.trim_db).clamp(-max_step, max_step);
+            self.trim_gain = 10f32.powf(self.trim_db / 20.0);
+        }
+
+        // Report what actually goes out, at the current trim
+        let trimmed = |lufs: f32| {
+            if lufs > LOUDNESS_FLOOR_LUFS {
+                (lufs + self.trim_db).max(LOUDNESS_FLOOR_LUFS)
+            } else {
+                lufs
+            }
+        };
+        self.momentary_lufs = trimmed(power_to_lufs(block));
+        self.integrated_lufs = trimmed(untrimmed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(amplitude: f32, i: usize) -> f32 {
+        amplitude * (i as f32 * 997.0 * std::f32::consts::TAU / 48_000.0).sin()
+    }
+
+    #[test]
+    fn test_full_scale_sine_reads_minus_three() {
+        let mut meter = LoudnessMeter::new(48_000.0, LoudnessSettings::default());
+        for i in 0..96_000 {
+            meter.process(sine(1.0, i));
+        }
+        // Reference: a 0 dBFS 1 kHz sine in one channel is -3.01 LUFS
+        assert!((meter.momentary_lufs() + 3.01).abs() < 0.1);
+        assert!((meter.integrated_lufs() + 3.01).abs() < 0.1);
+
+        let mut quiet = LoudnessMeter::new(48_000.0, LoudnessSettings::default());
+        for i in 0..96_000 {
+            quiet.process(sine(0.1, i));
+        }
+        assert!((quiet.integrated_lufs() + 23.01).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_silence_is_gated() {
+        let mut meter = LoudnessMeter::new(48_000.0, LoudnessSettings::default());
+        for _ in 0..48_000 {
+            meter.process(0.0);
+        }
+        assert_eq!(meter.integrated_lufs(), LOUDNESS_FLOOR_LUFS);
+    }
+
+    /// Gating done block by block, as the running sums stand in for.
+    fn gated_by_block(blocks: &[f32]) -> f32 {
+        let mean_above = |threshold: f32| {
+            let above: Vec<f32> = blocks
+                .iter()
+                .copied()
+                .filter(|power| power_to_lufs(*power) > threshold)
+                .collect();
+            (!above.is_empty()).then(|| above.iter().sum::<f32>() / above.len() as f32)
+        };
+        let Some(ungated) = mean_above(ABSOLUTE_GATE_LUFS) else {
+            return LOUDNESS_FLOOR_LUFS;
+        };
+        let gate = (power_to_lufs(ungated) + RELATIVE_GATE_LU).max(ABSOLUTE_GATE_LUFS);
+        mean_above(gate).map_or(LOUDNESS_FLOOR_LUFS, power_to_lufs)
+    }
+
+    #[test]
+    fn test_running_gates_match_gating_each_block() {
+        let settings = LoudnessSettings {
+            window_secs: 3.0,
+            ..LoudnessSettings::default()
+        };
+        let mut meter = LoudnessMeter::new(8_000.0, settings);
+        // Loud, quiet, silent and in between, long enough to cycle the window
+        let levels = [0.5, 0.02, 0.0, 0.2, 0.005, 0.8, 0.0, 0.1];
+        for (second, amplitude) in levels.iter().cycle().take(24).enumerate() {
+            for i in 0..8_000 {
+                let n = second * 8_000 + i;
+                meter.process(
+                    amplitude * (n as f32 * 997.0 * std::f32::consts::TAU / 8_000.0).sin(),
+                );
+            }
+            let exact = gated_by_block(&meter.blocks[..meter.block_count]);
+            assert!(
+                (meter.integrated_lufs() - exact).abs() < 2.0 * GATE_BIN_LU,
+                "second {}: {} against {}",
+                second,
+                meter.integrated_lufs(),
+                exact
+            );
+        }
+
+        // Once the window has only silence in it, nothing passes
+        for _ in 0..8_000 * 4 {
+            meter.process(0.0);
+        }
+        assert_eq!(meter.integrated_lufs(), LOUDNESS_FLOOR_LUFS);
+    }
+
+    #[test]
+    fn test_auto_gain_converges_on_target() {
+        let settings = LoudnessSettings {
+            auto_gain: true,
+            target_lufs: -20.0,
+            window_secs: 3.0,
+            rate_db_per_sec: 2.0,
+            ..LoudnessSettings::default()
+        };
+        let mut meter = LoudnessMeter::new(8_000.0, settings);
+        // A -30 LUFS-ish source fed back through the trim, for two minutes
+        for i in 0..8_000 * 120 {
+            let source = 0.045 * (i as f32 * 997.0 * std::f32::consts::TAU / 8_000.0).sin();
+            meter.process(source * meter.trim_gain());
+        }
+        assert!((meter.integrated_lufs() + 20.0).abs() < 0.5);
+        assert!(meter.trim_db() > 5.0 && meter.trim_db() <= 12.0);
+
+        // Held, the trim stays put however loud the source gets
+        let held = meter.trim_db();
+        meter.hold_trim(true);
+        for i in 0..8_000 * 10 {
+            let source = 0.5 * (i as f32 * 997.0 * std::f32::consts::TAU / 8_000.0).sin();
+            meter.process(source * meter.trim_gain());
+        }
+        assert_eq!(meter.trim_db(), held);
+    }
+}
@@ -0,0 +1,418 @@
+//! World → audio mapping profiles.
+//!
+//! A profile gives each audio parameter a curve: which world parameter drives
+//! it, the output range it sweeps, and the [`Curve`] it eases along. The
+//! default profile reproduces [`AudioParams::from_world_state`]; the other
+//! built-ins make the same world sound darker, brighter, sparser or bigger.
+//! When the world's harmony is known, the drone's pitch settles on the
+//! current chord's root in whichever octave the profile's curve lands nearest,
+//! and dissonant chords widen the detune. Above the neutral 0.5, world clarity
+//! thins every profile's texture and narrows its detune, as in
+//! [`clarity_gain`].
+
+use crate::params::{
+    AudioParams, DETUNE_SCALE, GAIN_SCALE, LayerAmounts, MAX_PRE_DELAY_MS, MOTION_SCALE,
+    PRE_DELAY_SCALE_MS, TEXTURE_SCALE, clarity_gain,
+};
+use WorldInput::{Clarity, Density, Energy, Rhythm, Space, Tension, Warmth};
+use ambient_core::curves::Curve::{self, EaseIn, EaseOut, Linear, SmoothStep};
+use ambient_core::harmony::Harmony;
+use ambient_core::world::WorldSnapshot;
+use std::str::FromStr;
+
+/// Profile used when none is selected.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Detune above 1.0 gained at full chord dissonance, as a share of the
+/// profile's own.
+const HARMONY_DETUNE: f32 = 0.5;
+
+/// World parameter that drives a curve.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorldInput {
+    Density,
+    Rhythm,
+    Tension,
+    Energy,
+    Warmth,
+    Space,
+    Clarity,
+}
+
+impl FromStr for WorldInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "density" => Ok(WorldInput::Density),
+            "rhythm" => Ok(WorldInput::Rhythm),
+            "tension" => Ok(WorldInput::Tension),
+            "energy" => Ok(WorldInput::Energy),
+            "warmth" => Ok(WorldInput::Warmth),
+            "space" => Ok(WorldInput::Space),
+            "clarity" => Ok(WorldInput::Clarity),
+            _ => Err(format!(
+                "unknown world input '{}' (expected density, rhythm, tension, energy, warmth, space or clarity)",
+                s
+            )),
+        }
+    }
+}
+
+/// One audio parameter as a function of one world parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldCurve {
+    pub input: WorldInput,
+    /// Output when the input is 0.
+    pub from: f32,
+    /// Output when the input is 1.
+    pub to: f32,
+    pub curve: Curve,
+}
+
+impl FieldCurve {
+    pub fn new(input: WorldInput, from: f32, to: f32, curve: Curve) -> Self {
+        Self {
+            input,
+            from,
+            to,
+            curve,
+        }
+    }
+
+    pub fn eval(&self, world: &WorldInputs) -> f32 {
+        let amount = self.curve.apply(world.get(self.input) as f64) as f32;
+        self.from + (self.to - self.from) * amount
+    }
+}
+
+/// World parameters a profile reads, each in [0, 1].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldInputs {
+    pub density: f32,
+    pub rhythm: f32,
+    pub tension: f32,
+    pub energy: f32,
+    pub warmth: f32,
+    pub space: f32,
+    pub clarity: f32,
+    pub sparkle_impulse: f32,
+    /// Key and chord, if the world has one to follow.
+    pub harmony: Option<Harmony>,
+}
+
+impl Default for WorldInputs {
+    /// A world at rest: every parameter at the neutral 0.5, no sparkle and
+    /// no harmony.
+    fn default() -> Self {
+        Self {
+            density: 0.5,
+            rhythm: 0.5,
+            tension: 0.5,
+            energy: 0.5,
+            warmth: 0.5,
+            space: 0.5,
+            clarity: 0.5,
+            sparkle_impulse: 0.0,
+            harmony: None,
+        }
+    }
+}
+
+impl WorldInputs {
+    /// The inputs `snapshot` gives, harmony included.
+    pub fn from_snapshot(snapshot: &WorldSnapshot) -> Self {
+        Self {
+            density: snapshot.density() as f32,
+            rhythm: snapshot.rhythm() as f32,
+            tension: snapshot.tension() as f32,
+            energy: snapshot.energy() as f32,
+            warmth: snapshot.warmth() as f32,
+            space: snapshot.space() as f32,
+            clarity: snapshot.clarity() as f32,
+            sparkle_impulse: snapshot.sparkle_impulse() as f32,
+            harmony: Some(snapshot.harmony()),
+        }
+    }
+
+    pub fn get(&self, input: WorldInput) -> f32 {
+        match input {
+            WorldInput::Density => self.density,
+            WorldInput::Rhythm => self.rhythm,
+            WorldInput::Tension => self.tension,
+            WorldInput::Energy => self.energy,
+            WorldInput::Warmth => self.warmth,
+            WorldInput::Space => self.space,
+            WorldInput::Clarity => self.clarity,
+        }
+    }
+}
+
+/// A complete world → audio mapping. Sparkle impulses always pass through.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MappingProfile {
+    pub master_gain: FieldCurve,
+    pub base_freq_hz: FieldCurve,
+    pub detune_ratio: FieldCurve,
+    pub brightness: FieldCurve,
+    pub motion: FieldCurve,
+    pub texture: FieldCurve,
+    pub reverb_size: FieldCurve,
+    pub pre_delay_ms: FieldCurve,
+    pub grit: FieldCurve,
+}
+
+impl Default for MappingProfile {
+    /// The original mapping: energy → gain, warmth → pitch and (inversely)
+    /// brightness, tension → detune, rhythm → motion, density → texture,
+    /// space → reverb size and pre-delay, low clarity → grit.
+    fn default() -> Self {
+        Self {
+            master_gain: FieldCurve::new(Energy, 0.0, GAIN_SCALE, Linear),
+            base_freq_hz: FieldCurve::new(Warmth, 80.0, 240.0, Linear),
+            detune_ratio: FieldCurve::new(Tension, 1.0, 1.0 + DETUNE_SCALE, Linear),
+            brightness: FieldCurve::new(Warmth, 1.0, 0.5, Linear),
+            motion: FieldCurve::new(Rhythm, 0.0, MOTION_SCALE, Linear),
+            texture: FieldCurve::new(Density, 0.0, TEXTURE_SCALE, Linear),
+            reverb_size: FieldCurve::new(Space, 0.0, 1.0, Linear),
+            pre_delay_ms: FieldCurve::new(Space, 0.0, PRE_DELAY_SCALE_MS, Linear),
+            grit: FieldCurve::new(Clarity, 1.0, -1.0, Linear),
+        }
+    }
+}
+
+impl MappingProfile {
+    /// Low, muffled and slow to open up.
+    pub fn dark() -> Self {
+        Self {
+            master_gain: FieldCurve::new(Energy, 0.0, 0.18, EaseIn),
+            base_freq_hz: FieldCurve::new(Warmth, 55.0, 140.0, Linear),
+            detune_ratio: FieldCurve::new(Tension, 1.0, 1.006, Linear),
+            brightness: FieldCurve::new(Warmth, 0.45, 0.15, Linear),
+            motion: FieldCurve::new(Rhythm, 0.0, 0.3, Linear),
+            texture: FieldCurve::new(Density, 0.0, 0.35, SmoothStep),
+            reverb_size: FieldCurve::new(Space, 0.2, 0.9, Linear),
+            pre_delay_ms: FieldCurve::new(Space, 10.0, 90.0, Linear),
+            grit: FieldCurve::new(Clarity, 1.0, -0.5, Linear),
+        }
+    }
+
+    /// Higher and airier, with livelier motion.
+    pub fn bright() -> Self {
+        Self {
+            master_gain: FieldCurve::new(Energy, 0.02, 0.2, Linear),
+            base_freq_hz: FieldCurve::new(Warmth, 180.0, 440.0, EaseOut),
+            detune_ratio: FieldCurve::new(Tension, 1.0, 1.012, Linear),
+            brightness: FieldCurve::new(Warmth, 1.0, 0.7, Linear),
+            motion: FieldCurve::new(Rhythm, 0.0, 0.6, Linear),
+            texture: FieldCurve::new(Density, 0.0, 0.2, Linear),
+            reverb_size: FieldCurve::new(Space, 0.0, 0.8, Linear),
+            pre_delay_ms: FieldCurve::new(Space, 0.0, 60.0, EaseOut),
+            grit: FieldCurve::new(Clarity, 0.6, -1.0, Linear),
+        }
+    }
+
+    /// Narrow ranges everywhere; the world nudges rather than steers.
+    pub fn minimal() -> Self {
+        Self {
+            master_gain: FieldCurve::new(Energy, 0.04, 0.12, Linear),
+            base_freq_hz: FieldCurve::new(Warmth, 110.0, 165.0, Linear),
+            detune_ratio: FieldCurve::new(Tension, 1.002, 1.002, Linear),
+            brightness: FieldCurve::new(Warmth, 0.5, 0.5, Linear),
+            motion: FieldCurve::new(Rhythm, 0.0, 0.15, Linear),
+            texture: FieldCurve::new(Density, 0.0, 0.08, Linear),
+            reverb_size: FieldCurve::new(Space, 0.4, 0.6, Linear),
+            pre_delay_ms: FieldCurve::new(Space, 30.0, 50.0, Linear),
+            grit: FieldCurve::new(Clarity, 0.3, -0.3, Linear),
+        }
+    }
+
+    /// Wide swings that build slowly and open up at the top.
+    pub fn cinematic() -> Self {
+        Self {
+            master_gain: FieldCurve::new(Energy, 0.0, 0.25, SmoothStep),
+            base_freq_hz: FieldCurve::new(Warmth, 65.0, 220.0, EaseOut),
+            detune_ratio: FieldCurve::new(Tension, 1.0, 1.02, EaseIn),
+            brightness: FieldCurve::new(Energy, 0.3, 0.95, SmoothStep),
+            motion: FieldCurve::new(Rhythm, 0.05, 0.7, SmoothStep),
+            texture: FieldCurve::new(Density, 0.05, 0.4, EaseIn),
+            reverb_size: FieldCurve::new(Space, 0.2, 1.0, SmoothStep),
+            pre_delay_ms: FieldCurve::new(Space, 20.0, 120.0, EaseIn),
+            grit: FieldCurve::new(Clarity, 1.0, -1.0, EaseOut),
+        }
+    }
+
+    /// Built-in profiles by name.
+    pub fn builtins() -> Vec<(&'static str, MappingProfile)> {
+        vec![
+            (DEFAULT_PROFILE, MappingProfile::default()),
+            ("dark", MappingProfile::dark()),
+            ("bright", MappingProfile::bright()),
+            ("minimal", MappingProfile::minimal()),
+            ("cinematic", MappingProfile::cinematic()),
+        ]
+    }
+
+    /// Audio parameters for the given world, clamped to ranges the synth handles.
+    pub fn map(&self, world: &WorldInputs) -> AudioParams {
+        let clear = clarity_gain(world.clarity);
+        let mut base_freq_hz = self.base_freq_hz.eval(world);
+        let mut detune_ratio = 1.0 + (self.detune_ratio.eval(world) - 1.0) * clear;
+        if let Some(harmony) = world.harmony {
+            base_freq_hz = nearest_pitch(base_freq_hz, harmony.chord_root());
+            detune_ratio += (detune_ratio - 1.0) * HARMONY_DETUNE * harmony.dissonance() as f32;
+        }
+        AudioParams {
+            master_gain: self.master_gain.eval(world).clamp(0.0, 1.0),
+            base_freq_hz: base_freq_hz.clamp(20.0, 20_000.0),
+            detune_ratio: detune_ratio.clamp(0.5, 2.0),
+            brightness: self.brightness.eval(world).clamp(0.0, 1.0),
+            motion: self.motion.eval(world).clamp(0.0, 1.0),
+            texture: (self.texture.eval(world) * clear).clamp(0.0, 1.0),
+            reverb_size: self.reverb_size.eval(world).clamp(0.0, 1.0),
+            pre_delay_ms: self.pre_delay_ms.eval(world).clamp(0.0, MAX_PRE_DELAY_MS),
+            grit: self.grit.eval(world).clamp(0.0, 1.0),
+            sparkle_impulse: world.sparkle_impulse,
+            pulse_bpm: 0.0,
+            layers: LayerAmounts::default(),
+        }
+    }
+}
+
+/// The pitch of `pitch_class` (0 for C) nearest `hz`, in any octave. A
+/// frequency that is not positive has no nearest pitch and maps to 0.
+fn nearest_pitch(hz: f32, pitch_class: u8) -> f32 {
+    if hz.is_nan() || hz <= 0.0 {
+        return 0.0;
+    }
+    let note = 69.0 + 12.0 * (hz / 440.0).log2();
+    let class = pitch_class as f32;
+    let snapped = class + 12.0 * ((note - class) / 12.0).round();
+    440.0 * ((snapped - 69.0) / 12.0).exp2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_profile_matches_from_world_state() {
+        for (i, t) in [0.0, 0.25, 0.5, 0.8, 1.0].into_iter().enumerate() {
+            let world = WorldInputs {
+                density: t,
+                rhythm: 1.0 - t,
+                tension: t,
+                energy: t * 0.5,
+                warmth: 1.0 - t * 0.5,
+                space: t,
+                clarity: 1.0 - t,
+                sparkle_impulse: i as f32 * 0.1,
+                harmony: None,
+            };
+            let expected = AudioParams::from_world_state(&world);
+            let mapped = MappingProfile::default().map(&world);
+            let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+            assert!(close(mapped.master_gain, expected.master_gain));
+            assert!(close(mapped.base_freq_hz, expected.base_freq_hz));
+            assert!(close(mapped.detune_ratio, expected.detune_ratio));
+            assert!(close(mapped.brightness, expected.brightness));
+            assert!(close(mapped.motion, expected.motion));
+            assert!(close(mapped.texture, expected.texture));
+            assert!(close(mapped.reverb_size, expected.reverb_size));
+            assert!(close(mapped.pre_delay_ms, expected.pre_delay_ms));
+            assert!(close(mapped.grit, expected.grit));
+            assert_eq!(mapped.sparkle_impulse, expected.sparkle_impulse);
+        }
+    }
+
+    #[test]
+    fn test_field_curve_spans_range() {
+        let world = |warmth: f32| WorldInputs {
+            density: 0.0,
+            rhythm: 0.0,
+            tension: 0.0,
+            energy: 0.0,
+            warmth,
+            space: 0.0,
+            clarity: 0.0,
+            sparkle_impulse: 0.0,
+            harmony: None,
+        };
+        let curve = FieldCurve::new(Warmth, 100.0, 300.0, EaseIn);
+        assert_eq!(curve.eval(&world(0.0)), 100.0);
+        assert_eq!(curve.eval(&world(0.5)), 150.0);
+        assert_eq!(curve.eval(&world(1.0)), 300.0);
+        let knee = FieldCurve::new(Warmth, 0.0, 1.0, Curve::Exponential(4.0));
+        assert!(knee.eval(&world(0.5)) < 0.5);
+        assert_eq!("warmth".parse(), Ok(Warmth));
+        assert_eq!("space".parse(), Ok(Space));
+        assert_eq!("clarity".parse(), Ok(Clarity));
+        assert!("humidity".parse::<WorldInput>().is_err());
+    }
+
+    #[test]
+    fn test_harmony_sets_pitch_and_detune() {
+        let world = WorldInputs {
+            density: 0.5,
+            rhythm: 0.5,
+            tension: 0.5,
+            energy: 0.5,
+            warmth: 0.5,
+            space: 0.5,
+            clarity: 0.5,
+            sparkle_impulse: 0.0,
+            harmony: Some(Harmony {
+                root: 9,
+                mode: ambient_core::harmony::Mode::Aeolian,
+                degree: 1,
+            }),
+        };
+        let profile = MappingProfile::default();
+        let free = profile.map(&WorldInputs {
+            harmony: None,
+            ..world
+        });
+        // 160 Hz lies nearest A3 of A minor's tonic
+        let tonic = profile.map(&world);
+        assert!((tonic.base_freq_hz - 220.0).abs() < 0.01);
+        assert_eq!(tonic.detune_ratio, free.detune_ratio);
+
+        // The dominant, E, sits a fifth above: E3 at 164.8 Hz, detuned wider
+        let dominant = profile.map(&WorldInputs {
+            harmony: world.harmony.map(|h| Harmony { degree: 5, ..h }),
+            ..world
+        });
+        assert!((dominant.base_freq_hz - 164.81).abs() < 0.01);
+        assert!(dominant.detune_ratio > free.detune_ratio);
+
+        assert_eq!(nearest_pitch(0.0, 9), 0.0);
+        assert_eq!(nearest_pitch(-50.0, 9), 0.0);
+    }
+
+    #[test]
+    fn test_clarity_trades_texture_and_detune_for_grit() {
+        let world = |clarity: f32| WorldInputs {
+            density: 0.8,
+            rhythm: 0.5,
+            tension: 0.8,
+            energy: 0.5,
+            warmth: 0.5,
+            space: 0.5,
+            clarity,
+            sparkle_impulse: 0.0,
+            harmony: None,
+        };
+        for (_, profile) in MappingProfile::builtins() {
+            let (gritty, neutral, pure) = (
+                profile.map(&world(0.0)),
+                profile.map(&world(0.5)),
+                profile.map(&world(1.0)),
+            );
+            assert_eq!(neutral.texture, gritty.texture);
+            assert!(pure.texture < neutral.texture * 0.5 || neutral.texture == 0.0);
+            assert!(pure.detune_ratio <= neutral.detune_ratio);
+            assert_eq!(pure.grit, 0.0);
+            assert!(gritty.grit > neutral.grit);
+        }
+    }
+}
@@ -0,0 +1,438 @@
+//! Master bus: master gain and its anti-click gate, loudness trim, lookahead
+//! peak limiter and output metering.
+//!
+//! The limiter delays the signal by a few milliseconds so gain reduction is
+//! already in place when a peak arrives, which keeps stacked layers under the
+//! ceiling without the audible distortion of clipping them.
+
+use crate::gate::{FadeGate, GateSettings};
+use crate::loudness::{LOUDNESS_FLOOR_LUFS, LoudnessMeter, LoudnessSettings};
+use crate::mixer::LayerSlot;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Output ceiling (about -1 dBFS).
+pub const LIMITER_CEILING: f32 = 0.89;
+
+/// Lookahead, and therefore added latency.
+const LOOKAHEAD_SECS: f32 = 0.005;
+
+/// Time for gain reduction to recover after a peak.
+const RELEASE_SECS: f32 = 0.15;
+
+/// Time for meter readings to fall back after a peak.
+const METER_FALL_SECS: f32 = 0.3;
+
+/// Lowest level reported by the meter, standing in for silence.
+pub const METER_FLOOR_DB: f32 = -120.0;
+
+fn to_db(linear: f32) -> f32 {
+    if linear > 0.0 {
+        (20.0 * linear.log10()).max(METER_FLOOR_DB)
+    } else {
+        METER_FLOOR_DB
+    }
+}
+
+/// Output level and limiter activity, in decibels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeterReading {
+    /// Peak output level (dBFS), with a short fall time.
+    pub peak_db: f32,
+    /// Gain reduction applied by the limiter (dB, 0 when idle), with a short fall time.
+    pub gain_reduction_db: f32,
+    /// K-weighted loudness of the last 400 ms.
+    pub momentary_lufs: f32,
+    /// Gated loudness over the rolling loudness window.
+    pub integrated_lufs: f32,
+    /// Trim applied by the loudness auto-gain (dB, 0 when off).
+    pub auto_gain_db: f32,
+}
+
+impl Default for MeterReading {
+    fn default() -> Self {
+        Self {
+            peak_db: METER_FLOOR_DB,
+            gain_reduction_db: 0.0,
+            momentary_lufs: LOUDNESS_FLOOR_LUFS,
+            integrated_lufs: LOUDNESS_FLOOR_LUFS,
+            auto_gain_db: 0.0,
+        }
+    }
+}
+
+/// Level of one layer, in dBFS.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LevelReading {
+    /// Peak level, with the output meter's fall time.
+    pub peak_db: f32,
+    /// RMS level, averaged over the same time.
+    pub rms_db: f32,
+}
+
+impl Default for LevelReading {
+    fn default() -> Self {
+        Self {
+            peak_db: METER_FLOOR_DB,
+            rms_db: METER_FLOOR_DB,
+        }
+    }
+}
+
+/// Peak and RMS of one layer's stem, measured before it is placed and mixed.
+#[derive(Clone, Debug)]
+pub struct LevelMeter {
+    fall: f32,
+    peak: f32,
+    mean_square: f32,
+}
+
+impl LevelMeter {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            fall: (-1.0 / (METER_FALL_SECS * sample_rate).max(1.0)).exp(),
+            peak: 0.0,
+            mean_square: 0.0,
+        }
+    }
+
+    /// Measures a block of the stem. Non-finite samples are left out.
+    pub fn process(&mut self, block: &[f32]) {
+        for &sample in block.iter().filter(|s| s.is_finite()) {
+            let power = sample * sample;
+            self.peak = sample.abs().max(self.peak * self.fall);
+            self.mean_square = power + self.fall * (self.mean_square - power);
+        }
+    }
+
+    pub fn reading(&self) -> LevelReading {
+        LevelReading {
+            peak_db: to_db(self.peak),
+            rms_db: to_db(self.mean_square.sqrt()),
+        }
+    }
+}
+
+/// Final stage of the mix, run once per frame.
+pub struct MasterBus {
+    /// `lookahead` frames of `channels` samples.
+    delay: Vec<f32>,
+    delay_pos: usize,
+    lookahead: usize,
+    channels: usize,
+    /// Gain the limiter is heading for, held for the lookahead after a peak.
+    hold_gain: f32,
+    hold_remaining: usize,
+    gain: f32,
+    /// Per-sample decrease that lands on `hold_gain` as the peak leaves the delay.
+    attack_step: f32,
+    release_coeff: f32,
+    meter_fall: f32,
+    meter_peak: f32,
+    meter_reduction: f32,
+    sample_rate: f32,
+    loudness: LoudnessMeter,
+    gate: FadeGate,
+    /// Master gain the current block ramps toward; the gate closes while it
+    /// is below the threshold.
+    gain_target: f32,
+}
+
+impl MasterBus {
+    pub fn new(sample_rate: f32) -> Self {
+        let lookahead = ((LOOKAHEAD_SECS * sample_rate) as usize).max(1);
+        let coeff = |secs: f32| (-1.0 / (secs * sample_rate).max(1.0)).exp();
+        Self {
+            delay: vec![0.0; lookahead],
+            delay_pos: 0,
+            lookahead,
+            channels: 1,
+            hold_gain: 1.0,
+            hold_remaining: 0,
+            gain: 1.0,
+            attack_step: 0.0,
+            release_coeff: coeff(RELEASE_SECS),
+            meter_fall: coeff(METER_FALL_SECS),
+            meter_peak: 0.0,
+            meter_reduction: 1.0,
+            sample_rate,
+            loudness: LoudnessMeter::new(sample_rate, LoudnessSettings::default()),
+            gate: FadeGate::new(sample_rate, GateSettings::default()),
+            gain_target: 1.0,
+        }
+    }
+
+    /// Replaces the loudness meter and auto-gain. Allocates, so call it before
+    /// handing the bus to the audio callback.
+    pub fn set_loudness(&mut self, settings: LoudnessSettings) {
+        self.loudness = LoudnessMeter::new(self.sample_rate, settings);
+    }
+
+    /// Holds the loudness trim where it is, or releases it.
+    pub fn hold_loudness_trim(&mut self, held: bool) {
+        self.loudness.hold_trim(held);
+    }
+
+    /// Replaces the gate settings, effective from the next frame.
+    pub fn set_gate(&mut self, settings: GateSettings) {
+        self.gate.set_settings(settings);
+    }
+
+    /// Sets the master gain the coming frames ramp toward. The gate fades
+    /// out as it drops below the threshold, and stays open until the first
+    /// call.
+    pub fn set_gain_target(&mut self, target: f32) {
+        self.gain_target = target;
+    }
+
+    /// Resizes the delay line for frames of `channels` samples. Allocates, so
+    /// call it before handing the bus to the audio callback.
+    pub fn set_channels(&mut self, channels: usize) {
+        self.channels = channels.max(1);
+        self.delay = vec![0.0; self.lookahead * self.channels];
+        self.delay_pos = 0;
+    }
+
+    /// Applies `master_gain` (capped at 1.0) through the gate and the loudness
+    /// trim, then limits to the ceiling.
+    pub fn process(&mut self, input: f32, master_gain: f32) -> f32 {
+        let mut frame = [input];
+        self.process_frame(&mut frame, master_gain);
+        frame[0]
+    }
+
+    /// [`MasterBus::process`] for one frame of every channel, with the limiter
+    /// linked across channels so the image does not shift under limiting.
+    /// The loudness meter hears a power-preserving downmix.
+    pub fn process_frame(&mut self, frame: &mut [f32], master_gain: f32) {
+        let gain =
+            self.gate.process(master_gain.min(1.0), self.gain_target) * self.loudness.trim_gain();
+
+        // Gain needed to bring the loudest channel under the ceiling
+        let level = frame
+            .iter()
+            .fold(0.0f32, |level, sample| level.max((sample * gain).abs()));
+        let required = if level > LIMITER_CEILING {
+            LIMITER_CEILING / level
+        } else {
+            1.0
+        };
+        if required <= self.hold_gain {
+            self.hold_gain = required;
+            self.hold_remaining = self.lookahead;
+            // Ramp down linearly so the gain is exactly there when the peak
+            // leaves the delay line; keep any steeper ramp already running
+            let step = (self.gain - required) / self.lookahead as f32;
+            self.attack_step = self.attack_step.max(step);
+        } else if self.hold_remaining > 0 {
+            self.hold_remaining -= 1;
+        } else {
+            self.hold_gain = required;
+        }
+
+        if self.gain > self.hold_gain {
+            self.gain = (self.gain - self.attack_step).max(self.hold_gain);
+        } else {
+            self.attack_step = 0.0;
+            self.gain = self.hold_gain + (self.gain - self.hold_gain) * self.release_coeff;
+        }
+
+        let start = self.delay_pos * self.channels;
+        let delayed = &mut self.delay[start..start + self.channels];
+        let mut peak = 0.0f32;
+        let mut downmix = 0.0;
+        for (sample, delayed) in frame.iter_mut().zip(delayed.iter_mut()) {
+            let input = *sample * gain;
+            // Clamp as a safety net for anything the envelope has not caught
+            let output = (*delayed * self.gain).clamp(-LIMITER_CEILING, LIMITER_CEILING);
+            *delayed = input;
+            *sample = output;
+            peak = peak.max(output.abs());
+            downmix += output;
+        }
+        self.delay_pos = (self.delay_pos + 1) % self.lookahead;
+
+        self.meter_peak = peak.max(self.meter_peak * self.meter_fall);
+        // Track the deepest reduction; fall is toward unity gain
+        self.meter_reduction = self
+            .gain
+            .min(1.0 - (1.0 - self.meter_reduction) * self.meter_fall);
+        self.loudness.process(downmix / (frame.len() as f32).sqrt());
+    }
+
+    pub fn meter(&self) -> MeterReading {
+        MeterReading {
+            peak_db: to_db(self.meter_peak),
+            gain_reduction_db: -to_db(self.meter_reduction).min(0.0),
+            momentary_lufs: self.loudness.momentary_lufs(),
+            integrated_lufs: self.loudness.integrated_lufs(),
+            auto_gain_db: self.loudness.trim_db(),
+        }
+    }
+}
+
+/// Meter readings published from the audio callback.
+#[derive(Debug)]
+pub struct SharedMeter {
+    peak_db: AtomicU32,
+    gain_reduction_db: AtomicU32,
+    momentary_lufs: AtomicU32,
+    integrated_lufs: AtomicU32,
+    auto_gain_db: AtomicU32,
+    /// Peak and RMS of each layer, indexed by [`LayerSlot::index`].
+    layer_peak_db: [AtomicU32; LayerSlot::ALL.len()],
+    layer_rms_db: [AtomicU32; LayerSlot::ALL.len()],
+    /// Set by whoever is measuring the output, so the loudness auto-gain
+    /// does not move under the measurement.
+    trim_held: AtomicBool,
+}
+
+impl Default for SharedMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SharedMeter {
+    pub fn new() -> Self {
+        let reading = MeterReading::default();
+        Self {
+            peak_db: AtomicU32::new(reading.peak_db.to_bits()),
+            gain_reduction_db: AtomicU32::new(reading.gain_reduction_db.to_bits()),
+            momentary_lufs: AtomicU32::new(reading.momentary_lufs.to_bits()),
+            integrated_lufs: AtomicU32::new(reading.integrated_lufs.to_bits()),
+            auto_gain_db: AtomicU32::new(reading.auto_gain_db.to_bits()),
+            layer_peak_db: LayerSlot::ALL.map(|_| AtomicU32::new(METER_FLOOR_DB.to_bits())),
+            layer_rms_db: LayerSlot::ALL.map(|_| AtomicU32::new(METER_FLOOR_DB.to_bits())),
+            trim_held: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set(&self, reading: MeterReading) {
+        self.peak_db
+            .store(reading.peak_db.to_bits(), Ordering::Relaxed);
+        self.gain_reduction_db
+            .store(reading.gain_reduction_db.to_bits(), Ordering::Relaxed);
+        self.momentary_lufs
+            .store(reading.momentary_lufs.to_bits(), Ordering::Relaxed);
+        self.integrated_lufs
+            .store(reading.integrated_lufs.to_bits(), Ordering::Relaxed);
+        self.auto_gain_db
+            .store(reading.auto_gain_db.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> MeterReading {
+        MeterReading {
+            peak_db: f32::from_bits(self.peak_db.load(Ordering::Relaxed)),
+            gain_reduction_db: f32::from_bits(self.gain_reduction_db.load(Ordering::Relaxed)),
+            momentary_lufs: f32::from_bits(self.momentary_lufs.load(Ordering::Relaxed)),
+            integrated_lufs: f32::from_bits(self.integrated_lufs.load(Ordering::Relaxed)),
+            auto_gain_db: f32::from_bits(self.auto_gain_db.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Holds the loudness auto-gain's trim from the next block, or releases it.
+    pub fn hold_trim(&self, held: bool) {
+        self.trim_held.store(held, Ordering::Relaxed);
+    }
+
+    pub fn trim_held(&self) -> bool {
+        self.trim_held.load(Ordering::Relaxed)
+    }
+
+    /// Publishes each layer's level, indexed by [`LayerSlot::index`].
+    pub fn set_layers(&self, levels: &[LevelReading; LayerSlot::ALL.len()]) {
+        for (i, level) in levels.iter().enumerate() {
+            self.layer_peak_db[i].store(level.peak_db.to_bits(), Ordering::Relaxed);
+            self.layer_rms_db[i].store(level.rms_db.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// Each layer's level, indexed by [`LayerSlot::index`].
+    pub fn layers(&self) -> [LevelReading; LayerSlot::ALL.len()] {
+        LayerSlot::ALL.map(|slot| LevelReading {
+            peak_db: f32::from_bits(self.layer_peak_db[slot.index()].load(Ordering::Relaxed)),
+            rms_db: f32::from_bits(self.layer_rms_db[slot.index()].load(Ordering::Relaxed)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quiet_signal_passes_after_lookahead() {
+        let mut bus = MasterBus::new(1_000.0);
+        let lookahead = bus.lookahead;
+        let output: Vec<f32> = (0..20).map(|_| bus.process(0.5, 1.0)).collect();
+        assert!(output[..lookahead].iter().all(|s| *s == 0.0));
+        assert!(output[lookahead..].iter().all(|s| (*s - 0.5).abs() < 1e-6));
+        assert_eq!(bus.meter().gain_reduction_db, 0.0);
+    }
+
+    #[test]
+    fn test_loud_stack_stays_under_ceiling() {
+        let mut bus = MasterBus::new(48_000.0);
+        let lookahead = bus.lookahead;
+        // A 4x overload sine with a sudden onset
+        let input: Vec<f32> = (0..48_000).map(|i| 4.0 * (i as f32 * 0.05).sin()).collect();
+        let output: Vec<f32> = input.iter().map(|x| bus.process(*x, 1.0)).collect();
+        assert!(output.iter().all(|s| s.abs() <= LIMITER_CEILING));
+
+        // Once settled the waveform is scaled, not clipped: every loud sample
+        // sees the same gain
+        for (i, out) in output.iter().enumerate().skip(24_000) {
+            let delayed = input[i - lookahead];
+            if delayed.abs() > 1.0 {
+                assert!((out / delayed - LIMITER_CEILING / 4.0).abs() < 1e-3);
+            }
+        }
+        let meter = bus.meter();
+        assert!(meter.gain_reduction_db > 10.0);
+        assert!(meter.peak_db <= 0.0 && meter.peak_db > -3.0);
+    }
+
+    #[test]
+    fn test_limiter_is_linked_across_channels() {
+        let mut bus = MasterBus::new(48_000.0);
+        bus.set_channels(2);
+        let mut frame = [0.0; 2];
+        for i in 0..48_000 {
+            let x = (i as f32 * 0.05).sin();
+            frame = [4.0 * x, 0.5 * x];
+            bus.process_frame(&mut frame, 1.0);
+            assert!(frame.iter().all(|s| s.abs() <= LIMITER_CEILING));
+        }
+        // The quiet channel is turned down with the loud one, keeping their ratio
+        if frame[1].abs() > 1e-3 {
+            assert!((frame[0] / frame[1] - 8.0).abs() < 1e-2);
+        }
+        assert!(bus.meter().gain_reduction_db > 10.0);
+    }
+
+    #[test]
+    fn test_level_meter_reads_peak_and_rms() {
+        let mut meter = LevelMeter::new(48_000.0);
+        assert_eq!(meter.reading(), LevelReading::default());
+
+        // A second of a half-scale sine: peak -6 dB, RMS 3 dB below it
+        let sine: Vec<f32> = (0..48_000).map(|i| 0.5 * (i as f32 * 0.05).sin()).collect();
+        meter.process(&sine);
+        let reading = meter.reading();
+        assert!((reading.peak_db + 6.02).abs() < 0.1);
+        assert!((reading.rms_db + 9.03).abs() < 0.5);
+
+        // Falls back over silence, and ignores non-finite samples
+        meter.process(&[0.0; 48_000]);
+        meter.process(&[f32::NAN]);
+        let reading = meter.reading();
+        assert!(reading.peak_db < -30.0 && reading.rms_db < -20.0);
+        assert!(reading.rms_db.is_finite());
+
+        let shared = SharedMeter::new();
+        let mut levels = [LevelReading::default(); LayerSlot::ALL.len()];
+        levels[LayerSlot::Wind.index()] = reading;
+        shared.set_layers(&levels);
+        assert_eq!(shared.layers(), levels);
+    }
+}
@@ -0,0 +1,644 @@
+use crate::ducking::{Ducker, DuckingSettings};
+use crate::effects::{EffectBus, EffectChain, EffectChains, SharedEffects};
+use crate::gate::GateSettings;
+use crate::layers::{DroneLayer, Layer, SparkleLayer, TextureLayer};
+use crate::loudness::LoudnessSettings;
+use crate::master::{LevelMeter, LevelReading, MasterBus, MeterReading};
+use crate::params::AudioParams;
+use crate::spatial::{SpatialSettings, Spatializer};
+use crate::transition::{CurveTable, TransitionEngine};
+use crate::wind::WindLayer;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Conservative per-layer gains to prevent clipping
+// These are tuned so that max combined output is around 0.8 before master gain
+const DRONE_LAYER_GAIN: f32 = 0.3; // Drone is loud, keep it moderate
+const TEXTURE_LAYER_GAIN: f32 = 0.4; // Texture needs to be audible but not overpowering
+const SPARKLE_LAYER_GAIN: f32 = 0.6; // Sparkles: balanced gain for audibility without crackling
+const SAMPLE_LAYER_GAIN: f32 = 0.5; // Field recordings: already normalized across voices
+const GRAIN_LAYER_GAIN: f32 = 0.4; // Granular cloud: sits behind the field recordings
+const WIND_LAYER_GAIN: f32 = 0.35; // Wind: swells reach well above its average level
+
+/// Frames mixed per pass; layers and effect chains run once per block.
+const BLOCK_FRAMES: usize = 256;
+
+/// Most layers the mixer holds at once, counting those fading out.
+pub const MAX_LAYERS: usize = 16;
+
+/// Fade in or out of a layer added, replaced or removed while playing.
+pub const LAYER_FADE_SECS: f32 = 0.05;
+
+/// Identifies a layer in the mix so it picks up the right gain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayerSlot {
+    Drone,
+    Texture,
+    Sparkle,
+    Samples,
+    Grains,
+    Wind,
+}
+
+impl LayerSlot {
+    pub const ALL: [LayerSlot; 6] = [
+        LayerSlot::Drone,
+        LayerSlot::Texture,
+        LayerSlot::Sparkle,
+        LayerSlot::Samples,
+        LayerSlot::Grains,
+        LayerSlot::Wind,
+    ];
+
+    /// Position in [`LayerSlot::ALL`].
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LayerSlot::Drone => "drone",
+            LayerSlot::Texture => "texture",
+            LayerSlot::Sparkle => "sparkle",
+            LayerSlot::Samples => "samples",
+            LayerSlot::Grains => "grains",
+            LayerSlot::Wind => "wind",
+        }
+    }
+}
+
+impl FromStr for LayerSlot {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LayerSlot::ALL
+            .into_iter()
+            .find(|slot| slot.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown layer '{}' (expected drone, texture, sparkle, samples, grains or wind)",
+                    s
+                )
+            })
+    }
+}
+
+/// Built layers, each with the slot it plays in.
+pub type LayerStack = Vec<(LayerSlot, Box<dyn Layer>)>;
+
+/// A change to the mix while it plays, applied at the start of the next block.
+pub enum MixCommand {
+    /// Fades the layer in alongside any already in its slot.
+    Add(LayerSlot, Box<dyn Layer>),
+    /// Fades out whatever is in the slot while fading the layer in.
+    Replace(LayerSlot, Box<dyn Layer>),
+    /// Fades out whatever is in the slot.
+    Remove(LayerSlot),
+    /// Fades every bus to dry, swaps in these chains and fades them in.
+    Effects(Box<BusChains>),
+}
+
+/// Something the mixer has let go of, to be dropped off the audio thread.
+pub enum Retired {
+    Layer(Box<dyn Layer>),
+    Effects(Box<BusChains>),
+}
+
+/// A layer in the mix and how far it is faded in.
+struct MixerLayer {
+    slot: LayerSlot,
+    layer: Box<dyn Layer>,
+    /// Fade gain at the end of the last block.
+    fade: f32,
+    /// Fading out, to be handed back once silent.
+    leaving: bool,
+}
+
+impl MixerLayer {
+    fn playing(slot: LayerSlot, layer: Box<dyn Layer>) -> Self {
+        Self {
+            slot,
+            layer,
+            fade: 1.0,
+            leaving: false,
+        }
+    }
+}
+
+/// Per-layer gains applied before master gain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayerGains {
+    pub drone: f32,
+    pub texture: f32,
+    pub sparkle: f32,
+    pub samples: f32,
+    pub grains: f32,
+    pub wind: f32,
+}
+
+impl LayerGains {
+    pub fn get(&self, slot: LayerSlot) -> f32 {
+        match slot {
+            LayerSlot::Drone => self.drone,
+            LayerSlot::Texture => self.texture,
+            LayerSlot::Sparkle => self.sparkle,
+            LayerSlot::Samples => self.samples,
+            LayerSlot::Grains => self.grains,
+            LayerSlot::Wind => self.wind,
+        }
+    }
+}
+
+impl Default for LayerGains {
+    fn default() -> Self {
+        Self {
+            drone: DRONE_LAYER_GAIN,
+            texture: TEXTURE_LAYER_GAIN,
+            sparkle: SPARKLE_LAYER_GAIN,
+            samples: SAMPLE_LAYER_GAIN,
+            grains: GRAIN_LAYER_GAIN,
+            wind: WIND_LAYER_GAIN,
+        }
+    }
+}
+
+/// Thread-safe layer gains so they can be retuned while the stream runs.
+#[derive(Debug)]
+pub struct SharedLayerGains {
+    drone: AtomicU32,
+    texture: AtomicU32,
+    sparkle: AtomicU32,
+    samples: AtomicU32,
+    grains: AtomicU32,
+    wind: AtomicU32,
+}
+
+impl SharedLayerGains {
+    pub fn new(initial: LayerGains) -> Self {
+        Self {
+            drone: AtomicU32::new(initial.drone.to_bits()),
+            texture: AtomicU32::new(initial.texture.to_bits()),
+            sparkle: AtomicU32::new(initial.sparkle.to_bits()),
+            samples: AtomicU32::new(initial.samples.to_bits()),
+            grains: AtomicU32::new(initial.grains.to_bits()),
+            wind: AtomicU32::new(initial.wind.to_bits()),
+        }
+    }
+
+    pub fn set(&self, gains: LayerGains) {
+        self.drone.store(gains.drone.to_bits(), Ordering::Relaxed);
+        self.texture
+            .store(gains.texture.to_bits(), Ordering::Relaxed);
+        self.sparkle
+            .store(gains.sparkle.to_bits(), Ordering::Relaxed);
+        self.samples
+            .store(gains.samples.to_bits(), Ordering::Relaxed);
+        self.grains.store(gains.grains.to_bits(), Ordering::Relaxed);
+        self.wind.store(gains.wind.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> LayerGains {
+        LayerGains {
+            drone: f32::from_bits(self.drone.load(Ordering::Relaxed)),
+            texture: f32::from_bits(self.texture.load(Ordering::Relaxed)),
+            sparkle: f32::from_bits(self.sparkle.load(Ordering::Relaxed)),
+            samples: f32::from_bits(self.samples.load(Ordering::Relaxed)),
+            grains: f32::from_bits(self.grains.load(Ordering::Relaxed)),
+            wind: f32::from_bits(self.wind.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// One block of each layer and of each output channel.
+struct BusBuffers {
+    /// The layer being rendered, before its gain.
+    layer: Vec<f32>,
+    /// Each layer after its gain, bus effects and ducking, indexed by
+    /// [`LayerSlot::index`].
+    stems: Vec<Vec<f32>>,
+    /// Dry sparkles, keying the ducker.
+    key: Vec<f32>,
+    /// The placed stems, one block per layout channel.
+    channels: Vec<Vec<f32>>,
+    /// One frame of every layout channel, through the master bus.
+    frame: Vec<f32>,
+}
+
+impl BusBuffers {
+    fn new(channels: usize) -> Self {
+        let block = || vec![0.0; BLOCK_FRAMES];
+        Self {
+            layer: block(),
+            stems: LayerSlot::ALL.iter().map(|_| block()).collect(),
+            key: block(),
+            channels: (0..channels).map(|_| block()).collect(),
+            frame: vec![0.0; channels],
+        }
+    }
+}
+
+fn chain(sample_rate: f32, chains: &EffectChains, bus: EffectBus) -> EffectChain {
+    EffectChain::new(sample_rate, chains.get(bus), BLOCK_FRAMES)
+}
+
+/// The effect chain on every bus: the drone, the sparkles, and the master
+/// chain once per layout channel.
+pub struct BusChains {
+    drone: EffectChain,
+    sparkle: EffectChain,
+    master: Vec<EffectChain>,
+}
+
+impl BusChains {
+    /// Builds `effects` for a layout of `channels`. Allocates, so build them
+    /// off the audio thread.
+    pub fn new(sample_rate: f32, effects: &EffectChains, channels: usize) -> Self {
+        Self {
+            drone: chain(sample_rate, effects, EffectBus::Drone),
+            sparkle: chain(sample_rate, effects, EffectBus::Sparkle),
+            master: (0..channels.max(1))
+                .map(|_| chain(sample_rate, effects, EffectBus::Master))
+                .collect(),
+        }
+    }
+
+    /// Starts every member fully off, so the chains fade in from dry.
+    pub fn start_dry(&mut self) {
+        self.all_mut().for_each(EffectChain::start_dry);
+    }
+
+    fn all_mut(&mut self) -> impl Iterator<Item = &mut EffectChain> {
+        [&mut self.drone, &mut self.sparkle]
+            .into_iter()
+            .chain(&mut self.master)
+    }
+
+    fn is_dry(&self) -> bool {
+        self.drone.is_dry() && self.sparkle.is_dry() && self.master.iter().all(EffectChain::is_dry)
+    }
+}
+
+/// Mixes the layer stack into interleaved output buffers.
+///
+/// The mixer has no dependency on an audio backend, so it drives both the
+/// real-time CPAL callback and offline rendering.
+///
+/// Each layer renders to its own stem; the drone and sparkle stems run their
+/// bus effects, the bed stems are ducked under the sparkles, and the
+/// [`Spatializer`] places every stem on the speaker layout. The master chain
+/// then runs on each layout channel and the master bus limits them together.
+pub struct Mixer {
+    /// At most [`MAX_LAYERS`], allocated up front.
+    layers: Vec<MixerLayer>,
+    /// Fade change per frame for layers coming and going.
+    fade_step: f32,
+    gains: LayerGains,
+    /// Gains at the end of the last block, where gain ramps start.
+    block_gains: LayerGains,
+    transitions: TransitionEngine,
+    /// Crossfade duration and curve to apply to the next parameter set.
+    pending_crossfade: Option<(f32, CurveTable)>,
+    /// Configured chains, kept to rebuild the master chains for a new layout.
+    effects: EffectChains,
+    chains: BusChains,
+    /// Chains to swap in once `chains` has faded to dry.
+    incoming_chains: Option<Box<BusChains>>,
+    /// Chains swapped out, until their owner takes them.
+    retired_chains: Option<Box<BusChains>>,
+    spatializer: Spatializer,
+    buses: BusBuffers,
+    /// Parameters at the end of the last block, where gain ramps start.
+    block_params: Option<AudioParams>,
+    /// Ducks the drone and texture under the sparkle layer.
+    ducker: Ducker,
+    /// Each stem's level before placement, indexed by [`LayerSlot::index`].
+    layer_meters: [LevelMeter; LayerSlot::ALL.len()],
+    master: MasterBus,
+}
+
+impl Mixer {
+    /// Creates a mixer with the default layer stack (drone, texture, wind, sparkle).
+    pub fn new(sample_rate: f32) -> Self {
+        Self::with_gains(sample_rate, LayerGains::default())
+    }
+
+    /// Creates a mixer with the default layer stack and custom layer gains.
+    pub fn with_gains(sample_rate: f32, gains: LayerGains) -> Self {
+        let drone_layer = Box::new(DroneLayer::new(sample_rate)) as Box<dyn Layer>;
+        let sparkle_layer = Box::new(SparkleLayer::new(sample_rate)) as Box<dyn Layer>;
+        let texture_layer = Box::new(TextureLayer::new(sample_rate)) as Box<dyn Layer>;
+        let wind_layer = Box::new(WindLayer::new(sample_rate)) as Box<dyn Layer>;
+        let effects = EffectChains::default();
+        let mut layers = Vec::with_capacity(MAX_LAYERS);
+        layers.extend([
+            MixerLayer::playing(LayerSlot::Drone, drone_layer),
+            MixerLayer::playing(LayerSlot::Texture, texture_layer),
+            MixerLayer::playing(LayerSlot::Wind, wind_layer),
+            MixerLayer::playing(LayerSlot::Sparkle, sparkle_layer),
+        ]);
+        Self {
+            layers,
+            fade_step: 1.0 / (LAYER_FADE_SECS * sample_rate).max(1.0),
+            gains,
+            block_gains: gains,
+            transitions: TransitionEngine::new(sample_rate),
+            pending_crossfade: None,
+            chains: BusChains::new(sample_rate, &effects, 1),
+            incoming_chains: None,
+            retired_chains: None,
+            effects,
+            spatializer: Spatializer::new(sample_rate, &SpatialSettings::default(), BLOCK_FRAMES),
+            buses: BusBuffers::new(1),
+            block_params: None,
+            ducker: Ducker::new(sample_rate, DuckingSettings::default()),
+            layer_meters: LayerSlot::ALL.map(|_| LevelMeter::new(sample_rate)),
+            master: MasterBus::new(sample_rate),
+        }
+    }
+
+    /// Replaces the layer stack outright, keeping the first [`MAX_LAYERS`].
+    /// Allocates, so call it before the mixer moves into the audio callback.
+    pub fn set_layers(&mut self, layers: LayerStack) {
+        self.layers = Vec::with_capacity(MAX_LAYERS);
+        self.layers.extend(
+            layers
+                .into_iter()
+                .take(MAX_LAYERS)
+                .map(|(slot, layer)| MixerLayer::playing(slot, layer)),
+        );
+    }
+
+    /// Applies a change to the layer stack while playing, fading over
+    /// [`LAYER_FADE_SECS`]. A layer that does not fit alongside the
+    /// [`MAX_LAYERS`] already held is handed back unplayed.
+    ///
+    /// New effect chains wait for the current ones to fade to dry; chains
+    /// still waiting when newer ones arrive are handed back.
+    pub fn apply(&mut self, command: MixCommand) -> Result<(), Retired> {
+        let (slot, incoming) = match command {
+            MixCommand::Add(slot, layer) => (slot, Some(layer)),
+            MixCommand::Replace(slot, layer) => {
+                self.fade_out(slot);
+                (slot, Some(layer))
+            }
+            MixCommand::Remove(slot) => {
+                self.fade_out(slot);
+                (slot, None)
+            }
+            MixCommand::Effects(chains) => {
+                self.chains.all_mut().for_each(|chain| chain.set_enabled(0));
+                return match self.incoming_chains.replace(chains) {
+                    Some(stale) => Err(Retired::Effects(stale)),
+                    None => Ok(()),
+                };
+            }
+        };
+        let Some(layer) = incoming else {
+            return Ok(());
+        };
+        if self.layers.len() == MAX_LAYERS {
+            return Err(Retired::Layer(layer));
+        }
+        self.layers.push(MixerLayer {
+            slot,
+            layer,
+            fade: 0.0,
+            leaving: false,
+        });
+        Ok(())
+    }
+
+    /// Takes out the layers still playing, leaving the mixer silent. Those
+    /// fading out are dropped. Allocates, so call it once the mixer is out
+    /// of the audio callback.
+    pub fn take_layers(&mut self) -> LayerStack {
+        self.layers
+            .drain(..)
+            .filter(|entry| !entry.leaving)
+            .map(|entry| (entry.slot, entry.layer))
+            .collect()
+    }
+
+    /// Layers held, counting those still fading out.
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    fn fade_out(&mut self, slot: LayerSlot) {
+        for entry in self.layers.iter_mut().filter(|entry| entry.slot == slot) {
+            entry.leaving = true;
+        }
+    }
+
+    /// Takes out a layer that has finished fading out, or chains swapped
+    /// out, so their owner can drop them off the audio thread.
+    pub fn take_retired(&mut self) -> Option<Retired> {
+        if let Some(chains) = self.retired_chains.take() {
+            return Some(Retired::Effects(chains));
+        }
+        let index = self
+            .layers
+            .iter()
+            .position(|entry| entry.leaving && entry.fade == 0.0)?;
+        Some(Retired::Layer(self.layers.remove(index).layer))
+    }
+
+    /// Swaps in waiting chains once the current ones are dry.
+    fn swap_chains(&mut self) {
+        if self.retired_chains.is_some() || !self.chains.is_dry() {
+            return;
+        }
+        if let Some(mut chains) = self.incoming_chains.take() {
+            std::mem::swap(&mut self.chains, &mut *chains);
+            self.retired_chains = Some(chains);
+        }
+    }
+
+    /// Rebuilds the effect chains. Allocates, so call it before the mixer
+    /// moves into the audio callback.
+    pub fn set_effects(&mut self, sample_rate: f32, effects: &EffectChains) {
+        self.effects = effects.clone();
+        self.chains = BusChains::new(sample_rate, effects, self.spatializer.channels());
+    }
+
+    /// Switches chain members on and off to match `effects`, or all of them
+    /// off while new chains wait to be swapped in.
+    pub fn set_effects_enabled(&mut self, effects: &SharedEffects) {
+        let mask = |bus| match self.incoming_chains {
+            Some(_) => 0,
+            None => effects.mask(bus),
+        };
+        let (drone, sparkle, master) = (
+            mask(EffectBus::Drone),
+            mask(EffectBus::Sparkle),
+            mask(EffectBus::Master),
+        );
+        self.chains.drone.set_enabled(drone);
+        self.chains.sparkle.set_enabled(sparkle);
+        for chain in &mut self.chains.master {
+            chain.set_enabled(master);
+        }
+    }
+
+    /// Places the layers on `settings.layout`, one master chain per channel.
+    /// Allocates, so call it before the mixer moves into the audio callback.
+    pub fn set_spatial(&mut self, sample_rate: f32, settings: &SpatialSettings) {
+        self.spatializer = Spatializer::new(sample_rate, settings, BLOCK_FRAMES);
+        let channels = self.spatializer.channels();
+        self.buses = BusBuffers::new(channels);
+        self.master.set_channels(channels);
+        let effects = self.effects.clone();
+        self.set_effects(sample_rate, &effects);
+    }
+
+    /// Replaces the per-layer gains, ramped in over the next block.
+    pub fn set_gains(&mut self, gains: LayerGains) {
+        self.gains = gains;
+    }
+
+    /// Replaces the ducking settings, effective from the next sample.
+    pub fn set_ducking(&mut self, settings: DuckingSettings) {
+        self.ducker.set_settings(settings);
+    }
+
+    /// Replaces the anti-click gate settings, effective from the next frame.
+    pub fn set_gate(&mut self, settings: GateSettings) {
+        self.master.set_gate(settings);
+    }
+
+    /// Replaces the loudness meter and auto-gain settings. Allocates, so call
+    /// it before the mixer moves into the audio callback.
+    pub fn set_loudness(&mut self, settings: LoudnessSettings) {
+        self.master.set_loudness(settings);
+    }
+
+    /// Holds the loudness auto-gain's trim where it is, or releases it.
+    pub fn hold_loudness_trim(&mut self, held: bool) {
+        self.master.hold_loudness_trim(held);
+    }
+
+    /// Output level and limiter gain reduction as of the last processed frame.
+    pub fn meter(&self) -> MeterReading {
+        self.master.meter()
+    }
+
+    /// Each layer's peak and RMS as of the last block, after its gain, bus
+    /// effects and ducking but before placement, indexed by [`LayerSlot::index`].
+    pub fn layer_levels(&self) -> [LevelReading; LayerSlot::ALL.len()] {
+        LayerSlot::ALL.map(|slot| self.layer_meters[slot.index()].reading())
+    }
+
+    /// Crossfades to the next parameter set over `secs`, shaped by `curve`,
+    /// instead of the short default glide. Used on scene changes.
+    pub fn start_transition(&mut self, secs: f32, curve: CurveTable) {
+        self.pending_crossfade = Some((secs, curve));
+    }
+
+    /// Renders interleaved samples into `output`. Layout channels go to the
+    /// output channels in order and any extra output channels are silent; a
+    /// mono layout is copied to every output channel.
+    pub fn process(&mut self, output: &mut [f32], params: &AudioParams, channels: u16) {
+        match self.pending_crossfade.take() {
+            Some((secs, curve)) => self.transitions.crossfade(*params, secs, curve),
+            None => self.transitions.set_target(*params),
+        }
+
+        self.swap_chains();
+        let channels = usize::from(channels.max(1));
+        let mono = self.spatializer.channels() == 1;
+        for chunk in output.chunks_mut(BLOCK_FRAMES * channels) {
+            let frames = chunk.len().div_ceil(channels);
+            let params = self.transitions.advance(frames as u32);
+            let from = self.block_params.replace(params).unwrap_or(params);
+
+            // Render each layer onto its stem
+            let buses = &mut self.buses;
+            for stem in &mut buses.stems {
+                stem[..frames].fill(0.0);
+            }
+            for entry in self.layers.iter_mut() {
+                let fade = entry.fade;
+                let change = self.fade_step * frames as f32;
+                entry.fade = if entry.leaving {
+                    (fade - change).max(0.0)
+                } else {
+                    (fade + change).min(1.0)
+                };
+                if fade == 0.0 && entry.fade == 0.0 {
+                    continue;
+                }
+                let block = &mut buses.layer[..frames];
+                entry.layer.process_block(block, &params);
+
+                // Ramp the gain across the block so layer fades do not step
+                let slot = entry.slot;
+                let start = self.block_gains.get(slot) * from.layers.get(slot) * fade;
+                let end = self.gains.get(slot) * params.layers.get(slot) * entry.fade;
+                let step = (end - start) / frames as f32;
+                let stem = &mut buses.stems[slot.index()];
+                for (i, (out, sample)) in stem.iter_mut().zip(block.iter()).enumerate() {
+                    // Ensure layer output is finite
+                    if sample.is_finite() {
+                        *out += sample * (start + step * (i + 1) as f32);
+                    }
+                }
+            }
+            self.block_gains = self.gains;
+            buses.key[..frames].copy_from_slice(&buses.stems[LayerSlot::Sparkle.index()][..frames]);
+
+            self.chains.drone.process(
+                &mut buses.stems[LayerSlot::Drone.index()][..frames],
+                &params,
+            );
+            self.chains.sparkle.process(
+                &mut buses.stems[LayerSlot::Sparkle.index()][..frames],
+                &params,
+            );
+            for i in 0..frames {
+                // Sparkles and their effects are not ducked, or they would duck themselves
+                let duck = self.ducker.process(buses.key[i]);
+                for slot in [LayerSlot::Drone, LayerSlot::Texture, LayerSlot::Wind] {
+                    buses.stems[slot.index()][i] *= duck;
+                }
+            }
+
+            // Meter and place the stems on the layout, and run the master chain per channel
+            for channel in &mut buses.channels {
+                channel[..frames].fill(0.0);
+            }
+            for slot in LayerSlot::ALL {
+                self.layer_meters[slot.index()].process(&buses.stems[slot.index()][..frames]);
+                self.spatializer.pan(
+                    slot,
+                    &buses.stems[slot.index()][..frames],
+                    &mut buses.channels,
+                    params.motion,
+                );
+            }
+            self.spatializer.finish(&mut buses.channels, frames);
+            for (chain, channel) in self.chains.master.iter_mut().zip(&mut buses.channels) {
+                chain.process(&mut channel[..frames], &params);
+            }
+
+            // Master gain, ramped like the layer gains and gated near zero,
+            // and lookahead limiting
+            self.master.set_gain_target(params.master_gain);
+            let gain_step = (params.master_gain - from.master_gain) / frames as f32;
+            for (i, frame) in chunk.chunks_mut(channels).enumerate() {
+                let gain = from.master_gain + gain_step * (i + 1) as f32;
+                for (sample, channel) in buses.frame.iter_mut().zip(&buses.channels) {
+                    *sample = channel[i];
+                }
+                self.master.process_frame(&mut buses.frame, gain);
+                if mono {
+                    frame.fill(buses.frame[0]);
+                } else {
+                    frame.fill(0.0);
+                    for (out, sample) in frame.iter_mut().zip(&buses.frame) {
+                        *out = *sample;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Noise sources shared by the layers.
+//!
+//! Everything draws on xorshift32, an integer generator with a period of
+//! 2^32 − 1, so nothing drifts or falls into the short cycles a float LCG
+//! does. White noise comes four lanes at a time from the SIMD kernel in
+//! [`dsp`](crate::dsp); pink and brown are shaped from it with one-pole
+//! filters. Pink (−3 dB/octave) sounds far more natural in an ambient bed
+//! than white; brown (−6 dB/octave) is a low rumble.
+
+use crate::dsp;
+use std::str::FromStr;
+
+/// Pink: Paul Kellet's three-pole approximation (±0.5 dB from 10 Hz up at
+/// 44.1-48 kHz) as (pole, input gain) pairs, plus the white feed-through.
+const PINK_POLES: [(f32, f32); 3] = [
+    (0.99765, 0.099_046),
+    (0.963, 0.296_516_4),
+    (0.57, 1.052_691_3),
+];
+const PINK_DIRECT: f32 = 0.1848;
+
+/// Brown: a leaky integrator, leaky enough that it never wanders off.
+const BROWN_POLE: f32 = 1.0 / 1.02;
+const BROWN_INPUT: f32 = 0.02 / 1.02;
+
+/// Output scaling to an RMS of about 0.25, so pink and brown peak near ±1
+/// like white noise.
+const PINK_GAIN: f32 = 0.15;
+const BROWN_GAIN: f32 = 4.4;
+
+/// Samples shaped per pass, sized to keep the scratch buffers on the stack.
+const CHUNK: usize = 64;
+
+/// Scalar xorshift32, for the random decisions a layer makes (when a grain
+/// starts, where it reads from) rather than for audio.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    /// A zero seed, which would stick at zero, is replaced.
+    pub fn new(seed: u32) -> Self {
+        Self {
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// Uniform in [0, 1), from the top 24 bits.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// Spectral slope of a noise source.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoiseColor {
+    /// Flat spectrum.
+    #[default]
+    White,
+    /// −3 dB per octave: equal energy per octave.
+    Pink,
+    /// −6 dB per octave.
+    Brown,
+}
+
+impl NoiseColor {
+    pub fn name(self) -> &'static str {
+        match self {
+            NoiseColor::White => "white",
+            NoiseColor::Pink => "pink",
+            NoiseColor::Brown => "brown",
+        }
+    }
+}
+
+impl FromStr for NoiseColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "white" => Ok(NoiseColor::White),
+            "pink" => Ok(NoiseColor::Pink),
+            "brown" => Ok(NoiseColor::Brown),
+            other => Err(format!(
+                "unknown noise color '{}', expected white, pink or brown",
+                other
+            )),
+        }
+    }
+}
+
+/// Noise color for each noise-based layer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NoiseColors {
+    pub texture: NoiseColor,
+    pub sparkle: NoiseColor,
+    pub wind: NoiseColor,
+}
+
+/// Block noise source of one color.
+#[derive(Clone, Debug)]
+pub struct Noise {
+    color: NoiseColor,
+    /// Four interleaved xorshift32 states, one per SIMD lane.
+    lanes: [u32; 4],
+    pink: [f32; 3],
+    brown: f32,
+}
+
+impl Noise {
+    pub fn new(seed: u32, color: NoiseColor) -> Self {
+        // Decorrelate the lanes; xorshift must never be seeded with zero
+        let lanes = std::array::from_fn(|lane| {
+            let mixed = (seed ^ (lane as u32).wrapping_mul(0x9E37_79B9)).wrapping_mul(0x85EB_CA6B);
+            mixed.max(1)
+        });
+        Self {
+            color,
+            lanes,
+            pink: [0.0; 3],
+            brown: 0.0,
+        }
+    }
+
+    pub fn color(&self) -> NoiseColor {
+        self.color
+    }
+
+    /// Overwrites `out` with the next `out.len()` samples.
+    pub fn fill(&mut self, out: &mut [f32]) {
+        dsp::white_noise(&mut self.lanes, out);
+        match self.color {
+            NoiseColor::White => {}
+            NoiseColor::Pink => {
+                for chunk in out.chunks_mut(CHUNK) {
+                    self.pink_chunk(chunk);
+                }
+            }
+            NoiseColor::Brown => {
+                for sample in out.iter_mut() {
+                    *sample *= BROWN_INPUT;
+                }
+                dsp::one_pole(out, &mut self.brown, BROWN_POLE);
+                for sample in out.iter_mut() {
+                    *sample *= BROWN_GAIN;
+                }
+            }
+        }
+    }
+
+    /// Replaces a chunk of white noise with pink: the sum of three one-poles
+    /// fed from it, plus some of the white itself.
+    fn pink_chunk(&mut self, chunk: &mut [f32]) {
+        let mut sum = [0.0; CHUNK];
+        let mut pole = [0.0; CHUNK];
+        let (sum, pole) = (&mut sum[..chunk.len()], &mut pole[..chunk.len()]);
+        for (&(coeff, gain), state) in PINK_POLES.iter().zip(&mut self.pink) {
+            for (p, white) in pole.iter_mut().zip(chunk.iter()) {
+                *p = white * gain;
+            }
+            dsp::one_pole(pole, state, coeff);
+            for (s, p) in sum.iter_mut().zip(pole.iter()) {
+                *s += p;
+            }
+        }
+        for (sample, s) in chunk.iter_mut().zip(sum.iter()) {
+            *sample = (s + *sample * PINK_DIRECT) * PINK_GAIN;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RMS level, and the energy of sample-to-sample differences relative to
+    /// it (a rough measure of high-frequency content).
+    fn measure(color: NoiseColor) -> (f32, f32) {
+        let mut noise = Noise::new(42, color);
+        let mut block = [0.0; 256];
+        let (mut energy, mut diff_energy, mut count) = (0.0, 0.0, 0.0);
+        let mut prev = 0.0;
+        for _ in 0..400 {
+            noise.fill(&mut block);
+            for &sample in &block {
+                assert!(sample.is_finite());
+                energy += sample * sample;
+                diff_energy += (sample - prev) * (sample - prev);
+                prev = sample;
+                count += 1.0;
+            }
+        }
+        ((energy / count).sqrt(), diff_energy / energy)
+    }
+
+    #[test]
+    fn test_colors_tilt_the_spectrum() {
+        let (white_rms, white_hf) = measure(NoiseColor::White);
+        let (pink_rms, pink_hf) = measure(NoiseColor::Pink);
+        let (brown_rms, brown_hf) = measure(NoiseColor::Brown);
+        // White noise's differences carry twice its energy
+        assert!((white_hf - 2.0).abs() < 0.1);
+        assert!(pink_hf < white_hf * 0.5);
+        assert!(brown_hf < pink_hf * 0.5);
+        assert!((white_rms - 0.577).abs() < 0.02);
+        for rms in [pink_rms, brown_rms] {
+            assert!((0.15..0.35).contains(&rms), "rms {}", rms);
+        }
+    }
+
+    #[test]
+    fn test_streams_do_not_repeat() {
+        let mut noise = Noise::new(1, NoiseColor::White);
+        let mut first = [0.0; 1024];
+        noise.fill(&mut first);
+        let mut later = [0.0; 1024];
+        for _ in 0..1000 {
+            noise.fill(&mut later);
+            assert_ne!(first, later);
+        }
+        let mut rng = Rng::new(0);
+        let values: Vec<f32> = (0..1000).map(|_| rng.next_f32()).collect();
+        assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+        assert!(values.windows(2).all(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn test_color_names() {
+        for color in [NoiseColor::White, NoiseColor::Pink, NoiseColor::Brown] {
+            assert_eq!(color.name().parse(), Ok(color));
+        }
+        assert!("blue".parse::<NoiseColor>().is_err());
+    }
+}
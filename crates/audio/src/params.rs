@@ -1,8 +1,43 @@
+use crate::mapping::WorldInputs;
+use crate::mixer::LayerSlot;
 use std::sync::atomic::{AtomicU32, Ordering};
 
+/// Master gain at full world energy; layers divide by this to recover energy.
+pub const GAIN_SCALE: f32 = 0.2;
+
+/// Texture at full world density; layers divide by this to recover density.
+pub const TEXTURE_SCALE: f32 = 0.3;
+
+/// Motion at full world rhythm.
+pub const MOTION_SCALE: f32 = 0.5;
+
+/// Detune above 1.0 at full world tension.
+pub const DETUNE_SCALE: f32 = 0.01;
+
+/// Reverb pre-delay at full world space, in ms.
+pub const PRE_DELAY_SCALE_MS: f32 = 80.0;
+
+/// Longest reverb pre-delay, in ms.
+pub const MAX_PRE_DELAY_MS: f32 = 200.0;
+
+/// Share of the texture bed and detune taken away at full world clarity.
+pub const CLARITY_CUT: f32 = 0.8;
+
+/// How much of the texture and detune world clarity leaves in: all of it up
+/// to the neutral 0.5, falling to `1 - CLARITY_CUT` at full clarity.
+pub fn clarity_gain(clarity: f32) -> f32 {
+    1.0 - CLARITY_CUT * ((clarity - 0.5) * 2.0).clamp(0.0, 1.0)
+}
+
+/// Grit for a world clarity: none from the neutral 0.5 up, rising to 1 as
+/// clarity falls to 0.
+pub fn clarity_grit(clarity: f32) -> f32 {
+    ((0.5 - clarity) * 2.0).clamp(0.0, 1.0)
+}
+
 /// Audio parameters that the callback uses.
 /// Minimal, numeric only.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct AudioParams {
     pub master_gain: f32,
     pub base_freq_hz: f32,
@@ -10,7 +45,125 @@ pub struct AudioParams {
     pub brightness: f32,
     pub motion: f32,
     pub texture: f32,
+    /// Size of the reverb's room, from a small room (0) to a hall (1).
+    pub reverb_size: f32,
+    /// Gap before the reverb tail starts, in ms; larger spaces have longer.
+    pub pre_delay_ms: f32,
+    /// Saturation of the drone and roughness of the texture bed, from clean
+    /// (0) to gritty (1).
+    pub grit: f32,
     pub sparkle_impulse: f32,
+    /// Tempo of the world's pulse grid for the delay to lock to; 0 leaves
+    /// the delay on its own rhythm-driven tempo.
+    pub pulse_bpm: f32,
+    pub layers: LayerAmounts,
+}
+
+/// How much of each layer is in the mix (0 = off, 1 = full).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayerAmounts {
+    pub drone: f32,
+    pub texture: f32,
+    pub sparkle: f32,
+    pub samples: f32,
+    pub grains: f32,
+    pub wind: f32,
+}
+
+impl Default for LayerAmounts {
+    fn default() -> Self {
+        Self {
+            drone: 1.0,
+            texture: 1.0,
+            sparkle: 1.0,
+            samples: 1.0,
+            grains: 1.0,
+            wind: 1.0,
+        }
+    }
+}
+
+impl LayerAmounts {
+    pub fn get(&self, slot: LayerSlot) -> f32 {
+        match slot {
+            LayerSlot::Drone => self.drone,
+            LayerSlot::Texture => self.texture,
+            LayerSlot::Sparkle => self.sparkle,
+            LayerSlot::Samples => self.samples,
+            LayerSlot::Grains => self.grains,
+            LayerSlot::Wind => self.wind,
+        }
+    }
+
+    /// Blends toward `to` by `amount` (0 = self, 1 = `to`).
+    pub fn lerp(&self, to: &LayerAmounts, amount: f32) -> LayerAmounts {
+        let lerp = |a: f32, b: f32| a + (b - a) * amount;
+        LayerAmounts {
+            drone: lerp(self.drone, to.drone),
+            texture: lerp(self.texture, to.texture),
+            sparkle: lerp(self.sparkle, to.sparkle),
+            samples: lerp(self.samples, to.samples),
+            grains: lerp(self.grains, to.grains),
+            wind: lerp(self.wind, to.wind),
+        }
+    }
+}
+
+/// Values that replace the world-derived ones, field by field. `None` keeps
+/// the mapped value.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ParamOverrides {
+    pub master_gain: Option<f32>,
+    pub base_freq_hz: Option<f32>,
+    pub detune_ratio: Option<f32>,
+    pub brightness: Option<f32>,
+    pub motion: Option<f32>,
+    pub texture: Option<f32>,
+    pub reverb_size: Option<f32>,
+    pub pre_delay_ms: Option<f32>,
+    pub grit: Option<f32>,
+    pub sparkle_impulse: Option<f32>,
+}
+
+impl ParamOverrides {
+    /// Names of the overridden fields.
+    pub fn fields(&self) -> Vec<&'static str> {
+        [
+            ("master_gain", self.master_gain),
+            ("base_freq_hz", self.base_freq_hz),
+            ("detune_ratio", self.detune_ratio),
+            ("brightness", self.brightness),
+            ("motion", self.motion),
+            ("texture", self.texture),
+            ("reverb_size", self.reverb_size),
+            ("pre_delay_ms", self.pre_delay_ms),
+            ("grit", self.grit),
+            ("sparkle_impulse", self.sparkle_impulse),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|_| name))
+        .collect()
+    }
+
+    pub fn apply_to(&self, params: &mut AudioParams) {
+        let fields = [
+            (self.master_gain, &mut params.master_gain),
+            (self.base_freq_hz, &mut params.base_freq_hz),
+            (self.detune_ratio, &mut params.detune_ratio),
+            (self.brightness, &mut params.brightness),
+            (self.motion, &mut params.motion),
+            (self.texture, &mut params.texture),
+            (self.reverb_size, &mut params.reverb_size),
+            (self.pre_delay_ms, &mut params.pre_delay_ms),
+            (self.grit, &mut params.grit),
+            (self.sparkle_impulse, &mut params.sparkle_impulse),
+        ];
+        for (value, field) in fields {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+    }
 }
 
 impl Default for AudioParams {
@@ -22,29 +175,45 @@ impl Default for AudioParams {
             brightness: 0.5,
             motion: 0.0,
             texture: 0.0,
+            reverb_size: 0.5,
+            pre_delay_ms: 0.5 * PRE_DELAY_SCALE_MS,
+            grit: 0.0,
             sparkle_impulse: 0.0,
+            pulse_bpm: 0.0,
+            layers: LayerAmounts::default(),
         }
     }
 }
 
 impl AudioParams {
-    /// Derive from world state variables.
-    pub fn from_world_state(
-        density: f32,
-        rhythm: f32,
-        tension: f32,
-        energy: f32,
-        warmth: f32,
-        sparkle_impulse: f32,
-    ) -> Self {
+    /// Derive from world state variables. Harmony is not followed; see
+    /// [`MappingProfile`](crate::mapping::MappingProfile) for that.
+    pub fn from_world_state(world: &WorldInputs) -> Self {
+        let WorldInputs {
+            density,
+            rhythm,
+            tension,
+            energy,
+            warmth,
+            space,
+            clarity,
+            sparkle_impulse,
+            ..
+        } = *world;
+        let clear = clarity_gain(clarity);
         Self {
-            master_gain: (energy * 0.2).clamp(0.0, 1.0), // energy -> gain, clamped
+            master_gain: (energy * GAIN_SCALE).clamp(0.0, 1.0), // energy -> gain, clamped
             base_freq_hz: (80.0 + warmth * 160.0).clamp(80.0, 240.0), // warmth -> freq range 80-240 Hz
-            detune_ratio: (1.0 + tension * 0.01).clamp(0.5, 2.0), // tension -> slight detune, clamped
+            detune_ratio: (1.0 + tension * DETUNE_SCALE * clear).clamp(0.5, 2.0), // tension -> slight detune, narrowed by clarity
             brightness: (1.0 - warmth * 0.5).clamp(0.0, 1.0), // warmth inverse -> brightness, clamped
-            motion: (rhythm * 0.5).clamp(0.0, 1.0),           // rhythm -> motion, clamped
-            texture: (density * 0.3).clamp(0.0, 1.0),         // density -> texture, clamped
+            motion: (rhythm * MOTION_SCALE).clamp(0.0, 1.0),  // rhythm -> motion, clamped
+            texture: (density * TEXTURE_SCALE * clear).clamp(0.0, 1.0), // density -> texture, thinned by clarity
+            reverb_size: space.clamp(0.0, 1.0),                         // space -> room size
+            pre_delay_ms: (space * PRE_DELAY_SCALE_MS).clamp(0.0, MAX_PRE_DELAY_MS), // space -> pre-delay
+            grit: clarity_grit(clarity), // low clarity -> grit
             sparkle_impulse,
+            pulse_bpm: 0.0,
+            layers: LayerAmounts::default(),
         }
     }
 }
@@ -58,7 +227,17 @@ pub struct SharedAudioParams {
     brightness: AtomicU32,
     motion: AtomicU32,
     texture: AtomicU32,
+    reverb_size: AtomicU32,
+    pre_delay_ms: AtomicU32,
+    grit: AtomicU32,
     sparkle_impulse: AtomicU32,
+    pulse_bpm: AtomicU32,
+    drone_amount: AtomicU32,
+    texture_amount: AtomicU32,
+    sparkle_amount: AtomicU32,
+    samples_amount: AtomicU32,
+    grains_amount: AtomicU32,
+    wind_amount: AtomicU32,
 }
 
 impl SharedAudioParams {
@@ -70,7 +249,17 @@ impl SharedAudioParams {
             brightness: AtomicU32::new(initial.brightness.to_bits()),
             motion: AtomicU32::new(initial.motion.to_bits()),
             texture: AtomicU32::new(initial.texture.to_bits()),
+            reverb_size: AtomicU32::new(initial.reverb_size.to_bits()),
+            pre_delay_ms: AtomicU32::new(initial.pre_delay_ms.to_bits()),
+            grit: AtomicU32::new(initial.grit.to_bits()),
             sparkle_impulse: AtomicU32::new(initial.sparkle_impulse.to_bits()),
+            pulse_bpm: AtomicU32::new(initial.pulse_bpm.to_bits()),
+            drone_amount: AtomicU32::new(initial.layers.drone.to_bits()),
+            texture_amount: AtomicU32::new(initial.layers.texture.to_bits()),
+            sparkle_amount: AtomicU32::new(initial.layers.sparkle.to_bits()),
+            samples_amount: AtomicU32::new(initial.layers.samples.to_bits()),
+            grains_amount: AtomicU32::new(initial.layers.grains.to_bits()),
+            wind_amount: AtomicU32::new(initial.layers.wind.to_bits()),
         }
     }
 
@@ -87,8 +276,27 @@ impl SharedAudioParams {
             .store(params.motion.to_bits(), Ordering::Relaxed);
         self.texture
             .store(params.texture.to_bits(), Ordering::Relaxed);
+        self.reverb_size
+            .store(params.reverb_size.to_bits(), Ordering::Relaxed);
+        self.pre_delay_ms
+            .store(params.pre_delay_ms.to_bits(), Ordering::Relaxed);
+        self.grit.store(params.grit.to_bits(), Ordering::Relaxed);
         self.sparkle_impulse
             .store(params.sparkle_impulse.to_bits(), Ordering::Relaxed);
+        self.pulse_bpm
+            .store(params.pulse_bpm.to_bits(), Ordering::Relaxed);
+        self.drone_amount
+            .store(params.layers.drone.to_bits(), Ordering::Relaxed);
+        self.texture_amount
+            .store(params.layers.texture.to_bits(), Ordering::Relaxed);
+        self.sparkle_amount
+            .store(params.layers.sparkle.to_bits(), Ordering::Relaxed);
+        self.samples_amount
+            .store(params.layers.samples.to_bits(), Ordering::Relaxed);
+        self.grains_amount
+            .store(params.layers.grains.to_bits(), Ordering::Relaxed);
+        self.wind_amount
+            .store(params.layers.wind.to_bits(), Ordering::Relaxed);
     }
 
     pub fn get(&self) -> AudioParams {
@@ -99,7 +307,19 @@ impl SharedAudioParams {
             brightness: f32::from_bits(self.brightness.load(Ordering::Relaxed)),
             motion: f32::from_bits(self.motion.load(Ordering::Relaxed)),
             texture: f32::from_bits(self.texture.load(Ordering::Relaxed)),
+            reverb_size: f32::from_bits(self.reverb_size.load(Ordering::Relaxed)),
+            pre_delay_ms: f32::from_bits(self.pre_delay_ms.load(Ordering::Relaxed)),
+            grit: f32::from_bits(self.grit.load(Ordering::Relaxed)),
             sparkle_impulse: f32::from_bits(self.sparkle_impulse.load(Ordering::Relaxed)),
+            pulse_bpm: f32::from_bits(self.pulse_bpm.load(Ordering::Relaxed)),
+            layers: LayerAmounts {
+                drone: f32::from_bits(self.drone_amount.load(Ordering::Relaxed)),
+                texture: f32::from_bits(self.texture_amount.load(Ordering::Relaxed)),
+                sparkle: f32::from_bits(self.sparkle_amount.load(Ordering::Relaxed)),
+                samples: f32::from_bits(self.samples_amount.load(Ordering::Relaxed)),
+                grains: f32::from_bits(self.grains_amount.load(Ordering::Relaxed)),
+                wind: f32::from_bits(self.wind_amount.load(Ordering::Relaxed)),
+            },
         }
     }
 }
@@ -0,0 +1,627 @@
+//! The layer stack the engine plays, changeable while it plays.
+//!
+//! The [`LayerRegistry`] keeps the stack as a list of [`LayerSpec`]s. A
+//! renderer attaching to it builds the stack as it stands and gets a
+//! [`LayerLink`]: the receiving end of a command queue ([`crate::spsc`]).
+//! Adding, replacing or removing a layer after that builds the new layer on
+//! the caller's thread and queues a [`MixCommand`], which the mixer
+//! applies at its next block with a short crossfade. New effect chains go
+//! the same way. Whatever the mixer lets go comes back through a second
+//! queue and is dropped on the control side, so the audio thread neither
+//! allocates nor frees. The registry counts the layers it has sent and not
+//! had back, and refuses changes the mixer would have no room for, so a
+//! change it accepts is always applied.
+//!
+//! A renderer going away hands the layers it was playing back to the
+//! registry, and the next one to attach takes them over through
+//! [`Layer::set_sample_rate`] rather than building them afresh, so an
+//! engine rebuilt after a device change plays on without the drone
+//! restarting, even at a different sample rate.
+
+use crate::effects::EffectChains;
+use crate::grain::GrainLayer;
+use crate::layers::{DroneLayer, DroneMode, Layer, SparkleLayer, TextureLayer};
+use crate::mixer::{BusChains, LayerSlot, LayerStack, MAX_LAYERS, MixCommand, Mixer, Retired};
+use crate::noise::{NoiseColor, NoiseColors};
+use crate::sample::{Sample, SampleLayer};
+use crate::smoother::SmoothingSettings;
+use crate::spsc::{self, Consumer, Producer};
+use crate::wind::WindLayer;
+use std::sync::{Arc, Mutex};
+
+/// Most layers in the stack, leaving the mixer room for as many again
+/// fading out.
+pub const MAX_STACK: usize = MAX_LAYERS / 2;
+
+/// Commands that can wait for the audio thread's next block.
+const QUEUE_LEN: usize = 16;
+
+/// A layer the registry knows how to build.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayerSpec {
+    Drone(DroneMode),
+    Texture(NoiseColor),
+    Sparkle(NoiseColor),
+    Wind(NoiseColor),
+    /// Every loaded sample, looped.
+    Samples,
+    /// A granular cloud over the loaded sample at this index.
+    Grains(usize),
+}
+
+impl LayerSpec {
+    pub fn slot(&self) -> LayerSlot {
+        match self {
+            LayerSpec::Drone(_) => LayerSlot::Drone,
+            LayerSpec::Texture(_) => LayerSlot::Texture,
+            LayerSpec::Sparkle(_) => LayerSlot::Sparkle,
+            LayerSpec::Wind(_) => LayerSlot::Wind,
+            LayerSpec::Samples => LayerSlot::Samples,
+            LayerSpec::Grains(_) => LayerSlot::Grains,
+        }
+    }
+
+    /// Whether the samples it needs are among `samples`.
+    pub fn playable(&self, samples: &[Sample]) -> bool {
+        match *self {
+            LayerSpec::Samples => !samples.is_empty(),
+            LayerSpec::Grains(source) => source < samples.len(),
+            _ => true,
+        }
+    }
+
+    /// Builds the layer, or `None` if it needs samples that are not loaded.
+    pub fn build(&self, sample_rate: f32, samples: &Arc<[Sample]>) -> Option<Box<dyn Layer>> {
+        if !self.playable(samples) {
+            return None;
+        }
+        Some(match *self {
+            LayerSpec::Drone(mode) => Box::new(DroneLayer::with_mode(sample_rate, mode)),
+            LayerSpec::Texture(color) => Box::new(TextureLayer::with_noise(sample_rate, color)),
+            LayerSpec::Sparkle(color) => Box::new(SparkleLayer::with_noise(sample_rate, color)),
+            LayerSpec::Wind(color) => Box::new(WindLayer::with_noise(sample_rate, color)),
+            LayerSpec::Samples => Box::new(SampleLayer::new(sample_rate, Arc::clone(samples))),
+            LayerSpec::Grains(source) => {
+                Box::new(GrainLayer::new(sample_rate, Arc::clone(samples), source))
+            }
+        })
+    }
+}
+
+/// The stack the engine starts with: drone, texture, wind and sparkle, then
+/// grains over `grain_source` if given, then the samples if any are loaded.
+pub fn default_stack(
+    drone_mode: DroneMode,
+    noise: NoiseColors,
+    grain_source: Option<usize>,
+    samples_loaded: bool,
+) -> Vec<LayerSpec> {
+    let mut stack = vec![
+        LayerSpec::Drone(drone_mode),
+        LayerSpec::Texture(noise.texture),
+        LayerSpec::Wind(noise.wind),
+        LayerSpec::Sparkle(noise.sparkle),
+    ];
+    stack.extend(grain_source.map(LayerSpec::Grains));
+    if samples_loaded {
+        stack.push(LayerSpec::Samples);
+    }
+    stack
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum RegistryError {
+    #[error("the stack already holds {MAX_STACK} layers")]
+    Full,
+    #[error("no {0} layer in the stack")]
+    Missing(&'static str),
+    #[error("no samples loaded for a {0} layer")]
+    NoSamples(&'static str),
+    /// The command queue is full, or the mixer has no room for more layers
+    /// while those replaced fade out.
+    #[error("the audio engine is not taking layer changes")]
+    Busy,
+}
+
+/// Retired items the queue back can hold: every layer the mixer may hold,
+/// plus effect chains from a full command queue and the two it keeps.
+const RETIRED_LEN: usize = MAX_LAYERS + QUEUE_LEN + 2;
+
+/// The renderer's ends of the queues.
+pub struct LayerLink {
+    /// Which attach this link came from.
+    generation: u64,
+    commands: Consumer<MixCommand>,
+    retired: Producer<Retired>,
+    /// Items the retired queue had no room for, held until it does so they
+    /// are never freed on the audio thread. Allocated up front.
+    overflow: Vec<Retired>,
+}
+
+impl LayerLink {
+    /// Applies queued commands to `mixer` and hands back whatever it has
+    /// let go. Call between blocks.
+    pub fn sync(&mut self, mixer: &mut Mixer) {
+        while let Some(retired) = self.overflow.pop() {
+            if let Err(retired) = self.retired.push(retired) {
+                self.overflow.push(retired);
+                break;
+            }
+        }
+        while let Some(command) = self.commands.pop() {
+            if let Err(retired) = mixer.apply(command) {
+                self.hand_back(retired);
+            }
+        }
+        while let Some(retired) = mixer.take_retired() {
+            self.hand_back(retired);
+        }
+    }
+
+    /// Queues `retired` for the control side to drop. The queue is sized so
+    /// it cannot fill while the registry counts what it sends; should it
+    /// anyway, the item waits in `overflow`, and past that is leaked, as a
+    /// leak costs less than freeing on the audio thread.
+    fn hand_back(&mut self, retired: Retired) {
+        if let Err(retired) = self.retired.push(retired) {
+            if self.overflow.len() < self.overflow.capacity() {
+                self.overflow.push(retired);
+            } else {
+                std::mem::forget(retired);
+            }
+        }
+    }
+}
+
+/// The control side's ends of the queues to the attached renderer.
+struct EngineEnd {
+    generation: u64,
+    sample_rate: f32,
+    /// Layout channels, each with its own master chain.
+    channels: usize,
+    commands: Producer<MixCommand>,
+    retired: Consumer<Retired>,
+    /// Layers sent to the mixer and not yet handed back: an upper bound on
+    /// those it holds, playing or fading out.
+    held: usize,
+}
+
+impl EngineEnd {
+    /// Queues `commands` all together, or none of them. Fails if they
+    /// would bring more layers than the mixer has room for, as while too
+    /// many are still fading out, so the mixer never turns one away.
+    fn send(&mut self, commands: Vec<MixCommand>) -> Result<(), RegistryError> {
+        // Free what the renderer has handed back
+        while let Some(retired) = self.retired.pop() {
+            if let Retired::Layer(_) = retired {
+                self.held -= 1;
+            }
+        }
+        let incoming = commands
+            .iter()
+            .filter(|command| matches!(command, MixCommand::Add(..) | MixCommand::Replace(..)))
+            .count();
+        if self.commands.space() < commands.len() || self.held + incoming > MAX_LAYERS {
+            return Err(RegistryError::Busy);
+        }
+        self.held += incoming;
+        for command in commands {
+            // Cannot fail: nothing else pushes, and the space only grows
+            let _ = self.commands.push(command);
+        }
+        Ok(())
+    }
+}
+
+struct RegistryState {
+    stack: Vec<LayerSpec>,
+    engine: Option<EngineEnd>,
+    /// Attaches so far.
+    generation: u64,
+    /// Layers the last renderer was playing, for the next to take over.
+    parked: LayerStack,
+}
+
+impl RegistryState {
+    /// Drops parked layers in `slot`, whose specs have changed.
+    fn unpark(&mut self, slot: LayerSlot) {
+        self.parked.retain(|(parked, _)| *parked != slot);
+    }
+}
+
+/// The layer stack, shared by the control side and whichever renderer is
+/// attached. Shared across engine rebuilds, so changes survive a restart.
+pub struct LayerRegistry {
+    samples: Arc<[Sample]>,
+    smoothing: SmoothingSettings,
+    state: Mutex<RegistryState>,
+}
+
+impl LayerRegistry {
+    /// A registry playing `stack` (up to [`MAX_STACK`] layers) over `samples`.
+    pub fn new(stack: Vec<LayerSpec>, samples: Arc<[Sample]>) -> Self {
+        Self::with_smoothing(stack, samples, SmoothingSettings::default())
+    }
+
+    /// As [`LayerRegistry::new`], building every layer with `smoothing`.
+    pub fn with_smoothing(
+        mut stack: Vec<LayerSpec>,
+        samples: Arc<[Sample]>,
+        smoothing: SmoothingSettings,
+    ) -> Self {
+        stack.truncate(MAX_STACK);
+        Self {
+            samples,
+            smoothing,
+            state: Mutex::new(RegistryState {
+                stack,
+                engine: None,
+                generation: 0,
+                parked: Vec::new(),
+            }),
+        }
+    }
+
+    /// The stack, in the order it was built.
+    pub fn stack(&self) -> Vec<LayerSpec> {
+        self.state.lock().unwrap().stack.clone()
+    }
+
+    /// Samples the samples and grains layers play from.
+    pub fn samples(&self) -> &Arc<[Sample]> {
+        &self.samples
+    }
+
+    fn build(&self, spec: &LayerSpec, sample_rate: f32) -> Option<Box<dyn Layer>> {
+        let mut layer = spec.build(sample_rate, &self.samples)?;
+        layer.set_smoothing(&self.smoothing);
+        Some(layer)
+    }
+
+    /// Builds the stack for a renderer at `sample_rate` placing layers on
+    /// `channels`, and links it in place of any renderer attached before.
+    /// Layers parked by the last renderer are moved to `sample_rate` and
+    /// played on; layers that need missing samples are left out.
+    pub fn attach(&self, sample_rate: f32, channels: usize) -> (LayerStack, LayerLink) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let mut parked = std::mem::take(&mut state.parked);
+        let layers: LayerStack = state
+            .stack
+            .iter()
+            .filter_map(|spec| {
+                let slot = spec.slot();
+                let layer = match parked.iter().position(|(parked, _)| *parked == slot) {
+                    Some(index) => {
+                        let mut layer = parked.remove(index).1;
+                        layer.set_sample_rate(sample_rate);
+                        layer
+                    }
+                    None => self.build(spec, sample_rate)?,
+                };
+                Some((slot, layer))
+            })
+            .collect();
+        let (command_tx, command_rx) = spsc::channel(QUEUE_LEN);
+        let (retired_tx, retired_rx) = spsc::channel(RETIRED_LEN);
+        state.generation += 1;
+        let generation = state.generation;
+        state.engine = Some(EngineEnd {
+            generation,
+            sample_rate,
+            channels,
+            commands: command_tx,
+            retired: retired_rx,
+            held: layers.len(),
+        });
+        let link = LayerLink {
+            generation,
+            commands: command_rx,
+            retired: retired_tx,
+            overflow: Vec::with_capacity(RETIRED_LEN),
+        };
+        (layers, link)
+    }
+
+    /// Takes back the layers `mixer` plays for the next renderer to attach,
+    /// if `link` is still the attached one. Called as a renderer is dropped,
+    /// off the audio thread.
+    pub fn detach(&self, link: &mut LayerLink, mixer: &mut Mixer) {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .engine
+            .as_ref()
+            .is_none_or(|engine| engine.generation != link.generation)
+        {
+            return;
+        }
+        // Apply whatever is still queued, so the layers match the stack
+        link.sync(mixer);
+        state.engine = None;
+        state.parked = mixer.take_layers();
+    }
+
+    /// Builds `spec` and queues the command `make` wraps it in, if a
+    /// renderer is attached. Checks the samples either way, so changes fail
+    /// alike with and without one.
+    fn queue(
+        &self,
+        state: &mut RegistryState,
+        spec: &LayerSpec,
+        make: impl FnOnce(Box<dyn Layer>) -> MixCommand,
+    ) -> Result<(), RegistryError> {
+        if !spec.playable(&self.samples) {
+            return Err(RegistryError::NoSamples(spec.slot().name()));
+        }
+        let Some(engine) = &mut state.engine else {
+            return Ok(());
+        };
+        let layer = self
+            .build(spec, engine.sample_rate)
+            .ok_or(RegistryError::NoSamples(spec.slot().name()))?;
+        engine.send(vec![make(layer)])
+    }
+
+    /// Fades `spec` in alongside the layers already playing.
+    pub fn add(&self, spec: LayerSpec) -> Result<(), RegistryError> {
+        let mut state = self.state.lock().unwrap();
+        if state.stack.len() >= MAX_STACK {
+            return Err(RegistryError::Full);
+        }
+        let slot = spec.slot();
+        self.queue(&mut state, &spec, |layer| MixCommand::Add(slot, layer))?;
+        state.stack.push(spec);
+        Ok(())
+    }
+
+    /// Crossfades from whatever is in `spec`'s slot to `spec`, or adds it if
+    /// the slot is empty.
+    pub fn replace(&self, spec: LayerSpec) -> Result<(), RegistryError> {
+        let mut state = self.state.lock().unwrap();
+        let slot = spec.slot();
+        let position = state.stack.iter().position(|s| s.slot() == slot);
+        let remaining = state.stack.iter().filter(|s| s.slot() != slot).count();
+        if remaining >= MAX_STACK {
+            return Err(RegistryError::Full);
+        }
+        self.queue(&mut state, &spec, |layer| MixCommand::Replace(slot, layer))?;
+        state.unpark(slot);
+        state.stack.retain(|s| s.slot() != slot);
+        let position = position.unwrap_or(state.stack.len());
+        state.stack.insert(position, spec);
+        Ok(())
+    }
+
+    /// Fades out every layer in `slot`.
+    pub fn remove(&self, slot: LayerSlot) -> Result<(), RegistryError> {
+        let mut state = self.state.lock().unwrap();
+        if !state.stack.iter().any(|s| s.slot() == slot) {
+            return Err(RegistryError::Missing(slot.name()));
+        }
+        if let Some(engine) = &mut state.engine {
+            engine.send(vec![MixCommand::Remove(slot)])?;
+        }
+        state.unpark(slot);
+        state.stack.retain(|s| s.slot() != slot);
+        Ok(())
+    }
+
+    /// Switches to `stack` (up to [`MAX_STACK`] layers), crossfading only
+    /// the slots whose layers change, and to `effects` if given, fading
+    /// through dry. Nothing changes if any of it fails.
+    pub fn switch(
+        &self,
+        stack: Vec<LayerSpec>,
+        effects: Option<&EffectChains>,
+    ) -> Result<(), RegistryError> {
+        if stack.len() > MAX_STACK {
+            return Err(RegistryError::Full);
+        }
+        if let Some(spec) = stack.iter().find(|spec| !spec.playable(&self.samples)) {
+            return Err(RegistryError::NoSamples(spec.slot().name()));
+        }
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let in_slot = |stack: &[LayerSpec], slot| -> Vec<LayerSpec> {
+            stack.iter().filter(|s| s.slot() == slot).cloned().collect()
+        };
+        let changed: Vec<_> = LayerSlot::ALL
+            .into_iter()
+            .filter(|&slot| in_slot(&state.stack, slot) != in_slot(&stack, slot))
+            .collect();
+        if let Some(engine) = &mut state.engine {
+            let mut commands = Vec::new();
+            for &slot in &changed {
+                let new = in_slot(&stack, slot);
+                if new.is_empty() {
+                    commands.push(MixCommand::Remove(slot));
+                }
+                for (i, spec) in new.iter().enumerate() {
+                    let layer = self
+                        .build(spec, engine.sample_rate)
+                        .ok_or(RegistryError::NoSamples(slot.name()))?;
+                    commands.push(match i {
+                        0 => MixCommand::Replace(slot, layer),
+                        _ => MixCommand::Add(slot, layer),
+                    });
+                }
+            }
+            if let Some(effects) = effects {
+                let mut chains = BusChains::new(engine.sample_rate, effects, engine.channels);
+                chains.start_dry();
+                commands.push(MixCommand::Effects(Box::new(chains)));
+            }
+            engine.send(commands)?;
+        }
+        for slot in changed {
+            state.unpark(slot);
+        }
+        state.stack = stack;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::AudioParams;
+
+    fn peak(mixer: &mut Mixer, link: &mut LayerLink, frames: usize) -> f32 {
+        let mut output = vec![0.0; frames];
+        link.sync(mixer);
+        mixer.process(&mut output, &AudioParams::default(), 1);
+        output.iter().fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn test_layers_come_and_go_while_playing() {
+        let samples: Arc<[Sample]> = Arc::from(vec![Sample::new("tone", vec![0.5; 4_800], 48_000)]);
+        let registry = LayerRegistry::new(vec![LayerSpec::Drone(DroneMode::Dual)], samples);
+        assert_eq!(
+            registry.add(LayerSpec::Grains(3)),
+            Err(RegistryError::NoSamples("grains"))
+        );
+
+        let (layers, mut link) = registry.attach(48_000.0, 1);
+        assert_eq!(layers.len(), 1);
+        let mut mixer = Mixer::new(48_000.0);
+        mixer.set_layers(layers);
+        assert!(peak(&mut mixer, &mut link, 4_800) > 0.0);
+
+        registry.add(LayerSpec::Samples).unwrap();
+        registry
+            .replace(LayerSpec::Drone(DroneMode::Additive(4)))
+            .unwrap();
+        assert_eq!(
+            registry.stack(),
+            [LayerSpec::Drone(DroneMode::Additive(4)), LayerSpec::Samples]
+        );
+        // Both commands land on the next block; the old drone fades out
+        // alongside the new one and comes back once silent
+        peak(&mut mixer, &mut link, 256);
+        assert_eq!(mixer.layer_count(), 3);
+        peak(&mut mixer, &mut link, 4_800);
+        link.sync(&mut mixer);
+        assert_eq!(mixer.layer_count(), 2);
+
+        registry.remove(LayerSlot::Drone).unwrap();
+        registry.remove(LayerSlot::Samples).unwrap();
+        assert_eq!(
+            registry.remove(LayerSlot::Wind),
+            Err(RegistryError::Missing("wind"))
+        );
+        peak(&mut mixer, &mut link, 4_800);
+        link.sync(&mut mixer);
+        assert_eq!(mixer.layer_count(), 0);
+        assert_eq!(peak(&mut mixer, &mut link, 4_800), 0.0);
+    }
+
+    #[test]
+    fn test_switch_changes_only_what_differs() {
+        let registry = LayerRegistry::new(
+            vec![
+                LayerSpec::Drone(DroneMode::Dual),
+                LayerSpec::Wind(NoiseColor::White),
+            ],
+            Arc::from(Vec::new()),
+        );
+        let (layers, mut link) = registry.attach(48_000.0, 1);
+        let mut mixer = Mixer::new(48_000.0);
+        mixer.set_layers(layers);
+        peak(&mut mixer, &mut link, 256);
+
+        assert_eq!(
+            registry.switch(vec![LayerSpec::Samples], None),
+            Err(RegistryError::NoSamples("samples"))
+        );
+        let stack = vec![
+            LayerSpec::Drone(DroneMode::Dual),
+            LayerSpec::Texture(NoiseColor::Pink),
+        ];
+        let effects = EffectChains {
+            master: vec![crate::effects::EffectKind::Limiter],
+            ..EffectChains::default()
+        };
+        registry.switch(stack.clone(), Some(&effects)).unwrap();
+        assert_eq!(registry.stack(), stack);
+        // The drone plays on; wind fades out as texture fades in
+        peak(&mut mixer, &mut link, 256);
+        assert_eq!(mixer.layer_count(), 3);
+        // The old chains fade to dry before the new ones swap in and come
+        // back for dropping, leaving only the new layers
+        assert!(peak(&mut mixer, &mut link, 4_800) > 0.0);
+        peak(&mut mixer, &mut link, 256);
+        link.sync(&mut mixer);
+        assert_eq!(mixer.layer_count(), 2);
+        let mut state = registry.state.lock().unwrap();
+        let retired = &mut state.engine.as_mut().unwrap().retired;
+        let mut chains = 0;
+        while let Some(item) = retired.pop() {
+            chains += usize::from(matches!(item, Retired::Effects(_)));
+        }
+        assert_eq!(chains, 1);
+    }
+
+    #[test]
+    fn test_layers_carry_over_to_the_next_renderer() {
+        let registry = LayerRegistry::new(
+            vec![
+                LayerSpec::Drone(DroneMode::Dual),
+                LayerSpec::Wind(NoiseColor::White),
+            ],
+            Arc::from(Vec::new()),
+        );
+        let (layers, mut link) = registry.attach(48_000.0, 1);
+        let mut mixer = Mixer::new(48_000.0);
+        mixer.set_layers(layers);
+        registry.add(LayerSpec::Texture(NoiseColor::Pink)).unwrap();
+        peak(&mut mixer, &mut link, 256);
+
+        // A stale link leaves the current renderer alone
+        let (layers, mut current) = registry.attach(48_000.0, 1);
+        registry.detach(&mut link, &mut mixer);
+        assert_eq!(registry.state.lock().unwrap().parked.len(), 0);
+        let mut mixer = Mixer::new(48_000.0);
+        mixer.set_layers(layers);
+        registry.detach(&mut current, &mut mixer);
+        assert_eq!(mixer.layer_count(), 0);
+        assert_eq!(registry.state.lock().unwrap().parked.len(), 3);
+
+        // Changes while detached drop only the layers they touch
+        registry.remove(LayerSlot::Wind).unwrap();
+        let (layers, _link) = registry.attach(44_100.0, 1);
+        assert_eq!(
+            layers.iter().map(|(slot, _)| *slot).collect::<Vec<_>>(),
+            [LayerSlot::Drone, LayerSlot::Texture]
+        );
+        assert!(registry.state.lock().unwrap().parked.is_empty());
+    }
+
+    #[test]
+    fn test_changes_wait_while_the_mixer_has_no_room() {
+        let registry = LayerRegistry::new(
+            vec![LayerSpec::Drone(DroneMode::Dual)],
+            Arc::from(Vec::new()),
+        );
+        let (layers, mut link) = registry.attach(48_000.0, 1);
+        let mut mixer = Mixer::new(48_000.0);
+        mixer.set_layers(layers);
+        // Until the mixer hands replaced layers back, each one counts
+        // against its room
+        for _ in 1..MAX_LAYERS {
+            registry.replace(LayerSpec::Drone(DroneMode::Dual)).unwrap();
+        }
+        let additive = LayerSpec::Drone(DroneMode::Additive(4));
+        assert_eq!(registry.replace(additive.clone()), Err(RegistryError::Busy));
+        assert_eq!(registry.stack(), [LayerSpec::Drone(DroneMode::Dual)]);
+
+        // Drones replaced before fading in come straight back, the first
+        // fades out under the last, and there is room again
+        link.sync(&mut mixer);
+        assert_eq!(mixer.layer_count(), 2);
+        registry.replace(additive.clone()).unwrap();
+        assert_eq!(registry.stack(), [additive]);
+        link.sync(&mut mixer);
+        assert_eq!(mixer.layer_count(), 2);
+    }
+}
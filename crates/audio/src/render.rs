@@ -0,0 +1,53 @@
+//! Offline rendering of the layer stack without an audio device.
+
+use crate::mixer::Mixer;
+use crate::params::AudioParams;
+
+/// Renders `seconds` of interleaved audio for a fixed set of parameters.
+///
+/// Uses the same mixer as the real-time engine, so headless builds and tests
+/// hear exactly what the device would play.
+pub fn render_offline(
+    params: &AudioParams,
+    sample_rate: u32,
+    channels: u16,
+    seconds: f32,
+) -> Vec<f32> {
+    let frames = (sample_rate as f32 * seconds.max(0.0)) as usize;
+    let mut output = vec![0.0f32; frames * channels as usize];
+    let mut mixer = Mixer::new(sample_rate as f32);
+    mixer.process(&mut output, params, channels);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::WorldInputs;
+
+    #[test]
+    fn test_render_offline_length_and_bounds() {
+        let params = AudioParams::from_world_state(&WorldInputs {
+            energy: 1.0,
+            ..WorldInputs::default()
+        });
+        let output = render_offline(&params, 48_000, 2, 0.5);
+        assert_eq!(output.len(), 48_000);
+        assert!(
+            output
+                .iter()
+                .all(|s| s.is_finite() && (-1.0..=1.0).contains(s))
+        );
+        // Drone smoothing ramps in, so the tail must be audible
+        assert!(output[40_000..].iter().any(|s| s.abs() > 0.0));
+    }
+
+    #[test]
+    fn test_render_offline_duplicates_channels() {
+        let params = AudioParams::default();
+        let output = render_offline(&params, 8_000, 2, 0.1);
+        for frame in output.chunks(2) {
+            assert_eq!(frame[0], frame[1]);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Output kept for recording: the last few minutes in memory, and WAV files
+//! written a block at a time.
+//!
+//! Both take what an [`OutputTap`](crate::tap::OutputTap) hands out and write
+//! 32-bit float WAV, like [`CaptureBuffer`](crate::backend::CaptureBuffer).
+
+use crate::backend::OutputFormat;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+fn wav_spec(format: OutputFormat) -> hound::WavSpec {
+    hound::WavSpec {
+        channels: format.channels,
+        sample_rate: format.sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    }
+}
+
+/// The newest `secs` seconds of output; older output is dropped as new
+/// arrives, and a change of format starts it over.
+#[derive(Debug)]
+pub struct RollingBuffer {
+    secs: f32,
+    format: OutputFormat,
+    samples: VecDeque<f32>,
+}
+
+impl RollingBuffer {
+    pub fn new(secs: f32) -> Self {
+        Self {
+            secs: secs.max(0.0),
+            format: OutputFormat::default(),
+            samples: VecDeque::new(),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        (self.format.sample_rate as f32 * self.secs) as usize * self.format.channels as usize
+    }
+
+    /// Appends interleaved `block` of `format`.
+    pub fn push(&mut self, block: &[f32], format: OutputFormat) {
+        if format != self.format {
+            self.format = format;
+            self.samples.clear();
+        }
+        let capacity = self.capacity();
+        let block = &block[block.len().saturating_sub(capacity)..];
+        let over = (self.samples.len() + block.len()).saturating_sub(capacity);
+        self.samples.drain(..over);
+        self.samples.extend(block);
+    }
+
+    /// Seconds of output held.
+    pub fn secs(&self) -> f64 {
+        let frames = self.samples.len() / usize::from(self.format.channels.max(1));
+        frames as f64 / self.format.sample_rate.max(1) as f64
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// A copy of what is held, oldest first.
+    pub fn samples(&self) -> Vec<f32> {
+        self.samples.iter().copied().collect()
+    }
+}
+
+/// Writes interleaved `samples` of `format` as a 32-bit float WAV file.
+pub fn write_wav(path: &Path, format: OutputFormat, samples: &[f32]) -> Result<(), hound::Error> {
+    let mut writer = hound::WavWriter::create(path, wav_spec(format))?;
+    for &sample in samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()
+}
+
+/// A WAV file written as output arrives. Flushing keeps the header current,
+/// so the file plays up to the last flush even if the process dies.
+pub struct WavFile {
+    writer: hound::WavWriter<BufWriter<File>>,
+    format: OutputFormat,
+}
+
+impl WavFile {
+    pub fn create(path: &Path, format: OutputFormat) -> Result<Self, hound::Error> {
+        Ok(Self {
+            writer: hound::WavWriter::create(path, wav_spec(format))?,
+            format,
+        })
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Bytes of samples written so far.
+    pub fn data_bytes(&self) -> u64 {
+        u64::from(self.writer.len()) * 4
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), hound::Error> {
+        for &sample in samples {
+            self.writer.write_sample(sample)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), hound::Error> {
+        self.writer.flush()
+    }
+
+    pub fn finalize(self) -> Result<(), hound::Error> {
+        self.writer.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_buffer_keeps_the_newest_and_writes_them() {
+        let format = OutputFormat {
+            sample_rate: 100,
+            channels: 2,
+        };
+        let mut buffer = RollingBuffer::new(1.0);
+        buffer.push(&[0.1; 150], format);
+        buffer.push(&[0.2; 100], format);
+        assert_eq!(buffer.secs(), 1.0);
+        let samples = buffer.samples();
+        assert_eq!(samples.len(), 200);
+        assert_eq!((samples[99], samples[100]), (0.1, 0.2));
+        // A block longer than the buffer leaves only its end
+        buffer.push(&(0..300).map(|i| i as f32).collect::<Vec<_>>(), format);
+        assert_eq!(buffer.samples()[0], 100.0);
+
+        let path = std::env::temp_dir().join(format!("rolling-{}.wav", std::process::id()));
+        write_wav(&path, buffer.format(), &buffer.samples()).unwrap();
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().sample_rate, 100);
+        assert_eq!(reader.len(), 200);
+        std::fs::remove_file(&path).unwrap();
+
+        buffer.push(&[0.3; 10], OutputFormat::default());
+        assert_eq!(buffer.samples(), vec![0.3; 10]);
+    }
+}
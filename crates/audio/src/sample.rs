@@ -0,0 +1,360 @@
+//! Looping playback of recorded samples (rain, wind, birds).
+//!
+//! Samples are decoded to mono at load time and played back at the output
+//! rate. Loops are closed with an equal-power crossfade so the seam is silent.
+
+use crate::layers::Layer;
+use crate::params::{AudioParams, TEXTURE_SCALE};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Most samples that play at once, reached at full density.
+pub const MAX_SAMPLE_VOICES: usize = 4;
+
+/// Overlap between the end and start of a loop.
+const LOOP_CROSSFADE_SECS: f32 = 1.0;
+
+/// Time for a voice to fade fully in or out when density changes.
+const VOICE_FADE_SECS: f32 = 2.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SampleError {
+    #[error("failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to decode WAV {path}: {source}")]
+    Wav { path: PathBuf, source: hound::Error },
+    #[error("failed to decode FLAC {path}: {source}")]
+    Flac {
+        path: PathBuf,
+        source: claxon::Error,
+    },
+    #[error("{0} has no audio")]
+    Empty(PathBuf),
+    #[error("{0} is not a WAV or FLAC file")]
+    Unsupported(PathBuf),
+}
+
+/// A decoded mono recording.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    name: String,
+    frames: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl Sample {
+    pub fn new(name: impl Into<String>, frames: Vec<f32>, sample_rate: u32) -> Self {
+        Self {
+            name: name.into(),
+            frames,
+            sample_rate,
+        }
+    }
+
+    /// Decodes a WAV or FLAC file, mixing all channels down to mono.
+    pub fn load(path: &Path) -> Result<Self, SampleError> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let (interleaved, channels, sample_rate) = match extension.as_deref() {
+            Some("wav") => decode_wav(path)?,
+            Some("flac") => decode_flac(path)?,
+            _ => return Err(SampleError::Unsupported(path.to_path_buf())),
+        };
+        let channels = channels.max(1) as usize;
+        let frames: Vec<f32> = interleaved
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect();
+        // Anything shorter cannot hold a loop crossfade
+        if frames.len() < 4 {
+            return Err(SampleError::Empty(path.to_path_buf()));
+        }
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self::new(name, frames, sample_rate))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn frames(&self) -> &[f32] {
+        &self.frames
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn duration_secs(&self) -> f32 {
+        self.frames.len() as f32 / self.sample_rate as f32
+    }
+
+    fn crossfade_frames(&self) -> f64 {
+        let max = self.frames.len() / 4;
+        ((LOOP_CROSSFADE_SECS * self.sample_rate as f32) as usize).clamp(1, max) as f64
+    }
+
+    /// Length of one loop: the file minus the overlapped tail.
+    fn loop_frames(&self) -> f64 {
+        self.frames.len() as f64 - self.crossfade_frames()
+    }
+
+    /// Linearly interpolated frame at a fractional position.
+    pub(crate) fn frame_at(&self, position: f64) -> f32 {
+        let index = position as usize;
+        let frac = (position - index as f64) as f32;
+        let a = self.frames[index.min(self.frames.len() - 1)];
+        let b = self.frames[(index + 1).min(self.frames.len() - 1)];
+        a + (b - a) * frac
+    }
+
+    /// Reads the looped signal, fading the tail into the head of the file.
+    fn read_looped(&self, position: f64) -> f32 {
+        let loop_frames = self.loop_frames();
+        if position < loop_frames {
+            return self.frame_at(position);
+        }
+        let amount = ((position - loop_frames) / self.crossfade_frames()) as f32;
+        let angle = amount * std::f32::consts::FRAC_PI_2;
+        self.frame_at(position) * angle.cos() + self.frame_at(position - loop_frames) * angle.sin()
+    }
+}
+
+fn decode_wav(path: &Path) -> Result<(Vec<f32>, u16, u32), SampleError> {
+    let wav_error = |source| SampleError::Wav {
+        path: path.to_path_buf(),
+        source,
+    };
+    let reader = hound::WavReader::open(path).map_err(wav_error)?;
+    let spec = reader.spec();
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>(),
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect::<Result<_, _>>()
+        }
+    }
+    .map_err(wav_error)?;
+    Ok((samples, spec.channels, spec.sample_rate))
+}
+
+fn decode_flac(path: &Path) -> Result<(Vec<f32>, u16, u32), SampleError> {
+    let flac_error = |source| SampleError::Flac {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut reader = claxon::FlacReader::open(path).map_err(flac_error)?;
+    let info = reader.streaminfo();
+    let scale = 1.0 / (1i64 << (info.bits_per_sample - 1)) as f32;
+    let samples = reader
+        .samples()
+        .map(|s| s.map(|s| s as f32 * scale))
+        .collect::<Result<_, _>>()
+        .map_err(flac_error)?;
+    Ok((samples, info.channels as u16, info.sample_rate))
+}
+
+/// Loads every WAV and FLAC file in `dir`, in file name order.
+///
+/// Files that fail to decode are logged and skipped.
+pub fn load_sample_dir(dir: &Path) -> Result<Vec<Sample>, SampleError> {
+    let io_error = |source| SampleError::Io {
+        path: dir.to_path_buf(),
+        source,
+    };
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(io_error)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut samples = Vec::new();
+    for path in paths {
+        match Sample::load(&path) {
+            Ok(sample) => {
+                info!(
+                    "Loaded sample '{}' ({:.1} s)",
+                    sample.name(),
+                    sample.duration_secs()
+                );
+                samples.push(sample);
+            }
+            Err(SampleError::Unsupported(_)) => {}
+            Err(e) => warn!("Skipping sample: {}", e),
+        }
+    }
+    Ok(samples)
+}
+
+struct Voice {
+    sample: usize,
+    position: f64,
+    gain: f32,
+}
+
+/// Layer that loops recorded samples, with density choosing how many play.
+pub struct SampleLayer {
+    samples: Arc<[Sample]>,
+    voices: Vec<Voice>,
+    sample_rate: f32,
+    fade_step: f32,
+}
+
+impl SampleLayer {
+    pub fn new(sample_rate: f32, samples: Arc<[Sample]>) -> Self {
+        // Stagger start points so voices sharing a file do not phase
+        let voices = if samples.is_empty() {
+            Vec::new()
+        } else {
+            (0..MAX_SAMPLE_VOICES)
+                .map(|i| {
+                    let sample = i % samples.len();
+                    Voice {
+                        sample,
+                        position: samples[sample].loop_frames() * i as f64
+                            / MAX_SAMPLE_VOICES as f64,
+                        gain: 0.0,
+                    }
+                })
+                .collect()
+        };
+        Self {
+            samples,
+            voices,
+            sample_rate,
+            fade_step: 1.0 / (VOICE_FADE_SECS * sample_rate),
+        }
+    }
+
+    /// Number of voices that should be audible at a given density (0-1).
+    pub fn active_voices(density: f32) -> usize {
+        (density.clamp(0.0, 1.0) * MAX_SAMPLE_VOICES as f32).ceil() as usize
+    }
+}
+
+impl Layer for SampleLayer {
+    fn process_block(&mut self, out: &mut [f32], params: &AudioParams) {
+        let density = params.texture / TEXTURE_SCALE;
+        let active = Self::active_voices(density);
+        // Scale so a full stack sits near a single voice in loudness
+        let scale = 1.0 / (MAX_SAMPLE_VOICES as f32).sqrt();
+
+        out.fill(0.0);
+        for (i, voice) in self.voices.iter_mut().enumerate() {
+            let target = if i < active { 1.0 } else { 0.0 };
+            let sample = &self.samples[voice.sample];
+            let step = sample.sample_rate as f64 / self.sample_rate as f64;
+            let end = sample.frames.len() as f64;
+            for out in out.iter_mut() {
+                if voice.gain < target {
+                    voice.gain = (voice.gain + self.fade_step).min(target);
+                } else if voice.gain > target {
+                    voice.gain = (voice.gain - self.fade_step).max(target);
+                }
+                if voice.gain > 0.0 {
+                    *out += sample.read_looped(voice.position) * voice.gain * scale;
+                }
+                // Keep silent voices moving so they re-enter mid-recording
+                voice.position += step;
+                if voice.position >= end {
+                    voice.position -= sample.loop_frames();
+                }
+            }
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        // Voices keep their place in the recordings, in source frames
+        self.sample_rate = sample_rate;
+        self.fade_step = 1.0 / (VOICE_FADE_SECS * sample_rate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(len: usize, sample_rate: u32) -> Sample {
+        let frames = (0..len)
+            .map(|i| (i as f32 * 220.0 * std::f32::consts::TAU / sample_rate as f32).sin())
+            .collect();
+        Sample::new("sine", frames, sample_rate)
+    }
+
+    #[test]
+    fn test_loop_seam_is_continuous() {
+        let sample = sine(8_000, 8_000);
+        let loop_frames = sample.loop_frames();
+        // Just before the wrap and just after it play the same material
+        let end = sample.frames.len() as f64;
+        let before = sample.read_looped(end - 1.0);
+        let after = sample.read_looped(end - 1.0 - loop_frames);
+        assert!((before - after).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_density_sets_voice_count() {
+        assert_eq!(SampleLayer::active_voices(0.0), 0);
+        assert_eq!(SampleLayer::active_voices(0.5), MAX_SAMPLE_VOICES / 2);
+        assert_eq!(SampleLayer::active_voices(1.0), MAX_SAMPLE_VOICES);
+
+        let mut layer = SampleLayer::new(8_000.0, Arc::from(vec![sine(4_000, 8_000)]));
+        let silent = AudioParams {
+            texture: 0.0,
+            ..AudioParams::default()
+        };
+        let mut block = [0.0; 1_000];
+        layer.process_block(&mut block, &silent);
+        assert!(block.iter().all(|s| *s == 0.0));
+        let dense = AudioParams {
+            texture: TEXTURE_SCALE,
+            ..AudioParams::default()
+        };
+        let mut peak: f32 = 0.0;
+        for _ in 0..40 {
+            layer.process_block(&mut block, &dense);
+            peak = block.iter().fold(peak, |peak, s| peak.max(s.abs()));
+        }
+        assert!(peak > 0.1 && peak.is_finite());
+    }
+
+    #[test]
+    fn test_load_wav_downmixes_to_mono() {
+        let dir = std::env::temp_dir().join(format!("ambient-samples-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rain.wav");
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 8_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..100 {
+            writer.write_sample(i16::MAX / 2).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        std::fs::write(dir.join("notes.txt"), "not audio").unwrap();
+
+        let samples = load_sample_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].name(), "rain");
+        assert_eq!(samples[0].frames.len(), 100);
+        assert!((samples[0].frames[0] - 0.25).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,173 @@
+//! Parameter smoothing set by time constant, so a glide takes as long at
+//! 44.1 kHz as at 48 kHz and whatever the block size.
+//!
+//! A [`Smoother`] is a one-pole lag: after one time constant it has covered
+//! 63% of a step, after three 95%. Layers advance theirs once per block
+//! (control rate); effects that modulate per sample step them per sample.
+//! Which time each parameter gets is a [`SmoothingSettings`]: a `fast` set
+//! for layers that must catch quick changes, like the sparkle impulse, and a
+//! `slow` set for the beds.
+
+/// One-pole lag toward a target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Smoother {
+    value: f32,
+    time_ms: f32,
+    /// Time constants per sample: `1 / (time * sample_rate)`. Infinite for
+    /// a time of zero, which follows the target at once.
+    rate: f32,
+    /// Share of the distance covered per sample.
+    coeff: f32,
+}
+
+impl Smoother {
+    /// Starts at `value`, gliding with time constant `time_ms`.
+    pub fn new(value: f32, time_ms: f32, sample_rate: f32) -> Self {
+        let mut smoother = Self {
+            value,
+            time_ms,
+            rate: f32::INFINITY,
+            coeff: 1.0,
+        };
+        smoother.set_time(time_ms, sample_rate);
+        smoother
+    }
+
+    /// Changes the time constant, keeping the current value.
+    pub fn set_time(&mut self, time_ms: f32, sample_rate: f32) {
+        self.time_ms = time_ms;
+        self.rate = 1000.0 / (time_ms.max(0.0) * sample_rate);
+        self.coeff = Self::share(self.rate);
+    }
+
+    /// Keeps the time constant at a new sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.set_time(self.time_ms, sample_rate);
+    }
+
+    fn share(time_constants: f32) -> f32 {
+        1.0 - (-time_constants).exp()
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Moves as far toward `target` as `frames` samples would, in one step.
+    pub fn advance(&mut self, target: f32, frames: usize) -> f32 {
+        if frames == 0 {
+            return self.value;
+        }
+        self.value += (target - self.value) * Self::share(self.rate * frames as f32);
+        self.value
+    }
+
+    /// Moves one sample toward `target`.
+    pub fn step(&mut self, target: f32) -> f32 {
+        self.value += (target - self.value) * self.coeff;
+        self.value
+    }
+}
+
+/// Time constant per parameter, in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SmoothingTimes {
+    pub master_gain: f32,
+    pub base_freq: f32,
+    pub detune: f32,
+    pub brightness: f32,
+    pub motion: f32,
+    pub texture: f32,
+    pub sparkle: f32,
+}
+
+impl SmoothingTimes {
+    /// Every parameter at `time_ms`.
+    pub fn uniform(time_ms: f32) -> Self {
+        Self {
+            master_gain: time_ms,
+            base_freq: time_ms,
+            detune: time_ms,
+            brightness: time_ms,
+            motion: time_ms,
+            texture: time_ms,
+            sparkle: time_ms,
+        }
+    }
+}
+
+/// Times for the layers that follow quickly and for those that glide.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SmoothingSettings {
+    /// Sparkles, which must catch impulses a block or two long.
+    pub fast: SmoothingTimes,
+    /// Drone, texture and wind.
+    pub slow: SmoothingTimes,
+}
+
+impl SmoothingSettings {
+    /// Both sets with every parameter at `time_ms`.
+    pub fn uniform(time_ms: f32) -> Self {
+        Self {
+            fast: SmoothingTimes::uniform(time_ms),
+            slow: SmoothingTimes::uniform(time_ms),
+        }
+    }
+}
+
+impl Default for SmoothingSettings {
+    fn default() -> Self {
+        Self {
+            fast: SmoothingTimes::uniform(1.0),
+            slow: SmoothingTimes {
+                master_gain: 5.0,
+                base_freq: 5.0,
+                detune: 5.0,
+                brightness: 10.0,
+                motion: 20.0,
+                texture: 10.0,
+                sparkle: 5.0,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glide_time_holds_across_rates_and_blocks() {
+        // One time constant in, 63% of the step is covered at any rate
+        for sample_rate in [22_050.0, 44_100.0, 96_000.0] {
+            let mut smoother = Smoother::new(0.0, 10.0, sample_rate);
+            let frames = (0.01 * sample_rate) as usize;
+            for _ in 0..frames {
+                smoother.step(1.0);
+            }
+            assert!((smoother.value() - 0.632).abs() < 0.01, "{}", sample_rate);
+        }
+
+        // Per-block and per-sample steps agree
+        let mut per_block = Smoother::new(0.0, 50.0, 48_000.0);
+        let mut per_sample = per_block;
+        for _ in 0..10 {
+            per_block.advance(1.0, 256);
+            for _ in 0..256 {
+                per_sample.step(1.0);
+            }
+        }
+        assert!((per_block.value() - per_sample.value()).abs() < 1e-4);
+
+        // A new rate keeps the time
+        let mut moved = Smoother::new(0.0, 10.0, 48_000.0);
+        moved.set_sample_rate(22_050.0);
+        moved.advance(1.0, 220);
+        assert!((moved.value() - 0.632).abs() < 0.01);
+
+        // Zero follows at once
+        let mut instant = Smoother::new(0.0, 0.0, 48_000.0);
+        assert!((instant.step(0.7) - 0.7).abs() < 1e-6);
+        assert!((instant.advance(0.2, 64) - 0.2).abs() < 1e-6);
+    }
+}
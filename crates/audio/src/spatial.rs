@@ -0,0 +1,594 @@
+//! Placing layers on a speaker layout beyond stereo.
+//!
+//! Speakers sit on a horizontal ring, at azimuths in degrees clockwise from
+//! the front (so the right of the room is +90). Each layer has a home
+//! [`Placement`] and is panned between the two speakers either side of it with
+//! constant-power gains, so a source keeps its loudness as it moves. `spread`
+//! blends that pair toward an even share on every speaker, for beds that
+//! should fill the room rather than come from a point.
+//!
+//! With the [`Panner::Ambisonic`] panner, layers are encoded into first-order
+//! B-format instead and decoded to the layout or to headphones once per
+//! block (see [`crate::ambisonics`]). The [`Panner::Binaural`] headphone mode
+//! renders the sparkles and texture through a [`BinauralPanner`] each and
+//! keeps the other layers centred.
+//!
+//! Sources move on slow trajectories driven by world motion: an orbit around
+//! the ring and a sway either side of the orbit, both still when the world is.
+
+use crate::ambisonics::{self, AmbisonicDecoder, BFORMAT_CHANNELS, Decoder};
+use crate::binaural::BinauralPanner;
+use crate::mixer::LayerSlot;
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+/// Most channels a layout may have.
+pub const MAX_SPEAKERS: usize = 32;
+
+/// Sway cycles per second at full motion.
+const SWAY_HZ: f32 = 0.05;
+
+/// Layers the headphone mode places; the rest stay centred.
+const BINAURAL_SLOTS: [LayerSlot; 2] = [LayerSlot::Sparkle, LayerSlot::Texture];
+
+/// Degrees either side of a binaural source its two images sit at full spread.
+const BINAURAL_WIDTH: f32 = 90.0;
+
+/// Output channels and where their speakers stand.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpeakerLayout {
+    /// Azimuth of each channel in degrees, `None` for an LFE channel, which
+    /// is left silent.
+    speakers: Vec<Option<f32>>,
+}
+
+impl SpeakerLayout {
+    /// One channel; the mix is copied to every output channel.
+    pub fn mono() -> Self {
+        Self::custom(vec![0.0])
+    }
+
+    /// Left and right at ±30°.
+    pub fn stereo() -> Self {
+        Self::custom(vec![-30.0, 30.0])
+    }
+
+    /// Front left, front right, rear left, rear right at ±45° and ±135°.
+    pub fn quad() -> Self {
+        Self::custom(vec![-45.0, 45.0, -135.0, 135.0])
+    }
+
+    /// L, R, C, LFE, Ls, Rs, in the usual WAVE channel order.
+    pub fn surround_5_1() -> Self {
+        Self {
+            speakers: vec![
+                Some(-30.0),
+                Some(30.0),
+                Some(0.0),
+                None,
+                Some(-110.0),
+                Some(110.0),
+            ],
+        }
+    }
+
+    /// L, R, C, LFE, rear left, rear right, side left, side right.
+    pub fn surround_7_1() -> Self {
+        Self {
+            speakers: vec![
+                Some(-30.0),
+                Some(30.0),
+                Some(0.0),
+                None,
+                Some(-150.0),
+                Some(150.0),
+                Some(-90.0),
+                Some(90.0),
+            ],
+        }
+    }
+
+    /// `count` speakers evenly spaced clockwise, the first just left of front.
+    pub fn ring(count: usize) -> Self {
+        let step = 360.0 / count.max(1) as f32;
+        Self::custom((0..count).map(|i| step * i as f32 - step / 2.0).collect())
+    }
+
+    /// One channel per azimuth, in degrees clockwise from the front.
+    pub fn custom(azimuths: Vec<f32>) -> Self {
+        Self {
+            speakers: azimuths.into_iter().map(Some).collect(),
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.speakers.len()
+    }
+
+    pub fn speakers(&self) -> &[Option<f32>] {
+        &self.speakers
+    }
+}
+
+impl Default for SpeakerLayout {
+    fn default() -> Self {
+        Self::mono()
+    }
+}
+
+/// Where a layer sits and how it moves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    /// Home azimuth in degrees clockwise from the front.
+    pub azimuth: f32,
+    /// 0 for a point between two speakers, 1 for an even share on all of them.
+    pub spread: f32,
+    /// Degrees per second around the ring at full motion; negative turns
+    /// counter-clockwise.
+    pub orbit: f32,
+    /// Degrees either side of the orbit the source swings at full motion.
+    pub sway: f32,
+}
+
+impl Placement {
+    pub const fn fixed(azimuth: f32, spread: f32) -> Self {
+        Self {
+            azimuth,
+            spread,
+            orbit: 0.0,
+            sway: 0.0,
+        }
+    }
+}
+
+/// Placement of each layer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayerPlacements {
+    pub drone: Placement,
+    pub texture: Placement,
+    pub sparkle: Placement,
+    pub samples: Placement,
+    pub grains: Placement,
+    pub wind: Placement,
+}
+
+impl LayerPlacements {
+    pub fn get(&self, slot: LayerSlot) -> Placement {
+        match slot {
+            LayerSlot::Drone => self.drone,
+            LayerSlot::Texture => self.texture,
+            LayerSlot::Sparkle => self.sparkle,
+            LayerSlot::Samples => self.samples,
+            LayerSlot::Grains => self.grains,
+            LayerSlot::Wind => self.wind,
+        }
+    }
+}
+
+impl Default for LayerPlacements {
+    /// The drone and beds fill the room, the wind and grains drift slowly
+    /// around it and the sparkles circle fastest.
+    fn default() -> Self {
+        Self {
+            drone: Placement::fixed(0.0, 0.6),
+            texture: Placement::fixed(180.0, 0.8),
+            sparkle: Placement {
+                azimuth: 0.0,
+                spread: 0.1,
+                orbit: 24.0,
+                sway: 30.0,
+            },
+            samples: Placement::fixed(0.0, 0.7),
+            grains: Placement {
+                azimuth: -60.0,
+                spread: 0.3,
+                orbit: -8.0,
+                sway: 20.0,
+            },
+            wind: Placement {
+                azimuth: 90.0,
+                spread: 0.4,
+                orbit: 6.0,
+                sway: 45.0,
+            },
+        }
+    }
+}
+
+/// How layers are placed on the layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Panner {
+    /// Between the two nearest speakers.
+    #[default]
+    Pairwise,
+    /// Through first-order B-format and a decoder.
+    Ambisonic(AmbisonicDecoder),
+    /// Headphone stereo: the sparkles and texture through an ITD/ILD head
+    /// model, the other layers centred. The layout is ignored.
+    Binaural,
+}
+
+/// Layout and placements the mixer spatializes with.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpatialSettings {
+    pub layout: SpeakerLayout,
+    pub placements: LayerPlacements,
+    pub panner: Panner,
+}
+
+impl SpatialSettings {
+    /// Channels the mixer renders: the layout's, or two for binaural decoding.
+    pub fn channels(&self) -> usize {
+        match self.panner {
+            Panner::Ambisonic(AmbisonicDecoder::Binaural) | Panner::Binaural => 2,
+            _ => self.layout.channels(),
+        }
+    }
+}
+
+/// A layer's position on its trajectory and the gains it was last panned with.
+#[derive(Default)]
+struct Source {
+    /// Degrees travelled around the ring.
+    orbit: f32,
+    /// Sway phase in radians.
+    sway_phase: f32,
+    /// Per-channel gains at the end of the last block, where ramps start.
+    gains: Vec<f32>,
+    target: Vec<f32>,
+}
+
+/// Where sources are panned to.
+enum Field {
+    /// Straight onto the speakers.
+    Speakers {
+        /// Directional channels sorted by azimuth (0..360), for finding the
+        /// pair either side of a source.
+        ring: Vec<(usize, f32)>,
+        channels: usize,
+    },
+    /// Into one block of B-format, decoded once every layer is in.
+    Ambisonic {
+        bformat: Vec<Vec<f32>>,
+        decoder: Decoder,
+    },
+    /// Onto two ears. Layers in [`BINAURAL_SLOTS`] have a pair of panners,
+    /// indexed by [`LayerSlot::index`], spread either side of the source.
+    Binaural {
+        images: Vec<Option<[BinauralPanner; 2]>>,
+    },
+}
+
+/// Pans mono layer blocks onto the channels of a [`SpeakerLayout`].
+/// Allocates on construction only, so it is safe in the audio callback.
+pub struct Spatializer {
+    sample_rate: f32,
+    field: Field,
+    placements: LayerPlacements,
+    /// Indexed by [`LayerSlot::index`].
+    sources: Vec<Source>,
+}
+
+/// Constant-power gains between the two speakers of `ring` either side of
+/// `azimuth`, blended toward an even share by `spread`.
+fn pair_gains(ring: &[(usize, f32)], azimuth: f32, spread: f32, gains: &mut [f32]) {
+    gains.fill(0.0);
+    let count = ring.len();
+    match count {
+        0 => return,
+        1 => gains[ring[0].0] = 1.0,
+        _ => {
+            let azimuth = azimuth.rem_euclid(360.0);
+            let next = ring.iter().position(|(_, a)| *a >= azimuth).unwrap_or(0);
+            let (prev_channel, prev_azimuth) = ring[(next + count - 1) % count];
+            let (next_channel, next_azimuth) = ring[next];
+            let arc = (next_azimuth - prev_azimuth).rem_euclid(360.0);
+            let t = if arc > 0.0 {
+                (azimuth - prev_azimuth).rem_euclid(360.0) / arc
+            } else {
+                0.0
+            };
+            gains[prev_channel] = (t * FRAC_PI_2).cos();
+            gains[next_channel] += (t * FRAC_PI_2).sin();
+        }
+    }
+    // Blend power toward an even share, keeping the total at 1
+    let spread = spread.clamp(0.0, 1.0);
+    let even = spread / count as f32;
+    for (channel, _) in ring {
+        let gain = &mut gains[*channel];
+        *gain = ((1.0 - spread) * *gain * *gain + even).sqrt();
+    }
+}
+
+impl Spatializer {
+    /// `max_block` is the longest block [`Spatializer::pan`] will be given.
+    pub fn new(sample_rate: f32, settings: &SpatialSettings, max_block: usize) -> Self {
+        let field = match settings.panner {
+            Panner::Pairwise => {
+                let mut ring: Vec<(usize, f32)> = settings
+                    .layout
+                    .speakers()
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(channel, azimuth)| {
+                        azimuth.map(|a| (channel, a.rem_euclid(360.0)))
+                    })
+                    .collect();
+                ring.sort_by(|a, b| a.1.total_cmp(&b.1));
+                Field::Speakers {
+                    ring,
+                    channels: settings.layout.channels(),
+                }
+            }
+            Panner::Ambisonic(decoder) => Field::Ambisonic {
+                bformat: vec![vec![0.0; max_block]; BFORMAT_CHANNELS],
+                decoder: Decoder::new(sample_rate, decoder, &settings.layout, max_block),
+            },
+            Panner::Binaural => Field::Binaural {
+                images: LayerSlot::ALL
+                    .iter()
+                    .map(|slot| {
+                        let azimuth = settings.placements.get(*slot).azimuth;
+                        // Each image carries half, so coincident images sum to unity
+                        BINAURAL_SLOTS.contains(slot).then(|| {
+                            std::array::from_fn(|_| {
+                                let mut panner = BinauralPanner::new(sample_rate, azimuth);
+                                panner.set_gain(0.5);
+                                panner
+                            })
+                        })
+                    })
+                    .collect(),
+            },
+        };
+        let width = match &field {
+            Field::Speakers { channels, .. } => *channels,
+            Field::Ambisonic { .. } => BFORMAT_CHANNELS,
+            Field::Binaural { .. } => 2,
+        };
+        let mut spatializer = Self {
+            sample_rate,
+            field,
+            placements: settings.placements,
+            sources: Vec::new(),
+        };
+        spatializer.sources = LayerSlot::ALL
+            .iter()
+            .map(|slot| {
+                let placement = settings.placements.get(*slot);
+                let mut gains = vec![0.0; width];
+                spatializer.pan_gains(placement.azimuth, placement.spread, &mut gains);
+                Source {
+                    orbit: 0.0,
+                    sway_phase: 0.0,
+                    target: gains.clone(),
+                    gains,
+                }
+            })
+            .collect();
+        spatializer
+    }
+
+    /// Output channels: the layout's, or two for binaural decoding.
+    pub fn channels(&self) -> usize {
+        match &self.field {
+            Field::Speakers { channels, .. } => *channels,
+            Field::Ambisonic { decoder, .. } => decoder.channels(),
+            Field::Binaural { .. } => 2,
+        }
+    }
+
+    /// Gains for a source at `azimuth` with `spread`: one per layout channel,
+    /// or one per B-format channel with the ambisonic panner. Centred, at
+    /// full level in each ear, in the headphone mode.
+    pub fn pan_gains(&self, azimuth: f32, spread: f32, gains: &mut [f32]) {
+        match &self.field {
+            Field::Speakers { ring, .. } => pair_gains(ring, azimuth, spread, gains),
+            Field::Ambisonic { .. } => ambisonics::encode(azimuth, spread, gains),
+            Field::Binaural { .. } => gains.fill(1.0),
+        }
+    }
+
+    /// Moves `slot` along its trajectory by one block and adds `block`, panned
+    /// with gains ramped from the last block, into `channels` (one buffer per
+    /// output channel), or into B-format for [`Spatializer::finish`].
+    pub fn pan(&mut self, slot: LayerSlot, block: &[f32], channels: &mut [Vec<f32>], motion: f32) {
+        let placement = self.placements.get(slot);
+        let secs = block.len() as f32 / self.sample_rate;
+        let mut source = std::mem::take(&mut self.sources[slot.index()]);
+        let motion = motion.clamp(0.0, 1.0);
+        source.orbit = (source.orbit + placement.orbit * motion * secs).rem_euclid(360.0);
+        source.sway_phase = (source.sway_phase + TAU * SWAY_HZ * motion * secs).rem_euclid(TAU);
+        let azimuth = placement.azimuth + source.orbit + placement.sway * source.sway_phase.sin();
+        self.pan_gains(azimuth, placement.spread, &mut source.target);
+
+        if let (Field::Binaural { images }, [left, right]) = (&mut self.field, &mut *channels)
+            && let Some(images) = &mut images[slot.index()]
+        {
+            // Two images either side of the source, as wide as its spread
+            let width = placement.spread.clamp(0.0, 1.0) * BINAURAL_WIDTH;
+            for (image, side) in images.iter_mut().zip([-1.0, 1.0]) {
+                image.set_azimuth(azimuth + side * width);
+                image.process(block, left, right);
+            }
+            self.sources[slot.index()] = source;
+            return;
+        }
+
+        let outputs = match &mut self.field {
+            Field::Speakers { .. } | Field::Binaural { .. } => channels,
+            Field::Ambisonic { bformat, .. } => bformat.as_mut_slice(),
+        };
+        let frames = block.len() as f32;
+        for ((out, from), to) in outputs.iter_mut().zip(&source.gains).zip(&source.target) {
+            if *from == 0.0 && *to == 0.0 {
+                continue;
+            }
+            let step = (to - from) / frames;
+            for (i, (out, sample)) in out.iter_mut().zip(block).enumerate() {
+                *out += sample * (from + step * (i + 1) as f32);
+            }
+        }
+        source.gains.copy_from_slice(&source.target);
+        self.sources[slot.index()] = source;
+    }
+
+    /// Completes a block of `frames` once every layer has been panned:
+    /// decodes the B-format into `channels`. Nothing to do for the pairwise
+    /// panner, which writes to `channels` directly.
+    pub fn finish(&mut self, channels: &mut [Vec<f32>], frames: usize) {
+        if let Field::Ambisonic { bformat, decoder } = &mut self.field {
+            decoder.decode(bformat, channels, frames);
+            for channel in bformat {
+                channel[..frames].fill(0.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn power(gains: &[f32]) -> f32 {
+        gains.iter().map(|g| g * g).sum()
+    }
+
+    fn spatializer(layout: SpeakerLayout) -> Spatializer {
+        Spatializer::new(
+            48_000.0,
+            &SpatialSettings {
+                layout,
+                ..Default::default()
+            },
+            4_800,
+        )
+    }
+
+    #[test]
+    fn test_pan_keeps_power_around_the_ring() {
+        for layout in [
+            SpeakerLayout::stereo(),
+            SpeakerLayout::quad(),
+            SpeakerLayout::surround_5_1(),
+            SpeakerLayout::ring(7),
+        ] {
+            let spatializer = spatializer(layout.clone());
+            let mut gains = vec![0.0; layout.channels()];
+            for azimuth in (-360..=360).step_by(5) {
+                for spread in [0.0, 0.5, 1.0] {
+                    spatializer.pan_gains(azimuth as f32, spread, &mut gains);
+                    assert!(
+                        (power(&gains) - 1.0).abs() < 1e-4,
+                        "{:?} at {}",
+                        layout,
+                        azimuth
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pan_lands_on_speakers() {
+        let spatializer = spatializer(SpeakerLayout::surround_5_1());
+        let mut gains = vec![0.0; 6];
+        spatializer.pan_gains(110.0, 0.0, &mut gains);
+        assert!((gains[5] - 1.0).abs() < 1e-6);
+        spatializer.pan_gains(15.0, 0.0, &mut gains);
+        assert!((gains[1] - gains[2]).abs() < 1e-6 && gains[1] > 0.7);
+        // The LFE channel never gets a directional source
+        spatializer.pan_gains(15.0, 1.0, &mut gains);
+        assert_eq!(gains[3], 0.0);
+        assert!(gains.iter().enumerate().all(|(i, g)| i == 3 || *g > 0.0));
+    }
+
+    #[test]
+    fn test_trajectory_follows_motion() {
+        let mut spatializer = spatializer(SpeakerLayout::quad());
+        let block = vec![1.0; 4_800];
+        let mut channels = vec![vec![0.0; 4_800]; 4];
+        // At rest the sparkles stay at their home between the front speakers
+        for _ in 0..50 {
+            spatializer.pan(LayerSlot::Sparkle, &block, &mut channels, 0.0);
+        }
+        let home = spatializer.sources[LayerSlot::Sparkle.index()]
+            .gains
+            .clone();
+        assert!((home[0] - home[1]).abs() < 1e-6);
+
+        // With motion they move off it, and stay at unit power
+        spatializer.pan(LayerSlot::Sparkle, &block, &mut channels, 1.0);
+        let moved = &spatializer.sources[LayerSlot::Sparkle.index()].gains;
+        assert!((moved[0] - home[0]).abs() > 1e-3);
+        assert!((power(moved) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_mono_layout_passes_every_layer_through() {
+        let mut spatializer = spatializer(SpeakerLayout::mono());
+        let block = vec![0.5; 64];
+        let mut channels = vec![vec![0.0; 64]];
+        for slot in LayerSlot::ALL {
+            spatializer.pan(slot, &block, &mut channels, 1.0);
+        }
+        assert!(channels[0].iter().all(|s| (s - 3.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_ambisonic_panner_decodes_to_the_layout() {
+        let mut spatializer = Spatializer::new(
+            48_000.0,
+            &SpatialSettings {
+                layout: SpeakerLayout::quad(),
+                placements: LayerPlacements::default(),
+                panner: Panner::Ambisonic(AmbisonicDecoder::Speakers),
+            },
+            64,
+        );
+        assert_eq!(spatializer.channels(), 4);
+        let block = vec![1.0; 64];
+        let mut channels = vec![vec![0.0; 64]; 4];
+        // Grains sit front left, so the front left speaker carries the most
+        spatializer.pan(LayerSlot::Grains, &block, &mut channels, 0.0);
+        spatializer.finish(&mut channels, 64);
+        let level = |c: usize| channels[c][63];
+        assert!(level(0) > level(1) && level(0) > level(2) && level(0) > level(3));
+        let power: f32 = (0..4).map(|c| level(c) * level(c)).sum();
+        assert!((power - 1.0).abs() < 1e-3);
+
+        // B-format is cleared for the next block
+        let mut silent = vec![vec![0.0; 64]; 4];
+        spatializer.finish(&mut silent, 64);
+        assert!(silent.iter().flatten().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn test_headphone_mode_places_sparkles_and_centres_the_rest() {
+        let placements = LayerPlacements {
+            sparkle: Placement::fixed(90.0, 0.0),
+            ..Default::default()
+        };
+        let mut spatializer = Spatializer::new(
+            48_000.0,
+            &SpatialSettings {
+                layout: SpeakerLayout::quad(),
+                placements,
+                panner: Panner::Binaural,
+            },
+            256,
+        );
+        assert_eq!(spatializer.channels(), 2);
+        let noise: Vec<f32> = (0..256)
+            .map(|i| ((i * 7919) % 113) as f32 / 56.0 - 1.0)
+            .collect();
+        let mut ears = vec![vec![0.0; 256]; 2];
+        spatializer.pan(LayerSlot::Sparkle, &noise, &mut ears, 1.0);
+        assert!(power(&ears[1]) > 4.0 * power(&ears[0]));
+
+        let mut ears = vec![vec![0.0; 256]; 2];
+        spatializer.pan(LayerSlot::Drone, &noise, &mut ears, 1.0);
+        assert_eq!(ears[0], noise);
+        assert_eq!(ears[1], noise);
+    }
+}
@@ -0,0 +1,162 @@
+//! Bounded single-producer, single-consumer queue, for handing owned values
+//! to and from the audio thread without either side waiting on the other.
+//!
+//! Lock-free: a ring of slots and two counters. The producer only writes
+//! slots the consumer has released and the consumer only reads slots the
+//! producer has published, each side learning which through the other's
+//! counter, so neither end ever blocks. Neither end allocates after
+//! [`channel`] returns.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Ring<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// Values popped so far, written only by the consumer.
+    head: AtomicUsize,
+    /// Values pushed so far, written only by the producer.
+    tail: AtomicUsize,
+}
+
+// SAFETY: a slot is only touched by one end at a time. The producer writes
+// slots in `tail..head + len`, the consumer reads those in `head..tail`,
+// and each moves its counter with release ordering only once it is done
+// with the slot, which the other end loads with acquire ordering before
+// touching it. Values cross threads, so `T` must be `Send`.
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        let (head, tail) = (*self.head.get_mut(), *self.tail.get_mut());
+        let len = self.slots.len();
+        for count in head..tail {
+            // SAFETY: slots between head and tail hold pushed values not
+            // yet popped, and both ends are gone
+            unsafe { self.slots[count % len].get_mut().assume_init_drop() };
+        }
+    }
+}
+
+/// The sending end.
+pub struct Producer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+/// The receiving end.
+pub struct Consumer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+/// A queue holding up to `capacity` values (at least one).
+pub fn channel<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let ring = Arc::new(Ring {
+        slots: (0..capacity.max(1))
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (
+        Producer {
+            ring: Arc::clone(&ring),
+        },
+        Consumer { ring },
+    )
+}
+
+impl<T> Producer<T> {
+    /// Values that can be pushed before the queue is full. Only grows until
+    /// the next push, as the consumer pops.
+    pub fn space(&self) -> usize {
+        let ring = &*self.ring;
+        ring.slots.len() - (ring.tail.load(Ordering::Relaxed) - ring.head.load(Ordering::Acquire))
+    }
+
+    /// Queues `value`, or hands it back if the queue is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let ring = &*self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        if tail - ring.head.load(Ordering::Acquire) == ring.slots.len() {
+            return Err(value);
+        }
+        // SAFETY: the slot is free (the consumer has moved past it) and
+        // this is the only producer
+        unsafe { (*ring.slots[tail % ring.slots.len()].get()).write(value) };
+        // Publish the value before the count that makes it visible
+        ring.tail.store(tail + 1, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T> Consumer<T> {
+    /// The oldest queued value, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        let ring = &*self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        if head == ring.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        // SAFETY: the producer published this slot before moving the tail
+        // past it, and this is the only consumer
+        let value = unsafe { (*ring.slots[head % ring.slots.len()].get()).assume_init_read() };
+        // Free the slot only once it is empty
+        ring.head.store(head + 1, Ordering::Release);
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_arrive_in_order_and_a_full_queue_refuses() {
+        let (mut producer, mut consumer) = channel(2);
+        assert_eq!(consumer.pop(), None::<u32>);
+        producer.push(1).unwrap();
+        producer.push(2).unwrap();
+        assert_eq!(producer.space(), 0);
+        assert_eq!(producer.push(3), Err(3));
+        assert_eq!(consumer.pop(), Some(1));
+        producer.push(3).unwrap();
+        assert_eq!(
+            (consumer.pop(), consumer.pop(), consumer.pop()),
+            (Some(2), Some(3), None)
+        );
+
+        // Across threads, nothing is lost or reordered
+        let sender = std::thread::spawn(move || {
+            for n in 0..10_000 {
+                let mut value = n;
+                while let Err(back) = producer.push(value) {
+                    value = back;
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < 10_000 {
+            if let Some(n) = consumer.pop() {
+                assert_eq!(n, expected);
+                expected += 1;
+            }
+        }
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn test_values_left_queued_are_dropped_with_the_queue() {
+        let value = Arc::new(());
+        let (mut producer, mut consumer) = channel(3);
+        for _ in 0..3 {
+            producer.push(Arc::clone(&value)).unwrap();
+        }
+        drop(consumer.pop());
+        assert_eq!(Arc::strong_count(&value), 3);
+        drop((producer, consumer));
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
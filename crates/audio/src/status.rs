@@ -0,0 +1,72 @@
+//! Health of the audio output, as reported by the watchdog.
+
+use std::sync::Mutex;
+
+/// What the output engine is doing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngineState {
+    /// Audio output is turned off or compiled out.
+    Disabled,
+    /// Opening the device for the first time.
+    Starting,
+    /// The stream is playing.
+    Running,
+    /// The stream failed or could not be opened; a rebuild is pending.
+    Restarting,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioStatus {
+    pub state: EngineState,
+    /// Output device in use while running.
+    pub device: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    /// Times the engine has been rebuilt after a failure.
+    pub restarts: u32,
+    /// Most recent reason the engine stopped or failed to start.
+    pub last_error: Option<String>,
+}
+
+impl AudioStatus {
+    pub fn new(state: EngineState) -> Self {
+        Self {
+            state,
+            device: None,
+            sample_rate: None,
+            channels: None,
+            restarts: 0,
+            last_error: None,
+        }
+    }
+}
+
+/// Status written by the watchdog thread and read by the API.
+///
+/// Never touched from the audio callback, so a mutex is fine here.
+#[derive(Debug)]
+pub struct SharedAudioStatus {
+    status: Mutex<AudioStatus>,
+}
+
+impl SharedAudioStatus {
+    pub fn new(initial: AudioStatus) -> Self {
+        Self {
+            status: Mutex::new(initial),
+        }
+    }
+
+    pub fn get(&self) -> AudioStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut AudioStatus)) {
+        f(&mut self
+            .status
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()));
+    }
+}
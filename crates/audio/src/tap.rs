@@ -0,0 +1,188 @@
+//! A copy of the master output for consumers off the audio thread, such as
+//! the stream encoder and the recorder, each with a tap of its own.
+//!
+//! The [`Renderer`](crate::backend::Renderer) offers every mixed block to
+//! each [`OutputTap`]. While nobody listens that costs one atomic load. While
+//! someone does, the block joins a bounded queue behind a lock the renderer
+//! only ever tries: if the consumer holds it, the consumer loses that block,
+//! and the callback never waits. Nor does it allocate: the queue's room is
+//! reserved off the audio thread, when listening starts and on every take,
+//! and output past it is dropped until then, as after a change of format.
+
+use crate::backend::OutputFormat;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// What the tap holds between takes.
+#[derive(Debug, Default)]
+struct Tapped {
+    format: OutputFormat,
+    samples: VecDeque<f32>,
+}
+
+impl Tapped {
+    /// Samples `secs` of the current format take.
+    fn len_for(&self, secs: f32) -> usize {
+        (self.format.sample_rate as f32 * secs) as usize * self.format.channels as usize
+    }
+
+    /// Makes room for `secs` of the current format. Never called on the
+    /// audio thread.
+    fn reserve(&mut self, secs: f32) {
+        let len = self.len_for(secs);
+        self.samples
+            .reserve_exact(len.saturating_sub(self.samples.len()));
+    }
+}
+
+#[derive(Debug)]
+pub struct OutputTap {
+    listening: AtomicBool,
+    /// Seconds of output kept before the oldest is dropped.
+    secs: f32,
+    tapped: Mutex<Tapped>,
+    dropped: AtomicU64,
+}
+
+impl OutputTap {
+    /// A tap keeping up to `secs` seconds of output, off until
+    /// [`OutputTap::set_listening`].
+    pub fn new(secs: f32) -> Self {
+        Self {
+            listening: AtomicBool::new(false),
+            secs: secs.max(0.0),
+            tapped: Mutex::new(Tapped::default()),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Turns copying on or off. Turning it on reserves the queue for the
+    /// last format seen (the default before any); turning it off empties it.
+    pub fn set_listening(&self, listening: bool) {
+        let mut tapped = self.tapped.lock().unwrap();
+        if listening {
+            tapped.reserve(self.secs);
+        }
+        let was = self.listening.swap(listening, Ordering::Relaxed);
+        if was && !listening {
+            tapped.samples.clear();
+        }
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.listening.load(Ordering::Relaxed)
+    }
+
+    /// Samples lost to a full queue, a busy lock or room not yet reserved.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Queues interleaved `block` of `format`, dropping the oldest output
+    /// past the tap's length. A change of format drops what was queued, and
+    /// output of it beyond the room already reserved until the next take.
+    pub fn push(&self, block: &[f32], format: OutputFormat) {
+        if !self.is_listening() {
+            return;
+        }
+        let Ok(mut tapped) = self.tapped.try_lock() else {
+            self.dropped
+                .fetch_add(block.len() as u64, Ordering::Relaxed);
+            return;
+        };
+        if tapped.format != format {
+            tapped.format = format;
+            tapped.samples.clear();
+        }
+        // Never past the reserved room, so the callback never allocates
+        let capacity = tapped.len_for(self.secs).min(tapped.samples.capacity());
+        let over = (tapped.samples.len() + block.len()).saturating_sub(capacity);
+        if over > 0 {
+            let over = over.min(tapped.samples.len());
+            tapped.samples.drain(..over);
+            self.dropped.fetch_add(over as u64, Ordering::Relaxed);
+        }
+        let room = capacity.saturating_sub(tapped.samples.len());
+        let kept = block.len().min(room);
+        tapped.samples.extend(&block[..kept]);
+        if kept < block.len() {
+            self.dropped
+                .fetch_add((block.len() - kept) as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Everything queued and its format, or None if nothing is. Reserves
+    /// the queue for the format last pushed.
+    pub fn take(&self) -> Option<(OutputFormat, Vec<f32>)> {
+        let mut tapped = self.tapped.lock().unwrap();
+        tapped.reserve(self.secs);
+        if tapped.samples.is_empty() {
+            return None;
+        }
+        let samples = tapped.samples.drain(..).collect();
+        Some((tapped.format, samples))
+    }
+}
+
+impl Default for OutputTap {
+    fn default() -> Self {
+        Self::new(2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tap_copies_only_while_listening_and_keeps_the_newest() {
+        let format = OutputFormat {
+            sample_rate: 100,
+            channels: 2,
+        };
+        let tap = OutputTap::new(1.0);
+        tap.push(&[0.5; 20], format);
+        assert!(tap.take().is_none());
+
+        tap.set_listening(true);
+        tap.push(&[0.1; 150], format);
+        tap.push(&[0.2; 100], format);
+        // Room for 200 samples; the oldest 50 went
+        let (taken_format, samples) = tap.take().unwrap();
+        assert_eq!(taken_format, format);
+        assert_eq!(samples.len(), 200);
+        assert_eq!(samples[99], 0.1);
+        assert_eq!(samples[100], 0.2);
+        assert_eq!(tap.dropped(), 50);
+        assert!(tap.take().is_none());
+
+        tap.push(&[0.3; 10], format);
+        tap.set_listening(false);
+        assert!(tap.take().is_none());
+    }
+
+    #[test]
+    fn test_a_larger_format_waits_for_room_reserved_by_a_take() {
+        // Listening reserves a millisecond of the default format
+        let tap = OutputTap::new(0.001);
+        tap.set_listening(true);
+        let reserved = tap.tapped.lock().unwrap().samples.capacity();
+        assert!((96..200).contains(&reserved), "reserved {}", reserved);
+        let large = OutputFormat {
+            sample_rate: 200_000,
+            channels: 1,
+        };
+
+        // The callback keeps to the room it has
+        tap.push(&[0.2; 200], large);
+        let (format, samples) = tap.take().unwrap();
+        assert_eq!(format, large);
+        assert_eq!(samples.len(), reserved);
+        assert_eq!(tap.dropped(), (200 - reserved) as u64);
+
+        // and the take made more
+        tap.push(&[0.3; 200], large);
+        assert_eq!(tap.take().unwrap().1.len(), 200);
+    }
+}
@@ -0,0 +1,146 @@
+//! Audio callback load and xrun counts, so a chain that is about to glitch
+//! shows up before it does.
+//!
+//! Each callback records how much of its deadline (the time its buffer takes
+//! to play) it spent rendering into a fixed ring of atomics. Readers summarize
+//! the most recent entries whenever they like; the audio thread never blocks
+//! and never allocates.
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Callbacks kept in the ring, about 5 s of 512-frame buffers at 48 kHz.
+pub const RING_LEN: usize = 512;
+
+/// Fraction of the deadline `elapsed` used for a buffer of `frames` frames.
+pub fn callback_load(elapsed: Duration, frames: usize, sample_rate: u32) -> f32 {
+    if frames == 0 || sample_rate == 0 {
+        return 0.0;
+    }
+    let deadline_secs = frames as f64 / sample_rate as f64;
+    (elapsed.as_secs_f64() / deadline_secs) as f32
+}
+
+/// Summary of the recent callbacks.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CallbackStats {
+    /// Callbacks recorded since startup.
+    pub callbacks: u64,
+    /// Callbacks the summary covers, up to `RING_LEN`.
+    pub window: usize,
+    /// Deadline fraction of the latest callback, and the mean and peak over the window.
+    pub load_last: f32,
+    pub load_mean: f32,
+    pub load_peak: f32,
+    /// Callbacks that took longer than their deadline, since startup.
+    pub deadline_misses: u64,
+    /// Underruns reported by the output backend, since startup.
+    pub xruns: u64,
+}
+
+/// Ring of recent callback loads, written by the audio callback.
+///
+/// There is one writer (the callback, which cpal never runs concurrently with
+/// itself); any number of readers. Shared across engine rebuilds, so the
+/// counters cover the whole run.
+#[derive(Debug)]
+pub struct CallbackTelemetry {
+    /// f32 bits of each load, indexed by callback number modulo `RING_LEN`.
+    loads: [AtomicU32; RING_LEN],
+    written: AtomicU64,
+    deadline_misses: AtomicU64,
+    xruns: AtomicU64,
+}
+
+impl Default for CallbackTelemetry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CallbackTelemetry {
+    pub fn new() -> Self {
+        Self {
+            loads: std::array::from_fn(|_| AtomicU32::new(0)),
+            written: AtomicU64::new(0),
+            deadline_misses: AtomicU64::new(0),
+            xruns: AtomicU64::new(0),
+        }
+    }
+
+    /// Records one callback's deadline fraction.
+    pub fn record(&self, load: f32) {
+        let index = self.written.load(Ordering::Relaxed);
+        self.loads[index as usize % RING_LEN].store(load.to_bits(), Ordering::Relaxed);
+        // Publish the slot before the count that makes it visible
+        self.written.store(index + 1, Ordering::Release);
+        if load > 1.0 {
+            self.deadline_misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts an underrun reported by the backend.
+    pub fn record_xrun(&self) {
+        self.xruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> CallbackStats {
+        let written = self.written.load(Ordering::Acquire);
+        let window = (written as usize).min(RING_LEN);
+        let load =
+            |n: u64| f32::from_bits(self.loads[n as usize % RING_LEN].load(Ordering::Relaxed));
+        let (mut sum, mut peak) = (0.0, 0.0_f32);
+        for n in written - window as u64..written {
+            let value = load(n);
+            sum += value;
+            peak = peak.max(value);
+        }
+        CallbackStats {
+            callbacks: written,
+            window,
+            load_last: if written > 0 { load(written - 1) } else { 0.0 },
+            load_mean: if window > 0 { sum / window as f32 } else { 0.0 },
+            load_peak: peak,
+            deadline_misses: self.deadline_misses.load(Ordering::Relaxed),
+            xruns: self.xruns.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_callback_load() {
+        // 512 frames at 48 kHz is a 10.67 ms deadline
+        let load = callback_load(Duration::from_micros(5_333), 512, 48_000);
+        assert!((load - 0.5).abs() < 1e-3);
+        assert_eq!(callback_load(Duration::from_millis(1), 0, 48_000), 0.0);
+    }
+
+    #[test]
+    fn test_stats_cover_the_latest_window() {
+        let telemetry = CallbackTelemetry::new();
+        assert_eq!(telemetry.stats(), CallbackStats::default());
+
+        for _ in 0..RING_LEN {
+            telemetry.record(2.0);
+        }
+        for _ in 0..RING_LEN - 1 {
+            telemetry.record(0.25);
+        }
+        telemetry.record(0.5);
+        telemetry.record_xrun();
+
+        let stats = telemetry.stats();
+        assert_eq!(stats.callbacks, 2 * RING_LEN as u64);
+        assert_eq!(stats.window, RING_LEN);
+        assert_eq!(stats.load_last, 0.5);
+        // The overloaded callbacks have rolled out of the window but still count as misses
+        assert_eq!(stats.load_peak, 0.5);
+        assert!((stats.load_mean - 0.25).abs() < 1e-3);
+        assert_eq!(stats.deadline_misses, RING_LEN as u64);
+        assert_eq!(stats.xruns, 1);
+    }
+}
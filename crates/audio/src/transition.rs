@@ -0,0 +1,272 @@
+//! Crossfades between audio parameter sets.
+//!
+//! Every parameter change glides briefly to avoid zipper noise; a scene change
+//! starts a longer crossfade, shaped by the scene's curve, from the outgoing
+//! to the incoming set.
+
+use crate::params::AudioParams;
+use ambient_core::curves::Curve;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Glide applied to ordinary parameter updates between ticks.
+pub const DEFAULT_GLIDE_SECS: f32 = 0.05;
+
+/// Points in a [`CurveTable`], including both ends.
+const CURVE_TABLE_POINTS: usize = 33;
+
+/// A [`Curve`] sampled into a fixed table so the audio callback can evaluate
+/// it without allocating.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CurveTable([f32; CURVE_TABLE_POINTS]);
+
+impl CurveTable {
+    pub fn from_curve(curve: &Curve) -> Self {
+        let last = (CURVE_TABLE_POINTS - 1) as f64;
+        Self(std::array::from_fn(|i| curve.apply(i as f64 / last) as f32))
+    }
+
+    pub fn linear() -> Self {
+        Self::from_curve(&Curve::Linear)
+    }
+
+    /// Maps progress `t` in [0, 1] to a blend amount, interpolating between points.
+    pub fn apply(&self, t: f32) -> f32 {
+        let position = t.clamp(0.0, 1.0) * (CURVE_TABLE_POINTS - 1) as f32;
+        let index = (position as usize).min(CURVE_TABLE_POINTS - 2);
+        let frac = position - index as f32;
+        self.0[index] + (self.0[index + 1] - self.0[index]) * frac
+    }
+}
+
+/// Blends two parameter sets by `amount` (0 = `from`, 1 = `to`).
+///
+/// Frequency is interpolated geometrically so pitch moves evenly. The sparkle
+/// impulse is taken from `to` as is, since blending would smear its triggers,
+/// and so is the pulse tempo, which the delay glides to by itself.
+pub fn interpolate(from: &AudioParams, to: &AudioParams, amount: f32) -> AudioParams {
+    let lerp = |a: f32, b: f32| a + (b - a) * amount;
+    let base_freq_hz = if from.base_freq_hz > 0.0 && to.base_freq_hz > 0.0 {
+        from.base_freq_hz * (to.base_freq_hz / from.base_freq_hz).powf(amount)
+    } else {
+        lerp(from.base_freq_hz, to.base_freq_hz)
+    };
+    AudioParams {
+        master_gain: lerp(from.master_gain, to.master_gain),
+        base_freq_hz,
+        detune_ratio: lerp(from.detune_ratio, to.detune_ratio),
+        brightness: lerp(from.brightness, to.brightness),
+        motion: lerp(from.motion, to.motion),
+        texture: lerp(from.texture, to.texture),
+        reverb_size: lerp(from.reverb_size, to.reverb_size),
+        pre_delay_ms: lerp(from.pre_delay_ms, to.pre_delay_ms),
+        grit: lerp(from.grit, to.grit),
+        sparkle_impulse: to.sparkle_impulse,
+        pulse_bpm: to.pulse_bpm,
+        layers: from.layers.lerp(&to.layers, amount),
+    }
+}
+
+/// Per-sample parameter source that moves between targets over time.
+pub struct TransitionEngine {
+    sample_rate: f32,
+    current: AudioParams,
+    from: AudioParams,
+    to: AudioParams,
+    position: u32,
+    length: u32,
+    curve: CurveTable,
+    /// True while a scene crossfade (rather than a glide) is running.
+    crossfading: bool,
+    /// False until the first target arrives, which is applied directly.
+    started: bool,
+}
+
+impl TransitionEngine {
+    pub fn new(sample_rate: f32) -> Self {
+        let params = AudioParams::default();
+        Self {
+            sample_rate,
+            current: params,
+            from: params,
+            to: params,
+            position: 0,
+            length: 0,
+            curve: CurveTable::linear(),
+            crossfading: false,
+            started: false,
+        }
+    }
+
+    fn samples(&self, secs: f32) -> u32 {
+        (secs.max(0.0) * self.sample_rate) as u32
+    }
+
+    /// Starts a crossfade from wherever the parameters are now to `to`.
+    pub fn crossfade(&mut self, to: AudioParams, secs: f32, curve: CurveTable) {
+        if !self.started {
+            self.set_target(to);
+            return;
+        }
+        self.from = self.current;
+        self.to = to;
+        self.position = 0;
+        self.length = self.samples(secs);
+        self.curve = curve;
+        self.crossfading = true;
+    }
+
+    /// Updates the destination.
+    ///
+    /// During a crossfade the incoming set keeps following the world while the
+    /// schedule is preserved; otherwise a short glide starts.
+    pub fn set_target(&mut self, to: AudioParams) {
+        if !self.started {
+            self.started = true;
+            self.current = to;
+            self.from = to;
+            self.to = to;
+            return;
+        }
+        if self.is_transitioning() && self.crossfading {
+            self.to = to;
+            return;
+        }
+        if to != self.to {
+            self.from = self.current;
+            self.to = to;
+            self.position = 0;
+            self.length = self.samples(DEFAULT_GLIDE_SECS);
+            self.curve = CurveTable::linear();
+            self.crossfading = false;
+        }
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.position < self.length
+    }
+
+    /// Advances one frame and returns the parameters for it.
+    pub fn next_params(&mut self) -> AudioParams {
+        self.advance(1)
+    }
+
+    /// Advances `frames` frames and returns the parameters for the last one.
+    pub fn advance(&mut self, frames: u32) -> AudioParams {
+        if self.is_transitioning() {
+            self.position = (self.position + frames).min(self.length);
+            let amount = self.curve.apply(self.position as f32 / self.length as f32);
+            self.current = interpolate(&self.from, &self.to, amount);
+        } else {
+            self.current = self.to;
+        }
+        self.current
+    }
+}
+
+/// Crossfade requests from the control side to the audio callback.
+#[derive(Debug)]
+pub struct SharedTransition {
+    sequence: AtomicU32,
+    secs: AtomicU32,
+    curve: [AtomicU32; CURVE_TABLE_POINTS],
+}
+
+impl Default for SharedTransition {
+    fn default() -> Self {
+        Self {
+            sequence: AtomicU32::new(0),
+            secs: AtomicU32::new(0),
+            curve: std::array::from_fn(|_| AtomicU32::new(0)),
+        }
+    }
+}
+
+impl SharedTransition {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests a crossfade over `secs`, shaped by `curve`, to the next
+    /// parameters the callback sees.
+    pub fn start(&self, secs: f32, curve: &CurveTable) {
+        self.secs.store(secs.to_bits(), Ordering::Relaxed);
+        for (slot, value) in self.curve.iter().zip(curve.0) {
+            slot.store(value.to_bits(), Ordering::Relaxed);
+        }
+        self.sequence.fetch_add(1, Ordering::Release);
+    }
+
+    /// Returns the requested duration and curve if a crossfade was started since `seen`.
+    pub fn poll(&self, seen: &mut u32) -> Option<(f32, CurveTable)> {
+        let sequence = self.sequence.load(Ordering::Acquire);
+        if sequence == *seen {
+            return None;
+        }
+        *seen = sequence;
+        let curve = CurveTable(std::array::from_fn(|i| {
+            f32::from_bits(self.curve[i].load(Ordering::Relaxed))
+        }));
+        Some((f32::from_bits(self.secs.load(Ordering::Relaxed)), curve))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(base_freq_hz: f32, master_gain: f32) -> AudioParams {
+        AudioParams {
+            base_freq_hz,
+            master_gain,
+            ..AudioParams::default()
+        }
+    }
+
+    #[test]
+    fn test_crossfade_has_no_jumps() {
+        let mut engine = TransitionEngine::new(1_000.0);
+        engine.set_target(params(80.0, 0.1));
+        let curve = CurveTable::from_curve(&Curve::SmoothStep);
+        engine.crossfade(params(240.0, 0.2), 2.0, curve);
+
+        let mut previous = engine.next_params().base_freq_hz;
+        for _ in 0..2_000 {
+            let freq = engine.next_params().base_freq_hz;
+            assert!(freq >= previous);
+            // Largest smoothstep step over 2000 samples is well under 1 Hz
+            assert!(freq - previous < 0.5);
+            previous = freq;
+        }
+        assert!(!engine.is_transitioning());
+        assert_eq!(engine.next_params(), params(240.0, 0.2));
+    }
+
+    #[test]
+    fn test_first_target_applies_directly() {
+        let mut engine = TransitionEngine::new(48_000.0);
+        engine.set_target(params(120.0, 0.3));
+        assert_eq!(engine.next_params(), params(120.0, 0.3));
+    }
+
+    #[test]
+    fn test_shared_transition_poll() {
+        let shared = SharedTransition::new();
+        let mut seen = 0;
+        assert_eq!(shared.poll(&mut seen), None);
+        let curve = CurveTable::from_curve(&Curve::Sigmoid(8.0));
+        shared.start(3.0, &curve);
+        assert_eq!(shared.poll(&mut seen), Some((3.0, curve)));
+        assert_eq!(shared.poll(&mut seen), None);
+    }
+
+    #[test]
+    fn test_curve_table_follows_curve() {
+        let curve = Curve::Exponential(4.0);
+        let table = CurveTable::from_curve(&curve);
+        for i in 0..=200 {
+            let t = i as f32 / 200.0;
+            assert!((table.apply(t) - curve.apply(t as f64) as f32).abs() < 0.01);
+        }
+        assert_eq!(table.apply(1.0), 1.0);
+    }
+}
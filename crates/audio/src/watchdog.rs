@@ -0,0 +1,232 @@
+//! Keeps the output alive across device loss.
+//!
+//! A dedicated thread owns the [`AudioBackend`], checks it once a second and
+//! rebuilds it when it reports a fatal error or stops calling back, when (for
+//! a device) the system's default output device changes, and when asked to
+//! through [`crate::health::SharedOutputHealth::request_restart`]. Failed
+//! rebuilds back off exponentially so an unplugged interface does not spin
+//! the CPU.
+
+use crate::backend::{AudioBackend, BackendKind, EngineSetup};
+use crate::status::{EngineState, SharedAudioStatus};
+#[cfg(feature = "cpal")]
+use cpal::traits::{DeviceTrait, HostTrait};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How often the running engine is checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A stream with no callbacks for this long is treated as dead.
+const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Delay before the first rebuild attempt, doubled after each failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Handle to the watchdog thread. Dropping it stops the thread and the engine.
+pub struct AudioWatchdog {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AudioWatchdog {
+    pub fn spawn(
+        setup: EngineSetup,
+        kind: BackendKind,
+        status: Arc<SharedAudioStatus>,
+    ) -> std::io::Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let thread = std::thread::Builder::new()
+            .name("audio-watchdog".to_string())
+            .spawn(move || run(setup, kind, status, thread_stop))?;
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for AudioWatchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Sleeps for `duration`, returning early (with true) if asked to stop.
+fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) -> bool {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if stop.load(Ordering::Relaxed) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(100).min(deadline - Instant::now()));
+    }
+    stop.load(Ordering::Relaxed)
+}
+
+/// Why a device backend should move to a new default output device, if it should.
+#[cfg(feature = "cpal")]
+fn default_device_changed(engine: &dyn AudioBackend, kind: &BackendKind) -> Option<String> {
+    if !matches!(kind, BackendKind::Cpal) {
+        return None;
+    }
+    let device = cpal::default_host().default_output_device()?;
+    let default = device.description().ok()?.to_string();
+    (default != engine.device_name())
+        .then(|| format!("default output device changed to {}", default))
+}
+
+#[cfg(not(feature = "cpal"))]
+fn default_device_changed(_: &dyn AudioBackend, _: &BackendKind) -> Option<String> {
+    None
+}
+
+fn run(
+    setup: EngineSetup,
+    kind: BackendKind,
+    status: Arc<SharedAudioStatus>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut backoff = INITIAL_BACKOFF;
+    while !stop.load(Ordering::Relaxed) {
+        match kind.start(&setup) {
+            Ok(engine) => {
+                info!("Audio engine started on {}", engine.device_name());
+                status.update(|s| {
+                    s.state = EngineState::Running;
+                    s.device = Some(engine.device_name().to_string());
+                    s.sample_rate = Some(engine.sample_rate());
+                    s.channels = Some(engine.channels());
+                });
+                backoff = INITIAL_BACKOFF;
+
+                let Some(reason) = supervise(engine.as_ref(), &setup, &kind, &stop) else {
+                    break;
+                };
+                drop(engine);
+                warn!("Audio engine stopped ({}), restarting", reason);
+                status.update(|s| {
+                    s.state = EngineState::Restarting;
+                    s.device = None;
+                    s.restarts += 1;
+                    s.last_error = Some(reason);
+                });
+            }
+            Err(e) => {
+                warn!(
+                    "Audio engine failed to start ({}), retrying in {:?}",
+                    e, backoff
+                );
+                status.update(|s| {
+                    s.state = EngineState::Restarting;
+                    s.device = None;
+                    s.last_error = Some(e.to_string());
+                });
+                if sleep_unless_stopped(backoff, &stop) {
+                    break;
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+    info!("Audio watchdog stopped");
+}
+
+/// Watches a running engine. Returns why it should be rebuilt, or None on shutdown.
+fn supervise(
+    engine: &dyn AudioBackend,
+    setup: &EngineSetup,
+    kind: &BackendKind,
+    stop: &AtomicBool,
+) -> Option<String> {
+    // A request made before this engine started was for the one before it
+    setup.health.take_restart_request();
+    let mut last_count = engine.callback_count();
+    let mut last_progress = Instant::now();
+    loop {
+        if sleep_unless_stopped(POLL_INTERVAL, stop) {
+            return None;
+        }
+        if let Some(error) = engine.fatal_error() {
+            return Some(error);
+        }
+
+        let count = engine.callback_count();
+        if count != last_count {
+            last_count = count;
+            last_progress = Instant::now();
+        } else if last_progress.elapsed() >= STALL_TIMEOUT {
+            return Some(format!(
+                "no audio callbacks for {} s",
+                STALL_TIMEOUT.as_secs()
+            ));
+        }
+
+        if let Some(reason) = default_device_changed(engine, kind) {
+            return Some(reason);
+        }
+        if setup.health.take_restart_request() {
+            return Some("restart requested".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::OutputFormat;
+    use crate::backend::tests::setup;
+
+    /// Waits up to five seconds for the status to satisfy `done`.
+    fn wait_for(status: &SharedAudioStatus, done: impl Fn(&crate::status::AudioStatus) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done(&status.get()) {
+            assert!(Instant::now() < deadline, "status {:?}", status.get());
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_watchdog_restarts_on_request_and_stops_when_dropped() {
+        let setup = setup();
+        let health = Arc::clone(&setup.health);
+        let status = Arc::new(SharedAudioStatus::new(crate::status::AudioStatus::new(
+            EngineState::Starting,
+        )));
+        let format = OutputFormat {
+            sample_rate: 8_000,
+            channels: 2,
+        };
+        let watchdog =
+            AudioWatchdog::spawn(setup, BackendKind::Null(format), Arc::clone(&status)).unwrap();
+        wait_for(&status, |s| s.state == EngineState::Running);
+        let running = status.get();
+        assert_eq!(running.device.as_deref(), Some("null"));
+        assert_eq!(running.sample_rate, Some(8_000));
+        assert_eq!(running.channels, Some(2));
+        assert_eq!(running.restarts, 0);
+
+        // A request is picked up on the next check and the engine rebuilt
+        health.request_restart();
+        wait_for(&status, |s| {
+            s.restarts == 1 && s.state == EngineState::Running
+        });
+        assert_eq!(
+            status.get().last_error.as_deref(),
+            Some("restart requested")
+        );
+
+        // Dropping it stops the thread within a sleep slice, not a poll
+        let started = Instant::now();
+        drop(watchdog);
+        assert!(started.elapsed() < POLL_INTERVAL, "{:?}", started.elapsed());
+    }
+}
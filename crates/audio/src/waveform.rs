@@ -0,0 +1,173 @@
+//! Min/max envelope of recent output, for drawing a scrolling waveform
+//! without moving raw audio.
+//!
+//! The renderer folds each block into bins of [`BIN_SECS`], taking the lowest
+//! and highest sample across all channels, and stores each finished bin in a
+//! fixed ring of atomics like [`CallbackTelemetry`](crate::telemetry::CallbackTelemetry):
+//! one writer, any number of readers, no locks or allocation on the audio
+//! thread.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Output covered by one bin: 10 ms.
+pub const BIN_SECS: f64 = 0.01;
+
+/// Output the ring keeps: 10 minutes, 60,000 bins.
+pub const HISTORY_SECS: f64 = 600.0;
+
+const BINS: usize = (HISTORY_SECS / BIN_SECS) as usize;
+
+fn pack(min: f32, max: f32) -> u64 {
+    (u64::from(min.to_bits()) << 32) | u64::from(max.to_bits())
+}
+
+fn unpack(bits: u64) -> (f32, f32) {
+    (
+        f32::from_bits((bits >> 32) as u32),
+        f32::from_bits(bits as u32),
+    )
+}
+
+/// Lowest and highest samples over a stretch of output.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Envelope {
+    /// Seconds of output covered.
+    pub secs: f64,
+    /// One entry per point, oldest first.
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+/// Ring of recent bins, written by the renderer. Shared across engine
+/// rebuilds, so the history survives a restart.
+#[derive(Debug)]
+pub struct SharedWaveform {
+    /// Packed min and max of each bin, indexed by bin number modulo the
+    /// ring's length.
+    bins: Box<[AtomicU64]>,
+    written: AtomicU64,
+}
+
+impl Default for SharedWaveform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SharedWaveform {
+    pub fn new() -> Self {
+        Self {
+            bins: (0..BINS).map(|_| AtomicU64::new(0)).collect(),
+            written: AtomicU64::new(0),
+        }
+    }
+
+    /// Records one finished bin.
+    pub fn record(&self, min: f32, max: f32) {
+        let index = self.written.load(Ordering::Relaxed);
+        self.bins[index as usize % BINS].store(pack(min, max), Ordering::Relaxed);
+        // Publish the bin before the count that makes it visible
+        self.written.store(index + 1, Ordering::Release);
+    }
+
+    /// The last `secs` of output (as much as there is, up to
+    /// [`HISTORY_SECS`]) reduced to at most `points` min/max pairs.
+    pub fn envelope(&self, secs: f64, points: usize) -> Envelope {
+        let written = self.written.load(Ordering::Acquire);
+        let wanted = (secs.max(0.0) / BIN_SECS).round() as u64;
+        let bins = wanted.min(written).min(BINS as u64);
+        let points = (points as u64).min(bins);
+        let mut envelope = Envelope {
+            secs: bins as f64 * BIN_SECS,
+            min: Vec::with_capacity(points as usize),
+            max: Vec::with_capacity(points as usize),
+        };
+        let first = written - bins;
+        for point in 0..points {
+            let (start, end) = (point * bins / points, (point + 1) * bins / points);
+            let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+            for n in first + start..first + end {
+                let (low, high) = unpack(self.bins[n as usize % BINS].load(Ordering::Relaxed));
+                min = min.min(low);
+                max = max.max(high);
+            }
+            envelope.min.push(min);
+            envelope.max.push(max);
+        }
+        envelope
+    }
+}
+
+/// The bin being filled, kept by the renderer.
+#[derive(Debug)]
+pub struct BinFolder {
+    bin_frames: usize,
+    frames: usize,
+    min: f32,
+    max: f32,
+}
+
+impl BinFolder {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            bin_frames: ((sample_rate as f64 * BIN_SECS) as usize).max(1),
+            frames: 0,
+            min: 0.0,
+            max: 0.0,
+        }
+    }
+
+    /// Folds interleaved `block` into bins, recording each one finished.
+    /// Non-finite samples are left out.
+    pub fn fold(&mut self, block: &[f32], channels: u16, waveform: &SharedWaveform) {
+        for frame in block.chunks_exact(usize::from(channels.max(1))) {
+            for &sample in frame.iter().filter(|s| s.is_finite()) {
+                self.min = self.min.min(sample);
+                self.max = self.max.max(sample);
+            }
+            self.frames += 1;
+            if self.frames == self.bin_frames {
+                waveform.record(self.min, self.max);
+                self.frames = 0;
+                self.min = 0.0;
+                self.max = 0.0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bins_fold_into_the_requested_points() {
+        let waveform = SharedWaveform::new();
+        assert_eq!(waveform.envelope(60.0, 600), Envelope::default());
+
+        // 1 s of a ramp at 1 kHz, stereo with the right channel inverted
+        let mut folder = BinFolder::new(1_000);
+        let block: Vec<f32> = (0..1000)
+            .flat_map(|i| {
+                let s = i as f32 / 1000.0;
+                [s, -s]
+            })
+            .collect();
+        for chunk in block.chunks(64) {
+            folder.fold(chunk, 2, &waveform);
+        }
+
+        // More points than bins gives one per bin
+        let all = waveform.envelope(60.0, 600);
+        assert_eq!(all.secs, 1.0);
+        assert_eq!(all.max.len(), 100);
+        assert_eq!(all.max[0], 0.009);
+
+        let half = waveform.envelope(0.5, 5);
+        assert_eq!(half.min.len(), 5);
+        // The last 50 bins, ten to a point
+        assert_eq!(half.min[0], -0.599);
+        assert_eq!(half.max[0], 0.599);
+        assert_eq!(half.max[4], 0.999);
+    }
+}
@@ -0,0 +1,218 @@
+//! Breathing wind: band-passed noise that swells on slow LFOs.
+//!
+//! Unlike the texture bed, which sits still, the wind's center frequency and
+//! level rise and fall over several seconds. World rhythm (motion) sets how
+//! quickly the gusts come, energy how deep they swell, and warmth (through
+//! brightness) where the band sits.
+
+use crate::layers::Layer;
+use crate::noise::{Noise, NoiseColor};
+use crate::params::{AudioParams, GAIN_SCALE, MOTION_SCALE};
+use crate::smoother::{Smoother, SmoothingSettings};
+
+/// Gust rate at rest and at full motion; periods of 25 s down to 5 s.
+const SLOW_GUST_HZ: f32 = 0.04;
+const FAST_GUST_HZ: f32 = 0.2;
+
+/// Band center range, swept by brightness.
+const LOW_CENTER_HZ: f32 = 250.0;
+const HIGH_CENTER_HZ: f32 = 1_000.0;
+
+/// Center sweep either side of the base, in octaves, at rest and at full energy.
+const MIN_SWEEP_OCTAVES: f32 = 0.5;
+const MAX_SWEEP_OCTAVES: f32 = 1.5;
+
+/// Share of the level that swells, at rest and at full energy.
+const MIN_SWELL_DEPTH: f32 = 0.3;
+const MAX_SWELL_DEPTH: f32 = 0.9;
+
+/// Band-pass resonance; low enough to stay airy rather than whistle.
+const Q: f32 = 1.5;
+
+/// Makeup gain for the energy the band-pass removes from white noise.
+const MAKEUP_GAIN: f32 = 3.0;
+
+/// Ratio between the level and center LFO rates, irrational so the two
+/// never line up into an audible loop.
+const LFO_RATIO: f32 = 0.618;
+
+/// Layer of filtered noise whose band and level swell like breathing wind.
+pub struct WindLayer {
+    sample_rate: f32,
+    noise: Noise,
+    /// LFO phases in cycles (0-1).
+    level_phase: f32,
+    center_phase: f32,
+    smoothed_motion: Smoother,
+    smoothed_energy: Smoother,
+    smoothed_brightness: Smoother,
+    // State-variable filter integrators and coefficients
+    ic1eq: f32,
+    ic2eq: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+}
+
+impl WindLayer {
+    pub fn new(sample_rate: f32) -> Self {
+        Self::with_noise(sample_rate, NoiseColor::White)
+    }
+
+    pub fn with_noise(sample_rate: f32, color: NoiseColor) -> Self {
+        let times = SmoothingSettings::default().slow;
+        let mut layer = Self {
+            sample_rate,
+            noise: Noise::new(0x2545_F491, color),
+            level_phase: 0.0,
+            center_phase: 0.25,
+            smoothed_motion: Smoother::new(0.0, times.motion, sample_rate),
+            smoothed_energy: Smoother::new(0.0, times.master_gain, sample_rate),
+            smoothed_brightness: Smoother::new(0.5, times.brightness, sample_rate),
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+        };
+        layer.set_center(LOW_CENTER_HZ);
+        layer
+    }
+
+    /// Recomputes the band-pass coefficients (trapezoidal SVF) for `center_hz`.
+    fn set_center(&mut self, center_hz: f32) {
+        let center_hz = center_hz.clamp(20.0, self.sample_rate * 0.45);
+        let g = (std::f32::consts::PI * center_hz / self.sample_rate).tan();
+        let k = 1.0 / Q;
+        self.a1 = 1.0 / (1.0 + g * (g + k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+
+    /// Band-passes one sample, normalized to unity gain at the center.
+    fn band_pass(&mut self, input: f32) -> f32 {
+        let v3 = input - self.ic2eq;
+        let v1 = self.a1 * self.ic1eq + self.a2 * v3;
+        let v2 = self.ic2eq + self.a2 * self.ic1eq + self.a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+        v1 / Q
+    }
+}
+
+impl Layer for WindLayer {
+    fn process_block(&mut self, out: &mut [f32], params: &AudioParams) {
+        let motion = (params.motion / MOTION_SCALE).clamp(0.0, 1.0);
+        let energy = (params.master_gain / GAIN_SCALE).clamp(0.0, 1.0);
+        let frames = out.len();
+        let motion = self.smoothed_motion.advance(motion, frames);
+        let energy = self.smoothed_energy.advance(energy, frames);
+        let brightness = self.smoothed_brightness.advance(params.brightness, frames);
+
+        let gust_hz = SLOW_GUST_HZ + (FAST_GUST_HZ - SLOW_GUST_HZ) * motion;
+        let level_step = gust_hz / self.sample_rate;
+
+        // The sweep is far slower than a block, so retune the band once per block
+        let block_secs = out.len() as f32 / self.sample_rate;
+        self.center_phase = (self.center_phase + gust_hz * LFO_RATIO * block_secs).fract();
+        let center_lfo = (self.center_phase * std::f32::consts::TAU).sin();
+        let brightness = brightness.clamp(0.0, 1.0);
+        let base_hz = LOW_CENTER_HZ * (HIGH_CENTER_HZ / LOW_CENTER_HZ).powf(brightness);
+        let sweep = MIN_SWEEP_OCTAVES + (MAX_SWEEP_OCTAVES - MIN_SWEEP_OCTAVES) * energy;
+        self.set_center(base_hz * (center_lfo * sweep).exp2());
+
+        // Squared swell lingers in the lulls and rises into each gust
+        let depth = MIN_SWELL_DEPTH + (MAX_SWELL_DEPTH - MIN_SWELL_DEPTH) * energy;
+        self.noise.fill(out);
+        for sample in out.iter_mut() {
+            self.level_phase = (self.level_phase + level_step).fract();
+            let level_lfo = (self.level_phase * std::f32::consts::TAU).sin();
+            let swell = (0.5 + 0.5 * level_lfo).powi(2);
+            let level = 1.0 - depth + depth * swell;
+
+            let wind = self.band_pass(*sample) * level * MAKEUP_GAIN;
+            *sample = if wind.is_finite() { wind } else { 0.0 };
+        }
+    }
+
+    fn set_smoothing(&mut self, settings: &SmoothingSettings) {
+        let (times, rate) = (settings.slow, self.sample_rate);
+        self.smoothed_motion.set_time(times.motion, rate);
+        self.smoothed_energy.set_time(times.master_gain, rate);
+        self.smoothed_brightness.set_time(times.brightness, rate);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        // Keep the integrators and retune the band for the new rate; the
+        // LFOs run in cycles
+        let center_hz = (self.a2 / self.a1).atan() * self.sample_rate / std::f32::consts::PI;
+        self.sample_rate = sample_rate;
+        self.set_center(center_hz);
+        for smoother in [
+            &mut self.smoothed_motion,
+            &mut self.smoothed_energy,
+            &mut self.smoothed_brightness,
+        ] {
+            smoother.set_sample_rate(sample_rate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RMS of consecutive one-second windows.
+    fn window_rms(layer: &mut WindLayer, params: &AudioParams, secs: usize) -> Vec<f32> {
+        let mut second = vec![0.0; layer.sample_rate as usize];
+        (0..secs)
+            .map(|_| {
+                let mut sum = 0.0;
+                for block in second.chunks_mut(256) {
+                    layer.process_block(block, params);
+                    sum += block.iter().map(|s| s * s).sum::<f32>();
+                }
+                (sum / second.len() as f32).sqrt()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_output_is_bounded_noise() {
+        let mut layer = WindLayer::new(8_000.0);
+        let params = AudioParams::default();
+        let mut peak: f32 = 0.0;
+        let mut block = [0.0; 250];
+        for _ in 0..160 {
+            layer.process_block(&mut block, &params);
+            for sample in block {
+                assert!(sample.is_finite());
+                peak = peak.max(sample.abs());
+            }
+        }
+        assert!(peak > 0.05 && peak < 4.0);
+    }
+
+    #[test]
+    fn test_energy_deepens_the_swell() {
+        let calm = AudioParams {
+            master_gain: 0.0,
+            motion: MOTION_SCALE,
+            ..AudioParams::default()
+        };
+        let stormy = AudioParams {
+            master_gain: GAIN_SCALE,
+            ..calm
+        };
+        let swing = |params: &AudioParams| {
+            let mut layer = WindLayer::new(4_000.0);
+            layer.set_smoothing(&SmoothingSettings::uniform(0.0));
+            // Ten seconds covers two full gusts at full motion
+            let rms = window_rms(&mut layer, params, 10);
+            let max = rms.iter().cloned().fold(0.0, f32::max);
+            let min = rms.iter().cloned().fold(f32::MAX, f32::min);
+            max / min
+        };
+        assert!(swing(&stormy) > swing(&calm) * 1.5);
+    }
+}
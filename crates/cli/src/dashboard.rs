@@ -0,0 +1,273 @@
+//! Terminal dashboard: live world/audio graphs with keyboard controls.
+
+use ambient_core::events::PerformAction;
+use futures_util::{SinkExt, StreamExt};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::symbols::Marker;
+use ratatui::text::Line;
+use ratatui::widgets::{Axis, Block, Chart, Dataset, Gauge, GraphType, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Number of snapshots kept per graph (~30 s at 10 Hz).
+const HISTORY_LEN: usize = 300;
+
+const PARAMS: [(&str, Color); 7] = [
+    ("density", Color::Cyan),
+    ("rhythm", Color::Green),
+    ("tension", Color::Red),
+    ("energy", Color::Yellow),
+    ("warmth", Color::Magenta),
+    ("space", Color::White),
+    ("clarity", Color::Blue),
+];
+
+const AUDIO_LEVELS: [&str; 7] = [
+    "master_gain",
+    "brightness",
+    "motion",
+    "texture",
+    "reverb_size",
+    "grit",
+    "sparkle_impulse",
+];
+
+/// Moves the action intensity by `steps` tenths, within -1 to 1. Below zero
+/// the keys push the other way.
+pub fn step_intensity(intensity: f64, steps: i32) -> f64 {
+    ((intensity * 10.0).round() + f64::from(steps)).clamp(-10.0, 10.0) / 10.0
+}
+
+/// Maps a key to the perform action it triggers.
+pub fn action_for_key(key: char, intensity: f64) -> Option<PerformAction> {
+    match key {
+        'p' => Some(PerformAction::Pulse { intensity }),
+        's' => Some(PerformAction::Stir { intensity }),
+        'c' => Some(PerformAction::Calm { intensity }),
+        'h' => Some(PerformAction::Heat { intensity }),
+        't' => Some(PerformAction::Tense { intensity }),
+        'e' => Some(PerformAction::Expand { intensity }),
+        'o' => Some(PerformAction::Contract { intensity }),
+        'l' => Some(PerformAction::Clarify { intensity }),
+        'r' => Some(PerformAction::Roughen { intensity }),
+        _ => None,
+    }
+}
+
+struct Dashboard {
+    history: [VecDeque<(f64, f64)>; 7],
+    audio: Value,
+    samples: u64,
+    intensity: f64,
+    status: String,
+}
+
+impl Dashboard {
+    fn new() -> Self {
+        Self {
+            history: Default::default(),
+            audio: Value::Null,
+            samples: 0,
+            intensity: 0.5,
+            status: "connected".to_string(),
+        }
+    }
+
+    fn handle_server_message(&mut self, message: &Value) {
+        match message["type"].as_str() {
+            Some("snapshot") => {
+                let world = &message["payload"]["world"];
+                for (i, (name, _)) in PARAMS.iter().enumerate() {
+                    let value = world[name].as_f64().unwrap_or(0.0);
+                    self.history[i].push_back((self.samples as f64, value));
+                    if self.history[i].len() > HISTORY_LEN {
+                        self.history[i].pop_front();
+                    }
+                }
+                self.audio = message["payload"]["audio"].clone();
+                self.samples += 1;
+            }
+            Some("event_ack") => {
+                self.status = format!("ack: {}", message["payload"]["action"]);
+            }
+            Some("error") => {
+                self.status = format!("error: {}", message["payload"]["message"]);
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [chart_area, audio_area, help_area] = Layout::vertical([
+            Constraint::Min(10),
+            Constraint::Length(AUDIO_LEVELS.len() as u16 + 2),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let points: Vec<Vec<(f64, f64)>> = self
+            .history
+            .iter()
+            .map(|h| h.iter().copied().collect())
+            .collect();
+        let datasets = PARAMS
+            .iter()
+            .zip(&points)
+            .map(|((name, color), data)| {
+                Dataset::default()
+                    .name(*name)
+                    .marker(Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(*color))
+                    .data(data)
+            })
+            .collect();
+        let x_end = self.samples.max(HISTORY_LEN as u64) as f64;
+        let chart = Chart::new(datasets)
+            .block(Block::bordered().title("World"))
+            .x_axis(Axis::default().bounds([x_end - HISTORY_LEN as f64, x_end]))
+            .y_axis(
+                Axis::default()
+                    .bounds([0.0, 1.0])
+                    .labels(["0.0", "0.5", "1.0"]),
+            );
+        frame.render_widget(chart, chart_area);
+
+        let audio_block = Block::bordered().title("Audio");
+        let inner = audio_block.inner(audio_area);
+        frame.render_widget(audio_block, audio_area);
+        let rows = Layout::vertical(vec![Constraint::Length(1); AUDIO_LEVELS.len()]).split(inner);
+        for (name, row) in AUDIO_LEVELS.iter().zip(rows.iter()) {
+            let value = self.audio[name].as_f64().unwrap_or(0.0);
+            let gauge = Gauge::default()
+                .label(format!("{:<16}{:.3}", name, value))
+                .ratio(value.clamp(0.0, 1.0))
+                .gauge_style(Style::default().fg(Color::Blue));
+            frame.render_widget(gauge, *row);
+        }
+
+        let help = Line::from(format!(
+            " [p]ulse [s]tir [c]alm [h]eat [t]ense [e]xpand c[o]ntract c[l]arify [r]oughen  [+/-] intensity {:.1}  [q]uit  | {}",
+            self.intensity, self.status
+        ));
+        frame.render_widget(Paragraph::new(help), help_area);
+    }
+}
+
+/// Runs the dashboard until the user quits or the connection drops.
+pub async fn run(base: &str, token: Option<&str>) -> anyhow::Result<()> {
+    let socket = crate::connect_ws(base, token).await?;
+
+    let terminal = ratatui::init();
+    let result = run_loop(terminal, socket).await;
+    ratatui::restore();
+    result
+}
+
+async fn run_loop(mut terminal: DefaultTerminal, socket: crate::WsStream) -> anyhow::Result<()> {
+    let (mut ws_tx, mut ws_rx) = socket.split();
+
+    // Terminal input is blocking, so read it on its own thread
+    let (key_tx, mut key_rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        loop {
+            if let Ok(Event::Key(key)) = event::read()
+                && key.kind == KeyEventKind::Press
+                && key_tx.send(key.code).is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let mut dashboard = Dashboard::new();
+    let mut redraw = tokio::time::interval(Duration::from_millis(100));
+    let mut request_counter = 0u64;
+
+    loop {
+        tokio::select! {
+            _ = redraw.tick() => {
+                terminal.draw(|frame| dashboard.draw(frame))?;
+            }
+            message = ws_rx.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    if let Ok(message) = serde_json::from_str::<Value>(&text) {
+                        dashboard.handle_server_message(&message);
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Ok(()),
+            },
+            Some(code) = key_rx.recv() => match code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('+') | KeyCode::Char('=') => {
+                    dashboard.intensity = step_intensity(dashboard.intensity, 1);
+                }
+                KeyCode::Char('-') => {
+                    dashboard.intensity = step_intensity(dashboard.intensity, -1);
+                }
+                KeyCode::Char(key) => {
+                    if let Some(action) = action_for_key(key, dashboard.intensity) {
+                        request_counter += 1;
+                        let message = json!({
+                            "version": "1.0",
+                            "type": "perform",
+                            "payload": {
+                                "request_id": format!("dashboard-{}", request_counter),
+                                "action": action,
+                            },
+                        });
+                        ws_tx.send(Message::Text(message.to_string().into())).await?;
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_for_key() {
+        assert_eq!(
+            action_for_key('p', 0.3),
+            Some(PerformAction::Pulse { intensity: 0.3 })
+        );
+        assert_eq!(
+            action_for_key('t', 1.0),
+            Some(PerformAction::Tense { intensity: 1.0 })
+        );
+        assert_eq!(
+            action_for_key('o', 0.2),
+            Some(PerformAction::Contract { intensity: 0.2 })
+        );
+        assert_eq!(
+            action_for_key('r', -0.3),
+            Some(PerformAction::Roughen { intensity: -0.3 })
+        );
+        assert_eq!(action_for_key('x', 0.5), None);
+    }
+
+    #[test]
+    fn test_step_intensity_crosses_zero() {
+        let mut intensity = 0.5;
+        for _ in 0..5 {
+            intensity = step_intensity(intensity, -1);
+        }
+        assert_eq!(intensity, 0.0);
+        assert_eq!(step_intensity(intensity, -1), -0.1);
+        assert_eq!(step_intensity(-1.0, -1), -1.0);
+        assert_eq!(step_intensity(0.9, 1), 1.0);
+        assert_eq!(step_intensity(1.0, 1), 1.0);
+    }
+}
@@ -0,0 +1,232 @@
+//! Command-line client for controlling a running ambient instance.
+//!
+//! ```text
+//! ambient-cli pulse 0.7
+//! ambient-cli heat -0.4
+//! ambient-cli scene sunrise
+//! ambient-cli watch
+//! ambient-cli dashboard
+//! ```
+
+mod dashboard;
+
+use ambient_client::{Client, ClientError};
+use ambient_core::events::PerformAction;
+use ambient_core::world::WorldSnapshot;
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use futures_util::StreamExt;
+use std::io::Write;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+
+type WsStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+const WORLD_FIELDS: [&str; 8] = [
+    "density",
+    "rhythm",
+    "tension",
+    "energy",
+    "warmth",
+    "space",
+    "clarity",
+    "sparkle_impulse",
+];
+
+#[derive(Debug, Parser)]
+#[command(name = "ambient-cli", about = "Control a running ambient instance")]
+struct Cli {
+    /// Base URL of the ambient API
+    #[arg(long, env = "AMBIENT_URL", default_value = "http://localhost:3000")]
+    url: String,
+    /// API token, required when the server has auth enabled
+    #[arg(long, env = "AMBIENT_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Intensities run from -1 to 1; a negative one does the reverse.
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Increase energy
+    #[command(allow_negative_numbers = true)]
+    Pulse { intensity: f64 },
+    /// Increase density
+    #[command(allow_negative_numbers = true)]
+    Stir { intensity: f64 },
+    /// Decrease tension
+    #[command(allow_negative_numbers = true)]
+    Calm { intensity: f64 },
+    /// Increase warmth
+    #[command(allow_negative_numbers = true)]
+    Heat { intensity: f64 },
+    /// Increase tension
+    #[command(allow_negative_numbers = true)]
+    Tense { intensity: f64 },
+    /// Increase space, opening up the room
+    #[command(allow_negative_numbers = true)]
+    Expand { intensity: f64 },
+    /// Decrease space, closing in the room
+    #[command(allow_negative_numbers = true)]
+    Contract { intensity: f64 },
+    /// Increase clarity, thinning the noise and narrowing the detune
+    #[command(allow_negative_numbers = true)]
+    Clarify { intensity: f64 },
+    /// Decrease clarity, adding grit and roughness
+    #[command(allow_negative_numbers = true)]
+    Roughen { intensity: f64 },
+    /// Switch to a named scene
+    Scene { name: String },
+    /// Freeze the world for a number of seconds
+    Freeze { seconds: f64 },
+    /// Take back the most recent action
+    Undo,
+    /// Increase energy around a point of the unit square
+    #[command(allow_negative_numbers = true)]
+    PulseAt { x: f64, y: f64, intensity: f64 },
+    /// Print the current world state as JSON
+    State,
+    /// Stream live world state as a table
+    Watch,
+    /// Interactive terminal dashboard with graphs and key controls
+    Dashboard,
+}
+
+impl Command {
+    fn action(&self) -> Option<PerformAction> {
+        let action = match self {
+            Command::Pulse { intensity } => PerformAction::Pulse {
+                intensity: *intensity,
+            },
+            Command::Stir { intensity } => PerformAction::Stir {
+                intensity: *intensity,
+            },
+            Command::Calm { intensity } => PerformAction::Calm {
+                intensity: *intensity,
+            },
+            Command::Heat { intensity } => PerformAction::Heat {
+                intensity: *intensity,
+            },
+            Command::Tense { intensity } => PerformAction::Tense {
+                intensity: *intensity,
+            },
+            Command::Expand { intensity } => PerformAction::Expand {
+                intensity: *intensity,
+            },
+            Command::Contract { intensity } => PerformAction::Contract {
+                intensity: *intensity,
+            },
+            Command::Clarify { intensity } => PerformAction::Clarify {
+                intensity: *intensity,
+            },
+            Command::Roughen { intensity } => PerformAction::Roughen {
+                intensity: *intensity,
+            },
+            Command::Scene { name } => PerformAction::Scene { name: name.clone() },
+            Command::Freeze { seconds } => PerformAction::Freeze { seconds: *seconds },
+            Command::Undo => PerformAction::Undo,
+            Command::PulseAt { x, y, intensity } => PerformAction::PulseAt {
+                x: *x,
+                y: *y,
+                intensity: *intensity,
+            },
+            Command::State | Command::Watch | Command::Dashboard => return None,
+        };
+        Some(action)
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let base = cli.url.trim_end_matches('/');
+    let token = cli.token.as_deref();
+    let mut client = Client::new(base);
+    if let Some(token) = token {
+        client = client.with_token(token);
+    }
+
+    match &cli.command {
+        Command::State => print_state(&client, base).await,
+        Command::Watch => watch(&client).await,
+        Command::Dashboard => dashboard::run(base, token).await,
+        command => {
+            let action = command.action().expect("perform command");
+            perform(&client, base, &action).await
+        }
+    }
+}
+
+/// Opens the server's WebSocket, authenticating with the token if given.
+async fn connect_ws(base: &str, token: Option<&str>) -> anyhow::Result<WsStream> {
+    let ws_url = format!("{}/ws", base.replacen("http", "ws", 1));
+    let mut request = ws_url.as_str().into_client_request()?;
+    if let Some(token) = token {
+        request.headers_mut().insert(
+            "Authorization",
+            HeaderValue::from_str(&format!("Bearer {}", token))?,
+        );
+    }
+    let (socket, _) = tokio_tungstenite::connect_async(request)
+        .await
+        .with_context(|| format!("failed to connect to {}", ws_url))?;
+    Ok(socket)
+}
+
+/// Names the server when a request could not reach it at all; errors the
+/// server answered with already say what went wrong.
+fn reached<T>(result: Result<T, ClientError>, base: &str) -> anyhow::Result<T> {
+    match result {
+        Err(ClientError::Http(e)) if !e.is_decode() => {
+            Err(e).with_context(|| format!("failed to reach {}", base))
+        }
+        result => Ok(result?),
+    }
+}
+
+async fn perform(client: &Client, base: &str, action: &PerformAction) -> anyhow::Result<()> {
+    let ack = reached(client.perform(action).await, base)?;
+    println!("{}", serde_json::to_string(&ack)?);
+    Ok(())
+}
+
+async fn print_state(client: &Client, base: &str) -> anyhow::Result<()> {
+    let state = reached(client.state().await, base)?;
+    println!("{}", serde_json::to_string_pretty(&state)?);
+    Ok(())
+}
+
+async fn watch(client: &Client) -> anyhow::Result<()> {
+    println!(
+        "{}",
+        WORLD_FIELDS
+            .iter()
+            .map(|field| format!("{:>16}", field))
+            .collect::<String>()
+    );
+    let mut snapshots = Box::pin(client.subscribe_snapshots());
+    while let Some(snapshot) = snapshots.next().await {
+        print!("\r{}", format_row(&snapshot));
+        std::io::stdout().flush()?;
+    }
+    println!();
+    Ok(())
+}
+
+fn format_row(world: &WorldSnapshot) -> String {
+    [
+        world.density(),
+        world.rhythm(),
+        world.tension(),
+        world.energy(),
+        world.warmth(),
+        world.space(),
+        world.clarity(),
+        world.sparkle_impulse(),
+    ]
+    .iter()
+    .map(|value| format!("{:>16.3}", value))
+    .collect()
+}
@@ -19,6 +19,8 @@ function App() {
     tension: 0.5,
     energy: 0.5,
     warmth: 0.5,
+    space: 0.5,
+    clarity: 0.5,
     sparkle_impulse: 0.0
   })
   const [eventFeed, setEventFeed] = useState<Array<{timestamp: number, action: string}>>([])
@@ -124,16 +126,16 @@ function App() {
     const x = (e.clientX - rect.left) / rect.width
     const y = (e.clientY - rect.top) / rect.height
 
-    // Map x to warmth (0 = cool, 1 = warm)
-    const warmthIntensity = x * intensity
-    // Map y to energy (0 = calm, 1 = energetic)
-    const energyIntensity = (1 - y) * intensity
+    // Map x to warmth (left cools, right warms; the centre leaves it be)
+    const warmthIntensity = (2 * x - 1) * intensity
+    // Map y to energy (bottom drains, top energises)
+    const energyIntensity = (1 - 2 * y) * intensity
 
-    // Send combined actions
-    if (warmthIntensity > 0.1) {
+    // Send combined actions; negative intensities push the other way
+    if (Math.abs(warmthIntensity) > 0.1) {
       connection.perform({ Heat: { intensity: warmthIntensity } })
     }
-    if (energyIntensity > 0.1) {
+    if (Math.abs(energyIntensity) > 0.1) {
       connection.perform({ Pulse: { intensity: energyIntensity } })
     }
   }
@@ -321,6 +323,38 @@ function App() {
                 >
                   Heat
                 </button>
+                <button
+                  onMouseDown={() => startAction({ Expand: { intensity } }, 'Expand')}
+                  onMouseUp={() => stopAction('Expand')}
+                  onMouseLeave={() => stopAction('Expand')}
+                  className={activeActions.has('Expand') ? 'active' : ''}
+                >
+                  Expand
+                </button>
+                <button
+                  onMouseDown={() => startAction({ Contract: { intensity } }, 'Contract')}
+                  onMouseUp={() => stopAction('Contract')}
+                  onMouseLeave={() => stopAction('Contract')}
+                  className={activeActions.has('Contract') ? 'active' : ''}
+                >
+                  Contract
+                </button>
+                <button
+                  onMouseDown={() => startAction({ Clarify: { intensity } }, 'Clarify')}
+                  onMouseUp={() => stopAction('Clarify')}
+                  onMouseLeave={() => stopAction('Clarify')}
+                  className={activeActions.has('Clarify') ? 'active' : ''}
+                >
+                  Clarify
+                </button>
+                <button
+                  onMouseDown={() => startAction({ Roughen: { intensity } }, 'Roughen')}
+                  onMouseUp={() => stopAction('Roughen')}
+                  onMouseLeave={() => stopAction('Roughen')}
+                  className={activeActions.has('Roughen') ? 'active' : ''}
+                >
+                  Roughen
+                </button>
               </div>
             </div>
 
@@ -400,6 +434,18 @@ function App() {
                   <div className="meter-fill" style={{ width: `${worldState.warmth * 100}%` }}></div>
                 </div>
               </div>
+              <div className="meter">
+                <label>Space: {(worldState.space ?? 0.5).toFixed(5)}</label>
+                <div className="meter-bar">
+                  <div className="meter-fill" style={{ width: `${(worldState.space ?? 0.5) * 100}%` }}></div>
+                </div>
+              </div>
+              <div className="meter">
+                <label>Clarity: {(worldState.clarity ?? 0.5).toFixed(5)}</label>
+                <div className="meter-bar">
+                  <div className="meter-fill" style={{ width: `${(worldState.clarity ?? 0.5) * 100}%` }}></div>
+                </div>
+              </div>
             </div>
           </div>
 
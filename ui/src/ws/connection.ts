@@ -23,6 +23,10 @@ export interface WorldSnapshot {
   tension: number;
   energy: number;
   warmth: number;
+  // Absent from schema 1.0 and from servers that predate space
+  space?: number;
+  // Absent from schema 1.0 and from servers that predate clarity
+  clarity?: number;
   sparkle_impulse: number;
 }
 
@@ -51,6 +55,9 @@ export interface EventAckPayload {
   request_id?: string;
   action: string;
   intensity?: number;
+  applied: boolean;
+  clamped_fields: string[];
+  resulting_snapshot: WorldSnapshot;
 }
 
 export interface ErrorPayload {
@@ -66,8 +73,14 @@ export type PerformAction =
   | { Stir: { intensity: number } }
   | { Tense: { intensity: number } }
   | { Heat: { intensity: number } }
+  | { Expand: { intensity: number } }
+  | { Contract: { intensity: number } }
+  | { Clarify: { intensity: number } }
+  | { Roughen: { intensity: number } }
   | { Scene: { name: string } }
-  | { Freeze: { seconds: number } };
+  | { Freeze: { seconds: number } }
+  | 'Undo'
+  | { PulseAt: { x: number; y: number; intensity: number } };
 
 // Message types
 export interface BaseMessage {
@@ -363,6 +376,28 @@ export class AmbientWorldConnection {
     });
   }
 
+  performUndo(requestId?: string): boolean {
+    return this.sendMessage({
+      version: '1.0',
+      type: 'perform',
+      payload: {
+        request_id: requestId,
+        action: 'Undo',
+      },
+    });
+  }
+
+  performPulseAt(x: number, y: number, intensity: number, requestId?: string): boolean {
+    return this.sendMessage({
+      version: '1.0',
+      type: 'perform',
+      payload: {
+        request_id: requestId,
+        action: { PulseAt: { x, y, intensity } },
+      },
+    });
+  }
+
   ping(): boolean {
     return this.sendMessage({
       version: '1.0',